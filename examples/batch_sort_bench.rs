@@ -0,0 +1,274 @@
+//! Compares [`run_batch`] with a [`run`] per job, on independent jobs of buffers of their own.
+//!
+//! ```text
+//! cargo run --release --example batch_sort_bench -- --jobs 8 --size 64k --repeats 20 --warmup 3
+//! ```
+//!
+//! Every argument is optional, the defaults are the ones above. Each repetition sorts all the jobs in one
+//! submission and measures the wall time between the submission and its completion, the gain is the overlap of the
+//! interleaved dispatches of different jobs.
+
+use std::time::Instant;
+
+use bevy::{
+    prelude::*,
+    render::{
+        RenderApp, RenderPlugin,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferInitDescriptor, BufferUsages, CommandEncoderDescriptor,
+            PipelineCache,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+    window::ExitCondition,
+};
+use bevy_radix_sort::{
+    PreparedSortJob, batch_interleaves, blocks_buffer_size, prelude::*, run_batch,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+/// The updates to wait for the pipelines before giving up.
+const MAX_LOAD_UPDATES: u32 = 1000;
+
+const USAGE: &str = "usage: batch_sort_bench [--jobs 8] [--size 64k] [--repeats 20] [--warmup 3]";
+
+#[derive(Debug, Clone)]
+struct BenchArgs {
+    jobs: u32,
+    size: u32,
+    repeats: u32,
+    warmup: u32,
+}
+
+impl BenchArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self {
+            jobs: 8,
+            size: 64 << 10,
+            repeats: 20,
+            warmup: 3,
+        };
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(USAGE.to_string());
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing the value of {arg}\n{USAGE}"))?;
+
+            match arg.as_str() {
+                "--jobs" => parsed.jobs = parse_number(&value)?.max(1),
+                "--size" => parsed.size = parse_size(&value)?.max(2),
+                "--repeats" => parsed.repeats = parse_number(&value)?.max(1),
+                "--warmup" => parsed.warmup = parse_number(&value)?,
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid number {value}"))
+}
+
+/// `65536`, `64k` or `16M`.
+fn parse_size(value: &str) -> Result<u32, String> {
+    let (digits, shift) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 10),
+        Some('m' | 'M') => (&value[..value.len() - 1], 20),
+        _ => (value, 0),
+    };
+
+    parse_number(digits)?
+        .checked_shl(shift)
+        .ok_or_else(|| format!("size {value} out of range"))
+}
+
+fn main() {
+    let args = match BenchArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    };
+
+    let Some(app) = create_bench_app(args.size) else {
+        std::process::exit(1);
+    };
+
+    let world = app.sub_app(RenderApp).world();
+    let render_device = world.resource::<RenderDevice>();
+    let render_queue = world.resource::<RenderQueue>();
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+    let max_compute_workgroups_per_dimension =
+        render_device.limits().max_compute_workgroups_per_dimension;
+
+    let mut rng = StdRng::seed_from_u64(args.size as u64);
+    let inputs: Vec<Vec<u32>> = (0..args.jobs)
+        .map(|_| (0..args.size).map(|_| rng.r#gen()).collect())
+        .collect();
+    let buffers: Vec<JobBuffers> = inputs
+        .iter()
+        .map(|keys| JobBuffers::new(render_device, keys))
+        .collect();
+    let jobs: Vec<PreparedSortJob> = buffers
+        .iter()
+        .map(|buffers| buffers.job(render_device, radix_sort_pipeline, args.size))
+        .collect();
+    assert!(args.jobs < 2 || batch_interleaves(&jobs));
+
+    let mut millis = [Vec::new(), Vec::new()];
+    for repeat in 0..args.warmup + args.repeats {
+        for (batched, millis) in [false, true].into_iter().zip(&mut millis) {
+            // Each repetition sorts the same keys, uploaded and completed before the measure
+            for (buffers, keys) in buffers.iter().zip(&inputs) {
+                render_queue.write_buffer(&buffers.keys[0], 0, bytemuck::cast_slice(keys));
+            }
+            render_queue.submit([]);
+            render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+
+            let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("batch_sort_bench: sort command encoder"),
+            });
+            let submitted = Instant::now();
+            if batched {
+                run_batch(
+                    &mut encoder,
+                    pipeline_cache,
+                    radix_sort_pipeline,
+                    max_compute_workgroups_per_dimension,
+                    &jobs,
+                );
+            } else {
+                for job in &jobs {
+                    bevy_radix_sort::run(
+                        &mut encoder,
+                        pipeline_cache,
+                        radix_sort_pipeline,
+                        job.bind_group(),
+                        max_compute_workgroups_per_dimension,
+                        job.count,
+                        job.pass_range.clone(),
+                        job.init_index,
+                        job.read_from_even,
+                    );
+                }
+            }
+            render_queue.submit([encoder.finish()]);
+            render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+
+            if repeat >= args.warmup {
+                millis.push(submitted.elapsed().as_secs_f64() * 1e3);
+            }
+        }
+    }
+
+    let median = |millis: &mut Vec<f64>| {
+        millis.sort_by(f64::total_cmp);
+        millis[millis.len() / 2]
+    };
+    let sequential = median(&mut millis[0]);
+    let batched = median(&mut millis[1]);
+    println!(
+        "{} jobs of {} keys: sequential {sequential:.3} ms, batched {batched:.3} ms ({:+.1}%)",
+        args.jobs,
+        args.size,
+        (batched / sequential - 1.0) * 100.0
+    );
+}
+
+/// Creates a headless app, `None` if its pipelines don't load.
+fn create_bench_app(max_keys: u32) -> Option<App> {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(RenderPlugin {
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default())
+        .add_plugins(RadixSortPlugin {
+            settings: max_keys.into(),
+        });
+
+    app.finish();
+    app.cleanup();
+
+    for _ in 0..MAX_LOAD_UPDATES {
+        app.update();
+
+        match check_load_state(app.sub_app(RenderApp).world()) {
+            LoadState::Loaded => return Some(app),
+            LoadState::Failed(err) => {
+                eprintln!("the sort failed to load: {err}");
+                return None;
+            }
+            LoadState::FallbackCpu => {
+                eprintln!("no GPU backend");
+                return None;
+            }
+            _ => {}
+        }
+    }
+
+    eprintln!("the pipelines didn't load");
+    None
+}
+
+/// The buffers of a job, with the keys on the even side.
+struct JobBuffers {
+    keys: [Buffer; 2],
+    vals: [Buffer; 2],
+    blocks: Buffer,
+}
+
+impl JobBuffers {
+    fn new(render_device: &RenderDevice, keys: &[u32]) -> Self {
+        let create = |contents: &[u32]| {
+            render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("batch_sort_bench: job buffer"),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                contents: bytemuck::cast_slice(contents),
+            })
+        };
+        let zeros = vec![0; keys.len()];
+
+        Self {
+            keys: [create(keys), create(&zeros)],
+            vals: [create(&zeros), create(&zeros)],
+            blocks: render_device.create_buffer(&BufferDescriptor {
+                label: Some("batch_sort_bench: job blocks buffer"),
+                size: blocks_buffer_size(keys.len() as u32),
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    fn job(
+        &self,
+        render_device: &RenderDevice,
+        radix_sort_pipeline: &RadixSortPipeline,
+        count: u32,
+    ) -> PreparedSortJob {
+        PreparedSortJob::new(
+            render_device,
+            radix_sort_pipeline,
+            &self.keys[0],
+            &self.keys[1],
+            Some((&self.vals[0], &self.vals[1])),
+            &self.blocks,
+            count,
+        )
+    }
+}
@@ -0,0 +1,161 @@
+//! Sorts 10k sprites by a [`SortKey`] with the [`EntitySortPlugin`] and colors each one by its rank in the
+//! [`SortedEntityIndices`], read back every few frames.
+//!
+//! The key is the distance of a sprite to a point circling the grid, so the ranks move as rings of color. Press space
+//! to despawn a random tenth of the sprites and spawn as many again, the sort picks them up from the next frame.
+//!
+//! ```text
+//! cargo run --example entity_sort_ranks
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+    },
+};
+use bevy_radix_sort::{BufferReadback, EntitySortPlugin, SortKey, SortedEntityIndices, prelude::*};
+use rand::Rng;
+
+const GRID_SIZE: u32 = 100;
+const SPRITE_SIZE: f32 = 7.0;
+
+fn main() {
+    let ranks = SortedRanks::default();
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: (GRID_SIZE * GRID_SIZE).into(),
+        })
+        .add_plugins(EntitySortPlugin)
+        .insert_resource(ranks.clone())
+        .add_systems(Startup, setup)
+        .add_systems(Update, (update_sort_keys, respawn_sprites, color_by_rank));
+
+    app.sub_app_mut(RenderApp)
+        .insert_resource(ranks)
+        .add_systems(Render, read_back_ranks.after(RenderSet::Render));
+
+    app.run();
+}
+
+/// The entities by rank, the last read back, handed from the render world to the main world.
+#[derive(Resource, Clone, Default)]
+struct SortedRanks(Arc<Mutex<Option<Vec<Entity>>>>);
+
+#[derive(Component)]
+struct GridSprite;
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2d);
+
+    for _ in 0..GRID_SIZE * GRID_SIZE {
+        spawn_sprite(&mut commands);
+    }
+}
+
+fn spawn_sprite(commands: &mut Commands) {
+    let half = GRID_SIZE as f32 * SPRITE_SIZE * 0.5;
+    let mut rng = rand::thread_rng();
+    let position = Vec2::new(rng.gen_range(-half..half), rng.gen_range(-half..half));
+
+    commands.spawn((
+        GridSprite,
+        SortKey(0),
+        Sprite::from_color(Color::WHITE, Vec2::splat(SPRITE_SIZE - 1.0)),
+        Transform::from_translation(position.extend(0.0)),
+    ));
+}
+
+fn update_sort_keys(time: Res<Time>, mut sprites: Query<(&Transform, &mut SortKey)>) {
+    let radius = GRID_SIZE as f32 * SPRITE_SIZE * 0.3;
+    let center = Vec2::from_angle(time.elapsed_secs() * 0.5) * radius;
+
+    for (transform, mut key) in &mut sprites {
+        key.0 = transform.translation.truncate().distance(center) as u32;
+    }
+}
+
+fn respawn_sprites(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    sprites: Query<Entity, With<GridSprite>>,
+) {
+    if !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut respawned = 0;
+    for entity in &sprites {
+        if rng.gen_ratio(1, 10) {
+            commands.entity(entity).despawn();
+            respawned += 1;
+        }
+    }
+    for _ in 0..respawned {
+        spawn_sprite(&mut commands);
+    }
+}
+
+fn color_by_rank(ranks: Res<SortedRanks>, mut sprites: Query<&mut Sprite, With<GridSprite>>) {
+    let Some(entities) = ranks.0.lock().unwrap().take() else {
+        return;
+    };
+
+    let count = entities.len().max(1) as f32;
+    for (rank, entity) in entities.into_iter().enumerate() {
+        // Despawned since the extraction
+        let Ok(mut sprite) = sprites.get_mut(entity) else {
+            continue;
+        };
+        sprite.color = Color::hsl(rank as f32 / count * 300.0, 0.8, 0.5);
+    }
+}
+
+/// Copies the sorted indices once the previous readback is done, and publishes the entities by rank.
+fn read_back_ranks(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    sorted: Res<SortedEntityIndices>,
+    ranks: Res<SortedRanks>,
+    mut in_flight: Local<Option<(BufferReadback, Vec<MainEntity>)>>,
+) {
+    if let Some((readback, entities)) = in_flight.as_mut() {
+        match readback.poll(&render_device) {
+            None => return,
+            Some(Ok(indices)) => {
+                let by_rank = indices
+                    .into_iter()
+                    .map(|index| entities[index as usize].id())
+                    .collect();
+                *ranks.0.lock().unwrap() = Some(by_rank);
+            }
+            Some(Err(err)) => error!("entity_sort_ranks: the readback failed: {err}"),
+        }
+        *in_flight = None;
+    }
+
+    let Some(buffer) = sorted.buffer().filter(|_| !sorted.is_empty()) else {
+        return;
+    };
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("entity_sort_ranks: readback command encoder"),
+    });
+    let readback = BufferReadback::new(
+        &render_device,
+        &mut encoder,
+        buffer,
+        0,
+        sorted.len() as usize,
+    );
+    render_queue.submit([encoder.finish()]);
+    *in_flight = Some((readback, sorted.entities().to_vec()));
+}
@@ -0,0 +1,821 @@
+//! Benchmarks the sort headless across sizes and configurations, writes every sample to a CSV and a summary to
+//! stdout.
+//!
+//! ```text
+//! cargo run --release --example gpu_sort_bench -- \
+//!     --sizes 64k,256k,1M,4M,16M --passes 2,4 --algorithms radix,bitonic --subgroup-fallback off,on \
+//!     --uploads off,serial,early --repeats 10 --warmup 3 --csv gpu_sort_bench.csv
+//! ```
+//!
+//! Every argument is optional, the defaults are the ones above but a single pass count (4), algorithm (radix),
+//! subgroup fallback (off) and upload (off). With `--uploads serial`, the keys and vals are copied into the global
+//! buffers in the measured encoder right before the sort, with `early` in a command buffer of their own submitted
+//! ahead of it, like the jobs do with `RadixSortJobsConfig::overlap_uploads`. The GPU time comes from timestamp queries around the recorded sort when the device
+//! supports them inside encoders, from the wall time between the submission and its completion otherwise (the
+//! `source` column). Each configuration (algorithm, subgroup fallback) gets its own app sized to the largest sweep.
+//!
+//! The time from the creation of the app of a configuration to its first `LoadState::Loaded` is printed as it loads.
+//! Bevy 0.15 compiles the pipelines of the `RadixSortPlugin` without a wgpu pipeline cache, so this time is the same
+//! on every run.
+//!
+//! The `PersistentPipelineCache` (feature `pipeline_cache`) only speeds up the pipelines of a `RadixSortCore`.
+//! `--pipeline-cache cold` deletes the stored blob of the adapter, then times a core from the creation of its cache
+//! to the completion of its first sort, its first-`Loaded` latency, and stores the blob. `--pipeline-cache warm`
+//! times the same from the stored blob, run it after a cold run:
+//!
+//! ```text
+//! cargo run --release --features pipeline_cache --example gpu_sort_bench -- --sizes 1M --pipeline-cache cold
+//! cargo run --release --features pipeline_cache --example gpu_sort_bench -- --sizes 1M --pipeline-cache warm
+//! ```
+//!
+//! The driver may keep a shader cache of its own, cold only means that the persistent cache starts empty.
+//!
+//! `run` counts the digit histograms of all its passes in a single read of the keys before them (`--histograms
+//! fused`). `--histograms per-pass` sorts the same global buffers with a `RadixSortCore` instead, whose passes scan
+//! the histogram of their digit themselves, to compare both on large inputs (radix only):
+//!
+//! ```text
+//! cargo run --release --example gpu_sort_bench -- --sizes 4M,16M,32M --histograms fused,per-pass --repeats 20
+//! ```
+
+use std::{fmt::Write as _, ops::Range, time::Instant};
+
+use bevy::{
+    prelude::*,
+    render::{
+        RenderApp, RenderPlugin,
+        render_asset::RenderAssets,
+        render_resource::{
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, CommandEncoderDescriptor,
+            PipelineCache,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+    window::ExitCondition,
+};
+use bevy_radix_sort::{
+    BufferReadback, RadixSortBuffers, RadixSortCore, RadixSortCoreBindGroups, SortJobUpload,
+    global_blocks_buffer, prelude::*, supports_job_timestamps,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType};
+
+/// The updates to wait for the pipelines of a configuration before giving up on it.
+const MAX_LOAD_UPDATES: u32 = 1000;
+
+const USAGE: &str = "\
+usage: gpu_sort_bench [--sizes 64k,256k,1M,4M,16M] [--passes 4] [--algorithms radix|bitonic,...]
+                      [--subgroup-fallback off|on,...] [--uploads off|serial|early,...] [--repeats 10]
+                      [--warmup 3] [--csv gpu_sort_bench.csv] [--pipeline-cache off|cold|warm]
+                      [--histograms fused|per-pass,...]";
+
+#[derive(Debug, Clone)]
+struct BenchArgs {
+    sizes: Vec<u32>,
+    /// Each `n` runs the passes `0..n`
+    passes: Vec<u32>,
+    algorithms: Vec<Algorithm>,
+    subgroup_fallbacks: Vec<bool>,
+    uploads: Vec<Upload>,
+    repeats: u32,
+    warmup: u32,
+    csv: String,
+    /// Times the first sort of a `RadixSortCore` through the persistent pipeline cache
+    pipeline_cache: PipelineCacheRun,
+    histograms: Vec<Histograms>,
+}
+
+impl Default for BenchArgs {
+    fn default() -> Self {
+        Self {
+            sizes: vec![64 << 10, 256 << 10, 1 << 20, 4 << 20, 16 << 20],
+            passes: vec![4],
+            algorithms: vec![Algorithm::Radix],
+            subgroup_fallbacks: vec![false],
+            uploads: vec![Upload::Off],
+            repeats: 10,
+            warmup: 3,
+            csv: "gpu_sort_bench.csv".to_string(),
+            pipeline_cache: PipelineCacheRun::Off,
+            histograms: vec![Histograms::Fused],
+        }
+    }
+}
+
+impl BenchArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(USAGE.to_string());
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing the value of {arg}\n{USAGE}"))?;
+
+            match arg.as_str() {
+                "--sizes" => parsed.sizes = parse_list(&value, parse_size)?,
+                "--passes" => parsed.passes = parse_list(&value, parse_passes)?,
+                "--algorithms" => parsed.algorithms = parse_list(&value, parse_algorithm)?,
+                "--subgroup-fallback" => {
+                    parsed.subgroup_fallbacks = parse_list(&value, parse_switch)?
+                }
+                "--uploads" => parsed.uploads = parse_list(&value, parse_upload)?,
+                "--repeats" => parsed.repeats = parse_number(&value)?.max(1),
+                "--warmup" => parsed.warmup = parse_number(&value)?,
+                "--csv" => parsed.csv = value,
+                "--pipeline-cache" => parsed.pipeline_cache = parse_pipeline_cache(&value)?,
+                "--histograms" => parsed.histograms = parse_list(&value, parse_histograms)?,
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn parse_list<T>(value: &str, parse: fn(&str) -> Result<T, String>) -> Result<Vec<T>, String> {
+    value.split(',').map(|item| parse(item.trim())).collect()
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid number {value}"))
+}
+
+/// `65536`, `64k` or `16M`.
+fn parse_size(value: &str) -> Result<u32, String> {
+    let (digits, shift) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 10),
+        Some('m' | 'M') => (&value[..value.len() - 1], 20),
+        _ => (value, 0),
+    };
+
+    parse_number(digits)?
+        .checked_mul(1 << shift)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size {value}"))
+}
+
+fn parse_passes(value: &str) -> Result<u32, String> {
+    match parse_number(value)? {
+        passes @ 1..=4 => Ok(passes),
+        _ => Err(format!("invalid pass count {value}, expected 1 to 4")),
+    }
+}
+
+fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    match value {
+        "radix" => Ok(Algorithm::Radix),
+        "bitonic" => Ok(Algorithm::Bitonic),
+        _ => Err(format!(
+            "unknown algorithm {value}, expected radix or bitonic"
+        )),
+    }
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("invalid switch {value}, expected on or off")),
+    }
+}
+
+fn parse_upload(value: &str) -> Result<Upload, String> {
+    match value {
+        "off" => Ok(Upload::Off),
+        "serial" => Ok(Upload::Serial),
+        "early" => Ok(Upload::Early),
+        _ => Err(format!(
+            "unknown upload {value}, expected off, serial or early"
+        )),
+    }
+}
+
+fn parse_pipeline_cache(value: &str) -> Result<PipelineCacheRun, String> {
+    match value {
+        "off" => Ok(PipelineCacheRun::Off),
+        "cold" => Ok(PipelineCacheRun::Cold),
+        "warm" => Ok(PipelineCacheRun::Warm),
+        _ => Err(format!(
+            "unknown pipeline cache run {value}, expected off, cold or warm"
+        )),
+    }
+}
+
+fn parse_histograms(value: &str) -> Result<Histograms, String> {
+    match value {
+        "fused" => Ok(Histograms::Fused),
+        "per-pass" => Ok(Histograms::PerPass),
+        _ => Err(format!(
+            "unknown histograms {value}, expected fused or per-pass"
+        )),
+    }
+}
+
+/// Whether the stored pipeline cache is deleted before the first sort of a `RadixSortCore`, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipelineCacheRun {
+    Off,
+    Cold,
+    Warm,
+}
+
+/// How the input reaches the global buffers, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Upload {
+    /// Written and completed before the measure
+    Off,
+    /// Copied in the sort encoder
+    Serial,
+    /// Copied in a command buffer submitted ahead of the sort
+    Early,
+}
+
+impl Upload {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Serial => "serial",
+            Self::Early => "early",
+        }
+    }
+}
+
+/// Where the passes of a radix sort find where each radix starts, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Histograms {
+    /// `run`, the digit histograms counted once before the passes
+    Fused,
+    /// A `RadixSortCore`, each pass scans the histogram of its digit
+    PerPass,
+}
+
+impl Histograms {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Fused => "fused",
+            Self::PerPass => "per-pass",
+        }
+    }
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::Radix => "radix",
+        Algorithm::Bitonic => "bitonic",
+    }
+}
+
+/// A sort and the configuration it ran with.
+#[derive(Debug, Clone)]
+struct Case {
+    algorithm: Algorithm,
+    subgroup_fallback: bool,
+    upload: Upload,
+    histograms: Histograms,
+    keys: u32,
+    pass_range: Range<u32>,
+}
+
+/// The duration of a repetition of a [`Case`].
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    millis: f64,
+    timestamps: bool,
+}
+
+fn main() {
+    let args = match BenchArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    };
+
+    let mut csv = String::from(
+        "algorithm,subgroup_fallback,upload,histograms,keys,passes,repeat,millis,source\n",
+    );
+    let mut summary = Vec::new();
+
+    for &algorithm in &args.algorithms {
+        for &subgroup_fallback in &args.subgroup_fallbacks {
+            let Some(app) = create_bench_app(&args, algorithm, subgroup_fallback) else {
+                continue;
+            };
+            if args.pipeline_cache != PipelineCacheRun::Off {
+                time_core_first_sort(&app, subgroup_fallback, args.pipeline_cache);
+            }
+
+            for &upload in &args.uploads {
+                for &histograms in &args.histograms {
+                    if histograms == Histograms::PerPass && algorithm != Algorithm::Radix {
+                        continue;
+                    }
+
+                    for &keys in &args.sizes {
+                        for &passes in &args.passes {
+                            let case = Case {
+                                algorithm,
+                                subgroup_fallback,
+                                upload,
+                                histograms,
+                                keys,
+                                pass_range: 0..passes,
+                            };
+                            let samples = run_case(&app, &case, &args);
+
+                            for (repeat, sample) in samples.iter().enumerate() {
+                                let source = if sample.timestamps {
+                                    "timestamps"
+                                } else {
+                                    "wall"
+                                };
+                                writeln!(
+                                    csv,
+                                    "{},{},{},{},{},{},{},{:.4},{}",
+                                    algorithm_name(algorithm),
+                                    subgroup_fallback,
+                                    upload.name(),
+                                    histograms.name(),
+                                    keys,
+                                    passes,
+                                    repeat,
+                                    sample.millis,
+                                    source,
+                                )
+                                .unwrap();
+                            }
+                            summary.push((case, samples));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Err(err) = std::fs::write(&args.csv, csv) {
+        eprintln!("failed to write {}: {err}", args.csv);
+    }
+
+    print_summary(&summary);
+    println!("samples written to {}", args.csv);
+}
+
+/// Creates a headless app sorting with the configuration, `None` if its pipelines don't load.
+fn create_bench_app(
+    args: &BenchArgs,
+    algorithm: Algorithm,
+    subgroup_fallback: bool,
+) -> Option<App> {
+    let max_keys = args.sizes.iter().copied().max().unwrap_or(1);
+    let settings = match RadixSortSettings::builder()
+        .max_keys(max_keys)
+        .algorithm(algorithm)
+        .subgroup_fallback(subgroup_fallback)
+        .build()
+    {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("skipping {}: {err:?}", algorithm_name(algorithm));
+            return None;
+        }
+    };
+
+    let start = Instant::now();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(RenderPlugin {
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default())
+        .add_plugins(RadixSortPlugin { settings });
+
+    app.finish();
+    app.cleanup();
+
+    for _ in 0..MAX_LOAD_UPDATES {
+        app.update();
+
+        let world = app.sub_app(RenderApp).world();
+        match check_load_state(world) {
+            LoadState::Loaded if world.contains_resource::<RadixSortBindGroup>() => {
+                println!(
+                    "{} (subgroup fallback {subgroup_fallback}) loaded in {:.1} ms (no pipeline cache)",
+                    algorithm_name(algorithm),
+                    start.elapsed().as_secs_f64() * 1e3
+                );
+                return Some(app);
+            }
+            LoadState::Failed(err) => {
+                eprintln!(
+                    "skipping {} (subgroup fallback {subgroup_fallback}): {err}",
+                    algorithm_name(algorithm)
+                );
+                return None;
+            }
+            LoadState::FallbackCpu => {
+                eprintln!("skipping {}: no GPU backend", algorithm_name(algorithm));
+                return None;
+            }
+            _ => {}
+        }
+    }
+
+    eprintln!(
+        "skipping {} (subgroup fallback {subgroup_fallback}): the pipelines didn't load",
+        algorithm_name(algorithm)
+    );
+    None
+}
+
+/// The keys of the first sort of [`time_core_first_sort`].
+#[cfg(feature = "pipeline_cache")]
+const FIRST_SORT_KEYS: u32 = 1 << 16;
+
+/// Times a `RadixSortCore` from the creation of the persistent pipeline cache of the adapter to the completion of
+/// its first sort, cold after deleting the stored blob, and stores the blob for the next runs.
+#[cfg(feature = "pipeline_cache")]
+fn time_core_first_sort(app: &App, subgroup_fallback: bool, run: PipelineCacheRun) {
+    use bevy::render::{render_resource::WgpuAdapterInfo, renderer::RenderAdapterInfo};
+    use bevy_radix_sort::{PersistentPipelineCache, PipelineCacheKey, blocks_buffer_size};
+
+    let world = app.sub_app(RenderApp).world();
+    let render_device = world.resource::<RenderDevice>();
+    let render_queue = world.resource::<RenderQueue>();
+    let device = render_device.wgpu_device();
+    let Some(persistent) = PersistentPipelineCache::in_platform_cache_dir() else {
+        eprintln!("pipeline cache: the platform has no cache directory");
+        return;
+    };
+    let adapter_info: &WgpuAdapterInfo = world.resource::<RenderAdapterInfo>();
+    let key = PipelineCacheKey::from(adapter_info);
+    let subgroup_size = world.get_resource::<SubgroupSize>().map_or(0, u32::from);
+    if subgroup_size == 0 && !subgroup_fallback {
+        eprintln!("pipeline cache: no subgroup size");
+        return;
+    }
+
+    if run == PipelineCacheRun::Cold {
+        let _ = std::fs::remove_file(persistent.path(&key));
+    } else if persistent.load_data(&key).is_none() {
+        eprintln!(
+            "pipeline cache: no stored blob, run --pipeline-cache cold first, timing a cold start"
+        );
+    }
+    let warm = persistent.load_data(&key).is_some();
+
+    // The buffers aren't part of the latency, an app creates them either way
+    let mut rng = StdRng::seed_from_u64(197);
+    let keys: Vec<u32> = (0..FIRST_SORT_KEYS).map(|_| rng.r#gen()).collect();
+    let size = (FIRST_SORT_KEYS as usize * std::mem::size_of::<u32>()) as u64;
+    let create_buffer = |size| {
+        render_device.create_buffer(&BufferDescriptor {
+            label: Some("gpu_sort_bench: first sort buffer"),
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    };
+    let (eve_keys, eve_vals, odd_keys, odd_vals) = (
+        create_buffer(size),
+        create_buffer(size),
+        create_buffer(size),
+        create_buffer(size),
+    );
+    let blocks = create_buffer(blocks_buffer_size(FIRST_SORT_KEYS));
+    render_queue.write_buffer(&eve_keys, 0, bytemuck::cast_slice(&keys));
+    render_queue.submit([]);
+    render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+
+    let start = Instant::now();
+    let Some(cache) = persistent.create_pipeline_cache(device, &key) else {
+        eprintln!("pipeline cache: the device doesn't support Features::PIPELINE_CACHE");
+        return;
+    };
+    let cache_created = start.elapsed();
+    let core =
+        RadixSortCore::with_pipeline_cache(device, subgroup_size, subgroup_fallback, Some(&cache));
+    let pipelines_created = start.elapsed();
+
+    let bind_groups = core.create_bind_groups(
+        device,
+        &RadixSortBuffers {
+            eve_keys: &eve_keys,
+            eve_vals: &eve_vals,
+            blocks: &blocks,
+            odd_keys: &odd_keys,
+            odd_vals: &odd_vals,
+        },
+    );
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("gpu_sort_bench: first sort command encoder"),
+    });
+    core.record(
+        &mut encoder,
+        &bind_groups,
+        render_device.limits().max_compute_workgroups_per_dimension,
+        FIRST_SORT_KEYS,
+        0..4,
+        true,
+        true,
+    );
+    render_queue.submit([encoder.finish()]);
+    render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+    let sorted = start.elapsed();
+
+    println!(
+        "RadixSortCore (subgroup fallback {subgroup_fallback}) first sort done {} in {:.1} ms \
+         (cache {:.1} ms, pipelines {:.1} ms)",
+        if warm { "warm" } else { "cold" },
+        sorted.as_secs_f64() * 1e3,
+        cache_created.as_secs_f64() * 1e3,
+        (pipelines_created - cache_created).as_secs_f64() * 1e3,
+    );
+
+    if let Err(err) = persistent.store(&key, &cache) {
+        eprintln!(
+            "pipeline cache: failed to store {}: {err}",
+            persistent.path(&key).display()
+        );
+    }
+}
+
+#[cfg(not(feature = "pipeline_cache"))]
+fn time_core_first_sort(_app: &App, _subgroup_fallback: bool, _run: PipelineCacheRun) {
+    eprintln!("--pipeline-cache needs the pipeline_cache feature");
+}
+
+/// Sorts random keys within the bits of the passes, `warmup` times unmeasured, then `repeats` times.
+fn run_case(app: &App, case: &Case, args: &BenchArgs) -> Vec<Sample> {
+    let world = app.sub_app(RenderApp).world();
+    let render_device = world.resource::<RenderDevice>();
+    let render_queue = world.resource::<RenderQueue>();
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+    let radix_sort_bind_group = world.resource::<RadixSortBindGroup>();
+    let storage_buffers = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+
+    let max_compute_workgroups_per_dimension =
+        render_device.limits().max_compute_workgroups_per_dimension;
+    let timestamps =
+        supports_job_timestamps(render_device).then(|| TimestampPair::new(render_device));
+
+    let key_mask = u32::MAX >> (32 - 8 * case.pass_range.end);
+    let mut rng = StdRng::seed_from_u64(case.keys as u64);
+    let keys: Vec<u32> = (0..case.keys)
+        .map(|_| rng.r#gen::<u32>() & key_mask)
+        .collect();
+    let keys_buffer = global_keys_buffer(storage_buffers, true).unwrap();
+
+    // The source of the uploads, sorted with their vals instead of the indices
+    let vals: Vec<u32> = (0..case.keys).collect();
+    let upload = (case.upload != Upload::Off).then(|| SortJobUpload {
+        keys: create_upload_buffer(render_device, &keys),
+        vals: Some(create_upload_buffer(render_device, &vals)),
+    });
+    let record_upload = |encoder: &mut wgpu::CommandEncoder| {
+        if let Some(upload) = &upload {
+            upload.record(encoder, storage_buffers, case.keys, true, false);
+        }
+    };
+
+    // The same kernels on the same buffers, without the fused digit histograms
+    let core = match case.histograms {
+        Histograms::Fused => None,
+        Histograms::PerPass => match create_core(world, case.subgroup_fallback) {
+            Some(core) => Some(core),
+            None => {
+                eprintln!("skipping the per-pass histograms: no subgroup size");
+                return Vec::new();
+            }
+        },
+    };
+
+    let mut samples = Vec::new();
+    for repeat in 0..args.warmup + args.repeats {
+        if upload.is_none() {
+            // Uploaded and completed before the measure
+            render_queue.write_buffer(keys_buffer, 0, bytemuck::cast_slice(&keys));
+            render_queue.submit([]);
+            render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        }
+
+        let mut upload_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("gpu_sort_bench: upload command encoder"),
+        });
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("gpu_sort_bench: sort command encoder"),
+        });
+        let submitted = Instant::now();
+
+        // The measure starts in the first command buffer of the submission
+        let first_encoder = if case.upload == Upload::Early {
+            &mut upload_encoder
+        } else {
+            &mut encoder
+        };
+        if let Some(timestamps) = &timestamps {
+            timestamps.begin(first_encoder);
+        }
+        record_upload(first_encoder);
+        match &core {
+            Some((core, bind_groups)) => core.record(
+                &mut encoder,
+                bind_groups,
+                max_compute_workgroups_per_dimension,
+                case.keys,
+                case.pass_range.clone(),
+                upload.is_none(),
+                true,
+            ),
+            None => bevy_radix_sort::run(
+                &mut encoder,
+                pipeline_cache,
+                radix_sort_pipeline,
+                radix_sort_bind_group,
+                max_compute_workgroups_per_dimension,
+                case.keys,
+                case.pass_range.clone(),
+                upload.is_none(),
+                true,
+            ),
+        }
+        let readback = timestamps
+            .as_ref()
+            .map(|timestamps| timestamps.end(render_device, &mut encoder));
+
+        render_queue.submit([upload_encoder.finish(), encoder.finish()]);
+        render_device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        let wall = submitted.elapsed().as_secs_f64() * 1e3;
+
+        if repeat < args.warmup {
+            continue;
+        }
+        samples.push(match readback {
+            Some(readback) => Sample {
+                millis: TimestampPair::read(render_device, render_queue, readback),
+                timestamps: true,
+            },
+            None => Sample {
+                millis: wall,
+                timestamps: false,
+            },
+        });
+    }
+
+    samples
+}
+
+/// A `RadixSortCore` of the default tile bound to the global buffers, `None` without a subgroup size to compile it
+/// with.
+fn create_core(
+    world: &World,
+    subgroup_fallback: bool,
+) -> Option<(RadixSortCore, RadixSortCoreBindGroups)> {
+    let render_device = world.resource::<RenderDevice>();
+    let storage_buffers = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+    let subgroup_size = world.get_resource::<SubgroupSize>().map_or(0, u32::from);
+    if subgroup_size == 0 && !subgroup_fallback {
+        return None;
+    }
+
+    let device = render_device.wgpu_device();
+    let core = RadixSortCore::new(device, subgroup_size, subgroup_fallback);
+    let bind_groups = core.create_bind_groups(
+        device,
+        &RadixSortBuffers {
+            eve_keys: global_keys_buffer(storage_buffers, true)?,
+            eve_vals: global_vals_buffer(storage_buffers, true)?,
+            blocks: global_blocks_buffer(storage_buffers)?,
+            odd_keys: global_keys_buffer(storage_buffers, false)?,
+            odd_vals: global_vals_buffer(storage_buffers, false)?,
+        },
+    );
+
+    Some((core, bind_groups))
+}
+
+fn create_upload_buffer(
+    render_device: &RenderDevice,
+    contents: &[u32],
+) -> bevy::render::render_resource::Buffer {
+    render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("gpu_sort_bench: upload buffer"),
+        usage: BufferUsages::COPY_SRC,
+        contents: bytemuck::cast_slice(contents),
+    })
+}
+
+/// The timestamps written before and after the sort.
+struct TimestampPair {
+    query_set: QuerySet,
+    resolve: bevy::render::render_resource::Buffer,
+}
+
+impl TimestampPair {
+    fn new(render_device: &RenderDevice) -> Self {
+        Self {
+            query_set: render_device
+                .wgpu_device()
+                .create_query_set(&QuerySetDescriptor {
+                    label: Some("gpu_sort_bench: timestamps"),
+                    ty: QueryType::Timestamp,
+                    count: 2,
+                }),
+            resolve: render_device.create_buffer(&BufferDescriptor {
+                label: Some("gpu_sort_bench: timestamps resolve buffer"),
+                size: 2 * std::mem::size_of::<u64>() as u64,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    fn begin(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    /// Resolves the pair and copies it out, a timestamp is 2 u32s.
+    fn end(
+        &self,
+        render_device: &RenderDevice,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> BufferReadback {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve, 0);
+        BufferReadback::new(render_device, encoder, &self.resolve, 0, 4)
+    }
+
+    /// The milliseconds between the timestamps.
+    fn read(
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        readback: BufferReadback,
+    ) -> f64 {
+        let words = readback
+            .wait(render_device)
+            .expect("the timestamps readback should map");
+        let begin = words[0] as u64 | (words[1] as u64) << 32;
+        let end = words[2] as u64 | (words[3] as u64) << 32;
+
+        end.saturating_sub(begin) as f64 * render_queue.get_timestamp_period() as f64 / 1e6
+    }
+}
+
+fn print_summary(summary: &[(Case, Vec<Sample>)]) {
+    println!(
+        "{:<8} {:<9} {:<7} {:<10} {:>10} {:>6} {:>10} {:>10} {:>10} {:>12}  source",
+        "algo",
+        "fallback",
+        "upload",
+        "histograms",
+        "keys",
+        "passes",
+        "min ms",
+        "median ms",
+        "mean ms",
+        "Mkeys/s"
+    );
+
+    for (case, samples) in summary {
+        if samples.is_empty() {
+            continue;
+        }
+
+        let mut millis: Vec<f64> = samples.iter().map(|sample| sample.millis).collect();
+        millis.sort_by(f64::total_cmp);
+        let median = millis[millis.len() / 2];
+        let mean = millis.iter().sum::<f64>() / millis.len() as f64;
+        let throughput = case.keys as f64 / (median * 1e3);
+        let source = if samples[0].timestamps {
+            "timestamps"
+        } else {
+            "wall"
+        };
+
+        println!(
+            "{:<8} {:<9} {:<7} {:<10} {:>10} {:>6} {:>10.3} {:>10.3} {:>10.3} {:>12.1}  {source}",
+            algorithm_name(case.algorithm),
+            if case.subgroup_fallback { "on" } else { "off" },
+            case.upload.name(),
+            case.histograms.name(),
+            case.keys,
+            format!("{:?}", case.pass_range),
+            millis[0],
+            median,
+            mean,
+            throughput,
+        );
+    }
+}
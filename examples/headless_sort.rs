@@ -0,0 +1,247 @@
+//! Sorts random keys from the command line, without a window: uploads them, sorts them on the GPU, reads them back
+//! and checks them against [`sort_on_cpu`].
+//!
+//! ```text
+//! cargo run --release --example headless_sort --features cpu_fallback -- 1000000 [seed] [--digest]
+//! ```
+//!
+//! With `--digest`, only the [`SortDigest`] of the sorted keys and vals is read back and compared with
+//! [`digest_on_cpu`], 16 bytes instead of the whole output, e.g. on CI runners with slow readbacks.
+//!
+//! Exits with a non-zero status when the results differ, or when the sort can't load, so it doubles as a smoke test
+//! on machines with a GPU.
+
+use std::{process::ExitCode, time::Instant};
+
+use bevy::{
+    prelude::*,
+    render::{
+        RenderApp, RenderPlugin,
+        render_asset::RenderAssets,
+        render_resource::{
+            BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Maintain, PipelineCache,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+    window::ExitCondition,
+};
+use bevy_radix_sort::{
+    BufferReadback, DIGEST_BUFFER_SIZE, DigestPipeline, DigestPlugin, SortDigest, digest_on_cpu,
+    prelude::*, sort_on_cpu,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+const DEFAULT_NUMBER_OF_KEYS: u32 = 1_000_000;
+
+/// The updates to wait for the pipelines before giving up.
+const MAX_LOAD_UPDATES: u32 = 1000;
+
+fn main() -> ExitCode {
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let digest = match flags.as_slice() {
+        [] => false,
+        [flag] if flag == "--digest" => true,
+        _ => {
+            eprintln!("usage: headless_sort [number_of_keys > 0] [seed] [--digest]");
+            return ExitCode::from(2);
+        }
+    };
+    let mut args = args.into_iter();
+    let (Some(number_of_keys), Some(seed)) = (
+        args.next()
+            .map_or(Some(DEFAULT_NUMBER_OF_KEYS), |arg| arg.parse().ok())
+            .filter(|&count| count > 0),
+        args.next().map_or(Some(0), |arg| arg.parse::<u64>().ok()),
+    ) else {
+        eprintln!("usage: headless_sort [number_of_keys > 0] [seed] [--digest]");
+        return ExitCode::from(2);
+    };
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(RenderPlugin {
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default())
+        .add_plugins(RadixSortPlugin {
+            settings: number_of_keys.into(),
+        })
+        .add_plugins(DigestPlugin);
+
+    let started = Instant::now();
+    app.finish();
+    app.cleanup();
+    if let Err(err) = wait_until_loaded(&mut app) {
+        eprintln!("the sort didn't load: {err}");
+        return ExitCode::FAILURE;
+    }
+    println!("loaded in {:?}", started.elapsed());
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let keys: Vec<u32> = (0..number_of_keys).map(|_| rng.r#gen()).collect();
+
+    let output = sort_on_gpu(&app, &keys, digest);
+
+    let started = Instant::now();
+    let mut expected_keys = keys.clone();
+    let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+    sort_on_cpu(&mut expected_keys, Some(&mut expected_vals), &(0..4));
+    println!("sorted on the CPU in {:?}", started.elapsed());
+
+    let (sorted_keys, sorted_vals) = match output {
+        SortOutput::Digest(digest) => {
+            let expected = digest_on_cpu(&expected_keys, Some(&expected_vals));
+            return if digest == expected {
+                println!("{number_of_keys} keys sorted correctly, digest {digest}");
+                ExitCode::SUCCESS
+            } else {
+                eprintln!("digest {digest} instead of {expected}");
+                ExitCode::FAILURE
+            };
+        }
+        SortOutput::Sorted(keys, vals) => (keys, vals),
+    };
+
+    // The sort is stable, the vals (the original indices) must match too
+    match (0..keys.len())
+        .find(|&i| sorted_keys[i] != expected_keys[i] || sorted_vals[i] != expected_vals[i])
+    {
+        None => {
+            println!("{number_of_keys} keys sorted correctly");
+            ExitCode::SUCCESS
+        }
+        Some(i) => {
+            eprintln!(
+                "mismatch at {i}: (key {}, val {}) instead of (key {}, val {})",
+                sorted_keys[i], sorted_vals[i], expected_keys[i], expected_vals[i]
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn wait_until_loaded(app: &mut App) -> Result<(), String> {
+    for _ in 0..MAX_LOAD_UPDATES {
+        app.update();
+
+        let world = app.sub_app(RenderApp).world();
+        match check_load_state(world) {
+            LoadState::Loaded
+                if world.contains_resource::<RadixSortBindGroup>()
+                    && world
+                        .resource::<DigestPipeline>()
+                        .load_state(world.resource::<PipelineCache>())
+                        == LoadState::Loaded =>
+            {
+                return Ok(());
+            }
+            LoadState::Failed(err) => return Err(err),
+            LoadState::FallbackCpu => return Err("no GPU backend".to_string()),
+            _ => {}
+        }
+    }
+
+    Err("the pipelines didn't compile in time".to_string())
+}
+
+/// What [`sort_on_gpu`] reads back.
+enum SortOutput {
+    Sorted(Vec<u32>, Vec<u32>),
+    Digest(SortDigest),
+}
+
+/// Uploads `keys` into the global buffers, argsorts them and reads the sorted keys and vals back, or only their
+/// digest, blocking.
+fn sort_on_gpu(app: &App, keys: &[u32], digest: bool) -> SortOutput {
+    let world = app.sub_app(RenderApp).world();
+    let render_device = world.resource::<RenderDevice>();
+    let render_queue = world.resource::<RenderQueue>();
+    let storage_buffers = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+
+    let number_of_keys = keys.len() as u32;
+    let options = RadixSortRunOptions::default();
+
+    let started = Instant::now();
+    render_queue.write_buffer(
+        global_keys_buffer(storage_buffers, true).unwrap(),
+        0,
+        bytemuck::cast_slice(keys),
+    );
+
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("headless_sort: command encoder"),
+    });
+    run_with_options(
+        &mut encoder,
+        pipeline_cache,
+        world.resource::<RadixSortPipeline>(),
+        world.resource::<RadixSortBindGroup>(),
+        render_device.limits().max_compute_workgroups_per_dimension,
+        number_of_keys,
+        &options,
+        true,
+    );
+
+    if digest {
+        let digest_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("headless_sort: digest buffer"),
+            size: DIGEST_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        world.resource::<DigestPipeline>().record_digest_sorted(
+            &mut encoder,
+            render_device,
+            pipeline_cache,
+            storage_buffers,
+            &options.pass_range,
+            true,
+            number_of_keys,
+            &digest_buffer,
+            render_device.limits().max_compute_workgroups_per_dimension,
+        );
+        let digest_readback =
+            BufferReadback::new(render_device, &mut encoder, &digest_buffer, 0, 4);
+
+        render_queue.submit([encoder.finish()]);
+        render_device.poll(Maintain::Wait).panic_on_timeout();
+        println!(
+            "uploaded, sorted and digested {number_of_keys} keys on the GPU in {:?}",
+            started.elapsed()
+        );
+
+        return SortOutput::Digest(SortDigest::from_words(
+            &digest_readback.wait(render_device).unwrap(),
+        ));
+    }
+
+    let sorted_keys = sorted_keys_buffer(storage_buffers, &options.pass_range, true).unwrap();
+    let sorted_vals = sorted_vals_buffer(storage_buffers, &options.pass_range, true).unwrap();
+    let keys_readback =
+        BufferReadback::new(render_device, &mut encoder, sorted_keys, 0, keys.len());
+    let vals_readback =
+        BufferReadback::new(render_device, &mut encoder, sorted_vals, 0, keys.len());
+
+    render_queue.submit([encoder.finish()]);
+    render_device.poll(Maintain::Wait).panic_on_timeout();
+    println!(
+        "uploaded and sorted {number_of_keys} keys on the GPU in {:?}",
+        started.elapsed()
+    );
+
+    SortOutput::Sorted(
+        keys_readback.wait(render_device).unwrap(),
+        vals_readback.wait(render_device).unwrap(),
+    )
+}
@@ -0,0 +1,266 @@
+//! Sorts splats by depth and writes their 16-byte instance records in the last scatter of the sort, instead of
+//! sorting the indices and gathering the records in a pass of their own.
+//!
+//! The hook in `payload_scatter.wgsl` reads the splat of each sorted index (the vals of the sort) and writes its
+//! compacted record at the sorted position. The records are read back once and checked against the depth order.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayoutEntries, Buffer, BufferAddress,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, Maintain, MapMode, PipelineCache,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+use bevy_radix_sort::{
+    PayloadScatterPipeline, PayloadScatterPlugin, f32_to_ordered_u32, prelude::*,
+    run_with_payload_scatter,
+};
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+const NUMBER_OF_SPLATS: u32 = 100_000;
+
+/// Must match `Splat` in `payload_scatter.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Splat {
+    position: [f32; 3],
+    radius: f32,
+    color: [f32; 4],
+}
+
+/// Must match `Instance` in `payload_scatter.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct Instance {
+    position: [f32; 3],
+    packed_color: u32,
+}
+
+const INSTANCES_SIZE: BufferAddress =
+    (NUMBER_OF_SPLATS as usize * std::mem::size_of::<Instance>()) as BufferAddress;
+
+fn main() {
+    let mut rng = rand::thread_rng();
+    let splats: Vec<Splat> = (0..NUMBER_OF_SPLATS)
+        .map(|_| Splat {
+            position: [
+                rng.gen_range(-20.0..20.0),
+                rng.gen_range(-20.0..20.0),
+                rng.gen_range(-20.0..20.0),
+            ],
+            radius: rng.gen_range(0.05..0.5),
+            color: [rng.r#gen(), rng.r#gen(), rng.r#gen(), 0.5],
+        })
+        .collect();
+    // Sorts by z, the vals are initialized to the indices of the splats by the sort
+    let keys = splats
+        .iter()
+        .map(|splat| f32_to_ordered_u32(splat.position[2]))
+        .collect();
+
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(NUMBER_OF_SPLATS).with_initial_keys(keys),
+        })
+        .add_plugins(PayloadScatterPlugin {
+            source: include_str!("payload_scatter.wgsl").into(),
+            bind_group_layout_entries: BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<Splat>(false),
+                    storage_buffer::<Instance>(false),
+                ),
+            )
+            .to_vec(),
+        })
+        .add_plugins(SplatsPlugin { splats })
+        .run();
+}
+
+struct SplatsPlugin {
+    splats: Vec<Splat>,
+}
+
+impl Plugin for SplatsPlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            read_back_instances
+                .after(RenderSet::Render)
+                .run_if(resource_exists::<SplatBuffers>),
+        );
+
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(SplatsNodeLabel, SplatsNode::default());
+        graph.add_node_edge(SplatsNodeLabel, bevy::render::graph::CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        let render_device = render_app.world().resource::<RenderDevice>();
+        let payload_scatter_pipeline = render_app.world().resource::<PayloadScatterPipeline>();
+
+        let splats = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("payload_scatter: splats buffer"),
+            contents: bytemuck::cast_slice(&self.splats),
+            usage: BufferUsages::STORAGE,
+        });
+        let instances = render_device.create_buffer(&BufferDescriptor {
+            label: Some("payload_scatter: instances buffer"),
+            size: INSTANCES_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = render_device.create_buffer(&BufferDescriptor {
+            label: Some("payload_scatter: readback buffer"),
+            size: INSTANCES_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = render_device.create_bind_group(
+            "payload_scatter: bind_group",
+            payload_scatter_pipeline.bind_group_layout(),
+            &BindGroupEntries::sequential((
+                splats.as_entire_binding(),
+                instances.as_entire_binding(),
+            )),
+        );
+
+        render_app.insert_resource(SplatBuffers {
+            bind_group,
+            instances,
+            readback,
+            sorted: AtomicBool::new(false),
+        });
+    }
+}
+
+#[derive(Resource)]
+struct SplatBuffers {
+    /// The group 1 of the hook
+    bind_group: BindGroup,
+    instances: Buffer,
+    readback: Buffer,
+    /// The sort ran and the instances were copied into `readback`, set by the node
+    sorted: AtomicBool,
+}
+
+fn read_back_instances(buffers: Res<SplatBuffers>, render_device: Res<RenderDevice>) {
+    if !buffers.sorted.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    let slice = buffers.readback.slice(..);
+    slice.map_async(MapMode::Read, |_| ());
+    render_device.poll(Maintain::wait()).panic_on_timeout();
+
+    {
+        let view = slice.get_mapped_range();
+        let instances: &[Instance] = bytemuck::cast_slice(&view);
+
+        let is_sorted = instances
+            .windows(2)
+            .all(|pair| pair[0].position[2] <= pair[1].position[2]);
+        info!("The first instances: {:?}", &instances[..4]);
+        info!("The instances are sorted by depth: {}", is_sorted);
+    }
+    buffers.readback.unmap();
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, RenderLabel)]
+struct SplatsNodeLabel;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+enum SplatsNodeState {
+    #[default]
+    OnLoad,
+    Sort,
+    /// The keys are only sorted once, the next sort would read the sorted keys
+    Done,
+}
+
+#[derive(Default)]
+struct SplatsNode {
+    state: SplatsNodeState,
+}
+
+impl render_graph::Node for SplatsNode {
+    fn update(&mut self, world: &mut World) {
+        match self.state {
+            SplatsNodeState::OnLoad => {
+                let pipeline_cache = world.resource::<PipelineCache>();
+                let load_states = [
+                    bevy_radix_sort::check_load_state(world),
+                    world
+                        .resource::<PayloadScatterPipeline>()
+                        .load_state(pipeline_cache),
+                ];
+
+                if let Some(LoadState::Failed(err)) = load_states
+                    .iter()
+                    .find(|load_state| matches!(load_state, LoadState::Failed(_)))
+                {
+                    panic!("{}", err);
+                }
+
+                if load_states
+                    .iter()
+                    .all(|load_state| *load_state == LoadState::Loaded)
+                {
+                    self.state = SplatsNodeState::Sort;
+                }
+            }
+            SplatsNodeState::Sort => self.state = SplatsNodeState::Done,
+            SplatsNodeState::Done => {}
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if self.state != SplatsNodeState::Sort {
+            return Ok(());
+        }
+
+        let max_compute_workgroups_per_dimension = world
+            .resource::<RenderDevice>()
+            .limits()
+            .max_compute_workgroups_per_dimension;
+        let buffers = world.resource::<SplatBuffers>();
+
+        let encoder = render_context.command_encoder();
+        run_with_payload_scatter(
+            encoder,
+            world.resource::<PipelineCache>(),
+            world.resource::<RadixSortPipeline>(),
+            world.resource::<RadixSortBindGroup>(),
+            world.resource::<PayloadScatterPipeline>(),
+            &buffers.bind_group,
+            max_compute_workgroups_per_dimension,
+            NUMBER_OF_SPLATS,
+            0..4,
+            true,
+            true,
+        );
+        encoder.copy_buffer_to_buffer(&buffers.instances, 0, &buffers.readback, 0, INSTANCES_SIZE);
+        buffers.sorted.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
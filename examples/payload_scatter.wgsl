@@ -0,0 +1,24 @@
+// The hook of the `payload_scatter` example, appended to the last scatter of the sort.
+//
+// The vals are the indices of the splats (`init_index`), the hook writes the compacted instance record of each
+// splat at its sorted position instead of the index.
+
+struct Splat {
+    position: vec3f,
+    radius: f32,
+    color: vec4f,
+}
+
+// Must match `Instance` in `payload_scatter.rs`, 16 bytes
+struct Instance {
+    position: vec3f,
+    packed_color: u32,
+}
+
+@group(1) @binding(0) var<storage, read      > splats: array<Splat>;
+@group(1) @binding(1) var<storage, read_write> instances: array<Instance>;
+
+fn write_payload(sorted_index: u32, key: u32, val: u32) {
+    let splat = splats[val];
+    instances[sorted_index] = Instance(splat.position, pack4x8unorm(splat.color));
+}
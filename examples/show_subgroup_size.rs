@@ -1,10 +1,10 @@
 use bevy::prelude::*;
-use bevy_radix_sort::{GetSubgroupSizePlugin, SubgroupSize};
+use bevy_radix_sort::prelude::*;
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_plugins(GetSubgroupSizePlugin)
+        .add_plugins(GetSubgroupSizePlugin::default())
         .add_systems(Startup, show_subgroup_size)
         .run();
 }
@@ -22,7 +22,7 @@ fn show_subgroup_size(mut commands: Commands, subgroup_size: Res<SubgroupSize>)
         },))
         .with_children(|parent| {
             parent.spawn((
-                Text::new(format!("subgroup_size: {}", subgroup_size.0)),
+                Text::new(format!("subgroup_size: {}", *subgroup_size)),
                 TextFont {
                     font_size: 30.0,
                     ..default()
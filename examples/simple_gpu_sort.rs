@@ -4,19 +4,14 @@ use bevy::{
         Extract, ExtractSchedule, Render, RenderApp, RenderSet,
         render_asset::RenderAssets,
         render_graph::{self, RenderGraph, RenderLabel},
-        render_resource::{
-            Buffer, BufferAddress, BufferDescriptor, BufferUsages, Maintain, MapMode, PipelineCache,
-        },
-        renderer::{RenderContext, RenderDevice},
+        render_resource::{CommandEncoderDescriptor, PipelineCache},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
         storage::GpuShaderStorageBuffer,
     },
+    tasks::AsyncComputeTaskPool,
 };
 use bevy_egui::{EguiContexts, EguiPlugin, egui};
-use bevy_radix_sort::{
-    EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
-    GetSubgroupSizePlugin, LoadState, RadixSortBindGroup, RadixSortPipeline, RadixSortPlugin,
-    RadixSortSettings,
-};
+use bevy_radix_sort::{BufferReadback, prelude::*};
 use rand::Rng;
 
 fn main() {
@@ -58,16 +53,15 @@ pub struct SimpleGpuSortPlugin {
 
 impl Plugin for SimpleGpuSortPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(GetSubgroupSizePlugin)
-            .add_plugins(RadixSortPlugin {
-                settings: self.max_number_of_keys.into(),
-            })
-            .init_resource::<SortCommand>()
-            .init_resource::<InputState>()
-            .add_systems(
-                Update,
-                (disable_requested, ui_system, handle_sort_event).chain(),
-            );
+        app.add_plugins(RadixSortPlugin {
+            settings: self.max_number_of_keys.into(),
+        })
+        .init_resource::<SortCommand>()
+        .init_resource::<InputState>()
+        .add_systems(
+            Update,
+            (disable_requested, ui_system, handle_sort_event).chain(),
+        );
 
         let render_app = app.sub_app_mut(RenderApp);
 
@@ -82,7 +76,7 @@ impl Plugin for SimpleGpuSortPlugin {
             )
             .add_systems(
                 Render,
-                write_radom_kvs_to_staging_bufs
+                write_random_kvs_to_gpu_storage_bufs
                     .in_set(RenderSet::PrepareResources)
                     .run_if(resource_exists::<SimpleGpuSortResource>),
             )
@@ -202,106 +196,68 @@ fn extract_sort_command(mut commands: Commands, sort_command: Extract<Res<SortCo
     });
 }
 
-fn write_radom_kvs_to_staging_bufs(
+fn write_random_kvs_to_gpu_storage_bufs(
     mut sort_resource: ResMut<SimpleGpuSortResource>,
-    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    storage_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
     sort_command: Res<SortCommand>,
 ) {
     if sort_command.requested {
-        sort_resource.write_random_kvs_to_staging_bufs(&device, sort_command.length);
+        sort_resource.write_random_kvs_to_gpu_storage_bufs(
+            &queue,
+            &storage_buffers,
+            sort_command.length,
+        );
     }
 }
 
 fn read_sorted_kvs_from_gpu_storage_bufs(
-    mut sort_resource: ResMut<SimpleGpuSortResource>,
+    sort_resource: Res<SimpleGpuSortResource>,
     device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    storage_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
     sort_command: Res<SortCommand>,
 ) {
     if sort_command.requested {
-        sort_resource.read_sorted_kvs_from_gpu_storage_bufs(&device);
+        sort_resource.read_sorted_kvs_from_gpu_storage_bufs(&device, &queue, &storage_buffers);
     }
 }
 
+// Writes go through `RenderQueue::write_buffer` and reads through `BufferReadback`, none of them blocks on a
+// mapping, which also keeps the example wasm32-safe.
 #[derive(Resource)]
 pub struct SimpleGpuSortResource {
-    // cpu-buffer -> gpu-staging-buffer -> gpu-destination-buffer
-    pub i_keys_buf: Buffer,
-    pub i_vals_buf: Buffer,
-    // gpu-source-buffer -> gpu-staging-buffer -> cpu-buffer
-    pub o_keys_buf: Buffer,
-    pub o_vals_buf: Buffer,
     // the length of the keys and vals
     pub length: usize,
 }
 
 impl SimpleGpuSortResource {
-    pub fn initialize(
-        mut commands: Commands,
-        device: Res<RenderDevice>,
-        radix_sort_settings: Res<RadixSortSettings>,
-    ) {
-        let max_number_of_keys = radix_sort_settings.max_number_of_keys() as usize;
-
-        let i_keys_buf = device.create_buffer(&BufferDescriptor {
-            label: Some("copy keys from cpu to gpu"),
-            size: (max_number_of_keys * std::mem::size_of::<u32>()) as BufferAddress,
-            usage: BufferUsages::COPY_SRC | BufferUsages::MAP_WRITE,
-            mapped_at_creation: false,
-        });
-
-        let i_vals_buf = device.create_buffer(&BufferDescriptor {
-            label: Some("copy vals from cpu to gpu"),
-            size: (max_number_of_keys * std::mem::size_of::<u32>()) as BufferAddress,
-            usage: BufferUsages::COPY_SRC | BufferUsages::MAP_WRITE,
-            mapped_at_creation: false,
-        });
-
-        let o_keys_buf = device.create_buffer(&BufferDescriptor {
-            label: Some("copy keys from gpu to cpu"),
-            size: (max_number_of_keys * std::mem::size_of::<u32>()) as BufferAddress,
-            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
-
-        let o_vals_buf = device.create_buffer(&BufferDescriptor {
-            label: Some("copy vals from gpu to cpu"),
-            size: (max_number_of_keys * std::mem::size_of::<u32>()) as BufferAddress,
-            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
-
-        commands.insert_resource(Self {
-            i_keys_buf,
-            i_vals_buf,
-            o_keys_buf,
-            o_vals_buf,
-            length: max_number_of_keys,
-        });
+    pub fn initialize(mut commands: Commands) {
+        commands.insert_resource(Self { length: 0 });
     }
 
-    pub fn write_random_kvs_to_staging_bufs(&mut self, device: &RenderDevice, length: usize) {
+    pub fn write_random_kvs_to_gpu_storage_bufs(
+        &mut self,
+        queue: &RenderQueue,
+        storage_buffers: &RenderAssets<GpuShaderStorageBuffer>,
+        length: usize,
+    ) {
         let mut rng = rand::thread_rng();
         let keys: Vec<u32> = (0..length)
             .map(|_| rng.gen_range(0..length as u32))
             .collect();
         let vals: Vec<u32> = (0u32..length as u32).collect();
 
-        let size = (length * std::mem::size_of::<u32>()) as BufferAddress;
-        let keys_slice = self.i_keys_buf.slice(0..size);
-        let vals_slice = self.i_vals_buf.slice(0..size);
-
-        keys_slice.map_async(MapMode::Write, |_| ());
-        vals_slice.map_async(MapMode::Write, |_| ());
-
-        device.poll(Maintain::wait()).panic_on_timeout();
-
-        keys_slice.get_mapped_range_mut()[..size as usize]
-            .copy_from_slice(bytemuck::cast_slice(&keys));
-        vals_slice.get_mapped_range_mut()[..size as usize]
-            .copy_from_slice(bytemuck::cast_slice(&vals));
+        let (Some(keys_buf), Some(vals_buf)) = (
+            global_keys_buffer(storage_buffers, true),
+            global_vals_buffer(storage_buffers, true),
+        ) else {
+            return;
+        };
 
-        self.i_keys_buf.unmap();
-        self.i_vals_buf.unmap();
+        // Applied before the commands of the frame are submitted
+        queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+        queue.write_buffer(vals_buf, 0, bytemuck::cast_slice(&vals));
 
         self.length = length;
 
@@ -310,29 +266,38 @@ impl SimpleGpuSortResource {
         info!("Vals: {:?}", &vals);
     }
 
-    pub fn read_sorted_kvs_from_gpu_storage_bufs(&mut self, device: &RenderDevice) {
-        let size = (self.length * std::mem::size_of::<u32>()) as BufferAddress;
-        let keys_slice = self.o_keys_buf.slice(0..size);
-        let vals_slice = self.o_vals_buf.slice(0..size);
-
-        keys_slice.map_async(MapMode::Read, |_| ());
-        vals_slice.map_async(MapMode::Read, |_| ());
-
-        device.poll(Maintain::wait()).panic_on_timeout();
-
-        {
-            let keys_view = keys_slice.get_mapped_range();
-            let keys: &[u32] = bytemuck::cast_slice(&keys_view);
-            let vals_view = vals_slice.get_mapped_range();
-            let vals: &[u32] = bytemuck::cast_slice(&vals_view);
-
-            info!("Sorted {} random keys", self.length);
-            info!("Keys: {:?}", &keys);
-            info!("Vals: {:?}", &vals);
-        }
+    pub fn read_sorted_kvs_from_gpu_storage_bufs(
+        &self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        storage_buffers: &RenderAssets<GpuShaderStorageBuffer>,
+    ) {
+        let (Some(keys_buf), Some(vals_buf)) = (
+            global_keys_buffer(storage_buffers, true),
+            global_vals_buffer(storage_buffers, true),
+        ) else {
+            return;
+        };
 
-        self.o_keys_buf.unmap();
-        self.o_vals_buf.unmap();
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("copy sorted kvs from gpu to cpu"),
+        });
+        let keys = BufferReadback::new(device, &mut encoder, keys_buf, 0, self.length);
+        let vals = BufferReadback::new(device, &mut encoder, vals_buf, 0, self.length);
+        queue.submit([encoder.finish()]);
+
+        // Resolves in a later frame, once the submissions of the renderer have completed the mappings
+        let device = device.clone();
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let keys = keys.read(device.clone()).await.unwrap();
+                let vals = vals.read(device).await.unwrap();
+
+                info!("Sorted {} random keys", keys.len());
+                info!("Keys: {:?}", &keys);
+                info!("Vals: {:?}", &vals);
+            })
+            .detach();
     }
 }
 
@@ -389,36 +354,8 @@ impl render_graph::Node for SimpleGpuSortNode {
 
         let simple_gpu_sort_resource = world.resource::<SimpleGpuSortResource>();
 
-        let storage_buffers = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
-        let eve_global_keys_buf = storage_buffers
-            .get(EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
-        let eve_global_vals_buf = storage_buffers
-            .get(EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
-
         let encoder = render_context.command_encoder();
 
-        let size = (simple_gpu_sort_resource.length * std::mem::size_of::<u32>()) as BufferAddress;
-
-        encoder.copy_buffer_to_buffer(
-            &simple_gpu_sort_resource.i_keys_buf,
-            0,
-            &eve_global_keys_buf.buffer,
-            0,
-            size,
-        );
-
-        encoder.copy_buffer_to_buffer(
-            &simple_gpu_sort_resource.i_vals_buf,
-            0,
-            &eve_global_vals_buf.buffer,
-            0,
-            size,
-        );
-
-        info!("before radix_sort: copy key/val from staging buffer to gpu storage buffer");
-
         bevy_radix_sort::run(
             encoder,
             pipeline_cache,
@@ -431,24 +368,6 @@ impl render_graph::Node for SimpleGpuSortNode {
             true,
         );
 
-        encoder.copy_buffer_to_buffer(
-            &eve_global_keys_buf.buffer,
-            0,
-            &simple_gpu_sort_resource.o_keys_buf,
-            0,
-            size,
-        );
-
-        encoder.copy_buffer_to_buffer(
-            &eve_global_vals_buf.buffer,
-            0,
-            &simple_gpu_sort_resource.o_vals_buf,
-            0,
-            size,
-        );
-
-        info!("after radix_sort: copy key/val from gpu storage buffer to staging buffer");
-
         Ok(())
     }
 }
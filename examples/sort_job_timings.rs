@@ -0,0 +1,74 @@
+//! Queues sorts of mixed sizes each frame and logs the GPU duration of each one.
+//!
+//! Needs a backend supporting the timestamp queries inside encoders, the jobs are reported unmeasured otherwise.
+
+use bevy::{
+    prelude::*,
+    render::{Render, RenderApp, RenderSet, renderer::RenderDevice},
+};
+use bevy_radix_sort::{
+    RadixSortJobs, RadixSortJobsConfig, SortJob, SortJobCompleted, prelude::*,
+    supports_job_timestamps,
+};
+
+/// The sizes of the jobs queued each frame, smallest first.
+const JOB_SIZES: [u32; 5] = [1_000, 10_000, 100_000, 500_000, 1_000_000];
+
+fn main() {
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: JOB_SIZES[JOB_SIZES.len() - 1].into(),
+        });
+
+    app.sub_app_mut(RenderApp)
+        .insert_resource(RadixSortJobsConfig {
+            timestamps: true,
+            // One job unmeasured on purpose
+            max_timed_jobs_per_frame: JOB_SIZES.len() as u32 - 1,
+            ..default()
+        })
+        .add_systems(
+            Render,
+            (
+                queue_jobs
+                    .in_set(RenderSet::Queue)
+                    .run_if(resource_exists::<RadixSortBindGroup>),
+                log_timings.in_set(RenderSet::Prepare),
+            ),
+        );
+
+    app.run();
+}
+
+/// Sorts whatever the global buffers hold, the durations don't depend on the keys much.
+fn queue_jobs(mut jobs: ResMut<RadixSortJobs>) {
+    for count in JOB_SIZES {
+        jobs.push(SortJob::new(count));
+    }
+}
+
+/// Logs the jobs completed during the previous frame, every 100 frames.
+fn log_timings(
+    render_device: Res<RenderDevice>,
+    mut completed: EventReader<SortJobCompleted>,
+    mut frames: Local<u32>,
+) {
+    let events: Vec<SortJobCompleted> = completed.read().copied().collect();
+    *frames += 1;
+    if events.is_empty() || *frames < 100 {
+        return;
+    }
+    *frames = 0;
+
+    if !supports_job_timestamps(&render_device) {
+        warn!("the device doesn't support the timestamp queries inside encoders");
+    }
+
+    for (event, count) in events.iter().zip(JOB_SIZES) {
+        match event.gpu_duration {
+            Some(duration) => info!("{:?}: {count:>8} keys in {duration:?}", event.id),
+            None => info!("{:?}: {count:>8} keys, unmeasured", event.id),
+        }
+    }
+}
@@ -0,0 +1,645 @@
+//! Draws 500k alpha-blended quads back-to-front in the order produced by the sorter, without any readback.
+//!
+//! Every frame, on the GPU:
+//!
+//! 1. the view-depth keygen pass writes the depth of every quad and its index into the global keys/vals buffers,
+//! 2. the radix sort sorts them back-to-front,
+//! 3. the valid-count pass counts the quads inside the view frustum, the keygen gives the others the sentinel key
+//!    [`DEFAULT_SENTINEL_KEY`] so they are sorted to the end,
+//! 4. the count is written as the `instance_count` of the `DrawIndexedIndirectArgs` of the quad mesh,
+//! 5. the sorted vals are bound as an instance-rate vertex buffer, the vertex shader reads `instances[instance_index]`,
+//!    and the quads are drawn with `draw_indexed_indirect`.
+//!
+//! The global buffers need [`BufferUsages::VERTEX`] for step 5, see [`RadixSortSettings::with_extra_buffer_usages`].
+//! Binding them as a read-only storage buffer and indexing `sorted_vals[instance_index]` works just as well.
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::core_3d::Transparent3d,
+    ecs::system::{SystemParamItem, lifetimeless::SRes},
+    pbr::{
+        MeshPipeline, MeshPipelineKey, RenderMeshInstances, SetMeshBindGroup, SetMeshViewBindGroup,
+    },
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        camera::ExtractedCamera,
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        mesh::{
+            MeshVertexBufferLayoutRef, RenderMesh, RenderMeshBufferInfo, allocator::MeshAllocator,
+        },
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, PipelineCache,
+            RenderPipelineDescriptor, ShaderStages, SpecializedMeshPipeline,
+            SpecializedMeshPipelineError, SpecializedMeshPipelines, VertexAttribute,
+            VertexBufferLayout, VertexFormat, VertexStepMode,
+            binding_types::storage_buffer_read_only_sized,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+        sync_world::MainEntity,
+        view::{ExtractedView, NoFrustumCulling},
+    },
+};
+use bevy_radix_sort::{
+    DEFAULT_SENTINEL_KEY, DRAW_INDEXED_INDIRECT_ARGS_SIZE, DrawIndexedIndirectTemplate,
+    VALID_COUNT_BUFFER_SIZE, ValidCountPipeline, ValidCountPlugin, ViewDepthKeygenPipeline,
+    ViewDepthKeygenPlugin, ViewDepthUniform, prelude::*,
+};
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+const NUMBER_OF_QUADS: u32 = 500_000;
+
+/// The keygen writes into the global buffers the first pass reads from.
+const READ_FROM_EVEN: bool = false;
+/// [`bevy_radix_sort::ViewDepthKeygenPipeline::record_view_depth_sort`] runs all 4 passes.
+const PASS_RANGE: Range<u32> = 0..4;
+
+const SORTED_INSTANCE_BUFFER_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(321282169148465678118280283736189788941);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(NUMBER_OF_QUADS)
+                .with_extra_buffer_usages(BufferUsages::VERTEX),
+        })
+        .add_plugins(ViewDepthKeygenPlugin)
+        .add_plugins(ValidCountPlugin)
+        .add_plugins(SortedQuadsPlugin)
+        .add_systems(Startup, setup)
+        .add_systems(Update, orbit_camera)
+        .run();
+}
+
+fn setup(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    // A 2x2 quad, the vertex shader scales it by the half size of each instance
+    commands.spawn((
+        Mesh3d(meshes.add(Rectangle::new(2.0, 2.0))),
+        SortedQuads,
+        NoFrustumCulling,
+    ));
+
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 0.0, 60.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+}
+
+fn orbit_camera(time: Res<Time>, mut cameras: Query<&mut Transform, With<Camera3d>>) {
+    for mut transform in &mut cameras {
+        let angle = time.elapsed_secs() * 0.2;
+        *transform = Transform::from_xyz(60.0 * angle.sin(), 10.0, 60.0 * angle.cos())
+            .looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+/// Marks the mesh drawn once per quad.
+#[derive(Component, Clone, Copy, ExtractComponent)]
+struct SortedQuads;
+
+/// Must match `Instance` in `sorted_instance_buffer.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Instance {
+    /// xyz: center, w: half size
+    pos_scale: [f32; 4],
+    color: [f32; 4],
+}
+
+/// The distance between the centers of two instances in `f32`s, the keygen reads `pos_scale.xyz`.
+const INSTANCE_STRIDE: u32 = (std::mem::size_of::<Instance>() / std::mem::size_of::<f32>()) as u32;
+
+struct SortedQuadsPlugin;
+
+impl Plugin for SortedQuadsPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            SORTED_INSTANCE_BUFFER_SHADER_HANDLE,
+            "sorted_instance_buffer.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins(ExtractComponentPlugin::<SortedQuads>::default());
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_command::<Transparent3d, DrawSortedQuads>()
+            .init_resource::<SpecializedMeshPipelines<SortedQuadsPipeline>>()
+            .add_systems(
+                Render,
+                (
+                    (write_view_uniform, write_draw_template).in_set(RenderSet::PrepareResources),
+                    prepare_sort_bind_groups
+                        .in_set(RenderSet::PrepareBindGroups)
+                        .run_if(not(resource_exists::<SortedQuadsSortBindGroups>)),
+                    queue_sorted_quads.in_set(RenderSet::QueueMeshes),
+                ),
+            );
+
+        // The keygen and the sort must be done before the cameras draw the quads
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(SortedQuadsNodeLabel, SortedQuadsNode::default());
+        graph.add_node_edge(SortedQuadsNodeLabel, bevy::render::graph::CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<SortedQuadsPipeline>()
+            .init_resource::<SortedQuadsBuffers>();
+    }
+}
+
+#[derive(Resource)]
+struct SortedQuadsBuffers {
+    instances_bind_group: BindGroup,
+    instances: Buffer,
+    view_uniform: Buffer,
+    /// The number of quads inside the view frustum
+    valid_count: Buffer,
+    /// The fields of the indirect args taken from the quad mesh
+    draw_template: Buffer,
+    /// The `DrawIndexedIndirectArgs` of the quads
+    draw_indirect: Buffer,
+}
+
+impl FromWorld for SortedQuadsBuffers {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let sorted_quads_pipeline = world.resource::<SortedQuadsPipeline>();
+        let view_depth_pipeline = world.resource::<ViewDepthKeygenPipeline>();
+        let valid_count_pipeline = world.resource::<ValidCountPipeline>();
+
+        let mut rng = rand::thread_rng();
+        let instances: Vec<Instance> = (0..NUMBER_OF_QUADS)
+            .map(|_| Instance {
+                pos_scale: [
+                    rng.gen_range(-20.0..20.0),
+                    rng.gen_range(-20.0..20.0),
+                    rng.gen_range(-20.0..20.0),
+                    0.15,
+                ],
+                color: [rng.r#gen(), rng.r#gen(), rng.r#gen(), 0.3],
+            })
+            .collect();
+
+        let instances = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("sorted_instance_buffer: instances buffer"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let instances_bind_group = render_device.create_bind_group(
+            "sorted_instance_buffer: instances bind_group",
+            &sorted_quads_pipeline.instances_layout,
+            &BindGroupEntries::single(instances.as_entire_binding()),
+        );
+
+        let view_uniform = view_depth_pipeline.create_uniform_buffer(
+            render_device,
+            &ViewDepthUniform::new(Mat4::IDENTITY, &default()),
+        );
+
+        let valid_count = render_device.create_buffer(&BufferDescriptor {
+            label: Some("sorted_instance_buffer: valid_count buffer"),
+            size: VALID_COUNT_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let draw_template = valid_count_pipeline.create_template_buffer(render_device, &default());
+        let draw_indirect = render_device.create_buffer(&BufferDescriptor {
+            label: Some("sorted_instance_buffer: draw_indirect buffer"),
+            size: DRAW_INDEXED_INDIRECT_ARGS_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            instances_bind_group,
+            instances,
+            view_uniform,
+            valid_count,
+            draw_template,
+            draw_indirect,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SortedQuadsSortBindGroups {
+    keygen: BindGroup,
+    valid_count: BindGroup,
+}
+
+fn prepare_sort_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    view_depth_pipeline: Res<ViewDepthKeygenPipeline>,
+    valid_count_pipeline: Res<ValidCountPipeline>,
+    buffers: Res<SortedQuadsBuffers>,
+) {
+    let Some(keygen) = view_depth_pipeline.create_global_bind_group(
+        &render_device,
+        &sbufs,
+        &buffers.instances,
+        &buffers.view_uniform,
+        READ_FROM_EVEN,
+    ) else {
+        return;
+    };
+    let Some(valid_count) = valid_count_pipeline.create_sorted_bind_group(
+        &render_device,
+        &sbufs,
+        &PASS_RANGE,
+        READ_FROM_EVEN,
+        &buffers.valid_count,
+    ) else {
+        return;
+    };
+
+    commands.insert_resource(SortedQuadsSortBindGroups {
+        keygen,
+        valid_count,
+    });
+}
+
+fn write_view_uniform(
+    render_queue: Res<RenderQueue>,
+    view_depth_pipeline: Res<ViewDepthKeygenPipeline>,
+    buffers: Res<SortedQuadsBuffers>,
+    views: Query<&ExtractedView, With<ExtractedCamera>>,
+) {
+    // The example has a single camera
+    let Some(view) = views.iter().next() else {
+        return;
+    };
+
+    let uniform = ViewDepthUniform::from_extracted_view(view, &default());
+    view_depth_pipeline.write_uniform_buffer(&render_queue, &buffers.view_uniform, &uniform);
+}
+
+fn write_draw_template(
+    render_queue: Res<RenderQueue>,
+    valid_count_pipeline: Res<ValidCountPipeline>,
+    buffers: Res<SortedQuadsBuffers>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    mesh_allocator: Res<MeshAllocator>,
+    sorted_quads: Query<&MainEntity, With<SortedQuads>>,
+) {
+    // The example has a single mesh drawn once per quad
+    let Some(main_entity) = sorted_quads.iter().next() else {
+        return;
+    };
+    let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(*main_entity) else {
+        return;
+    };
+    let Some(RenderMeshBufferInfo::Indexed { count, .. }) = meshes
+        .get(mesh_instance.mesh_asset_id)
+        .map(|mesh| &mesh.buffer_info)
+    else {
+        return;
+    };
+    let (Some(vertex_buffer_slice), Some(index_buffer_slice)) = (
+        mesh_allocator.mesh_vertex_slice(&mesh_instance.mesh_asset_id),
+        mesh_allocator.mesh_index_slice(&mesh_instance.mesh_asset_id),
+    ) else {
+        return;
+    };
+
+    let template = DrawIndexedIndirectTemplate {
+        index_count: *count,
+        first_index: index_buffer_slice.range.start,
+        base_vertex: vertex_buffer_slice.range.start as i32,
+        first_instance: 0,
+    };
+    valid_count_pipeline.write_template_buffer(&render_queue, &buffers.draw_template, &template);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_sorted_quads(
+    transparent_3d_draw_functions: Res<DrawFunctions<Transparent3d>>,
+    sorted_quads_pipeline: Res<SortedQuadsPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<SortedQuadsPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    sorted_quads: Query<(Entity, &MainEntity), With<SortedQuads>>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(Entity, &ExtractedView, &Msaa)>,
+) {
+    let draw_sorted_quads = transparent_3d_draw_functions.read().id::<DrawSortedQuads>();
+
+    for (view_entity, view, msaa) in &views {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view_entity) else {
+            continue;
+        };
+
+        let view_key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr)
+            | MeshPipelineKey::BLEND_ALPHA;
+        let rangefinder = view.rangefinder3d();
+
+        for (entity, main_entity) in &sorted_quads {
+            let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(*main_entity)
+            else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+                continue;
+            };
+
+            let key =
+                view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology());
+            let pipeline = pipelines
+                .specialize(&pipeline_cache, &sorted_quads_pipeline, key, &mesh.layout)
+                .unwrap();
+
+            transparent_phase.add(Transparent3d {
+                entity: (entity, *main_entity),
+                pipeline,
+                draw_function: draw_sorted_quads,
+                distance: rangefinder.distance_translation(&mesh_instance.translation),
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::NONE,
+            });
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SortedQuadsPipeline {
+    mesh_pipeline: MeshPipeline,
+    /// ```wgsl
+    /// @group(2) @binding(0) var<storage, read> instances: array<Instance>;
+    /// ```
+    instances_layout: BindGroupLayout,
+}
+
+impl FromWorld for SortedQuadsPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let instances_layout = render_device.create_bind_group_layout(
+            "sorted_instance_buffer: instances layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::VERTEX,
+                storage_buffer_read_only_sized(false, None),
+            ),
+        );
+
+        Self {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+            instances_layout,
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for SortedQuadsPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+
+        descriptor.layout.push(self.instances_layout.clone());
+        descriptor.vertex.shader = SORTED_INSTANCE_BUFFER_SHADER_HANDLE;
+        // The sorted vals: one `u32` index per instance
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<u32>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![VertexAttribute {
+                format: VertexFormat::Uint32,
+                offset: 0,
+                shader_location: 3,
+            }],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = SORTED_INSTANCE_BUFFER_SHADER_HANDLE;
+
+        Ok(descriptor)
+    }
+}
+
+type DrawSortedQuads = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetInstancesBindGroup<2>,
+    DrawSortedInstances,
+);
+
+struct SetInstancesBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetInstancesBindGroup<I> {
+    type Param = SRes<SortedQuadsBuffers>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        _entity: Option<()>,
+        buffers: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        pass.set_bind_group(I, &buffers.into_inner().instances_bind_group, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+struct DrawSortedInstances;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawSortedInstances {
+    type Param = (
+        SRes<RenderAssets<RenderMesh>>,
+        SRes<RenderMeshInstances>,
+        SRes<MeshAllocator>,
+        SRes<RenderAssets<GpuShaderStorageBuffer>>,
+        SRes<SortedQuadsBuffers>,
+    );
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        _entity: Option<()>,
+        (meshes, render_mesh_instances, mesh_allocator, sbufs, buffers): SystemParamItem<
+            'w,
+            '_,
+            Self::Param,
+        >,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let mesh_allocator = mesh_allocator.into_inner();
+
+        let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(item.main_entity())
+        else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_instance.mesh_asset_id) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(vertex_buffer_slice) =
+            mesh_allocator.mesh_vertex_slice(&mesh_instance.mesh_asset_id)
+        else {
+            return RenderCommandResult::Skip;
+        };
+        // The indices of the quads, back-to-front
+        let Some(sorted_vals) = sorted_vals_buffer(sbufs.into_inner(), &PASS_RANGE, READ_FROM_EVEN)
+        else {
+            return RenderCommandResult::Skip;
+        };
+
+        let sorted_vals_size = (NUMBER_OF_QUADS as usize * std::mem::size_of::<u32>()) as u64;
+
+        pass.set_vertex_buffer(0, vertex_buffer_slice.buffer.slice(..));
+        pass.set_vertex_buffer(1, sorted_vals.slice(0..sorted_vals_size));
+
+        match &gpu_mesh.buffer_info {
+            RenderMeshBufferInfo::Indexed { index_format, .. } => {
+                let Some(index_buffer_slice) =
+                    mesh_allocator.mesh_index_slice(&mesh_instance.mesh_asset_id)
+                else {
+                    return RenderCommandResult::Skip;
+                };
+
+                pass.set_index_buffer(index_buffer_slice.buffer.slice(..), 0, *index_format);
+                // Only the quads inside the view frustum, written by the node
+                pass.draw_indexed_indirect(&buffers.into_inner().draw_indirect, 0);
+            }
+            RenderMeshBufferInfo::NonIndexed => {
+                // The quads outside of the view frustum are drawn too, they are clipped anyway
+                pass.draw(vertex_buffer_slice.range, 0..NUMBER_OF_QUADS);
+            }
+        }
+
+        RenderCommandResult::Success
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, RenderLabel)]
+struct SortedQuadsNodeLabel;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+enum SortedQuadsState {
+    #[default]
+    OnLoad,
+    Loaded,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct SortedQuadsNode {
+    state: SortedQuadsState,
+}
+
+impl render_graph::Node for SortedQuadsNode {
+    fn update(&mut self, world: &mut World) {
+        if matches!(self.state, SortedQuadsState::OnLoad) {
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let keygen_load_state = world
+                .resource::<ViewDepthKeygenPipeline>()
+                .load_state(pipeline_cache);
+            let valid_count_load_state = world
+                .resource::<ValidCountPipeline>()
+                .load_state(pipeline_cache);
+            let radix_sort_load_state = bevy_radix_sort::check_load_state(world);
+            let load_states = [
+                &keygen_load_state,
+                &valid_count_load_state,
+                &radix_sort_load_state,
+            ];
+
+            for load_state in load_states {
+                if let LoadState::Failed(err) = load_state {
+                    panic!("{}", err);
+                }
+            }
+
+            if load_states
+                .iter()
+                .all(|load_state| **load_state == LoadState::Loaded)
+            {
+                self.state = SortedQuadsState::Loaded;
+            }
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if matches!(self.state, SortedQuadsState::OnLoad) {
+            return Ok(());
+        }
+
+        let Some(sort_bind_groups) = world.get_resource::<SortedQuadsSortBindGroups>() else {
+            return Ok(());
+        };
+        let Some(radix_sort_bind_group) = world.get_resource::<RadixSortBindGroup>() else {
+            return Ok(());
+        };
+
+        let max_compute_workgroups_per_dimension = world
+            .resource::<RenderDevice>()
+            .limits()
+            .max_compute_workgroups_per_dimension;
+
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let buffers = world.resource::<SortedQuadsBuffers>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+        let view_depth_pipeline = world.resource::<ViewDepthKeygenPipeline>();
+        let valid_count_pipeline = world.resource::<ValidCountPipeline>();
+
+        let encoder = render_context.command_encoder();
+        view_depth_pipeline.record_view_depth_sort(
+            encoder,
+            pipeline_cache,
+            &sort_bind_groups.keygen,
+            radix_sort_pipeline,
+            radix_sort_bind_group,
+            max_compute_workgroups_per_dimension,
+            NUMBER_OF_QUADS,
+            INSTANCE_STRIDE,
+            READ_FROM_EVEN,
+        );
+        valid_count_pipeline.record_valid_count(
+            encoder,
+            pipeline_cache,
+            &sort_bind_groups.valid_count,
+            NUMBER_OF_QUADS,
+            DEFAULT_SENTINEL_KEY,
+        );
+        valid_count_pipeline.record_write_draw_indirect(
+            encoder,
+            render_device,
+            pipeline_cache,
+            &buffers.draw_template,
+            &buffers.valid_count,
+            &buffers.draw_indirect,
+            0,
+        );
+
+        Ok(())
+    }
+}
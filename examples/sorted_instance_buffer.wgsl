@@ -0,0 +1,42 @@
+#import bevy_pbr::mesh_view_bindings::view
+
+struct Instance {
+    /// xyz: center, w: half size
+    pos_scale: vec4<f32>,
+    color: vec4<f32>,
+}
+
+/// The per-instance data in spawn order, indexed through the sorted vals
+@group(2) @binding(0) var<storage, read> instances: array<Instance>;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    /// The sorted vals bound as an instance-rate vertex buffer: the index of the instance to draw
+    @location(3) instance_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let instance = instances[vertex.instance_index];
+
+    // Billboard the quad so it always faces the camera
+    let right = view.world_from_view[0].xyz;
+    let up = view.world_from_view[1].xyz;
+    let world_position = instance.pos_scale.xyz
+        + (right * vertex.position.x + up * vertex.position.y) * instance.pos_scale.w;
+
+    var out: VertexOutput;
+    out.clip_position = view.clip_from_world * vec4<f32>(world_position, 1.0);
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,403 @@
+//! Draws 100k alpha-blended quads through a custom [`Material`], ordered by the sorter without any readback.
+//!
+//! Every frame, on the GPU:
+//!
+//! 1. the view-depth keygen pass writes the encoded depth of every quad and its index into the global keys/vals,
+//! 2. the radix sort orders the indices back-to-front,
+//! 3. the material binds the instances and the sorted vals as storage buffers, quad `vertex_index / 4` of the mesh
+//!    draws instance `sorted_vals[vertex_index / 4]`, so the quads are blended in depth order.
+//!
+//! The sorted vals end up on the side of the global buffers given by [`is_output_even`], the material binds the
+//! matching handle. Press space to stop sorting: the quads are then drawn in spawn order and blend wrongly.
+
+use std::ops::Range;
+
+use bevy::{
+    asset::{RenderAssetUsages, load_internal_asset},
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        camera::ExtractedCamera,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{AsBindGroup, BindGroup, Buffer, PipelineCache, ShaderRef},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+        view::{ExtractedView, NoFrustumCulling},
+    },
+};
+use bevy_radix_sort::{
+    ViewDepthKeygenPipeline, ViewDepthKeygenPlugin, ViewDepthUniform, prelude::*,
+};
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+const NUMBER_OF_QUADS: u32 = 100_000;
+
+/// The keygen writes into the global buffers the first pass reads from.
+const READ_FROM_EVEN: bool = false;
+/// [`ViewDepthKeygenPipeline::record_view_depth_sort`] runs all 4 passes.
+const PASS_RANGE: Range<u32> = 0..4;
+
+const SORTED_INSTANCING_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(260163585448129729045237434336251755246);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: NUMBER_OF_QUADS.into(),
+        })
+        .add_plugins(ViewDepthKeygenPlugin)
+        .add_plugins(SortedInstancingPlugin)
+        .add_systems(Startup, setup)
+        .add_systems(Update, (toggle_sort, orbit_camera))
+        .run();
+}
+
+/// Must match `Instance` in `sorted_instancing.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Instance {
+    /// xyz: center, w: half size
+    pos_scale: [f32; 4],
+    color: [f32; 4],
+}
+
+/// The distance between the centers of two instances in `f32`s, the keygen reads `pos_scale.xyz`.
+const INSTANCE_STRIDE: u32 = (std::mem::size_of::<Instance>() / std::mem::size_of::<f32>()) as u32;
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+struct SortedInstancingMaterial {
+    #[storage(0, read_only)]
+    instances: Handle<ShaderStorageBuffer>,
+    /// The global vals buffer holding the result of the sort
+    #[storage(1, read_only)]
+    sorted_vals: Handle<ShaderStorageBuffer>,
+    #[uniform(2)]
+    sorted: u32,
+}
+
+impl Material for SortedInstancingMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SORTED_INSTANCING_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SORTED_INSTANCING_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+/// The instances read by the keygen and the material.
+#[derive(Resource, ExtractResource, Clone)]
+struct InstanceBuffer(Handle<ShaderStorageBuffer>);
+
+/// Whether the node sorts the quads, toggled with space.
+#[derive(Resource, ExtractResource, Clone, Copy)]
+struct SortEnabled(bool);
+
+struct SortedInstancingPlugin;
+
+impl Plugin for SortedInstancingPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            SORTED_INSTANCING_SHADER_HANDLE,
+            "sorted_instancing.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins((
+            MaterialPlugin::<SortedInstancingMaterial>::default(),
+            ExtractResourcePlugin::<InstanceBuffer>::default(),
+            ExtractResourcePlugin::<SortEnabled>::default(),
+        ))
+        .insert_resource(SortEnabled(true));
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            (
+                write_view_uniform.in_set(RenderSet::PrepareResources),
+                prepare_keygen_bind_group
+                    .in_set(RenderSet::PrepareBindGroups)
+                    .run_if(resource_exists::<InstanceBuffer>),
+            ),
+        );
+
+        // The keygen and the sort must be done before the cameras draw the quads
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(SortedInstancingNodeLabel, SortedInstancingNode::default());
+        graph.add_node_edge(
+            SortedInstancingNodeLabel,
+            bevy::render::graph::CameraDriverLabel,
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<ViewUniformBuffer>();
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SortedInstancingMaterial>>,
+    mut sbufs: ResMut<Assets<ShaderStorageBuffer>>,
+) {
+    let mut rng = rand::thread_rng();
+    let instances: Vec<Instance> = (0..NUMBER_OF_QUADS)
+        .map(|_| Instance {
+            pos_scale: [
+                rng.gen_range(-20.0..20.0),
+                rng.gen_range(-20.0..20.0),
+                rng.gen_range(-20.0..20.0),
+                0.25,
+            ],
+            color: [rng.r#gen(), rng.r#gen(), rng.r#gen(), 0.3],
+        })
+        .collect();
+    let instances = sbufs.add(ShaderStorageBuffer::new(
+        bytemuck::cast_slice(&instances),
+        RenderAssetUsages::RENDER_WORLD,
+    ));
+    commands.insert_resource(InstanceBuffer(instances.clone()));
+
+    let sorted_vals = if is_output_even(&PASS_RANGE, READ_FROM_EVEN) {
+        EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE
+    } else {
+        ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE
+    };
+
+    commands.spawn((
+        Mesh3d(meshes.add(instance_quads())),
+        MeshMaterial3d(materials.add(SortedInstancingMaterial {
+            instances,
+            sorted_vals,
+            sorted: 1,
+        })),
+        // The quads are placed by the vertex shader, the bounds of the mesh don't hold them
+        NoFrustumCulling,
+        NotShadowCaster,
+    ));
+
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 0.0, 60.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    commands.spawn((
+        Text::new("sorted (space to toggle)"),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        },
+    ));
+}
+
+/// A 2x2 quad per instance, the vertex shader scales it by the half size of the instance and moves it.
+fn instance_quads() -> Mesh {
+    let corners = [
+        [-1.0, -1.0, 0.0],
+        [1.0, -1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+    ];
+
+    let positions: Vec<[f32; 3]> = (0..NUMBER_OF_QUADS).flat_map(|_| corners).collect();
+    let indices: Vec<u32> = (0..NUMBER_OF_QUADS)
+        .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|corner| 4 * quad + corner))
+        .collect();
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn toggle_sort(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut sort_enabled: ResMut<SortEnabled>,
+    mut materials: ResMut<Assets<SortedInstancingMaterial>>,
+    mut texts: Query<&mut Text>,
+) {
+    if !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+
+    sort_enabled.0 = !sort_enabled.0;
+    for (_, material) in materials.iter_mut() {
+        material.sorted = sort_enabled.0 as u32;
+    }
+    for mut text in &mut texts {
+        text.0 = if sort_enabled.0 {
+            "sorted (space to toggle)"
+        } else {
+            "unsorted (space to toggle)"
+        }
+        .to_string();
+    }
+}
+
+fn orbit_camera(time: Res<Time>, mut cameras: Query<&mut Transform, With<Camera3d>>) {
+    for mut transform in &mut cameras {
+        let angle = time.elapsed_secs() * 0.2;
+        *transform = Transform::from_xyz(60.0 * angle.sin(), 10.0, 60.0 * angle.cos())
+            .looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+/// The camera the keygen projects the quads with.
+#[derive(Resource)]
+struct ViewUniformBuffer(Buffer);
+
+impl FromWorld for ViewUniformBuffer {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let view_depth_pipeline = world.resource::<ViewDepthKeygenPipeline>();
+
+        Self(view_depth_pipeline.create_uniform_buffer(
+            render_device,
+            &ViewDepthUniform::new(Mat4::IDENTITY, &default()),
+        ))
+    }
+}
+
+fn write_view_uniform(
+    render_queue: Res<RenderQueue>,
+    view_depth_pipeline: Res<ViewDepthKeygenPipeline>,
+    view_uniform: Res<ViewUniformBuffer>,
+    views: Query<&ExtractedView, With<ExtractedCamera>>,
+) {
+    // The example has a single camera
+    let Some(view) = views.iter().next() else {
+        return;
+    };
+
+    let uniform = ViewDepthUniform::from_extracted_view(view, &default());
+    view_depth_pipeline.write_uniform_buffer(&render_queue, &view_uniform.0, &uniform);
+}
+
+#[derive(Resource)]
+struct KeygenBindGroup(BindGroup);
+
+fn prepare_keygen_bind_group(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    view_depth_pipeline: Res<ViewDepthKeygenPipeline>,
+    view_uniform: Res<ViewUniformBuffer>,
+    instances: Res<InstanceBuffer>,
+) {
+    let Some(instances) = sbufs.get(&instances.0) else {
+        return;
+    };
+    let Some(keygen) = view_depth_pipeline.create_global_bind_group(
+        &render_device,
+        &sbufs,
+        &instances.buffer,
+        &view_uniform.0,
+        READ_FROM_EVEN,
+    ) else {
+        return;
+    };
+
+    commands.insert_resource(KeygenBindGroup(keygen));
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, RenderLabel)]
+struct SortedInstancingNodeLabel;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+enum SortedInstancingState {
+    #[default]
+    OnLoad,
+    Loaded,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct SortedInstancingNode {
+    state: SortedInstancingState,
+}
+
+impl render_graph::Node for SortedInstancingNode {
+    fn update(&mut self, world: &mut World) {
+        if matches!(self.state, SortedInstancingState::OnLoad) {
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let load_states = [
+                world
+                    .resource::<ViewDepthKeygenPipeline>()
+                    .load_state(pipeline_cache),
+                bevy_radix_sort::check_load_state(world),
+            ];
+
+            for load_state in &load_states {
+                if let LoadState::Failed(err) = load_state {
+                    panic!("{}", err);
+                }
+            }
+
+            if load_states
+                .iter()
+                .all(|load_state| *load_state == LoadState::Loaded)
+            {
+                self.state = SortedInstancingState::Loaded;
+            }
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if matches!(self.state, SortedInstancingState::OnLoad)
+            || !world
+                .get_resource::<SortEnabled>()
+                .is_some_and(|sort| sort.0)
+        {
+            return Ok(());
+        }
+
+        let (Some(keygen_bind_group), Some(radix_sort_bind_group)) = (
+            world.get_resource::<KeygenBindGroup>(),
+            world.get_resource::<RadixSortBindGroup>(),
+        ) else {
+            return Ok(());
+        };
+
+        let max_compute_workgroups_per_dimension = world
+            .resource::<RenderDevice>()
+            .limits()
+            .max_compute_workgroups_per_dimension;
+
+        world
+            .resource::<ViewDepthKeygenPipeline>()
+            .record_view_depth_sort(
+                render_context.command_encoder(),
+                world.resource::<PipelineCache>(),
+                &keygen_bind_group.0,
+                world.resource::<RadixSortPipeline>(),
+                radix_sort_bind_group,
+                max_compute_workgroups_per_dimension,
+                NUMBER_OF_QUADS,
+                INSTANCE_STRIDE,
+                READ_FROM_EVEN,
+            );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+// The material of the `sorted_instancing` example: the mesh holds a quad per instance, quad `vertex_index / 4` draws
+// the instance at the same position in the sorted vals, so the quads are blended back-to-front.
+
+#import bevy_pbr::mesh_view_bindings::view
+
+// Must match `Instance` in `sorted_instancing.rs`
+struct Instance {
+    // xyz: center, w: half size
+    pos_scale: vec4f,
+    color: vec4f,
+}
+
+// The per-instance data in spawn order
+@group(2) @binding(0) var<storage, read> instances: array<Instance>;
+// The indices of the instances, back-to-front
+@group(2) @binding(1) var<storage, read> sorted_vals: array<u32>;
+// 0 to draw the instances in spawn order, ignoring the sort
+@group(2) @binding(2) var<uniform> sorted: u32;
+
+struct Vertex {
+    @builtin(vertex_index) vertex_index: u32,
+    // The corner of the quad
+    @location(0) position: vec3f,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec4f,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let quad = vertex.vertex_index / 4u;
+    let instance = instances[select(quad, sorted_vals[quad], sorted != 0u)];
+
+    // Billboard the quad so it always faces the camera
+    let right = view.world_from_view[0].xyz;
+    let up = view.world_from_view[1].xyz;
+    let world_position = instance.pos_scale.xyz
+        + (right * vertex.position.x + up * vertex.position.y) * instance.pos_scale.w;
+
+    var out: VertexOutput;
+    out.clip_position = view.clip_from_world * vec4f(world_position, 1.0);
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4f {
+    return in.color;
+}
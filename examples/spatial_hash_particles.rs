@@ -0,0 +1,496 @@
+//! Hashes 200k moving particles into a grid, counts the neighbors of every particle and colors it by density, all on
+//! the GPU every frame:
+//!
+//! 1. `integrate` moves the particles and writes the id of their cell into the global keys,
+//! 2. the [`RadixSortJobsNode`](bevy_radix_sort::RadixSortJobsNode) sorts the particle indices by cell id, a
+//!    [`SortJob`] queued each frame over the 16 significant bits of the ids (2 passes),
+//! 3. the [`CellRangesPipeline`] writes where the run of every cell starts and ends in the sorted keys,
+//! 4. `count_neighbors` walks the 3x3 cells around each particle through the cell ranges and the sorted indices.
+//!
+//! The particles are drawn as a single mesh holding a quad per particle, its material reads the particle and
+//! density buffers and moves quad `vertex_index / 4` to its particle, so nothing is read back.
+
+use bevy::{
+    asset::{RenderAssetUsages, load_internal_asset},
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        camera::ScalingMode,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{
+            AsBindGroup, BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
+            Buffer, BufferDescriptor, BufferUsages, CachedComputePipelineId, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderRef, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::{RenderContext, RenderDevice},
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+        view::NoFrustumCulling,
+    },
+    sprite::{Material2d, Material2dPlugin},
+};
+use bevy_radix_sort::{
+    CellRangesPipeline, CellRangesPlugin, RadixSortJobs, RadixSortJobsLabel, SortJob,
+    cell_ranges_buffer_size, prelude::*,
+};
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+const NUMBER_OF_PARTICLES: u32 = 200_000;
+
+/// The cells per side of the grid.
+const GRID_SIZE: u32 = 256;
+const NUMBER_OF_CELLS: u32 = GRID_SIZE * GRID_SIZE;
+/// The bits of the cell ids, the sort runs the passes covering them.
+const CELL_KEY_BITS: u8 = 2 * GRID_SIZE.ilog2() as u8;
+/// The side of a cell and the radius within which two particles are neighbors.
+const CELL_SIZE: f32 = 4.0;
+const GRID_EXTENT: f32 = GRID_SIZE as f32 * CELL_SIZE;
+
+/// The half size of the quad of a particle.
+const PARTICLE_HALF_SIZE: f32 = 0.75;
+
+/// The workgroup size of the passes in `spatial_hash_particles.wgsl`.
+const WORKGROUP_SIZE: u32 = 256;
+
+const PARTICLES_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(213263054897956383751639260554993433485);
+const PARTICLES_MATERIAL_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(90328021252259191617042986972786187468);
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(NUMBER_OF_PARTICLES)
+                .with_significant_key_bits(CELL_KEY_BITS),
+        })
+        .add_plugins(CellRangesPlugin)
+        .add_plugins(ParticlesPlugin)
+        .run();
+}
+
+/// Must match `Particle` in `spatial_hash_particles.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+}
+
+/// Must match `PushConstants` in `spatial_hash_particles.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ParticlesPushConstants {
+    number_of_particles: u32,
+    grid_size: u32,
+    cell_size: f32,
+    delta_time: f32,
+}
+
+/// The particle and density storage buffers, shared by the material and the compute passes.
+#[derive(Resource, ExtractResource, Clone)]
+struct ParticleBuffers {
+    particles: Handle<ShaderStorageBuffer>,
+    densities: Handle<ShaderStorageBuffer>,
+}
+
+/// The time step of the `integrate` pass.
+#[derive(Resource, ExtractResource, Clone, Copy, Default)]
+struct ParticleTime(f32);
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+struct ParticleMaterial {
+    #[storage(0, read_only, visibility(vertex))]
+    particles: Handle<ShaderStorageBuffer>,
+    #[storage(1, read_only, visibility(vertex))]
+    densities: Handle<ShaderStorageBuffer>,
+}
+
+impl Material2d for ParticleMaterial {
+    fn vertex_shader() -> ShaderRef {
+        PARTICLES_MATERIAL_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        PARTICLES_MATERIAL_SHADER_HANDLE.into()
+    }
+}
+
+struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PARTICLES_SHADER_HANDLE,
+            "spatial_hash_particles.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            PARTICLES_MATERIAL_SHADER_HANDLE,
+            "spatial_hash_particles_material.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins((
+            Material2dPlugin::<ParticleMaterial>::default(),
+            ExtractResourcePlugin::<ParticleBuffers>::default(),
+            ExtractResourcePlugin::<ParticleTime>::default(),
+        ))
+        .init_resource::<ParticleTime>()
+        .add_systems(Startup, setup)
+        .add_systems(Update, update_particle_time);
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            (
+                queue_particles_sort
+                    .in_set(RenderSet::Queue)
+                    .run_if(radix_sort_loaded())
+                    .run_if(particles_loaded),
+                prepare_particles_bind_groups
+                    .in_set(RenderSet::PrepareBindGroups)
+                    .run_if(resource_exists::<ParticleBuffers>),
+            ),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ParticlesPipeline>();
+
+        // The jobs node is added by the `RadixSortPlugin::finish`, the plugin must be added before this one
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(ParticlesIntegrateLabel, ParticlesIntegrateNode);
+        graph.add_node(ParticlesNeighborsLabel, ParticlesNeighborsNode);
+        graph.add_node_edge(ParticlesIntegrateLabel, RadixSortJobsLabel);
+        graph.add_node_edge(RadixSortJobsLabel, ParticlesNeighborsLabel);
+        graph.add_node_edge(
+            ParticlesNeighborsLabel,
+            bevy::render::graph::CameraDriverLabel,
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ParticleMaterial>>,
+    mut sbufs: ResMut<Assets<ShaderStorageBuffer>>,
+) {
+    let mut rng = rand::thread_rng();
+    let half_extent = 0.5 * GRID_EXTENT;
+    let particles: Vec<Particle> = (0..NUMBER_OF_PARTICLES)
+        .map(|_| {
+            let direction = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU));
+            Particle {
+                position: [
+                    rng.gen_range(-half_extent..half_extent),
+                    rng.gen_range(-half_extent..half_extent),
+                ],
+                velocity: (direction * rng.gen_range(5.0..30.0)).to_array(),
+            }
+        })
+        .collect();
+
+    let particles = sbufs.add(ShaderStorageBuffer::new(
+        bytemuck::cast_slice(&particles),
+        RenderAssetUsages::RENDER_WORLD,
+    ));
+    let densities = sbufs.add(ShaderStorageBuffer::new(
+        bytemuck::cast_slice(&vec![0u32; NUMBER_OF_PARTICLES as usize]),
+        RenderAssetUsages::RENDER_WORLD,
+    ));
+    commands.insert_resource(ParticleBuffers {
+        particles: particles.clone(),
+        densities: densities.clone(),
+    });
+
+    commands.spawn((
+        Mesh2d(meshes.add(particle_quads())),
+        MeshMaterial2d(materials.add(ParticleMaterial {
+            particles,
+            densities,
+        })),
+        // The quads are moved by the vertex shader, the bounds of the mesh don't hold them
+        NoFrustumCulling,
+    ));
+
+    commands.spawn((
+        Camera2d,
+        OrthographicProjection {
+            scaling_mode: ScalingMode::AutoMin {
+                min_width: GRID_EXTENT,
+                min_height: GRID_EXTENT,
+            },
+            ..OrthographicProjection::default_2d()
+        },
+    ));
+}
+
+/// A quad per particle centered on the origin, the vertex shader adds the position of the particle.
+fn particle_quads() -> Mesh {
+    let s = PARTICLE_HALF_SIZE;
+    let corners = [[-s, -s, 0.0], [s, -s, 0.0], [s, s, 0.0], [-s, s, 0.0]];
+
+    let positions: Vec<[f32; 3]> = (0..NUMBER_OF_PARTICLES).flat_map(|_| corners).collect();
+    let indices: Vec<u32> = (0..NUMBER_OF_PARTICLES)
+        .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|corner| 4 * quad + corner))
+        .collect();
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn update_particle_time(time: Res<Time>, mut particle_time: ResMut<ParticleTime>) {
+    // Large steps after a hitch would tunnel the particles through the walls
+    particle_time.0 = time.delta_secs().min(1.0 / 30.0);
+}
+
+#[derive(Resource)]
+struct ParticlesPipeline {
+    integrate_pipeline: CachedComputePipelineId,
+    count_neighbors_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout of `spatial_hash_particles.wgsl`
+    bind_group_layout: BindGroupLayout,
+    /// The `(start, end)` of every cell
+    cell_ranges: Buffer,
+}
+
+impl FromWorld for ParticlesPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "spatial_hash_particles: bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer::<Particle>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<[u32; 2]>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let queue = |label: &'static str, entry_point: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::COMPUTE,
+                    range: 0..std::mem::size_of::<ParticlesPushConstants>() as u32,
+                }],
+                shader: PARTICLES_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: entry_point.into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+        let integrate_pipeline = queue("spatial_hash_particles: integrate pipeline", "integrate");
+        let count_neighbors_pipeline = queue(
+            "spatial_hash_particles: count_neighbors pipeline",
+            "count_neighbors",
+        );
+
+        let cell_ranges = render_device.create_buffer(&BufferDescriptor {
+            label: Some("spatial_hash_particles: cell_ranges buffer"),
+            size: cell_ranges_buffer_size(NUMBER_OF_CELLS),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            integrate_pipeline,
+            count_neighbors_pipeline,
+            bind_group_layout,
+            cell_ranges,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct ParticlesBindGroups {
+    particles: BindGroup,
+    cell_ranges: BindGroup,
+}
+
+fn prepare_particles_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    pipeline: Res<ParticlesPipeline>,
+    cell_ranges_pipeline: Res<CellRangesPipeline>,
+    settings: Res<RadixSortSettings>,
+    buffers: Res<ParticleBuffers>,
+    sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+) {
+    let pass_range = settings.default_pass_range();
+    let (Some(particles), Some(densities), Some(keys), Some(sorted_keys), Some(sorted_vals)) = (
+        sbufs.get(&buffers.particles),
+        sbufs.get(&buffers.densities),
+        global_keys_buffer(&sbufs, true),
+        sorted_keys_buffer(&sbufs, &pass_range, true),
+        sorted_vals_buffer(&sbufs, &pass_range, true),
+    ) else {
+        return;
+    };
+
+    let particles = render_device.create_bind_group(
+        "spatial_hash_particles: bind_group",
+        &pipeline.bind_group_layout,
+        &BindGroupEntries::sequential((
+            particles.buffer.as_entire_binding(),
+            keys.as_entire_binding(),
+            sorted_vals.as_entire_binding(),
+            pipeline.cell_ranges.as_entire_binding(),
+            densities.buffer.as_entire_binding(),
+        )),
+    );
+    let cell_ranges =
+        cell_ranges_pipeline.create_bind_group(&render_device, sorted_keys, &pipeline.cell_ranges);
+
+    commands.insert_resource(ParticlesBindGroups {
+        particles,
+        cell_ranges,
+    });
+}
+
+/// Whether the passes and the sort can run.
+fn particles_loaded(world: &World) -> bool {
+    let (Some(pipeline), Some(cell_ranges_pipeline)) = (
+        world.get_resource::<ParticlesPipeline>(),
+        world.get_resource::<CellRangesPipeline>(),
+    ) else {
+        return false;
+    };
+    let pipeline_cache = world.resource::<PipelineCache>();
+
+    world.contains_resource::<ParticlesBindGroups>()
+        && cell_ranges_pipeline.load_state(pipeline_cache) == LoadState::Loaded
+        && [
+            pipeline.integrate_pipeline,
+            pipeline.count_neighbors_pipeline,
+        ]
+        .into_iter()
+        .all(|id| pipeline_cache.get_compute_pipeline(id).is_some())
+}
+
+fn queue_particles_sort(mut jobs: ResMut<RadixSortJobs>, settings: Res<RadixSortSettings>) {
+    jobs.push(SortJob::for_settings(NUMBER_OF_PARTICLES, &settings));
+}
+
+/// Whether the nodes run this frame: only around the sort queued by [`queue_particles_sort`], once everything loaded.
+fn particles_sorted_this_frame(world: &World) -> bool {
+    !world.resource::<RadixSortJobs>().is_empty()
+}
+
+/// Records a pass of `spatial_hash_particles.wgsl` over all the particles.
+fn record_particles_pass(
+    render_context: &mut RenderContext,
+    world: &World,
+    label: &'static str,
+    pipeline_id: CachedComputePipelineId,
+) {
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let bind_groups = world.resource::<ParticlesBindGroups>();
+    let push_constants = ParticlesPushConstants {
+        number_of_particles: NUMBER_OF_PARTICLES,
+        grid_size: GRID_SIZE,
+        cell_size: CELL_SIZE,
+        delta_time: world.resource::<ParticleTime>().0,
+    };
+
+    let mut pass = render_context
+        .command_encoder()
+        .begin_compute_pass(&ComputePassDescriptor {
+            label: Some(label),
+            ..default()
+        });
+    pass.set_pipeline(pipeline_cache.get_compute_pipeline(pipeline_id).unwrap());
+    pass.set_bind_group(0, &bind_groups.particles, &[]);
+    pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+    pass.dispatch_workgroups(NUMBER_OF_PARTICLES.div_ceil(WORKGROUP_SIZE), 1, 1);
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, RenderLabel)]
+struct ParticlesIntegrateLabel;
+
+/// Moves the particles and writes their cell ids, before the sort.
+struct ParticlesIntegrateNode;
+
+impl render_graph::Node for ParticlesIntegrateNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if particles_sorted_this_frame(world) {
+            record_particles_pass(
+                render_context,
+                world,
+                "spatial_hash_particles: integrate compute pass",
+                world.resource::<ParticlesPipeline>().integrate_pipeline,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, RenderLabel)]
+struct ParticlesNeighborsLabel;
+
+/// Builds the cell ranges of the sorted cell ids and counts the neighbors, after the sort.
+struct ParticlesNeighborsNode;
+
+impl render_graph::Node for ParticlesNeighborsNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if !particles_sorted_this_frame(world) {
+            return Ok(());
+        }
+
+        let max_compute_workgroups_per_dimension = world
+            .resource::<RenderDevice>()
+            .limits()
+            .max_compute_workgroups_per_dimension;
+        world
+            .resource::<CellRangesPipeline>()
+            .record_build_cell_ranges(
+                render_context.command_encoder(),
+                world.resource::<PipelineCache>(),
+                &world.resource::<ParticlesBindGroups>().cell_ranges,
+                max_compute_workgroups_per_dimension,
+                NUMBER_OF_PARTICLES,
+                NUMBER_OF_CELLS,
+            );
+
+        record_particles_pass(
+            render_context,
+            world,
+            "spatial_hash_particles: count_neighbors compute pass",
+            world
+                .resource::<ParticlesPipeline>()
+                .count_neighbors_pipeline,
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+// The compute passes of the `spatial_hash_particles` example, around the sort and the cell-range pass.
+//
+// `integrate` moves the particles and writes the id of their grid cell as the key of the sort, `count_neighbors`
+// walks the 3x3 cells around each particle through the cell ranges of the sorted keys.
+
+// Must match `Particle` in `spatial_hash_particles.rs`
+struct Particle {
+    position: vec2f,
+    velocity: vec2f,
+}
+
+struct PushConstants {
+    number_of_particles: u32,
+    grid_size: u32,
+    cell_size: f32,
+    delta_time: f32,
+}
+
+var<push_constant> pc: PushConstants;
+
+// `bevy_radix_sort::EMPTY_CELL`
+const EMPTY_CELL: u32 = 0xffffffffu;
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+// The even global keys, the input of the sort
+@group(0) @binding(1) var<storage, read_write> keys: array<u32>;
+// The sorted vals: the particle indices, ordered by cell
+@group(0) @binding(2) var<storage, read      > sorted_vals: array<u32>;
+@group(0) @binding(3) var<storage, read      > cell_ranges: array<vec2u>;
+@group(0) @binding(4) var<storage, read_write> densities: array<u32>;
+
+fn half_extent() -> f32 {
+    return 0.5 * f32(pc.grid_size) * pc.cell_size;
+}
+
+fn cell_of(position: vec2f) -> vec2i {
+    let cell = vec2i(floor((position + half_extent()) / pc.cell_size));
+    return clamp(cell, vec2i(0), vec2i(i32(pc.grid_size) - 1));
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn integrate(@builtin(global_invocation_id) id: vec3u) {
+    let index = id.x;
+    if index >= pc.number_of_particles {
+        return;
+    }
+
+    var particle = particles[index];
+    particle.position += particle.velocity * pc.delta_time;
+
+    // Bounce off the walls of the grid
+    let bound = half_extent() - 0.001;
+    if abs(particle.position.x) > bound {
+        particle.velocity.x = -particle.velocity.x;
+    }
+    if abs(particle.position.y) > bound {
+        particle.velocity.y = -particle.velocity.y;
+    }
+    particle.position = clamp(particle.position, vec2f(-bound), vec2f(bound));
+    particles[index] = particle;
+
+    let cell = cell_of(particle.position);
+    keys[index] = u32(cell.y) * pc.grid_size + u32(cell.x);
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn count_neighbors(@builtin(global_invocation_id) id: vec3u) {
+    let index = id.x;
+    if index >= pc.number_of_particles {
+        return;
+    }
+
+    let position = particles[index].position;
+    let cell = cell_of(position);
+
+    // The radius is the cell size, so the neighbors are all within the 3x3 cells
+    var count = 0u;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let neighbor = cell + vec2i(dx, dy);
+            if any(neighbor < vec2i(0)) || any(neighbor >= vec2i(i32(pc.grid_size))) {
+                continue;
+            }
+
+            let range = cell_ranges[u32(neighbor.y) * pc.grid_size + u32(neighbor.x)];
+            if range.x == EMPTY_CELL {
+                continue;
+            }
+
+            for (var i = range.x; i < range.y; i++) {
+                let other = sorted_vals[i];
+                if other != index && distance(particles[other].position, position) < pc.cell_size {
+                    count += 1u;
+                }
+            }
+        }
+    }
+
+    densities[index] = count;
+}
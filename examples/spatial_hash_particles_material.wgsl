@@ -0,0 +1,47 @@
+// The material of the `spatial_hash_particles` example: the mesh holds a quad per particle, the vertex shader moves
+// quad `vertex_index / 4` to its particle and colors it by the neighbor count.
+
+#import bevy_sprite::mesh2d_functions::{get_world_from_local, mesh2d_position_local_to_clip}
+
+// Must match `Particle` in `spatial_hash_particles.rs`
+struct Particle {
+    position: vec2f,
+    velocity: vec2f,
+}
+
+@group(2) @binding(0) var<storage, read> particles: array<Particle>;
+@group(2) @binding(1) var<storage, read> densities: array<u32>;
+
+// The neighbor count drawn in the hottest color
+const MAX_DENSITY: f32 = 24.0;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @builtin(vertex_index) vertex_index: u32,
+    // The corner of the quad, relative to its particle
+    @location(0) position: vec3f,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec4f,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let particle = vertex.vertex_index / 4u;
+    let position = vec4f(vertex.position.xy + particles[particle].position, 0.0, 1.0);
+
+    let density = clamp(f32(densities[particle]) / MAX_DENSITY, 0.0, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position =
+        mesh2d_position_local_to_clip(get_world_from_local(vertex.instance_index), position);
+    out.color = vec4f(mix(vec3f(0.1, 0.3, 1.0), vec3f(1.0, 0.2, 0.05), density), 1.0);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4f {
+    return in.color;
+}
@@ -0,0 +1,150 @@
+//! Y-sorts 80k moving sprites with the [`YSortPlugin`] every frame and compares the time of the ordering on the GPU
+//! with a CPU sort of the same keys, the sort the `Transparent2d` phase would do.
+//!
+//! ```text
+//! cargo run --release --example y_sort_stress -- 80000
+//! ```
+//!
+//! The GPU duration needs a backend supporting the timestamp queries inside encoders. The sprites are still drawn by
+//! Bevy in z order, the example only measures the orderings.
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    prelude::*,
+    render::{Render, RenderApp, RenderSet, renderer::RenderDevice, view::VisibilitySystems},
+    utils::FloatOrd,
+};
+use bevy_radix_sort::{
+    RadixSortJobsConfig, SortJobCompleted, YSortPlugin, YSortedSprites, prelude::*,
+    supports_job_timestamps,
+};
+use rand::Rng;
+
+/// The frames averaged per log line.
+const LOG_FRAMES: u32 = 120;
+
+#[derive(Resource)]
+struct SpriteCount(u32);
+
+#[derive(Component)]
+struct Velocity(Vec2);
+
+fn main() {
+    let count: u32 = std::env::args()
+        .nth(1)
+        .map(|count| count.parse().expect("the sprite count should be a number"))
+        .unwrap_or(80_000);
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .add_plugins(RadixSortPlugin {
+            settings: count.into(),
+        })
+        .add_plugins(YSortPlugin)
+        .insert_resource(SpriteCount(count))
+        .add_systems(Startup, setup)
+        .add_systems(Update, move_sprites)
+        .add_systems(
+            PostUpdate,
+            time_cpu_sort.after(VisibilitySystems::CheckVisibility),
+        );
+
+    app.sub_app_mut(RenderApp)
+        .insert_resource(RadixSortJobsConfig {
+            timestamps: true,
+            ..default()
+        })
+        .add_systems(Render, time_gpu_sort.in_set(RenderSet::Queue));
+
+    app.run();
+}
+
+fn setup(mut commands: Commands, count: Res<SpriteCount>) {
+    commands.spawn(Camera2d);
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..count.0 {
+        let position = Vec2::new(rng.gen_range(-600.0..600.0), rng.gen_range(-350.0..350.0));
+        commands.spawn((
+            Sprite::from_color(
+                Color::hsl(rng.gen_range(0.0..360.0), 0.7, 0.5),
+                Vec2::splat(4.0),
+            ),
+            Transform::from_translation(position.extend(0.0)),
+            Velocity(Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * 40.0),
+        ));
+    }
+}
+
+fn move_sprites(time: Res<Time>, mut sprites: Query<(&mut Transform, &mut Velocity)>) {
+    for (mut transform, mut velocity) in &mut sprites {
+        transform.translation += (velocity.0 * time.delta_secs()).extend(0.0);
+        if transform.translation.x.abs() > 600.0 {
+            velocity.0.x = -velocity.0.x;
+        }
+        if transform.translation.y.abs() > 350.0 {
+            velocity.0.y = -velocity.0.y;
+        }
+    }
+}
+
+/// Sorts the visible sprites by y on the CPU, as a phase sorting its items would.
+fn time_cpu_sort(
+    sprites: Query<(Entity, &GlobalTransform, &ViewVisibility), With<Sprite>>,
+    mut items: Local<Vec<(FloatOrd, Entity)>>,
+    mut total: Local<(Duration, u32)>,
+) {
+    items.clear();
+    items.extend(
+        sprites
+            .iter()
+            .filter(|(_, _, visibility)| visibility.get())
+            .map(|(entity, transform, _)| (FloatOrd(-transform.translation().y), entity)),
+    );
+
+    let start = Instant::now();
+    items.sort_by_key(|&(key, _)| key);
+    total.0 += start.elapsed();
+    total.1 += 1;
+
+    if total.1 == LOG_FRAMES {
+        info!(
+            "CPU: {} sprites ordered in {:?} on average",
+            items.len(),
+            total.0 / LOG_FRAMES
+        );
+        *total = default();
+    }
+}
+
+/// Reads the GPU duration of the Y-sort of the previous frame, before the sort of this frame is queued.
+fn time_gpu_sort(
+    render_device: Res<RenderDevice>,
+    sorted: Res<YSortedSprites>,
+    mut completed: EventReader<SortJobCompleted>,
+    mut total: Local<(Duration, u32)>,
+) {
+    for event in completed.read() {
+        if Some(event.id) != sorted.job() {
+            continue;
+        }
+        if let Some(duration) = event.gpu_duration {
+            total.0 += duration;
+            total.1 += 1;
+        }
+    }
+
+    if total.1 == LOG_FRAMES {
+        info!(
+            "GPU: {} sprites ordered in {:?} on average",
+            sorted.len(),
+            total.0 / LOG_FRAMES
+        );
+        *total = default();
+    } else if !supports_job_timestamps(&render_device) {
+        warn_once!(
+            "the device doesn't support the timestamp queries inside encoders, no GPU durations"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! The adapter key and the directory shared by the caches persisted across runs, the
+//! [`crate::SubgroupSizeCache`] and the [`crate::PersistentPipelineCache`].
+
+use std::path::PathBuf;
+
+use bevy::render::render_resource::WgpuAdapterInfo;
+
+/// Identifies the adapter and the driver a cached value belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AdapterCacheKey {
+    pub backend: String,
+    pub vendor: u32,
+    pub device: u32,
+    pub driver: String,
+    pub driver_info: String,
+}
+
+impl From<&WgpuAdapterInfo> for AdapterCacheKey {
+    fn from(info: &WgpuAdapterInfo) -> Self {
+        Self {
+            backend: format!("{:?}", info.backend),
+            vendor: info.vendor,
+            device: info.device,
+            driver: info.driver.clone(),
+            driver_info: info.driver_info.clone(),
+        }
+    }
+}
+
+impl AdapterCacheKey {
+    /// `<backend>\t<vendor>\t<device>\t<driver>\t<driver_info>`, a line of the cache files once prefixed or suffixed.
+    pub(crate) fn to_fields(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            sanitize_field(&self.backend),
+            self.vendor,
+            self.device,
+            sanitize_field(&self.driver),
+            sanitize_field(&self.driver_info)
+        )
+    }
+}
+
+/// Replaces the tabs and newlines of `field`, the separators of the cache files, by spaces.
+pub(crate) fn sanitize_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+/// The directory of the caches in the platform cache directory (e.g. `~/.cache/bevy_radix_sort` on Linux), `None`
+/// if the platform has none.
+pub(crate) fn platform_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("bevy_radix_sort"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapter_cache_key_fields() {
+        let key = AdapterCacheKey {
+            backend: "Vulkan".into(),
+            vendor: 0x10de,
+            device: 0x2705,
+            driver: "NVIDIA".into(),
+            driver_info: "566.36\tbeta\r\n".into(),
+        };
+
+        // The separators of a driver string don't add fields
+        assert_eq!(key.to_fields(), "Vulkan\t4318\t9989\tNVIDIA\t566.36 beta  ");
+        assert_eq!(key.to_fields().split('\t').count(), 5);
+    }
+}
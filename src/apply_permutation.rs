@@ -0,0 +1,462 @@
+//! Sorting a main world `Vec` by keys sorted on the GPU, the permutation read back and applied in place.
+//!
+//! ```text
+//!  main PostUpdate:       ApplyGpuPermutation::sort ─▶ keys extracted by the closure ─▶ cell
+//!  render Prepare:        a SortJob uploading the keys into the global buffers, an argsort
+//!  render Render:         the job's callback copies the sorted vals (the permutation) into a BufferReadback
+//!  render after Render:   the readback polled, never blocking, until it's mapped ─▶ cell
+//!  main First:            the permutation applied by following its cycles, GpuPermutationApplied sent
+//! ```
+//!
+//! The permutation arrives a few frames after [`ApplyGpuPermutation::sort`]. Elements pushed or removed in between
+//! make it stale: it's dropped with an error in the [`GpuPermutationApplied`] event. Elements modified in place are
+//! reordered by the keys they had when the sort was requested.
+//!
+//! The sort is a [`SortJob`] on the global buffers, so it overwrites what they hold, like the other jobs on them. It
+//! needs the vals allocated, and as many keys as the elements in the capacity of the [`RadixSortSettings`].
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_resource::{BufferInitDescriptor, BufferUsages},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{BufferReadback, RadixSortJobs, RadixSortRunOptions, RadixSortSettings, SortJob};
+
+/// Reorders `items` by `permutation`, the sorted vals of an argsort: `items[i]` becomes the element at
+/// `permutation[i]`.
+///
+/// Swaps the elements along the cycles of the permutation, without cloning them, and leaves `items` untouched when
+/// `permutation` isn't a permutation of `0..items.len()`.
+pub fn apply_permutation<T>(items: &mut [T], permutation: &[u32]) -> Result<(), String> {
+    if permutation.len() != items.len() {
+        return Err(format!(
+            "the permutation has {} indices for {} elements",
+            permutation.len(),
+            items.len()
+        ));
+    }
+
+    let mut placed = vec![false; items.len()];
+    for &index in permutation {
+        match placed.get_mut(index as usize) {
+            Some(placed @ false) => *placed = true,
+            Some(true) => return Err(format!("the index {index} is repeated")),
+            None => return Err(format!("the index {index} is out of range")),
+        }
+    }
+
+    placed.fill(false);
+    for start in 0..items.len() {
+        let mut current = start;
+        // Moves the element each position takes into it, the last one of the cycle is in place after the swaps
+        while !placed[current] {
+            placed[current] = true;
+            let next = permutation[current] as usize;
+            if next == start {
+                break;
+            }
+            items.swap(current, next);
+            current = next;
+        }
+    }
+
+    Ok(())
+}
+
+/// Identifies a call of [`ApplyGpuPermutation::sort`], increasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GpuPermutationId(pub u64);
+
+/// A main world `Vec` sorted by a key of its elements on the GPU, see the [module docs](self). Needs the
+/// [`ApplyGpuPermutationPlugin`] of the same `T`.
+#[derive(Resource)]
+pub struct ApplyGpuPermutation<T: Send + Sync + 'static> {
+    pub items: Vec<T>,
+    key: Arc<dyn Fn(&T) -> u32 + Send + Sync>,
+    options: RadixSortRunOptions,
+    next_id: u64,
+    /// The last sort requested, not extracted yet
+    requested: Option<GpuPermutationId>,
+    /// The last sort extracted, waiting for its permutation
+    in_flight: Option<GpuPermutationId>,
+}
+
+impl<T: Send + Sync + 'static> ApplyGpuPermutation<T> {
+    /// `items` sorted by `key`, over all the 32 bits of the keys.
+    pub fn new(items: Vec<T>, key: impl Fn(&T) -> u32 + Send + Sync + 'static) -> Self {
+        Self {
+            items,
+            key: Arc::new(key),
+            options: default(),
+            next_id: 0,
+            requested: None,
+            in_flight: None,
+        }
+    }
+
+    /// Sorts over fewer passes when the set bits of the keys lie within the lowest `key_bits` bits.
+    pub fn with_key_bits(mut self, key_bits: u32) -> Self {
+        self.options = RadixSortRunOptions::for_key_bits(key_bits);
+        self
+    }
+
+    /// Requests a sort of the [`Self::items`], the keys are extracted in [`PostUpdate`] of this frame. Supersedes the
+    /// sort in flight, if any: its permutation is dropped.
+    pub fn sort(&mut self) -> GpuPermutationId {
+        let id = GpuPermutationId(self.next_id);
+        self.next_id += 1;
+        self.requested = Some(id);
+
+        id
+    }
+
+    /// Whether a sort is requested or waiting for its permutation.
+    pub fn is_sorting(&self) -> bool {
+        self.requested.is_some() || self.in_flight.is_some()
+    }
+}
+
+impl<T: Send + Sync + 'static> fmt::Debug for ApplyGpuPermutation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApplyGpuPermutation")
+            .field("len", &self.items.len())
+            .field("options", &self.options)
+            .field("requested", &self.requested)
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sent in the main world once the permutation of an [`ApplyGpuPermutation::sort`] is applied, or failed to.
+#[derive(Event)]
+pub struct GpuPermutationApplied<T: Send + Sync + 'static> {
+    pub id: GpuPermutationId,
+    pub result: Result<(), String>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Send + Sync + 'static> GpuPermutationApplied<T> {
+    fn new(id: GpuPermutationId, result: Result<(), String>) -> Self {
+        Self {
+            id,
+            result,
+            marker: PhantomData,
+        }
+    }
+}
+
+// Not derived, the derives would bound `T`
+impl<T: Send + Sync + 'static> Clone for GpuPermutationApplied<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id, self.result.clone())
+    }
+}
+
+impl<T: Send + Sync + 'static> fmt::Debug for GpuPermutationApplied<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GpuPermutationApplied")
+            .field("id", &self.id)
+            .field("result", &self.result)
+            .finish()
+    }
+}
+
+/// Sorts the [`ApplyGpuPermutation<T>`] resource of the main world on the GPU. Requires the
+/// [`crate::RadixSortPlugin`].
+pub struct ApplyGpuPermutationPlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for ApplyGpuPermutationPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Send + Sync + 'static> Plugin for ApplyGpuPermutationPlugin<T> {
+    fn build(&self, app: &mut App) {
+        let cell = PermutationCell::<T>::default();
+
+        app.add_event::<GpuPermutationApplied<T>>()
+            .insert_resource(cell.clone())
+            .add_systems(
+                First,
+                apply_gpu_permutations::<T>.run_if(resource_exists::<ApplyGpuPermutation<T>>),
+            )
+            .add_systems(
+                PostUpdate,
+                request_gpu_permutations::<T>.run_if(resource_exists::<ApplyGpuPermutation<T>>),
+            );
+        app.sub_app_mut(RenderApp)
+            .insert_resource(cell)
+            .add_systems(
+                Render,
+                (
+                    queue_gpu_permutations::<T>.in_set(RenderSet::PrepareResources),
+                    poll_gpu_permutations::<T>.after(RenderSet::Render),
+                ),
+            );
+    }
+}
+
+/// A sort extracted from the main world, with its keys and options.
+struct PermutationRequest {
+    id: GpuPermutationId,
+    keys: Vec<u32>,
+    options: RadixSortRunOptions,
+}
+
+/// The requests, readbacks and permutations of the sorts of a `T`, shared by both worlds.
+#[derive(Default)]
+struct PermutationShared {
+    requests: Mutex<Vec<PermutationRequest>>,
+    readbacks: Mutex<Vec<(GpuPermutationId, BufferReadback)>>,
+    permutations: Mutex<Vec<(GpuPermutationId, Result<Vec<u32>, String>)>>,
+}
+
+#[derive(Resource)]
+struct PermutationCell<T> {
+    shared: Arc<PermutationShared>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for PermutationCell<T> {
+    fn default() -> Self {
+        Self {
+            shared: default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for PermutationCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+fn request_gpu_permutations<T: Send + Sync + 'static>(
+    mut permutation: ResMut<ApplyGpuPermutation<T>>,
+    cell: Res<PermutationCell<T>>,
+    mut applied: EventWriter<GpuPermutationApplied<T>>,
+) {
+    let Some(id) = permutation.requested.take() else {
+        return;
+    };
+
+    // Already sorted, and the sort doesn't write the indices of fewer than 2 keys
+    if permutation.items.len() < 2 {
+        permutation.in_flight = None;
+        applied.send(GpuPermutationApplied::new(id, Ok(())));
+        return;
+    }
+
+    let keys = permutation.items.iter().map(&*permutation.key).collect();
+    cell.shared
+        .requests
+        .lock()
+        .unwrap()
+        .push(PermutationRequest {
+            id,
+            keys,
+            options: permutation.options.clone(),
+        });
+    permutation.in_flight = Some(id);
+}
+
+fn queue_gpu_permutations<T: Send + Sync + 'static>(
+    cell: Res<PermutationCell<T>>,
+    render_device: Res<RenderDevice>,
+    radix_sort_settings: Option<Res<RadixSortSettings>>,
+    jobs: Option<ResMut<RadixSortJobs>>,
+) {
+    let requests = std::mem::take(&mut *cell.shared.requests.lock().unwrap());
+    if requests.is_empty() {
+        return;
+    }
+    let (Some(radix_sort_settings), Some(mut jobs)) = (radix_sort_settings, jobs) else {
+        error!("radix_sort: ApplyGpuPermutationPlugin without the RadixSortPlugin");
+        return;
+    };
+
+    for PermutationRequest { id, keys, options } in requests {
+        let count = keys.len() as u32;
+        let capacity = radix_sort_settings
+            .max_number_of_keys()
+            .min(radix_sort_settings.max_number_of_values());
+        if count > capacity || !radix_sort_settings.allocate_values() {
+            let error = format!(
+                "{id:?} sorts {count} elements, the global buffers hold {} keys and {} vals",
+                radix_sort_settings.max_number_of_keys(),
+                if radix_sort_settings.allocate_values() {
+                    radix_sort_settings.max_number_of_values()
+                } else {
+                    0
+                }
+            );
+            cell.shared
+                .permutations
+                .lock()
+                .unwrap()
+                .push((id, Err(error)));
+            continue;
+        }
+
+        let upload = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("apply_permutation: keys upload buffer"),
+            usage: BufferUsages::COPY_SRC,
+            contents: bytemuck::cast_slice(&keys),
+        });
+        let shared = cell.shared.clone();
+        let device = render_device.clone();
+        jobs.push(
+            SortJob::new(count)
+                .with_options(options)
+                .with_upload(upload, None)
+                .with_on_complete(move |encoder, output| {
+                    let Some(vals) = output.vals else {
+                        return;
+                    };
+                    let readback =
+                        BufferReadback::new(&device, encoder, vals, 0, output.count as usize);
+                    shared.readbacks.lock().unwrap().push((id, readback));
+                }),
+        );
+    }
+}
+
+/// Polls the readbacks, submitted with the frame by now.
+fn poll_gpu_permutations<T: Send + Sync + 'static>(
+    cell: Res<PermutationCell<T>>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut readbacks = cell.shared.readbacks.lock().unwrap();
+    readbacks.retain_mut(|(id, readback)| match readback.poll(&render_device) {
+        None => true,
+        Some(result) => {
+            let result = result.map_err(|err| format!("the readback of {id:?} failed: {err}"));
+            cell.shared.permutations.lock().unwrap().push((*id, result));
+            false
+        }
+    });
+}
+
+fn apply_gpu_permutations<T: Send + Sync + 'static>(
+    mut permutation: ResMut<ApplyGpuPermutation<T>>,
+    cell: Res<PermutationCell<T>>,
+    mut applied: EventWriter<GpuPermutationApplied<T>>,
+) {
+    let permutations = std::mem::take(&mut *cell.shared.permutations.lock().unwrap());
+    for (id, indices) in permutations {
+        // Superseded by a later sort
+        if permutation.in_flight != Some(id) {
+            continue;
+        }
+        permutation.in_flight = None;
+
+        let result =
+            indices.and_then(|indices| apply_permutation(&mut permutation.items, &indices));
+        if let Err(err) = &result {
+            error!("radix_sort: the GPU permutation wasn't applied: {}", err);
+        }
+        applied.send(GpuPermutationApplied::new(id, result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, run_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_apply_permutation() {
+        let mut items = vec!['a', 'b', 'c', 'd', 'e'];
+        // A 3-cycle, a fixed point and a swap
+        apply_permutation(&mut items, &[2, 0, 1, 3, 4]).unwrap();
+        assert_eq!(items, vec!['c', 'a', 'b', 'd', 'e']);
+        apply_permutation(&mut items, &[0, 1, 2, 4, 3]).unwrap();
+        assert_eq!(items, vec!['c', 'a', 'b', 'e', 'd']);
+
+        for invalid in [&[0, 1, 2, 3][..], &[0, 1, 2, 3, 3], &[0, 1, 2, 3, 5]] {
+            assert!(apply_permutation(&mut items, invalid).is_err());
+            assert_eq!(items, vec!['c', 'a', 'b', 'e', 'd']);
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Item {
+        key: u32,
+        name: String,
+    }
+
+    #[test]
+    fn test_apply_gpu_permutation() {
+        let count = 100_000;
+        let items: Vec<Item> = (0..count)
+            .map(|i: u32| Item {
+                key: i.wrapping_mul(2_654_435_761) % 10_000,
+                name: format!("item {i}"),
+            })
+            .collect();
+        let mut expected = items.clone();
+        expected.sort_by_key(|item| item.key);
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: count.into(),
+            })
+            .add_plugins(ApplyGpuPermutationPlugin::<Item>::default())
+            .insert_resource(
+                ApplyGpuPermutation::new(items, |item: &Item| item.key).with_key_bits(14),
+            );
+        let id = app
+            .world_mut()
+            .resource_mut::<ApplyGpuPermutation<Item>>()
+            .sort();
+
+        run_once(&mut app);
+        let mut frames = 0;
+        while app
+            .world()
+            .resource::<ApplyGpuPermutation<Item>>()
+            .is_sorting()
+        {
+            assert!(frames < 100, "the permutation wasn't applied");
+            app.update();
+            frames += 1;
+        }
+
+        // Stable, like `sort_by_key`
+        assert_eq!(
+            app.world().resource::<ApplyGpuPermutation<Item>>().items,
+            expected
+        );
+        let events = app
+            .world()
+            .resource::<Events<GpuPermutationApplied<Item>>>();
+        assert_eq!(
+            events
+                .get_cursor()
+                .read(events)
+                .map(|event| (event.id, event.result.clone()))
+                .collect::<Vec<_>>(),
+            vec![(id, Ok(()))]
+        );
+    }
+}
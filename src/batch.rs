@@ -0,0 +1,602 @@
+//! Several independent sorts recorded with their steps interleaved, see [`run_batch`].
+//!
+//! Recorded one after the other, small sorts leave most of the GPU idle: each step of a pass waits for the previous
+//! one of the same sort. The jobs of a batch sort buffers of their own, so [`run_batch`] records the count steps of
+//! all the jobs, then all their scans and all their scatters, digit by digit, and the driver may overlap the
+//! dispatches of different jobs:
+//!
+//! ```text
+//!  sequential:   count 1 ─▶ scan 1 ─▶ scatter 1 ─▶ ... ─▶ count 2 ─▶ scan 2 ─▶ scatter 2 ─▶ ...
+//!  interleaved:  count 1, count 2, ... ─▶ scan 1, scan 2, ... ─▶ scatter 1, scatter 2, ... ─▶ next digit
+//! ```
+//!
+//! How much the dispatches overlap depends on the backend, the results are the same as the sequential runs.
+
+use std::{collections::HashSet, ops::Range};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferAddress, BufferId, CommandEncoder, ComputePassDescriptor, PipelineCache,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    Algorithm, NUMBER_OF_BYTES_PER_KEY, RadixSortBindGroup, RadixSortPipeline, SortPipelines,
+    blocks_buffer_size, check_run_arguments, create_dummy_vals_buffers, is_input_even,
+    passes_needed, record_count_step, record_scan_step, record_scatter_step, run,
+};
+
+/// A sort on buffers of its own, with the arguments of [`run`], for [`run_batch`].
+#[derive(Debug, Clone)]
+pub struct PreparedSortJob {
+    bind_group: RadixSortBindGroup,
+    /// The buffers the sort binds, the jobs sharing none are interleaved
+    buffers: Vec<BufferId>,
+    /// The number of keys sorted.
+    pub count: u32,
+    pub pass_range: Range<u32>,
+    /// Initialize the vals with the original indices during the first pass.
+    pub init_index: bool,
+    /// Sort the even side of the buffers, the odd side otherwise.
+    pub read_from_even: bool,
+}
+
+impl PreparedSortJob {
+    /// Binds the buffers of a sort of `count` keys like [`RadixSortBindGroup::from_buffers`]: an argsort of the
+    /// `eve_keys` over all the passes of the pipeline if it has `vals`, a keys-only sort otherwise.
+    ///
+    /// The keys buffers hold at least `count` keys of the [`RadixSortPipeline::key_type`], 8 bytes each for
+    /// [`crate::KeyType::U64`], the vals buffers `count` u32s, `blocks` [`blocks_buffer_size`]`(count)` bytes, all
+    /// with `STORAGE` usage. A job with `blocks` of its own can be interleaved with the other jobs, e.g. not if it
+    /// binds the global blocks buffer another job of the batch binds too.
+    ///
+    /// # Panics
+    ///
+    /// If a buffer is too small for `count` keys.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        render_device: &RenderDevice,
+        radix_sort_pipeline: &RadixSortPipeline,
+        eve_keys: &Buffer,
+        odd_keys: &Buffer,
+        vals: Option<(&Buffer, &Buffer)>,
+        blocks: &Buffer,
+        count: u32,
+    ) -> Self {
+        let keys_size =
+            count as BufferAddress * radix_sort_pipeline.key_type().size() as BufferAddress;
+        let vals_size = count as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+        let (eve_vals, odd_vals) = vals.unzip();
+        let buffers = [
+            ("eve_keys", Some(eve_keys), keys_size),
+            ("odd_keys", Some(odd_keys), keys_size),
+            ("eve_vals", eve_vals, vals_size),
+            ("odd_vals", odd_vals, vals_size),
+            ("blocks", Some(blocks), blocks_buffer_size(count)),
+        ];
+        for (name, buffer, size) in buffers {
+            let Some(buffer) = buffer else {
+                continue;
+            };
+            assert!(
+                buffer.size() >= size,
+                "run_batch: {} holds {} bytes, less than the {} bytes of {} keys",
+                name,
+                buffer.size(),
+                size,
+                count
+            );
+        }
+
+        let dummy_vals;
+        let (eve_vals, odd_vals) = match vals {
+            Some(vals) => vals,
+            None => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                (&dummy_vals.0, &dummy_vals.1)
+            }
+        };
+        let bind_group = RadixSortBindGroup::from_buffers(
+            render_device,
+            radix_sort_pipeline.bind_group_layout(),
+            eve_keys,
+            eve_vals,
+            blocks,
+            odd_keys,
+            odd_vals,
+        );
+
+        Self {
+            bind_group,
+            buffers: buffers
+                .into_iter()
+                .filter_map(|(_, buffer, _)| buffer.map(Buffer::id))
+                .collect(),
+            count,
+            pass_range: 0..passes_needed(radix_sort_pipeline.key_bits()),
+            init_index: vals.is_some() && radix_sort_pipeline.allocate_values(),
+            read_from_even: true,
+        }
+    }
+
+    pub fn with_pass_range(mut self, pass_range: Range<u32>) -> Self {
+        self.pass_range = pass_range;
+        self
+    }
+
+    pub fn with_init_index(mut self, init_index: bool) -> Self {
+        self.init_index = init_index;
+        self
+    }
+
+    pub fn with_read_from_even(mut self, read_from_even: bool) -> Self {
+        self.read_from_even = read_from_even;
+        self
+    }
+
+    pub fn bind_group(&self) -> &RadixSortBindGroup {
+        &self.bind_group
+    }
+
+    /// `true` if both jobs bind a same buffer, they can't be interleaved.
+    pub fn aliases(&self, other: &PreparedSortJob) -> bool {
+        self.buffers.iter().any(|id| other.buffers.contains(id))
+    }
+}
+
+/// `true` if [`run_batch`] interleaves the `jobs`: two jobs at least, none binding a buffer of another.
+pub fn batch_interleaves(jobs: &[PreparedSortJob]) -> bool {
+    let mut bound = HashSet::new();
+    jobs.len() >= 2
+        && jobs.iter().all(|job| {
+            // A job may bind a buffer twice, e.g. the same vals on both sides
+            let buffers: HashSet<BufferId> = job.buffers.iter().copied().collect();
+            buffers.into_iter().all(|id| bound.insert(id))
+        })
+}
+
+/// Sorts each job like [`run`] on its bind group, with the steps of the jobs interleaved if
+/// [`batch_interleaves`] returns `true`. Otherwise, with the [`Algorithm::Bitonic`] backend, or with the
+/// `verify-sorts` feature so each sort is verified on its own, the jobs are recorded one after the other, in order.
+///
+/// Claims the global buffers for the frame on behalf of the calling source file, like [`run`].
+#[track_caller]
+pub fn run_batch(
+    encoder: &mut CommandEncoder,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    max_compute_workgroups_per_dimension: u32,
+    jobs: &[PreparedSortJob],
+) {
+    let sequential = !batch_interleaves(jobs)
+        || radix_sort_pipeline.active_algorithm(pipeline_cache) == Algorithm::Bitonic
+        || cfg!(all(feature = "verify-sorts", debug_assertions));
+    if sequential {
+        for job in jobs {
+            run(
+                encoder,
+                pipeline_cache,
+                radix_sort_pipeline,
+                &job.bind_group,
+                max_compute_workgroups_per_dimension,
+                job.count,
+                job.pass_range.clone(),
+                job.init_index,
+                job.read_from_even,
+            );
+        }
+        return;
+    }
+
+    let jobs: Vec<&PreparedSortJob> = jobs
+        .iter()
+        .filter(|job| {
+            check_run_arguments(
+                radix_sort_pipeline,
+                &job.bind_group,
+                job.count,
+                &job.pass_range,
+                job.init_index,
+            ) && job.count >= 2
+        })
+        .collect();
+    if jobs.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_batch", jobs = jobs.len()).entered();
+
+    for job in &jobs {
+        radix_sort_pipeline.counters().record(job.count);
+    }
+    // Logged, the sorts are recorded anyway
+    let _ = radix_sort_pipeline
+        .claims()
+        .claim_or_report(std::panic::Location::caller());
+
+    let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("radix_sort batch compute pass"),
+        ..default()
+    });
+
+    // The `step`-th pass of each job still sorting, with the bind group reading its input side
+    let number_of_steps = jobs
+        .iter()
+        .map(|job| job.pass_range.end.saturating_sub(job.pass_range.start))
+        .max()
+        .unwrap_or_default();
+    for step in 0..number_of_steps {
+        let passes: Vec<_> = jobs
+            .iter()
+            .filter_map(|job| {
+                let pass_index = job.pass_range.start + step;
+                (pass_index < job.pass_range.end).then(|| {
+                    let bind_group = job
+                        .bind_group
+                        .bind_group(is_input_even(pass_index, job.read_from_even));
+                    (job, pass_index, bind_group)
+                })
+            })
+            .collect();
+
+        for &(job, pass_index, bind_group) in &passes {
+            record_count_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                job.count,
+                pass_index,
+            );
+        }
+        for &(job, _, bind_group) in &passes {
+            record_scan_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                job.count,
+            );
+        }
+        for &(job, pass_index, bind_group) in &passes {
+            record_scatter_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                job.count,
+                pass_index,
+                job.init_index && step == 0,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssets,
+        render_resource::{BufferDescriptor, BufferUsages, CommandEncoderDescriptor},
+        renderer::RenderQueue,
+        storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        KeyType, RadixSortPlugin, RadixSortSettings, global_blocks_buffer, is_output_even,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// The keys of a job, within the bits of its passes.
+    fn job_keys(count: u32, pass_range: &Range<u32>, seed: u32) -> Vec<u32> {
+        let mask = u32::MAX >> (32 - 8 * pass_range.end);
+        (0..count)
+            .map(|i| (i ^ seed).wrapping_mul(2654435761) & mask)
+            .collect()
+    }
+
+    /// The buffers of a job, with the keys on the even side.
+    struct JobBuffers {
+        keys: [Buffer; 2],
+        vals: [Buffer; 2],
+        blocks: Buffer,
+    }
+
+    impl JobBuffers {
+        fn new(render_device: &RenderDevice, keys: &[u32]) -> Self {
+            let zeros = vec![0; keys.len()];
+            Self {
+                keys: [
+                    create_storage_buffer(render_device, keys),
+                    create_storage_buffer(render_device, &zeros),
+                ],
+                vals: [
+                    create_storage_buffer(render_device, &zeros),
+                    create_storage_buffer(render_device, &zeros),
+                ],
+                blocks: render_device.create_buffer(&BufferDescriptor {
+                    label: Some("unit_test: batch blocks buffer"),
+                    size: blocks_buffer_size(keys.len() as u32),
+                    usage: BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                }),
+            }
+        }
+
+        fn job(
+            &self,
+            render_device: &RenderDevice,
+            radix_sort_pipeline: &RadixSortPipeline,
+            blocks: &Buffer,
+            count: u32,
+            pass_range: Range<u32>,
+        ) -> PreparedSortJob {
+            PreparedSortJob::new(
+                render_device,
+                radix_sort_pipeline,
+                &self.keys[0],
+                &self.keys[1],
+                Some((&self.vals[0], &self.vals[1])),
+                blocks,
+                count,
+            )
+            .with_pass_range(pass_range)
+        }
+
+        /// The sorted keys and vals of `job`.
+        fn read(
+            &self,
+            render_device: &RenderDevice,
+            render_queue: &RenderQueue,
+            job: &PreparedSortJob,
+        ) -> (Vec<u32>, Vec<u32>) {
+            let side = usize::from(!is_output_even(&job.pass_range, job.read_from_even));
+            let len = job.count as usize;
+            (
+                read_buffer(render_device, render_queue, &self.keys[side], len),
+                read_buffer(render_device, render_queue, &self.vals[side], len),
+            )
+        }
+    }
+
+    /// Sorts the jobs once with [`run_batch`], once with a [`run`] per job, and checks both against the CPU.
+    fn check_batch(sizes: Vec<(u32, Range<u32>)>, shared_blocks: bool) {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 100_000.into(),
+        });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let max_wg = render_device.limits().max_compute_workgroups_per_dimension;
+                let keys: Vec<Vec<u32>> = sizes
+                    .iter()
+                    .zip(0..)
+                    .map(|((count, pass_range), seed)| job_keys(*count, pass_range, seed))
+                    .collect();
+
+                let mut results = Vec::new();
+                for batched in [true, false] {
+                    let buffers: Vec<JobBuffers> = keys
+                        .iter()
+                        .map(|keys| JobBuffers::new(&render_device, keys))
+                        .collect();
+                    let jobs: Vec<PreparedSortJob> = buffers
+                        .iter()
+                        .zip(&sizes)
+                        .map(|(buffers, (count, pass_range))| {
+                            let blocks = if shared_blocks {
+                                global_blocks_buffer(&sbufs).unwrap()
+                            } else {
+                                &buffers.blocks
+                            };
+                            buffers.job(
+                                &render_device,
+                                &radix_sort_pipeline,
+                                blocks,
+                                *count,
+                                pass_range.clone(),
+                            )
+                        })
+                        .collect();
+                    assert_eq!(batch_interleaves(&jobs), !shared_blocks);
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: batch command encoder"),
+                        });
+                    if batched {
+                        run_batch(
+                            &mut encoder,
+                            &pipeline_cache,
+                            &radix_sort_pipeline,
+                            max_wg,
+                            &jobs,
+                        );
+                    } else {
+                        for job in &jobs {
+                            run(
+                                &mut encoder,
+                                &pipeline_cache,
+                                &radix_sort_pipeline,
+                                job.bind_group(),
+                                max_wg,
+                                job.count,
+                                job.pass_range.clone(),
+                                job.init_index,
+                                job.read_from_even,
+                            );
+                        }
+                    }
+                    render_queue.submit([encoder.finish()]);
+
+                    results.push(
+                        buffers
+                            .iter()
+                            .zip(&jobs)
+                            .map(|(buffers, job)| buffers.read(&render_device, &render_queue, job))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+
+                assert_eq!(results[0], results[1]);
+                for (keys, (sorted_keys, sorted_vals)) in keys.iter().zip(&results[0]) {
+                    let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+                    expected.sort_by_key(|&(key, _)| key);
+                    let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) =
+                        expected.into_iter().unzip();
+                    assert_eq!(*sorted_keys, expected_keys);
+                    assert_eq!(*sorted_vals, expected_vals);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_batch() {
+        check_batch(
+            vec![
+                (65_536, 0..4),
+                (1_000, 0..2),
+                (30_000, 0..4),
+                (2, 0..1),
+                (50_000, 0..3),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_run_batch_shared_blocks() {
+        check_batch(vec![(20_000, 0..4), (7_000, 0..2), (40_000, 0..4)], true);
+    }
+
+    #[test]
+    fn test_run_batch_u64_keys() {
+        let count = 20_000;
+        let keys: Vec<u64> = (0..u64::from(count))
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+        let mut expected: Vec<(u64, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort_by_key(|&(key, _)| key);
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(count).with_key_type(KeyType::U64),
+        });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>| {
+                // The keys take two u32s each, the vals one
+                let buffers = JobBuffers::new(&render_device, bytemuck::cast_slice(&keys));
+                let job = PreparedSortJob::new(
+                    &render_device,
+                    &radix_sort_pipeline,
+                    &buffers.keys[0],
+                    &buffers.keys[1],
+                    Some((&buffers.vals[0], &buffers.vals[1])),
+                    &buffers.blocks,
+                    count,
+                );
+                assert_eq!(job.pass_range, 0..8);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: batch command encoder"),
+                });
+                run_batch(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    &[job],
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let sorted_keys = crate::u64_keys(&read_buffer(
+                    &render_device,
+                    &render_queue,
+                    &buffers.keys[0],
+                    2 * count as usize,
+                ));
+                let sorted_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    &buffers.vals[0],
+                    count as usize,
+                );
+                let (expected_keys, expected_vals): (Vec<u64>, Vec<u32>) =
+                    expected.iter().copied().unzip();
+                assert_eq!(sorted_keys, expected_keys);
+                assert_eq!(sorted_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "less than the 16000 bytes of 2000 keys")]
+    fn test_u64_keys_buffers_too_small() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(2_000).with_key_type(KeyType::U64),
+        });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>, radix_sort_pipeline: Res<RadixSortPipeline>| {
+                // u32 keys buffers hold half the bytes
+                let buffers = JobBuffers::new(&render_device, &[0; 2_000]);
+                buffers.job(
+                    &render_device,
+                    &radix_sort_pipeline,
+                    &buffers.blocks,
+                    2_000,
+                    0..8,
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 1_000.into(),
+        });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>, radix_sort_pipeline: Res<RadixSortPipeline>| {
+                let a = JobBuffers::new(&render_device, &[3; 1_000]);
+                let b = JobBuffers::new(&render_device, &[5; 1_000]);
+                let job = |buffers: &JobBuffers, blocks: &Buffer| {
+                    buffers.job(&render_device, &radix_sort_pipeline, blocks, 1_000, 0..4)
+                };
+
+                let (job_a, job_b) = (job(&a, &a.blocks), job(&b, &b.blocks));
+                assert!(!job_a.aliases(&job_b));
+                assert!(batch_interleaves(&[job_a.clone(), job_b.clone()]));
+                assert!(!batch_interleaves(&[job_a.clone()]));
+                assert!(job(&b, &a.blocks).aliases(&job_a));
+                assert!(!batch_interleaves(&[job_a.clone(), job_b, job_a]));
+            },
+        );
+    }
+}
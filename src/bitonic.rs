@@ -0,0 +1,317 @@
+//! The bitonic sort backend of [`Algorithm::Bitonic`], behind the same [`crate::run`] and [`crate::RadixSortBindGroup`].
+//!
+//! A sorting network of `log2(n) * (log2(n) + 1) / 2` compare-and-swap steps over the keys padded to a power of two.
+//! The steps comparing elements less than 512 apart run in shared memory, so a few thousand keys take a handful
+//! of dispatches:
+//!
+//! ```text
+//!  copy      keys_i ──► keys_o      (skipped if the sort ends on the side it reads from, then keys_i is sorted)
+//!  local     sort the blocks of 512 keys in shared memory
+//!  k = 1024  global (mirrored), local (distances 256..1)
+//!  k = 2048  global (mirrored), global (distance 512), local (distances 256..1)
+//!  ...
+//! ```
+//!
+//! The result is left on the same side of the global buffers as the radix sort over the same `pass_range`, and only
+//! the bits of the digits in `pass_range` are compared.
+//!
+//! **The sort isn't stable**: the keys equal within the compared bits come out in any order.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{
+        BindGroupLayout, CachedComputePipelineId, ComputePipelineDescriptor, PipelineCache,
+        PushConstantRange, ShaderDefVal, ShaderStages,
+    },
+};
+use wgpu::{BindGroup, ComputePipeline};
+
+use crate::{
+    Algorithm, KeyTransform, LoadState, NUMBER_OF_RADIX_BITS, NUMBER_OF_THREADS_PER_WORKGROUP,
+    PassRecorder, compute_pipelines_load_state, dispatch_workgroup_ext_with, is_output_even,
+};
+
+pub const BITONIC_SORT_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(127602732458059541214975776626105260912);
+
+/// The number of keys sorted in shared memory by a workgroup, two per thread.
+pub const BITONIC_KEYS_PER_BLOCK: u32 = 2 * NUMBER_OF_THREADS_PER_WORKGROUP;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const KEY_SHIFT_OFFSET: u32 = 8;
+const KEY_MASK_OFFSET: u32 = 12;
+const BLOCK_SIZE_OFFSET: u32 = 16;
+const DISTANCE_OFFSET: u32 = 20;
+const FLAGS_OFFSET: u32 = 24;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..28,
+};
+
+/// Copies the keys and vals from the input to the output side.
+const COPY_KEYS: u32 = 1;
+/// Writes the indices of the keys as vals.
+const INIT_INDEX: u32 = 2;
+
+/// The pipelines of [`Algorithm::Bitonic`], part of the [`crate::RadixSortPipeline`].
+///
+/// They use the bind group layout of the radix sort and ignore the global blocks buffer.
+#[derive(Debug, Clone)]
+pub struct BitonicSortPipeline {
+    /// Copies the input to the output side, and/or writes the indices as vals
+    copy_pipeline: CachedComputePipelineId,
+    /// A compare-and-swap step between blocks of shared memory
+    global_pipeline: CachedComputePipelineId,
+    /// The compare-and-swap steps within blocks of shared memory
+    local_pipeline: CachedComputePipelineId,
+}
+
+impl BitonicSortPipeline {
+    pub(crate) fn new(
+        pipeline_cache: &PipelineCache,
+        bind_group_layout: &BindGroupLayout,
+        allocate_values: bool,
+        key_transform: &KeyTransform,
+    ) -> Self {
+        let mut cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+        cdefs.extend(key_transform.shader_defs());
+        if !allocate_values {
+            cdefs.push("KEYS_ONLY".into());
+        }
+
+        let queue = |label: &'static str, def: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: BITONIC_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &[def.into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        Self {
+            copy_pipeline: queue("radix_sort: bitonic copy pipeline", "COPY_PIPELINE"),
+            global_pipeline: queue("radix_sort: bitonic global pipeline", "GLOBAL_PIPELINE"),
+            local_pipeline: queue("radix_sort: bitonic local pipeline", "LOCAL_PIPELINE"),
+        }
+    }
+
+    /// The pipelines by name: `copy_pipeline` moves the input to the output side if needed, then the
+    /// `local_pipeline` and `global_pipeline` steps alternate as the sorted runs outgrow the shared memory.
+    pub fn pipeline_ids(&self) -> [(&'static str, CachedComputePipelineId); 3] {
+        [
+            ("bitonic copy_pipeline", self.copy_pipeline),
+            ("bitonic global_pipeline", self.global_pipeline),
+            ("bitonic local_pipeline", self.local_pipeline),
+        ]
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &self.pipeline_ids())
+    }
+
+    /// The pipelines of a loaded [`BitonicSortPipeline`].
+    pub(crate) fn pipelines<'a>(&self, pipeline_cache: &'a PipelineCache) -> BitonicPipelines<'a> {
+        let get = move |id| &**pipeline_cache.get_compute_pipeline(id).unwrap();
+
+        BitonicPipelines {
+            copy_pipeline: get(self.copy_pipeline),
+            global_pipeline: get(self.global_pipeline),
+            local_pipeline: get(self.local_pipeline),
+        }
+    }
+}
+
+/// The compute pipelines a bitonic sort dispatches.
+pub(crate) struct BitonicPipelines<'a> {
+    pub copy_pipeline: &'a ComputePipeline,
+    pub global_pipeline: &'a ComputePipeline,
+    pub local_pipeline: &'a ComputePipeline,
+}
+
+/// The bits of the keys the radix sort over `pass_range` compares, as `(shift, mask)`.
+pub(crate) fn compared_bits(pass_range: &Range<u32>) -> (u32, u32) {
+    let key_bits = u32::BITS;
+    let lowest = (pass_range.start * NUMBER_OF_RADIX_BITS).min(key_bits);
+    let highest = (pass_range.end * NUMBER_OF_RADIX_BITS).min(key_bits);
+
+    let mask = match highest.saturating_sub(lowest) {
+        0 => 0,
+        bits if bits >= key_bits => u32::MAX,
+        bits => (1 << bits) - 1,
+    };
+
+    (lowest % key_bits, mask)
+}
+
+/// Records the bitonic sort replacing the passes of [`crate::run`], `number_of_keys` must be at least 2.
+///
+/// The bind groups are the ones of the radix sort, the result is left on the side [`is_output_even`] tells.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_bitonic_sort<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &BitonicPipelines,
+    eve_bind_group: &BindGroup,
+    odd_bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    if pass_range.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::bitonic", number_of_keys).entered();
+
+    // The keys are sorted in place in the output slot of the bind group. If the sort ends on the side it reads
+    // from, the bind group of the other side has the input in its output slot, nothing to copy.
+    let in_place = is_output_even(&pass_range, read_from_even) == read_from_even;
+    let (bind_group, flags) = match (in_place, read_from_even) {
+        (true, true) => (odd_bind_group, 0),
+        (true, false) => (eve_bind_group, 0),
+        (false, true) => (eve_bind_group, COPY_KEYS),
+        (false, false) => (odd_bind_group, COPY_KEYS),
+    };
+    let flags = flags | if init_index { INIT_INDEX } else { 0 };
+
+    let (key_shift, key_mask) = compared_bits(&pass_range);
+    let set_push_constants = |pass: &mut R, block_size: u32, distance: u32| {
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(KEY_SHIFT_OFFSET, bytemuck::bytes_of(&key_shift));
+        pass.set_push_constants(KEY_MASK_OFFSET, bytemuck::bytes_of(&key_mask));
+        pass.set_push_constants(BLOCK_SIZE_OFFSET, bytemuck::bytes_of(&block_size));
+        pass.set_push_constants(DISTANCE_OFFSET, bytemuck::bytes_of(&distance));
+        pass.set_push_constants(FLAGS_OFFSET, bytemuck::bytes_of(&flags));
+    };
+
+    if flags != 0 {
+        pass.set_pipeline(pipelines.copy_pipeline);
+        pass.set_bind_group(bind_group);
+        set_push_constants(pass, 0, 0);
+        dispatch_workgroup_ext_with(
+            pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    let padded_number_of_keys = number_of_keys.next_power_of_two();
+    let number_of_blocks = number_of_keys.div_ceil(BITONIC_KEYS_PER_BLOCK);
+    let number_of_pairs_workgroups =
+        (padded_number_of_keys / 2).div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+    let record_local = |pass: &mut R, block_size: u32| {
+        pass.set_pipeline(pipelines.local_pipeline);
+        pass.set_bind_group(bind_group);
+        set_push_constants(pass, block_size, 0);
+        dispatch_workgroup_ext_with(
+            pass,
+            number_of_blocks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    };
+
+    // The blocks merged in shared memory first
+    record_local(pass, padded_number_of_keys.min(BITONIC_KEYS_PER_BLOCK));
+
+    let mut block_size = 2 * BITONIC_KEYS_PER_BLOCK;
+    while block_size <= padded_number_of_keys {
+        // The mirrored step, then the distances no block of shared memory covers
+        let mut distance = 0;
+        while distance == 0 || distance >= BITONIC_KEYS_PER_BLOCK {
+            pass.set_pipeline(pipelines.global_pipeline);
+            pass.set_bind_group(bind_group);
+            set_push_constants(pass, block_size, distance);
+            dispatch_workgroup_ext_with(
+                pass,
+                number_of_pairs_workgroups,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+
+            distance = if distance == 0 {
+                block_size / 4
+            } else {
+                distance / 2
+            };
+        }
+
+        record_local(pass, block_size);
+        block_size *= 2;
+    }
+}
+
+/// The backend [`crate::run`] records with the settings `algorithm` and `allow_fallback`, once the radix
+/// pipelines are in `radix_load_state`.
+pub(crate) fn select_algorithm(
+    algorithm: Algorithm,
+    allow_fallback: bool,
+    radix_load_state: &LoadState,
+) -> Algorithm {
+    match (algorithm, radix_load_state) {
+        (Algorithm::Radix, LoadState::Failed(_)) if allow_fallback => Algorithm::Bitonic,
+        (algorithm, _) => algorithm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compared_bits() {
+        assert_eq!(compared_bits(&(0..4)), (0, u32::MAX));
+        assert_eq!(compared_bits(&(0..1)), (0, 0xFF));
+        assert_eq!(compared_bits(&(1..3)), (8, 0xFFFF));
+        assert_eq!(compared_bits(&(3..4)), (24, 0xFF));
+        // Past the key, as for the radix sort the passes beyond the 4th compare nothing
+        assert_eq!(compared_bits(&(2..6)), (16, 0xFFFF));
+        assert_eq!(compared_bits(&(4..5)), (0, 0));
+    }
+
+    #[test]
+    fn test_select_algorithm() {
+        let failed = LoadState::Failed("no subgroups".into());
+        for load_state in [
+            LoadState::OnLoad(default()),
+            LoadState::Loaded,
+            failed.clone(),
+        ] {
+            assert_eq!(
+                select_algorithm(Algorithm::Bitonic, false, &load_state),
+                Algorithm::Bitonic
+            );
+        }
+
+        assert_eq!(
+            select_algorithm(Algorithm::Radix, true, &LoadState::OnLoad(default())),
+            Algorithm::Radix
+        );
+        assert_eq!(
+            select_algorithm(Algorithm::Radix, true, &LoadState::Loaded),
+            Algorithm::Radix
+        );
+        assert_eq!(
+            select_algorithm(Algorithm::Radix, true, &failed),
+            Algorithm::Bitonic
+        );
+        assert_eq!(
+            select_algorithm(Algorithm::Radix, false, &failed),
+            Algorithm::Radix
+        );
+    }
+}
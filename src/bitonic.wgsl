@@ -0,0 +1,213 @@
+// The bitonic sort of `Algorithm::Bitonic`, on the same bindings as `radix_sort.wgsl`.
+//
+// The network only has ascending compare-and-swaps: the first step of the merge of a block compares the mirrored
+// positions of the block, the next steps the elements `distance` apart. The elements past `number_of_keys` act as
+// `+inf` and always sit at the higher position of a pair, so the pairs touching them are skipped.
+
+#import bevy_radix_sort::key_transform::transform_key
+
+/// Read the unsorted keys from this buffer, only copied
+@group(0) @binding(0) var<storage, read      > global_keys_i: array<u32>;
+/// Read the unsorted vals from this buffer, only copied
+@group(0) @binding(1) var<storage, read      > global_vals_i: array<u32>;
+/// The keys sorted in place
+@group(0) @binding(3) var<storage, read_write> global_keys_o: array<u32>;
+/// The vals sorted in place
+@group(0) @binding(4) var<storage, read_write> global_vals_o: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys to be sorted.
+    number_of_keys: u32,
+    /// The lowest bit of the keys compared, `8 * pass_range.start`.
+    key_shift: u32,
+    /// The bits of the shifted keys compared.
+    key_mask: u32,
+    /// The size of the blocks being merged, a power of two.
+    block_size: u32,
+    /// The distance between the compared elements, 0 for the mirrored first step of a merge.
+    distance: u32,
+    /// COPY_PIPELINE only, a combination of `COPY_KEYS` and `INIT_INDEX`.
+    flags: u32,
+}
+var<push_constant> pc: PushConstants;
+
+const COPY_KEYS: u32 = 1u;
+const INIT_INDEX: u32 = 2u;
+
+/// The elements sorted in shared memory by a workgroup, two per thread.
+const NUMBER_OF_KEYS_PER_BLOCK: u32 = 2u * #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+
+fn get_workgroup_index(workgroup_id: vec3u, num_workgroups: vec3u) -> u32 {
+    return workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+}
+
+fn sort_key(key: u32) -> u32 {
+    return (transform_key(key) >> pc.key_shift) & pc.key_mask;
+}
+
+/// The lower position of the `pair`-th pair of a step, its partner is at the higher one.
+fn pair_lower(pair: u32, block_size: u32, distance: u32) -> u32 {
+    if distance == 0u {
+        let half = block_size / 2u;
+        return (pair / half) * block_size + pair % half;
+    }
+
+    return (pair / distance) * 2u * distance + pair % distance;
+}
+
+fn pair_upper(lower: u32, block_size: u32, distance: u32) -> u32 {
+    if distance == 0u {
+        return lower ^ (block_size - 1u);
+    }
+
+    return lower + distance;
+}
+
+#ifdef COPY_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let index = get_workgroup_index(workgroup_id, num_workgroups) * #{NUMBER_OF_THREADS_PER_WORKGROUP}u
+        + local_invocation_index;
+    if index >= pc.number_of_keys {
+        return;
+    }
+
+    if (pc.flags & COPY_KEYS) != 0u {
+        global_keys_o[index] = global_keys_i[index];
+#ifndef KEYS_ONLY
+        if index < arrayLength(&global_vals_o) {
+            global_vals_o[index] = global_vals_i[index];
+        }
+#endif // KEYS_ONLY
+    }
+
+#ifndef KEYS_ONLY
+    if (pc.flags & INIT_INDEX) != 0u && index < arrayLength(&global_vals_o) {
+        global_vals_o[index] = index;
+    }
+#endif // KEYS_ONLY
+}
+#endif // COPY_PIPELINE
+
+#ifdef GLOBAL_PIPELINE
+/// One compare-and-swap per thread, for the steps comparing elements of different blocks of shared memory.
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let pair = get_workgroup_index(workgroup_id, num_workgroups) * #{NUMBER_OF_THREADS_PER_WORKGROUP}u
+        + local_invocation_index;
+    let lower = pair_lower(pair, pc.block_size, pc.distance);
+    let upper = pair_upper(lower, pc.block_size, pc.distance);
+    if upper >= pc.number_of_keys {
+        return;
+    }
+
+    let lower_key = global_keys_o[lower];
+    let upper_key = global_keys_o[upper];
+    if sort_key(lower_key) > sort_key(upper_key) {
+        global_keys_o[lower] = upper_key;
+        global_keys_o[upper] = lower_key;
+#ifndef KEYS_ONLY
+        // The vals buffers may hold fewer vals than the keys, see `RadixSortSettings::with_max_number_of_values`
+        if upper < arrayLength(&global_vals_o) {
+            let lower_val = global_vals_o[lower];
+            global_vals_o[lower] = global_vals_o[upper];
+            global_vals_o[upper] = lower_val;
+        }
+#endif // KEYS_ONLY
+    }
+}
+#endif // GLOBAL_PIPELINE
+
+#ifdef LOCAL_PIPELINE
+var<workgroup> local_keys: array<u32, NUMBER_OF_KEYS_PER_BLOCK>;
+#ifndef KEYS_ONLY
+var<workgroup> local_vals: array<u32, NUMBER_OF_KEYS_PER_BLOCK>;
+#endif // KEYS_ONLY
+
+fn local_compare_and_swap(block_offset: u32, pair: u32, block_size: u32, distance: u32) {
+    let lower = pair_lower(pair, block_size, distance);
+    let upper = pair_upper(lower, block_size, distance);
+    if block_offset + upper >= pc.number_of_keys {
+        return;
+    }
+
+    let lower_key = local_keys[lower];
+    let upper_key = local_keys[upper];
+    if sort_key(lower_key) > sort_key(upper_key) {
+        local_keys[lower] = upper_key;
+        local_keys[upper] = lower_key;
+#ifndef KEYS_ONLY
+        let lower_val = local_vals[lower];
+        local_vals[lower] = local_vals[upper];
+        local_vals[upper] = lower_val;
+#endif // KEYS_ONLY
+    }
+}
+
+/// The steps within a block of [`NUMBER_OF_KEYS_PER_BLOCK`] elements in shared memory:
+/// - `block_size` <= [`NUMBER_OF_KEYS_PER_BLOCK`]: the whole sort of each block, up to merges of `block_size`,
+/// - `block_size` > [`NUMBER_OF_KEYS_PER_BLOCK`]: the last steps of the merge of `block_size`, the ones comparing
+///   elements less than [`NUMBER_OF_KEYS_PER_BLOCK`] apart.
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let block_offset = get_workgroup_index(workgroup_id, num_workgroups) * NUMBER_OF_KEYS_PER_BLOCK;
+
+    for (var i = 0u; i < 2u; i++) {
+        let local_index = local_invocation_index + i * #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+        let index = block_offset + local_index;
+        if index < pc.number_of_keys {
+            local_keys[local_index] = global_keys_o[index];
+#ifndef KEYS_ONLY
+            if index < arrayLength(&global_vals_o) {
+                local_vals[local_index] = global_vals_o[index];
+            }
+#endif // KEYS_ONLY
+        }
+    }
+    workgroupBarrier();
+
+    if pc.block_size <= NUMBER_OF_KEYS_PER_BLOCK {
+        for (var block_size = 2u; block_size <= pc.block_size; block_size <<= 1u) {
+            local_compare_and_swap(block_offset, local_invocation_index, block_size, 0u);
+            workgroupBarrier();
+
+            for (var distance = block_size / 4u; distance > 0u; distance >>= 1u) {
+                local_compare_and_swap(block_offset, local_invocation_index, block_size, distance);
+                workgroupBarrier();
+            }
+        }
+    } else {
+        for (var distance = NUMBER_OF_KEYS_PER_BLOCK / 2u; distance > 0u; distance >>= 1u) {
+            local_compare_and_swap(block_offset, local_invocation_index, pc.block_size, distance);
+            workgroupBarrier();
+        }
+    }
+
+    for (var i = 0u; i < 2u; i++) {
+        let local_index = local_invocation_index + i * #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+        let index = block_offset + local_index;
+        if index < pc.number_of_keys {
+            global_keys_o[index] = local_keys[local_index];
+#ifndef KEYS_ONLY
+            if index < arrayLength(&global_vals_o) {
+                global_vals_o[index] = local_vals[local_index];
+            }
+#endif // KEYS_ONLY
+        }
+    }
+}
+#endif // LOCAL_PIPELINE
@@ -0,0 +1,310 @@
+//! Cell-range building pass for spatial hashing.
+//!
+//! The typical use of a GPU radix sort in games is spatial hashing: sort particle indices by cell id,
+//! then find where the run of each cell starts and ends in the sorted keys.
+//!
+//! ```text
+//!  sorted_keys  [ 0, 0, 2, 2, 2, 5 ]
+//!  cell_ranges  [ (0, 2), EMPTY, (2, 5), EMPTY, EMPTY, (5, 6) ]
+//! ```
+//!
+//! The pass is meant to be recorded in the same encoder right after [`crate::run`].
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, compute_pipelines_load_state,
+    dispatch_workgroup_ext,
+};
+
+pub const CELL_RANGES_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(144149834800300488104892352488691032453);
+
+/// Both `start` and `end` of an empty cell are set to this value.
+pub const EMPTY_CELL: u32 = u32::MAX;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const NUMBER_OF_CELLS_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+/// The size in bytes of a `cell_ranges` buffer holding `number_of_cells` `(start, end)` pairs.
+pub const fn cell_ranges_buffer_size(number_of_cells: u32) -> BufferAddress {
+    number_of_cells as BufferAddress * 2 * std::mem::size_of::<u32>() as BufferAddress
+}
+
+pub struct CellRangesPlugin;
+
+impl Plugin for CellRangesPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            CELL_RANGES_SHADER_HANDLE,
+            "cell_ranges.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<CellRangesPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct CellRangesPipeline {
+    /// Reset every cell to [`EMPTY_CELL`]
+    clear_pipeline: CachedComputePipelineId,
+    /// Write `start`/`end` of each run of the sorted keys
+    build_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > sorted_keys: array<u32>;
+    /// @binding(1) var<storage, read_write> cell_ranges: array<vec2<u32>>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for CellRangesPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "cell_ranges bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<[u32; 2]>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let clear_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("cell_ranges: clear pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: CELL_RANGES_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["CLEAR_CELL_RANGES_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let build_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("cell_ranges: build pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: CELL_RANGES_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["BUILD_CELL_RANGES_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            clear_pipeline,
+            build_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl CellRangesPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("cell_ranges clear_pipeline", self.clear_pipeline),
+                ("cell_ranges build_pipeline", self.build_pipeline),
+            ],
+        )
+    }
+
+    /// `cell_ranges` must hold at least [`cell_ranges_buffer_size`] bytes for the number of cells passed to
+    /// [`CellRangesPipeline::record_build_cell_ranges`].
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        cell_ranges: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "cell_ranges: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                cell_ranges.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Writes `(start, end)` of every cell into `cell_ranges`, or `(EMPTY_CELL, EMPTY_CELL)` if no key falls into the cell.
+    ///
+    /// Keys greater than or equal to `number_of_cells` are ignored.
+    pub fn record_build_cell_ranges(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        number_of_cells: u32,
+    ) {
+        let clear_pipeline = pipeline_cache
+            .get_compute_pipeline(self.clear_pipeline)
+            .unwrap();
+        let build_pipeline = pipeline_cache
+            .get_compute_pipeline(self.build_pipeline)
+            .unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("cell_ranges compute pass"),
+            ..default()
+        });
+
+        // Push constants can only be set once a pipeline is bound,
+        // both pipelines share the same layout so they are kept across `set_pipeline`.
+        pass.set_pipeline(clear_pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(NUMBER_OF_CELLS_OFFSET, bytemuck::bytes_of(&number_of_cells));
+
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_cells.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+
+        if number_of_keys == 0 {
+            return;
+        }
+
+        pass.set_pipeline(build_pipeline);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    fn cpu_cell_ranges(sorted_keys: &[u32], number_of_cells: u32) -> Vec<u32> {
+        let mut ranges = vec![EMPTY_CELL; number_of_cells as usize * 2];
+
+        for (i, &cell) in sorted_keys.iter().enumerate() {
+            if cell >= number_of_cells {
+                continue;
+            }
+
+            let cell = cell as usize;
+            if ranges[2 * cell] == EMPTY_CELL {
+                ranges[2 * cell] = i as u32;
+            }
+            ranges[2 * cell + 1] = i as u32 + 1;
+        }
+
+        ranges
+    }
+
+    fn run_cell_ranges_test(sorted_keys: Vec<u32>, number_of_cells: u32) {
+        let mut app = create_render_test_app();
+        app.add_plugins(CellRangesPlugin);
+
+        let expected = cpu_cell_ranges(&sorted_keys, number_of_cells);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  cell_ranges_pipeline: Res<CellRangesPipeline>| {
+                let keys_buf = create_storage_buffer(&render_device, &sorted_keys);
+                let ranges_buf = create_storage_buffer(
+                    &render_device,
+                    &vec![0u32; number_of_cells as usize * 2],
+                );
+                let bind_group =
+                    cell_ranges_pipeline.create_bind_group(&render_device, &keys_buf, &ranges_buf);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: cell_ranges command encoder"),
+                });
+                cell_ranges_pipeline.record_build_cell_ranges(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    sorted_keys.len() as u32,
+                    number_of_cells,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let ranges = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    &ranges_buf,
+                    number_of_cells as usize * 2,
+                );
+                assert_eq!(ranges, expected);
+            },
+        );
+    }
+
+    #[test]
+    fn test_cell_ranges_known_layout() {
+        // 8 particles in a 4x2 grid: cells 1, 3, 4 and 6 are empty, the last particle is alone in cell 7
+        run_cell_ranges_test(vec![0, 0, 2, 2, 2, 5, 5, 7], 8);
+    }
+
+    #[test]
+    fn test_cell_ranges_out_of_range_keys() {
+        // Keys past `number_of_cells` (e.g. culled particles) are ignored
+        run_cell_ranges_test(vec![1, 1, 3, 9, 9, u32::MAX], 4);
+    }
+
+    #[test]
+    fn test_cell_ranges_large() {
+        let sorted_keys: Vec<u32> = (0..100_000u32).map(|i| i / 7).collect();
+        run_cell_ranges_test(sorted_keys, 20_000);
+    }
+}
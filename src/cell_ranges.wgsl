@@ -0,0 +1,65 @@
+/// Sorted keys, each key is the id of the cell an element belongs to
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// `cell_ranges[cell]` = (start, end) of the run of `cell` in `sorted_keys`, `end` is exclusive
+@group(0) @binding(1) var<storage, read_write> cell_ranges: array<vec2<u32>>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of sorted keys.
+    number_of_keys: u32,
+    /// The number of cells, keys greater than or equal to it are ignored.
+    number_of_cells: u32,
+}
+var<push_constant> pc: PushConstants;
+
+/// Both `start` and `end` of an empty cell are set to this value.
+const EMPTY_CELL: u32 = 0xFFFFFFFFu;
+
+fn get_global_index(workgroup_id: vec3u, num_workgroups: vec3u, local_invocation_id_x: u32) -> u32 {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    return workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id_x;
+}
+
+#ifdef CLEAR_CELL_RANGES_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let cell = get_global_index(workgroup_id, num_workgroups, local_invocation_id.x);
+
+    if cell < pc.number_of_cells {
+        cell_ranges[cell] = vec2u(EMPTY_CELL);
+    }
+}
+#endif // CLEAR_CELL_RANGES_PIPELINE
+
+#ifdef BUILD_CELL_RANGES_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let key_index = get_global_index(workgroup_id, num_workgroups, local_invocation_id.x);
+    if key_index >= pc.number_of_keys {
+        return;
+    }
+
+    let cell = sorted_keys[key_index];
+    if cell >= pc.number_of_cells {
+        return;
+    }
+
+    // The head of a run writes `start`, the tail of a run writes `end`
+    if key_index == 0u || sorted_keys[key_index - 1u] != cell {
+        cell_ranges[cell].x = key_index;
+    }
+
+    if key_index == pc.number_of_keys - 1u || sorted_keys[key_index + 1u] != cell {
+        cell_ranges[cell].y = key_index + 1u;
+    }
+}
+#endif // BUILD_CELL_RANGES_PIPELINE
@@ -0,0 +1,175 @@
+//! Detects several users of the global buffers within one frame, which would overwrite each other's keys.
+//!
+//! [`crate::run`] and [`crate::RadixSortPipeline::acquire`] claim the global buffers for the frame on behalf of the
+//! source file calling them, the claims are reset when the render world starts a frame:
+//!
+//! ```text
+//!  frame N:  particles.rs: run ─▶ claimed by particles.rs
+//!            particles.rs: run ─▶ ok, same owner
+//!            my_plugin.rs: run ─▶ conflict, logged once with both call sites
+//!  frame N+1: reset
+//! ```
+//!
+//! The claims only detect the conflicts, the sorts are still recorded.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    panic::Location,
+    sync::Mutex,
+};
+
+use bevy::prelude::*;
+
+use crate::RadixSortPipeline;
+
+/// A claim of the global buffers, by the source file of `location`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalBuffersClaim {
+    pub location: &'static Location<'static>,
+}
+
+impl GlobalBuffersClaim {
+    pub fn owner(&self) -> &'static str {
+        self.location.file()
+    }
+}
+
+/// The global buffers were claimed by two owners within one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalBuffersConflict {
+    /// The first claim of the frame.
+    pub first: GlobalBuffersClaim,
+    pub second: GlobalBuffersClaim,
+}
+
+impl Display for GlobalBuffersConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the global buffers are used by {} and {} in the same frame, the second sort overwrites the keys of the first",
+            self.first.location, self.second.location
+        )
+    }
+}
+
+impl std::error::Error for GlobalBuffersConflict {}
+
+/// The claims of the current frame, part of the [`RadixSortPipeline`].
+#[derive(Debug, Default)]
+pub struct GlobalBuffersClaims {
+    state: Mutex<ClaimsState>,
+}
+
+#[derive(Debug, Default)]
+struct ClaimsState {
+    first: Option<GlobalBuffersClaim>,
+    /// The owner pairs already logged, a conflict repeats every frame
+    reported: HashSet<(&'static str, &'static str)>,
+}
+
+impl GlobalBuffersClaims {
+    /// Claims the global buffers for the frame on behalf of the file of `location`.
+    pub fn claim(&self, location: &'static Location<'static>) -> Result<(), GlobalBuffersConflict> {
+        let second = GlobalBuffersClaim { location };
+        let mut state = self.state.lock().unwrap();
+
+        match state.first {
+            None => {
+                state.first = Some(second);
+                Ok(())
+            }
+            Some(first) if first.owner() == second.owner() => Ok(()),
+            Some(first) => Err(GlobalBuffersConflict { first, second }),
+        }
+    }
+
+    /// [`Self::claim`], logging a conflict once per pair of owners: as an error in debug builds, a warning otherwise.
+    pub fn claim_or_report(
+        &self,
+        location: &'static Location<'static>,
+    ) -> Result<(), GlobalBuffersConflict> {
+        let Err(conflict) = self.claim(location) else {
+            return Ok(());
+        };
+
+        let pair = (conflict.first.owner(), conflict.second.owner());
+        if self.state.lock().unwrap().reported.insert(pair) {
+            if cfg!(debug_assertions) {
+                error!("radix_sort: {}", conflict);
+            } else {
+                warn!("radix_sort: {}", conflict);
+            }
+        }
+
+        Err(conflict)
+    }
+
+    /// The first claim of the current frame.
+    pub fn current(&self) -> Option<GlobalBuffersClaim> {
+        self.state.lock().unwrap().first
+    }
+
+    /// Starts a new frame.
+    pub fn reset(&self) {
+        self.state.lock().unwrap().first = None;
+    }
+}
+
+/// Resets the [`GlobalBuffersClaims`] of the [`RadixSortPipeline`] at the start of the render world frame.
+pub fn reset_global_buffers_claims(radix_sort_pipeline: Res<RadixSortPipeline>) {
+    radix_sort_pipeline.claims().reset();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn here() -> &'static Location<'static> {
+        Location::caller()
+    }
+
+    /// A location in another file, as another plugin would claim from.
+    fn elsewhere() -> &'static Location<'static> {
+        crate::test_utils::test_utils_location()
+    }
+
+    #[test]
+    fn test_single_claim() {
+        let claims = GlobalBuffersClaims::default();
+
+        let location = here();
+        assert_eq!(claims.claim(location), Ok(()));
+        // Several sorts of the same owner
+        assert_eq!(claims.claim(here()), Ok(()));
+        assert!(claims.claim_or_report(here()).is_ok());
+        assert_eq!(claims.current(), Some(GlobalBuffersClaim { location }));
+
+        claims.reset();
+        assert_eq!(claims.current(), None);
+        assert_eq!(claims.claim(elsewhere()), Ok(()));
+    }
+
+    #[test]
+    fn test_double_claim() {
+        let claims = GlobalBuffersClaims::default();
+
+        let first = here();
+        let second = elsewhere();
+        assert_eq!(claims.claim(first), Ok(()));
+
+        let conflict = claims.claim(second).unwrap_err();
+        assert_eq!(conflict.first.location, first);
+        assert_eq!(conflict.second.location, second);
+        let message = conflict.to_string();
+        assert!(message.contains(&first.to_string()), "{message}");
+        assert!(message.contains(&second.to_string()), "{message}");
+
+        assert!(claims.claim_or_report(second).is_err());
+        // The first claim stays until the next frame
+        assert_eq!(claims.current().unwrap().location, first);
+        claims.reset();
+        assert!(claims.claim_or_report(second).is_ok());
+    }
+}
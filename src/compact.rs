@@ -0,0 +1,586 @@
+//! Argsorts in [`MemoryMode::Compact`], with a single global vals buffer instead of two.
+//!
+//! The pack pass stores the index of each key below its bits, the keys-only kernels sort the packed keys, and the
+//! unpack pass splits them back into the keys and their indices in the vals buffer:
+//!
+//! ```text
+//!  keys      [ 7, 2, 7, 1 ]
+//!  packed    [ 7|0, 2|1, 7|2, 1|3 ]   the index in the low bits
+//!  sorted    [ 1|3, 2|1, 7|0, 7|2 ]
+//!  unpacked  [ 1, 2, 7, 7 ]           vals [ 3, 1, 0, 2 ]
+//! ```
+//!
+//! The sort is stable since the indices break the ties. It is limited to
+//! `key_bits + compact_index_bits(number_of_keys) <= 32`, and pays for the passes of the index bits: the passes cover
+//! both, e.g. 3 passes for 8-bit keys of 2^16 keys.
+//!
+//! The global buffers take 3 times the capacity in u32s instead of 4, see [`RadixSortSettings::allocated_size`]. The
+//! vals are always in the even vals buffer, [`compact_vals_buffer`], whatever side the keys are sorted to.
+
+use std::{fmt, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages, binding_types::storage_buffer,
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    KeyTransform, KeyType, LoadState, MemoryMode, NUMBER_OF_BYTES_PER_KEY,
+    NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup, RadixSortPipeline, RadixSortSettings,
+    compact_index_bits, compute_pipelines_load_state, dispatch_workgroup_ext, global_keys_buffer,
+    global_vals_buffer, passes_needed, run, sorted_keys_buffer,
+};
+
+pub const COMPACT_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(38451892053566805108287816504722375004);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const INDEX_BITS_OFFSET: u32 = 8;
+const INDEX_MASK_OFFSET: u32 = 12;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..16,
+};
+
+/// The single global vals buffer of [`MemoryMode::Compact`], holding the indices unpacked by [`run_compact`].
+pub fn compact_vals_buffer(sbufs: &RenderAssets<GpuShaderStorageBuffer>) -> Option<&Buffer> {
+    global_vals_buffer(sbufs, true)
+}
+
+/// The reasons [`run_compact`] rejects the sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactSortError {
+    /// The [`RadixSortSettings`] aren't in [`MemoryMode::Compact`], or sort keys only.
+    NotCompact,
+    /// The keys have a [`KeyTransform`] or aren't [`KeyType::U32`], the packed keys would be out of order.
+    UnsupportedKeys,
+    /// The keys and their indices don't fit in 32 bits.
+    TooManyBits { key_bits: u32, index_bits: u32 },
+    /// More keys than the vals buffer holds.
+    TooManyKeys { number_of_keys: u32, capacity: u32 },
+    /// The global buffers haven't been prepared yet.
+    NotPrepared,
+}
+
+impl fmt::Display for CompactSortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotCompact => write!(
+                f,
+                "the settings of the sort aren't in the compact memory mode"
+            ),
+            Self::UnsupportedKeys => write!(
+                f,
+                "the compact memory mode packs the indices into untransformed u32 keys"
+            ),
+            Self::TooManyBits {
+                key_bits,
+                index_bits,
+            } => write!(
+                f,
+                "{key_bits} key bits and {index_bits} index bits exceed the 32 bits of a key"
+            ),
+            Self::TooManyKeys {
+                number_of_keys,
+                capacity,
+            } => write!(
+                f,
+                "the sort holds {number_of_keys} keys, more than the capacity of {capacity} vals"
+            ),
+            Self::NotPrepared => write!(f, "the global buffers aren't prepared yet"),
+        }
+    }
+}
+
+impl std::error::Error for CompactSortError {}
+
+pub struct CompactSortPlugin;
+
+impl Plugin for CompactSortPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            COMPACT_SHADER_HANDLE,
+            "compact.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<CompactSortPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct CompactSortPipeline {
+    /// Store the index of each key below its bits
+    pack_pipeline: CachedComputePipelineId,
+    /// Split the packed keys into the keys and their indices
+    unpack_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read_write> keys: array<u32>;
+    /// @binding(1) var<storage, read_write> vals: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for CompactSortPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "compact bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (storage_buffer::<u32>(false), storage_buffer::<u32>(false)),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let pack_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("compact: pack pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: COMPACT_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["PACK_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let unpack_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("compact: unpack pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: COMPACT_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["UNPACK_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pack_pipeline,
+            unpack_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl CompactSortPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("compact pack_pipeline", self.pack_pipeline),
+                ("compact unpack_pipeline", self.unpack_pipeline),
+            ],
+        )
+    }
+
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        keys: &Buffer,
+        vals: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "compact: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((keys.as_entire_binding(), vals.as_entire_binding())),
+        )
+    }
+
+    /// Replaces each of the first `number_of_keys` keys by `(key << index_bits) | index`, the bits of the keys
+    /// above `32 - index_bits` are lost. The vals aren't touched.
+    ///
+    /// # Panics
+    ///
+    /// If `index_bits` isn't below 32, no bit of the keys would be left.
+    #[track_caller]
+    pub fn record_pack(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        index_bits: u32,
+    ) {
+        debug_assert!(compact_index_bits(number_of_keys) <= index_bits);
+
+        self.record(
+            encoder,
+            pipeline_cache,
+            self.pack_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            index_bits,
+        );
+    }
+
+    /// Splits the first `number_of_keys` keys packed by [`Self::record_pack`] into the keys and the vals.
+    ///
+    /// # Panics
+    ///
+    /// If `index_bits` isn't below 32, as [`Self::record_pack`].
+    #[track_caller]
+    pub fn record_unpack(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        index_bits: u32,
+    ) {
+        self.record(
+            encoder,
+            pipeline_cache,
+            self.unpack_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            index_bits,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[track_caller]
+    fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        pipeline: CachedComputePipelineId,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        index_bits: u32,
+    ) {
+        // The shifts of WGSL take their amount modulo 32
+        assert!(
+            index_bits < u32::BITS,
+            "compact: {index_bits} index bits leave no bit of the keys"
+        );
+        if number_of_keys == 0 {
+            return;
+        }
+
+        let pipeline = pipeline_cache.get_compute_pipeline(pipeline).unwrap();
+        let index_mask = 1u32.checked_shl(index_bits).map_or(u32::MAX, |bit| bit - 1);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("compact compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(INDEX_BITS_OFFSET, bytemuck::bytes_of(&index_bits));
+        pass.set_push_constants(INDEX_MASK_OFFSET, bytemuck::bytes_of(&index_mask));
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+/// Argsorts the first `number_of_keys` global keys in [`MemoryMode::Compact`], see the module docs.
+///
+/// The keys must fit in the [`RadixSortPipeline::key_bits`]. Returns the pass range of the sort: the sorted keys
+/// are in [`crate::sorted_keys_buffer`] of it, their indices in [`compact_vals_buffer`]. The passes only cover the
+/// index bits of `number_of_keys`, smaller sorts need fewer passes than the capacity.
+#[allow(clippy::too_many_arguments)]
+#[track_caller]
+pub fn run_compact(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    compact_pipeline: &CompactSortPipeline,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    read_from_even: bool,
+) -> Result<Range<u32>, CompactSortError> {
+    if radix_sort_pipeline.memory_mode() != MemoryMode::Compact {
+        return Err(CompactSortError::NotCompact);
+    }
+    if radix_sort_pipeline.key_type() != KeyType::U32
+        || *radix_sort_pipeline.key_transform() != KeyTransform::None
+    {
+        return Err(CompactSortError::UnsupportedKeys);
+    }
+
+    let key_bits = radix_sort_pipeline.key_bits();
+    let index_bits = compact_index_bits(number_of_keys);
+    if key_bits + index_bits > u32::BITS {
+        return Err(CompactSortError::TooManyBits {
+            key_bits,
+            index_bits,
+        });
+    }
+
+    let pass_range = 0..passes_needed(key_bits + index_bits);
+    let (Some(input_keys), Some(output_keys), Some(vals)) = (
+        global_keys_buffer(sbufs, read_from_even),
+        sorted_keys_buffer(sbufs, &pass_range, read_from_even),
+        compact_vals_buffer(sbufs),
+    ) else {
+        return Err(CompactSortError::NotPrepared);
+    };
+
+    let capacity = (vals.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+    if number_of_keys > capacity {
+        return Err(CompactSortError::TooManyKeys {
+            number_of_keys,
+            capacity,
+        });
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_compact", number_of_keys).entered();
+
+    let pack_bind_group = compact_pipeline.create_bind_group(render_device, input_keys, vals);
+    compact_pipeline.record_pack(
+        encoder,
+        pipeline_cache,
+        &pack_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        index_bits,
+    );
+
+    run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.clone(),
+        false,
+        read_from_even,
+    );
+
+    let unpack_bind_group = compact_pipeline.create_bind_group(render_device, output_keys, vals);
+    compact_pipeline.record_unpack(
+        encoder,
+        pipeline_cache,
+        &unpack_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        index_bits,
+    );
+
+    Ok(pass_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, RadixSortSettings,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_compact_index_bits() {
+        assert_eq!(compact_index_bits(0), 0);
+        assert_eq!(compact_index_bits(1), 0);
+        assert_eq!(compact_index_bits(2), 1);
+        assert_eq!(compact_index_bits(1 << 16), 16);
+        assert_eq!(compact_index_bits((1 << 16) + 1), 17);
+        assert_eq!(compact_index_bits(1 << 24), 24);
+    }
+
+    /// Argsorts `number_of_keys` keys of `key_bits` bits with the `settings`.
+    fn run_compact_test(
+        settings: RadixSortSettings,
+        number_of_keys: u32,
+        key_bits: u32,
+        read_from_even: bool,
+        expected_pass_range: Result<Range<u32>, CompactSortError>,
+    ) {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings })
+            .add_plugins(CompactSortPlugin);
+
+        let key_mask = ((1u64 << key_bits) - 1) as u32;
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761).rotate_left(7) & key_mask)
+            .collect();
+
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  compact_pipeline: Res<CompactSortPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: compact command encoder"),
+                });
+                let result = run_compact(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &compact_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    read_from_even,
+                );
+                assert_eq!(result, expected_pass_range);
+                let Ok(pass_range) = result else {
+                    return;
+                };
+                render_queue.submit([encoder.finish()]);
+
+                // A single vals buffer
+                assert!(global_vals_buffer(&sbufs, false).is_none());
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &pass_range, read_from_even);
+                let output_keys =
+                    read_buffer(&render_device, &render_queue, output_keys_buf.unwrap(), n);
+                let output_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    compact_vals_buffer(&sbufs).unwrap(),
+                    n,
+                );
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "32 index bits leave no bit of the keys")]
+    fn test_record_pack_all_index_bits() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: compact_settings(1_000, 8),
+            })
+            .add_plugins(CompactSortPlugin);
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             compact_pipeline: Res<CompactSortPipeline>,
+             sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let keys = global_keys_buffer(&sbufs, true).unwrap();
+                let vals = compact_vals_buffer(&sbufs).unwrap();
+                let bind_group = compact_pipeline.create_bind_group(&render_device, keys, vals);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: compact command encoder"),
+                });
+                compact_pipeline.record_pack(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    1_000,
+                    32,
+                );
+            },
+        );
+    }
+
+    fn compact_settings(capacity: u32, key_bits: u8) -> RadixSortSettings {
+        RadixSortSettings::builder()
+            .max_keys(capacity)
+            .significant_key_bits(key_bits)
+            .memory_mode(MemoryMode::Compact)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run_compact() {
+        // 17 index bits below 12 key bits, 4 passes
+        run_compact_test(compact_settings(100_000, 12), 100_000, 12, true, Ok(0..4));
+        run_compact_test(compact_settings(100_000, 12), 100_000, 12, false, Ok(0..4));
+        // Fewer keys than the capacity, 10 index bits below 8 key bits: 3 passes and many duplicates
+        run_compact_test(compact_settings(1 << 20, 8), 1_000, 8, true, Ok(0..3));
+        // The largest keys of 2^24 keys
+        run_compact_test(compact_settings(1 << 24, 8), 1 << 24, 8, false, Ok(0..4));
+    }
+
+    #[test]
+    fn test_run_compact_rejected() {
+        run_compact_test(
+            RadixSortSettings::from(1_000),
+            1_000,
+            8,
+            true,
+            Err(CompactSortError::NotCompact),
+        );
+        // Unlike the builder, the settings don't check the bits
+        run_compact_test(
+            RadixSortSettings::from(1_000).with_memory_mode(MemoryMode::Compact),
+            1_000,
+            32,
+            true,
+            Err(CompactSortError::TooManyBits {
+                key_bits: 32,
+                index_bits: 10,
+            }),
+        );
+        run_compact_test(
+            compact_settings(1_000, 8).with_key_transform(KeyTransform::Complement),
+            1_000,
+            8,
+            true,
+            Err(CompactSortError::UnsupportedKeys),
+        );
+    }
+}
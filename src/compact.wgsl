@@ -0,0 +1,42 @@
+// The packing of `compact.rs`: the index of each key below its bits, so a keys-only sort argsorts the keys.
+
+/// The keys to pack or unpack in place
+@group(0) @binding(0) var<storage, read_write> keys: array<u32>;
+/// Write the unpacked indices to this buffer
+@group(0) @binding(1) var<storage, read_write> vals: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys packed or unpacked.
+    number_of_keys: u32,
+    /// The number of bits of the indices, the keys are stored above them.
+    index_bits: u32,
+    /// `(1 << index_bits) - 1`
+    index_mask: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let key_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+    if key_index >= pc.number_of_keys {
+        return;
+    }
+
+#ifdef PACK_PIPELINE
+    // The indices are unique, so the packed keys are: equal keys keep their order, like the vals of a stable sort
+    keys[key_index] = (keys[key_index] << pc.index_bits) | key_index;
+#endif // PACK_PIPELINE
+
+#ifdef UNPACK_PIPELINE
+    let packed = keys[key_index];
+    keys[key_index] = packed >> pc.index_bits;
+    vals[key_index] = packed & pc.index_mask;
+#endif // UNPACK_PIPELINE
+}
@@ -0,0 +1,320 @@
+//! The CPU fallback of [`crate::run`], for adapters where no GPU backend of the sort can run.
+//!
+//! With [`crate::RadixSortSettings::with_cpu_fallback`], [`crate::check_load_state`] reports
+//! [`crate::LoadState::FallbackCpu`] instead of [`crate::LoadState::Failed`], and [`run_cpu`] sorts the global buffers
+//! through the CPU:
+//!
+//! ```text
+//!  global_keys/vals (input side)  ──readback──►  stable sort by the digits of pass_range  ──write_buffer──►
+//!  global_keys/vals (output side, as after run)
+//! ```
+//!
+//! The result is left where [`crate::sorted_keys_buffer`] expects it, so the passes consuming the sorted buffers on
+//! the GPU are unchanged. The readback blocks, the sort is parallelized with the `rayon` feature.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+            Maintain, MapMode,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    DIGEST_KEYS_SEED, DIGEST_VALS_SEED, NUMBER_OF_BYTES_PER_KEY, RadixSortSettings, SortDigest,
+    compared_bits, digest_element_hash, global_keys_buffer, global_vals_buffer,
+    initialize_global_vals, is_output_even,
+};
+
+/// Inserted once [`initialize_cpu_fallback`] unmapped the global vals buffers.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct CpuFallbackInitialized;
+
+/// Initializes the global vals buffers when the [`crate::RadixSortPipeline`] couldn't be created (see
+/// [`crate::RadixSortUnsupported`]), which [`crate::RadixSortBindGroup::initialize`] otherwise does.
+pub fn initialize_cpu_fallback(
+    mut commands: Commands,
+    radix_sort_settings: Res<RadixSortSettings>,
+    sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+) {
+    initialize_global_vals(&radix_sort_settings, &sbufs);
+    commands.insert_resource(CpuFallbackInitialized);
+}
+
+/// Sorts `keys` (and `vals` along) by the digits in `pass_range`, stable like the radix sort over `pass_range`.
+pub fn sort_on_cpu(keys: &mut [u32], vals: Option<&mut [u32]>, pass_range: &Range<u32>) {
+    if pass_range.is_empty() {
+        return;
+    }
+
+    let (key_shift, key_mask) = compared_bits(pass_range);
+    let compared = move |key: u32| (key >> key_shift) & key_mask;
+
+    let Some(vals) = vals else {
+        sort_by_key(keys, |key| compared(*key));
+        return;
+    };
+
+    let mut pairs: Vec<(u32, u32)> = keys.iter().copied().zip(vals.iter().copied()).collect();
+    sort_by_key(&mut pairs, |(key, _)| compared(*key));
+
+    for ((key, val), (sorted_key, sorted_val)) in keys.iter_mut().zip(vals.iter_mut()).zip(pairs) {
+        *key = sorted_key;
+        *val = sorted_val;
+    }
+}
+
+#[cfg(feature = "rayon")]
+fn sort_by_key<T: Send, F: Fn(&T) -> u32 + Sync>(data: &mut [T], f: F) {
+    use rayon::slice::ParallelSliceMut;
+
+    data.par_sort_by_key(f);
+}
+
+#[cfg(not(feature = "rayon"))]
+fn sort_by_key<T, F: Fn(&T) -> u32>(data: &mut [T], f: F) {
+    data.sort_by_key(f);
+}
+
+/// The [`SortDigest`] of `keys` and as many first `vals`, the same as [`crate::DigestPipeline::record_digest`]
+/// writes on the GPU.
+pub fn digest_on_cpu(keys: &[u32], vals: Option<&[u32]>) -> SortDigest {
+    let fold = |values: &[u32], seed| {
+        values
+            .iter()
+            .zip(0..)
+            .map(|(&value, index)| digest_element_hash(value, index, seed))
+            .fold((0u32, 0u32), |(sum, xor), hash| {
+                (sum.wrapping_add(hash), xor ^ hash)
+            })
+    };
+
+    let (keys_sum, keys_xor) = fold(keys, DIGEST_KEYS_SEED);
+    let (vals_sum, vals_xor) =
+        vals.map_or((0, 0), |vals| fold(&vals[..keys.len()], DIGEST_VALS_SEED));
+
+    SortDigest {
+        keys_sum,
+        keys_xor,
+        vals_sum,
+        vals_xor,
+    }
+}
+
+/// Like [`crate::run`] on the global buffers, but sorted on the CPU: the input side is read back (blocking), sorted
+/// with [`sort_on_cpu`] and written to the output side with `render_queue`.
+///
+/// The keys must have been written by submitted commands or queue writes. Returns `false` if the global buffers
+/// aren't prepared yet.
+pub fn run_cpu(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) -> bool {
+    let (Some(input_keys), Some(output_keys)) = (
+        global_keys_buffer(sbufs, read_from_even),
+        global_keys_buffer(sbufs, is_output_even(&pass_range, read_from_even)),
+    ) else {
+        return false;
+    };
+    let vals_buffers = global_vals_buffer(sbufs, read_from_even).zip(global_vals_buffer(
+        sbufs,
+        is_output_even(&pass_range, read_from_even),
+    ));
+
+    if init_index && vals_buffers.is_none() {
+        error!(
+            "radix_sort: init_index sorts key/value pairs, but the vals buffers aren't allocated (RadixSortSettings::without_values)"
+        );
+        return true;
+    }
+
+    if number_of_keys < 2 || pass_range.is_empty() {
+        return true;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_cpu", number_of_keys).entered();
+
+    let mut keys = read_back(render_device, render_queue, input_keys, number_of_keys);
+    let mut vals = vals_buffers.map(|(input_vals, _)| {
+        if init_index {
+            (0..number_of_keys).collect()
+        } else {
+            read_back(render_device, render_queue, input_vals, number_of_keys)
+        }
+    });
+
+    sort_on_cpu(&mut keys, vals.as_deref_mut(), &pass_range);
+
+    render_queue.write_buffer(output_keys, 0, bytemuck::cast_slice(&keys));
+    if let (Some(vals), Some((_, output_vals))) = (vals, vals_buffers) {
+        render_queue.write_buffer(output_vals, 0, bytemuck::cast_slice(&vals));
+    }
+
+    true
+}
+
+/// Copies the first `number_of_keys` u32s of `buffer` into a staging buffer and maps it, blocking until done.
+fn read_back(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    buffer: &Buffer,
+    number_of_keys: u32,
+) -> Vec<u32> {
+    let size = (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as BufferAddress;
+
+    let staging_buf = render_device.create_buffer(&BufferDescriptor {
+        label: Some("radix_sort: cpu fallback staging buffer"),
+        size,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("radix_sort: cpu fallback readback command encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buf, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    let slice = staging_buf.slice(..);
+    slice.map_async(MapMode::Read, |_| ());
+    render_device.poll(Maintain::Wait).panic_on_timeout();
+
+    let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging_buf.unmap();
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{Render, RenderApp, RenderSet};
+
+    use crate::{
+        GetSubgroupSizePlugin, LoadState, RadixSortPipeline, RadixSortPlugin, RadixSortUnsupported,
+        check_load_state, sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{create_render_test_app, read_buffer},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sort_on_cpu() {
+        let keys: Vec<u32> = (0..1_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+
+        // Stable by the digits 1..3, as the radix sort over these passes
+        let compared = |key: u32| (key >> 8) & 0xFFFF;
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort_by_key(|(key, _)| compared(*key));
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        let mut sorted_keys = keys.clone();
+        let mut sorted_vals: Vec<u32> = (0..1_000).collect();
+        sort_on_cpu(&mut sorted_keys, Some(&mut sorted_vals), &(1..3));
+        assert_eq!(sorted_keys, expected_keys);
+        assert_eq!(sorted_vals, expected_vals);
+
+        let mut sorted_keys = keys.clone();
+        sort_on_cpu(&mut sorted_keys, None, &(0..4));
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+        assert_eq!(sorted_keys, expected_keys);
+
+        // No pass, nothing moves
+        let mut sorted_keys = keys.clone();
+        sort_on_cpu(&mut sorted_keys, None, &(2..2));
+        assert_eq!(sorted_keys, keys);
+    }
+
+    #[test]
+    fn test_cpu_fallback() {
+        let keys: Vec<u32> = (0..5_000u32).map(|i| (i * 7_919) % 1_000).collect();
+        let number_of_keys = keys.len() as u32;
+
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort_by_key(|(key, _)| *key);
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(number_of_keys)
+                    .with_initial_keys(keys)
+                    .with_cpu_fallback(),
+            });
+        app.finish();
+        app.cleanup();
+
+        // As if the adapter couldn't create the pipelines
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .world_mut()
+            .remove_resource::<RadixSortPipeline>();
+        render_app
+            .world_mut()
+            .insert_resource(RadixSortUnsupported("forced by the test".into()));
+
+        render_app.add_systems(
+            Render,
+            (move |world: &World| {
+                assert_eq!(check_load_state(world), LoadState::FallbackCpu);
+
+                let render_device = world.resource::<RenderDevice>();
+                let render_queue = world.resource::<RenderQueue>();
+                let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+                assert!(run_cpu(
+                    render_device,
+                    render_queue,
+                    sbufs,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                ));
+
+                let sorted_keys = sorted_keys_buffer(sbufs, &(0..4), true).unwrap();
+                let sorted_vals = sorted_vals_buffer(sbufs, &(0..4), true).unwrap();
+                assert_eq!(
+                    read_buffer(
+                        render_device,
+                        render_queue,
+                        sorted_keys,
+                        number_of_keys as usize
+                    ),
+                    expected_keys
+                );
+                assert_eq!(
+                    read_buffer(
+                        render_device,
+                        render_queue,
+                        sorted_vals,
+                        number_of_keys as usize
+                    ),
+                    expected_vals
+                );
+            })
+            .in_set(RenderSet::Cleanup),
+        );
+
+        app.update();
+        assert!(
+            app.sub_app(RenderApp)
+                .world()
+                .contains_resource::<CpuFallbackInitialized>()
+        );
+    }
+}
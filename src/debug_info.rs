@@ -0,0 +1,627 @@
+//! What the last sort encoded, to find out why a sort misbehaves without a GPU debugger.
+//!
+//! [`crate::run`] (so the [`crate::RadixSortJobsNode`] and [`crate::run_with_options`] too) and the indirect sorts
+//! ([`crate::run_auto`], [`crate::run_unless_sorted`], [`crate::run_gpu_driven`]) describe every sort they encode,
+//! including the ones they reject or find empty, in the [`RadixSortDebugInfo`] of the [`RadixSortPipeline`]. It's
+//! copied into the [`RadixSortDebugInfo`] resource of the render world at the end of each frame:
+//!
+//! ```text
+//!  run / run_auto / ...:  DebugPassRecorder ─▶ RadixSortDebugInfoRecorder
+//!  Render (Cleanup):      RadixSortDebugInfoRecorder ─▶ RadixSortDebugInfo (render world)
+//!  First (main world):    RadixSortDebugInfo (main world), with the `main_world_debug_info` feature
+//! ```
+//!
+//! The sorts [`crate::run_batch`] interleaves aren't described, those it records one by one through `run` are.
+
+use std::{ops::Range, sync::Mutex};
+
+#[cfg(feature = "main_world_debug_info")]
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    render::{Render, RenderApp, RenderSet},
+};
+use wgpu::{BindGroup, ComputePipeline};
+
+use crate::{
+    Algorithm, BITONIC_KEYS_PER_BLOCK, BitonicPipelines, DigitHistogramsPipelines, PassRecorder,
+    RadixSortPipeline, SortPipelines, is_output_even, tile_size,
+};
+
+/// The last sort encoded, see the [module](self).
+#[derive(Resource, Reflect, Debug, Clone, Default, PartialEq, Eq)]
+#[reflect(Resource, Debug, Default)]
+pub struct RadixSortDebugInfo {
+    /// The number of sorts described since the [`RadixSortPipeline`] was created, this one included. 0 before the
+    /// first one.
+    pub run: u64,
+    pub number_of_keys: u32,
+    pub pass_range: Range<u32>,
+    pub init_index: bool,
+    pub read_from_even: bool,
+    /// The side of the global buffers holding the output once every pass ran, see [`is_output_even`].
+    pub output_even: bool,
+    /// The backend the sort was recorded with, see [`RadixSortPipeline::active_algorithm`].
+    pub algorithm: Algorithm,
+    /// The sort wasn't recorded with the configured algorithm, after the fallback of
+    /// [`RadixSortSettings::with_fallback`](crate::RadixSortSettings::with_fallback) or for an indirect sort, always
+    /// a radix sort.
+    pub algorithm_fallback: bool,
+    /// See [`RadixSortPipeline::subgroup_fallback`].
+    pub subgroup_fallback: bool,
+    /// The keys a workgroup of the count and scatter steps (of the local step of the bitonic sort) covers.
+    pub tile_size: u32,
+    pub early_out: RadixSortEarlyOut,
+    /// The dispatches of each pipeline of the sort, in the order they're first set. Empty if nothing was recorded.
+    pub stages: Vec<RadixSortDebugStage>,
+}
+
+impl RadixSortDebugInfo {
+    /// Describes a sort with these arguments, without any stage yet.
+    pub(crate) fn new(
+        radix_sort_pipeline: &RadixSortPipeline,
+        algorithm: Algorithm,
+        number_of_keys: u32,
+        pass_range: &Range<u32>,
+        init_index: bool,
+        read_from_even: bool,
+    ) -> Self {
+        let bitonic =
+            algorithm == Algorithm::Bitonic && radix_sort_pipeline.bitonic_pipeline().is_some();
+
+        Self {
+            run: 0,
+            number_of_keys,
+            pass_range: pass_range.clone(),
+            init_index,
+            read_from_even,
+            output_even: is_output_even(pass_range, read_from_even),
+            algorithm: if bitonic {
+                Algorithm::Bitonic
+            } else {
+                Algorithm::Radix
+            },
+            algorithm_fallback: algorithm != radix_sort_pipeline.algorithm,
+            subgroup_fallback: radix_sort_pipeline.subgroup_fallback(),
+            tile_size: if bitonic {
+                BITONIC_KEYS_PER_BLOCK
+            } else {
+                tile_size()
+            },
+            early_out: RadixSortEarlyOut::None,
+            stages: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_early_out(mut self, early_out: RadixSortEarlyOut) -> Self {
+        self.early_out = early_out;
+        self
+    }
+
+    pub(crate) fn with_stages(mut self, stages: Vec<RadixSortDebugStage>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// The stage dispatching the pipeline called `name`.
+    pub fn stage(&self, name: &str) -> Option<&RadixSortDebugStage> {
+        self.stages.iter().find(|stage| stage.name == name)
+    }
+}
+
+/// Why a sort recorded fewer dispatches than its pass range needs, or might run fewer on the GPU.
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum RadixSortEarlyOut {
+    /// Every dispatch of the pass range was recorded and runs.
+    #[default]
+    None,
+    /// Nothing was recorded: fewer than 2 keys or an empty pass range.
+    Empty,
+    /// Nothing was recorded, the arguments were rejected with an error logged.
+    InvalidArguments,
+    /// [`crate::run_auto`]: the passes above the highest digit of the maximum key are skipped on the GPU.
+    HighDigits,
+    /// [`crate::run_unless_sorted`]: every pass is skipped on the GPU if the keys are already sorted.
+    IfSorted,
+    /// [`crate::run_gpu_driven`]: the count and the passes are read on the GPU, the recorded workgroups are the
+    /// largest the dispatches can take.
+    GpuDriven,
+}
+
+/// The dispatches of one pipeline of a sort.
+#[derive(Reflect, Debug, Clone, Default, PartialEq, Eq)]
+#[reflect(Debug, Default, PartialEq)]
+pub struct RadixSortDebugStage {
+    /// The pipeline, named as in [`RadixSortPipeline::pipeline_ids`], e.g. `scatter_pipeline`.
+    pub name: String,
+    pub dispatches: u32,
+    /// The workgroups of all the dispatches.
+    pub workgroups: u64,
+}
+
+/// The last [`RadixSortDebugInfo`] of a [`RadixSortPipeline`], see [`RadixSortPipeline::debug_info`].
+#[derive(Debug, Default)]
+pub struct RadixSortDebugInfoRecorder {
+    last: Mutex<RadixSortDebugInfo>,
+}
+
+impl RadixSortDebugInfoRecorder {
+    /// Numbers `info` and keeps it as the last sort.
+    pub(crate) fn record(&self, mut info: RadixSortDebugInfo) {
+        let mut last = self.last.lock().unwrap();
+        info.run = last.run + 1;
+        *last = info;
+    }
+
+    /// The last sort described, [`RadixSortDebugInfo::default`] before any.
+    pub fn last(&self) -> RadixSortDebugInfo {
+        self.last.lock().unwrap().clone()
+    }
+}
+
+/// The radix sort pipelines by [`RadixSortDebugStage::name`].
+pub(crate) fn radix_stages<'a>(
+    pipelines: &SortPipelines<'a>,
+) -> [(&'a ComputePipeline, &'static str); 5] {
+    [
+        (pipelines.count_radix_pipeline, "count_radix_pipeline"),
+        (pipelines.scan_upsweep_pipeline, "scan_upsweep_pipeline"),
+        (pipelines.scan_dnsweep_pipeline, "scan_dnsweep_pipeline"),
+        (
+            pipelines.scan_last_block_pipeline,
+            "scan_last_block_pipeline",
+        ),
+        (pipelines.scatter_pipeline, "scatter_pipeline"),
+    ]
+}
+
+/// The digit histograms pipelines of [`crate::run`] by [`RadixSortDebugStage::name`].
+pub(crate) fn digit_histograms_stages<'a>(
+    pipelines: &DigitHistogramsPipelines<'a>,
+) -> [(&'a ComputePipeline, &'static str); 3] {
+    [
+        (
+            pipelines.digit_histograms_pipeline,
+            "digit_histograms_pipeline",
+        ),
+        (
+            pipelines.scan_digit_histograms_pipeline,
+            "scan_digit_histograms_pipeline",
+        ),
+        (pipelines.scatter_pipeline, "fused_scatter_pipeline"),
+    ]
+}
+
+/// The bitonic sort pipelines by [`RadixSortDebugStage::name`].
+pub(crate) fn bitonic_stages<'a>(
+    pipelines: &BitonicPipelines<'a>,
+) -> [(&'a ComputePipeline, &'static str); 3] {
+    [
+        (pipelines.copy_pipeline, "bitonic copy_pipeline"),
+        (pipelines.local_pipeline, "bitonic local_pipeline"),
+        (pipelines.global_pipeline, "bitonic global_pipeline"),
+    ]
+}
+
+/// Records into `pass` and counts the dispatches of the named pipelines.
+pub(crate) struct DebugPassRecorder<'p, 'a, R> {
+    pass: &'p mut R,
+    pipelines: Vec<(&'a ComputePipeline, &'static str)>,
+    stages: Vec<RadixSortDebugStage>,
+    /// The stage of the pipeline last set, `None` for a pipeline not named.
+    current: Option<usize>,
+}
+
+impl<'p, 'a, R: PassRecorder> DebugPassRecorder<'p, 'a, R> {
+    pub(crate) fn new(
+        pass: &'p mut R,
+        pipelines: impl IntoIterator<Item = (&'a ComputePipeline, &'static str)>,
+    ) -> Self {
+        Self {
+            pass,
+            pipelines: pipelines.into_iter().collect(),
+            stages: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// The stages of the pipelines set, in order.
+    pub(crate) fn finish(self) -> Vec<RadixSortDebugStage> {
+        self.stages
+    }
+}
+
+impl<R: PassRecorder> PassRecorder for DebugPassRecorder<'_, '_, R> {
+    fn set_pipeline(&mut self, pipeline: &ComputePipeline) {
+        self.pass.set_pipeline(pipeline);
+
+        self.current = self
+            .pipelines
+            .iter()
+            .find(|(named, _)| std::ptr::eq(*named, pipeline))
+            .map(|(_, name)| {
+                self.stages
+                    .iter()
+                    .position(|stage| stage.name == *name)
+                    .unwrap_or_else(|| {
+                        self.stages.push(RadixSortDebugStage {
+                            name: name.to_string(),
+                            ..default()
+                        });
+                        self.stages.len() - 1
+                    })
+            });
+    }
+
+    fn set_bind_group(&mut self, bind_group: &BindGroup) {
+        self.pass.set_bind_group(bind_group);
+    }
+
+    fn set_push_constants(&mut self, offset: u32, data: &[u8]) {
+        self.pass.set_push_constants(offset, data);
+    }
+
+    fn dispatch_workgroups(&mut self, x: u32, y: u32, z: u32) {
+        self.pass.dispatch_workgroups(x, y, z);
+
+        if let Some(stage) = self.current.map(|index| &mut self.stages[index]) {
+            stage.dispatches += 1;
+            stage.workgroups += x as u64 * y as u64 * z as u64;
+        }
+    }
+
+    fn begin_sort_pass(&mut self, pass_index: u32) {
+        self.pass.begin_sort_pass(pass_index);
+    }
+}
+
+/// The [`RadixSortDebugInfo`] the render world shares with the main world.
+#[cfg(feature = "main_world_debug_info")]
+#[derive(Resource, Debug, Clone, Default)]
+struct RadixSortDebugInfoCell(Arc<Mutex<RadixSortDebugInfo>>);
+
+/// Adds the [`RadixSortDebugInfo`] and the systems updating it, to the main world too with the
+/// `main_world_debug_info` feature.
+pub(crate) fn build_debug_info(app: &mut App) {
+    app.register_type::<RadixSortDebugInfo>()
+        .register_type::<RadixSortEarlyOut>()
+        .register_type::<RadixSortDebugStage>();
+    app.sub_app_mut(RenderApp)
+        .init_resource::<RadixSortDebugInfo>()
+        .add_systems(
+            Render,
+            update_radix_sort_debug_info
+                .in_set(RenderSet::Cleanup)
+                .run_if(resource_exists::<RadixSortPipeline>),
+        );
+
+    #[cfg(feature = "main_world_debug_info")]
+    {
+        let cell = RadixSortDebugInfoCell::default();
+
+        app.init_resource::<RadixSortDebugInfo>()
+            .insert_resource(cell.clone())
+            .add_systems(First, sync_radix_sort_debug_info);
+        app.sub_app_mut(RenderApp).insert_resource(cell);
+    }
+}
+
+/// Copies the last sort of the pipeline into the resource, and the cell shared with the main world.
+fn update_radix_sort_debug_info(
+    radix_sort_pipeline: Res<RadixSortPipeline>,
+    mut debug_info: ResMut<RadixSortDebugInfo>,
+    #[cfg(feature = "main_world_debug_info")] cell: Res<RadixSortDebugInfoCell>,
+) {
+    let last = radix_sort_pipeline.debug_info().last();
+    // A reinitialized pipeline numbers its sorts from 1 again
+    if last.run == 0 || *debug_info == last {
+        return;
+    }
+
+    #[cfg(feature = "main_world_debug_info")]
+    {
+        *cell.0.lock().unwrap() = last.clone();
+    }
+    *debug_info = last;
+}
+
+#[cfg(feature = "main_world_debug_info")]
+fn sync_radix_sort_debug_info(
+    cell: Res<RadixSortDebugInfoCell>,
+    mut debug_info: ResMut<RadixSortDebugInfo>,
+) {
+    let last = cell.0.lock().unwrap();
+    if *debug_info != *last {
+        *debug_info = last.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssets,
+        render_resource::{CommandEncoderDescriptor, PipelineCache},
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        RadixSortBindGroup, RadixSortPlugin, RadixSortSettings, ReduceMaxPipeline, ReduceMaxPlugin,
+        run, run_auto,
+        test_utils::{create_render_test_app, run_render_system_once},
+        workgroups_for,
+    };
+
+    use super::*;
+
+    /// Records `run` with these arguments and returns what it described, nothing is submitted.
+    #[allow(clippy::too_many_arguments)]
+    fn describe_run(
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        radix_sort_pipeline: &RadixSortPipeline,
+        radix_bind_group: &RadixSortBindGroup,
+        number_of_keys: u32,
+        pass_range: Range<u32>,
+        init_index: bool,
+        read_from_even: bool,
+    ) -> RadixSortDebugInfo {
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("unit_test: debug info command encoder"),
+        });
+        run(
+            &mut encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            render_device.limits().max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_range,
+            init_index,
+            read_from_even,
+        );
+
+        radix_sort_pipeline.debug_info().last()
+    }
+
+    fn check_radix_stages(info: &RadixSortDebugInfo) {
+        let passes = info.pass_range.len() as u32;
+        let workgroups = workgroups_for(info.number_of_keys) as u64 * passes as u64;
+
+        let count = info.stage("count_radix_pipeline").unwrap();
+        assert_eq!((count.dispatches, count.workgroups), (passes, workgroups));
+        assert!(info.stage("scan_upsweep_pipeline").unwrap().dispatches >= passes);
+
+        // `run` counts the digits of all its passes at once, the indirect sorts count a digit per pass
+        if let Some(digit_histograms) = info.stage("digit_histograms_pipeline") {
+            assert_eq!(
+                (digit_histograms.dispatches, digit_histograms.workgroups),
+                (1, workgroups / passes as u64)
+            );
+            let scan = info.stage("scan_digit_histograms_pipeline").unwrap();
+            assert_eq!((scan.dispatches, scan.workgroups), (1, passes as u64));
+            let scatter = info.stage("fused_scatter_pipeline").unwrap();
+            assert_eq!(
+                (scatter.dispatches, scatter.workgroups),
+                (passes, workgroups)
+            );
+            assert!(info.stage("scan_last_block_pipeline").is_none());
+            assert!(info.stage("scatter_pipeline").is_none());
+        } else {
+            let scatter = info.stage("scatter_pipeline").unwrap();
+            assert_eq!(
+                (scatter.dispatches, scatter.workgroups),
+                (passes, workgroups)
+            );
+            let last_block = info.stage("scan_last_block_pipeline").unwrap();
+            assert_eq!(
+                (last_block.dispatches, last_block.workgroups),
+                (passes, passes as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_debug_info() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(100_000).with_max_number_of_values(50_000),
+        });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>| {
+                let describe = |number_of_keys, pass_range, init_index, read_from_even| {
+                    describe_run(
+                        &render_device,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        number_of_keys,
+                        pass_range,
+                        init_index,
+                        read_from_even,
+                    )
+                };
+
+                let info = describe(40_000, 0..4, true, true);
+                assert_eq!(info.run, 1);
+                assert_eq!(info.number_of_keys, 40_000);
+                assert_eq!(info.pass_range, 0..4);
+                assert!(info.init_index && info.output_even);
+                assert_eq!(info.algorithm, Algorithm::Radix);
+                assert!(!info.algorithm_fallback);
+                assert_eq!(
+                    info.subgroup_fallback,
+                    radix_sort_pipeline.subgroup_fallback()
+                );
+                assert_eq!(info.tile_size, tile_size());
+                assert_eq!(info.early_out, RadixSortEarlyOut::None);
+                assert_eq!(info.stages.len(), 6);
+                assert!(info.stage("digit_histograms_pipeline").is_some());
+                check_radix_stages(&info);
+
+                // Keys-only, the passes are counted from 0 whatever the start of the range
+                let info = describe(100_000, 1..4, false, false);
+                assert_eq!(info.run, 2);
+                assert!(!info.init_index && !info.output_even);
+                check_radix_stages(&info);
+
+                let info = describe(1, 0..4, true, true);
+                assert_eq!(info.early_out, RadixSortEarlyOut::Empty);
+                assert!(info.stages.is_empty());
+
+                let info = describe(1_000, 2..2, false, true);
+                assert_eq!(info.early_out, RadixSortEarlyOut::Empty);
+                assert!(info.stages.is_empty());
+
+                // More pairs than the vals buffers hold
+                let info = describe(60_000, 0..4, true, true);
+                assert_eq!(info.early_out, RadixSortEarlyOut::InvalidArguments);
+                assert!(info.stages.is_empty());
+                assert_eq!(info.run, 5);
+
+                // More keys than the blocks buffer holds the blocks and the digit histograms of
+                let max_number_of_keys = radix_bind_group.max_number_of_keys();
+                assert!(max_number_of_keys >= 100_000);
+                let info = describe(max_number_of_keys + 1, 0..4, false, true);
+                assert_eq!(info.early_out, RadixSortEarlyOut::InvalidArguments);
+                assert_eq!(info.run, 6);
+            },
+        );
+    }
+
+    #[test]
+    fn test_bitonic_debug_info() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(5_000).with_algorithm(Algorithm::Bitonic),
+        });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>| {
+                let info = describe_run(
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    5_000,
+                    0..3,
+                    true,
+                    true,
+                );
+
+                assert_eq!(info.algorithm, Algorithm::Bitonic);
+                assert_eq!(info.tile_size, BITONIC_KEYS_PER_BLOCK);
+                assert!(!info.output_even);
+                // Sorted out of place, the input is copied first
+                assert_eq!(info.stage("bitonic copy_pipeline").unwrap().dispatches, 1);
+                let local = info.stage("bitonic local_pipeline").unwrap();
+                let blocks = 5_000u32.div_ceil(BITONIC_KEYS_PER_BLOCK) as u64;
+                assert_eq!(local.workgroups, local.dispatches as u64 * blocks);
+                assert!(info.stage("bitonic global_pipeline").unwrap().dispatches > 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_auto_debug_info() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 10_000.into(),
+        })
+        .add_plugins(ReduceMaxPlugin);
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>,
+             reduce_max_pipeline: Res<ReduceMaxPipeline>,
+             sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: debug info command encoder"),
+                });
+                run_auto(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &reduce_max_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    10_000,
+                    0..3,
+                    true,
+                    true,
+                );
+
+                // Every pass is recorded, the GPU zeroes the args of the skipped ones
+                let info = radix_sort_pipeline.debug_info().last();
+                assert_eq!(info.early_out, RadixSortEarlyOut::HighDigits);
+                assert!(info.stage("digit_histograms_pipeline").is_none());
+                check_radix_stages(&info);
+                assert!(info.stage("copy_pipeline").is_some());
+            },
+        );
+    }
+
+    #[test]
+    fn test_debug_info_resource() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 1_000.into(),
+        });
+
+        run_render_system_once(
+            &mut app,
+            |mut done: Local<bool>,
+             render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>| {
+                if !std::mem::replace(&mut *done, true) {
+                    describe_run(
+                        &render_device,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        1_000,
+                        0..2,
+                        false,
+                        true,
+                    );
+                }
+            },
+        );
+        // The render world copies it at the end of the frame, the main world at the start of the next one
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let render_world = app.sub_app(RenderApp).world();
+        let info = render_world.resource::<RadixSortDebugInfo>();
+        assert_eq!(
+            *info,
+            render_world
+                .resource::<RadixSortPipeline>()
+                .debug_info()
+                .last()
+        );
+        assert_eq!((info.run, info.number_of_keys), (1, 1_000));
+
+        #[cfg(feature = "main_world_debug_info")]
+        assert_eq!(app.world().resource::<RadixSortDebugInfo>(), info);
+    }
+}
@@ -0,0 +1,176 @@
+//! Sort statistics in the Bevy [`bevy::diagnostic::DiagnosticsStore`], e.g. printed next to the FPS by `LogDiagnosticsPlugin`.
+//!
+//! [`crate::run`] and [`crate::run_auto`] count the sorts they record into the [`RadixSortCounters`] of the
+//! [`RadixSortPipeline`]. The render world only holds the counters, the main world drains them once per frame:
+//!
+//! ```text
+//!  render world                       main world
+//!  run(.., number_of_keys, ..)  ──▶   RadixSortCounters  ──▶  radix_sort/sorts_per_frame
+//!                                                        ──▶  radix_sort/keys_per_sort
+//! ```
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    render::RenderApp,
+};
+
+use crate::RadixSortPipeline;
+
+/// Requires the [`crate::RadixSortPlugin`] to be added before it, the counters belong to its pipeline.
+pub struct RadixSortDiagnosticsPlugin;
+
+impl RadixSortDiagnosticsPlugin {
+    /// The average number of keys of the sorts recorded during the last frame with any sort.
+    pub const KEYS_PER_SORT: DiagnosticPath = DiagnosticPath::const_new("radix_sort/keys_per_sort");
+    /// The number of sorts recorded during the last frame.
+    pub const SORTS_PER_FRAME: DiagnosticPath =
+        DiagnosticPath::const_new("radix_sort/sorts_per_frame");
+}
+
+impl Plugin for RadixSortDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(Self::KEYS_PER_SORT))
+            .register_diagnostic(Diagnostic::new(Self::SORTS_PER_FRAME))
+            .add_systems(
+                Update,
+                update_diagnostics.run_if(resource_exists::<RadixSortDiagnosticsCounters>),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(radix_sort_pipeline) = app
+            .sub_app(RenderApp)
+            .world()
+            .get_resource::<RadixSortPipeline>()
+        else {
+            warn!("radix_sort: RadixSortDiagnosticsPlugin requires the RadixSortPlugin");
+            return;
+        };
+
+        let counters = RadixSortDiagnosticsCounters(radix_sort_pipeline.counters().clone());
+        app.insert_resource(counters);
+    }
+}
+
+/// The number of sorts and keys recorded since the counters were last drained.
+#[derive(Debug, Default)]
+pub struct RadixSortCounters {
+    sorts: AtomicU32,
+    keys: AtomicU64,
+}
+
+impl RadixSortCounters {
+    pub(crate) fn record(&self, number_of_keys: u32) {
+        self.sorts.fetch_add(1, Ordering::Relaxed);
+        self.keys
+            .fetch_add(number_of_keys as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the number of sorts and their total number of keys, and resets both.
+    pub fn take(&self) -> (u32, u64) {
+        (
+            self.sorts.swap(0, Ordering::Relaxed),
+            self.keys.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+/// The [`RadixSortCounters`] of the render world [`RadixSortPipeline`], shared with the main world.
+#[derive(Resource, Debug, Clone)]
+pub struct RadixSortDiagnosticsCounters(pub Arc<RadixSortCounters>);
+
+fn update_diagnostics(counters: Res<RadixSortDiagnosticsCounters>, mut diagnostics: Diagnostics) {
+    let (sorts, keys) = counters.0.take();
+
+    diagnostics.add_measurement(&RadixSortDiagnosticsPlugin::SORTS_PER_FRAME, || {
+        sorts as f64
+    });
+
+    if sorts > 0 {
+        diagnostics.add_measurement(&RadixSortDiagnosticsPlugin::KEYS_PER_SORT, || {
+            keys as f64 / sorts as f64
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        diagnostic::DiagnosticsStore,
+        render::{
+            Render, RenderSet, render_resource::CommandEncoderDescriptor,
+            render_resource::PipelineCache, renderer::RenderDevice, renderer::RenderQueue,
+        },
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortBindGroup, RadixSortPlugin, run,
+        test_utils::create_render_test_app,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let number_of_keys = 10_000;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            })
+            .add_plugins(RadixSortDiagnosticsPlugin);
+
+        // Two sorts per frame
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            (move |render_device: Res<RenderDevice>,
+                   render_queue: Res<RenderQueue>,
+                   pipeline_cache: Res<PipelineCache>,
+                   radix_sort_pipeline: Res<RadixSortPipeline>,
+                   radix_bind_group: Res<RadixSortBindGroup>| {
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: diagnostics command encoder"),
+                });
+                for _ in 0..2 {
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        0..4,
+                        true,
+                        true,
+                    );
+                }
+                render_queue.submit([encoder.finish()]);
+            })
+            .in_set(RenderSet::Cleanup)
+            .run_if(resource_exists::<RadixSortBindGroup>),
+        );
+
+        app.finish();
+        app.cleanup();
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let store = app.world().resource::<DiagnosticsStore>();
+        let sorts_per_frame = store
+            .get(&RadixSortDiagnosticsPlugin::SORTS_PER_FRAME)
+            .and_then(Diagnostic::value);
+        let keys_per_sort = store
+            .get(&RadixSortDiagnosticsPlugin::KEYS_PER_SORT)
+            .and_then(Diagnostic::value);
+        assert_eq!(sorts_per_frame, Some(2.0));
+        assert_eq!(keys_per_sort, Some(number_of_keys as f64));
+    }
+}
@@ -0,0 +1,430 @@
+//! An order-sensitive digest of sorted keys and vals on the GPU, e.g. for golden tests comparing a few u32s across
+//! adapters instead of reading back the whole output:
+//!
+//! ```text
+//!  element i  ─▶  hash(key_i, i), hash(val_i, i)  ─▶  wrapping sum and xor of the hashes  ─▶  SortDigest
+//! ```
+//!
+//! The position enters the hash of each element, so any permutation changes the digest, and the sum and the xor
+//! don't depend on the order the workgroups fold them in. With the `cpu_fallback` feature, `digest_on_cpu`
+//! computes the same digest on the CPU.
+
+use std::{fmt, num::NonZeroU64, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer, BufferAddress,
+            BufferUsages, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer_read_only, storage_buffer_sized},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP,
+    compute_pipelines_load_state, create_dummy_vals_buffers, dispatch_workgroup_ext,
+    sorted_keys_buffer, sorted_vals_buffer,
+};
+
+pub const DIGEST_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(60829431190570736186709503786630419819);
+
+/// The size in bytes of the output of [`DigestPipeline::record_digest`], a [`SortDigest`].
+pub const DIGEST_BUFFER_SIZE: BufferAddress = 4 * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+/// The seeds of the position hashes of the keys and the vals, as in `digest.wgsl`.
+pub(crate) const DIGEST_KEYS_SEED: u32 = 0x243f6a88;
+pub(crate) const DIGEST_VALS_SEED: u32 = 0x85a308d3;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const DIGEST_VALS_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+/// The finalizer of MurmurHash3, `mix` in `digest.wgsl`.
+const fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85ebca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2ae35);
+    x ^= x >> 16;
+    x
+}
+
+/// The hash of the element `value` at `index`, folded into the digest.
+pub(crate) const fn digest_element_hash(value: u32, index: u32, seed: u32) -> u32 {
+    mix(value ^ mix(index ^ seed))
+}
+
+/// The digest of keys and vals: the wrapping sums and the xors of the hashes of their elements with their position.
+/// The vals words are 0 for keys-only buffers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SortDigest {
+    pub keys_sum: u32,
+    pub keys_xor: u32,
+    pub vals_sum: u32,
+    pub vals_xor: u32,
+}
+
+impl SortDigest {
+    /// The digest written by [`DigestPipeline::record_digest`], read back as 4 u32s.
+    ///
+    /// # Panics
+    ///
+    /// If `words` doesn't hold 4 u32s.
+    pub fn from_words(words: &[u32]) -> Self {
+        *bytemuck::from_bytes(bytemuck::cast_slice(words))
+    }
+}
+
+/// 32 hex digits, e.g. to store golden digests as strings.
+impl fmt::Display for SortDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:08x}{:08x}{:08x}{:08x}",
+            self.keys_sum, self.keys_xor, self.vals_sum, self.vals_xor
+        )
+    }
+}
+
+pub struct DigestPlugin;
+
+impl Plugin for DigestPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, DIGEST_SHADER_HANDLE, "digest.wgsl", Shader::from_wgsl);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<DigestPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct DigestPipeline {
+    /// Folds the hashes of the elements into the digest
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > keys: array<u32>;
+    /// @binding(1) var<storage, read      > vals: array<u32>;
+    /// @binding(2) var<storage, read_write> digest: array<atomic<u32>, 4>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for DigestPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "digest bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_sized(false, NonZeroU64::new(DIGEST_BUFFER_SIZE)),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("digest: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: DIGEST_SHADER_HANDLE,
+            shader_defs: vec![ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            )],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl DigestPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &[("digest pipeline", self.pipeline)])
+    }
+
+    /// Writes the [`SortDigest`] of the first `count` keys, and vals unless `vals` is `None`, into the first
+    /// [`DIGEST_BUFFER_SIZE`] bytes of `out_buffer`, which needs the `STORAGE` and `COPY_DST` usages.
+    ///
+    /// # Panics
+    ///
+    /// If a buffer is too small, or if `out_buffer` lacks a usage.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_digest(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        keys: &Buffer,
+        vals: Option<&Buffer>,
+        count: u32,
+        out_buffer: &Buffer,
+        max_compute_workgroups_per_dimension: u32,
+    ) {
+        let size = count as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+        for (name, buffer) in [("keys", Some(keys)), ("vals", vals)] {
+            let Some(buffer) = buffer else {
+                continue;
+            };
+            assert!(
+                buffer.size() >= size,
+                "digest: {} holds {} bytes, less than its {} u32s",
+                name,
+                buffer.size(),
+                count
+            );
+        }
+        assert!(
+            out_buffer.size() >= DIGEST_BUFFER_SIZE,
+            "digest: out_buffer holds {} bytes, less than the {} bytes of a digest",
+            out_buffer.size(),
+            DIGEST_BUFFER_SIZE
+        );
+        assert!(
+            out_buffer
+                .usage()
+                .contains(BufferUsages::STORAGE | BufferUsages::COPY_DST),
+            "digest: out_buffer needs the STORAGE and COPY_DST usages"
+        );
+
+        encoder.clear_buffer(out_buffer, 0, Some(DIGEST_BUFFER_SIZE));
+        if count == 0 {
+            return;
+        }
+
+        // Keys-only buffers are bound with dummy vals the kernel doesn't touch
+        let dummy_vals;
+        let vals_buffer = match vals {
+            Some(vals) => vals,
+            None => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                &dummy_vals.0
+            }
+        };
+
+        let bind_group = render_device.create_bind_group(
+            "digest: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                keys.as_entire_binding(),
+                vals_buffer.as_entire_binding(),
+                out_buffer.as_entire_binding(),
+            )),
+        );
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("digest compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&count));
+        pass.set_push_constants(
+            DIGEST_VALS_OFFSET,
+            bytemuck::bytes_of(&u32::from(vals.is_some())),
+        );
+        dispatch_workgroup_ext(
+            &mut pass,
+            count.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    /// [`Self::record_digest`] of the global buffers holding the result of [`crate::run`] over `pass_range` with
+    /// the same `read_from_even`, the vals are digested unless the sort is keys-only.
+    ///
+    /// Returns `false` without recording anything if the global buffers haven't been prepared yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_digest_sorted(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        count: u32,
+        out_buffer: &Buffer,
+        max_compute_workgroups_per_dimension: u32,
+    ) -> bool {
+        let Some(keys) = sorted_keys_buffer(sbufs, pass_range, read_from_even) else {
+            return false;
+        };
+        let vals = sorted_vals_buffer(sbufs, pass_range, read_from_even);
+
+        self.record_digest(
+            encoder,
+            render_device,
+            pipeline_cache,
+            keys,
+            vals,
+            count,
+            out_buffer,
+            max_compute_workgroups_per_dimension,
+        );
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+    };
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    /// The GPU digests of each `(keys, vals)`.
+    fn gpu_digests(inputs: Vec<(Vec<u32>, Option<Vec<u32>>)>) -> Vec<SortDigest> {
+        let mut app = create_render_test_app();
+        app.add_plugins(DigestPlugin);
+
+        let digests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let output = digests.clone();
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  digest_pipeline: Res<DigestPipeline>| {
+                let out_buffer = create_storage_buffer(&render_device, &[0xDEADBEEF; 4]);
+                for (keys, vals) in &inputs {
+                    // Never empty, a zero-sized binding is invalid
+                    let keys_buffer =
+                        create_storage_buffer(&render_device, &[keys.as_slice(), &[0]].concat());
+                    let vals_buffer = vals.as_ref().map(|vals| {
+                        create_storage_buffer(&render_device, &[vals.as_slice(), &[0]].concat())
+                    });
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: digest command encoder"),
+                        });
+                    digest_pipeline.record_digest(
+                        &mut encoder,
+                        &render_device,
+                        &pipeline_cache,
+                        &keys_buffer,
+                        vals_buffer.as_ref(),
+                        keys.len() as u32,
+                        &out_buffer,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let words = read_buffer(&render_device, &render_queue, &out_buffer, 4);
+                    output.lock().unwrap().push(SortDigest::from_words(&words));
+                }
+            },
+        );
+
+        std::mem::take(&mut *digests.lock().unwrap())
+    }
+
+    #[test]
+    fn test_digest_order_sensitive() {
+        let keys: Vec<u32> = (0..10_000).collect();
+        let mut swapped = keys.clone();
+        swapped.swap(17, 9_000);
+        let vals: Vec<u32> = (0..10_000).rev().collect();
+
+        let digests = gpu_digests(vec![
+            (keys.clone(), Some(vals.clone())),
+            (swapped, Some(vals.clone())),
+            (keys.clone(), None),
+            (vals, Some(keys)),
+            (Vec::new(), None),
+        ]);
+
+        assert_ne!(digests[0], digests[1]);
+        // The keys words don't depend on the vals
+        assert_eq!(digests[0].keys_sum, digests[2].keys_sum);
+        assert_eq!(digests[0].keys_xor, digests[2].keys_xor);
+        assert_eq!((digests[2].vals_sum, digests[2].vals_xor), (0, 0));
+        // The keys and the vals are hashed with their own seeds
+        assert_ne!(
+            (digests[0].keys_sum, digests[0].vals_sum),
+            (digests[3].vals_sum, digests[3].keys_sum)
+        );
+        assert_eq!(digests[4], SortDigest::default());
+    }
+
+    #[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_digest_matches_cpu() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(189);
+        let inputs: Vec<(Vec<u32>, Option<Vec<u32>>)> = [1, 255, 256, 257, 10_000, 1_000_003]
+            .into_iter()
+            .map(|count| {
+                let keys = (0..count).map(|_| rng.r#gen()).collect();
+                let vals = (count % 2 == 1).then(|| (0..count).map(|_| rng.r#gen()).collect());
+                (keys, vals)
+            })
+            .collect();
+
+        let digests = gpu_digests(inputs.clone());
+        for ((keys, vals), digest) in inputs.iter().zip(digests) {
+            assert_eq!(
+                digest,
+                crate::digest_on_cpu(keys, vals.as_deref()),
+                "{} keys",
+                keys.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let digest = SortDigest {
+            keys_sum: 0x1,
+            keys_xor: 0xabcdef,
+            vals_sum: 0,
+            vals_xor: u32::MAX,
+        };
+        assert_eq!(digest.to_string(), "0000000100abcdef00000000ffffffff");
+        assert_eq!(SortDigest::from_words(&[1, 0xabcdef, 0, u32::MAX]), digest);
+    }
+}
@@ -0,0 +1,73 @@
+// The digest of `digest.rs`: each element is hashed with its position, the hashes are folded by a wrapping sum and
+// a xor, reduced in shared memory first. Must match `digest_on_cpu`.
+
+/// The keys digested
+@group(0) @binding(0) var<storage, read      > keys: array<u32>;
+/// The vals digested, unless `digest_vals` is 0
+@group(0) @binding(1) var<storage, read      > vals: array<u32>;
+/// `[keys_sum, keys_xor, vals_sum, vals_xor]`, cleared before the pass
+@group(0) @binding(2) var<storage, read_write> digest: array<atomic<u32>, 4>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys digested.
+    number_of_keys: u32,
+    /// 1 if the vals are digested with the keys, 0 for keys-only buffers.
+    digest_vals: u32,
+}
+var<push_constant> pc: PushConstants;
+
+/// The seeds of the position hashes of the keys and the vals, `DIGEST_KEYS_SEED` and `DIGEST_VALS_SEED`.
+const KEYS_SEED: u32 = 0x243f6a88u;
+const VALS_SEED: u32 = 0x85a308d3u;
+
+/// The finalizer of MurmurHash3.
+fn mix(value: u32) -> u32 {
+    var x = value;
+    x ^= x >> 16u;
+    x *= 0x85ebca6bu;
+    x ^= x >> 13u;
+    x *= 0xc2b2ae35u;
+    x ^= x >> 16u;
+    return x;
+}
+
+fn element_hash(value: u32, index: u32, seed: u32) -> u32 {
+    return mix(value ^ mix(index ^ seed));
+}
+
+var<workgroup> local_digest: array<atomic<u32>, 4>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    if local_invocation_index < 4u {
+        atomicStore(&local_digest[local_invocation_index], 0u);
+    }
+    workgroupBarrier();
+
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_index;
+    if index < pc.number_of_keys {
+        let key_hash = element_hash(keys[index], index, KEYS_SEED);
+        atomicAdd(&local_digest[0], key_hash);
+        atomicXor(&local_digest[1], key_hash);
+        if pc.digest_vals != 0u {
+            let val_hash = element_hash(vals[index], index, VALS_SEED);
+            atomicAdd(&local_digest[2], val_hash);
+            atomicXor(&local_digest[3], val_hash);
+        }
+    }
+    workgroupBarrier();
+
+    if local_invocation_index == 0u {
+        atomicAdd(&digest[0], atomicLoad(&local_digest[0]));
+        atomicXor(&digest[1], atomicLoad(&local_digest[1]));
+        atomicAdd(&digest[2], atomicLoad(&local_digest[2]));
+        atomicXor(&digest[3], atomicLoad(&local_digest[3]));
+    }
+}
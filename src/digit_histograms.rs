@@ -0,0 +1,519 @@
+//! [`run_with_digit_histograms`]: a sort snapshotting the global histogram of each of its passes.
+//!
+//! The count and the block-wise scan of a pass leave the total count of each radix of the digit in the last block of
+//! the global blocks buffer, which the next pass overwrites. The sort is split into two compute passes per digit so
+//! the counts can be copied out in between:
+//!
+//! ```text
+//!  for each pass d:  [count, scan blocks] ─▶ copy last block ─▶ histograms[d] ─▶ [scan last block, scatter]
+//! ```
+//!
+//! The histograms buffer holds [`NUMBER_OF_RADIX`] `u32` counts per digit, digit-major: the count of the radix `r`
+//! of the digit `d` (0 is the least significant byte) is at index `d * NUMBER_OF_RADIX + r`, see
+//! [`digit_histogram_offset`]. The digits outside the `pass_range` aren't written.
+//!
+//! The histograms don't depend on the order of the keys, [`DigitHistogramsPipeline::record_digit_histograms`]
+//! computes them all in a single sweep over the keys instead, each key counted in the histogram of every digit of the
+//! range, e.g. before the sort to pick its passes:
+//!
+//! ```text
+//!  keys ─▶ [fused count, one read] ─▶ histograms[pass_range]
+//! ```
+//!
+//! [`crate::run`] records the same fused count before its passes, into the end of the global blocks buffer, and the
+//! scatter of each pass reads where each radix starts from the scanned histogram of its digit instead of scanning
+//! the last block. It still counts the keys at each pass: its scatter needs the counts of each tile in the order of
+//! the keys of the pass, the global histograms alone don't place the keys.
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    Algorithm, HISTOGRAM_BUFFER_SIZE, LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_RADIX,
+    RadixSortBindGroup, RadixSortPipeline, SortPipelines, compute_pipelines_load_state,
+    dispatch_workgroup_ext, global_blocks_buffer, global_keys_buffer, is_input_even,
+    load_keys_shader, passes_needed, record_count_step, record_scan_blocks_step,
+    record_scan_last_block_step, record_scatter_step, run, workgroups_for,
+};
+
+pub const DIGIT_HISTOGRAMS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(187402490161330698899025297468613016094);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const PASS_START_OFFSET: u32 = 8;
+const PASS_END_OFFSET: u32 = 12;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..16,
+};
+
+/// The size in bytes of the histograms buffer of [`run_with_digit_histograms`], one histogram per digit of a key.
+pub const DIGIT_HISTOGRAMS_BUFFER_SIZE: BufferAddress =
+    passes_needed(32) as BufferAddress * HISTOGRAM_BUFFER_SIZE;
+
+/// The offset in bytes of the histogram of `digit` in the histograms buffer of [`run_with_digit_histograms`].
+pub const fn digit_histogram_offset(digit: u32) -> BufferAddress {
+    digit as BufferAddress * HISTOGRAM_BUFFER_SIZE
+}
+
+/// [`crate::run`] on the global buffers, copying the [`NUMBER_OF_RADIX`] counts of the digit of each pass into
+/// `histograms`, see the module docs for the layout.
+///
+/// `histograms` must hold at least [`DIGIT_HISTOGRAMS_BUFFER_SIZE`] bytes and have the `COPY_DST` usage. The sorts of
+/// fewer than 2 keys don't write them, like [`crate::run`] doesn't sort them. With [`Algorithm::Bitonic`] active
+/// there are no histograms: the sort runs and the digits of the `pass_range` are cleared.
+#[allow(clippy::too_many_arguments)]
+#[track_caller]
+pub fn run_with_digit_histograms(
+    encoder: &mut CommandEncoder,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+    histograms: &Buffer,
+) {
+    if radix_sort_pipeline.active_algorithm(pipeline_cache) != Algorithm::Radix {
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_range.clone(),
+            init_index,
+            read_from_even,
+        );
+        for digit in pass_range {
+            encoder.clear_buffer(
+                histograms,
+                digit_histogram_offset(digit),
+                Some(HISTOGRAM_BUFFER_SIZE),
+            );
+        }
+        return;
+    }
+
+    if init_index && !radix_sort_pipeline.allocate_values() {
+        error!(
+            "radix_sort: init_index sorts key/value pairs, but the vals buffers aren't allocated (RadixSortSettings::without_values)"
+        );
+        return;
+    }
+
+    if number_of_keys < 2 {
+        return;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span =
+        tracing::info_span!("radix_sort::run_with_digit_histograms", number_of_keys).entered();
+
+    radix_sort_pipeline.counters().record(number_of_keys);
+    // Logged, the sort is recorded anyway
+    let _ = radix_sort_pipeline.acquire();
+
+    let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+    let global_blocks_buf = global_blocks_buffer(sbufs).unwrap();
+    let last_block_offset = ((workgroups_for(number_of_keys) - 1)
+        * NUMBER_OF_RADIX
+        * NUMBER_OF_BYTES_PER_KEY) as BufferAddress;
+
+    for pass_index in pass_range.clone() {
+        let bind_group = radix_bind_group.bind_group(is_input_even(pass_index, read_from_even));
+
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("radix_sort digit histogram compute pass"),
+                ..default()
+            });
+            record_count_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                pass_index,
+            );
+            record_scan_blocks_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(
+            global_blocks_buf,
+            last_block_offset,
+            histograms,
+            digit_histogram_offset(pass_index),
+            HISTOGRAM_BUFFER_SIZE,
+        );
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("radix_sort digit scatter compute pass"),
+            ..default()
+        });
+        record_scan_last_block_step(&mut pass, &pipelines, bind_group, number_of_keys);
+        record_scatter_step(
+            &mut pass,
+            &pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_index,
+            init_index && pass_index == pass_range.start,
+        );
+    }
+}
+
+pub struct DigitHistogramsPlugin;
+
+impl Plugin for DigitHistogramsPlugin {
+    fn build(&self, app: &mut App) {
+        load_keys_shader(app);
+        load_internal_asset!(
+            app,
+            DIGIT_HISTOGRAMS_SHADER_HANDLE,
+            "digit_histograms.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<DigitHistogramsPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct DigitHistogramsPipeline {
+    /// Count every digit of the range of each key of a tile, then add the tile counts to the histograms
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > keys_i: array<u32>;
+    /// @binding(1) var<storage, read_write> histograms: array<atomic<u32>>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for DigitHistogramsPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "digit_histograms bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("digit_histograms: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: DIGIT_HISTOGRAMS_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl DigitHistogramsPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[("digit_histograms pipeline", self.pipeline)],
+        )
+    }
+
+    /// `histograms` must hold at least [`DIGIT_HISTOGRAMS_BUFFER_SIZE`] bytes and have the `COPY_DST` usage.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        keys: &Buffer,
+        histograms: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "digit_histograms: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                keys.as_entire_binding(),
+                histograms.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group counting the keys in the global buffer [`crate::run`] over `pass_range` with the same
+    /// `read_from_even` reads first, the histograms are then the ones [`run_with_digit_histograms`] snapshots.
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    pub fn create_input_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        histograms: &Buffer,
+    ) -> Option<BindGroup> {
+        let keys = global_keys_buffer(sbufs, is_input_even(pass_range.start, read_from_even))?;
+
+        Some(self.create_bind_group(render_device, keys, histograms))
+    }
+
+    /// Writes the histograms of the digits of `pass_range` of the first `number_of_keys` keys into `histograms`,
+    /// reading each key once, see the module docs for the layout.
+    ///
+    /// `histograms` must be the one of the bind group. The digits outside the `pass_range` aren't written.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_digit_histograms(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        histograms: &Buffer,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        pass_range: Range<u32>,
+    ) {
+        if pass_range.is_empty() {
+            return;
+        }
+
+        encoder.clear_buffer(
+            histograms,
+            digit_histogram_offset(pass_range.start),
+            Some(digit_histogram_offset(pass_range.end) - digit_histogram_offset(pass_range.start)),
+        );
+
+        if number_of_keys == 0 {
+            return;
+        }
+
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("digit_histograms compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(PASS_START_OFFSET, bytemuck::bytes_of(&pass_range.start));
+        pass.set_push_constants(PASS_END_OFFSET, bytemuck::bytes_of(&pass_range.end));
+        dispatch_workgroup_ext(
+            &mut pass,
+            workgroups_for(number_of_keys),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+    };
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, global_keys_buffer, sorted_keys_buffer,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    fn cpu_digit_histograms(keys: &[u32]) -> Vec<u32> {
+        let mut histograms = vec![0; (passes_needed(32) * NUMBER_OF_RADIX) as usize];
+        for key in keys {
+            for digit in 0..passes_needed(32) {
+                let radix = (key >> (digit * 8)) & (NUMBER_OF_RADIX - 1);
+                histograms[(digit * NUMBER_OF_RADIX + radix) as usize] += 1;
+            }
+        }
+
+        histograms
+    }
+
+    #[test]
+    fn test_fused_digit_histograms() {
+        let number_of_keys = 100_003;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(DigitHistogramsPlugin);
+
+        let mut rng = StdRng::seed_from_u64(160);
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|_| rng.r#gen::<u32>() >> rng.gen_range(0..12))
+            .collect();
+        let expected = cpu_digit_histograms(&keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  digit_histograms_pipeline: Res<DigitHistogramsPipeline>| {
+                let len = (DIGIT_HISTOGRAMS_BUFFER_SIZE / 4) as usize;
+                let keys_buf = create_storage_buffer(&render_device, &keys);
+
+                for pass_range in [0..4, 1..3, 3..4] {
+                    let histograms = create_storage_buffer(&render_device, &vec![u32::MAX; len]);
+                    let bind_group = digit_histograms_pipeline.create_bind_group(
+                        &render_device,
+                        &keys_buf,
+                        &histograms,
+                    );
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: fused digit histograms command encoder"),
+                        });
+                    digit_histograms_pipeline.record_digit_histograms(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &bind_group,
+                        &histograms,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        pass_range.clone(),
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let output = read_buffer(&render_device, &render_queue, &histograms, len);
+                    for digit in 0..passes_needed(32) {
+                        let range = (digit * NUMBER_OF_RADIX) as usize
+                            ..((digit + 1) * NUMBER_OF_RADIX) as usize;
+                        if pass_range.contains(&digit) {
+                            assert_eq!(output[range.clone()], expected[range], "digit {digit}");
+                        } else {
+                            assert!(output[range].iter().all(|&count| count == u32::MAX));
+                        }
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_digit_histograms() {
+        let number_of_keys = 70_001;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            });
+
+        let mut rng = StdRng::seed_from_u64(156);
+        // Skewed digits, the high byte mostly 0
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|_| rng.r#gen::<u32>() >> rng.gen_range(0..12))
+            .collect();
+        let expected = cpu_digit_histograms(&keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let len = (DIGIT_HISTOGRAMS_BUFFER_SIZE / 4) as usize;
+
+                for pass_range in [0..4, 0..2] {
+                    let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+                    let histograms = create_storage_buffer(&render_device, &vec![u32::MAX; len]);
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: digit histograms command encoder"),
+                        });
+                    run_with_digit_histograms(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        pass_range.clone(),
+                        true,
+                        true,
+                        &histograms,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let output = read_buffer(&render_device, &render_queue, &histograms, len);
+                    for digit in 0..passes_needed(32) {
+                        let range = (digit * NUMBER_OF_RADIX) as usize
+                            ..((digit + 1) * NUMBER_OF_RADIX) as usize;
+                        if pass_range.contains(&digit) {
+                            assert_eq!(output[range.clone()], expected[range], "digit {digit}");
+                        } else {
+                            assert!(output[range].iter().all(|&count| count == u32::MAX));
+                        }
+                    }
+
+                    // The split passes still sort
+                    let output_keys_buf = sorted_keys_buffer(&sbufs, &pass_range, true).unwrap();
+                    let output_keys =
+                        read_buffer(&render_device, &render_queue, output_keys_buf, keys.len());
+                    let mut expected_keys = keys.clone();
+                    expected_keys
+                        .sort_by_key(|key| key & ((1u64 << (8 * pass_range.end)) - 1) as u32);
+                    assert_eq!(output_keys, expected_keys, "{pass_range:?}");
+                }
+            },
+        );
+    }
+}
@@ -0,0 +1,54 @@
+#import bevy_radix_sort::keys
+
+/// The keys to count, in any order
+@group(0) @binding(0) var<storage, read      > keys_i: array<u32>;
+/// `NUMBER_OF_RADIX` counts per digit, digit-major, the digits of the range cleared before the pass
+@group(0) @binding(1) var<storage, read_write> histograms: array<atomic<u32>>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys.
+    number_of_keys: u32,
+    /// The digits counted, `pass_start..pass_end`.
+    pass_start: u32,
+    pass_end: u32,
+}
+var<push_constant> pc: PushConstants;
+
+var<workgroup> local_histograms: array<atomic<u32>, keys::NUMBER_OF_PASSES * keys::NUMBER_OF_RADIX>;
+
+/// One tile of the sort per workgroup, every key read once for all the digits.
+@compute @workgroup_size(keys::NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    for (var i = local_invocation_index; i < keys::NUMBER_OF_PASSES * keys::NUMBER_OF_RADIX; i += keys::NUMBER_OF_THREADS_PER_WORKGROUP) {
+        atomicStore(&local_histograms[i], 0u);
+    }
+    workgroupBarrier();
+
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let start_index = workgroup_index * keys::NUMBER_OF_KEYS_PER_SCATTER_BLOCK + local_invocation_index;
+    let close_index = min(start_index + keys::NUMBER_OF_KEYS_PER_SCATTER_BLOCK, pc.number_of_keys);
+    for (var key_index = start_index; key_index < close_index; key_index += keys::NUMBER_OF_THREADS_PER_WORKGROUP) {
+        let key = keys_i[key_index];
+        for (var digit = pc.pass_start; digit < pc.pass_end; digit++) {
+            atomicAdd(&local_histograms[digit * keys::NUMBER_OF_RADIX + keys::extract_digit(key, digit)], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    // One atomic per workgroup and non-empty radix
+    for (var digit = pc.pass_start; digit < pc.pass_end; digit++) {
+        for (var radix = local_invocation_index; radix < keys::NUMBER_OF_RADIX; radix += keys::NUMBER_OF_THREADS_PER_WORKGROUP) {
+            let index = digit * keys::NUMBER_OF_RADIX + radix;
+            let count = atomicLoad(&local_histograms[index]);
+            if count > 0u {
+                atomicAdd(&histograms[index], count);
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Sorting the entities with a [`SortKey`] into a GPU buffer of their indices in key order, for GPU-driven
+//! rendering.
+//!
+//! ```text
+//!  Extract:          (Entity, SortKey) ─▶ dense indices 0..n, in query order
+//!  PrepareResources: the keys uploaded into the buffers of the plugin, a SortJob argsorting them
+//!  Render:           RadixSortJobsNode ─▶ SortedEntityIndices::buffer, the dense indices in key order
+//! ```
+//!
+//! The dense indices are assigned again every frame, [`SortedEntityIndices::entity`] maps them back to the main
+//! world entities. The sort is stable: entities of equal keys keep their query order.
+//!
+//! The entities are a snapshot of the extraction. An entity despawned after it still has its index for the frame,
+//! the main world may not know its [`MainEntity`] anymore: look it up with `Query::get`, not expecting it to exist.
+//! An entity spawned or given a [`SortKey`] after it is sorted from the next frame on.
+
+use bevy::{
+    prelude::*,
+    render::{
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+        render_resource::{Buffer, BufferAddress, BufferDescriptor, BufferUsages},
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+    },
+};
+
+use crate::{
+    NUMBER_OF_BYTES_PER_KEY, RadixSortBindGroup, RadixSortJobs, RadixSortPipeline, SortJob,
+    SortJobBuffers, SortJobId, blocks_buffer_size, radix_sort_loaded,
+};
+
+/// The key an entity is sorted by, see the [module docs](self).
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[reflect(Component, Debug, Default, PartialEq, Hash)]
+pub struct SortKey(pub u32);
+
+/// Sorts the entities with a [`SortKey`] every frame into the [`SortedEntityIndices`] of the render world.
+/// Requires the [`crate::RadixSortPlugin`], with u32 keys and the vals allocated: the sort is an argsort of buffers of
+/// the plugin, not bounded by the capacity of the global buffers.
+pub struct EntitySortPlugin;
+
+impl Plugin for EntitySortPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SortKey>();
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<ExtractedSortKeys>()
+            .init_resource::<SortedEntityIndices>()
+            .add_systems(ExtractSchedule, extract_sort_keys)
+            .add_systems(
+                Render,
+                queue_entity_sort
+                    .in_set(RenderSet::PrepareResources)
+                    .run_if(resource_exists::<RadixSortPipeline>),
+            );
+    }
+}
+
+/// The keys extracted this frame, along the entities they belong to.
+#[derive(Resource, Debug, Default)]
+struct ExtractedSortKeys {
+    entities: Vec<MainEntity>,
+    keys: Vec<u32>,
+}
+
+fn extract_sort_keys(
+    mut extracted: ResMut<ExtractedSortKeys>,
+    query: Extract<Query<(Entity, &SortKey)>>,
+) {
+    extracted.entities.clear();
+    extracted.keys.clear();
+    for (entity, key) in &query {
+        extracted.entities.push(entity.into());
+        extracted.keys.push(key.0);
+    }
+}
+
+/// The entities of the frame sorted by their [`SortKey`], in the render world.
+///
+/// The [`Self::buffer`] is written by the [`crate::RadixSortJobsNode`], before the cameras: bind it in
+/// [`RenderSet::PrepareBindGroups`] or later, e.g. as an instance-rate vertex buffer or a read-only storage buffer of
+/// a custom phase. It is reallocated when the entities outgrow it, so compare its id to rebuild the bind groups.
+///
+/// The sort job has the top priority, a [`crate::RadixSortJobsConfig`] budget still defers it after other jobs of
+/// the top priority queued before.
+#[derive(Resource, Debug, Default)]
+pub struct SortedEntityIndices {
+    buffers: Option<EntitySortBuffers>,
+    entities: Vec<MainEntity>,
+    /// The vals of the side the sort ends on
+    output_even: bool,
+    job: Option<SortJobId>,
+}
+
+/// The buffers sorted, with the capacity of the keys.
+#[derive(Debug)]
+struct EntitySortBuffers {
+    capacity: u32,
+    eve_vals: Buffer,
+    odd_vals: Buffer,
+    eve_keys: Buffer,
+    bind_group: RadixSortBindGroup,
+}
+
+impl EntitySortBuffers {
+    fn new(
+        render_device: &RenderDevice,
+        radix_sort_pipeline: &RadixSortPipeline,
+        capacity: u32,
+    ) -> Self {
+        let create_buffer = |label, usage| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: capacity as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let indices_usage = BufferUsages::COPY_SRC | BufferUsages::VERTEX;
+
+        let eve_keys = create_buffer("entity_sort: eve keys buffer", BufferUsages::empty());
+        let odd_keys = create_buffer("entity_sort: odd keys buffer", BufferUsages::empty());
+        let eve_vals = create_buffer("entity_sort: eve indices buffer", indices_usage);
+        let odd_vals = create_buffer("entity_sort: odd indices buffer", indices_usage);
+        let blocks = render_device.create_buffer(&BufferDescriptor {
+            label: Some("entity_sort: blocks buffer"),
+            size: blocks_buffer_size(capacity),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group = RadixSortBindGroup::from_buffers(
+            render_device,
+            radix_sort_pipeline.bind_group_layout(),
+            &eve_keys,
+            &eve_vals,
+            &blocks,
+            &odd_keys,
+            &odd_vals,
+        );
+
+        Self {
+            capacity,
+            eve_vals,
+            odd_vals,
+            eve_keys,
+            bind_group,
+        }
+    }
+}
+
+impl SortedEntityIndices {
+    /// The dense indices of the entities in key order, [`Self::len`] of them. `None` until the sort first runs.
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffers.as_ref().map(|buffers| {
+            if self.output_even {
+                &buffers.eve_vals
+            } else {
+                &buffers.odd_vals
+            }
+        })
+    }
+
+    /// The number of entities sorted this frame, 0 while the sort isn't loaded.
+    pub fn len(&self) -> u32 {
+        self.entities.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The entity of a dense index of the [`Self::buffer`], which may have been despawned since the extraction.
+    pub fn entity(&self, index: u32) -> Option<MainEntity> {
+        self.entities.get(index as usize).copied()
+    }
+
+    /// The entities by dense index.
+    pub fn entities(&self) -> &[MainEntity] {
+        &self.entities
+    }
+
+    /// The job sorting the entities this frame, `None` for fewer than 2 entities.
+    pub fn job(&self) -> Option<SortJobId> {
+        self.job
+    }
+
+    /// Uploads `keys` and queues their argsort into the [`Self::buffer`], the keys of `entities` in the order of
+    /// their dense indices. Returns the entities of the previous frame, to reuse their allocation.
+    pub(crate) fn queue(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        radix_sort_pipeline: &RadixSortPipeline,
+        jobs: &mut RadixSortJobs,
+        entities: Vec<MainEntity>,
+        keys: &[u32],
+    ) -> Vec<MainEntity> {
+        debug_assert_eq!(entities.len(), keys.len());
+
+        self.job = None;
+        let previous = std::mem::replace(&mut self.entities, entities);
+        let count = self.len();
+        if self
+            .buffers
+            .as_ref()
+            .is_none_or(|buffers| buffers.capacity < count)
+        {
+            let capacity = count.max(1).next_power_of_two();
+            self.buffers = Some(EntitySortBuffers::new(
+                render_device,
+                radix_sort_pipeline,
+                capacity,
+            ));
+        }
+        let buffers = self.buffers.as_ref().unwrap();
+        render_queue.write_buffer(&buffers.eve_keys, 0, bytemuck::cast_slice(keys));
+
+        let job = SortJob::new(count)
+            .with_buffers(SortJobBuffers::Custom(buffers.bind_group.clone()))
+            .with_priority(u8::MAX);
+        let output_even = job.output_even();
+        if count < 2 {
+            // The sort doesn't write the indices of fewer than 2 keys
+            let output = if output_even {
+                &buffers.eve_vals
+            } else {
+                &buffers.odd_vals
+            };
+            let indices: Vec<u32> = (0..count).collect();
+            render_queue.write_buffer(output, 0, bytemuck::cast_slice(&indices));
+        } else {
+            self.job = Some(jobs.push(job));
+        }
+        self.output_even = output_even;
+
+        previous
+    }
+
+    /// Sorts no entity this frame, while the sort isn't loaded. Returns the entities of the previous frame.
+    pub(crate) fn clear(&mut self) -> Vec<MainEntity> {
+        self.job = None;
+        let mut previous = std::mem::take(&mut self.entities);
+        previous.clear();
+
+        previous
+    }
+}
+
+fn queue_entity_sort(world: &mut World) {
+    let loaded = radix_sort_loaded()(world);
+    let mut extracted = world.resource_mut::<ExtractedSortKeys>();
+    let entities = std::mem::take(&mut extracted.entities);
+    let keys = std::mem::take(&mut extracted.keys);
+
+    world.resource_scope(|world, mut sorted: Mut<SortedEntityIndices>| {
+        let previous = if loaded {
+            world.resource_scope(|world, mut jobs: Mut<RadixSortJobs>| {
+                sorted.queue(
+                    world.resource::<RenderDevice>(),
+                    world.resource::<RenderQueue>(),
+                    world.resource::<RadixSortPipeline>(),
+                    &mut jobs,
+                    entities,
+                    &keys,
+                )
+            })
+        } else {
+            sorted.clear()
+        };
+
+        // Handed back for the next extraction, keeping the allocations
+        let mut extracted = world.resource_mut::<ExtractedSortKeys>();
+        extracted.entities = previous;
+        extracted.keys = keys;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, read_buffer, run_once},
+    };
+
+    use super::*;
+
+    fn create_entity_sort_test_app() -> App {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            })
+            .add_plugins(EntitySortPlugin);
+
+        app
+    }
+
+    /// The entities in the order of the sorted buffer.
+    fn sorted_entities(app: &App) -> Vec<Entity> {
+        let render_world = app.sub_app(RenderApp).world();
+        let sorted = render_world.resource::<SortedEntityIndices>();
+        let indices = read_buffer(
+            render_world.resource::<RenderDevice>(),
+            render_world.resource::<RenderQueue>(),
+            sorted.buffer().unwrap(),
+            sorted.len() as usize,
+        );
+
+        indices
+            .into_iter()
+            .map(|index| sorted.entity(index).unwrap().id())
+            .collect()
+    }
+
+    #[test]
+    fn test_entity_sort() {
+        let mut app = create_entity_sort_test_app();
+        // More entities than the capacity of the global buffers, in buffers of their own
+        let entities: Vec<(Entity, u32)> = (0..3000u32)
+            .map(|i| {
+                let key = i.wrapping_mul(2_654_435_761) % 500;
+                (app.world_mut().spawn(SortKey(key)).id(), key)
+            })
+            .collect();
+        run_once(&mut app);
+        app.update();
+
+        let mut expected = entities.clone();
+        expected.sort_by_key(|&(entity, key)| (key, entity));
+        let sorted = sorted_entities(&app);
+        let keys: Vec<u32> = sorted
+            .iter()
+            .map(|&entity| app.world().get::<SortKey>(entity).unwrap().0)
+            .collect();
+        assert_eq!(sorted.len(), entities.len());
+        assert!(keys.is_sorted());
+        assert_eq!(
+            keys,
+            expected.iter().map(|&(_, key)| key).collect::<Vec<_>>()
+        );
+
+        // The despawned entities are dropped from the next frame
+        for &(entity, _) in &entities[..2990] {
+            app.world_mut().despawn(entity);
+        }
+        app.update();
+        let mut expected: Vec<(u32, Entity)> = entities[2990..]
+            .iter()
+            .map(|&(entity, key)| (key, entity))
+            .collect();
+        expected.sort_by_key(|&(key, _)| key);
+        let sorted = sorted_entities(&app);
+        assert_eq!(
+            sorted
+                .iter()
+                .map(|&entity| app.world().get::<SortKey>(entity).unwrap().0)
+                .collect::<Vec<_>>(),
+            expected.iter().map(|&(key, _)| key).collect::<Vec<_>>()
+        );
+
+        // A single entity isn't sorted
+        for &(entity, _) in &entities[2990..2999] {
+            app.world_mut().despawn(entity);
+        }
+        app.update();
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(render_world.resource::<SortedEntityIndices>().job(), None);
+        assert_eq!(sorted_entities(&app), vec![entities[2999].0]);
+    }
+}
@@ -0,0 +1,367 @@
+//! A user compute pass dispatched over the sorted keys/vals right after the sort, see [`run_with_epilogue`].
+//!
+//! Which side of the global buffers holds the sorted output depends on the pass range and the input side
+//! (see [`crate::sorted_keys_buffer`]), [`run_with_epilogue`] picks it and hands the buffers to the [`Epilogue`]:
+//!
+//! ```text
+//!  run(pass_range, read_from_even) ─▶ sorted_keys/vals ─▶ Epilogue::bind_group ─▶ dispatch(workgroups(count))
+//! ```
+//!
+//! The epilogue pipeline has the push constants of the helper passes, [`EPILOGUE_PUSH_CONSTANT_RANGE`]:
+//!
+//! ```wgsl
+//! struct PushConstants {
+//!     workgroup_offset: u32,
+//!     number_of_keys: u32,
+//! }
+//!
+//! var<push_constant> pc: PushConstants;
+//! ```
+//!
+//! with `workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset` when the
+//! workgroups exceed `max_compute_workgroups_per_dimension`.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, Buffer, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            PipelineCache, PushConstantRange, ShaderStages,
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    RadixSortBindGroup, RadixSortPipeline, dispatch_workgroup_ext, global_keys_buffer,
+    global_vals_buffer, run, sorted_keys_buffer, sorted_vals_buffer,
+};
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+
+/// The push constants of an [`Epilogue`] pipeline, see the module docs.
+pub const EPILOGUE_PUSH_CONSTANT_RANGE: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..8,
+};
+
+/// A compute pass over the output of the sort, recorded by [`run_with_epilogue`].
+pub trait Epilogue {
+    /// The pipeline to dispatch, with [`EPILOGUE_PUSH_CONSTANT_RANGE`].
+    fn pipeline_id(&self) -> CachedComputePipelineId;
+
+    /// The bind group 0 of the pipeline, binding the buffers holding the sorted keys/vals.
+    ///
+    /// The sort is keys-only without the global vals buffers, `sorted_vals` is then the keys buffer.
+    fn bind_group(&self, world: &World, sorted_keys: &Buffer, sorted_vals: &Buffer) -> BindGroup;
+
+    /// The number of workgroups to dispatch over `number_of_keys` sorted keys.
+    fn workgroups(&self, number_of_keys: u32) -> u32;
+}
+
+/// [`crate::run`] on the global buffers, then `epilogue` over the sorted output in the same encoder.
+///
+/// Meant to be called from the `run` of a render graph node, with the render world. Below 2 keys nothing is
+/// sorted and the epilogue reads the input side. Returns `false` if the epilogue pipeline isn't loaded yet,
+/// in which case only the sort is recorded.
+pub fn run_with_epilogue(
+    encoder: &mut CommandEncoder,
+    world: &World,
+    epilogue: &impl Epilogue,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) -> bool {
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+    let max_compute_workgroups_per_dimension = world
+        .resource::<RenderDevice>()
+        .limits()
+        .max_compute_workgroups_per_dimension;
+
+    run(
+        encoder,
+        pipeline_cache,
+        world.resource::<RadixSortPipeline>(),
+        world.resource::<RadixSortBindGroup>(),
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.clone(),
+        init_index,
+        read_from_even,
+    );
+
+    let Some(epilogue_pipeline) = pipeline_cache.get_compute_pipeline(epilogue.pipeline_id())
+    else {
+        return false;
+    };
+
+    // `run` doesn't record anything below 2 keys, the output is still on the input side
+    let (sorted_keys, sorted_vals) = if number_of_keys < 2 {
+        (
+            global_keys_buffer(sbufs, read_from_even),
+            global_vals_buffer(sbufs, read_from_even),
+        )
+    } else {
+        (
+            sorted_keys_buffer(sbufs, &pass_range, read_from_even),
+            sorted_vals_buffer(sbufs, &pass_range, read_from_even),
+        )
+    };
+    let sorted_keys = sorted_keys.unwrap();
+    let bind_group = epilogue.bind_group(world, sorted_keys, sorted_vals.unwrap_or(sorted_keys));
+
+    let number_of_workgroups = epilogue.workgroups(number_of_keys);
+    if number_of_workgroups == 0 {
+        return true;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::epilogue", number_of_keys).entered();
+
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("radix_sort epilogue compute pass"),
+        ..default()
+    });
+
+    pass.set_pipeline(epilogue_pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+    dispatch_workgroup_ext(
+        &mut pass,
+        number_of_workgroups,
+        max_compute_workgroups_per_dimension,
+        WORKGROUP_OFFSET_OFFSET,
+    );
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        RenderApp,
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CommandEncoderDescriptor,
+            ComputePipelineDescriptor,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderQueue,
+    };
+
+    use crate::{
+        RadixSortPlugin,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    const PREFIX_MAX_SHADER_HANDLE: Handle<Shader> =
+        Handle::weak_from_u128(93118095179231358065759631710998043421);
+
+    /// Writes the prefix max of the sorted keys, then of the sorted vals, in a single invocation.
+    const PREFIX_MAX_SHADER: &str = r"
+struct PushConstants {
+    workgroup_offset: u32,
+    number_of_keys: u32,
+}
+
+var<push_constant> pc: PushConstants;
+
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+@group(0) @binding(1) var<storage, read      > sorted_vals: array<u32>;
+@group(0) @binding(2) var<storage, read_write> outputs: array<u32>;
+
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    var max_key = 0u;
+    var max_val = 0u;
+    for (var index = 0u; index < pc.number_of_keys; index++) {
+        max_key = max(max_key, sorted_keys[index]);
+        max_val = max(max_val, sorted_vals[index]);
+        outputs[index] = max_key;
+        outputs[pc.number_of_keys + index] = max_val;
+    }
+}
+";
+
+    struct PrefixMaxPlugin;
+
+    impl Plugin for PrefixMaxPlugin {
+        fn build(&self, app: &mut App) {
+            app.world_mut().resource_mut::<Assets<Shader>>().insert(
+                PREFIX_MAX_SHADER_HANDLE.id(),
+                Shader::from_wgsl(PREFIX_MAX_SHADER, file!()),
+            );
+        }
+
+        fn finish(&self, app: &mut App) {
+            app.sub_app_mut(RenderApp)
+                .init_resource::<PrefixMaxEpilogue>();
+        }
+    }
+
+    #[derive(Resource)]
+    struct PrefixMaxEpilogue {
+        pipeline_id: CachedComputePipelineId,
+        bind_group_layout: BindGroupLayout,
+        outputs: Buffer,
+    }
+
+    impl FromWorld for PrefixMaxEpilogue {
+        fn from_world(world: &mut World) -> Self {
+            let render_device = world.resource::<RenderDevice>();
+            let pipeline_cache = world.resource::<PipelineCache>();
+
+            let bind_group_layout = render_device.create_bind_group_layout(
+                "unit_test: prefix max bindgroup layout",
+                &BindGroupLayoutEntries::sequential(
+                    ShaderStages::COMPUTE,
+                    (
+                        storage_buffer_read_only::<u32>(false),
+                        storage_buffer_read_only::<u32>(false),
+                        storage_buffer::<u32>(false),
+                    ),
+                ),
+            );
+
+            let pipeline_id = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("unit_test: prefix max pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![EPILOGUE_PUSH_CONSTANT_RANGE],
+                shader: PREFIX_MAX_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+            let outputs = create_storage_buffer(render_device, &[0; 2 * 10_003]);
+
+            Self {
+                pipeline_id,
+                bind_group_layout,
+                outputs,
+            }
+        }
+    }
+
+    impl Epilogue for PrefixMaxEpilogue {
+        fn pipeline_id(&self) -> CachedComputePipelineId {
+            self.pipeline_id
+        }
+
+        fn bind_group(
+            &self,
+            world: &World,
+            sorted_keys: &Buffer,
+            sorted_vals: &Buffer,
+        ) -> BindGroup {
+            world.resource::<RenderDevice>().create_bind_group(
+                "unit_test: prefix max bind_group",
+                &self.bind_group_layout,
+                &BindGroupEntries::sequential((
+                    sorted_keys.as_entire_binding(),
+                    sorted_vals.as_entire_binding(),
+                    self.outputs.as_entire_binding(),
+                )),
+            )
+        }
+
+        fn workgroups(&self, number_of_keys: u32) -> u32 {
+            (number_of_keys > 0) as u32
+        }
+    }
+
+    fn prefix_max(values: &[u32]) -> Vec<u32> {
+        values
+            .iter()
+            .scan(0, |max, &value| {
+                *max = value.max(*max);
+                Some(*max)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prefix_max_epilogue() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 10_003.into(),
+        })
+        .add_plugins(PrefixMaxPlugin);
+
+        run_render_system_once(&mut app, |world: &World| {
+            let render_device = world.resource::<RenderDevice>();
+            let render_queue = world.resource::<RenderQueue>();
+            let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+            let prefix_max_epilogue = world.resource::<PrefixMaxEpilogue>();
+
+            for (number_of_keys, pass_range, read_from_even) in [
+                (10_003, 0..4, true),
+                (10_003, 0..3, true),
+                (10_003, 1..4, false),
+                (5_000, 0..1, false),
+                (1, 0..4, true),
+                (1, 0..3, false),
+            ] {
+                let keys: Vec<u32> = (0..number_of_keys)
+                    .map(|i: u32| i.wrapping_mul(2654435761) >> 8)
+                    .collect();
+                render_queue.write_buffer(
+                    global_keys_buffer(sbufs, read_from_even).unwrap(),
+                    0,
+                    bytemuck::cast_slice(&keys),
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: epilogue command encoder"),
+                });
+                assert!(run_with_epilogue(
+                    &mut encoder,
+                    world,
+                    prefix_max_epilogue,
+                    number_of_keys,
+                    pass_range.clone(),
+                    true,
+                    read_from_even,
+                ));
+                render_queue.submit([encoder.finish()]);
+
+                // The only key of a 1-key sort is still on the input side
+                let output_even = if number_of_keys < 2 {
+                    read_from_even
+                } else {
+                    crate::is_output_even(&pass_range, read_from_even)
+                };
+                let read = |buffer| {
+                    read_buffer(render_device, render_queue, buffer, number_of_keys as usize)
+                };
+                let sorted_keys = read(global_keys_buffer(sbufs, output_even).unwrap());
+                let sorted_vals = read(global_vals_buffer(sbufs, output_even).unwrap());
+
+                let mut expected = prefix_max(&sorted_keys);
+                expected.extend(prefix_max(&sorted_vals));
+                let outputs = read_buffer(
+                    render_device,
+                    render_queue,
+                    &prefix_max_epilogue.outputs,
+                    2 * number_of_keys as usize,
+                );
+                assert_eq!(outputs, expected, "{number_of_keys} {pass_range:?}");
+
+                if pass_range.end == 4 && pass_range.start == 0 {
+                    assert!(sorted_keys.is_sorted());
+                    assert_eq!(&outputs[..number_of_keys as usize], &sorted_keys[..]);
+                }
+            }
+        });
+    }
+}
@@ -1,9 +1,17 @@
-use std::ops::Deref;
+use std::{
+    fmt,
+    num::NonZeroU32,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+};
 
 use bevy::{
     prelude::*,
     render::{
         RenderApp,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
         render_resource::{
             BindGroup, BindGroupEntries, BindGroupLayoutEntries, Buffer, BufferDescriptor,
             BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
@@ -11,29 +19,290 @@ use bevy::{
             ShaderModuleDescriptor, ShaderSource, ShaderStages, binding_types::storage_buffer,
         },
         renderer::{RenderDevice, RenderQueue},
+        settings::{WgpuFeatures, WgpuLimits},
     },
 };
 
+use crate::FALLBACK_SUBGROUP_SIZE;
+
 pub const GET_SUBGROUPS_SIZE_SHADER: &str = include_str!("get_subgroup_size.wgsl");
 
-pub struct GetSubgroupSizePlugin;
+/// Resolves the [`SubgroupSize`] of the current adapter.
+///
+/// The adapter limits (`min_subgroup_size`/`max_subgroup_size`) are consulted first;
+/// when they are non-zero and equal the value is used directly.
+/// Otherwise (limits absent or reported as a range) a probe compute shader is dispatched
+/// to find out which size the driver actually picks.
+/// With the `subgroup_size_cache` feature, the probed value is persisted per adapter and reused on the next run.
+///
+/// The resolved [`SubgroupSize`] is inserted into both the main world and the [`RenderApp`] world.
+/// The main world is authoritative: changes made there are extracted into the render world every frame.
+/// The initial value is written to both worlds directly in [`Plugin::finish`], so it doesn't depend on
+/// extraction (or on any window/camera existing) and is available in headless apps as well.
+///
+/// Once the size is resolved a [`SubgroupSizeReady`] event is sent (and triggered for observers)
+/// exactly once in both worlds.
+///
+/// [`crate::RadixSortPlugin`] adds it when it's missing, add it before to configure it. Adding it again is a no-op,
+/// the size is resolved once with the configuration of the last one added before the app finishes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GetSubgroupSizePlugin {
+    /// Always dispatch the probe shader, even if the adapter limits report a fixed subgroup size.
+    pub force_probe: bool,
+}
 
 impl Plugin for GetSubgroupSizePlugin {
-    fn build(&self, _app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        // The instance being built isn't registered yet
+        if app.is_plugin_added::<Self>() {
+            return;
+        }
+
+        app.add_event::<SubgroupSizeReady>()
+            .register_type::<SubgroupSize>()
+            .add_plugins(ExtractResourcePlugin::<SubgroupSize>::default());
+    }
+
+    // Unique plugins panic when added twice, e.g. after the `RadixSortPlugin` added it
+    fn is_unique(&self) -> bool {
+        false
+    }
 
     fn finish(&self, app: &mut App) {
-        let render_app = app.sub_app_mut(RenderApp);
+        // Added before the `RenderPlugin`, its `finish` creates the `RenderDevice` after this one, see `cleanup`
+        let render_device_exists = app
+            .get_sub_app(RenderApp)
+            .is_some_and(|render_app| render_app.world().contains_resource::<RenderDevice>());
+        if render_device_exists {
+            Self::resolve(app);
+        }
+    }
 
-        let render_device = render_app.world().resource::<RenderDevice>();
-        let render_queue = render_app.world().resource::<RenderQueue>();
+    fn cleanup(&self, app: &mut App) {
+        Self::resolve(app);
+    }
+}
 
-        let get_subgroup_size_utils = GetSubgroupSizeUtils::new(render_device);
-        let subgroup_size = get_subgroup_size_utils.get_subgroup_size(render_device, render_queue);
+impl GetSubgroupSizePlugin {
+    /// Inserts the [`SubgroupSize`] into both worlds, unless it's already resolved, with the configuration of the
+    /// last instance added to `app`.
+    pub(crate) fn resolve(app: &mut App) {
+        let config = app
+            .get_added_plugins::<Self>()
+            .last()
+            .copied()
+            .copied()
+            .unwrap_or_default();
+
+        // Only `RenderDevice`/`RenderQueue` are needed, so this works the same in windowless apps.
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            warn!(
+                "GetSubgroupSizePlugin requires the RenderApp sub-app (RenderPlugin), SubgroupSize won't be available"
+            );
+            return;
+        };
+
+        // Already resolved, by the `RadixSortPlugin` or by another instance
+        if render_app.world().contains_resource::<SubgroupSize>() {
+            return;
+        }
+
+        let Some(render_device) = render_app.world().get_resource::<RenderDevice>() else {
+            warn!(
+                "GetSubgroupSizePlugin requires the RenderDevice, SubgroupSize won't be available"
+            );
+            return;
+        };
+        let limits = render_device.limits();
+
+        // The probe blocks on the readback, which the browser doesn't allow
+        let force_probe = config.force_probe && !cfg!(target_arch = "wasm32");
+
+        let subgroup_size = if !subgroups_supported(render_device) {
+            info!(
+                "subgroup_size (no subgroup operations, emulated): {}",
+                SubgroupSize::FALLBACK
+            );
+            SubgroupSize::FALLBACK
+        } else {
+            match subgroup_size_from_limits(&limits) {
+                Some(subgroup_size) if !force_probe => {
+                    info!("subgroup_size (adapter limits): {}", subgroup_size);
+                    subgroup_size
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                _ => probe_subgroup_size(render_app.world_mut()),
+                #[cfg(target_arch = "wasm32")]
+                _ => unreachable!(
+                    "subgroups are only supported on wasm32 when the limits pin their size"
+                ),
+            }
+        };
+
+        #[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+        render_app.add_systems(
+            bevy::render::Render,
+            verify_cached_subgroup_size.run_if(resource_exists::<SubgroupSizeVerification>),
+        );
 
-        info!("subgroup_size: {}", subgroup_size.deref());
+        let ready = SubgroupSizeReady(subgroup_size.into());
+
+        render_app
+            .add_event::<SubgroupSizeReady>()
+            .insert_resource(subgroup_size);
+        render_app.world_mut().send_event(ready);
+        render_app.world_mut().trigger(ready);
 
-        render_app.insert_resource(subgroup_size);
         app.insert_resource(subgroup_size);
+        app.world_mut().send_event(ready);
+        app.world_mut().trigger(ready);
+    }
+}
+
+#[cfg(all(not(feature = "subgroup_size_cache"), not(target_arch = "wasm32")))]
+fn probe_subgroup_size(render_world: &mut World) -> SubgroupSize {
+    let render_device = render_world.resource::<RenderDevice>();
+    let render_queue = render_world.resource::<RenderQueue>();
+
+    let get_subgroup_size_utils = GetSubgroupSizeUtils::new(render_device);
+    let subgroup_size = get_subgroup_size_utils.get_subgroup_size(render_device, render_queue);
+    info!("subgroup_size (probe): {}", subgroup_size);
+
+    subgroup_size
+}
+
+/// On a cache hit the cached value is used immediately and the probe still runs in the background
+/// (see [`verify_cached_subgroup_size`]) to verify and refresh the cache for the next run.
+#[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+fn probe_subgroup_size(render_world: &mut World) -> SubgroupSize {
+    use crate::{SubgroupSizeCache, SubgroupSizeCacheKey};
+    use bevy::render::{render_resource::WgpuAdapterInfo, renderer::RenderAdapterInfo};
+
+    let render_device = render_world.resource::<RenderDevice>();
+    let render_queue = render_world.resource::<RenderQueue>();
+    let adapter_info: &WgpuAdapterInfo = render_world.resource::<RenderAdapterInfo>();
+    let key = SubgroupSizeCacheKey::from(adapter_info);
+
+    let get_subgroup_size_utils = GetSubgroupSizeUtils::new(render_device);
+
+    let Some(cache) = SubgroupSizeCache::in_platform_cache_dir() else {
+        let subgroup_size = get_subgroup_size_utils.get_subgroup_size(render_device, render_queue);
+        info!("subgroup_size (probe): {}", subgroup_size);
+        return subgroup_size;
+    };
+
+    if let Some(cached) = cache.load(&key) {
+        info!("subgroup_size (cache): {}", cached);
+
+        let pending = get_subgroup_size_utils.dispatch(render_device, render_queue);
+        render_world.insert_resource(SubgroupSizeVerification {
+            pending,
+            cached,
+            cache,
+            key,
+        });
+
+        return cached;
+    }
+
+    let subgroup_size = get_subgroup_size_utils.get_subgroup_size(render_device, render_queue);
+    info!("subgroup_size (probe): {}", subgroup_size);
+
+    if let Err(err) = cache.store(&key, subgroup_size) {
+        warn!(
+            "Failed to store subgroup_size in {}: {}",
+            cache.path().display(),
+            err
+        );
+    }
+
+    subgroup_size
+}
+
+#[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+#[derive(Resource)]
+struct SubgroupSizeVerification {
+    pending: PendingSubgroupSize,
+    cached: SubgroupSize,
+    cache: crate::SubgroupSizeCache,
+    key: crate::SubgroupSizeCacheKey,
+}
+
+/// Waits (without blocking) for the background probe started on a cache hit and refreshes the cache.
+///
+/// The pipelines have already been specialized with the cached value,
+/// so a mismatch only takes effect on the next run.
+#[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+fn verify_cached_subgroup_size(
+    mut commands: Commands,
+    verification: Res<SubgroupSizeVerification>,
+    render_device: Res<RenderDevice>,
+) {
+    let _ = render_device.poll(Maintain::Poll);
+
+    let Some(probed) = verification.pending.try_get() else {
+        return;
+    };
+
+    let probed = match probed {
+        Ok(probed) => probed,
+        Err(err) => {
+            warn!("Failed to verify the cached subgroup_size: {}", err);
+            commands.remove_resource::<SubgroupSizeVerification>();
+            return;
+        }
+    };
+
+    if probed != verification.cached {
+        warn!(
+            "Cached subgroup_size {} doesn't match the probed subgroup_size {}, the cache has been refreshed for the next run",
+            verification.cached, probed
+        );
+
+        if let Err(err) = verification.cache.store(&verification.key, probed) {
+            warn!(
+                "Failed to store subgroup_size in {}: {}",
+                verification.cache.path().display(),
+                err
+            );
+        }
+    }
+
+    commands.remove_resource::<SubgroupSizeVerification>();
+}
+
+/// Sent exactly once, in both the main world and the [`RenderApp`] world,
+/// when the [`SubgroupSize`] has been resolved.
+///
+/// Useful to gate the specialization of pipelines depending on the subgroup size
+/// without polling the resource every frame.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubgroupSizeReady(pub u32);
+
+/// Returns `true` if the sort can use the subgroup operations of the adapter,
+/// otherwise they are emulated in shared memory with [`SubgroupSize::FALLBACK`].
+///
+/// Requires [`WgpuFeatures::SUBGROUP`]. On wasm32 the limits must also pin the subgroup size,
+/// as the probe dispatch can't block on its readback in the browser.
+pub fn subgroups_supported(render_device: &RenderDevice) -> bool {
+    if !render_device.features().contains(WgpuFeatures::SUBGROUP) {
+        return false;
+    }
+
+    !cfg!(target_arch = "wasm32") || subgroup_size_from_limits(&render_device.limits()).is_some()
+}
+
+/// Returns the subgroup size if the adapter limits pin it to a single value.
+///
+/// Returns `None` when the limits are zero (not reported by the backend)
+/// or describe a range, in which case only the probe dispatch can tell which size is used.
+pub fn subgroup_size_from_limits(limits: &WgpuLimits) -> Option<SubgroupSize> {
+    let (min, max) = (limits.min_subgroup_size, limits.max_subgroup_size);
+
+    if min == max {
+        SubgroupSize::try_from(min).ok()
+    } else {
+        None
     }
 }
 
@@ -91,11 +360,40 @@ impl GetSubgroupSizeUtils {
         }
     }
 
+    /// Blocks until the probe has been read back, not available on wasm32 (see [`Self::dispatch`]).
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn get_subgroup_size(
         &self,
         render_device: &RenderDevice,
         render_queue: &RenderQueue,
     ) -> SubgroupSize {
+        let pending = self.dispatch(render_device, render_queue);
+        render_device.poll(Maintain::Wait).panic_on_timeout();
+
+        let probed = pending
+            .try_get()
+            .expect("get_subgroup_size staging buffer should be mapped after Maintain::Wait");
+        // The smallest size the adapter runs keeps the lane math of the kernels correct
+        probed.unwrap_or_else(|err| {
+            let fallback = SubgroupSize::try_from(render_device.limits().min_subgroup_size)
+                .unwrap_or(SubgroupSize::FALLBACK);
+            error!(
+                "get_subgroup_size probe failed, falling back to subgroup_size {}: {}",
+                fallback, err
+            );
+            fallback
+        })
+    }
+
+    /// Dispatches the probe and returns without waiting for the readback.
+    ///
+    /// The device has to be polled (e.g. `Maintain::Poll` once per frame) until
+    /// [`PendingSubgroupSize::try_get`] returns the result.
+    pub fn dispatch(
+        &self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) -> PendingSubgroupSize {
         let staging_buf = render_device.create_buffer(&BufferDescriptor {
             label: Some("get_subgroup_size staging buffer"),
             size: 4,
@@ -122,28 +420,391 @@ impl GetSubgroupSizeUtils {
 
         render_queue.submit([encoder.finish()]);
 
+        let state = Arc::new(AtomicU8::new(PendingSubgroupSize::PENDING));
         {
-            let slice = staging_buf.slice(0..4);
-            slice.map_async(MapMode::Read, |_| ());
-            render_device.poll(Maintain::Wait).panic_on_timeout();
-            let subgroup_size: u32 = bytemuck::cast_slice(&slice.get_mapped_range())[0];
-            staging_buf.unmap();
+            let state = state.clone();
+            staging_buf
+                .slice(0..4)
+                .map_async(MapMode::Read, move |result| {
+                    let mapped = match result {
+                        Ok(()) => PendingSubgroupSize::MAPPED,
+                        Err(_) => PendingSubgroupSize::MAP_FAILED,
+                    };
+                    state.store(mapped, Ordering::Release);
+                });
+        }
+
+        PendingSubgroupSize { staging_buf, state }
+    }
+}
+
+/// The in-flight readback of a subgroup size probe, see [`GetSubgroupSizeUtils::dispatch`].
+#[derive(Debug, Clone)]
+pub struct PendingSubgroupSize {
+    staging_buf: Buffer,
+    state: Arc<AtomicU8>,
+}
+
+impl PendingSubgroupSize {
+    const PENDING: u8 = 0;
+    const MAPPED: u8 = 1;
+    const MAP_FAILED: u8 = 2;
+    const TAKEN: u8 = 3;
+
+    /// Returns the probed size once the staging buffer has been mapped, or failed to, `None` before and after.
+    ///
+    /// The inner result is an error if the mapping failed or the driver reported a subgroup size of 0.
+    pub fn try_get(&self) -> Option<Result<SubgroupSize, SubgroupSizeError>> {
+        let state = self
+            .state
+            .fetch_update(Ordering::Acquire, Ordering::Acquire, |state| {
+                matches!(state, Self::MAPPED | Self::MAP_FAILED).then_some(Self::TAKEN)
+            })
+            .ok()?;
+        if state == Self::MAP_FAILED {
+            return Some(Err(SubgroupSizeError::ReadbackFailed));
+        }
+
+        let subgroup_size: u32 =
+            bytemuck::cast_slice(&self.staging_buf.slice(0..4).get_mapped_range())[0];
+        self.staging_buf.unmap();
+
+        Some(SubgroupSize::try_from(subgroup_size))
+    }
+}
+
+/// The number of threads per subgroup of the current adapter.
+///
+/// Available in both the main world and the [`RenderApp`] world, see [`GetSubgroupSizePlugin`].
+///
+/// The value is never zero, but drivers are not guaranteed to report a power of two,
+/// use [`SubgroupSize::log2`] when the math relies on it.
+#[derive(Resource, ExtractResource, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Resource, Debug, PartialEq)]
+pub struct SubgroupSize(NonZeroU32);
+
+impl SubgroupSize {
+    /// The size of the subgroups emulated in shared memory when the adapter has no subgroup operations,
+    /// see [`subgroups_supported`].
+    pub const FALLBACK: Self = Self(NonZeroU32::new(FALLBACK_SUBGROUP_SIZE).unwrap());
+
+    pub const fn new(value: NonZeroU32) -> Self {
+        Self(value)
+    }
+
+    pub const fn get(&self) -> u32 {
+        self.0.get()
+    }
 
-            SubgroupSize(subgroup_size)
+    /// `true` if a subgroup executes 32 threads (Nvidia, recent AMD in wave32 mode, Apple).
+    pub const fn is_wave32(&self) -> bool {
+        self.get() == 32
+    }
+
+    /// `true` if a subgroup executes 64 threads (older AMD, AMD in wave64 mode).
+    pub const fn is_wave64(&self) -> bool {
+        self.get() == 64
+    }
+
+    /// Returns `log2(subgroup_size)`, or an error if the subgroup size is not a power of two.
+    pub const fn log2(&self) -> Result<u32, SubgroupSizeError> {
+        if self.0.is_power_of_two() {
+            Ok(self.0.trailing_zeros())
+        } else {
+            Err(SubgroupSizeError::NotPowerOfTwo(self.get()))
         }
     }
+
+    /// The number of subgroups a workgroup of `number_of_threads` threads is made of.
+    ///
+    /// Used to size the shared memory holding one value per subgroup.
+    pub const fn subgroups_per_workgroup(&self, number_of_threads: u32) -> u32 {
+        number_of_threads.div_ceil(self.get())
+    }
 }
 
-#[derive(Resource, Debug, Clone, Copy, Deref)]
-pub struct SubgroupSize(pub u32);
+impl TryFrom<u32> for SubgroupSize {
+    type Error = SubgroupSizeError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        NonZeroU32::new(value)
+            .map(Self)
+            .ok_or(SubgroupSizeError::Zero)
+    }
+}
 
 impl From<SubgroupSize> for u32 {
     fn from(value: SubgroupSize) -> Self {
-        value.0
+        value.get()
     }
 }
 impl From<&SubgroupSize> for u32 {
     fn from(value: &SubgroupSize) -> Self {
-        value.0
+        value.get()
+    }
+}
+
+impl fmt::Display for SubgroupSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubgroupSizeError {
+    /// The driver reported a subgroup size of 0.
+    Zero,
+    /// The driver reported a subgroup size that is not a power of two.
+    NotPowerOfTwo(u32),
+    /// The staging buffer of the probe couldn't be mapped, e.g. after a device loss.
+    ReadbackFailed,
+}
+
+impl fmt::Display for SubgroupSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero => write!(f, "subgroup size is 0"),
+            Self::NotPowerOfTwo(value) => {
+                write!(f, "subgroup size {} is not a power of two", value)
+            }
+            Self::ReadbackFailed => write!(f, "the subgroup size probe couldn't be read back"),
+        }
+    }
+}
+
+impl std::error::Error for SubgroupSizeError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        RadixSortPipeline, RadixSortPlugin,
+        test_utils::{create_headless_render_test_app, create_render_test_app, run_once},
+    };
+
+    use super::*;
+
+    fn limits(min_subgroup_size: u32, max_subgroup_size: u32) -> WgpuLimits {
+        WgpuLimits {
+            min_subgroup_size,
+            max_subgroup_size,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn test_subgroup_size_from_limits() {
+        assert_eq!(
+            subgroup_size_from_limits(&limits(32, 32)).map(u32::from),
+            Some(32)
+        );
+        assert_eq!(
+            subgroup_size_from_limits(&limits(64, 64)).map(u32::from),
+            Some(64)
+        );
+
+        // Not reported by the backend
+        assert!(subgroup_size_from_limits(&limits(0, 0)).is_none());
+        // A range, the probe has to disambiguate
+        assert!(subgroup_size_from_limits(&limits(32, 64)).is_none());
+        assert!(subgroup_size_from_limits(&limits(4, 128)).is_none());
+    }
+
+    #[test]
+    fn test_subgroup_size_helpers() {
+        let wave32 = SubgroupSize::try_from(32).unwrap();
+        assert!(wave32.is_wave32());
+        assert!(!wave32.is_wave64());
+        assert_eq!(wave32.log2(), Ok(5));
+        assert_eq!(wave32.subgroups_per_workgroup(256), 8);
+        assert_eq!(wave32.to_string(), "32");
+
+        let wave64 = SubgroupSize::try_from(64).unwrap();
+        assert!(!wave64.is_wave32());
+        assert!(wave64.is_wave64());
+        assert_eq!(wave64.log2(), Ok(6));
+        assert_eq!(wave64.subgroups_per_workgroup(256), 4);
+
+        assert_eq!(SubgroupSize::try_from(1).unwrap().log2(), Ok(0));
+        assert_eq!(u32::from(SubgroupSize::try_from(16).unwrap()), 16);
+    }
+
+    #[test]
+    fn test_subgroup_size_rejections() {
+        assert_eq!(SubgroupSize::try_from(0), Err(SubgroupSizeError::Zero));
+
+        let weird = SubgroupSize::try_from(48).unwrap();
+        assert_eq!(weird.log2(), Err(SubgroupSizeError::NotPowerOfTwo(48)));
+        assert_eq!(weird.subgroups_per_workgroup(256), 6);
+    }
+
+    #[test]
+    fn test_forced_probe() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin { force_probe: true });
+        run_once(&mut app);
+
+        let subgroup_size: u32 = app.world().resource::<SubgroupSize>().into();
+        let limits = app
+            .sub_app(RenderApp)
+            .world()
+            .resource::<RenderDevice>()
+            .limits();
+
+        assert!(subgroup_size.is_power_of_two());
+        if limits.min_subgroup_size != 0 {
+            assert!(subgroup_size >= limits.min_subgroup_size);
+            assert!(subgroup_size <= limits.max_subgroup_size);
+        }
+    }
+
+    #[test]
+    fn test_headless_subgroup_size() {
+        let mut app = create_headless_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default());
+        run_once(&mut app);
+
+        assert!(app.world().contains_resource::<SubgroupSize>());
+        assert!(
+            app.sub_app(RenderApp)
+                .world()
+                .contains_resource::<SubgroupSize>()
+        );
+    }
+
+    #[test]
+    fn test_subgroup_size_in_both_worlds() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default());
+        run_once(&mut app);
+
+        let main_world_subgroup_size = *app.world().resource::<SubgroupSize>();
+        let render_world_subgroup_size = *app.sub_app(RenderApp).world().resource::<SubgroupSize>();
+
+        assert_eq!(main_world_subgroup_size, render_world_subgroup_size);
+    }
+
+    #[test]
+    fn test_failed_readback() {
+        let mut app = create_render_test_app();
+        run_once(&mut app);
+
+        let render_device = app.sub_app(RenderApp).world().resource::<RenderDevice>();
+        let pending = PendingSubgroupSize {
+            staging_buf: render_device.create_buffer(&BufferDescriptor {
+                label: Some("get_subgroup_size staging buffer"),
+                size: 4,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            state: Arc::new(AtomicU8::new(PendingSubgroupSize::MAP_FAILED)),
+        };
+
+        // Reported once instead of pending forever
+        assert_eq!(
+            pending.try_get(),
+            Some(Err(SubgroupSizeError::ReadbackFailed))
+        );
+        assert_eq!(pending.try_get(), None);
+    }
+
+    #[derive(Resource, Default)]
+    struct ReadyCount(usize);
+
+    #[test]
+    fn test_subgroup_size_ready_sent_once() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .init_resource::<ReadyCount>()
+            .add_systems(
+                Update,
+                |mut events: EventReader<SubgroupSizeReady>, mut count: ResMut<ReadyCount>| {
+                    count.0 += events.read().count();
+                },
+            );
+
+        run_once(&mut app);
+        for _ in 0..3 {
+            app.update();
+        }
+
+        assert_eq!(app.world().resource::<ReadyCount>().0, 1);
+    }
+
+    fn assert_sort_initialized(app: &App, expected_plugins: usize) {
+        assert_eq!(
+            app.get_added_plugins::<GetSubgroupSizePlugin>().len(),
+            expected_plugins
+        );
+        assert_eq!(app.world().resource::<ReadyCount>().0, 1);
+
+        let render_world = app.sub_app(RenderApp).world();
+        assert!(render_world.contains_resource::<SubgroupSize>());
+        assert!(render_world.contains_resource::<RadixSortPipeline>());
+        assert_eq!(
+            app.world().resource::<SubgroupSize>(),
+            render_world.resource::<SubgroupSize>()
+        );
+    }
+
+    fn count_ready(app: &mut App) {
+        app.init_resource::<ReadyCount>().add_systems(
+            Update,
+            |mut events: EventReader<SubgroupSizeReady>, mut count: ResMut<ReadyCount>| {
+                count.0 += events.read().count();
+            },
+        );
+    }
+
+    #[test]
+    fn test_auto_added_by_radix_sort_plugin() {
+        let mut app = create_render_test_app();
+        count_ready(&mut app);
+        app.add_plugins(RadixSortPlugin {
+            settings: 1024.into(),
+        });
+        run_once(&mut app);
+
+        assert_sort_initialized(&app, 1);
+    }
+
+    #[test]
+    fn test_added_before_radix_sort_plugin() {
+        let mut app = create_render_test_app();
+        count_ready(&mut app);
+        app.add_plugins(GetSubgroupSizePlugin { force_probe: true })
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+
+        assert_sort_initialized(&app, 1);
+        assert!(app.get_added_plugins::<GetSubgroupSizePlugin>()[0].force_probe);
+    }
+
+    #[test]
+    fn test_added_after_radix_sort_plugin() {
+        let mut app = create_render_test_app();
+        count_ready(&mut app);
+        app.add_plugins(RadixSortPlugin {
+            settings: 1024.into(),
+        })
+        .add_plugins(GetSubgroupSizePlugin { force_probe: true });
+        run_once(&mut app);
+
+        assert_sort_initialized(&app, 2);
+    }
+
+    #[test]
+    fn test_added_twice() {
+        let mut app = create_render_test_app();
+        count_ready(&mut app);
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+
+        assert_sort_initialized(&app, 2);
     }
 }
@@ -0,0 +1,494 @@
+//! Group-by over arbitrary sorted keys: the key, the first index and the size of each group of equal keys.
+//!
+//! Unlike [`crate::CellRangesPipeline`], the keys don't have to be dense indices, only the groups present are written:
+//!
+//! ```text
+//!  sorted_keys    [ 7, 7, 90, 90, 90, 4000 ]
+//!  group_keys     [ 7, 90, 4000 ]
+//!  group_offsets  [ 0, 2, 5 ]
+//!  group_counts   [ 2, 3, 1 ]
+//!  group_count    [ 3, 1, 1, 1, 3, 1, 1, 0 ]
+//! ```
+//!
+//! The group heads are marked and counted per block, the block counts are scanned, then the heads are compacted in
+//! order. The `group_count` buffer holds the number of groups followed by two sets of indirect dispatch args, so the
+//! per-group work can be launched with `dispatch_workgroups_indirect` without a readback:
+//!
+//! - at [`GROUP_COUNT_THREADS_INDIRECT_OFFSET`], one thread per group with workgroups of
+//!   [`NUMBER_OF_THREADS_PER_WORKGROUP`] threads,
+//! - at [`GROUP_COUNT_WORKGROUPS_INDIRECT_OFFSET`], one workgroup per group. Past
+//!   `max_compute_workgroups_per_dimension` groups they are split over y like [`crate::dispatch_workgroup_ext`], the
+//!   group of a workgroup is `workgroup_id.y * num_workgroups.x + workgroup_id.x` and the ones past the number of
+//!   groups must return.
+//!
+//! The pass is meant to be recorded in the same encoder right after [`crate::run`].
+
+use std::{num::NonZeroU64, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, BufferDescriptor, BufferUsages, CachedComputePipelineId, CommandEncoder,
+            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, PushConstantRange,
+            ShaderDefVal, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only, storage_buffer_sized},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP,
+    compute_pipelines_load_state, dispatch_workgroup_ext, sorted_keys_buffer,
+};
+
+pub const GROUP_BY_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(154999057398538852383385740853471898978);
+
+/// The size in bytes of the `group_count` buffer:
+/// `[number_of_groups, threads_x, 1, 1, groups_x, groups_y, 1, 0]`.
+///
+/// It should be created with [`BufferUsages::INDIRECT`] to be passed to `dispatch_workgroups_indirect`.
+pub const GROUP_COUNT_BUFFER_SIZE: BufferAddress = 8 * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+/// The offset in bytes of the indirect dispatch args of one thread per group in the `group_count` buffer.
+pub const GROUP_COUNT_THREADS_INDIRECT_OFFSET: BufferAddress =
+    NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+/// The offset in bytes of the indirect dispatch args of one workgroup per group in the `group_count` buffer.
+pub const GROUP_COUNT_WORKGROUPS_INDIRECT_OFFSET: BufferAddress =
+    4 * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const MAX_COMPUTE_WORKGROUPS_PER_DIMENSION_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+/// The size in bytes of each of the `group_keys`, `group_offsets` and `group_counts` buffers, in the worst case of
+/// `number_of_keys` distinct keys.
+pub const fn group_by_buffer_size(number_of_keys: u32) -> BufferAddress {
+    number_of_keys as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress
+}
+
+/// The size in bytes of each of the `group_keys`, `group_offsets` and `group_counts` buffers when the number of
+/// groups is known to be at most `max_number_of_groups`, e.g. the number of distinct ids of the keys.
+///
+/// Only the first `number_of_groups` elements are written, the group-by overflows the buffers if there are more.
+pub const fn group_by_buffer_size_for_groups(
+    number_of_keys: u32,
+    max_number_of_groups: u32,
+) -> BufferAddress {
+    let number_of_groups = if max_number_of_groups < number_of_keys {
+        max_number_of_groups
+    } else {
+        number_of_keys
+    };
+
+    group_by_buffer_size(number_of_groups)
+}
+
+pub struct GroupByPlugin;
+
+impl Plugin for GroupByPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            GROUP_BY_SHADER_HANDLE,
+            "group_by.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<GroupByPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct GroupByPipeline {
+    /// Count the group heads of each block
+    count_heads_pipeline: CachedComputePipelineId,
+    /// Exclusive prefix sum of the group heads of each block, and write the `group_count`
+    scan_blocks_pipeline: CachedComputePipelineId,
+    /// Write the key and the offset of each group, in order
+    compact_heads_pipeline: CachedComputePipelineId,
+    /// Write the size of each group from its tail
+    count_groups_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > sorted_keys: array<u32>;
+    /// @binding(1) var<storage, read_write> group_keys: array<u32>;
+    /// @binding(2) var<storage, read_write> group_offsets: array<u32>;
+    /// @binding(3) var<storage, read_write> group_counts: array<u32>;
+    /// @binding(4) var<storage, read_write> group_count: array<u32, 8>;
+    /// @binding(5) var<storage, read_write> block_offsets: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for GroupByPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "group_by bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer_sized(false, NonZeroU64::new(GROUP_COUNT_BUFFER_SIZE)),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let queue = |label: &'static str, defs: &[&'static str]| {
+            let defs: Vec<ShaderDefVal> = defs.iter().map(|&def| def.into()).collect();
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: GROUP_BY_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), defs.as_slice()].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let count_heads_pipeline =
+            queue("group_by: count_heads pipeline", &["COUNT_HEADS_PIPELINE"]);
+        let scan_blocks_pipeline =
+            queue("group_by: scan_blocks pipeline", &["SCAN_BLOCKS_PIPELINE"]);
+        let compact_heads_pipeline = queue(
+            "group_by: compact_heads pipeline",
+            &["GROUP_INDEX_PIPELINE", "COMPACT_HEADS_PIPELINE"],
+        );
+        let count_groups_pipeline = queue(
+            "group_by: count_groups pipeline",
+            &["GROUP_INDEX_PIPELINE", "COUNT_GROUPS_PIPELINE"],
+        );
+
+        Self {
+            count_heads_pipeline,
+            scan_blocks_pipeline,
+            compact_heads_pipeline,
+            count_groups_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl GroupByPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("group_by count_heads_pipeline", self.count_heads_pipeline),
+                ("group_by scan_blocks_pipeline", self.scan_blocks_pipeline),
+                (
+                    "group_by compact_heads_pipeline",
+                    self.compact_heads_pipeline,
+                ),
+                ("group_by count_groups_pipeline", self.count_groups_pipeline),
+            ],
+        )
+    }
+
+    /// `group_keys`, `group_offsets` and `group_counts` must hold at least [`group_by_buffer_size`] bytes (see
+    /// [`group_by_buffer_size_for_groups`] when the number of groups is bounded), `group_count` at least
+    /// [`GROUP_COUNT_BUFFER_SIZE`] bytes.
+    ///
+    /// The scratch buffer of the block offsets is sized from `sorted_keys` and kept alive by the bind group.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        group_keys: &Buffer,
+        group_offsets: &Buffer,
+        group_counts: &Buffer,
+        group_count: &Buffer,
+    ) -> BindGroup {
+        let max_number_of_keys = (sorted_keys.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+        let max_number_of_blocks = max_number_of_keys
+            .div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP)
+            .max(1);
+        let block_offsets = render_device.create_buffer(&BufferDescriptor {
+            label: Some("group_by: block_offsets buffer"),
+            size: (max_number_of_blocks * NUMBER_OF_BYTES_PER_KEY) as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        render_device.create_bind_group(
+            "group_by: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                group_keys.as_entire_binding(),
+                group_offsets.as_entire_binding(),
+                group_counts.as_entire_binding(),
+                group_count.as_entire_binding(),
+                block_offsets.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group reading the sorted keys from the global buffer holding the result of [`crate::run`]
+    /// over `pass_range` with the same `read_from_even` (see [`crate::sorted_keys_buffer`]).
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_sorted_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        group_keys: &Buffer,
+        group_offsets: &Buffer,
+        group_counts: &Buffer,
+        group_count: &Buffer,
+    ) -> Option<BindGroup> {
+        let sorted_keys = sorted_keys_buffer(sbufs, pass_range, read_from_even)?;
+
+        Some(self.create_bind_group(
+            render_device,
+            sorted_keys,
+            group_keys,
+            group_offsets,
+            group_counts,
+            group_count,
+        ))
+    }
+
+    /// Writes the distinct keys of the first `number_of_keys` sorted keys into `group_keys`, the index of the first
+    /// key of each group into `group_offsets`, the size of each group into `group_counts`, and the number of groups
+    /// with the indirect dispatch args into `group_count`, see the module docs.
+    ///
+    /// Only the first `number_of_groups` elements of the group buffers are written.
+    pub fn record_group_by(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        let count_heads_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_heads_pipeline)
+            .unwrap();
+        let scan_blocks_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_blocks_pipeline)
+            .unwrap();
+        let compact_heads_pipeline = pipeline_cache
+            .get_compute_pipeline(self.compact_heads_pipeline)
+            .unwrap();
+        let count_groups_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_groups_pipeline)
+            .unwrap();
+
+        let number_of_blocks = number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("group_by compute pass"),
+            ..default()
+        });
+
+        // All pipelines share the same layout so the push constants are kept across `set_pipeline`.
+        pass.set_pipeline(scan_blocks_pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(
+            MAX_COMPUTE_WORKGROUPS_PER_DIMENSION_OFFSET,
+            bytemuck::bytes_of(&max_compute_workgroups_per_dimension),
+        );
+
+        // The scan still runs without keys, so `group_count` is reset to no groups
+        if number_of_keys == 0 {
+            pass.dispatch_workgroups(1, 1, 1);
+            return;
+        }
+
+        pass.set_pipeline(count_heads_pipeline);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blocks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+
+        pass.set_pipeline(scan_blocks_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+
+        for pipeline in [compact_heads_pipeline, count_groups_pipeline] {
+            pass.set_pipeline(pipeline);
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_blocks,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    /// `(group_keys, group_offsets, group_counts)`
+    fn cpu_group_by(sorted_keys: &[u32]) -> (Vec<u32>, Vec<u32>, Vec<u32>) {
+        let mut group_keys = Vec::new();
+        let mut group_offsets = Vec::new();
+        let mut group_counts: Vec<u32> = Vec::new();
+
+        for (i, &key) in sorted_keys.iter().enumerate() {
+            if group_keys.last() == Some(&key) {
+                *group_counts.last_mut().unwrap() += 1;
+            } else {
+                group_keys.push(key);
+                group_offsets.push(i as u32);
+                group_counts.push(1);
+            }
+        }
+
+        (group_keys, group_offsets, group_counts)
+    }
+
+    fn run_group_by_test(sorted_keys: Vec<u32>, max_compute_workgroups_per_dimension: Option<u32>) {
+        let mut app = create_render_test_app();
+        app.add_plugins(GroupByPlugin);
+
+        let (expected_keys, expected_offsets, expected_counts) = cpu_group_by(&sorted_keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  group_by_pipeline: Res<GroupByPipeline>| {
+                let number_of_keys = sorted_keys.len();
+                let max_compute_workgroups_per_dimension = max_compute_workgroups_per_dimension
+                    .unwrap_or(render_device.limits().max_compute_workgroups_per_dimension);
+
+                // Never empty, a zero-sized binding is invalid
+                let len = number_of_keys.max(1);
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[sorted_keys.as_slice(), &[0]].concat());
+                let group_keys_buf = create_storage_buffer(&render_device, &vec![0u32; len]);
+                let group_offsets_buf = create_storage_buffer(&render_device, &vec![0u32; len]);
+                let group_counts_buf = create_storage_buffer(&render_device, &vec![0u32; len]);
+                let group_count_buf = create_storage_buffer(&render_device, &[u32::MAX; 8]);
+                let bind_group = group_by_pipeline.create_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &group_keys_buf,
+                    &group_offsets_buf,
+                    &group_counts_buf,
+                    &group_count_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: group_by command encoder"),
+                });
+                group_by_pipeline.record_group_by(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys as u32,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = expected_keys.len() as u32;
+                let groups_x = n.min(max_compute_workgroups_per_dimension);
+                let group_count = read_buffer(&render_device, &render_queue, &group_count_buf, 8);
+                assert_eq!(
+                    group_count,
+                    [
+                        n,
+                        n.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                        1,
+                        1,
+                        groups_x,
+                        n.div_ceil(groups_x.max(1)).max(1),
+                        1,
+                        0
+                    ]
+                );
+                // Enough workgroups for every group
+                assert!(group_count[4] * group_count[5] >= n);
+
+                let n = n as usize;
+                let group_keys = read_buffer(&render_device, &render_queue, &group_keys_buf, n);
+                let group_offsets =
+                    read_buffer(&render_device, &render_queue, &group_offsets_buf, n);
+                let group_counts = read_buffer(&render_device, &render_queue, &group_counts_buf, n);
+                assert_eq!(group_keys, expected_keys);
+                assert_eq!(group_offsets, expected_offsets);
+                assert_eq!(group_counts, expected_counts);
+            },
+        );
+    }
+
+    #[test]
+    fn test_group_by_known_layout() {
+        run_group_by_test(vec![7, 7, 90, 90, 90, 4000], None);
+        run_group_by_test(vec![], None);
+    }
+
+    #[test]
+    fn test_group_by_sparse_duplicates() {
+        // A few hundred sparse keys over the whole u32 range, each one repeated a lot
+        let mut rng = StdRng::seed_from_u64(159);
+        let ids: Vec<u32> = (0..300).map(|_| rng.r#gen::<u32>()).collect();
+        let mut sorted_keys: Vec<u32> = (0..100_000)
+            .map(|_| ids[rng.gen_range(0..ids.len())])
+            .collect();
+        sorted_keys.sort();
+        run_group_by_test(sorted_keys.clone(), None);
+
+        // The workgroups per group split over y
+        run_group_by_test(sorted_keys, Some(64));
+        run_group_by_test(vec![u32::MAX; 70_000], None);
+    }
+
+    #[test]
+    fn test_group_by_buffer_size() {
+        assert_eq!(group_by_buffer_size(1_000), 4_000);
+        assert_eq!(group_by_buffer_size_for_groups(1_000, 16), 64);
+        assert_eq!(group_by_buffer_size_for_groups(10, 16), 40);
+    }
+}
@@ -0,0 +1,166 @@
+/// Sorted keys, usually the output of the radix sort
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// `group_keys[i]` = the key of the i-th group
+@group(0) @binding(1) var<storage, read_write> group_keys: array<u32>;
+/// `group_offsets[i]` = the index of the first sorted key of the i-th group
+@group(0) @binding(2) var<storage, read_write> group_offsets: array<u32>;
+/// `group_counts[i]` = the number of sorted keys of the i-th group
+@group(0) @binding(3) var<storage, read_write> group_counts: array<u32>;
+/// [number_of_groups, threads_x, 1, 1, groups_x, groups_y, 1, 0], see `group_by.rs`
+@group(0) @binding(4) var<storage, read_write> group_count: array<u32, 8>;
+/// The number of group heads in each block, then the exclusive prefix sum of it
+@group(0) @binding(5) var<storage, read_write> block_offsets: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of sorted keys.
+    number_of_keys: u32,
+    /// The limit of the device, the workgroups per group are split over y past it.
+    max_compute_workgroups_per_dimension: u32,
+}
+var<push_constant> pc: PushConstants;
+
+fn get_workgroup_index(workgroup_id: vec3u, num_workgroups: vec3u) -> u32 {
+    return workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+}
+
+fn get_key_index(workgroup_index: u32, local_invocation_id_x: u32) -> u32 {
+    return workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id_x;
+}
+
+fn is_group_head(key_index: u32) -> bool {
+    return key_index < pc.number_of_keys && (key_index == 0u || sorted_keys[key_index] != sorted_keys[key_index - 1u]);
+}
+
+fn is_group_tail(key_index: u32) -> bool {
+    return key_index < pc.number_of_keys
+        && (key_index == pc.number_of_keys - 1u || sorted_keys[key_index] != sorted_keys[key_index + 1u]);
+}
+
+#ifdef COUNT_HEADS_PIPELINE
+var<workgroup> local_count: atomic<u32>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, local_invocation_id.x);
+
+    if local_invocation_id.x == 0u {
+        atomicStore(&local_count, 0u);
+    }
+    workgroupBarrier();
+
+    if is_group_head(key_index) {
+        atomicAdd(&local_count, 1u);
+    }
+    workgroupBarrier();
+
+    if local_invocation_id.x == 0u {
+        block_offsets[workgroup_index] = atomicLoad(&local_count);
+    }
+}
+#endif // COUNT_HEADS_PIPELINE
+
+#ifdef SCAN_BLOCKS_PIPELINE
+var<workgroup> sums: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(local_invocation_id) local_invocation_id: vec3u) {
+    let tid = local_invocation_id.x;
+    let number_of_blocks = (pc.number_of_keys + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+
+    // Each thread owns a contiguous chunk of blocks
+    let chunk = (number_of_blocks + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+    let chunk_begin = min(tid * chunk, number_of_blocks);
+    let chunk_end = min(chunk_begin + chunk, number_of_blocks);
+
+    var sum = 0u;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        sum += block_offsets[i];
+    }
+    sums[tid] = sum;
+    workgroupBarrier();
+
+    // Inclusive scan of the chunk sums (Hillis-Steele)
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = sums[tid - offset];
+        }
+        workgroupBarrier();
+        sums[tid] += v;
+        workgroupBarrier();
+    }
+
+    var running = sums[tid] - sum;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        let count = block_offsets[i];
+        block_offsets[i] = running;
+        running += count;
+    }
+
+    if tid == #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u {
+        let number_of_groups = sums[tid];
+        group_count[0] = number_of_groups;
+        // One thread per group
+        group_count[1] = (number_of_groups + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+        group_count[2] = 1u;
+        group_count[3] = 1u;
+        // One workgroup per group
+        let groups_x = min(number_of_groups, pc.max_compute_workgroups_per_dimension);
+        group_count[4] = groups_x;
+        group_count[5] = max((number_of_groups + groups_x - 1u) / max(groups_x, 1u), 1u);
+        group_count[6] = 1u;
+        group_count[7] = 0u;
+    }
+}
+#endif // SCAN_BLOCKS_PIPELINE
+
+// Shared by `COMPACT_HEADS_PIPELINE` and `COUNT_GROUPS_PIPELINE`
+#ifdef GROUP_INDEX_PIPELINE
+var<workgroup> group_indices: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let tid = local_invocation_id.x;
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, tid);
+
+    // Inclusive scan of the group heads: the index of the group a key belongs to, plus one
+    group_indices[tid] = u32(is_group_head(key_index));
+    workgroupBarrier();
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = group_indices[tid - offset];
+        }
+        workgroupBarrier();
+        group_indices[tid] += v;
+        workgroupBarrier();
+    }
+
+    let group_index = block_offsets[workgroup_index] + group_indices[tid] - 1u;
+
+#ifdef COMPACT_HEADS_PIPELINE
+    if is_group_head(key_index) {
+        group_keys[group_index] = sorted_keys[key_index];
+        group_offsets[group_index] = key_index;
+    }
+#endif // COMPACT_HEADS_PIPELINE
+
+#ifdef COUNT_GROUPS_PIPELINE
+    if is_group_tail(key_index) {
+        group_counts[group_index] = key_index + 1u - group_offsets[group_index];
+    }
+#endif // COUNT_GROUPS_PIPELINE
+}
+#endif // GROUP_INDEX_PIPELINE
@@ -0,0 +1,343 @@
+//! Reuses the scanned histograms of a sort whose keys haven't changed, e.g. the same keys re-sorted every frame
+//! of a static scene.
+//!
+//! The offsets a pass scatters the keys to only depend on the input keys of the pass, so [`HistogramCache`] keeps
+//! one blocks buffer per pass instead of sharing the global one, and skips the count and scan steps on a hit:
+//!
+//! ```text
+//!  miss:  count(blocks[p]) ─▶ scan(blocks[p]) ─▶ scatter(blocks[p])   for each pass p
+//!  hit:                                          scatter(blocks[p])   for each pass p
+//! ```
+//!
+//! Whether the keys changed is up to the caller, nothing watches the global keys buffer: call
+//! [`HistogramCache::invalidate`] whenever they're written, by a keygen pass or an upload.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor,
+            PipelineCache,
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    RadixSortBindGroup, RadixSortPipeline, SortPipelines, blocks_buffer_size,
+    create_dummy_vals_buffers, global_keys_buffer, global_vals_buffer, is_input_even,
+    passes_needed, record_count_step, record_scan_step, record_scatter_step,
+};
+
+/// The sort the cached offsets were computed for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedSort {
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    read_from_even: bool,
+}
+
+/// Opt-in cache of the scanned histograms of [`HistogramCache::run`], see the module docs.
+///
+/// Costs one blocks buffer per pass, `4 * blocks_buffer_size(max_number_of_keys)` bytes of VRAM.
+#[derive(Resource, Debug)]
+pub struct HistogramCache {
+    /// The blocks buffer of each pass, holding its scanned histograms
+    blocks: Vec<Buffer>,
+    /// The bind groups of each pass, binding the global keys/vals and the blocks of the pass
+    bind_groups: Vec<RadixSortBindGroup>,
+    cached: Option<CachedSort>,
+}
+
+impl HistogramCache {
+    /// Creates the cache for sorts of up to `max_number_of_keys` keys of the global buffers.
+    pub fn new(
+        render_device: &RenderDevice,
+        radix_sort_pipeline: &RadixSortPipeline,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        max_number_of_keys: u32,
+    ) -> Self {
+        let dummy_vals;
+        let (eve_vals, odd_vals) = match (
+            global_vals_buffer(sbufs, true),
+            global_vals_buffer(sbufs, false),
+        ) {
+            (Some(eve_vals), Some(odd_vals)) => (eve_vals, odd_vals),
+            _ => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                (&dummy_vals.0, &dummy_vals.1)
+            }
+        };
+        let eve_keys = global_keys_buffer(sbufs, true).unwrap();
+        let odd_keys = global_keys_buffer(sbufs, false).unwrap();
+
+        let blocks: Vec<Buffer> = (0..passes_needed(32))
+            .map(|_| {
+                render_device.create_buffer(&BufferDescriptor {
+                    label: Some("radix_sort: histogram cache blocks buffer"),
+                    size: blocks_buffer_size(max_number_of_keys),
+                    usage: BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+
+        let bind_groups = blocks
+            .iter()
+            .map(|blocks| {
+                RadixSortBindGroup::from_buffers(
+                    render_device,
+                    radix_sort_pipeline.bind_group_layout(),
+                    eve_keys,
+                    eve_vals,
+                    blocks,
+                    odd_keys,
+                    odd_vals,
+                )
+            })
+            .collect();
+
+        Self {
+            blocks,
+            bind_groups,
+            cached: None,
+        }
+    }
+
+    /// Forgets the cached histograms, the next [`Self::run`] counts and scans again.
+    pub fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    /// `true` if a [`Self::run`] with the same `number_of_keys`, `pass_range` and `read_from_even` skips the
+    /// count and scan steps.
+    pub fn is_valid(
+        &self,
+        number_of_keys: u32,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+    ) -> bool {
+        self.cached
+            == Some(CachedSort {
+                number_of_keys,
+                pass_range: pass_range.clone(),
+                read_from_even,
+            })
+    }
+
+    /// [`crate::run`] on the global buffers, reusing the histograms of the previous call unless the arguments
+    /// (except `init_index`) changed or the cache was invalidated. Returns `true` on a hit.
+    ///
+    /// The input keys must be the same as the input keys of the previous call, which a sort over an even
+    /// number of passes overwrites with its output.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_sort_pipeline: &RadixSortPipeline,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        pass_range: Range<u32>,
+        init_index: bool,
+        read_from_even: bool,
+    ) -> bool {
+        if init_index && !radix_sort_pipeline.allocate_values() {
+            error!(
+                "radix_sort: init_index sorts key/value pairs, but the vals buffers aren't allocated (RadixSortSettings::without_values)"
+            );
+            return false;
+        }
+
+        if blocks_buffer_size(number_of_keys) > self.blocks[0].size() {
+            error!(
+                "radix_sort: {} keys exceed the capacity of the HistogramCache",
+                number_of_keys
+            );
+            return false;
+        }
+
+        if number_of_keys < 2 {
+            return false;
+        }
+
+        #[cfg(feature = "trace")]
+        let _span =
+            tracing::info_span!("radix_sort::histogram_cache::run", number_of_keys).entered();
+
+        radix_sort_pipeline.counters().record(number_of_keys);
+
+        let hit = self.is_valid(number_of_keys, &pass_range, read_from_even);
+        let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("radix_sort cached compute pass"),
+            ..default()
+        });
+
+        for pass_index in pass_range.clone() {
+            let bind_group = self.bind_groups[pass_index as usize]
+                .bind_group(is_input_even(pass_index, read_from_even));
+
+            if !hit {
+                record_count_step(
+                    &mut pass,
+                    &pipelines,
+                    bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    pass_index,
+                );
+                record_scan_step(
+                    &mut pass,
+                    &pipelines,
+                    bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                );
+            }
+
+            record_scatter_step(
+                &mut pass,
+                &pipelines,
+                bind_group,
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                pass_index,
+                init_index && pass_index == pass_range.start,
+            );
+        }
+
+        self.cached = Some(CachedSort {
+            number_of_keys,
+            pass_range,
+            read_from_even,
+        });
+
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        RadixSortPlugin, run, sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cached_and_uncached() {
+        let number_of_keys = 100_003;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: number_of_keys.into(),
+        });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) % 30_000)
+            .collect();
+        let other_keys: Vec<u32> = keys.iter().map(|key| key ^ 0x5555).collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let max_compute_workgroups_per_dimension =
+                    render_device.limits().max_compute_workgroups_per_dimension;
+                let mut histogram_cache = HistogramCache::new(
+                    &render_device,
+                    &radix_sort_pipeline,
+                    &sbufs,
+                    number_of_keys,
+                );
+
+                // Writes `keys` and sorts them, with the cache or with `run`
+                let sort = |keys: &[u32], histogram_cache: Option<&mut HistogramCache>| {
+                    render_queue.write_buffer(
+                        global_keys_buffer(&sbufs, true).unwrap(),
+                        0,
+                        bytemuck::cast_slice(keys),
+                    );
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: histogram cache command encoder"),
+                        });
+                    let hit = match histogram_cache {
+                        Some(histogram_cache) => histogram_cache.run(
+                            &mut encoder,
+                            &pipeline_cache,
+                            &radix_sort_pipeline,
+                            max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            0..4,
+                            true,
+                            true,
+                        ),
+                        None => {
+                            run(
+                                &mut encoder,
+                                &pipeline_cache,
+                                &radix_sort_pipeline,
+                                &radix_bind_group,
+                                max_compute_workgroups_per_dimension,
+                                number_of_keys,
+                                0..4,
+                                true,
+                                true,
+                            );
+                            false
+                        }
+                    };
+                    render_queue.submit([encoder.finish()]);
+
+                    let read = |buffer| {
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            buffer,
+                            number_of_keys as usize,
+                        )
+                    };
+                    let output = (
+                        read(sorted_keys_buffer(&sbufs, &(0..4), true).unwrap()),
+                        read(sorted_vals_buffer(&sbufs, &(0..4), true).unwrap()),
+                    );
+                    (hit, output)
+                };
+
+                let (_, expected) = sort(&keys, None);
+                assert_eq!(
+                    sort(&keys, Some(&mut histogram_cache)),
+                    (false, expected.clone())
+                );
+                assert_eq!(sort(&keys, Some(&mut histogram_cache)), (true, expected));
+
+                let (_, other_expected) = sort(&other_keys, None);
+                histogram_cache.invalidate();
+                assert_eq!(
+                    sort(&other_keys, Some(&mut histogram_cache)),
+                    (false, other_expected.clone())
+                );
+                assert_eq!(
+                    sort(&other_keys, Some(&mut histogram_cache)),
+                    (true, other_expected)
+                );
+                assert!(!histogram_cache.is_valid(number_of_keys, &(0..3), true));
+            },
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! GPU durations of the [`SortJob`](crate::SortJob)s, measured with timestamp queries.
+//!
+//! With [`RadixSortJobsConfig::timestamps`] and a device supporting the timestamp queries inside encoders, the
+//! [`crate::RadixSortJobsNode`] writes a pair of timestamps around each job it records. The pool holds
+//! [`RadixSortJobsConfig::max_timed_jobs_per_frame`] pairs, the jobs past them are unmeasured:
+//!
+//! ```text
+//!  Prepare:  JobTimestampQueries (re)created to the size of the config
+//!  Render:   ts 0 │ job 0 │ ts 1   ts 2 │ job 1 │ ts 3   job 2 (no pair left) ... ─▶ resolved into the readback
+//!  Cleanup:  readback mapped, blocking ─▶ RadixSortJobTimings, SortJobCompleted::gpu_duration
+//! ```
+//!
+//! Reading the timestamps back waits for the GPU to finish the frame, the measurements are meant for profiling.
+
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Maintain, MapMode,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        settings::WgpuFeatures,
+    },
+    utils::HashMap,
+};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType};
+
+use crate::{RadixSortJobsConfig, SortJobId};
+
+/// The size of a resolved timestamp.
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// The GPU durations of the jobs recorded during the last frame with any job, in the render world.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct RadixSortJobTimings {
+    /// The frame recording the jobs.
+    pub frame: u32,
+    /// `None` for the jobs past the pool of queries, or when the timestamps aren't supported.
+    pub durations: HashMap<SortJobId, Option<Duration>>,
+}
+
+/// Returns `true` if `render_device` can write the timestamps of the jobs.
+pub fn supports_job_timestamps(render_device: &RenderDevice) -> bool {
+    render_device
+        .features()
+        .contains(WgpuFeatures::TIMESTAMP_QUERY | WgpuFeatures::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+}
+
+/// The pool of timestamp pairs of the node.
+#[derive(Resource)]
+pub(crate) struct JobTimestampQueries {
+    query_set: QuerySet,
+    resolve: Buffer,
+    readback: Buffer,
+    /// The number of pairs.
+    capacity: u32,
+}
+
+impl JobTimestampQueries {
+    fn new(render_device: &RenderDevice, capacity: u32) -> Self {
+        let size = 2 * capacity as u64 * TIMESTAMP_SIZE;
+
+        Self {
+            query_set: render_device
+                .wgpu_device()
+                .create_query_set(&QuerySetDescriptor {
+                    label: Some("radix_sort: job timestamps"),
+                    ty: QueryType::Timestamp,
+                    count: 2 * capacity,
+                }),
+            resolve: render_device.create_buffer(&BufferDescriptor {
+                label: Some("radix_sort: job timestamps resolve buffer"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: render_device.create_buffer(&BufferDescriptor {
+                label: Some("radix_sort: job timestamps readback buffer"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            capacity,
+        }
+    }
+
+    /// Writes the start (`end == false`) or the end timestamp of the `index`-th job recorded, if it has a pair.
+    pub(crate) fn write(&self, encoder: &mut CommandEncoder, index: usize, end: bool) {
+        if index < self.capacity as usize {
+            encoder.write_timestamp(&self.query_set, 2 * index as u32 + end as u32);
+        }
+    }
+
+    /// Copies the timestamps of the first `jobs` jobs recorded into the readback buffer.
+    pub(crate) fn resolve(&self, encoder: &mut CommandEncoder, jobs: usize) {
+        let queries = 2 * (jobs as u32).min(self.capacity);
+        if queries == 0 {
+            return;
+        }
+
+        encoder.resolve_query_set(&self.query_set, 0..queries, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve,
+            0,
+            &self.readback,
+            0,
+            queries as u64 * TIMESTAMP_SIZE,
+        );
+    }
+
+    /// Reads the durations of the first `jobs` jobs recorded, whose commands must have been submitted. Blocks, so
+    /// none are measured on wasm32.
+    pub(crate) fn read(
+        &self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        jobs: usize,
+    ) -> Vec<Option<Duration>> {
+        let measured = jobs.min(self.capacity as usize);
+        let mut durations = vec![None; jobs];
+        if measured == 0 || cfg!(target_arch = "wasm32") {
+            return durations;
+        }
+
+        let slice = self.readback.slice(0..2 * measured as u64 * TIMESTAMP_SIZE);
+        slice.map_async(MapMode::Read, |_| ());
+        render_device.poll(Maintain::Wait);
+
+        let period = render_queue.get_timestamp_period() as f64;
+        {
+            let timestamps: &[u64] = bytemuck::cast_slice(&slice.get_mapped_range());
+            for (duration, pair) in durations.iter_mut().zip(timestamps.chunks_exact(2)) {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                *duration = Some(Duration::from_nanos((ticks as f64 * period) as u64));
+            }
+        }
+        self.readback.unmap();
+
+        durations
+    }
+}
+
+/// Creates the [`JobTimestampQueries`] to the size of the [`RadixSortJobsConfig`], or removes them.
+pub(crate) fn prepare_job_timestamp_queries(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    config: Res<RadixSortJobsConfig>,
+    queries: Option<Res<JobTimestampQueries>>,
+) {
+    let capacity = if config.timestamps && supports_job_timestamps(&render_device) {
+        config.max_timed_jobs_per_frame
+    } else {
+        0
+    };
+    if queries.as_ref().map_or(0, |queries| queries.capacity) == capacity {
+        return;
+    }
+
+    if capacity == 0 {
+        commands.remove_resource::<JobTimestampQueries>();
+    } else {
+        commands.insert_resource(JobTimestampQueries::new(&render_device, capacity));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{Render, RenderApp, RenderSet};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortBindGroup, RadixSortJobs, RadixSortPlugin, SortJob,
+        SortJobCompleted, test_utils::create_render_test_app, test_utils::run_once,
+    };
+
+    use super::*;
+
+    fn queue_timed_jobs(mut jobs: ResMut<RadixSortJobs>, mut queued: Local<bool>) {
+        if std::mem::replace(&mut *queued, true) {
+            return;
+        }
+
+        for count in [100, 4_000, 1_000] {
+            jobs.push(SortJob::new(count));
+        }
+    }
+
+    #[test]
+    fn test_job_timings() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 4096.into(),
+            });
+        app.sub_app_mut(RenderApp)
+            .insert_resource(RadixSortJobsConfig {
+                timestamps: true,
+                max_timed_jobs_per_frame: 2,
+                ..default()
+            })
+            .add_systems(
+                Render,
+                queue_timed_jobs
+                    .in_set(RenderSet::Queue)
+                    .run_if(resource_exists::<RadixSortBindGroup>),
+            );
+
+        run_once(&mut app);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        let supported = supports_job_timestamps(render_world.resource::<RenderDevice>());
+        let completed: Vec<SortJobCompleted> = render_world
+            .resource::<Events<SortJobCompleted>>()
+            .iter_current_update_events()
+            .copied()
+            .collect();
+        assert_eq!(completed.len(), 3);
+
+        // The third job has no pair of queries left
+        let measured: Vec<bool> = completed
+            .iter()
+            .map(|event| event.gpu_duration.is_some())
+            .collect();
+        assert_eq!(measured, vec![supported, supported, false]);
+
+        let timings = render_world.resource::<RadixSortJobTimings>();
+        assert_eq!(timings.durations.len(), 3);
+        for event in &completed {
+            assert_eq!(timings.durations[&event.id], event.gpu_duration);
+            assert_eq!(timings.frame, event.frame);
+        }
+    }
+}
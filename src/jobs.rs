@@ -0,0 +1,1332 @@
+//! A queue of sorts, recorded by priority by the [`RadixSortJobsNode`] of the render graph each frame.
+//!
+//! Render-world systems push [`SortJob`]s into the [`RadixSortJobs`] before the graph runs, e.g. in
+//! [`RenderSet::Queue`] or [`RenderSet::PrepareResources`]. The node records each job with
+//! [`crate::run_with_options`] on its buffers, and the queue is cleared in [`RenderSet::Cleanup`]:
+//!
+//! ```text
+//!  Queue/Prepare:  jobs.push(job) ─▶ SortJobId
+//!  Render:         RadixSortJobsNode (before the cameras): run the scheduled jobs, highest priority first
+//!  Cleanup:        the scheduled jobs cleared and a SortJobCompleted sent per job recorded, the others deferred
+//!                  with a SortJobDeferred. Unless the sort isn't loaded yet, then all of them wait for the next frame
+//! ```
+//!
+//! The [`RadixSortJobsConfig`] bounds the jobs and the keys recorded per frame. A deferred job gains one priority
+//! level per frame waiting, so a job of priority `p` waits at most `255 - p` frames before reaching the top
+//! priority, where the jobs run in push order.
+//!
+//! The jobs on the global buffers share them: each job sorts what the global buffers hold when it runs, a job
+//! overwrites the results of the previous one. Jobs on their own [`RadixSortBindGroup`] share only the pipelines.
+//! A job on the global buffers with [`RadixSortRunOptions::preserve_input`] sorts with
+//! [`crate::run_preserving_input`], which needs the [`crate::PreserveInputPlugin`]. One with a [`SortJobRedirect`]
+//! sorts with [`crate::run_into_output`], its last pass writing buffers of the user instead of the global buffers.
+//!
+//! For the single sort of the global buffers most apps need, setting the [`SortLength`] is enough: the node sorts
+//! its first `len` keys every frame, before the jobs.
+//!
+//! A job may carry a [`SortJobCallback`], called by the node right after recording the job to encode follow-up
+//! commands, e.g. copying the results out of the global buffers before the next job overwrites them.
+//!
+//! A job on the global buffers may also carry a [`SortJobUpload`], copied into its input side before it sorts. With
+//! [`RadixSortJobsConfig::overlap_uploads`], the upload of the first job goes into a command buffer of its own,
+//! submitted ahead of the sorts in the same submission:
+//!
+//! ```text
+//!  command buffer 1:  keys copy ─▶ vals copy                upload of the first job
+//!  command buffer 2:  sort 1 ─▶ upload 2 ─▶ sort 2 ─▶ ...   the jobs after it share the global buffers
+//! ```
+//!
+//! The keys are copied first, the vals the first pass only reads once its key-only count and scan steps are done.
+//! How much of the copies overlaps the previous commands is up to the driver, wgpu still orders the copies before
+//! the passes binding the buffers.
+//!
+//! All the jobs are submitted on the [`RenderQueue`], the only queue wgpu exposes per device, so there is no async
+//! compute path: the sorts run in order with the rest of the frame, after the commands recorded before the node and
+//! before the ones after it, e.g. the camera passes. The results are visible to any command of a later command buffer
+//! on that queue without further synchronization, and to the CPU once the submission completes, see
+//! [`crate::BufferReadback`]. Overlapping the sorts with the graphics work needs a second queue, which would take a
+//! hal-level device of the app's own, outside of Bevy's renderer.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use bevy::{
+    core::FrameCount,
+    prelude::*,
+    render::{
+        Render, RenderSet,
+        graph::CameraDriverLabel,
+        render_asset::RenderAssets,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_resource::{
+            Buffer, BufferAddress, CommandEncoder, CommandEncoderDescriptor, PipelineCache,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    JobTimestampQueries, NUMBER_OF_BYTES_PER_KEY, PreserveInputScratch, RadixSortBindGroup,
+    RadixSortJobTimings, RadixSortOutput, RadixSortPipeline, RadixSortRunOptions,
+    RadixSortSettings, global_keys_buffer, global_vals_buffer, is_output_even,
+    prepare_job_timestamp_queries, preserved_output_even, radix_sort_loaded, run_into_output,
+    run_preserving_input, run_with_options, swap_global_buffers,
+};
+
+/// Identifies a [`SortJob`] pushed into the [`RadixSortJobs`], increasing in push order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SortJobId(pub u64);
+
+/// The buffers a [`SortJob`] sorts.
+#[derive(Debug, Clone, Default)]
+pub enum SortJobBuffers {
+    /// The global buffers of the [`RadixSortPlugin`](crate::RadixSortPlugin), through the [`RadixSortBindGroup`]
+    /// resource.
+    #[default]
+    Global,
+    /// Buffers of their own, see [`crate::RadixSortPipeline::create_bind_group_for`], or
+    /// [`RadixSortBindGroup::from_buffers`] without the checks.
+    Custom(RadixSortBindGroup),
+}
+
+/// The results of a [`SortJob`], passed to its [`SortJobCallback`].
+#[derive(Debug, Clone, Copy)]
+pub struct SortJobOutput<'a> {
+    pub id: SortJobId,
+    /// The number of keys sorted.
+    pub count: u32,
+    /// Whether the sorted keys are on the `EVE_*` side of the buffers.
+    pub output_even: bool,
+    /// The sorted keys, for the jobs on the global buffers. A job on buffers of its own captures them in its callback.
+    pub keys: Option<&'a Buffer>,
+    /// The sorted vals, for the jobs on the global buffers.
+    pub vals: Option<&'a Buffer>,
+}
+
+/// Encodes commands after a [`SortJob`], on the command encoder of the [`RadixSortJobsNode`].
+///
+/// The callback is `'static` and owned by its job: it can't borrow anything of the frame pushing the job, and is
+/// dropped with the job once recorded. The buffers it's handed only live for the call.
+#[derive(Clone)]
+pub struct SortJobCallback(pub Arc<dyn Fn(&mut CommandEncoder, &SortJobOutput) + Send + Sync>);
+
+impl SortJobCallback {
+    pub fn new(
+        callback: impl Fn(&mut CommandEncoder, &SortJobOutput) + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for SortJobCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SortJobCallback").finish_non_exhaustive()
+    }
+}
+
+/// The buffers a [`SortJob`] on the global buffers copies its input from, see the module docs.
+///
+/// Both need the `COPY_SRC` usage and hold at least [`SortJob::count`] u32s.
+#[derive(Debug, Clone)]
+pub struct SortJobUpload {
+    pub keys: Buffer,
+    /// Not copied with [`RadixSortRunOptions::init_index`], the first pass writes the indices.
+    pub vals: Option<Buffer>,
+}
+
+impl SortJobUpload {
+    /// Copies the first `count` keys and vals into the `read_from_even` side of the global buffers, the vals unless
+    /// `init_index` overwrites them.
+    pub fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        count: u32,
+        read_from_even: bool,
+        init_index: bool,
+    ) {
+        let size = count as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+        if size == 0 {
+            return;
+        }
+
+        if let Some(keys) = global_keys_buffer(sbufs, read_from_even) {
+            encoder.copy_buffer_to_buffer(&self.keys, 0, keys, 0, size);
+        }
+        if let (Some(src), Some(vals), false) = (
+            &self.vals,
+            global_vals_buffer(sbufs, read_from_even),
+            init_index,
+        ) {
+            encoder.copy_buffer_to_buffer(src, 0, vals, 0, size);
+        }
+    }
+}
+
+/// The buffers the last pass of a [`SortJob`] on the global buffers writes, see [`crate::run_into_output`].
+///
+/// Both need the `STORAGE` usage and hold at least [`SortJob::count`] u32s, the vals are required unless the sort
+/// is keys-only. The [`SortJobOutput`] of the job points at them.
+#[derive(Debug, Clone)]
+pub struct SortJobRedirect {
+    pub keys: Buffer,
+    pub vals: Option<Buffer>,
+}
+
+/// A sort recorded by the [`RadixSortJobsNode`].
+#[derive(Debug, Clone)]
+pub struct SortJob {
+    pub buffers: SortJobBuffers,
+    /// The number of keys sorted.
+    pub count: u32,
+    pub options: RadixSortRunOptions,
+    /// Sort the `EVE_*` side of the buffers, the `ODD_*` side otherwise.
+    pub read_from_even: bool,
+    pub on_complete: Option<SortJobCallback>,
+    /// The higher priorities are recorded first, see [`RadixSortJobsConfig`].
+    pub priority: u8,
+    /// Copied into the global buffers before the sort, ignored with an error for [`SortJobBuffers::Custom`].
+    pub upload: Option<SortJobUpload>,
+    /// Written by the last pass instead of the global buffers, ignored with an error for [`SortJobBuffers::Custom`].
+    pub redirect: Option<SortJobRedirect>,
+}
+
+impl SortJob {
+    /// An argsort of the first `count` keys of the even global buffers.
+    pub fn new(count: u32) -> Self {
+        Self {
+            buffers: SortJobBuffers::Global,
+            count,
+            options: default(),
+            read_from_even: true,
+            on_complete: None,
+            priority: 0,
+            upload: None,
+            redirect: None,
+        }
+    }
+
+    /// An argsort of the first `count` keys of the even global buffers, over the
+    /// [`RadixSortSettings::default_pass_range`].
+    pub fn for_settings(count: u32, settings: &RadixSortSettings) -> Self {
+        Self::new(count).with_options(settings.default_run_options())
+    }
+
+    pub fn with_buffers(mut self, buffers: SortJobBuffers) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    pub fn with_options(mut self, options: RadixSortRunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_read_from_even(mut self, read_from_even: bool) -> Self {
+        self.read_from_even = read_from_even;
+        self
+    }
+
+    pub fn with_on_complete(
+        mut self,
+        callback: impl Fn(&mut CommandEncoder, &SortJobOutput) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_complete = Some(SortJobCallback::new(callback));
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Copies the input of the job from `keys` and `vals` before sorting, see [`SortJobUpload`].
+    pub fn with_upload(mut self, keys: Buffer, vals: Option<Buffer>) -> Self {
+        self.upload = Some(SortJobUpload { keys, vals });
+        self
+    }
+
+    /// Sorts into `keys` and `vals` instead of the global buffers, see [`SortJobRedirect`].
+    pub fn with_redirect(mut self, keys: Buffer, vals: Option<Buffer>) -> Self {
+        self.redirect = Some(SortJobRedirect { keys, vals });
+        self
+    }
+
+    /// Whether the sorted keys end up on the `EVE_*` side of the buffers, the side the last pass would have written
+    /// for a job with a [`SortJobRedirect`].
+    pub fn output_even(&self) -> bool {
+        if self.options.preserve_input {
+            preserved_output_even(&self.options.pass_range, self.read_from_even)
+        } else {
+            is_output_even(&self.options.pass_range, self.read_from_even)
+        }
+    }
+
+    /// Whether the node records the job, a job on the global buffers can't sort more keys than they hold, nor
+    /// initialize more indices than the vals buffers hold.
+    fn fits(&self, max_number_of_keys: u32, max_number_of_values: u32) -> bool {
+        !matches!(self.buffers, SortJobBuffers::Global)
+            || (self.count <= max_number_of_keys
+                && (!self.options.init_index || self.count <= max_number_of_values))
+    }
+}
+
+/// Sent in the render world for each [`SortJob`] recorded by the [`RadixSortJobsNode`], in [`RenderSet::Cleanup`].
+///
+/// The events of a frame can be read until the cleanup of the next frame.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortJobCompleted {
+    pub id: SortJobId,
+    /// Whether the sorted keys are on the `EVE_*` side of the buffers, see [`SortJob::output_even`].
+    pub final_parity: bool,
+    /// The [`FrameCount`] of the frame recording the job.
+    pub frame: u32,
+    /// The time the GPU took to sort, with [`RadixSortJobsConfig::timestamps`] and a query pair left for the job.
+    pub gpu_duration: Option<Duration>,
+}
+
+/// Sent in the render world for each [`SortJob`] over the budget of the [`RadixSortJobsConfig`], in
+/// [`RenderSet::Cleanup`]. The job stays queued for the next frame.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortJobDeferred {
+    pub id: SortJobId,
+    /// The number of frames the job has been deferred, including this one.
+    pub frames_deferred: u32,
+    /// The [`FrameCount`] of the frame deferring the job.
+    pub frame: u32,
+}
+
+/// The budget of the [`RadixSortJobsNode`] per frame, in the render world. Unbounded by default.
+///
+/// The node always records at least one job per frame, so a job over `max_keys_per_frame` still runs alone.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadixSortJobsConfig {
+    pub max_jobs_per_frame: usize,
+    /// The sum of the [`SortJob::count`]s recorded per frame.
+    pub max_keys_per_frame: u64,
+    /// Measure the GPU duration of the jobs, see [`crate::RadixSortJobTimings`].
+    pub timestamps: bool,
+    /// The size of the pool of timestamp pairs, the jobs recorded past it are unmeasured.
+    pub max_timed_jobs_per_frame: u32,
+    /// [`swap_global_buffers`] after the frames whose last job on the global buffers ends on the `ODD_*` side, so the
+    /// `EVE_*` handles name the sorted buffers, see [`crate::swap`]. Not after a last job with a [`SortJobRedirect`],
+    /// whose results aren't in the global buffers.
+    pub swap_global_buffers: bool,
+    /// Record the [`SortJobUpload`] of the first job in a command buffer ahead of the sorts, see the module docs.
+    /// Every upload is recorded right before its job otherwise, e.g. if a driver misbehaves.
+    pub overlap_uploads: bool,
+}
+
+impl Default for RadixSortJobsConfig {
+    fn default() -> Self {
+        Self {
+            max_jobs_per_frame: usize::MAX,
+            max_keys_per_frame: u64::MAX,
+            timestamps: false,
+            max_timed_jobs_per_frame: 32,
+            swap_global_buffers: false,
+            overlap_uploads: true,
+        }
+    }
+}
+
+/// The number of keys of the global buffers the [`RadixSortJobsNode`] sorts each frame over the
+/// [`RadixSortSettings::default_run_options`], reading from the even side, before the [`RadixSortJobs`]. `0` skips
+/// the sort.
+///
+/// Written by any render-world system before the graph runs, it keeps its value across frames. A length over the
+/// capacity is clamped with a warning.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SortLength(pub u32);
+
+/// The [`SortJob`]s of the current frame, in the render world.
+#[derive(Resource, Debug, Default)]
+pub struct RadixSortJobs {
+    next_id: u64,
+    jobs: Vec<(SortJobId, SortJob)>,
+    /// The number of frames each job has been deferred, along `jobs`
+    deferred: Vec<u32>,
+}
+
+impl RadixSortJobs {
+    /// Queues `job` after the jobs already pushed.
+    pub fn push(&mut self, job: SortJob) -> SortJobId {
+        let id = SortJobId(self.next_id);
+        self.next_id += 1;
+        self.jobs.push((id, job));
+        self.deferred.push(0);
+
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// The queued jobs, in push order.
+    pub fn iter(&self) -> impl Iterator<Item = (SortJobId, &SortJob)> {
+        self.jobs.iter().map(|(id, job)| (*id, job))
+    }
+
+    /// The jobs recorded this frame within the budget of `config`, in the order they're recorded.
+    pub fn scheduled(&self, config: &RadixSortJobsConfig) -> Vec<(SortJobId, &SortJob)> {
+        self.schedule(config)
+            .into_iter()
+            .map(|i| (self.jobs[i].0, &self.jobs[i].1))
+            .collect()
+    }
+
+    /// The priority of the job at `index`, raised by the frames it waited.
+    fn effective_priority(&self, index: usize) -> u8 {
+        let deferred = self.deferred[index].min(u8::MAX as u32) as u8;
+        self.jobs[index].1.priority.saturating_add(deferred)
+    }
+
+    /// The indices of the scheduled jobs, in the order they're recorded.
+    fn schedule(&self, config: &RadixSortJobsConfig) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.jobs.len()).collect();
+        // The ids break the ties, the older jobs first
+        order.sort_by_key(|&i| {
+            (
+                std::cmp::Reverse(self.effective_priority(i)),
+                self.jobs[i].0,
+            )
+        });
+
+        let mut keys = 0u64;
+        let mut scheduled = Vec::new();
+        for i in order {
+            let count = self.jobs[i].1.count as u64;
+            let over_budget = scheduled.len() >= config.max_jobs_per_frame
+                || keys.saturating_add(count) > config.max_keys_per_frame;
+            if over_budget && !scheduled.is_empty() {
+                break;
+            }
+
+            keys += count;
+            scheduled.push(i);
+        }
+
+        scheduled
+    }
+
+    /// Removes the scheduled jobs, ages the others and returns the removed ones, in the order they're recorded.
+    fn take_scheduled(&mut self, config: &RadixSortJobsConfig) -> Vec<(SortJobId, SortJob)> {
+        let order = self.schedule(config);
+        let mut jobs: Vec<_> = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let taken = order.into_iter().filter_map(|i| jobs[i].take()).collect();
+
+        let deferred = std::mem::take(&mut self.deferred);
+        for (job, frames) in jobs.into_iter().zip(deferred) {
+            if let Some(job) = job {
+                self.jobs.push(job);
+                self.deferred.push(frames + 1);
+            }
+        }
+
+        taken
+    }
+}
+
+/// Whether the [`RadixSortJobsNode`] records the jobs this frame.
+fn jobs_runnable(world: &World) -> bool {
+    radix_sort_loaded()(world)
+}
+
+#[derive(RenderLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RadixSortJobsLabel;
+
+/// Records the [`RadixSortJobs`], before the camera driver so the views can use the results.
+#[derive(Debug, Default)]
+pub struct RadixSortJobsNode;
+
+impl Node for RadixSortJobsNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(jobs) = world.get_resource::<RadixSortJobs>() else {
+            return Ok(());
+        };
+        let sort_length = world.get_resource::<SortLength>().map_or(0, |len| len.0);
+        if (jobs.is_empty() && sort_length == 0) || !jobs_runnable(world) {
+            return Ok(());
+        }
+
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::jobs", jobs = jobs.len()).entered();
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+        let global_bind_group = world.resource::<RadixSortBindGroup>();
+        let radix_sort_settings = world.resource::<RadixSortSettings>();
+        let max_number_of_keys = radix_sort_settings.max_number_of_keys();
+        let max_number_of_values = radix_sort_settings.max_number_of_values();
+        let config = world.resource::<RadixSortJobsConfig>();
+        let timestamps = world.get_resource::<JobTimestampQueries>();
+        let preserve_input_scratch = world.get_resource::<PreserveInputScratch>();
+        let render_device = render_context.render_device().clone();
+        let max_compute_workgroups_per_dimension =
+            render_device.limits().max_compute_workgroups_per_dimension;
+
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let scheduled = jobs.scheduled(config);
+
+        // The first job on the global buffers, unless the `SortLength` sorts them before it
+        let early_upload = scheduled
+            .iter()
+            .find(|(_, job)| {
+                matches!(job.buffers, SortJobBuffers::Global)
+                    && job.fits(max_number_of_keys, max_number_of_values)
+            })
+            .filter(|(_, job)| config.overlap_uploads && sort_length == 0 && job.upload.is_some())
+            .map(|&(id, _)| id);
+        if let Some((_, job)) = scheduled.iter().find(|&&(id, _)| Some(id) == early_upload) {
+            let mut upload_encoder =
+                render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("radix_sort: jobs upload command encoder"),
+                });
+            job.upload.as_ref().unwrap().record(
+                &mut upload_encoder,
+                sbufs,
+                job.count,
+                job.read_from_even,
+                job.options.init_index,
+            );
+            render_context.add_command_buffer(upload_encoder.finish());
+        }
+
+        let encoder = render_context.command_encoder();
+        if sort_length > 0 {
+            if sort_length > max_number_of_keys {
+                warn_once!(
+                    "radix_sort: the SortLength is {}, the global buffers hold {}",
+                    sort_length,
+                    max_number_of_keys
+                );
+            }
+            run_with_options(
+                encoder,
+                pipeline_cache,
+                radix_sort_pipeline,
+                global_bind_group,
+                max_compute_workgroups_per_dimension,
+                sort_length.min(max_number_of_keys),
+                &radix_sort_settings.default_run_options(),
+                true,
+            );
+        }
+
+        for (index, &(id, job)) in scheduled.iter().enumerate() {
+            if !job.fits(max_number_of_keys, max_number_of_values) {
+                error!(
+                    "radix_sort: {:?} sorts {} keys, the global buffers hold {} keys and {} vals",
+                    id, job.count, max_number_of_keys, max_number_of_values
+                );
+                continue;
+            }
+
+            let bind_group = match &job.buffers {
+                SortJobBuffers::Global => global_bind_group,
+                SortJobBuffers::Custom(bind_group) => bind_group,
+            };
+
+            match (&job.upload, &job.buffers) {
+                (Some(upload), SortJobBuffers::Global) if Some(id) != early_upload => {
+                    upload.record(
+                        encoder,
+                        sbufs,
+                        job.count,
+                        job.read_from_even,
+                        job.options.init_index,
+                    );
+                }
+                (Some(_), SortJobBuffers::Custom(_)) => {
+                    error!(
+                        "radix_sort: {:?} uploads into buffers of its own, only the global buffers take uploads",
+                        id
+                    );
+                }
+                _ => {}
+            }
+
+            if let Some(timestamps) = timestamps {
+                timestamps.write(encoder, index, false);
+            }
+            if let Some(redirect) = &job.redirect {
+                if let SortJobBuffers::Custom(_) = job.buffers {
+                    error!(
+                        "radix_sort: {:?} redirects the output of buffers of its own, only the global buffers can be redirected",
+                        id
+                    );
+                } else if let Err(err) = run_into_output(
+                    encoder,
+                    &render_device,
+                    pipeline_cache,
+                    radix_sort_pipeline,
+                    bind_group,
+                    sbufs,
+                    max_compute_workgroups_per_dimension,
+                    job.count,
+                    &job.options,
+                    job.read_from_even,
+                    &RadixSortOutput {
+                        keys: &redirect.keys,
+                        vals: redirect.vals.as_ref(),
+                    },
+                ) {
+                    error!("radix_sort: {:?} can't be redirected: {}", id, err);
+                }
+            } else if job.options.preserve_input && matches!(job.buffers, SortJobBuffers::Global) {
+                match preserve_input_scratch {
+                    Some(scratch) => {
+                        run_preserving_input(
+                            encoder,
+                            &render_device,
+                            pipeline_cache,
+                            radix_sort_pipeline,
+                            bind_group,
+                            scratch,
+                            sbufs,
+                            max_compute_workgroups_per_dimension,
+                            job.count,
+                            &job.options,
+                            job.read_from_even,
+                        );
+                    }
+                    None => error!(
+                        "radix_sort: {:?} preserves its input, which needs the PreserveInputPlugin",
+                        id
+                    ),
+                }
+            } else {
+                run_with_options(
+                    encoder,
+                    pipeline_cache,
+                    radix_sort_pipeline,
+                    bind_group,
+                    max_compute_workgroups_per_dimension,
+                    job.count,
+                    &job.options,
+                    job.read_from_even,
+                );
+            }
+            if let Some(timestamps) = timestamps {
+                timestamps.write(encoder, index, true);
+            }
+
+            if let Some(callback) = &job.on_complete {
+                let global = matches!(job.buffers, SortJobBuffers::Global);
+                let output_even = job.output_even();
+                let output = match &job.redirect {
+                    Some(redirect) => SortJobOutput {
+                        id,
+                        count: job.count,
+                        output_even,
+                        keys: global.then_some(&redirect.keys),
+                        vals: global.then_some(redirect.vals.as_ref()).flatten(),
+                    },
+                    None => SortJobOutput {
+                        id,
+                        count: job.count,
+                        output_even,
+                        keys: global
+                            .then(|| global_keys_buffer(sbufs, output_even))
+                            .flatten(),
+                        vals: global
+                            .then(|| global_vals_buffer(sbufs, output_even))
+                            .flatten(),
+                    },
+                };
+                (callback.0)(encoder, &output);
+            }
+        }
+
+        if let Some(timestamps) = timestamps {
+            timestamps.resolve(encoder, scheduled.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Clears the [`RadixSortJobs`] recorded by the node this frame, sending their [`SortJobCompleted`] and a
+/// [`SortJobDeferred`] for the others.
+fn clear_radix_sort_jobs(world: &mut World) {
+    // The render world doesn't run the `First` schedule updating the events
+    world.resource_mut::<Events<SortJobCompleted>>().update();
+    world.resource_mut::<Events<SortJobDeferred>>().update();
+
+    if !jobs_runnable(world) {
+        return;
+    }
+
+    let radix_sort_settings = world.resource::<RadixSortSettings>();
+    let max_number_of_keys = radix_sort_settings.max_number_of_keys();
+    let max_number_of_values = radix_sort_settings.max_number_of_values();
+    let frame = world
+        .get_resource::<FrameCount>()
+        .map_or(0, |frame| frame.0);
+    let config = *world.resource::<RadixSortJobsConfig>();
+    let mut radix_sort_jobs = world.resource_mut::<RadixSortJobs>();
+    let jobs = radix_sort_jobs.take_scheduled(&config);
+    let deferred: Vec<SortJobDeferred> = radix_sort_jobs
+        .jobs
+        .iter()
+        .zip(&radix_sort_jobs.deferred)
+        .map(|((id, _), &frames_deferred)| SortJobDeferred {
+            id: *id,
+            frames_deferred,
+            frame,
+        })
+        .collect();
+
+    let durations = match world.get_resource::<JobTimestampQueries>() {
+        Some(timestamps) => timestamps.read(
+            world.resource::<RenderDevice>(),
+            world.resource::<RenderQueue>(),
+            jobs.len(),
+        ),
+        None => vec![None; jobs.len()],
+    };
+
+    let swap = config.swap_global_buffers
+        && jobs
+            .iter()
+            .rfind(|(_, job)| {
+                matches!(job.buffers, SortJobBuffers::Global)
+                    && job.fits(max_number_of_keys, max_number_of_values)
+            })
+            .is_some_and(|(_, job)| job.redirect.is_none() && !job.output_even());
+
+    let completed: Vec<SortJobCompleted> = jobs
+        .into_iter()
+        .zip(durations)
+        .filter(|((_, job), _)| job.fits(max_number_of_keys, max_number_of_values))
+        .map(|((id, job), gpu_duration)| SortJobCompleted {
+            id,
+            final_parity: job.output_even(),
+            frame,
+            gpu_duration,
+        })
+        .collect();
+    if !completed.is_empty() {
+        world.insert_resource(RadixSortJobTimings {
+            frame,
+            durations: completed
+                .iter()
+                .map(|event| (event.id, event.gpu_duration))
+                .collect(),
+        });
+    }
+
+    world.send_event_batch(completed);
+    world.send_event_batch(deferred);
+
+    if swap {
+        swap_global_buffers(world);
+    }
+}
+
+/// Adds the [`RadixSortJobs`], its node and its cleanup to the render app.
+pub(crate) fn build_jobs(render_app: &mut SubApp) {
+    render_app
+        .init_resource::<RadixSortJobs>()
+        .init_resource::<RadixSortJobsConfig>()
+        .init_resource::<SortLength>()
+        .init_resource::<Events<SortJobCompleted>>()
+        .init_resource::<Events<SortJobDeferred>>()
+        .init_resource::<RadixSortJobTimings>()
+        .add_systems(
+            Render,
+            (
+                prepare_job_timestamp_queries.in_set(RenderSet::PrepareResources),
+                clear_radix_sort_jobs.in_set(RenderSet::Cleanup),
+            ),
+        );
+
+    let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+    render_graph.add_node(RadixSortJobsLabel, RadixSortJobsNode);
+    // Without the camera driver (e.g. a custom graph) the node runs in any order
+    let _ = render_graph.try_add_node_edge(RadixSortJobsLabel, CameraDriverLabel);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::Buffer,
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, blocks_buffer_size, global_keys_buffer,
+        sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, random_keys, read_buffer, run_once,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut jobs = RadixSortJobs::default();
+        assert!(jobs.is_empty());
+
+        let ids: Vec<SortJobId> = (0..3).map(|i| jobs.push(SortJob::new(i))).collect();
+        assert_eq!(ids, vec![SortJobId(0), SortJobId(1), SortJobId(2)]);
+        assert_eq!(jobs.len(), 3);
+        assert_eq!(
+            jobs.iter()
+                .map(|(id, job)| (id, job.count))
+                .collect::<Vec<_>>(),
+            vec![(SortJobId(0), 0), (SortJobId(1), 1), (SortJobId(2), 2)]
+        );
+
+        // The ids keep increasing across frames
+        jobs.take_scheduled(&RadixSortJobsConfig::default());
+        assert!(jobs.is_empty());
+        assert_eq!(jobs.push(SortJob::new(0)), SortJobId(3));
+    }
+
+    fn scheduled_ids(jobs: &RadixSortJobs, config: &RadixSortJobsConfig) -> Vec<u64> {
+        jobs.scheduled(config).iter().map(|(id, _)| id.0).collect()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut jobs = RadixSortJobs::default();
+        for (count, priority) in [(100, 0), (200, 2), (300, 1), (400, 2)] {
+            jobs.push(SortJob::new(count).with_priority(priority));
+        }
+
+        // By priority, then in push order
+        let unbounded = RadixSortJobsConfig::default();
+        assert_eq!(scheduled_ids(&jobs, &unbounded), vec![1, 3, 2, 0]);
+
+        let two_jobs = RadixSortJobsConfig {
+            max_jobs_per_frame: 2,
+            ..default()
+        };
+        assert_eq!(scheduled_ids(&jobs, &two_jobs), vec![1, 3]);
+
+        // The budget stops at the first job over it
+        let keys = RadixSortJobsConfig {
+            max_keys_per_frame: 700,
+            ..default()
+        };
+        assert_eq!(scheduled_ids(&jobs, &keys), vec![1, 3]);
+
+        // A single job over the budget still runs
+        let few_keys = RadixSortJobsConfig {
+            max_keys_per_frame: 10,
+            ..default()
+        };
+        assert_eq!(scheduled_ids(&jobs, &few_keys), vec![1]);
+
+        let taken = jobs.take_scheduled(&two_jobs);
+        assert_eq!(
+            taken.iter().map(|(id, _)| id.0).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(
+            jobs.iter().map(|(id, _)| id.0).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(jobs.deferred, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_schedule_aging() {
+        let mut jobs = RadixSortJobs::default();
+        let low = jobs.push(SortJob::new(1).with_priority(0));
+
+        let one_job = RadixSortJobsConfig {
+            max_jobs_per_frame: 1,
+            ..default()
+        };
+        // A new job of priority 3 each frame, the low priority job overtakes them after 4 frames
+        let mut frames = 0;
+        loop {
+            jobs.push(SortJob::new(1).with_priority(3));
+            frames += 1;
+            let taken = jobs.take_scheduled(&one_job);
+            if taken[0].0 == low {
+                break;
+            }
+            assert!(frames <= 4);
+        }
+        assert_eq!(frames, 4);
+    }
+
+    /// The output buffers and the expected pairs of a job.
+    #[derive(Resource, Default)]
+    struct JobResults(Vec<(Buffer, Buffer, Vec<(u32, u32)>)>);
+
+    fn expected_pairs(keys: &[u32]) -> Vec<(u32, u32)> {
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        expected
+    }
+
+    fn queue_jobs(
+        mut commands: Commands,
+        mut jobs: ResMut<RadixSortJobs>,
+        render_device: Res<RenderDevice>,
+        render_queue: Res<RenderQueue>,
+        radix_sort_pipeline: Res<RadixSortPipeline>,
+        sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+        mut queued: Local<bool>,
+    ) {
+        if std::mem::replace(&mut *queued, true) {
+            return;
+        }
+
+        let mut results = JobResults::default();
+
+        // On the global buffers
+        let keys = random_keys(1, 1_000, u32::MAX);
+        let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+        render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+        assert_eq!(jobs.push(SortJob::new(1_000)), SortJobId(0));
+        results.0.push((
+            sorted_keys_buffer(&sbufs, &(0..4), true).unwrap().clone(),
+            sorted_vals_buffer(&sbufs, &(0..4), true).unwrap().clone(),
+            expected_pairs(&keys),
+        ));
+
+        // On buffers of their own, one ending on the odd side
+        for (count, options) in [
+            (37, RadixSortRunOptions::default()),
+            (20_000, RadixSortRunOptions::for_key_bits(24)),
+        ] {
+            let keys: Vec<u32> = random_keys(count as u64, count, u32::MAX)
+                .into_iter()
+                .map(|key| key & options.key_mask)
+                .collect();
+            let zeros = vec![0; count as usize];
+            let eve_keys = create_storage_buffer(&render_device, &keys);
+            let eve_vals = create_storage_buffer(&render_device, &zeros);
+            let odd_keys = create_storage_buffer(&render_device, &zeros);
+            let odd_vals = create_storage_buffer(&render_device, &zeros);
+            let blocks = create_storage_buffer(
+                &render_device,
+                &vec![0; blocks_buffer_size(count) as usize / 4],
+            );
+            let bind_group = RadixSortBindGroup::from_buffers(
+                &render_device,
+                radix_sort_pipeline.bind_group_layout(),
+                &eve_keys,
+                &eve_vals,
+                &blocks,
+                &odd_keys,
+                &odd_vals,
+            );
+
+            let job = SortJob::new(count)
+                .with_buffers(SortJobBuffers::Custom(bind_group))
+                .with_options(options);
+            let (output_keys, output_vals) = if job.output_even() {
+                (eve_keys, eve_vals)
+            } else {
+                (odd_keys, odd_vals)
+            };
+            jobs.push(job);
+            results
+                .0
+                .push((output_keys, output_vals, expected_pairs(&keys)));
+        }
+
+        commands.insert_resource(results);
+    }
+
+    fn check_jobs(
+        render_device: Res<RenderDevice>,
+        render_queue: Res<RenderQueue>,
+        results: Option<Res<JobResults>>,
+    ) {
+        let Some(results) = results else {
+            return;
+        };
+
+        for (keys_buf, vals_buf, expected) in &results.0 {
+            let keys = read_buffer(&render_device, &render_queue, keys_buf, expected.len());
+            let vals = read_buffer(&render_device, &render_queue, vals_buf, expected.len());
+            let output: Vec<(u32, u32)> = keys.into_iter().zip(vals).collect();
+            assert_eq!(&output, expected);
+        }
+    }
+
+    #[test]
+    fn test_jobs() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 4096.into(),
+            });
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            (
+                queue_jobs
+                    .in_set(RenderSet::Queue)
+                    .run_if(resource_exists::<RadixSortBindGroup>),
+                check_jobs.in_set(RenderSet::Cleanup),
+            ),
+        );
+
+        // The bind group of the global buffers is created in the first frame
+        run_once(&mut app);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(render_world.resource::<JobResults>().0.len(), 3);
+        assert!(render_world.resource::<RadixSortJobs>().is_empty());
+    }
+
+    /// The buffers the callbacks copied the sorted keys into, and the sorted keys.
+    #[derive(Resource)]
+    struct CallbackResults(Vec<Buffer>, Vec<u32>);
+
+    fn queue_jobs_with_callbacks(
+        mut commands: Commands,
+        mut jobs: ResMut<RadixSortJobs>,
+        render_device: Res<RenderDevice>,
+        render_queue: Res<RenderQueue>,
+        sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+        mut queued: Local<bool>,
+    ) {
+        if std::mem::replace(&mut *queued, true) {
+            return;
+        }
+
+        let count = 1_000;
+        // Within 24 bits, all the jobs sort the keys fully
+        let keys: Vec<u32> = random_keys(7, count, u32::MAX)
+            .into_iter()
+            .map(|key| key & 0x00FF_FFFF)
+            .collect();
+        let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+        render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+        // Each job sorts the output of the previous one: even -> even, even -> odd, odd -> odd
+        let mut copies = Vec::new();
+        for (options, read_from_even) in [
+            (RadixSortRunOptions::default(), true),
+            (RadixSortRunOptions::for_key_bits(24), true),
+            (RadixSortRunOptions::default(), false),
+        ] {
+            let copy = create_storage_buffer(&render_device, &vec![0; count as usize]);
+            let dst = copy.clone();
+            jobs.push(
+                SortJob::new(count)
+                    .with_options(options)
+                    .with_read_from_even(read_from_even)
+                    .with_on_complete(move |encoder, output| {
+                        let keys = output.keys.unwrap();
+                        encoder.copy_buffer_to_buffer(keys, 0, &dst, 0, output.count as u64 * 4);
+                    }),
+            );
+            copies.push(copy);
+        }
+
+        let mut sorted = keys;
+        sorted.sort();
+        commands.insert_resource(CallbackResults(copies, sorted));
+    }
+
+    #[test]
+    fn test_job_completed() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 4096.into(),
+            });
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            queue_jobs_with_callbacks
+                .in_set(RenderSet::Queue)
+                .run_if(resource_exists::<RadixSortBindGroup>),
+        );
+
+        run_once(&mut app);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        let completed: Vec<SortJobCompleted> = render_world
+            .resource::<Events<SortJobCompleted>>()
+            .iter_current_update_events()
+            .copied()
+            .collect();
+        assert_eq!(
+            completed
+                .iter()
+                .map(|event| (event.id, event.final_parity))
+                .collect::<Vec<_>>(),
+            vec![
+                (SortJobId(0), true),
+                (SortJobId(1), false),
+                (SortJobId(2), false)
+            ]
+        );
+        assert!(
+            completed
+                .iter()
+                .all(|event| event.frame == completed[0].frame)
+        );
+
+        let render_device = render_world.resource::<RenderDevice>();
+        let render_queue = render_world.resource::<RenderQueue>();
+        let CallbackResults(copies, sorted) = render_world.resource::<CallbackResults>();
+        for copy in copies {
+            let keys = read_buffer(render_device, render_queue, copy, sorted.len());
+            assert_eq!(&keys, sorted);
+        }
+
+        assert!(
+            render_world
+                .resource::<Events<SortJobDeferred>>()
+                .is_empty()
+        );
+
+        // The events of a frame are dropped by the cleanup of the next one
+        app.update();
+        app.update();
+        let render_world = app.sub_app(RenderApp).world();
+        assert!(
+            render_world
+                .resource::<Events<SortJobCompleted>>()
+                .is_empty()
+        );
+    }
+
+    fn queue_over_budget(mut jobs: ResMut<RadixSortJobs>, mut queued: Local<bool>) {
+        if std::mem::replace(&mut *queued, true) {
+            return;
+        }
+
+        for priority in [0, 1, 2] {
+            jobs.push(SortJob::new(1_000).with_priority(priority));
+        }
+    }
+
+    #[test]
+    fn test_jobs_budget() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 4096.into(),
+            });
+        app.sub_app_mut(RenderApp)
+            .insert_resource(RadixSortJobsConfig {
+                max_jobs_per_frame: 2,
+                ..default()
+            })
+            .add_systems(
+                Render,
+                queue_over_budget
+                    .in_set(RenderSet::Queue)
+                    .run_if(resource_exists::<RadixSortBindGroup>),
+            );
+
+        run_once(&mut app);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        let completed: Vec<SortJobId> = render_world
+            .resource::<Events<SortJobCompleted>>()
+            .iter_current_update_events()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(completed, vec![SortJobId(2), SortJobId(1)]);
+        let deferred: Vec<SortJobDeferred> = render_world
+            .resource::<Events<SortJobDeferred>>()
+            .iter_current_update_events()
+            .copied()
+            .collect();
+        assert_eq!(deferred.len(), 1);
+        assert_eq!(deferred[0].id, SortJobId(0));
+        assert_eq!(deferred[0].frames_deferred, 1);
+        assert_eq!(render_world.resource::<RadixSortJobs>().len(), 1);
+
+        // The deferred job runs in the next frame
+        app.update();
+        let render_world = app.sub_app(RenderApp).world();
+        let completed: Vec<SortJobId> = render_world
+            .resource::<Events<SortJobCompleted>>()
+            .iter_current_update_events()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(completed, vec![SortJobId(0)]);
+        assert!(render_world.resource::<RadixSortJobs>().is_empty());
+    }
+
+    #[test]
+    fn test_sort_length() {
+        let capacity = 1024;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: capacity.into(),
+            });
+        run_once(&mut app);
+
+        // Zero skips the frame, over the capacity is clamped
+        for (frame, len) in [300, 0, 1000, 5000].into_iter().enumerate() {
+            let keys = random_keys(frame as u64 + 1, capacity, u32::MAX);
+            let render_world = app.sub_app_mut(RenderApp).world_mut();
+            let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+            render_world.resource::<RenderQueue>().write_buffer(
+                global_keys_buffer(sbufs, true).unwrap(),
+                0,
+                bytemuck::cast_slice(&keys),
+            );
+            render_world.insert_resource(SortLength(len));
+            app.update();
+
+            let render_world = app.sub_app(RenderApp).world();
+            let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+            let output_keys = read_buffer(
+                render_world.resource::<RenderDevice>(),
+                render_world.resource::<RenderQueue>(),
+                sorted_keys_buffer(sbufs, &(0..4), true).unwrap(),
+                capacity as usize,
+            );
+
+            let sorted = len.min(capacity) as usize;
+            let mut expected = keys[..sorted].to_vec();
+            expected.sort();
+            assert_eq!(output_keys[..sorted], expected, "length {len}");
+            assert_eq!(output_keys[sorted..], keys[sorted..], "length {len}");
+        }
+    }
+
+    #[test]
+    fn test_job_uploads() {
+        let count = 3000;
+
+        // The upload of the first job in a command buffer of its own, then every upload inline
+        for overlap_uploads in [true, false] {
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin {
+                    settings: 4096.into(),
+                });
+            run_once(&mut app);
+
+            let render_world = app.sub_app_mut(RenderApp).world_mut();
+            render_world
+                .resource_mut::<RadixSortJobsConfig>()
+                .overlap_uploads = overlap_uploads;
+            let render_device = render_world.resource::<RenderDevice>().clone();
+
+            // An argsort of uploaded keys, then a sort of uploaded pairs from the odd side
+            let keys_a = random_keys(3, count, u32::MAX);
+            let keys_b = random_keys(11, count, u32::MAX);
+            let vals_b: Vec<u32> = (0..count).map(|i| i * 3 + 1).collect();
+            let mut expected_a: Vec<(u32, u32)> = keys_a.iter().copied().zip(0..).collect();
+            expected_a.sort_by_key(|&(key, _)| key);
+            let mut expected_b: Vec<(u32, u32)> =
+                keys_b.iter().copied().zip(vals_b.iter().copied()).collect();
+            expected_b.sort_by_key(|&(key, _)| key);
+
+            let mut outputs = Vec::new();
+            for (keys, vals, init_index, read_from_even) in [
+                (&keys_a, None, true, true),
+                (&keys_b, Some(&vals_b), false, false),
+            ] {
+                let output_keys = create_storage_buffer(&render_device, &vec![0; count as usize]);
+                let output_vals = create_storage_buffer(&render_device, &vec![0; count as usize]);
+                let (dst_keys, dst_vals) = (output_keys.clone(), output_vals.clone());
+                let job = SortJob::new(count)
+                    .with_options(RadixSortRunOptions {
+                        init_index,
+                        ..default()
+                    })
+                    .with_read_from_even(read_from_even)
+                    .with_upload(
+                        create_storage_buffer(&render_device, keys),
+                        vals.map(|vals| create_storage_buffer(&render_device, vals)),
+                    )
+                    .with_on_complete(move |encoder, output| {
+                        let size = output.count as u64 * 4;
+                        encoder.copy_buffer_to_buffer(output.keys.unwrap(), 0, &dst_keys, 0, size);
+                        encoder.copy_buffer_to_buffer(output.vals.unwrap(), 0, &dst_vals, 0, size);
+                    });
+                render_world.resource_mut::<RadixSortJobs>().push(job);
+                outputs.push((output_keys, output_vals));
+            }
+            app.update();
+
+            let render_world = app.sub_app(RenderApp).world();
+            let render_queue = render_world.resource::<RenderQueue>();
+            for ((output_keys, output_vals), expected) in
+                outputs.iter().zip([expected_a, expected_b])
+            {
+                let keys = read_buffer(&render_device, render_queue, output_keys, count as usize);
+                let vals = read_buffer(&render_device, render_queue, output_vals, count as usize);
+                let output: Vec<(u32, u32)> = keys.into_iter().zip(vals).collect();
+                assert_eq!(output, expected, "overlap_uploads {overlap_uploads}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_job_redirect() {
+        let count = 3000;
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 4096.into(),
+            });
+        run_once(&mut app);
+
+        let render_world = app.sub_app_mut(RenderApp).world_mut();
+        render_world
+            .resource_mut::<RadixSortJobsConfig>()
+            .swap_global_buffers = true;
+        let render_device = render_world.resource::<RenderDevice>().clone();
+
+        // The low byte only, one pass from the even side would end on the odd side
+        let keys: Vec<u32> = (0..count).map(|i| (i * 7919) % 256).collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort_by_key(|&(key, _)| key);
+
+        let output_keys = create_storage_buffer(&render_device, &vec![0; count as usize]);
+        let output_vals = create_storage_buffer(&render_device, &vec![0; count as usize]);
+        let (redirect_keys, redirect_vals) = (output_keys.clone(), output_vals.clone());
+        let job = SortJob::new(count)
+            .with_options(RadixSortRunOptions::for_key_bits(8))
+            .with_upload(create_storage_buffer(&render_device, &keys), None)
+            .with_redirect(output_keys.clone(), Some(output_vals.clone()))
+            .with_on_complete(move |_, output| {
+                assert_eq!(output.keys.map(Buffer::id), Some(redirect_keys.id()));
+                assert_eq!(output.vals.map(Buffer::id), Some(redirect_vals.id()));
+            });
+        render_world.resource_mut::<RadixSortJobs>().push(job);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        let render_queue = render_world.resource::<RenderQueue>();
+        let sorted_keys = read_buffer(&render_device, render_queue, &output_keys, count as usize);
+        let sorted_vals = read_buffer(&render_device, render_queue, &output_vals, count as usize);
+        let sorted: Vec<(u32, u32)> = sorted_keys.into_iter().zip(sorted_vals).collect();
+        assert_eq!(sorted, expected);
+
+        // The results aren't in the global buffers, they aren't swapped
+        let events = render_world.resource::<Events<crate::GlobalBuffersSwapped>>();
+        assert_eq!(events.iter_current_update_events().count(), 0);
+    }
+}
@@ -0,0 +1,350 @@
+//! Sorting the keys by a transform of their bits, applied by the histogram and scatter kernels as they extract the
+//! digits of the passes. The keys are stored untransformed: e.g. [`KeyTransform::FloatFlip`] sorts the bits of `f32`
+//! keys in float order, without a keygen mapping them with [`crate::f32_to_ordered_u32`] first.
+//!
+//! ```ignore
+//! let settings = RadixSortSettings::from(1 << 20).with_key_transform(KeyTransform::FloatFlip);
+//! ```
+//!
+//! The built-in transforms are shader defs of the kernels, a [`KeyTransform::Custom`] expression is compiled into
+//! the `bevy_radix_sort::custom_key_transform` module, one per app. Changing the transform re-queues the pipelines,
+//! see [`crate::SettingsChanges::kernels`].
+//!
+//! The bitonic sort, the validation of the `verify-sorts` feature and the maximum and order checks of
+//! [`crate::run_auto`] and [`crate::run_unless_sorted`] transform the keys too. The other helper passes reading the
+//! keys themselves see the stored keys, e.g. the [`crate::top_k`], the statistics and the digit histograms, and
+//! [`crate::run_cpu`] sorts them untransformed.
+
+use bevy::{asset::load_internal_asset, prelude::*, render::render_resource::ShaderDefVal};
+use wgpu::naga;
+
+use crate::RadixSortSettings;
+
+pub const KEY_TRANSFORM_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(204955061044160780431352567711086290417);
+
+/// The module holding the `custom_transform_key` function of a [`KeyTransform::Custom`] expression.
+pub const CUSTOM_KEY_TRANSFORM_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(95130089468437603630782528997297794364);
+
+/// The name of the key in the expression of a [`KeyTransform::Custom`].
+pub const CUSTOM_KEY_TRANSFORM_ARGUMENT: &str = "k";
+
+/// The transform of the keys the sort orders them by, [`KeyTransform::None`] by default.
+#[derive(Reflect, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum KeyTransform {
+    #[default]
+    None,
+    /// Flips the sign bit, the bits of `i32` keys sort in signed order.
+    SignFlip,
+    /// Flips the sign bit of positive floats and all the bits of negative ones, the bits of `f32` keys sort in float
+    /// order, see [`crate::f32_to_ordered_u32`].
+    FloatFlip,
+    /// Flips all the bits, a descending sort. Unlike a reversed ascending sort, equal keys keep their order.
+    Complement,
+    /// Clears the bits outside the mask, the other bits don't take part in the order, e.g. the payload packed in the
+    /// low bits of a key.
+    Mask(u32),
+    /// A WGSL expression of the key `k: u32` evaluating to a `u32`, e.g. `"(k >> 16u) | (k << 16u)"`.
+    ///
+    /// See [`Self::validate`], the pipelines fail to build otherwise.
+    Custom(String),
+}
+
+impl KeyTransform {
+    /// The transformed `key` the order follows, `None` for a [`Self::Custom`] expression.
+    pub fn apply(&self, key: u32) -> Option<u32> {
+        match self {
+            Self::None => Some(key),
+            Self::SignFlip => Some(key ^ 0x8000_0000),
+            Self::FloatFlip => Some(crate::f32_to_ordered_u32(f32::from_bits(key))),
+            Self::Complement => Some(!key),
+            Self::Mask(mask) => Some(key & mask),
+            Self::Custom(_) => None,
+        }
+    }
+
+    /// The shader defs selecting the transform in `bevy_radix_sort::key_transform`, for the pipelines importing it.
+    pub fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        match self {
+            Self::None => vec![],
+            Self::SignFlip => vec![ShaderDefVal::UInt("KEY_TRANSFORM_XOR".into(), 0x8000_0000)],
+            Self::FloatFlip => vec!["KEY_TRANSFORM_FLOAT_FLIP".into()],
+            Self::Complement => vec![ShaderDefVal::UInt("KEY_TRANSFORM_XOR".into(), u32::MAX)],
+            Self::Mask(mask) => vec![ShaderDefVal::UInt("KEY_TRANSFORM_AND".into(), *mask)],
+            Self::Custom(_) => vec!["KEY_TRANSFORM_CUSTOM".into()],
+        }
+    }
+
+    /// Checks that the expression of a [`Self::Custom`] compiles, the built-in transforms always do.
+    pub fn validate(&self) -> Result<(), KeyTransformError> {
+        let Self::Custom(expression) = self else {
+            return Ok(());
+        };
+
+        let source = custom_transform_function(expression);
+        let invalid = |message: String| KeyTransformError {
+            expression: expression.clone(),
+            message,
+        };
+        let module = naga::front::wgsl::parse_str(&source)
+            .map_err(|err| invalid(err.emit_to_string(&source)))?;
+        // An expression closing the function could sneak in other items
+        if module.functions.len() != 1
+            || !module.entry_points.is_empty()
+            || !module.global_variables.is_empty()
+        {
+            return Err(invalid("the expression declares other items".into()));
+        }
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .map_err(|err| invalid(err.into_inner().to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// The reason [`KeyTransform::validate`] rejects a [`KeyTransform::Custom`] expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyTransformError {
+    pub expression: String,
+    /// The message of the WGSL compiler.
+    pub message: String,
+}
+
+impl std::fmt::Display for KeyTransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the key transform `{}` doesn't compile: {}",
+            self.expression, self.message
+        )
+    }
+}
+
+impl std::error::Error for KeyTransformError {}
+
+fn custom_transform_function(expression: &str) -> String {
+    format!(
+        "fn custom_transform_key({CUSTOM_KEY_TRANSFORM_ARGUMENT}: u32) -> u32 {{\n    return {expression};\n}}\n"
+    )
+}
+
+/// The source of `bevy_radix_sort::custom_key_transform`, the identity unless `key_transform` is a
+/// [`KeyTransform::Custom`].
+fn custom_key_transform_source(key_transform: &KeyTransform) -> String {
+    let expression = match key_transform {
+        KeyTransform::Custom(expression) => expression.as_str(),
+        _ => CUSTOM_KEY_TRANSFORM_ARGUMENT,
+    };
+
+    format!(
+        "#define_import_path bevy_radix_sort::custom_key_transform\n\n{}",
+        custom_transform_function(expression)
+    )
+}
+
+fn custom_key_transform_shader(key_transform: &KeyTransform) -> Shader {
+    Shader::from_wgsl(
+        custom_key_transform_source(key_transform),
+        "bevy_radix_sort/custom_key_transform.wgsl",
+    )
+}
+
+/// Loads `key_transform.wgsl`, and the custom module of `key_transform` it imports.
+pub(crate) fn load_key_transform_shader(app: &mut App, key_transform: &KeyTransform) {
+    load_internal_asset!(
+        app,
+        KEY_TRANSFORM_SHADER_HANDLE,
+        "key_transform.wgsl",
+        Shader::from_wgsl
+    );
+
+    app.world_mut().resource_mut::<Assets<Shader>>().insert(
+        CUSTOM_KEY_TRANSFORM_SHADER_HANDLE.id(),
+        custom_key_transform_shader(key_transform),
+    );
+}
+
+/// Compiles the expression of a changed [`KeyTransform::Custom`] into the custom module, before the render world
+/// re-queues the pipelines importing it.
+pub(crate) fn update_custom_key_transform_shader(
+    radix_sort_settings: Res<RadixSortSettings>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    let key_transform = radix_sort_settings.key_transform();
+    if !matches!(key_transform, KeyTransform::Custom(_)) {
+        return;
+    }
+
+    let source = custom_key_transform_source(key_transform);
+    let unchanged = shaders
+        .get(&CUSTOM_KEY_TRANSFORM_SHADER_HANDLE)
+        .is_some_and(|shader| shader.source.as_str() == source);
+    if !unchanged {
+        shaders.insert(
+            CUSTOM_KEY_TRANSFORM_SHADER_HANDLE.id(),
+            custom_key_transform_shader(key_transform),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use bevy::render::{
+        render_asset::RenderAssets,
+        render_resource::{CommandEncoderDescriptor, PipelineCache},
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortBindGroup, RadixSortPipeline, RadixSortPlugin,
+        SettingsError, global_keys_buffer, global_vals_buffer, run,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    /// Sorts `keys` on the GPU with `key_transform`, checks the stored keys and their indices against a stable sort
+    /// by `compare`.
+    fn check_sort(key_transform: KeyTransform, compare: impl Fn(u32, u32) -> Ordering) {
+        let number_of_keys = 20_000u32;
+        let keys: Vec<u32> = [0, 1, 0x7FFF_FFFF, 0x8000_0000, u32::MAX]
+            .into_iter()
+            .chain((5..number_of_keys).map(|i| i.wrapping_mul(2654435761)))
+            .collect();
+        let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+        expected_vals.sort_by(|&a, &b| compare(keys[a as usize], keys[b as usize]));
+        let expected_keys: Vec<u32> = expected_vals.iter().map(|&i| keys[i as usize]).collect();
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(number_of_keys)
+                    .with_key_transform(key_transform.clone()),
+            });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                render_queue.write_buffer(
+                    global_keys_buffer(&sbufs, true).unwrap(),
+                    0,
+                    bytemuck::cast_slice(&keys),
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: key transform command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let len = number_of_keys as usize;
+                let sorted_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    global_keys_buffer(&sbufs, true).unwrap(),
+                    len,
+                );
+                let sorted_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    global_vals_buffer(&sbufs, true).unwrap(),
+                    len,
+                );
+                assert_eq!(sorted_keys, expected_keys, "{key_transform:?}");
+                assert_eq!(sorted_vals, expected_vals, "{key_transform:?}");
+            },
+        );
+    }
+
+    #[test]
+    fn test_sign_flip() {
+        check_sort(KeyTransform::SignFlip, |a, b| (a as i32).cmp(&(b as i32)));
+    }
+
+    #[test]
+    fn test_float_flip() {
+        // The total order of the floats, NaNs included
+        check_sort(KeyTransform::FloatFlip, |a, b| {
+            f32::from_bits(a).total_cmp(&f32::from_bits(b))
+        });
+    }
+
+    #[test]
+    fn test_complement() {
+        check_sort(KeyTransform::Complement, |a, b| b.cmp(&a));
+    }
+
+    #[test]
+    fn test_mask() {
+        let mask = 0x00FF_F000;
+        check_sort(KeyTransform::Mask(mask), |a, b| (a & mask).cmp(&(b & mask)));
+    }
+
+    #[test]
+    fn test_custom() {
+        check_sort(
+            KeyTransform::Custom("(k >> 16u) | (k << 16u)".into()),
+            |a, b| a.rotate_right(16).cmp(&b.rotate_right(16)),
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        for key in [0, 1, 0x7FFF_FFFF, 0x8000_0000, u32::MAX] {
+            assert_eq!(KeyTransform::None.apply(key), Some(key));
+            assert_eq!(KeyTransform::Complement.apply(key), Some(u32::MAX - key));
+            assert_eq!(
+                KeyTransform::FloatFlip.apply(key),
+                Some(crate::f32_to_ordered_u32(f32::from_bits(key)))
+            );
+        }
+        assert_eq!(KeyTransform::Custom("k".into()).apply(1), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(KeyTransform::Mask(0xFF).validate(), Ok(()));
+        assert_eq!(
+            KeyTransform::Custom("k * 3u + 1u".into()).validate(),
+            Ok(())
+        );
+
+        for expression in ["k +", "0.5", "k; } fn other() -> u32 { return 0u"] {
+            let err = KeyTransform::Custom(expression.into())
+                .validate()
+                .unwrap_err();
+            assert_eq!(err.expression, expression);
+            assert!(!err.message.is_empty(), "{expression}");
+        }
+
+        let built = RadixSortSettings::builder()
+            .max_keys(1024)
+            .key_transform(KeyTransform::Custom("k +".into()))
+            .build();
+        assert_eq!(built.unwrap_err(), SettingsError::InvalidKeyTransform);
+    }
+}
@@ -0,0 +1,26 @@
+#define_import_path bevy_radix_sort::key_transform
+
+// The transform of `KeyTransform` the passes extract their digits from, set by the shader defs of the pipelines.
+// The keys are stored untransformed, only their order follows the transformed keys.
+
+#ifdef KEY_TRANSFORM_CUSTOM
+#import bevy_radix_sort::custom_key_transform::custom_transform_key
+#endif
+
+fn transform_key(key: u32) -> u32 {
+    var transformed = key;
+#ifdef KEY_TRANSFORM_FLOAT_FLIP
+    // Negative floats have all their bits flipped, positive ones only the sign bit
+    transformed ^= select(0x80000000u, 0xFFFFFFFFu, (key & 0x80000000u) != 0u);
+#endif
+#ifdef KEY_TRANSFORM_XOR
+    transformed ^= #{KEY_TRANSFORM_XOR}u;
+#endif
+#ifdef KEY_TRANSFORM_AND
+    transformed &= #{KEY_TRANSFORM_AND}u;
+#endif
+#ifdef KEY_TRANSFORM_CUSTOM
+    transformed = custom_transform_key(transformed);
+#endif
+    return transformed;
+}
@@ -0,0 +1,168 @@
+//! The WGSL module `bevy_radix_sort::keys`, shared by the sort shaders and user shaders generating keys.
+//!
+//! It holds the constants of the sort (digit size, tile size, ..) set from the Rust constants of the same names,
+//! the digit extraction of the passes and the mappings of [`crate::ordered_keys`]. `radix_sort.wgsl` imports it
+//! too, so the keys generated by a user shader can't drift from what the sort expects:
+//!
+//! ```wgsl
+//! #import bevy_radix_sort::keys
+//!
+//! @compute @workgroup_size(keys::NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+//! fn main(/* .. */) {
+//!     global_keys_o[index] = keys::f32_to_ordered_u32(depth);
+//! }
+//! ```
+//!
+//! It also defines the layouts shared with the Rust side: `SortParams` ([`crate::GpuSortParams`]) and
+//! `RadixSortGpuParams`, the push constants of the sort kernels ([`crate::RadixSortGpuParams`]).
+//!
+//! The module is loaded by the [`crate::RadixSortPlugin`].
+
+use bevy::{asset::load_internal_asset, prelude::*, render::render_resource::ShaderDefVal};
+
+use crate::{
+    NUMBER_OF_RADIX, NUMBER_OF_RADIX_BITS, NUMBER_OF_ROWS_PER_WORKGROUP,
+    NUMBER_OF_THREADS_PER_WORKGROUP, ORDERED_KEYS_SHADER_HANDLE,
+};
+
+pub const KEYS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(149732404989501625041882290246864828614);
+
+/// The import path of `keys.wgsl`.
+pub const KEYS_IMPORT_PATH: &str = "bevy_radix_sort::keys";
+
+/// The shader defs setting the constants of `keys.wgsl`.
+fn keys_shader_defs() -> Vec<ShaderDefVal> {
+    vec![
+        ShaderDefVal::UInt("NUMBER_OF_RADIX_BITS".into(), NUMBER_OF_RADIX_BITS),
+        ShaderDefVal::UInt("NUMBER_OF_RADIX".into(), NUMBER_OF_RADIX),
+        ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        ),
+        ShaderDefVal::UInt(
+            "NUMBER_OF_ROWS_PER_WORKGROUP".into(),
+            NUMBER_OF_ROWS_PER_WORKGROUP,
+        ),
+    ]
+}
+
+/// Loads `keys.wgsl` with its constants, and `ordered_keys.wgsl` importing it.
+///
+/// The plugins whose shaders import either call this in `build`.
+pub(crate) fn load_keys_shader(app: &mut App) {
+    load_internal_asset!(
+        app,
+        ORDERED_KEYS_SHADER_HANDLE,
+        "ordered_keys.wgsl",
+        Shader::from_wgsl
+    );
+
+    let path = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("keys.wgsl")
+        .to_string_lossy()
+        .into_owned();
+    app.world_mut().resource_mut::<Assets<Shader>>().insert(
+        KEYS_SHADER_HANDLE.id(),
+        Shader::from_wgsl_with_defs(include_str!("keys.wgsl"), path, keys_shader_defs()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::PipelineCache,
+        renderer::{RenderDevice, RenderQueue},
+    };
+
+    use crate::{
+        NUMBER_OF_BYTES_PER_KEY, RadixSortPlugin, f32_to_ordered_u32, i32_to_ordered_u32,
+        passes_needed,
+        test_utils::{
+            TestShaderPipeline, TestShaderPlugin, create_render_test_app, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// A user shader writing the constants, then the digits and the ordered keys of each input.
+    const TEST_SHADER: &str = r"
+#import bevy_radix_sort::keys
+
+@group(0) @binding(0) var<storage, read      > inputs: array<u32>;
+@group(0) @binding(1) var<storage, read_write> outputs: array<u32>;
+
+@compute @workgroup_size(keys::NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3u) {
+    let index = global_invocation_id.x;
+    if index == 0u {
+        outputs[0] = keys::NUMBER_OF_RADIX_BITS;
+        outputs[1] = keys::NUMBER_OF_RADIX;
+        outputs[2] = keys::NUMBER_OF_PASSES;
+        outputs[3] = keys::NUMBER_OF_THREADS_PER_WORKGROUP;
+        outputs[4] = keys::NUMBER_OF_ROWS_PER_WORKGROUP;
+        outputs[5] = keys::NUMBER_OF_KEYS_PER_SCATTER_BLOCK;
+    }
+    if index >= arrayLength(&inputs) {
+        return;
+    }
+
+    let key = inputs[index];
+    let base = 6u + 6u * index;
+    for (var pass_index = 0u; pass_index < keys::NUMBER_OF_PASSES; pass_index++) {
+        outputs[base + pass_index] = keys::extract_digit(key, pass_index);
+    }
+    outputs[base + 4u] = keys::f32_to_ordered_u32(bitcast<f32>(key));
+    outputs[base + 5u] = keys::i32_to_ordered_u32(bitcast<i32>(key));
+}
+";
+
+    #[test]
+    fn test_import_keys() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin { settings: 1.into() })
+            .add_plugins(TestShaderPlugin(TEST_SHADER));
+
+        let inputs: Vec<u32> = [0, 1, 0x1234_5678, 0x8000_0000, u32::MAX]
+            .into_iter()
+            .chain((0..1_000u32).map(|i| i.wrapping_mul(2654435761)))
+            .collect();
+
+        let number_of_passes = passes_needed(NUMBER_OF_BYTES_PER_KEY * 8);
+        let mut expected = vec![
+            NUMBER_OF_RADIX_BITS,
+            NUMBER_OF_RADIX,
+            number_of_passes,
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+            NUMBER_OF_ROWS_PER_WORKGROUP,
+            NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP,
+        ];
+        for &key in &inputs {
+            expected.extend((0..number_of_passes).map(|pass_index| {
+                (key >> (pass_index * NUMBER_OF_RADIX_BITS)) & (NUMBER_OF_RADIX - 1)
+            }));
+            expected.push(f32_to_ordered_u32(f32::from_bits(key)));
+            expected.push(i32_to_ordered_u32(key as i32));
+        }
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  test_shader_pipeline: Res<TestShaderPipeline>| {
+                let outputs = test_shader_pipeline.run(
+                    &render_device,
+                    &render_queue,
+                    &pipeline_cache,
+                    &inputs,
+                    expected.len(),
+                    (inputs.len() as u32).div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                );
+                assert_eq!(outputs, expected);
+            },
+        );
+    }
+}
@@ -0,0 +1,100 @@
+#define_import_path bevy_radix_sort::keys
+
+// The definitions shared by the sort shaders and the shaders generating their keys.
+//
+// ```wgsl
+// #import bevy_radix_sort::keys
+//
+// let digit = keys::extract_digit(key, pass_index);
+// ```
+//
+// The constants are set from the Rust constants of the same names when the module is loaded.
+// The module is self-contained, `sort_core` inlines it into the sort kernels without `naga_oil`.
+
+/// The number of bits of a digit, one pass sorts the keys by one digit.
+const NUMBER_OF_RADIX_BITS: u32 = #{NUMBER_OF_RADIX_BITS}u;
+/// The number of values of a digit.
+const NUMBER_OF_RADIX: u32 = #{NUMBER_OF_RADIX}u;
+/// The number of passes sorting all the bits of a key.
+const NUMBER_OF_PASSES: u32 = 32u / NUMBER_OF_RADIX_BITS;
+const NUMBER_OF_THREADS_PER_WORKGROUP: u32 = #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+const NUMBER_OF_ROWS_PER_WORKGROUP: u32 = #{NUMBER_OF_ROWS_PER_WORKGROUP}u;
+/// The number of keys a workgroup of the count and scatter steps processes, the tile size of the sort.
+const NUMBER_OF_KEYS_PER_SCATTER_BLOCK: u32 = NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP;
+
+/// The digit of `key` sorted by the pass `pass_index`, pass 0 sorts the least significant digit.
+fn extract_digit(key: u32, pass_index: u32) -> u32 {
+    return extractBits(key, pass_index * NUMBER_OF_RADIX_BITS, NUMBER_OF_RADIX_BITS);
+}
+
+const SIGN_BIT: u32 = 0x80000000u;
+
+/// Maps a f32 to a u32 so that the unsigned order of the keys matches the total order of the floats:
+/// -NaN < -inf < .. < -0.0 < +0.0 < .. < +inf < +NaN.
+fn f32_to_ordered_u32(f: f32) -> u32 {
+    let bits = bitcast<u32>(f);
+    let mask = select(SIGN_BIT, 0xFFFFFFFFu, (bits & SIGN_BIT) != 0u);
+    return bits ^ mask;
+}
+
+/// The inverse of `f32_to_ordered_u32`.
+fn ordered_u32_to_f32(key: u32) -> f32 {
+    let mask = select(0xFFFFFFFFu, SIGN_BIT, (key & SIGN_BIT) != 0u);
+    return bitcast<f32>(key ^ mask);
+}
+
+/// Maps an i32 to a u32 so that the unsigned order of the keys matches the order of the integers.
+fn i32_to_ordered_u32(i: i32) -> u32 {
+    return bitcast<u32>(i) ^ SIGN_BIT;
+}
+
+/// The inverse of `i32_to_ordered_u32`.
+fn ordered_u32_to_i32(key: u32) -> i32 {
+    return bitcast<i32>(key ^ SIGN_BIT);
+}
+
+/// The parameters of a GPU-driven sort (`run_gpu_driven`), written by a user shader into a buffer of 16 bytes:
+///
+/// ```text
+///  offset  field      
+///  0       count      the number of keys, clamped to the capacity the sort is recorded for
+///  4       pass_mask  bit `i` set if digit `i` of some key isn't zero, the passes up to the highest set bit run
+///  8       flags      `SORT_PARAMS_*`, the other bits are cleared
+///  12      padding
+/// ```
+///
+/// Must match `GpuSortParams` in `reduce_max.rs`.
+struct SortParams {
+    count: u32,
+    pass_mask: u32,
+    flags: u32,
+    padding: u32,
+}
+
+/// The first pass writes the indices of the keys into the vals, ignored by keys-only sorts.
+const SORT_PARAMS_INIT_INDEX: u32 = 1u;
+
+/// The push constants of the kernels of `radix_sort.wgsl`, 24 bytes set before each dispatch of a step:
+///
+/// ```text
+///  offset  field             
+///  0       workgroup_offset  the workgroups dispatched before by the same step, see `radix_sort.wgsl`
+///  4       number_of_keys    the number of keys sorted
+///  8       number_of_blks    the number of blocks (histograms), ceil(number_of_keys / NUMBER_OF_KEYS_PER_SCATTER_BLOCK)
+///  12      pass_index        the digit sorted, 0 for the least significant one
+///  16      sweep_size        the stride of the up-sweep and down-sweep of the scan step, 0 in the other steps
+///  20      init_index        1 if the scatter writes the indices of the keys into the vals, in the first pass only
+/// ```
+///
+/// Must match `RadixSortGpuParams` in `sort_core.rs`. `RADIX_SORT_GPU_PARAMS_VERSION` changes with the layout.
+struct RadixSortGpuParams {
+    workgroup_offset: u32,
+    number_of_keys: u32,
+    number_of_blks: u32,
+    pass_index: u32,
+    sweep_size: u32,
+    init_index: u32,
+}
+
+/// The version of the layout of `RadixSortGpuParams`.
+const RADIX_SORT_GPU_PARAMS_VERSION: u32 = 1u;
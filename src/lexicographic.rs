@@ -0,0 +1,516 @@
+//! Sorts by several u32 fields lexicographically, chaining one stable [`crate::run`] per field.
+//!
+//! An LSD sort of the tuples is a stable sort by each field, from the least to the most significant one. The first
+//! sort copies its field into the global keys and initializes the vals with the indices, each next sort gathers
+//! its field through the vals, the permutation of the sorts before it, and sorts on from the side they ended on:
+//!
+//! ```text
+//!  fields           (most significant first)  [ 1, 0, 1, 0 ]  [ 5, 7, 2, 7 ]
+//!  sort by field 1  keys [ 2, 5, 7, 7 ]  vals [ 2, 0, 1, 3 ]
+//!  gather field 0   keys [ 1, 1, 0, 0 ]  vals [ 2, 0, 1, 3 ]
+//!  sort by field 0  keys [ 0, 0, 1, 1 ]  vals [ 1, 3, 2, 0 ]   ties keep the order of field 1
+//! ```
+//!
+//! The sorted vals hold the index of each tuple in the fields, and the sorted keys the most significant field.
+//! Each field pays for its own passes, a field of small keys can skip the high digits with
+//! [`FieldSpec::pass_range`].
+
+use std::{fmt, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup,
+    RadixSortPipeline, compute_pipelines_load_state, dispatch_workgroup_ext, global_keys_buffer,
+    global_vals_buffer, is_output_even, passes_needed, read_from_even_starting_on, run,
+};
+
+pub const LEXICOGRAPHIC_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(191693526803880039739418862092980959549);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..8,
+};
+
+/// One field of the tuples [`run_lexicographic`] sorts.
+#[derive(Debug, Clone)]
+pub struct FieldSpec<'a> {
+    /// The keys of the field, in the original order of the tuples. The least significant field is copied from
+    /// it, which needs the `COPY_SRC` usage.
+    pub keys: &'a Buffer,
+    /// The passes sorting the field, all the passes of [`crate::RadixSortSettings::key_bits`] with `None`.
+    pub pass_range: Option<Range<u32>>,
+}
+
+impl<'a> FieldSpec<'a> {
+    pub fn new(keys: &'a Buffer) -> Self {
+        Self {
+            keys,
+            pass_range: None,
+        }
+    }
+
+    /// Sorts the field over `pass_range` only, e.g. `0..1` for keys below 256.
+    pub fn with_pass_range(mut self, pass_range: Range<u32>) -> Self {
+        self.pass_range = Some(pass_range);
+        self
+    }
+}
+
+/// The reasons [`run_lexicographic`] rejects the fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexicographicError {
+    /// There is no field to sort by.
+    NoFields,
+    /// The permutation lives in the vals, the sort can't be keys-only.
+    KeysOnly,
+    /// The tuples are more than the global buffers hold.
+    TooManyKeys { number_of_keys: u32, capacity: u32 },
+    /// The keys buffer of field `field` holds fewer than `number_of_keys` u32s.
+    FieldTooSmall { field: usize, len: u32 },
+}
+
+impl fmt::Display for LexicographicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoFields => write!(f, "a lexicographic sort needs at least one field"),
+            Self::KeysOnly => write!(
+                f,
+                "a lexicographic sort keeps the permutation in the vals, the sort can't be keys-only"
+            ),
+            Self::TooManyKeys {
+                number_of_keys,
+                capacity,
+            } => write!(
+                f,
+                "the fields hold {number_of_keys} keys, more than the capacity of {capacity} keys"
+            ),
+            Self::FieldTooSmall { field, len } => write!(
+                f,
+                "the keys buffer of field {field} holds {len} keys, fewer than the keys to sort"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LexicographicError {}
+
+pub struct LexicographicPlugin;
+
+impl Plugin for LexicographicPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            LEXICOGRAPHIC_SHADER_HANDLE,
+            "lexicographic.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<LexicographicPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LexicographicPipeline {
+    /// Gather the keys of the next field through the permutation
+    gather_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read_write> keys: array<u32>;
+    /// @binding(1) var<storage, read      > vals: array<u32>;
+    /// @binding(2) var<storage, read      > field: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for LexicographicPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "lexicographic bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let gather_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("lexicographic: gather pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: LEXICOGRAPHIC_SHADER_HANDLE,
+            shader_defs: cdefs,
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            gather_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl LexicographicPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[("lexicographic gather_pipeline", self.gather_pipeline)],
+        )
+    }
+
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        keys: &Buffer,
+        vals: &Buffer,
+        field: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "lexicographic: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                keys.as_entire_binding(),
+                vals.as_entire_binding(),
+                field.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Writes `keys[i] = field[vals[i]]` for each of the first `number_of_keys` keys.
+    pub fn record_gather(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        if number_of_keys == 0 {
+            return;
+        }
+
+        let pipeline = pipeline_cache
+            .get_compute_pipeline(self.gather_pipeline)
+            .unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("lexicographic compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+/// Sorts the first `number_of_keys` tuples of `fields` lexicographically, see the module docs.
+///
+/// `fields` goes from the most to the least significant field. The sort starts from the `read_from_even` side of
+/// the global buffers, and returns the side it ends on: the sorted vals, the index of each tuple, are in
+/// [`crate::global_vals_buffer`] of it, and the sorted keys of the most significant field in
+/// [`crate::global_keys_buffer`]. As [`crate::run`] sorts stably, tuples equal in every field keep their order.
+#[allow(clippy::too_many_arguments)]
+pub fn run_lexicographic(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    lexicographic_pipeline: &LexicographicPipeline,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    fields: &[FieldSpec],
+    read_from_even: bool,
+) -> Result<bool, LexicographicError> {
+    let Some((least_significant, more_significant)) = fields.split_last() else {
+        return Err(LexicographicError::NoFields);
+    };
+    if !radix_sort_pipeline.allocate_values() {
+        return Err(LexicographicError::KeysOnly);
+    }
+
+    let input_keys = global_keys_buffer(sbufs, read_from_even).unwrap();
+    let capacity = (input_keys.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+    if number_of_keys > capacity {
+        return Err(LexicographicError::TooManyKeys {
+            number_of_keys,
+            capacity,
+        });
+    }
+    for (field, spec) in fields.iter().enumerate() {
+        let len = (spec.keys.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+        if len < number_of_keys {
+            return Err(LexicographicError::FieldTooSmall { field, len });
+        }
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!(
+        "radix_sort::run_lexicographic",
+        number_of_keys,
+        number_of_fields = fields.len()
+    )
+    .entered();
+
+    let all_passes = 0..passes_needed(radix_sort_pipeline.key_bits());
+    let pass_range_of = |spec: &FieldSpec| spec.pass_range.clone().unwrap_or(all_passes.clone());
+
+    encoder.copy_buffer_to_buffer(
+        least_significant.keys,
+        0,
+        input_keys,
+        0,
+        number_of_keys as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+    );
+    let pass_range = pass_range_of(least_significant);
+    let stage_read_from_even = read_from_even_starting_on(&pass_range, read_from_even);
+    run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.clone(),
+        true,
+        stage_read_from_even,
+    );
+    let mut even = is_output_even(&pass_range, stage_read_from_even);
+
+    for spec in more_significant.iter().rev() {
+        let gather_bind_group = lexicographic_pipeline.create_bind_group(
+            render_device,
+            global_keys_buffer(sbufs, even).unwrap(),
+            global_vals_buffer(sbufs, even).unwrap(),
+            spec.keys,
+        );
+        lexicographic_pipeline.record_gather(
+            encoder,
+            pipeline_cache,
+            &gather_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+
+        let pass_range = pass_range_of(spec);
+        let stage_read_from_even = read_from_even_starting_on(&pass_range, even);
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_range.clone(),
+            false,
+            stage_read_from_even,
+        );
+        even = is_output_even(&pass_range, stage_read_from_even);
+    }
+
+    Ok(even)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        RadixSortPlugin,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// Sorts the tuples of `fields` (most significant first) with the pass ranges of `pass_ranges`, and checks the
+    /// result against a stable CPU sort of the tuples.
+    fn run_lexicographic_test(
+        fields: Vec<Vec<u32>>,
+        pass_ranges: Vec<Option<Range<u32>>>,
+        read_from_even: bool,
+        expected_result: Result<(), LexicographicError>,
+    ) {
+        let number_of_keys = fields.first().map_or(0, Vec::len) as u32;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: number_of_keys.max(1).into(),
+        })
+        .add_plugins(LexicographicPlugin);
+
+        // `sort_by_key` is stable, equal tuples keep their original order
+        let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+        expected_vals.sort_by_key(|&i| {
+            fields
+                .iter()
+                .map(|f| f.get(i as usize).copied())
+                .collect::<Vec<_>>()
+        });
+        let expected_keys: Vec<u32> = fields.first().map_or(vec![], |most_significant| {
+            expected_vals
+                .iter()
+                .filter_map(|&i| most_significant.get(i as usize).copied())
+                .collect()
+        });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  lexicographic_pipeline: Res<LexicographicPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let field_bufs: Vec<Buffer> = fields
+                    .iter()
+                    .map(|field| create_storage_buffer(&render_device, field))
+                    .collect();
+                let specs: Vec<FieldSpec> = field_bufs
+                    .iter()
+                    .zip(&pass_ranges)
+                    .map(|(keys, pass_range)| FieldSpec {
+                        keys,
+                        pass_range: pass_range.clone(),
+                    })
+                    .collect();
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: lexicographic command encoder"),
+                });
+                let result = run_lexicographic(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &lexicographic_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    &specs,
+                    read_from_even,
+                );
+                assert_eq!(result.map(|_| ()), expected_result);
+                let Ok(even) = result else {
+                    return;
+                };
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = global_keys_buffer(&sbufs, even).unwrap();
+                let output_vals_buf = global_vals_buffer(&sbufs, even).unwrap();
+                let output_keys = read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                let output_vals = read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                assert_eq!(output_vals, expected_vals);
+                assert_eq!(output_keys, expected_keys);
+            },
+        );
+    }
+
+    /// (cluster, material, depth) tuples with many ties in the first two fields.
+    fn cluster_material_depth(number_of_keys: u32) -> Vec<Vec<u32>> {
+        let hash = |i: u32, seed: u32| (i ^ seed).wrapping_mul(2654435761).rotate_left(13);
+        vec![
+            (0..number_of_keys).map(|i| hash(i, 1) % 4).collect(),
+            (0..number_of_keys).map(|i| hash(i, 2) % 200).collect(),
+            // Ties in the least significant field too, only the original order breaks them
+            (0..number_of_keys).map(|i| hash(i, 3) % 5000).collect(),
+        ]
+    }
+
+    #[test]
+    fn test_lexicographic() {
+        let fields = cluster_material_depth(10_000);
+
+        // All passes of every field
+        run_lexicographic_test(fields.clone(), vec![None; 3], true, Ok(()));
+        // One pass for the small fields, so the sides alternate between odd and even pass counts
+        run_lexicographic_test(
+            fields,
+            vec![Some(0..1), Some(0..1), Some(0..2)],
+            false,
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn test_lexicographic_odd_first_pass() {
+        // The most significant field in its second byte, sorted by pass 1 alone
+        let mut fields = cluster_material_depth(5000);
+        fields[0].iter_mut().for_each(|key| *key <<= 8);
+        run_lexicographic_test(
+            fields,
+            vec![Some(1..2), Some(0..1), Some(0..2)],
+            true,
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn test_lexicographic_single_field() {
+        let fields = cluster_material_depth(3000).split_off(2);
+        run_lexicographic_test(fields, vec![None], true, Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        run_lexicographic_test(vec![], vec![], true, Err(LexicographicError::NoFields));
+        run_lexicographic_test(
+            vec![vec![1; 100], vec![2; 50]],
+            vec![None; 2],
+            true,
+            Err(LexicographicError::FieldTooSmall { field: 1, len: 50 }),
+        );
+    }
+}
@@ -0,0 +1,29 @@
+/// The keys of the next sort, on the side the previous sort ended on
+@group(0) @binding(0) var<storage, read_write> keys: array<u32>;
+/// The permutation of the previous sorts, the index of each key in the fields
+@group(0) @binding(1) var<storage, read      > vals: array<u32>;
+/// The keys of the next field, in the original order
+@group(0) @binding(2) var<storage, read      > field: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys of each field.
+    number_of_keys: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let key_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+    if key_index >= pc.number_of_keys {
+        return;
+    }
+
+    keys[key_index] = field[vals[key_index]];
+}
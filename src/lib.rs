@@ -1,24 +1,175 @@
 //! Radix sort algorithm used for sorting keys of type `u32`.
 
+pub mod apply_permutation;
+pub mod batch;
+pub mod bitonic;
+pub mod cell_ranges;
+pub mod claims;
+pub mod compact;
+pub mod debug_info;
+pub mod diagnostics;
+pub mod digest;
+pub mod digit_histograms;
+pub mod entity_sort;
+pub mod epilogue;
 pub mod get_subgroup_size;
+pub mod group_by;
+pub mod histogram_cache;
+pub mod job_timings;
+pub mod jobs;
+pub mod key_transform;
+pub mod keys;
+pub mod lexicographic;
+pub mod lower_bound;
+pub mod merge;
+pub mod morton;
+pub mod ordered_keys;
+pub mod output_redirect;
+pub mod packed_segments;
+pub mod payload_scatter;
+pub mod preserve_input;
+pub mod preset;
+pub mod readback;
+pub mod reduce_max;
+pub mod reinit;
+pub mod reverse;
+pub mod run_length;
+pub mod scan;
+pub mod settings;
+pub mod sort_core;
+pub mod startup;
+pub mod stats;
+pub mod status;
+pub mod swap;
+pub mod top_k;
+pub mod tuning;
+pub mod user_buffers;
+pub mod valid_count;
+pub mod view_depth;
+pub mod y_sort;
+pub use apply_permutation::*;
+pub use batch::*;
+pub use bitonic::*;
+pub use cell_ranges::*;
+pub use claims::*;
+pub use compact::*;
+pub use debug_info::*;
+pub use diagnostics::*;
+pub use digest::*;
+pub use digit_histograms::*;
+pub use entity_sort::*;
+pub use epilogue::*;
 pub use get_subgroup_size::*;
+pub use group_by::*;
+pub use histogram_cache::*;
+pub use job_timings::*;
+pub use jobs::*;
+pub use key_transform::*;
+pub use keys::*;
+pub use lexicographic::*;
+pub use lower_bound::*;
+pub use merge::*;
+pub use morton::*;
+pub use ordered_keys::*;
+pub use output_redirect::*;
+pub use packed_segments::*;
+pub use payload_scatter::*;
+pub use preserve_input::*;
+pub use preset::*;
+pub use readback::*;
+pub use reduce_max::*;
+pub use reinit::*;
+pub use reverse::*;
+pub use run_length::*;
+pub use scan::*;
+pub use settings::*;
+pub use sort_core::*;
+pub use startup::*;
+pub use stats::*;
+pub use status::*;
+pub use swap::*;
+pub use top_k::*;
+pub use tuning::*;
+pub use user_buffers::*;
+pub use valid_count::*;
+pub use view_depth::*;
+pub use y_sort::*;
+
+#[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+pub mod cpu;
+#[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+pub use cpu::*;
+
+#[cfg(all(feature = "verify-sorts", debug_assertions))]
+pub mod verify;
+#[cfg(all(feature = "verify-sorts", debug_assertions))]
+pub use verify::*;
+
+#[cfg(all(
+    any(feature = "subgroup_size_cache", feature = "pipeline_cache"),
+    not(target_arch = "wasm32")
+))]
+pub mod adapter_cache;
+#[cfg(all(
+    any(feature = "subgroup_size_cache", feature = "pipeline_cache"),
+    not(target_arch = "wasm32")
+))]
+pub use adapter_cache::*;
+
+#[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+pub mod subgroup_size_cache;
+#[cfg(all(feature = "subgroup_size_cache", not(target_arch = "wasm32")))]
+pub use subgroup_size_cache::*;
+
+#[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+pub mod pipeline_cache;
+#[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+pub use pipeline_cache::*;
+
+#[cfg(any(test, feature = "test-utils"))]
+#[doc(hidden)]
+pub mod test_utils;
+
+/// The commonly used types of a sort, `use bevy_radix_sort::prelude::*;`.
+///
+/// The helper passes (scan, top-k, keygens, ...) stay in the crate root.
+pub mod prelude {
+    #[doc(hidden)]
+    pub use crate::{
+        Algorithm, EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+        GetSubgroupSizePlugin, KeyTransform, KeyType, LoadProgress, LoadState,
+        ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+        RadixSortBindGroup, RadixSortInitialCount, RadixSortPipeline, RadixSortPipelineInfo,
+        RadixSortPlugin, RadixSortPreset, RadixSortRunOptions, RadixSortSettings,
+        RadixSortSettingsBuilder, RadixSortStatus, RadixSortStatusChanged, SettingsError,
+        SubgroupSize, check_load_state, global_keys_buffer, global_vals_buffer, is_input_even,
+        is_output_even, radix_sort_failed, radix_sort_loaded, run, run_with_options,
+        sorted_keys_buffer, sorted_vals_buffer,
+    };
+}
 
-use std::ops::Range;
+use std::{num::NonZeroU64, ops::Range, sync::Arc};
 
 use bevy::{
     asset::{RenderAssetUsages, load_internal_asset},
+    ecs::system::RunSystemOnce,
     prelude::*,
     render::{
-        Render, RenderApp, RenderSet,
-        render_asset::RenderAssets,
+        ExtractSchedule, Render, RenderApp, RenderSet,
+        extract_resource::{ExtractResource, extract_resource},
+        render_asset::{RenderAssets, prepare_assets},
         render_resource::{
-            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BufferUsages,
-            CachedComputePipelineId, CachedPipelineState, CommandEncoder, ComputePass,
-            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, PushConstantRange,
-            ShaderDefVal, ShaderStages,
-            binding_types::{storage_buffer, storage_buffer_read_only},
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, BufferDescriptor, BufferUsages, CachedComputePipelineId,
+            CachedPipelineState, CommandEncoder, ComputePass, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, ShaderDefVal, ShaderStages,
+            binding_types::{
+                storage_buffer, storage_buffer_read_only, storage_buffer_read_only_sized,
+                storage_buffer_sized,
+            },
         },
         renderer::RenderDevice,
+        settings::{WgpuFeatures, WgpuLimits},
         storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
     },
 };
@@ -34,6 +185,16 @@ pub const NUMBER_OF_BYTES_PER_KEY: u32 = std::mem::size_of::<u32>() as u32;
 pub const NUMBER_OF_RADIX_BITS: u32 = 8;
 /// The range of the radix, the range of the radix with 8 bits is [0, 255].
 pub const NUMBER_OF_RADIX: u32 = 1 << NUMBER_OF_RADIX_BITS;
+/// The size in bytes of a buffer holding one count per radix, as written by [`RadixSortPipeline::record_histogram`].
+pub const HISTOGRAM_BUFFER_SIZE: BufferAddress =
+    (NUMBER_OF_RADIX * NUMBER_OF_BYTES_PER_KEY) as BufferAddress;
+
+/// The number of passes needed to sort keys whose set bits all lie within the lowest `key_bits` bits.
+///
+/// For example, 30-bit Morton codes need `passes_needed(30) = 4` passes, keys below 65536 need 2.
+pub const fn passes_needed(key_bits: u32) -> u32 {
+    key_bits.div_ceil(NUMBER_OF_RADIX_BITS)
+}
 
 /// `WARP` is a term used by Nvidia to refer to a group of parallel threads that execute the same instruction set within a time slice.
 /// `WARP` also has synonymous terms such as `WAVEFRONT` (AMD), `SIMD Group` (Apple), etc.
@@ -74,7 +235,8 @@ pub const NUMBER_OF_THREADS_PER_WORKGROUP: u32 = NUMBER_OF_RADIX;
 ///
 /// The number is good for avoiding `Bank Conflict` in the `shared memory` of the GPU.
 ///
-/// TODO: Refactor to automatically select configurations to adapt to different hardware devices for maximum performance.
+/// The [`AutoTunePlugin`] times the candidates on the adapter and picks the [`TileConfig`] of the [`RadixSortCore`]s,
+/// this constant stays the tile of the plugin's own sort.
 pub const NUMBER_OF_ROWS_PER_WORKGROUP: u32 = 7;
 
 pub const RADIX_SORT_SHADER_HANDLE: Handle<Shader> =
@@ -138,202 +300,1044 @@ pub const ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE: Handle<ShaderStorageBuffer> =
 pub const ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE: Handle<ShaderStorageBuffer> =
     Handle::weak_from_u128(123456789012345678901234567890123456789);
 
+/// Returns the `EVE_*` (`even` = true) or `ODD_*` global keys buffer, `None` if it hasn't been prepared yet.
+pub fn global_keys_buffer(
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    even: bool,
+) -> Option<&Buffer> {
+    let handle = if even {
+        EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE
+    } else {
+        ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE
+    };
+
+    sbufs.get(handle.id()).map(|sbuf| &sbuf.buffer)
+}
+
+/// Returns the `EVE_*` (`even` = true) or `ODD_*` global vals buffer, `None` if it hasn't been prepared yet.
+pub fn global_vals_buffer(
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    even: bool,
+) -> Option<&Buffer> {
+    let handle = if even {
+        EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE
+    } else {
+        ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE
+    };
+
+    sbufs.get(handle.id()).map(|sbuf| &sbuf.buffer)
+}
+
+/// Returns the global blocks buffer holding the per-block radix histograms, `None` if it hasn't been prepared yet.
+pub fn global_blocks_buffer(sbufs: &RenderAssets<GpuShaderStorageBuffer>) -> Option<&Buffer> {
+    sbufs
+        .get(GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE.id())
+        .map(|sbuf| &sbuf.buffer)
+}
+
+/// Whether the pass `pass_index` of [`run`] reads the `EVE_*` (true) or `ODD_*` (false) global buffers,
+/// and writes the other side. Successive passes alternate, so the same `read_from_even` is passed for every pass.
+pub fn is_input_even(pass_index: u32, read_from_even: bool) -> bool {
+    (pass_index + read_from_even as u32) % 2 == 1
+}
+
+/// Whether [`run`] over `pass_range` leaves the sorted keys/vals in the `EVE_*` (true) or `ODD_*` (false) global buffers.
+pub fn is_output_even(pass_range: &Range<u32>, read_from_even: bool) -> bool {
+    (pass_range.end + read_from_even as u32) % 2 == 1
+}
+
+/// The `read_from_even` of [`run`] whose first pass over `pass_range` reads the `EVE_*` (`even` = true) or `ODD_*`
+/// global buffers, as [`is_input_even`] counts the passes from 0.
+pub(crate) fn read_from_even_starting_on(pass_range: &Range<u32>, even: bool) -> bool {
+    even ^ (pass_range.start % 2 == 1)
+}
+
+/// Returns the global keys buffer holding the result of [`run`] over `pass_range`.
+pub fn sorted_keys_buffer<'a>(
+    sbufs: &'a RenderAssets<GpuShaderStorageBuffer>,
+    pass_range: &Range<u32>,
+    read_from_even: bool,
+) -> Option<&'a Buffer> {
+    global_keys_buffer(sbufs, is_output_even(pass_range, read_from_even))
+}
+
+/// Returns the global vals buffer holding the result of [`run`] over `pass_range`.
+///
+/// With `init_index`, or with the indices written by a keygen pass, these are the original indices in sorted order,
+/// which can be bound as an instance-rate vertex buffer (see [`RadixSortSettings::with_extra_buffer_usages`])
+/// or as a read-only storage buffer indexed by `instance_index` to draw instances in sorted order without a readback.
+pub fn sorted_vals_buffer<'a>(
+    sbufs: &'a RenderAssets<GpuShaderStorageBuffer>,
+    pass_range: &Range<u32>,
+    read_from_even: bool,
+) -> Option<&'a Buffer> {
+    global_vals_buffer(sbufs, is_output_even(pass_range, read_from_even))
+}
+
+/// Sorts with the global buffers of the `settings`, in any position among the plugins of the app.
+///
+/// The plugin is wired up in [`Plugin::finish`], once the [`RenderPlugin`](bevy::render::RenderPlugin) is built.
+/// Added before it, the pipelines are created in [`Plugin::cleanup`] instead, after the `RenderDevice` exists: the
+/// helper plugins reading the [`RadixSortPipeline`] in their `finish` are then added after the `RenderPlugin`.
 pub struct RadixSortPlugin {
     pub settings: RadixSortSettings,
 }
 
+/// Reported by [`RadixSortPipelineInfo::unsupported`] when the [`RenderApp`] sub-app doesn't exist.
+const NO_RENDER_APP: &str =
+    "no RenderApp sub-app (RenderPlugin missing or disabled), the RadixSortPlugin is disabled";
+/// Reported by [`RadixSortPipelineInfo::unsupported`] when the [`RenderApp`] has no [`RenderDevice`] once every plugin
+/// finished, e.g. a `RenderPlugin` whose renderer creation is deferred.
+const NO_RENDER_DEVICE: &str = "no RenderDevice once the plugins finished (renderer creation failed or deferred), the RadixSortPlugin is disabled";
+
+/// The global buffers of the sort, see [`global_keys_buffer`].
+const GLOBAL_STORAGE_BUFFER_HANDLES: [Handle<ShaderStorageBuffer>; 5] = [
+    EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+    EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+    GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE,
+    ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+    ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+];
+
+impl RadixSortPlugin {
+    /// Releases the GPU resources of the sort: the global buffers in both worlds, and the [`RadixSortPipeline`],
+    /// [`RadixSortBindGroup`] and the other resources of the plugin in the [`RenderApp`] world.
+    ///
+    /// The systems of the plugin stay but do nothing afterwards. The plugin can't be added to the same [`App`]
+    /// twice, a new app gets fresh buffers.
+    pub fn shutdown(app: &mut App) {
+        remove_global_buffers(app.world_mut());
+        app.world_mut().remove_resource::<RadixSortPipelineInfo>();
+        app.world_mut().remove_resource::<RadixSortInitialCount>();
+        app.world_mut().remove_resource::<RadixSortStatus>();
+        app.world_mut().remove_resource::<RadixSortStatusCell>();
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        let world = render_app.world_mut();
+
+        if let Some(mut sbufs) = world.get_resource_mut::<RenderAssets<GpuShaderStorageBuffer>>() {
+            for handle in GLOBAL_STORAGE_BUFFER_HANDLES {
+                sbufs.remove(handle.id());
+            }
+        }
+        world.remove_resource::<RadixSortBindGroup>();
+        world.remove_resource::<RadixSortJobs>();
+        world.remove_resource::<JobTimestampQueries>();
+        world.remove_resource::<RadixSortPipeline>();
+        world.remove_resource::<RadixSortUnsupported>();
+        world.remove_resource::<RadixSortCreationErrors>();
+        world.remove_resource::<RadixSortInitialCount>();
+        world.remove_resource::<RadixSortStatusCell>();
+        #[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+        world.remove_resource::<CpuFallbackInitialized>();
+    }
+}
+
+/// Removes the global [`ShaderStorageBuffer`] assets from the main `world`, the [`RenderApp`] drops their GPU buffers
+/// once the removal is extracted. Their handles are weak, nothing else keeps them alive.
+pub fn remove_global_buffers(world: &mut World) {
+    let Some(mut sbufs) = world.get_resource_mut::<Assets<ShaderStorageBuffer>>() else {
+        return;
+    };
+
+    for handle in GLOBAL_STORAGE_BUFFER_HANDLES {
+        sbufs.remove(handle.id());
+    }
+}
+
+/// Releases the global buffers on [`AppExit`], before the render world drops.
+fn remove_global_buffers_on_exit(world: &mut World) {
+    if !world.resource::<Events<AppExit>>().is_empty() {
+        remove_global_buffers(world);
+    }
+}
+
 impl Plugin for RadixSortPlugin {
     fn build(&self, app: &mut App) {
+        // The rest is wired in `finish`, once the plugins added after this one (e.g. `DefaultPlugins`) are built
+        if !app.is_plugin_added::<GetSubgroupSizePlugin>() {
+            app.add_plugins(GetSubgroupSizePlugin::default());
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        if app.get_sub_app(RenderApp).is_none() {
+            warn!("radix_sort: {}", NO_RENDER_APP);
+            app.insert_resource(RadixSortPipelineInfo {
+                unsupported: Some(NO_RENDER_APP.to_string()),
+                ..default()
+            })
+            .insert_resource(RadixSortStatus::Failed {
+                message: NO_RENDER_APP.to_string(),
+                adapter: String::new(),
+            });
+            return;
+        }
+
         load_internal_asset!(
             app,
             RADIX_SORT_SHADER_HANDLE,
             "radix_sort.wgsl",
             Shader::from_wgsl
         );
+        load_internal_asset!(
+            app,
+            BITONIC_SORT_SHADER_HANDLE,
+            "bitonic.wgsl",
+            Shader::from_wgsl
+        );
+        #[cfg(all(feature = "verify-sorts", debug_assertions))]
+        load_internal_asset!(
+            app,
+            VERIFY_SORT_SHADER_HANDLE,
+            "verify.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            SORT_STATS_SHADER_HANDLE,
+            "stats.wgsl",
+            Shader::from_wgsl
+        );
+        load_keys_shader(app);
+        load_key_transform_shader(app, self.settings.key_transform());
+
+        create_shader_storage_buffers(
+            &mut app
+                .world_mut()
+                .resource_mut::<Assets<ShaderStorageBuffer>>(),
+            &self.settings,
+        );
 
-        create_shader_storage_buffers(app, self.settings.max_number_of_keys());
-
-        app.insert_resource(self.settings);
+        let initial_count = RadixSortInitialCount(self.settings.initial_count());
+        app.register_type::<RadixSortSettings>()
+            .register_type::<KeyType>()
+            .register_type::<KeyTransform>()
+            .register_type::<Algorithm>()
+            .register_type::<RadixSortInitialCount>()
+            .register_type::<RadixSortPipelineInfo>()
+            .insert_resource(self.settings.clone())
+            .insert_resource(AppliedRadixSortSettings(self.settings.clone()))
+            .insert_resource(initial_count)
+            .add_systems(
+                PostUpdate,
+                (
+                    reallocate_global_buffers,
+                    update_custom_key_transform_shader,
+                )
+                    .run_if(resource_changed::<RadixSortSettings>),
+            )
+            .add_systems(Last, remove_global_buffers_on_exit);
         app.sub_app_mut(RenderApp)
-            .insert_resource(self.settings)
+            .insert_resource(self.settings.clone())
+            .insert_resource(AppliedRadixSortSettings(self.settings.clone()))
+            .insert_resource(initial_count)
+            .add_systems(ExtractSchedule, extract_resource::<RadixSortSettings>)
             .add_systems(
                 Render,
-                RadixSortBindGroup::initialize
+                (
+                    reinitialize_radix_sort
+                        .in_set(RenderSet::PrepareResources)
+                        .run_if(resource_changed::<RadixSortSettings>),
+                    push_buffer_error_scopes
+                        .in_set(RenderSet::PrepareAssets)
+                        .before(prepare_assets::<GpuShaderStorageBuffer>),
+                    pop_buffer_error_scopes
+                        .in_set(RenderSet::PrepareAssets)
+                        .after(prepare_assets::<GpuShaderStorageBuffer>),
+                    prepare_user_buffers
+                        .in_set(RenderSet::PrepareAssets)
+                        .after(prepare_assets::<GpuShaderStorageBuffer>)
+                        .run_if(resource_exists::<RadixSortPipeline>),
+                    reset_global_buffers_claims
+                        .in_set(RenderSet::ExtractCommands)
+                        .run_if(resource_exists::<RadixSortPipeline>),
+                    RadixSortBindGroup::initialize
+                        .in_set(RenderSet::PrepareBindGroups)
+                        .run_if(resource_exists::<RadixSortPipeline>)
+                        .run_if(not(resource_exists::<RadixSortBindGroup>))
+                        .run_if(not(resource_exists::<RadixSortCreationErrors>)),
+                    poll_sort_stats
+                        .in_set(RenderSet::Render)
+                        .after(bevy::render::renderer::render_system)
+                        .run_if(resource_exists::<RadixSortPipeline>),
+                ),
+            )
+            .init_resource::<BufferErrorScopes>()
+            .init_resource::<Events<RadixSortStats>>();
+        build_jobs(app.sub_app_mut(RenderApp));
+        build_startup(app);
+        build_status(app);
+        build_swap(app);
+        build_debug_info(app);
+
+        #[cfg(all(feature = "verify-sorts", debug_assertions))]
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            poll_sort_verifications
+                .in_set(RenderSet::Render)
+                .after(bevy::render::renderer::render_system)
+                .run_if(resource_exists::<RadixSortPipeline>),
+        );
+
+        #[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+        if cpu_fallback_enabled(&self.settings) {
+            app.sub_app_mut(RenderApp).add_systems(
+                Render,
+                initialize_cpu_fallback
                     .in_set(RenderSet::PrepareBindGroups)
-                    .run_if(not(resource_exists::<RadixSortBindGroup>)),
+                    .run_if(resource_exists::<RadixSortUnsupported>)
+                    .run_if(not(resource_exists::<CpuFallbackInitialized>))
+                    .run_if(not(resource_exists::<RadixSortCreationErrors>)),
             );
+        }
+
+        // Added before the `RenderPlugin`, its `finish` creates the `RenderDevice` after this one, see `cleanup`
+        if app
+            .sub_app(RenderApp)
+            .world()
+            .contains_resource::<RenderDevice>()
+        {
+            self.initialize(app);
+        }
     }
 
-    fn finish(&self, app: &mut App) {
+    fn cleanup(&self, app: &mut App) {
+        if !app.world().contains_resource::<RadixSortPipelineInfo>() {
+            self.initialize(app);
+        }
+    }
+}
+
+impl RadixSortPlugin {
+    /// Resolves the [`SubgroupSize`] and creates the pipelines, once the [`RenderDevice`] exists.
+    fn initialize(&self, app: &mut App) {
+        // Plugins finish in the order they were added, an auto-added `GetSubgroupSizePlugin` after this one
+        GetSubgroupSizePlugin::resolve(app);
+
         let render_app = app.sub_app_mut(RenderApp);
-        render_app.init_resource::<RadixSortPipeline>();
+        if !render_app.world().contains_resource::<RenderDevice>() {
+            error!("radix_sort: {}", NO_RENDER_DEVICE);
+            app.insert_resource(RadixSortPipelineInfo {
+                unsupported: Some(NO_RENDER_DEVICE.to_string()),
+                ..default()
+            });
+            app.world()
+                .resource::<RadixSortStatusCell>()
+                .publish(RadixSortStatus::Failed {
+                    message: NO_RENDER_DEVICE.to_string(),
+                    adapter: String::new(),
+                });
+            return;
+        }
+        render_app
+            .world()
+            .resource::<RadixSortStatusCell>()
+            .watch_device_lost(render_app.world().resource::<RenderDevice>());
+
+        #[cfg(not(feature = "render_startup"))]
+        let info = run_radix_sort_startup(render_app.world_mut());
+        // Bevy runs `RenderStartup` itself, the published info replaces this one on the next frame
+        #[cfg(feature = "render_startup")]
+        let info = RadixSortPipelineInfo::default();
+        app.insert_resource(info);
+    }
+}
+
+/// A main world summary of the render world [`RadixSortPipeline`], which holds GPU handles, e.g. for inspectors.
+#[derive(Resource, Reflect, Debug, Clone, Default, PartialEq, Eq)]
+#[reflect(Resource, Debug, Default)]
+pub struct RadixSortPipelineInfo {
+    /// See [`RadixSortPipeline::subgroup_fallback`].
+    pub subgroup_fallback: bool,
+    /// See [`RadixSortPipeline::allocate_values`].
+    pub allocate_values: bool,
+    /// See [`RadixSortSettings::with_memory_mode`].
+    pub memory_mode: MemoryMode,
+    /// The pipelines weren't created, see [`RadixSortUnsupported`].
+    pub unsupported: Option<String>,
+}
+
+/// The number of storage buffers the bind group of the [`RadixSortPipeline`] binds to the compute stage.
+pub const NUMBER_OF_STORAGE_BUFFERS_PER_STAGE: u32 = 5;
+
+/// The bytes of workgroup memory the scatter kernel stages a tile in, with the emulated subgroups. The ballots of
+/// [`multisplit_supported`] subgroups need less.
+pub const MIN_WORKGROUP_STORAGE_SIZE: u32 = scatter_workgroup_storage_size(0, true);
+
+/// Returns an error naming the limit if the adapter can't create the pipelines of the sort.
+///
+/// Downlevel adapters (DX11-class, some Android GPUs) may allow as few as 4 storage buffers per shader stage.
+pub fn check_limits(limits: &WgpuLimits) -> Result<(), String> {
+    if limits.max_storage_buffers_per_shader_stage < NUMBER_OF_STORAGE_BUFFERS_PER_STAGE {
+        return Err(format!(
+            "max_storage_buffers_per_shader_stage is {}, the sort needs at least {}",
+            limits.max_storage_buffers_per_shader_stage, NUMBER_OF_STORAGE_BUFFERS_PER_STAGE
+        ));
     }
+
+    if limits.max_compute_workgroup_storage_size < MIN_WORKGROUP_STORAGE_SIZE {
+        return Err(format!(
+            "max_compute_workgroup_storage_size is {}, the sort needs at least {}",
+            limits.max_compute_workgroup_storage_size, MIN_WORKGROUP_STORAGE_SIZE
+        ));
+    }
+
+    Ok(())
 }
 
-fn create_shader_storage_buffers(app: &mut App, max_number_of_keys: u32) {
-    let number_of_keys_per_scatter_block =
-        NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP;
-    let max_number_of_blks = max_number_of_keys.div_ceil(number_of_keys_per_scatter_block);
+/// Inserted into the [`RenderApp`] world instead of the [`RadixSortPipeline`] when the adapter doesn't meet
+/// [`check_limits`], [`check_load_state`] then reports the reason.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct RadixSortUnsupported(pub String);
 
-    let mut sbufs = app
-        .world_mut()
-        .resource_mut::<Assets<ShaderStorageBuffer>>();
+/// Inserted into the [`RenderApp`] world when creating the buffers, layouts or bind groups of the sort raised wgpu
+/// errors, e.g. a capacity over `max_buffer_size`. [`check_load_state`] then reports them as [`LoadState::Failed`].
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct RadixSortCreationErrors(pub Vec<String>);
 
-    let usages = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
-    let size = (max_number_of_keys * NUMBER_OF_BYTES_PER_KEY) as usize;
+/// Captures the validation and out-of-memory errors of the following device calls, see [`pop_error_scopes`].
+///
+/// No-op on wasm32, where the scopes resolve asynchronously.
+pub(crate) fn push_error_scopes(render_device: &RenderDevice) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let device = render_device.wgpu_device();
+        device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = render_device;
+}
 
-    let mut eve_global_keys_buf =
-        ShaderStorageBuffer::with_size(size, RenderAssetUsages::default());
-    eve_global_keys_buf.buffer_description.label =
-        Some("radix_sort: global_keys buffer - input when even-pass, output when odd-pass");
-    eve_global_keys_buf.buffer_description.usage = usages;
+/// Returns the errors raised since [`push_error_scopes`].
+pub(crate) fn pop_error_scopes(render_device: &RenderDevice) -> Vec<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let device = render_device.wgpu_device();
+        let validation = bevy::tasks::block_on(device.pop_error_scope());
+        let out_of_memory = bevy::tasks::block_on(device.pop_error_scope());
+
+        validation
+            .into_iter()
+            .chain(out_of_memory)
+            .map(|err| err.to_string())
+            .collect()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = render_device;
+        Vec::new()
+    }
+}
 
-    let mut eve_global_vals_buf =
-        ShaderStorageBuffer::with_size(size, RenderAssetUsages::default());
-    eve_global_vals_buf.buffer_description.label =
-        Some("radix_sort: global_vals buffer - input when even-pass, output when odd-pass");
-    eve_global_vals_buf.buffer_description.usage = usages;
-    eve_global_vals_buf.buffer_description.mapped_at_creation = true;
+/// Inserts the [`RadixSortCreationErrors`] if `errors` isn't empty.
+fn report_creation_errors(commands: &mut Commands, errors: Vec<String>) {
+    if errors.is_empty() {
+        return;
+    }
+
+    for err in &errors {
+        error!(
+            "radix_sort: failed to create the resources of the sort: {}",
+            err
+        );
+    }
+    commands.insert_resource(RadixSortCreationErrors(errors));
+}
+
+/// Whether [`push_buffer_error_scopes`] pushed scopes this frame.
+#[derive(Resource, Debug, Default)]
+struct BufferErrorScopes {
+    pushed: bool,
+}
+
+/// Captures the errors of the creation of the global buffers, until they're prepared.
+fn push_buffer_error_scopes(
+    mut scopes: ResMut<BufferErrorScopes>,
+    render_device: Res<RenderDevice>,
+    sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+) {
+    if global_keys_buffer(&sbufs, true).is_none() {
+        push_error_scopes(&render_device);
+        scopes.pushed = true;
+    }
+}
+
+fn pop_buffer_error_scopes(
+    mut commands: Commands,
+    mut scopes: ResMut<BufferErrorScopes>,
+    render_device: Res<RenderDevice>,
+) {
+    if std::mem::take(&mut scopes.pushed) {
+        report_creation_errors(&mut commands, pop_error_scopes(&render_device));
+    }
+}
+
+/// Inserts the global buffers of the `settings`, replacing the previous ones, see [`reinit`].
+fn create_shader_storage_buffers(
+    sbufs: &mut Assets<ShaderStorageBuffer>,
+    settings: &RadixSortSettings,
+) {
+    let max_number_of_keys = settings.max_number_of_keys();
+
+    let usages = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let global_usages = usages | settings.extra_buffer_usages();
+    let size = (max_number_of_keys * settings.key_type().size()) as usize;
+    let vals_size = (settings.max_number_of_values() * NUMBER_OF_BYTES_PER_KEY) as usize;
 
     let mut global_blocks_buf = ShaderStorageBuffer::with_size(
-        (max_number_of_blks * NUMBER_OF_RADIX * NUMBER_OF_BYTES_PER_KEY) as usize,
+        blocks_buffer_size(max_number_of_keys) as usize,
         RenderAssetUsages::default(),
     );
     global_blocks_buf.buffer_description.label = Some("radix_sort: global_blocks buffer");
     global_blocks_buf.buffer_description.usage = usages;
 
+    // The render world binds the user buffers under the handles of the global keys/vals buffers instead
+    if settings.user_buffers().is_some() {
+        sbufs.insert(GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE.id(), global_blocks_buf);
+        for handle in [
+            EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+            ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+        ] {
+            sbufs.remove(handle.id());
+        }
+        return;
+    }
+
+    let mut eve_global_keys_buf =
+        ShaderStorageBuffer::with_size(size, RenderAssetUsages::default());
+    eve_global_keys_buf.buffer_description.label =
+        Some("radix_sort: global_keys buffer - input when even-pass, output when odd-pass");
+    eve_global_keys_buf.buffer_description.usage = global_usages;
+
     let mut odd_global_keys_buf =
         ShaderStorageBuffer::with_size(size, RenderAssetUsages::default());
     odd_global_keys_buf.buffer_description.label =
         Some("radix_sort: global_keys buffer - input when odd-pass, output when even-pass");
-    odd_global_keys_buf.buffer_description.usage = usages;
+    odd_global_keys_buf.buffer_description.usage = global_usages;
 
-    let mut odd_global_vals_buf =
-        ShaderStorageBuffer::with_size(size, RenderAssetUsages::default());
-    odd_global_vals_buf.buffer_description.label =
-        Some("radix_sort: global_vals buffer - input when odd-pass, output when even-pass");
-    odd_global_vals_buf.buffer_description.usage = usages;
-    odd_global_vals_buf.buffer_description.mapped_at_creation = true;
+    if let Some(initial_keys) = settings.initial_keys() {
+        let mut data = vec![0; size];
+        let initial_keys: &[u8] = bytemuck::cast_slice(initial_keys);
+        data[..initial_keys.len()].copy_from_slice(initial_keys);
+        eve_global_keys_buf.data = Some(data);
+    }
 
     sbufs.insert(
         EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id(),
         eve_global_keys_buf,
     );
-    sbufs.insert(
-        EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id(),
-        eve_global_vals_buf,
-    );
     sbufs.insert(GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE.id(), global_blocks_buf);
     sbufs.insert(
         ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id(),
         odd_global_keys_buf,
     );
-    sbufs.insert(
-        ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id(),
-        odd_global_vals_buf,
-    );
+
+    if settings.allocate_values() && settings.memory_mode() == MemoryMode::Compact {
+        // The indices are unpacked into it after each sort, it's never initialized
+        let mut compact_vals_buf =
+            ShaderStorageBuffer::with_size(vals_size, RenderAssetUsages::default());
+        compact_vals_buf.buffer_description.label =
+            Some("radix_sort: global_vals buffer - the indices unpacked by run_compact");
+        compact_vals_buf.buffer_description.usage = global_usages;
+
+        sbufs.insert(EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id(), compact_vals_buf);
+        sbufs.remove(ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id());
+    } else if settings.allocate_values() {
+        let mut eve_global_vals_buf =
+            ShaderStorageBuffer::with_size(vals_size, RenderAssetUsages::default());
+        eve_global_vals_buf.buffer_description.label =
+            Some("radix_sort: global_vals buffer - input when even-pass, output when odd-pass");
+        eve_global_vals_buf.buffer_description.usage = global_usages;
+        eve_global_vals_buf.buffer_description.mapped_at_creation = true;
+
+        let mut odd_global_vals_buf =
+            ShaderStorageBuffer::with_size(vals_size, RenderAssetUsages::default());
+        odd_global_vals_buf.buffer_description.label =
+            Some("radix_sort: global_vals buffer - input when odd-pass, output when even-pass");
+        odd_global_vals_buf.buffer_description.usage = global_usages;
+        odd_global_vals_buf.buffer_description.mapped_at_creation = true;
+
+        sbufs.insert(
+            EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id(),
+            eve_global_vals_buf,
+        );
+        sbufs.insert(
+            ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id(),
+            odd_global_vals_buf,
+        );
+    }
 }
 
-#[derive(Resource, Debug, Clone, Copy)]
+/// The number of keys the even global buffers hold after initialization, see [`RadixSortSettings::with_initial_keys`].
+///
+/// `0` without initial contents, sort `0..count` with [`run`] and `read_from_even` to sort the initial data.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[reflect(Resource, Debug, Default)]
+pub struct RadixSortInitialCount(pub u32);
+
+/// Reflected for inspectors, except for the [`BufferUsages`] which aren't reflectable.
+///
+/// Extracted to the render world when it changes, replacing it after startup re-initializes the sort, see [`reinit`].
+#[derive(Resource, ExtractResource, Reflect, Debug, Clone)]
+#[reflect(Resource, Debug)]
 pub struct RadixSortSettings {
     max_number_of_keys: u32,
+    /// The number of vals the global vals buffers hold, `None` for as many as the keys.
+    max_number_of_values: Option<u32>,
+    key_type: KeyType,
+    /// The transform of the keys the passes order them by.
+    key_transform: KeyTransform,
+    /// Usages added to the `STORAGE | COPY_SRC | COPY_DST` usages of the global keys/vals buffers.
+    #[reflect(ignore)]
+    #[reflect(default = "BufferUsages::empty")]
+    extra_buffer_usages: BufferUsages,
+    /// Emulate the subgroup operations even if the adapter supports them.
+    force_subgroup_fallback: bool,
+    /// The backend [`run`] sorts with.
+    algorithm: Algorithm,
+    /// Sort with [`Algorithm::Bitonic`] if the radix pipelines fail to build.
+    allow_fallback: bool,
+    /// Report [`LoadState::FallbackCpu`] instead of [`LoadState::Failed`], see `with_cpu_fallback`.
+    cpu_fallback: bool,
+    /// Create the global vals buffers, `false` for keys-only sorts.
+    allocate_values: bool,
+    /// Written into the even global keys buffer when it's created.
+    initial_keys: Option<Vec<u32>>,
+    /// Written into the even global vals buffer when it's created, instead of the leading indices.
+    initial_vals: Option<Vec<u32>>,
+    /// The low bits the keys may have set, `None` for all 32.
+    significant_key_bits: Option<u8>,
+    /// Sort these buffers instead of allocating the global keys/vals buffers.
+    user_buffers: Option<RadixSortUserBuffers>,
+    /// The number of global vals buffers.
+    memory_mode: MemoryMode,
 }
 
 impl RadixSortSettings {
+    /// Validates the settings on `build`, unlike `From<u32>`.
+    pub fn builder() -> RadixSortSettingsBuilder {
+        RadixSortSettingsBuilder::default()
+    }
+
+    /// The number of keys the global buffers hold, exactly the requested capacity.
     pub fn max_number_of_keys(&self) -> u32 {
         self.max_number_of_keys
     }
-}
 
-impl From<u32> for RadixSortSettings {
-    fn from(max_number_of_keys: u32) -> Self {
-        Self { max_number_of_keys }
+    /// The number of vals the global vals buffers hold, [`Self::max_number_of_keys`] unless
+    /// [`Self::with_max_number_of_values`] requested fewer.
+    pub fn max_number_of_values(&self) -> u32 {
+        self.max_number_of_values
+            .map_or(self.max_number_of_keys, |max_number_of_values| {
+                max_number_of_values.min(self.max_number_of_keys).max(1)
+            })
     }
-}
 
-/// ## Introduction
-///
-/// This implementation of the `radix-sort` algorithm is based on the paper:
-/// [Fast 4-way parallel radix sorting on GPUs](http://www.sci.utah.edu/~csilva/papers/cgf.pdf)
-///
-/// The radix sort algorithm can be divided into 3 steps:
-///
-/// 1. Count the number of each radix in the `block` and generate a histogram (count_radix_pipeline).
-/// 2. Perform prefix sum operations on the histogram (scan_upsweep_pipeline, scan_dnsweep_pipeline, scan_last_block_pipeline).
-/// 3. Based on the histogram information, write the key values in the `block` to new ordered positions (scatter_pipeline).
-///
-/// ## Modification
-///
-/// I have made modifications to the __Step 2: Compute prefix sum on the histogram__ in the paper to improve performance.
-///
-/// The memory layout of `global_histogram_buffer` in the paper is as follows:
-///
-/// ```text
-///               workgroup0      workgroup1        ...           workgroupN                                                                    
-///           +---------------+---------------+---------------+---------------+                                                                 
-/// radix 0   |      V_0      |      V_1      |     ...       |     V_n-1     |                                                                 
-///           +---------------+---------------+---------------+---------------+                                                                 
-/// radix 1   |      V_n      |      V_n+1    |     ...       |     V_2n-1    |                                                                 
-///           +---------------+---------------+---------------+---------------+                                                                 
-///  ...      |                              ...                              |                                                                 
-///           +---------------+---------------+---------------+---------------+                                                                 
-/// radix 255 |     V_255n    |     V_255n+1  |     ...       |    V_256n-1   |                                                                 
-///           +---------------+---------------+---------------+---------------+                                                                 
-/// ```
-///
-/// This memory layout is beneficial for __Step 2__ prefix sum calculations, but not for __Step 1__ and __Step 3__,
-/// because the memory access to `global_histogram_buffer` in __Step 1__ and __Step 3__ is `Non-Coalesced Memory Access`.
-///
-/// `Non-Coalesced Memory Access` can cause significant delays, leading to a drastic decrease in the performance of `radix_sort`.
-///  To address this issue, I redesigned the memory layout of `global_histogram_buffer`:
-///
-/// ```text
-///                  radix 0         radix 1          ...            radix 255                                                                    
-///             +---------------+---------------+---------------+---------------+                                                                 
-/// workgroup0  |      V_0      |      V_1      |     ...       |     V_255     |                                                                 
-///             +---------------+---------------+---------------+---------------+                                                                 
-/// workgroup1  |     V_256     |     V_257     |     ...       |     V_511     |                                                                 
-///             +---------------+---------------+---------------+---------------+                                                                 
-///    ...      |                              ...                              |                                                                 
-///             +---------------+---------------+---------------+---------------+                                                                 
-/// workgroupN  |     V_256n    |    V_256n+1   |     ...       |  V_256n+255   |                                                                 
-///             +---------------+---------------+---------------+---------------+                                                         
-/// ```
-///
-/// This solves the delay problem caused by `Non-Coalesced Memory Access`,
-/// but brings a new problem: how to perform prefix sum calculations on such a memory layout?
-///
-/// The idea is simple: change the unit of the prefix sum operation from `radix` to `histogram`,
-/// and perform the prefix sum operation on the `histogram`.
-/// This means accumulating `histogram-workgroup0` to `histogram-workgroup1`, `histogram-workgroup1` to `histogram-workgroup2`, and so on.
-///
-/// Of course, we cannot use a serial prefix sum as it would degrade performance.
-/// Therefore, we use the [Blelloch](https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda)
-/// parallel prefix sum algorithm, which consists of an up-sweep and a down-sweep step:
-///
-/// ### Up-Sweep
-///
-/// ```text
-///           ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐                                                       
-///           │H0│ │H1│ │H2│ │H3│ │H4│ │H5│ │H6│ │H7│                                                       
-///           └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘                                                       
-///            └───┬▼─┐  └───┬▼─┐  └───┬▼─┐  └───┬▼─┐                                                       
-/// Round 0        │H1│      │H3│      │H5│      │H7│                                                       
-///                └┴─┴──────┴┬─┘      └──┴──────┴┬─┘                                                       
-///                          ┌▼─┐                ┌▼─┐                                                       
-/// Round 1                  │H3│                │H7│                                                       
-///                          └──┴────────────────┴┬─┘                                                       
-///                                              ┌▼─┐                                                       
-/// Round 2                                      │H7│                                                       
-///                                              └──┘   
-/// ```
-///
-/// ### Down-Sweep
-///
-/// ```text
-///     Round 1   Round 0   Round 1                                                                          
-///     ┌────┐    ┌────┐    ┌────┐                                                                           
-/// ┌──┐│┌──┐│┌──┐│┌──┐│┌──┐│┌──┐│┌──┐ ┌──┐                                                                  
+    /// Allocates the global vals buffers for `max_number_of_values` vals only, e.g. for keys-only sorts of up to
+    /// the full capacity next to smaller key/value sorts.
+    ///
+    /// [`run`] refuses to initialize the indices of more keys, and the vals of a sort of more keys aren't
+    /// meaningful. Unlike the builder, the capacity is clamped to `1..=max_number_of_keys`, and the initial vals
+    /// are truncated to it once set.
+    pub fn with_max_number_of_values(mut self, max_number_of_values: u32) -> Self {
+        self.max_number_of_values = Some(max_number_of_values);
+        self
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    pub fn with_key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
+
+    pub fn key_transform(&self) -> &KeyTransform {
+        &self.key_transform
+    }
+
+    /// Orders the keys by `key_transform` of their bits, the keys are stored untransformed, see [`key_transform`].
+    ///
+    /// Unlike the builder, a [`KeyTransform::Custom`] expression isn't validated: the pipelines fail to build if it
+    /// doesn't compile.
+    pub fn with_key_transform(mut self, key_transform: KeyTransform) -> Self {
+        self.key_transform = key_transform;
+        self
+    }
+
+    pub fn extra_buffer_usages(&self) -> BufferUsages {
+        self.extra_buffer_usages
+    }
+
+    /// Adds `usages` to the global keys/vals buffers,
+    /// e.g. [`BufferUsages::VERTEX`] to bind the sorted vals as an instance-rate vertex buffer.
+    pub fn with_extra_buffer_usages(mut self, usages: BufferUsages) -> Self {
+        self.extra_buffer_usages |= usages;
+        self
+    }
+
+    pub fn force_subgroup_fallback(&self) -> bool {
+        self.force_subgroup_fallback
+    }
+
+    /// Emulates the subgroup operations in shared memory even if the adapter supports them (see
+    /// [`subgroups_supported`]), slower but independent of the subgroup size reported by the driver.
+    pub fn with_subgroup_fallback(mut self) -> Self {
+        self.force_subgroup_fallback = true;
+        self
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Sorts with `algorithm` instead of the radix sort, e.g. [`Algorithm::Bitonic`] for a few thousand keys.
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn allow_fallback(&self) -> bool {
+        self.allow_fallback
+    }
+
+    /// Also queues the [`Algorithm::Bitonic`] pipelines, [`run`] sorts with them if the radix pipelines fail to
+    /// build (e.g. the drivers reject the subgroup kernels). Mind that the bitonic sort isn't stable.
+    pub fn with_fallback(mut self) -> Self {
+        self.allow_fallback = true;
+        self
+    }
+
+    pub fn cpu_fallback(&self) -> bool {
+        self.cpu_fallback
+    }
+
+    /// [`check_load_state`] reports [`LoadState::FallbackCpu`] instead of [`LoadState::Failed`] when no GPU backend
+    /// can run, the sorts are then done by [`run_cpu`] on the same global buffers.
+    #[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+    pub fn with_cpu_fallback(mut self) -> Self {
+        self.cpu_fallback = true;
+        self
+    }
+
+    pub fn allocate_values(&self) -> bool {
+        self.allocate_values
+    }
+
+    /// Skips the global vals buffers for keys-only sorts (histograms, unique counting),
+    /// saving `2 * max_number_of_values * 4` bytes of VRAM.
+    ///
+    /// [`global_vals_buffer`] then returns `None`, and [`run`] refuses to initialize the indices.
+    pub fn without_values(mut self) -> Self {
+        self.allocate_values = false;
+        self
+    }
+
+    pub fn initial_keys(&self) -> Option<&[u32]> {
+        self.initial_keys.as_deref()
+    }
+
+    /// Uploads `keys` into the even global keys buffer once, when it's created, e.g. static data sorted at startup.
+    ///
+    /// Unlike the builder, keys past the capacity are dropped with a warning.
+    pub fn with_initial_keys(mut self, keys: Vec<u32>) -> Self {
+        self.initial_keys = Some(self.truncated(keys, "initial_keys", self.max_number_of_keys));
+        self
+    }
+
+    pub fn initial_vals(&self) -> Option<&[u32]> {
+        self.initial_vals.as_deref()
+    }
+
+    /// Uploads `vals` into the even global vals buffer once, when it's created. The rest of the buffer
+    /// still holds the indices, see [`run`] for `init_index`.
+    ///
+    /// Ignored for keys-only sorts (see [`Self::without_values`]), vals past the [`Self::max_number_of_values`] are
+    /// dropped with a warning.
+    pub fn with_initial_vals(mut self, vals: Vec<u32>) -> Self {
+        self.initial_vals = Some(self.truncated(vals, "initial_vals", self.max_number_of_values()));
+        self
+    }
+
+    /// The number of keys the even global buffers hold after initialization, the longer of the initial contents.
+    pub fn initial_count(&self) -> u32 {
+        if self.user_buffers.is_some() {
+            return 0;
+        }
+
+        let len = |data: Option<&[u32]>| data.map_or(0, |data| data.len() as u32);
+        let vals_len = if self.allocate_values && self.memory_mode == MemoryMode::Full {
+            len(self.initial_vals())
+        } else {
+            0
+        };
+
+        len(self.initial_keys()).max(vals_len)
+    }
+
+    /// The declared significant key bits, clamped to the 32 bits of the keys, 64 for [`KeyType::U64`].
+    pub fn significant_key_bits(&self) -> Option<u8> {
+        let max_bits = self.key_type.bits().max(NUMBER_OF_BYTES_PER_KEY * 8) as u8;
+        self.significant_key_bits.map(|bits| bits.min(max_bits))
+    }
+
+    /// Declares that the keys only set their lowest `bits` bits, e.g. 20 for cell hashes: the default pass range of
+    /// the sort skips the digits above them, see [`Self::default_pass_range`].
+    ///
+    /// Unlike the builder, more bits than the keys have are clamped by the getters, whether the key type is set
+    /// before or after. More than 64 bits are clamped with a warning.
+    pub fn with_significant_key_bits(mut self, bits: u8) -> Self {
+        let max_bits = KeyType::U64.bits() as u8;
+        if bits > max_bits {
+            warn!(
+                "radix_sort: significant_key_bits is {}, the keys have at most {} bits",
+                bits, max_bits
+            );
+        }
+
+        self.significant_key_bits = Some(bits.min(max_bits));
+        self
+    }
+
+    /// Checks the settings the unvalidated `with_*` setters may combine with [`KeyType::U64`] keys, which their
+    /// kernels don't support: [`RadixSortSettingsBuilder::build`] returns the error, the [`RadixSortPipeline`] logs
+    /// it and [`run`] rejects the sorts.
+    pub fn validate_key_type(&self) -> Result<(), SettingsError> {
+        if self.key_type != KeyType::U64 {
+            return Ok(());
+        }
+
+        let unsupported = if self.algorithm == Algorithm::Bitonic {
+            Some("the bitonic sort")
+        } else if self.allow_fallback {
+            Some("the bitonic fallback")
+        } else if self.cpu_fallback {
+            Some("the CPU fallback")
+        } else if self.key_transform != KeyTransform::None {
+            Some("key transforms")
+        } else if self.initial_keys.is_some() {
+            Some("initial keys")
+        } else if self.user_buffers.is_some() {
+            Some("user buffers")
+        } else {
+            None
+        };
+
+        unsupported.map_or(Ok(()), |unsupported| {
+            Err(SettingsError::U64Unsupported(unsupported))
+        })
+    }
+
+    /// The number of low bits the keys may have set, the [`KeyType::bits`] unless
+    /// [`Self::with_significant_key_bits`] declared fewer.
+    pub fn key_bits(&self) -> u32 {
+        let key_type_bits = self.key_type.bits();
+        self.significant_key_bits
+            .map_or(key_type_bits, |bits| u32::from(bits).min(key_type_bits))
+    }
+
+    /// The passes covering the [`Self::key_bits`], e.g. `0..3` for 20-bit keys.
+    pub fn default_pass_range(&self) -> Range<u32> {
+        0..passes_needed(self.key_bits())
+    }
+
+    /// An argsort over the [`Self::default_pass_range`], e.g. for [`SortJob::for_settings`].
+    pub fn default_run_options(&self) -> RadixSortRunOptions {
+        RadixSortRunOptions::for_key_bits(self.key_bits())
+    }
+
+    pub fn user_buffers(&self) -> Option<&RadixSortUserBuffers> {
+        self.user_buffers.as_ref()
+    }
+
+    /// Sorts the buffer assets of `user_buffers` instead of allocating the global keys/vals buffers, see
+    /// [`user_buffers`]. Only the global blocks buffer is still allocated.
+    ///
+    /// The initial contents are ignored, the user buffers keep theirs.
+    pub fn with_user_buffers(mut self, user_buffers: RadixSortUserBuffers) -> Self {
+        self.user_buffers = Some(user_buffers);
+        self
+    }
+
+    pub fn memory_mode(&self) -> MemoryMode {
+        self.memory_mode
+    }
+
+    /// The bits [`run_compact`] packs the indices of a full sort in, 0 unless in [`MemoryMode::Compact`].
+    pub fn compact_index_bits(&self) -> u32 {
+        match self.memory_mode {
+            MemoryMode::Full => 0,
+            MemoryMode::Compact => compact_index_bits(self.max_number_of_keys),
+        }
+    }
+
+    /// Allocates a single global vals buffer in [`MemoryMode::Compact`], argsorting with [`run_compact`].
+    ///
+    /// Unlike the builder, the constraints of the mode aren't validated: [`run_compact`] rejects the sorts of keys
+    /// too wide for their indices, and the initial vals are ignored.
+    pub fn with_memory_mode(mut self, memory_mode: MemoryMode) -> Self {
+        self.memory_mode = memory_mode;
+        self
+    }
+
+    fn truncated(&self, mut data: Vec<u32>, name: &str, capacity: u32) -> Vec<u32> {
+        let capacity = capacity as usize;
+        if data.len() > capacity {
+            warn!(
+                "radix_sort: {} holds {} elements, only the first {} fit the global buffers",
+                name,
+                data.len(),
+                capacity
+            );
+            data.truncate(capacity);
+        }
+
+        data
+    }
+
+    /// The size in bytes of all the global buffers the [`RadixSortPlugin`] creates.
+    pub fn allocated_size(&self) -> BufferAddress {
+        self.allocated_keys_size()
+            + self.allocated_vals_size()
+            + blocks_buffer_size(self.max_number_of_keys)
+    }
+
+    /// The size in bytes of both global keys buffers, 0 with user buffers.
+    pub fn allocated_keys_size(&self) -> BufferAddress {
+        if self.user_buffers.is_some() {
+            return 0;
+        }
+
+        2 * (self.max_number_of_keys * self.key_type.size()) as BufferAddress
+    }
+
+    /// The size in bytes of the global vals buffers, both of them or the single one of [`MemoryMode::Compact`],
+    /// 0 for keys-only sorts or with user buffers.
+    pub fn allocated_vals_size(&self) -> BufferAddress {
+        if self.user_buffers.is_some() || !self.allocate_values {
+            return 0;
+        }
+
+        let number_of_buffers = match self.memory_mode {
+            MemoryMode::Full => 2,
+            MemoryMode::Compact => 1,
+        };
+        number_of_buffers * (self.max_number_of_values() * NUMBER_OF_BYTES_PER_KEY) as BufferAddress
+    }
+}
+
+impl From<u32> for RadixSortSettings {
+    fn from(max_number_of_keys: u32) -> Self {
+        Self {
+            max_number_of_keys,
+            max_number_of_values: None,
+            key_type: KeyType::U32,
+            key_transform: KeyTransform::None,
+            extra_buffer_usages: BufferUsages::empty(),
+            force_subgroup_fallback: false,
+            algorithm: Algorithm::Radix,
+            allow_fallback: false,
+            cpu_fallback: false,
+            allocate_values: true,
+            initial_keys: None,
+            initial_vals: None,
+            significant_key_bits: None,
+            user_buffers: None,
+            memory_mode: MemoryMode::Full,
+        }
+    }
+}
+
+/// ## Introduction
+///
+/// This implementation of the `radix-sort` algorithm is based on the paper:
+/// [Fast 4-way parallel radix sorting on GPUs](http://www.sci.utah.edu/~csilva/papers/cgf.pdf)
+///
+/// The radix sort algorithm can be divided into 3 steps:
+///
+/// 1. Count the number of each radix in the `block` and generate a histogram (count_radix_pipeline).
+/// 2. Perform prefix sum operations on the histogram (scan_upsweep_pipeline, scan_dnsweep_pipeline, scan_last_block_pipeline).
+/// 3. Based on the histogram information, write the key values in the `block` to new ordered positions (scatter_pipeline).
+///
+/// ## Modification
+///
+/// I have made modifications to the __Step 2: Compute prefix sum on the histogram__ in the paper to improve performance.
+///
+/// The memory layout of `global_histogram_buffer` in the paper is as follows:
+///
+/// ```text
+///               workgroup0      workgroup1        ...           workgroupN                                                                    
+///           +---------------+---------------+---------------+---------------+                                                                 
+/// radix 0   |      V_0      |      V_1      |     ...       |     V_n-1     |                                                                 
+///           +---------------+---------------+---------------+---------------+                                                                 
+/// radix 1   |      V_n      |      V_n+1    |     ...       |     V_2n-1    |                                                                 
+///           +---------------+---------------+---------------+---------------+                                                                 
+///  ...      |                              ...                              |                                                                 
+///           +---------------+---------------+---------------+---------------+                                                                 
+/// radix 255 |     V_255n    |     V_255n+1  |     ...       |    V_256n-1   |                                                                 
+///           +---------------+---------------+---------------+---------------+                                                                 
+/// ```
+///
+/// This memory layout is beneficial for __Step 2__ prefix sum calculations, but not for __Step 1__ and __Step 3__,
+/// because the memory access to `global_histogram_buffer` in __Step 1__ and __Step 3__ is `Non-Coalesced Memory Access`.
+///
+/// `Non-Coalesced Memory Access` can cause significant delays, leading to a drastic decrease in the performance of `radix_sort`.
+///  To address this issue, I redesigned the memory layout of `global_histogram_buffer`:
+///
+/// ```text
+///                  radix 0         radix 1          ...            radix 255                                                                    
+///             +---------------+---------------+---------------+---------------+                                                                 
+/// workgroup0  |      V_0      |      V_1      |     ...       |     V_255     |                                                                 
+///             +---------------+---------------+---------------+---------------+                                                                 
+/// workgroup1  |     V_256     |     V_257     |     ...       |     V_511     |                                                                 
+///             +---------------+---------------+---------------+---------------+                                                                 
+///    ...      |                              ...                              |                                                                 
+///             +---------------+---------------+---------------+---------------+                                                                 
+/// workgroupN  |     V_256n    |    V_256n+1   |     ...       |  V_256n+255   |                                                                 
+///             +---------------+---------------+---------------+---------------+                                                         
+/// ```
+///
+/// This solves the delay problem caused by `Non-Coalesced Memory Access`,
+/// but brings a new problem: how to perform prefix sum calculations on such a memory layout?
+///
+/// The idea is simple: change the unit of the prefix sum operation from `radix` to `histogram`,
+/// and perform the prefix sum operation on the `histogram`.
+/// This means accumulating `histogram-workgroup0` to `histogram-workgroup1`, `histogram-workgroup1` to `histogram-workgroup2`, and so on.
+///
+/// Of course, we cannot use a serial prefix sum as it would degrade performance.
+/// Therefore, we use the [Blelloch](https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda)
+/// parallel prefix sum algorithm, which consists of an up-sweep and a down-sweep step:
+///
+/// ### Up-Sweep
+///
+/// ```text
+///           ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐ ┌──┐                                                       
+///           │H0│ │H1│ │H2│ │H3│ │H4│ │H5│ │H6│ │H7│                                                       
+///           └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘ └┬─┘                                                       
+///            └───┬▼─┐  └───┬▼─┐  └───┬▼─┐  └───┬▼─┐                                                       
+/// Round 0        │H1│      │H3│      │H5│      │H7│                                                       
+///                └┴─┴──────┴┬─┘      └──┴──────┴┬─┘                                                       
+///                          ┌▼─┐                ┌▼─┐                                                       
+/// Round 1                  │H3│                │H7│                                                       
+///                          └──┴────────────────┴┬─┘                                                       
+///                                              ┌▼─┐                                                       
+/// Round 2                                      │H7│                                                       
+///                                              └──┘   
+/// ```
+///
+/// ### Down-Sweep
+///
+/// ```text
+///     Round 1   Round 0   Round 1                                                                          
+///     ┌────┐    ┌────┐    ┌────┐                                                                           
+/// ┌──┐│┌──┐│┌──┐│┌──┐│┌──┐│┌──┐│┌──┐ ┌──┐                                                                  
 /// │H0│││H1│││H2│││H3│││H4│││H5│││H6│ │H7│                                                                  
 /// └──┘│└──┘│└▲─┘│└──┘│└▲─┘│└──┘│└▲─┘ └──┘                                                                  
 ///     │┌──┐│ │  │┌──┐│ │  │┌──┐│ │   ┌──┐                                                                  
@@ -414,6 +1418,13 @@ pub struct RadixSortPipeline {
     scan_last_block_pipeline: CachedComputePipelineId,
     /// Write the key values to new ordered positions based on the radix.
     scatter_pipeline: CachedComputePipelineId,
+    /// Count the histograms of every digit of the pass range of [`run`] in a single read of the keys.
+    digit_histograms_pipeline: CachedComputePipelineId,
+    /// Perform prefix sum (exclusive) operation on the histogram of each digit counted by the
+    /// `digit_histograms_pipeline`.
+    scan_digit_histograms_pipeline: CachedComputePipelineId,
+    /// The `scatter_pipeline` reading where each radix starts from the scanned digit histograms.
+    fused_scatter_pipeline: CachedComputePipelineId,
     /// The bindgroup layout is:
     ///
     /// ```wgsl
@@ -422,86 +1433,645 @@ pub struct RadixSortPipeline {
     /// @binding(2) var<storage, read_write> global_keys_o: array<u32>;
     /// ```
     bind_group_layout: BindGroupLayout,
+    /// The subgroup operations are emulated in shared memory.
+    subgroup_fallback: bool,
+    /// The global vals buffers exist, otherwise the kernels are compiled with `KEYS_ONLY`.
+    allocate_values: bool,
+    /// The kernels are compiled with `KEYS_ONLY` in [`MemoryMode::Compact`] too, [`run_compact`] packs the indices.
+    memory_mode: MemoryMode,
+    /// See [`RadixSortSettings::with_algorithm`].
+    algorithm: Algorithm,
+    /// See [`RadixSortSettings::key_bits`].
+    key_bits: u32,
+    /// The bits of the indices of a full sort in [`MemoryMode::Compact`], 0 otherwise.
+    compact_index_bits: u32,
+    /// [`run`] rejects the pass ranges other than `0..1` of [`KeyType::U8`] keys.
+    key_type: KeyType,
+    /// The [`KeyType::U64`] kernels load the keys as u64, see [`Self::native_u64`].
+    native_u64: bool,
+    /// [`run`] rejects the sorts of settings failing [`RadixSortSettings::validate_key_type`].
+    settings_error: Option<SettingsError>,
+    /// The kernels are compiled with its shader defs, see [`RadixSortSettings::with_key_transform`].
+    key_transform: KeyTransform,
+    /// Queued for [`Algorithm::Bitonic`] or [`RadixSortSettings::with_fallback`].
+    bitonic_pipeline: Option<BitonicSortPipeline>,
+    /// The sorts recorded by [`run`], drained by the [`RadixSortDiagnosticsPlugin`].
+    counters: Arc<RadixSortCounters>,
+    /// The owners of the global buffers in the current frame.
+    claims: Arc<GlobalBuffersClaims>,
+    /// The validation passes [`run`] appends with the `verify-sorts` feature.
+    #[cfg(all(feature = "verify-sorts", debug_assertions))]
+    verifier: Arc<SortVerifier>,
+    /// The statistics pass [`run_with_options`] appends with [`RadixSortRunOptions::stats`].
+    stats: Arc<SortStatsRecorder>,
+    /// The last sort [`run`] and the indirect sorts encoded.
+    debug_info: Arc<RadixSortDebugInfoRecorder>,
 }
 
 impl RadixSortPipeline {
     pub fn bind_group_layout(&self) -> &BindGroupLayout {
         &self.bind_group_layout
     }
-}
 
-impl FromWorld for RadixSortPipeline {
-    fn from_world(world: &mut World) -> Self {
-        let render_device = world.resource::<RenderDevice>();
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let subgroup_size = world.resource::<SubgroupSize>();
+    /// The layouts of the bind groups the pipelines use, by group index. The sort only uses group 0.
+    pub fn bind_group_layouts(&self) -> &[BindGroupLayout] {
+        std::slice::from_ref(&self.bind_group_layout)
+    }
 
-        let bind_group_layout = render_device.create_bind_group_layout(
-            "radix_sort bindgroup layout",
-            &BindGroupLayoutEntries::sequential(
-                ShaderStages::COMPUTE,
-                (
-                    // Read unsorted(sub-sort) keys from this buffer
-                    storage_buffer_read_only::<u32>(false),
-                    // Read unsorted(sub-sort) vals from this buffer
-                    storage_buffer_read_only::<u32>(false),
-                    // Read/Write histograms of count of each radix
-                    storage_buffer::<u32>(false),
-                    // Write sorted(sub-sort) keys to this buffer
-                    storage_buffer::<u32>(false),
-                    // Write sorted(sub-sort) vals to this buffer
-                    storage_buffer::<u32>(false),
-                ),
-            ),
-        );
+    /// Creates a [`RadixSortBindGroup`] sorting user buffers instead of the global ones, for [`run`] and the steps.
+    ///
+    /// The keys buffers must have the same size and the `STORAGE` usage, the sort holds `size / 4` keys, `size / 8`
+    /// for [`KeyType::U64`]. The vals buffers are required unless the sort is keys-only, and must hold a u32 per key.
+    /// The global blocks buffer is shared as scratch, so the keys can't outnumber the capacity of the
+    /// [`RadixSortSettings`]. [`Self::record_histogram`] always reads the global blocks after the count.
+    ///
+    /// The buffers don't need to be [`ShaderStorageBuffer`] assets, e.g. the buffers of another crate: the bind group
+    /// keeps them alive, like a clone of a [`Buffer`] would.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_bind_group_for(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        keys_eve: &Buffer,
+        keys_odd: &Buffer,
+        vals_eve: Option<&Buffer>,
+        vals_odd: Option<&Buffer>,
+    ) -> Result<RadixSortBindGroup, String> {
+        if keys_eve.size() != keys_odd.size() {
+            return Err(format!(
+                "the keys buffers have different sizes ({} and {} bytes)",
+                keys_eve.size(),
+                keys_odd.size()
+            ));
+        }
+        let keys_size = keys_eve.size();
+        let key_size = self.key_type.size() as BufferAddress;
+        if keys_size == 0 || keys_size % key_size != 0 {
+            return Err(format!(
+                "the keys buffers hold {keys_size} bytes, not a positive number of {:?} keys",
+                self.key_type
+            ));
+        }
 
-        let cdefs = vec![
-            ShaderDefVal::UInt(
-                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
-                NUMBER_OF_THREADS_PER_WORKGROUP,
-            ),
-            ShaderDefVal::UInt(
-                "NUMBER_OF_ROWS_PER_WORKGROUP".into(),
-                NUMBER_OF_ROWS_PER_WORKGROUP,
-            ),
-            ShaderDefVal::UInt("NUMBER_OF_RADIX".into(), NUMBER_OF_RADIX),
-            ShaderDefVal::UInt("NUMBER_OF_RADIX_BITS".into(), NUMBER_OF_RADIX_BITS),
-            ShaderDefVal::UInt(
-                "NUMBER_OF_THREADS_PER_SUBGROUP".into(),
-                subgroup_size.into(),
-            ),
-        ];
+        let global_blocks_buf = global_blocks_buffer(sbufs)
+            .ok_or_else(|| "the global blocks buffer isn't prepared yet".to_string())?;
+        let number_of_keys = (keys_size / key_size) as u32;
+        let max_number_of_keys = max_number_of_keys_for_blocks(global_blocks_buf.size());
+        if number_of_keys > max_number_of_keys {
+            return Err(format!(
+                "the keys buffers hold {number_of_keys} keys, more than the {max_number_of_keys} keys the global blocks buffer holds the blocks of"
+            ));
+        }
 
-        let count_radix_pipeline =
-            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-                label: Some("radix_sort: count_radix pipeline".into()),
-                layout: vec![bind_group_layout.clone()],
-                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
-                shader: RADIX_SORT_SHADER_HANDLE,
-                shader_defs: [cdefs.as_slice(), &["COUNT_RADIX_PIPELINE".into()]].concat(),
-                entry_point: "main".into(),
-                zero_initialize_workgroup_memory: false,
-            });
+        let dummy_vals;
+        let (vals_eve, vals_odd) = match (self.allocate_values, vals_eve, vals_odd) {
+            (true, Some(vals_eve), Some(vals_odd)) => {
+                let vals_size =
+                    number_of_keys as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+                for vals in [vals_eve, vals_odd] {
+                    if vals.size() < vals_size {
+                        return Err(format!(
+                            "a vals buffer holds {} bytes, less than the {vals_size} bytes of a val per key",
+                            vals.size()
+                        ));
+                    }
+                }
+                (vals_eve, vals_odd)
+            }
+            (true, _, _) => {
+                return Err("both vals buffers are required unless the sort is keys-only".into());
+            }
+            (false, _, _) => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                (&dummy_vals.0, &dummy_vals.1)
+            }
+        };
 
-        let scan_upsweep_pipeline =
-            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-                label: Some("radix_sort: scan_upsweep pipeline".into()),
-                layout: vec![bind_group_layout.clone()],
-                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
-                shader: RADIX_SORT_SHADER_HANDLE,
-                shader_defs: [cdefs.as_slice(), &["SCAN_UP_SWEEP_PIPELINE".into()]].concat(),
-                entry_point: "main".into(),
-                zero_initialize_workgroup_memory: false,
-            });
+        for (name, buffer) in [
+            ("keys_eve", keys_eve),
+            ("keys_odd", keys_odd),
+            ("vals_eve", vals_eve),
+            ("vals_odd", vals_odd),
+        ] {
+            if !buffer.usage().contains(BufferUsages::STORAGE) {
+                return Err(format!("{name} doesn't have the STORAGE usage"));
+            }
+        }
 
-        let scan_dnsweep_pipeline =
-            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-                label: Some("radix_sort: scan_dnsweep pipeline".into()),
-                layout: vec![bind_group_layout.clone()],
-                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
-                shader: RADIX_SORT_SHADER_HANDLE,
-                shader_defs: [cdefs.as_slice(), &["SCAN_DOWN_SWEEP_PIPELINE".into()]].concat(),
-                entry_point: "main".into(),
+        Ok(RadixSortBindGroup::from_buffers(
+            render_device,
+            &self.bind_group_layout,
+            keys_eve,
+            vals_eve,
+            global_blocks_buf,
+            keys_odd,
+            vals_odd,
+        ))
+    }
+
+    /// `true` if the pipelines emulate the subgroup operations, see [`RadixSortSettings::with_subgroup_fallback`].
+    pub fn subgroup_fallback(&self) -> bool {
+        self.subgroup_fallback
+    }
+
+    /// `false` if the sort is keys-only, see [`RadixSortSettings::without_values`], or in [`MemoryMode::Compact`]
+    /// where the kernels never scatter the vals.
+    pub fn allocate_values(&self) -> bool {
+        self.allocate_values
+    }
+
+    pub fn memory_mode(&self) -> MemoryMode {
+        self.memory_mode
+    }
+
+    /// The bits the passes sort: the [`Self::key_bits`], and the packed indices in [`MemoryMode::Compact`].
+    pub fn sorted_bits(&self) -> u32 {
+        self.key_bits + self.compact_index_bits
+    }
+
+    /// Applies the settings the kernels don't depend on: the algorithm, queuing or dropping the bitonic pipelines,
+    /// and the key bits. See [`SettingsChanges`].
+    pub(crate) fn apply_settings(
+        &mut self,
+        pipeline_cache: &PipelineCache,
+        radix_sort_settings: &RadixSortSettings,
+    ) {
+        self.algorithm = radix_sort_settings.algorithm();
+        self.key_bits = radix_sort_settings.key_bits();
+        self.key_type = radix_sort_settings.key_type();
+        self.compact_index_bits = radix_sort_settings.compact_index_bits();
+        self.settings_error = settings_error(radix_sort_settings);
+
+        let bitonic_needed =
+            self.algorithm == Algorithm::Bitonic || radix_sort_settings.allow_fallback();
+        if bitonic_needed && self.bitonic_pipeline.is_none() {
+            self.bitonic_pipeline = Some(BitonicSortPipeline::new(
+                pipeline_cache,
+                &self.bind_group_layout,
+                self.allocate_values,
+                &self.key_transform,
+            ));
+        } else if !bitonic_needed {
+            self.bitonic_pipeline = None;
+        }
+    }
+
+    /// The [`RadixSortSettings::key_bits`], [`run`] warns about the passes above them.
+    pub fn key_bits(&self) -> u32 {
+        self.key_bits
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    /// `true` if the [`KeyType::U64`] kernels load the keys as u64, with `WgpuFeatures::SHADER_INT64`. Otherwise
+    /// they load them as their low and high u32 halves, `false` for the other key types.
+    pub fn native_u64(&self) -> bool {
+        self.native_u64
+    }
+
+    /// The transform the kernels order the keys by, the helper passes compiling `radix_sort.wgsl` apply it too.
+    pub fn key_transform(&self) -> &KeyTransform {
+        &self.key_transform
+    }
+
+    pub fn counters(&self) -> &Arc<RadixSortCounters> {
+        &self.counters
+    }
+
+    #[cfg(all(feature = "verify-sorts", debug_assertions))]
+    pub fn verifier(&self) -> &SortVerifier {
+        &self.verifier
+    }
+
+    pub fn stats(&self) -> &SortStatsRecorder {
+        &self.stats
+    }
+
+    /// See [`RadixSortDebugInfo`].
+    pub fn debug_info(&self) -> &RadixSortDebugInfoRecorder {
+        &self.debug_info
+    }
+
+    pub fn claims(&self) -> &GlobalBuffersClaims {
+        &self.claims
+    }
+
+    /// Claims the global buffers for the frame on behalf of the calling source file, like [`run`] does, e.g. before
+    /// writing the keys. Logs a conflict with the owner of the frame, see [`GlobalBuffersClaims`].
+    #[track_caller]
+    pub fn acquire(&self) -> Result<(), GlobalBuffersConflict> {
+        self.claims.claim_or_report(std::panic::Location::caller())
+    }
+
+    /// The pipelines of [`Algorithm::Bitonic`], `None` unless it's selected or [`RadixSortSettings::with_fallback`].
+    pub fn bitonic_pipeline(&self) -> Option<&BitonicSortPipeline> {
+        self.bitonic_pipeline.as_ref()
+    }
+
+    /// The backend [`run`] records: the [`RadixSortSettings::algorithm`], or [`Algorithm::Bitonic`] once the radix
+    /// pipelines failed to build with [`RadixSortSettings::with_fallback`].
+    pub fn active_algorithm(&self, pipeline_cache: &PipelineCache) -> Algorithm {
+        let radix_load_state = self.radix_load_state(pipeline_cache);
+        let algorithm = select_algorithm(
+            self.algorithm,
+            self.bitonic_pipeline.is_some(),
+            &radix_load_state,
+        );
+
+        if let (LoadState::Failed(err), true) = (&radix_load_state, algorithm != self.algorithm) {
+            warn_once!("radix_sort: falling back to the bitonic sort: {}", err);
+        }
+
+        algorithm
+    }
+
+    /// The pipelines of the radix sort by name, in the order a pass dispatches them:
+    ///
+    /// - `count_radix_pipeline` counts the digits of each block of keys (step 1),
+    /// - `scan_upsweep_pipeline`, `scan_dnsweep_pipeline` and `scan_last_block_pipeline` scan the histograms
+    ///   (step 2),
+    /// - `scatter_pipeline` writes the keys to their sorted positions (step 3),
+    /// - `digit_histograms_pipeline`, `scan_digit_histograms_pipeline` and `fused_scatter_pipeline` replace
+    ///   `scan_last_block_pipeline` and `scatter_pipeline` in [`run`], counting the digits of all its passes once
+    ///   before them.
+    pub fn radix_pipeline_ids(&self) -> [(&'static str, CachedComputePipelineId); 8] {
+        [
+            ("count_radix_pipeline", self.count_radix_pipeline),
+            ("scan_upsweep_pipeline", self.scan_upsweep_pipeline),
+            ("scan_dnsweep_pipeline", self.scan_dnsweep_pipeline),
+            ("scan_last_block_pipeline", self.scan_last_block_pipeline),
+            ("scatter_pipeline", self.scatter_pipeline),
+            ("digit_histograms_pipeline", self.digit_histograms_pipeline),
+            (
+                "scan_digit_histograms_pipeline",
+                self.scan_digit_histograms_pipeline,
+            ),
+            ("fused_scatter_pipeline", self.fused_scatter_pipeline),
+        ]
+    }
+
+    /// Every pipeline of the sort queued by name, the [`Self::radix_pipeline_ids`] followed by the
+    /// [`BitonicSortPipeline::pipeline_ids`] if they're queued, e.g. to wait for them on a loading screen.
+    ///
+    /// The radix pipelines are queued even for [`Algorithm::Bitonic`], but [`check_load_state`] only waits for the
+    /// pipelines of the [`Self::active_algorithm`].
+    pub fn pipeline_ids(&self) -> Vec<(&'static str, CachedComputePipelineId)> {
+        let mut pipeline_ids = self.radix_pipeline_ids().to_vec();
+        if let Some(bitonic_pipeline) = &self.bitonic_pipeline {
+            pipeline_ids.extend(bitonic_pipeline.pipeline_ids());
+        }
+
+        pipeline_ids
+    }
+
+    fn radix_load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &self.radix_pipeline_ids())
+    }
+
+    /// Counts the radix `digit` (0 is the least significant byte) of the first `number_of_keys` keys
+    /// and copies the [`NUMBER_OF_RADIX`] per-bucket counts into `histogram`, the first step of a counting sort.
+    ///
+    /// With `bucket_offsets`, the exclusive prefix sum of the counts is copied instead, i.e. where each bucket starts.
+    ///
+    /// The keys are read from the `EVE_*` global keys buffer if `read_from_even` is true, `ODD_*` otherwise.
+    /// `histogram` must hold at least [`HISTOGRAM_BUFFER_SIZE`] bytes and have the `COPY_DST` usage.
+    /// The global blocks buffer is used as scratch, so it must not be recorded while a sort is in flight in between.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_histogram(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        digit: u32,
+        read_from_even: bool,
+        bucket_offsets: bool,
+        histogram: &Buffer,
+    ) {
+        assert!(
+            digit < passes_needed(32),
+            "digit({digit}) must be less than {}",
+            passes_needed(32)
+        );
+
+        if number_of_keys == 0 {
+            encoder.clear_buffer(histogram, 0, Some(HISTOGRAM_BUFFER_SIZE));
+            return;
+        }
+
+        let count_radix_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_radix_pipeline)
+            .unwrap();
+        let scan_upsweep_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_upsweep_pipeline)
+            .unwrap();
+        let scan_dnsweep_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_dnsweep_pipeline)
+            .unwrap();
+        let scan_last_block_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_last_block_pipeline)
+            .unwrap();
+        let global_blocks_buf = global_blocks_buffer(sbufs).unwrap();
+
+        let number_of_blks = workgroups_for(number_of_keys);
+
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("radix_sort histogram compute pass"),
+                ..default()
+            });
+
+            pass.set_pipeline(count_radix_pipeline);
+            pass.set_bind_group(
+                0,
+                if read_from_even {
+                    radix_bind_group.eve_bind_group()
+                } else {
+                    radix_bind_group.odd_bind_group()
+                },
+                &[],
+            );
+            pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+            pass.set_push_constants(NUMBER_OF_BLKS_OFFSET, bytemuck::bytes_of(&number_of_blks));
+            pass.set_push_constants(PASS_INDEX_OFFSET, bytemuck::bytes_of(&digit));
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_blks,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+
+            // The inclusive block-wise scan leaves the total counts in the last block,
+            // the scan of the last block turns them into the bucket offsets.
+            record_scan_blocks(
+                &mut pass,
+                scan_upsweep_pipeline,
+                scan_dnsweep_pipeline,
+                number_of_blks,
+                max_compute_workgroups_per_dimension,
+            );
+
+            if bucket_offsets {
+                pass.set_pipeline(scan_last_block_pipeline);
+                pass.dispatch_workgroups(1, 1, 1);
+            }
+        }
+
+        let last_block_offset =
+            ((number_of_blks - 1) * NUMBER_OF_RADIX * NUMBER_OF_BYTES_PER_KEY) as u64;
+        encoder.copy_buffer_to_buffer(
+            global_blocks_buf,
+            last_block_offset,
+            histogram,
+            0,
+            HISTOGRAM_BUFFER_SIZE,
+        );
+    }
+
+    /// The first step of the pass `digit` of [`run`]: counts the radix `digit` of the first `number_of_keys` keys
+    /// of each block into the global blocks buffer.
+    ///
+    /// The three steps of a pass must be recorded in order, with the same `number_of_keys`, `digit` and
+    /// `read_from_even` (the pass reads the `EVE_*` global buffers if true, see [`is_input_even`]). Passes of
+    /// your own can be recorded in between as long as they don't write the input keys or the global blocks buffer.
+    /// Like [`run`], panics unless the pipelines are loaded and requires `number_of_keys >= 2`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_count_step(
+        &self,
+        pass: &mut ComputePass,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        digit: u32,
+        read_from_even: bool,
+    ) {
+        record_count_step(
+            pass,
+            &SortPipelines::new(pipeline_cache, self),
+            radix_bind_group.bind_group(read_from_even),
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            digit,
+        );
+    }
+
+    /// The second step of a pass, after [`Self::record_count_step`]: scans the block histograms into the offset
+    /// each block scatters each radix to.
+    pub fn record_scan_step(
+        &self,
+        pass: &mut ComputePass,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        read_from_even: bool,
+    ) {
+        record_scan_step(
+            pass,
+            &SortPipelines::new(pipeline_cache, self),
+            radix_bind_group.bind_group(read_from_even),
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+    }
+
+    /// The last step of a pass, after [`Self::record_scan_step`]: writes the keys/vals stably sorted by the radix
+    /// `digit` into the other side of the global buffers, the input of the next pass.
+    ///
+    /// With `init_index`, the vals written are the indices of the keys instead of the input vals,
+    /// only meaningful for the first pass of a sort.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_scatter_step(
+        &self,
+        pass: &mut ComputePass,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        digit: u32,
+        init_index: bool,
+        read_from_even: bool,
+    ) {
+        record_scatter_step(
+            pass,
+            &SortPipelines::new(pipeline_cache, self),
+            radix_bind_group.bind_group(read_from_even),
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            digit,
+            init_index,
+        );
+    }
+}
+
+/// The shader defs `radix_sort.wgsl` is compiled with, shared by every pipeline queued from it.
+///
+/// With `subgroup_fallback` the subgroups are emulated in shared memory with [`SubgroupSize::FALLBACK`] threads.
+/// The digits are extracted from the keys transformed by `key_transform`.
+pub(crate) fn radix_sort_shader_defs(
+    subgroup_size: &SubgroupSize,
+    subgroup_fallback: bool,
+    key_transform: &KeyTransform,
+) -> Vec<ShaderDefVal> {
+    shader_defs(subgroup_size.get(), subgroup_fallback)
+        .into_iter()
+        .map(|(name, value)| match value {
+            Some(value) => ShaderDefVal::UInt(name.into(), value),
+            None => name.into(),
+        })
+        .chain(key_transform.shader_defs())
+        .collect()
+}
+
+/// Queues the pipelines of the sort, or inserts [`RadixSortUnsupported`] if the adapter can't run them or the
+/// [`SubgroupSize`] wasn't resolved.
+///
+/// The [`RadixSortStartupSet::Pipelines`] system of the [`RadixSortStartup`] schedule.
+pub fn init_radix_sort_pipeline(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    pipeline_cache: Res<PipelineCache>,
+    subgroup_size: Option<Res<SubgroupSize>>,
+    radix_sort_settings: Res<RadixSortSettings>,
+) {
+    // E.g. the probe failed, reported instead of waiting for the pipelines forever
+    let Some(subgroup_size) = subgroup_size else {
+        let err = "init_radix_sort_pipeline: the SubgroupSize wasn't resolved".to_string();
+        error!("radix_sort: {}", err);
+        commands.insert_resource(RadixSortUnsupported(err));
+        return;
+    };
+
+    // Creating the bind group layout would fail with an uncaptured validation error
+    if let Err(err) = check_limits(&render_device.limits()) {
+        error!("radix_sort is not supported by this adapter: {}", err);
+        commands.insert_resource(RadixSortUnsupported(err));
+        return;
+    }
+
+    push_error_scopes(&render_device);
+    let radix_sort_pipeline = RadixSortPipeline::new(
+        &render_device,
+        &pipeline_cache,
+        &subgroup_size,
+        &radix_sort_settings,
+    );
+    report_creation_errors(&mut commands, pop_error_scopes(&render_device));
+
+    commands.insert_resource(radix_sort_pipeline);
+}
+
+/// Runs [`init_radix_sort_pipeline`] again in the render `world`, when the settings change the kernels. Its params
+/// are validated: a missing one inserts [`RadixSortUnsupported`] instead of panicking.
+pub(crate) fn run_init_radix_sort_pipeline(world: &mut World) {
+    if let Err(err) = world.run_system_once(init_radix_sort_pipeline) {
+        error!("radix_sort: failed to initialize the pipelines: {}", err);
+        world.insert_resource(RadixSortUnsupported(err.to_string()));
+    }
+}
+
+impl RadixSortPipeline {
+    pub fn new(
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        subgroup_size: &SubgroupSize,
+        radix_sort_settings: &RadixSortSettings,
+    ) -> Self {
+        let multisplit_supported = multisplit_supported(
+            subgroup_size.get(),
+            render_device.limits().max_compute_workgroup_storage_size,
+        );
+        let subgroup_fallback = radix_sort_settings.force_subgroup_fallback()
+            || !subgroups_supported(render_device)
+            || !multisplit_supported;
+        if subgroup_fallback {
+            info!("radix_sort: emulating the subgroup operations in shared memory");
+        }
+        if !multisplit_supported {
+            info!(
+                "radix_sort: the ranking of subgroups of {} threads doesn't fit in the workgroup memory",
+                subgroup_size.get()
+            );
+        }
+
+        let key_type = radix_sort_settings.key_type();
+        let key_size = NonZeroU64::new(key_type.size() as u64);
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "radix_sort bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    // Read unsorted(sub-sort) keys from this buffer
+                    storage_buffer_read_only_sized(false, key_size),
+                    // Read unsorted(sub-sort) vals from this buffer
+                    storage_buffer_read_only::<u32>(false),
+                    // Read/Write histograms of count of each radix
+                    storage_buffer::<u32>(false),
+                    // Write sorted(sub-sort) keys to this buffer
+                    storage_buffer_sized(false, key_size),
+                    // Write sorted(sub-sort) vals to this buffer
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let memory_mode = radix_sort_settings.memory_mode();
+        let allocate_values =
+            radix_sort_settings.allocate_values() && memory_mode == MemoryMode::Full;
+        let key_transform = radix_sort_settings.key_transform().clone();
+
+        let mut cdefs = radix_sort_shader_defs(subgroup_size, subgroup_fallback, &key_transform);
+        if !allocate_values {
+            cdefs.push("KEYS_ONLY".into());
+        }
+        let native_u64 = key_type == KeyType::U64
+            && render_device
+                .features()
+                .contains(WgpuFeatures::SHADER_INT64);
+        if key_type == KeyType::U64 {
+            cdefs.push("KEY_U64".into());
+            if native_u64 {
+                cdefs.push("KEY_U64_NATIVE".into());
+            } else {
+                info!("radix_sort: no SHADER_INT64, sorting the u64 keys as their u32 halves");
+            }
+        }
+
+        let count_radix_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: count_radix pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &["COUNT_RADIX_PIPELINE".into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let scan_upsweep_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: scan_upsweep pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &["SCAN_UP_SWEEP_PIPELINE".into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let scan_dnsweep_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: scan_dnsweep pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &["SCAN_DOWN_SWEEP_PIPELINE".into()]].concat(),
+                entry_point: "main".into(),
                 zero_initialize_workgroup_memory: false,
             });
 
@@ -526,13 +2096,98 @@ impl FromWorld for RadixSortPipeline {
             zero_initialize_workgroup_memory: false,
         });
 
+        let digit_histograms_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: digit_histograms pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &["DIGIT_HISTOGRAMS_PIPELINE".into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let scan_digit_histograms_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: scan_digit_histograms pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [
+                    cdefs.as_slice(),
+                    &[
+                        "SCAN_LAST_BLOCK_PIPELINE".into(),
+                        "SCAN_DIGIT_HISTOGRAMS".into(),
+                    ],
+                ]
+                .concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let fused_scatter_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("radix_sort: fused_scatter pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [
+                    cdefs.as_slice(),
+                    &["SCATTER_PIPELINE".into(), "FUSED_DIGIT_HISTOGRAMS".into()],
+                ]
+                .concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        // The radix pipelines are queued anyway, the helper passes record their steps
+        let algorithm = radix_sort_settings.algorithm();
+        let bitonic_pipeline =
+            (algorithm == Algorithm::Bitonic || radix_sort_settings.allow_fallback()).then(|| {
+                BitonicSortPipeline::new(
+                    pipeline_cache,
+                    &bind_group_layout,
+                    allocate_values,
+                    &key_transform,
+                )
+            });
+
         Self {
             count_radix_pipeline,
             scan_upsweep_pipeline,
             scan_dnsweep_pipeline,
             scan_last_block_pipeline,
             scatter_pipeline,
+            digit_histograms_pipeline,
+            scan_digit_histograms_pipeline,
+            fused_scatter_pipeline,
             bind_group_layout,
+            subgroup_fallback,
+            allocate_values,
+            memory_mode,
+            algorithm,
+            key_bits: radix_sort_settings.key_bits(),
+            compact_index_bits: radix_sort_settings.compact_index_bits(),
+            key_type,
+            native_u64,
+            settings_error: settings_error(radix_sort_settings),
+            bitonic_pipeline,
+            counters: default(),
+            claims: default(),
+            #[cfg(all(feature = "verify-sorts", debug_assertions))]
+            verifier: Arc::new(SortVerifier::new(
+                render_device,
+                pipeline_cache,
+                &bind_group_layout,
+                &key_transform,
+            )),
+            stats: Arc::new(SortStatsRecorder::new(
+                render_device,
+                pipeline_cache,
+                &bind_group_layout,
+            )),
+            debug_info: default(),
+            key_transform,
         }
     }
 }
@@ -549,6 +2204,10 @@ pub struct RadixSortBindGroup {
     eve_bind_group: BindGroup,
     /// When pass is odd, set this bind_group to compute pass
     odd_bind_group: BindGroup,
+    /// The number of vals the smaller vals buffer holds.
+    max_number_of_values: u32,
+    /// The number of keys the blocks buffer holds the blocks of, see [`max_number_of_keys_for_blocks`].
+    max_number_of_keys: u32,
 }
 
 impl RadixSortBindGroup {
@@ -559,45 +2218,76 @@ impl RadixSortBindGroup {
         render_device: Res<RenderDevice>,
         sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
     ) {
-        let bind_group_layout = radix_sort_pipeline.bind_group_layout();
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::prepare_bind_groups").entered();
 
-        let eve_global_keys_buf = sbufs
-            .get(EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
-        let eve_global_vals_buf = sbufs
-            .get(EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
-        let global_blocks_buf = sbufs.get(GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE.id()).unwrap();
-        let odd_global_keys_buf = sbufs
-            .get(ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
-        let odd_global_vals_buf = sbufs
-            .get(ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id())
-            .unwrap();
+        let bind_group_layout = radix_sort_pipeline.bind_group_layout();
 
-        // Initialize `eve_global_vals_buf`/`odd_global_vals_buf` with a sequence of natural numbers,
-        // which is very useful as it can serve as the default index value for the first call.
-        let init_vals: Vec<u32> = (0..radix_sort_settings.max_number_of_keys()).collect();
-        let byte_size =
-            (radix_sort_settings.max_number_of_keys() * NUMBER_OF_BYTES_PER_KEY) as usize;
+        // Reallocated buffers are prepared a frame later if their preparation is deferred
+        let (Some(eve_global_keys_buf), Some(global_blocks_buf), Some(odd_global_keys_buf)) = (
+            sbufs.get(EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id()),
+            sbufs.get(GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE.id()),
+            sbufs.get(ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE.id()),
+        ) else {
+            return;
+        };
+        if radix_sort_settings.allocate_values() && global_vals_buffer(&sbufs, true).is_none() {
+            return;
+        }
 
-        eve_global_vals_buf.buffer.slice(..).get_mapped_range_mut()[..byte_size]
-            .copy_from_slice(bytemuck::cast_slice(&init_vals));
-        eve_global_vals_buf.buffer.unmap();
+        // The user buffers aren't mapped at creation, they keep their vals
+        let user_vals = radix_sort_settings
+            .user_buffers()
+            .and(global_vals_buffer(&sbufs, true).zip(global_vals_buffer(&sbufs, false)))
+            .map(|(eve, odd)| (eve.clone(), odd.clone()));
+        let (eve_global_vals_buf, odd_global_vals_buf) = user_vals
+            .or_else(|| initialize_global_vals(&radix_sort_settings, &sbufs))
+            .unwrap_or_else(|| create_dummy_vals_buffers(&render_device));
+
+        push_error_scopes(&render_device);
+        let radix_sort_bind_group = Self::from_buffers(
+            &render_device,
+            bind_group_layout,
+            &eve_global_keys_buf.buffer,
+            &eve_global_vals_buf,
+            &global_blocks_buf.buffer,
+            &odd_global_keys_buf.buffer,
+            &odd_global_vals_buf,
+        );
+        let errors = pop_error_scopes(&render_device);
+        if !errors.is_empty() {
+            report_creation_errors(&mut commands, errors);
+            return;
+        }
 
-        odd_global_vals_buf.buffer.slice(..).get_mapped_range_mut()[..byte_size]
-            .copy_from_slice(bytemuck::cast_slice(&init_vals));
-        odd_global_vals_buf.buffer.unmap();
+        commands.insert_resource(radix_sort_bind_group);
+        commands.remove_resource::<RadixSortReinitializing>();
+    }
 
+    /// Binds buffers of their own, e.g. for a [`SortJob`], sorted by [`run`] like the global buffers.
+    ///
+    /// For up to `n` keys the keys and vals buffers hold `n` u32s and `blocks` [`blocks_buffer_size`]`(n)` bytes,
+    /// all with `STORAGE` usage. `bind_group_layout` is [`RadixSortPipeline::bind_group_layout`], the vals buffers
+    /// are bound even by keys-only pipelines. [`run`] rejects more keys than `blocks` holds the blocks of, see
+    /// [`Self::max_number_of_keys`].
+    pub fn from_buffers(
+        render_device: &RenderDevice,
+        bind_group_layout: &BindGroupLayout,
+        eve_keys: &Buffer,
+        eve_vals: &Buffer,
+        blocks: &Buffer,
+        odd_keys: &Buffer,
+        odd_vals: &Buffer,
+    ) -> Self {
         let eve_bind_group = render_device.create_bind_group(
             "radix_sort: bind_group for even-pass",
             bind_group_layout,
             &BindGroupEntries::sequential((
-                eve_global_keys_buf.buffer.as_entire_binding(),
-                eve_global_vals_buf.buffer.as_entire_binding(),
-                global_blocks_buf.buffer.as_entire_binding(),
-                odd_global_keys_buf.buffer.as_entire_binding(),
-                odd_global_vals_buf.buffer.as_entire_binding(),
+                eve_keys.as_entire_binding(),
+                eve_vals.as_entire_binding(),
+                blocks.as_entire_binding(),
+                odd_keys.as_entire_binding(),
+                odd_vals.as_entire_binding(),
             )),
         );
 
@@ -605,20 +2295,22 @@ impl RadixSortBindGroup {
             "radix_sort: bind_group for odd-pass",
             bind_group_layout,
             &BindGroupEntries::sequential((
-                odd_global_keys_buf.buffer.as_entire_binding(),
-                odd_global_vals_buf.buffer.as_entire_binding(),
-                global_blocks_buf.buffer.as_entire_binding(),
-                eve_global_keys_buf.buffer.as_entire_binding(),
-                eve_global_vals_buf.buffer.as_entire_binding(),
+                odd_keys.as_entire_binding(),
+                odd_vals.as_entire_binding(),
+                blocks.as_entire_binding(),
+                eve_keys.as_entire_binding(),
+                eve_vals.as_entire_binding(),
             )),
         );
 
-        let radix_sort_bind_group = Self {
+        Self {
             eve_bind_group,
             odd_bind_group,
-        };
-
-        commands.insert_resource(radix_sort_bind_group);
+            max_number_of_values: (eve_vals.size().min(odd_vals.size())
+                / NUMBER_OF_BYTES_PER_KEY as BufferAddress)
+                as u32,
+            max_number_of_keys: max_number_of_keys_for_blocks(blocks.size()),
+        }
     }
 
     pub fn eve_bind_group(&self) -> &BindGroup {
@@ -628,96 +2320,282 @@ impl RadixSortBindGroup {
     pub fn odd_bind_group(&self) -> &BindGroup {
         &self.odd_bind_group
     }
-}
-
-const WORKGROUP_OFFSET_OFFSET: u32 = 0;
-/// The number of keys to be sorted.
-const NUMBER_OF_KEYS_OFFSET: u32 = 4;
-/// The number of blocks(histogram) required.
-///
-/// `number_of_blks` = ceil(`number_of_keys` / [`NUMBER_OF_THREADS_PER_WORKGROUP`])
-const NUMBER_OF_BLKS_OFFSET: u32 = 8;
-/// The current `pass` index being processed. For `u32` type with 8-bit `radix`, it requires 4 passes to process.
-/// So the valid range for `pass_index` is [0, 3].
-///
-/// Since we are using the LSD (Least Significant Digit) sorting method, the `pass_index` represents:
-/// - `pass_index` = 0: Processing the least significant 8 bits of the `radix`,         0x000000XX
-/// - `pass_index` = 1: Processing the second least significant 8 bits of the `radix`,  0x0000XX00
-/// - `pass_index` = 2: Processing the second most significant 8 bits of the `radix`,   0x00XX0000
-/// - `pass_index` = 3: Processing the most significant 8 bits of the `radix`,          0xXX000000
-const PASS_INDEX_OFFSET: u32 = 12;
-/// Used to control the step size of the prefix sum (inclusive) algorithm in step 2, up-sweep and down-sweep
-const SWEEP_SIZE_OFFSET: u32 = 16;
-/// Used to control whether to automatically write the index to `odd_global_vals_buf` in the 0th pass
-const INIT_INDEX_OFFSET: u32 = 20;
-
-const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
-    stages: ShaderStages::COMPUTE,
-    range: 0..24,
-};
-
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub enum LoadState {
-    OnLoad,
-    Loaded,
-    Failed(String),
-}
 
-pub fn check_load_state(world: &World) -> LoadState {
-    let pipeline_cache = world.resource::<PipelineCache>();
-    let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
-
-    let (
-        count_radix_pipeline_state,
-        scan_upsweep_pipeline_state,
-        scan_dnsweep_pipeline_state,
-        scan_last_block_pipeline_state,
-        scatter_pipeline_state,
-    ) = (
-        pipeline_cache.get_compute_pipeline_state(radix_sort_pipeline.count_radix_pipeline),
-        pipeline_cache.get_compute_pipeline_state(radix_sort_pipeline.scan_upsweep_pipeline),
-        pipeline_cache.get_compute_pipeline_state(radix_sort_pipeline.scan_dnsweep_pipeline),
-        pipeline_cache.get_compute_pipeline_state(radix_sort_pipeline.scan_last_block_pipeline),
-        pipeline_cache.get_compute_pipeline_state(radix_sort_pipeline.scatter_pipeline),
-    );
-
-    if let CachedPipelineState::Err(err) = count_radix_pipeline_state {
-        return LoadState::Failed(format!("Failed to load count_radix_pipeline: {:?}", err));
+    /// The number of keys whose vals the bound vals buffers hold, see [`RadixSortSettings::with_max_number_of_values`].
+    pub fn max_number_of_values(&self) -> u32 {
+        self.max_number_of_values
     }
 
-    if let CachedPipelineState::Err(err) = scan_upsweep_pipeline_state {
-        return LoadState::Failed(format!("Failed to load scan_upsweep_pipeline: {:?}", err));
+    /// The most keys [`run`] sorts with this bind group, the blocks buffer keeps the digit histograms past their
+    /// blocks, see [`max_number_of_keys_for_blocks`].
+    pub fn max_number_of_keys(&self) -> u32 {
+        self.max_number_of_keys
     }
 
-    if let CachedPipelineState::Err(err) = scan_dnsweep_pipeline_state {
-        return LoadState::Failed(format!("Failed to load scan_dnsweep_pipeline: {:?}", err));
+    /// Swaps the even and odd bind groups, along the buffers they bind, see [`swap_global_buffers`].
+    pub fn swap_sides(&mut self) {
+        std::mem::swap(&mut self.eve_bind_group, &mut self.odd_bind_group);
     }
 
-    if let CachedPipelineState::Err(err) = scan_last_block_pipeline_state {
-        return LoadState::Failed(format!(
-            "Failed to load scan_last_block_pipeline: {:?}",
-            err
-        ));
+    /// The bind group of a pass reading the `EVE_*` global buffers if `read_from_even` is true, `ODD_*` otherwise.
+    pub fn bind_group(&self, read_from_even: bool) -> &BindGroup {
+        if read_from_even {
+            &self.eve_bind_group
+        } else {
+            &self.odd_bind_group
+        }
     }
+}
 
-    if let CachedPipelineState::Err(err) = scatter_pipeline_state {
-        return LoadState::Failed(format!("Failed to load scatter_pipeline: {:?}", err));
+/// Writes the indices (and the initial vals) into the global vals buffers mapped at creation and unmaps them,
+/// `None` for keys-only sorts.
+pub(crate) fn initialize_global_vals(
+    radix_sort_settings: &RadixSortSettings,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+) -> Option<(Buffer, Buffer)> {
+    // The kernels of the compact mode are keys-only, its vals buffer is bound by `run_compact` only
+    if !radix_sort_settings.allocate_values()
+        || radix_sort_settings.memory_mode() == MemoryMode::Compact
+    {
+        return None;
     }
 
-    if matches!(count_radix_pipeline_state, CachedPipelineState::Ok(_))
-        && matches!(scan_upsweep_pipeline_state, CachedPipelineState::Ok(_))
-        && matches!(scan_dnsweep_pipeline_state, CachedPipelineState::Ok(_))
-        && matches!(scan_last_block_pipeline_state, CachedPipelineState::Ok(_))
-        && matches!(scatter_pipeline_state, CachedPipelineState::Ok(_))
-    {
-        return LoadState::Loaded;
+    let eve_global_vals_buf = &sbufs
+        .get(EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id())
+        .unwrap()
+        .buffer;
+    let odd_global_vals_buf = &sbufs
+        .get(ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id())
+        .unwrap()
+        .buffer;
+
+    // Initialize `eve_global_vals_buf`/`odd_global_vals_buf` with a sequence of natural numbers,
+    // which is very useful as it can serve as the default index value for the first call.
+    let mut init_vals: Vec<u32> = (0..radix_sort_settings.max_number_of_values()).collect();
+    let byte_size = (radix_sort_settings.max_number_of_values() * NUMBER_OF_BYTES_PER_KEY) as usize;
+
+    odd_global_vals_buf.slice(..).get_mapped_range_mut()[..byte_size]
+        .copy_from_slice(bytemuck::cast_slice(&init_vals));
+    odd_global_vals_buf.unmap();
+
+    // The initial vals replace the leading indices of the even buffer only
+    if let Some(initial_vals) = radix_sort_settings.initial_vals() {
+        init_vals[..initial_vals.len()].copy_from_slice(initial_vals);
     }
 
-    LoadState::OnLoad
+    eve_global_vals_buf.slice(..).get_mapped_range_mut()[..byte_size]
+        .copy_from_slice(bytemuck::cast_slice(&init_vals));
+    eve_global_vals_buf.unmap();
+
+    Some((eve_global_vals_buf.clone(), odd_global_vals_buf.clone()))
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn run(
+/// The `KEYS_ONLY` kernels never access the vals, two buffers since a buffer can't be bound
+/// both read-only and read-write in the same bind group.
+pub(crate) fn create_dummy_vals_buffers(render_device: &RenderDevice) -> (Buffer, Buffer) {
+    let create_dummy_buf = |label| {
+        render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    };
+
+    (
+        create_dummy_buf("radix_sort: dummy eve_global_vals buffer"),
+        create_dummy_buf("radix_sort: dummy odd_global_vals buffer"),
+    )
+}
+
+/// What the sort waits for before it can run, reported by [`check_load_state`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum LoadState {
+    OnLoad(LoadProgress),
+    /// [`run`] can record the sort: its pipelines are compiled and the [`RadixSortBindGroup`] exists.
+    Loaded,
+    Failed(String),
+    /// No GPU backend can run but [`RadixSortSettings::cpu_fallback`] is set, sort with `run_cpu` instead of [`run`].
+    /// Only reported with the `cpu_fallback` feature.
+    FallbackCpu,
+}
+
+/// The stage of a [`LoadState::OnLoad`], mirrored in [`RadixSortStatus::Loading`], e.g. to tell a stuck compilation
+/// from buffers that never get prepared.
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum LoadProgress {
+    /// The [`RadixSortPipeline`] isn't created yet, it is once the [`SubgroupSize`] is resolved when the plugin
+    /// finishes.
+    #[default]
+    WaitingForSubgroupSize,
+    /// `ready` of the `total` pipelines needed are compiled.
+    CompilingPipelines { ready: u32, total: u32 },
+    /// The pipelines are compiled, the [`RadixSortBindGroup`] isn't created yet: the global buffers (or the
+    /// [`RadixSortSettings::with_user_buffers`]) aren't prepared or are being reallocated.
+    PreparingBuffers,
+}
+
+/// [`RadixSortSettings::cpu_fallback`] unless [`RadixSortSettings::validate_key_type`] rejects it, `run_cpu` sorts
+/// u32 keys.
+fn cpu_fallback_enabled(settings: &RadixSortSettings) -> bool {
+    settings.cpu_fallback() && settings.validate_key_type().is_ok()
+}
+
+/// The state of the sort in the render world, updated every frame, see [`LoadState`].
+///
+/// Panics if the [`RadixSortPlugin`] wasn't added, [`radix_sort_loaded`] doesn't.
+pub fn check_load_state(world: &World) -> LoadState {
+    // Not even the CPU fallback can use the global buffers
+    if let Some(RadixSortCreationErrors(errors)) = world.get_resource::<RadixSortCreationErrors>() {
+        return LoadState::Failed(format!(
+            "Failed to create the resources of the sort: {}",
+            errors.join("; ")
+        ));
+    }
+
+    match gpu_load_state(world) {
+        LoadState::Failed(err) if cpu_fallback_enabled(world.resource::<RadixSortSettings>()) => {
+            warn_once!("radix_sort: sorting on the CPU: {}", err);
+            LoadState::FallbackCpu
+        }
+        load_state => load_state,
+    }
+}
+
+/// A run condition for systems using the sort: true once [`check_load_state`] reports [`LoadState::Loaded`] and the
+/// [`RadixSortBindGroup`] exists. In the main world, once the [`RadixSortStatus`] is [`RadixSortStatus::Ready`].
+///
+/// Unlike [`check_load_state`], it doesn't panic before the plugin finished or after [`RadixSortPlugin::shutdown`].
+///
+/// ```ignore
+/// app.sub_app_mut(RenderApp)
+///     .add_systems(Render, queue_my_sort.in_set(RenderSet::Queue).run_if(radix_sort_loaded()));
+/// ```
+pub fn radix_sort_loaded() -> impl FnMut(&World) -> bool + Clone {
+    |world: &World| match world.get_resource::<RadixSortStatus>() {
+        Some(status) => *status == RadixSortStatus::Ready,
+        None => {
+            world.contains_resource::<RadixSortBindGroup>()
+                && try_check_load_state(world) == Some(LoadState::Loaded)
+        }
+    }
+}
+
+/// A run condition: true when [`check_load_state`] reports [`LoadState::Failed`], e.g. to report the error. In the
+/// main world, when the [`RadixSortStatus`] is [`RadixSortStatus::Failed`]. [`LoadState::FallbackCpu`] isn't a
+/// failure.
+pub fn radix_sort_failed() -> impl FnMut(&World) -> bool + Clone {
+    |world: &World| match world.get_resource::<RadixSortStatus>() {
+        Some(status) => matches!(status, RadixSortStatus::Failed { .. }),
+        None => matches!(try_check_load_state(world), Some(LoadState::Failed(_))),
+    }
+}
+
+/// [`check_load_state`], `None` while the resources it reads don't exist.
+pub(crate) fn try_check_load_state(world: &World) -> Option<LoadState> {
+    let initialized = world.contains_resource::<RenderDevice>()
+        && world.contains_resource::<RadixSortSettings>()
+        && (world.contains_resource::<RadixSortPipeline>()
+            || world.contains_resource::<RadixSortUnsupported>()
+            || world.contains_resource::<RadixSortCreationErrors>()
+            // Not finished yet rather than shut down
+            || !world.contains_resource::<SubgroupSize>());
+
+    initialized.then(|| check_load_state(world))
+}
+
+fn gpu_load_state(world: &World) -> LoadState {
+    let render_device = world.resource::<RenderDevice>();
+    if !render_device
+        .features()
+        .contains(WgpuFeatures::PUSH_CONSTANTS)
+    {
+        // WebGPU doesn't expose push constants
+        return LoadState::Failed(
+            "The adapter doesn't support push constants (WgpuFeatures::PUSH_CONSTANTS), which the sort requires"
+                .into(),
+        );
+    }
+
+    if let Some(RadixSortUnsupported(err)) = world.get_resource::<RadixSortUnsupported>() {
+        return LoadState::Failed(err.clone());
+    }
+
+    let Some(radix_sort_pipeline) = world.get_resource::<RadixSortPipeline>() else {
+        return LoadState::OnLoad(LoadProgress::WaitingForSubgroupSize);
+    };
+
+    if world.contains_resource::<RadixSortReinitializing>() {
+        return LoadState::OnLoad(LoadProgress::PreparingBuffers);
+    }
+
+    // Until the user buffers are prepared and checked
+    if world
+        .resource::<RadixSortSettings>()
+        .user_buffers()
+        .is_some()
+        && !world.contains_resource::<RadixSortBindGroup>()
+    {
+        return LoadState::OnLoad(LoadProgress::PreparingBuffers);
+    }
+
+    let pipeline_cache = world.resource::<PipelineCache>();
+
+    let pipelines_load_state = match (
+        radix_sort_pipeline.active_algorithm(pipeline_cache),
+        radix_sort_pipeline.bitonic_pipeline(),
+    ) {
+        (Algorithm::Bitonic, Some(bitonic_pipeline)) => bitonic_pipeline.load_state(pipeline_cache),
+        _ => radix_sort_pipeline.radix_load_state(pipeline_cache),
+    };
+
+    match pipelines_load_state {
+        LoadState::Loaded if !world.contains_resource::<RadixSortBindGroup>() => {
+            LoadState::OnLoad(LoadProgress::PreparingBuffers)
+        }
+        load_state => load_state,
+    }
+}
+
+/// Combines the states of `(name, id)` compute pipelines into a single [`LoadState`],
+/// [`LoadProgress::CompilingPipelines`] until all of them are compiled.
+///
+/// The first failed pipeline (in the given order) determines the error message.
+pub(crate) fn compute_pipelines_load_state(
+    pipeline_cache: &PipelineCache,
+    pipelines: &[(&str, CachedComputePipelineId)],
+) -> LoadState {
+    let mut ready = 0;
+
+    for (name, id) in pipelines {
+        match pipeline_cache.get_compute_pipeline_state(*id) {
+            CachedPipelineState::Err(err) => {
+                return LoadState::Failed(format!("Failed to load {}: {:?}", name, err));
+            }
+            CachedPipelineState::Ok(_) => ready += 1,
+            _ => {}
+        }
+    }
+
+    let total = pipelines.len() as u32;
+    if ready == total {
+        LoadState::Loaded
+    } else {
+        LoadState::OnLoad(LoadProgress::CompilingPipelines { ready, total })
+    }
+}
+
+/// Sorts the first `number_of_keys` keys (and vals) of the bind group by the digits in `pass_range`, with the
+/// [`RadixSortPipeline::active_algorithm`].
+///
+/// Claims the global buffers for the frame on behalf of the calling source file, see [`RadixSortPipeline::acquire`].
+///
+/// A sort with `init_index` can't hold more keys than [`RadixSortBindGroup::max_number_of_values`], the other
+/// sorts of more keys sort the keys only and leave the vals unspecified.
+#[allow(clippy::too_many_arguments)]
+#[track_caller]
+pub fn run(
     encoder: &mut CommandEncoder,
     pipeline_cache: &PipelineCache,
     radix_sort_pipeline: &RadixSortPipeline,
@@ -728,184 +2606,289 @@ pub fn run(
     init_index: bool,
     read_from_even: bool,
 ) {
+    let algorithm = radix_sort_pipeline.active_algorithm(pipeline_cache);
+    let debug_info = RadixSortDebugInfo::new(
+        radix_sort_pipeline,
+        algorithm,
+        number_of_keys,
+        &pass_range,
+        init_index,
+        read_from_even,
+    );
+
+    if !check_run_arguments(
+        radix_sort_pipeline,
+        radix_bind_group,
+        number_of_keys,
+        &pass_range,
+        init_index,
+    ) {
+        radix_sort_pipeline
+            .debug_info
+            .record(debug_info.with_early_out(RadixSortEarlyOut::InvalidArguments));
+        return;
+    }
     if number_of_keys < 2 {
+        radix_sort_pipeline
+            .debug_info
+            .record(debug_info.with_early_out(RadixSortEarlyOut::Empty));
         return;
     }
 
-    let count_radix_pipeline = pipeline_cache
-        .get_compute_pipeline(radix_sort_pipeline.count_radix_pipeline)
-        .unwrap();
-    let scan_upsweep_pipeline = pipeline_cache
-        .get_compute_pipeline(radix_sort_pipeline.scan_upsweep_pipeline)
-        .unwrap();
-    let scan_dnsweep_pipeline = pipeline_cache
-        .get_compute_pipeline(radix_sort_pipeline.scan_dnsweep_pipeline)
-        .unwrap();
-    let scan_last_block_pipeline = pipeline_cache
-        .get_compute_pipeline(radix_sort_pipeline.scan_last_block_pipeline)
-        .unwrap();
-    let scatter_pipeline = pipeline_cache
-        .get_compute_pipeline(radix_sort_pipeline.scatter_pipeline)
-        .unwrap();
-
-    let number_of_keys_per_scatter_block =
-        NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP;
-    let number_of_blks = number_of_keys.div_ceil(number_of_keys_per_scatter_block);
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run", number_of_keys).entered();
+
+    radix_sort_pipeline.counters.record(number_of_keys);
+    // Logged, the sort is recorded anyway
+    let _ = radix_sort_pipeline
+        .claims
+        .claim_or_report(std::panic::Location::caller());
+
+    // The verifier checksums and compares u32 keys
+    #[cfg(all(feature = "verify-sorts", debug_assertions))]
+    let verification = (radix_sort_pipeline.key_type() != KeyType::U64)
+        .then(|| {
+            radix_sort_pipeline.verifier.begin(
+                encoder,
+                pipeline_cache,
+                radix_bind_group,
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                read_from_even,
+            )
+        })
+        .flatten();
+
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("radix_sort compute pass"),
+        ..default()
+    });
+
+    let stages = match (algorithm, radix_sort_pipeline.bitonic_pipeline()) {
+        (Algorithm::Bitonic, Some(bitonic_pipeline)) => {
+            let pipelines = bitonic_pipeline.pipelines(pipeline_cache);
+            let mut recorder = DebugPassRecorder::new(&mut pass, bitonic_stages(&pipelines));
+            record_bitonic_sort(
+                &mut recorder,
+                &pipelines,
+                radix_bind_group.eve_bind_group(),
+                radix_bind_group.odd_bind_group(),
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                pass_range.clone(),
+                init_index,
+                read_from_even,
+            );
+            recorder.finish()
+        }
+        // The tail of the global blocks holds the digit histograms of u32 keys, the u64 keys count a digit per pass
+        _ if radix_sort_pipeline.key_type() == KeyType::U64 => {
+            let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+            let mut recorder = DebugPassRecorder::new(&mut pass, radix_stages(&pipelines));
+            record_sort_passes(
+                &mut recorder,
+                &pipelines,
+                radix_bind_group.eve_bind_group(),
+                radix_bind_group.odd_bind_group(),
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                pass_range.clone(),
+                init_index,
+                read_from_even,
+            );
+            recorder.finish()
+        }
+        _ => {
+            let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+            let digit_histograms =
+                DigitHistogramsPipelines::new(pipeline_cache, radix_sort_pipeline);
+            let mut recorder = DebugPassRecorder::new(
+                &mut pass,
+                radix_stages(&pipelines)
+                    .into_iter()
+                    .chain(digit_histograms_stages(&digit_histograms)),
+            );
+            record_fused_sort_passes(
+                &mut recorder,
+                &pipelines,
+                &digit_histograms,
+                radix_bind_group.eve_bind_group(),
+                radix_bind_group.odd_bind_group(),
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                pass_range.clone(),
+                init_index,
+                read_from_even,
+            );
+            recorder.finish()
+        }
+    };
+    let early_out = if pass_range.is_empty() {
+        RadixSortEarlyOut::Empty
+    } else {
+        RadixSortEarlyOut::None
+    };
+    radix_sort_pipeline
+        .debug_info
+        .record(debug_info.with_early_out(early_out).with_stages(stages));
 
+    #[cfg(all(feature = "verify-sorts", debug_assertions))]
     {
-        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-            label: Some("radix_sort compute pass"),
-            ..default()
-        });
+        drop(pass);
+        radix_sort_pipeline.verifier.finish(
+            encoder,
+            pipeline_cache,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            verification,
+            number_of_keys,
+            pass_range,
+            init_index,
+            read_from_even,
+            algorithm,
+        );
+    }
+}
 
-        pass.set_pipeline(count_radix_pipeline);
-        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
-        pass.set_push_constants(NUMBER_OF_BLKS_OFFSET, bytemuck::bytes_of(&number_of_blks));
-        pass.set_push_constants(INIT_INDEX_OFFSET, bytemuck::bytes_of(&(init_index as u32)));
-
-        for pass_index in pass_range {
-            pass.set_push_constants(PASS_INDEX_OFFSET, bytemuck::bytes_of(&pass_index));
-
-            // If read_from_even is true:
-            //   pass_index == 0: `even_global_keys_buf`-> `odd_global_keys_buf`
-            //   pass_index == 1: `odd_global_keys_buf` -> `even_global_keys_buf`
-            //   pass_index == 2: `even_global_keys_buf`-> `odd_global_keys_buf`
-            //   pass_index == 3: `odd_global_keys_buf` -> `even_global_keys_buf`
-            // If read_from_even is false:
-            //   pass_index == 0: `odd_global_keys_buf` -> `even_global_keys_buf`
-            //   pass_index == 1: `even_global_keys_buf`-> `odd_global_keys_buf`
-            //   pass_index == 2: `odd_global_keys_buf` -> `even_global_keys_buf`
-            //   pass_index == 3: `even_global_keys_buf`-> `odd_global_keys_buf`
-            if (pass_index + read_from_even as u32) % 2 == 0 {
-                pass.set_bind_group(0, radix_bind_group.odd_bind_group(), &[]);
-            } else {
-                pass.set_bind_group(0, radix_bind_group.eve_bind_group(), &[]);
-            }
+/// Logs the [`RadixSortSettings::validate_key_type`] error of unvalidated settings.
+fn settings_error(radix_sort_settings: &RadixSortSettings) -> Option<SettingsError> {
+    let err = radix_sort_settings.validate_key_type().err();
+    if let Some(err) = err {
+        error!(
+            "radix_sort: invalid settings, run rejects the sorts: {}",
+            err
+        );
+    }
 
-            // 1. count radix histogram
-            {
-                pass.set_pipeline(count_radix_pipeline);
+    err
+}
 
-                dispatch_workgroup_ext(
-                    &mut pass,
-                    number_of_blks,
-                    max_compute_workgroups_per_dimension,
-                    WORKGROUP_OFFSET_OFFSET,
-                );
-            }
+/// Logs why [`run`] can't sort with these arguments and returns `false`, warns of a pass range sorting digits above
+/// the significant key bits.
+pub(crate) fn check_run_arguments(
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    number_of_keys: u32,
+    pass_range: &Range<u32>,
+    init_index: bool,
+) -> bool {
+    if let Some(err) = radix_sort_pipeline.settings_error {
+        error!("radix_sort: {}", err);
+        return false;
+    }
 
-            // 2. scan blocks
-            {
-                // scan up sweep(inclusive)
-                pass.set_pipeline(scan_upsweep_pipeline);
-                let num_round = log2_floor(number_of_blks);
-                for r in 0..num_round {
-                    let sweep_size = 1 << r;
-                    let number_of_workgroups = number_of_blks / (2 * sweep_size);
-
-                    pass.set_push_constants(SWEEP_SIZE_OFFSET, bytemuck::bytes_of(&sweep_size));
-
-                    dispatch_workgroup_ext(
-                        &mut pass,
-                        number_of_workgroups,
-                        max_compute_workgroups_per_dimension,
-                        WORKGROUP_OFFSET_OFFSET,
-                    );
-                }
+    if init_index && radix_sort_pipeline.memory_mode() == MemoryMode::Compact {
+        error!(
+            "radix_sort: the kernels of the compact memory mode sort the keys only, argsort with run_compact instead of init_index"
+        );
+        return false;
+    }
 
-                // scan down sweep(inclusive)
-                pass.set_pipeline(scan_dnsweep_pipeline);
-                let num_round = log2_ceil(number_of_blks).saturating_sub(1);
-                for r in 0..num_round {
-                    let num_slots = num_round - r;
-                    let sweep_size = 1 << num_slots;
+    if init_index && !radix_sort_pipeline.allocate_values() {
+        error!(
+            "radix_sort: init_index sorts key/value pairs, but the vals buffers aren't allocated (RadixSortSettings::without_values)"
+        );
+        return false;
+    }
 
-                    let num_src_blocks_with_full_slots = number_of_blks / (2 * sweep_size);
-                    let extra_slots = 32 - (number_of_blks % sweep_size).leading_zeros();
+    if number_of_keys > radix_bind_group.max_number_of_keys() {
+        error!(
+            "radix_sort: {} keys need a blocks buffer of {} bytes, the bound one holds the blocks of {} keys",
+            number_of_keys,
+            blocks_buffer_size(number_of_keys),
+            radix_bind_group.max_number_of_keys()
+        );
+        return false;
+    }
 
-                    let number_of_workgroups =
-                        num_src_blocks_with_full_slots * num_slots + extra_slots;
+    if init_index && number_of_keys > radix_bind_group.max_number_of_values() {
+        error!(
+            "radix_sort: init_index sorts {} key/value pairs, but the vals buffers hold {} vals (RadixSortSettings::with_max_number_of_values)",
+            number_of_keys,
+            radix_bind_group.max_number_of_values()
+        );
+        return false;
+    }
 
-                    pass.set_push_constants(SWEEP_SIZE_OFFSET, bytemuck::bytes_of(&sweep_size));
+    if radix_sort_pipeline.key_type() == KeyType::U8 && *pass_range != (0..1) {
+        error!(
+            "radix_sort: U8 keys sort in the single pass 0..1, not {:?}",
+            pass_range
+        );
+        return false;
+    }
 
-                    dispatch_workgroup_ext(
-                        &mut pass,
-                        number_of_workgroups,
-                        max_compute_workgroups_per_dimension,
-                        WORKGROUP_OFFSET_OFFSET,
-                    );
-                }
+    let key_bits = radix_sort_pipeline.key_type().bits().max(32);
+    if pass_range.end > passes_needed(key_bits) {
+        error!(
+            "radix_sort: u{} keys sort in the passes 0..{}, not {:?}",
+            key_bits,
+            passes_needed(key_bits),
+            pass_range
+        );
+        return false;
+    }
 
-                // scan last block/histogram(exclusive)
-                pass.set_pipeline(scan_last_block_pipeline);
-                pass.dispatch_workgroups(1, 1, 1);
-            }
+    let sorted_bits = radix_sort_pipeline.sorted_bits();
+    if pass_range.end > passes_needed(sorted_bits) {
+        warn_once!(
+            "radix_sort: the pass range {:?} sorts digits above the {} significant key bits",
+            pass_range,
+            sorted_bits
+        );
+    }
 
-            // scatter
-            {
-                pass.set_pipeline(scatter_pipeline);
+    true
+}
 
-                dispatch_workgroup_ext(
-                    &mut pass,
-                    number_of_blks,
-                    max_compute_workgroups_per_dimension,
-                    WORKGROUP_OFFSET_OFFSET,
-                );
-            }
+/// The pipelines of a loaded [`RadixSortPipeline`].
+impl<'a> SortPipelines<'a> {
+    pub fn new(pipeline_cache: &'a PipelineCache, radix_sort_pipeline: &RadixSortPipeline) -> Self {
+        let get = move |id| &**pipeline_cache.get_compute_pipeline(id).unwrap();
 
-            // Only the first pass needs to write the index to `global_vals_buf`
-            pass.set_push_constants(INIT_INDEX_OFFSET, bytemuck::bytes_of(&0));
+        Self {
+            count_radix_pipeline: get(radix_sort_pipeline.count_radix_pipeline),
+            scan_upsweep_pipeline: get(radix_sort_pipeline.scan_upsweep_pipeline),
+            scan_dnsweep_pipeline: get(radix_sort_pipeline.scan_dnsweep_pipeline),
+            scan_last_block_pipeline: get(radix_sort_pipeline.scan_last_block_pipeline),
+            scatter_pipeline: get(radix_sort_pipeline.scatter_pipeline),
+            tile: TileConfig::DEFAULT,
         }
     }
 }
 
-const fn log2_floor(x: u32) -> u32 {
-    31 - x.leading_zeros()
-}
-
-const fn log2_ceil(x: u32) -> u32 {
-    32 - x.leading_zeros() - (x.is_power_of_two() as u32)
-}
-
-pub fn dispatch_workgroup_ext(
-    pass: &mut ComputePass,
-    number_of_workgroups: u32,
-    max_compute_workgroups_per_dimension: u32,
-    workgroup_offset_offset: u32,
-) {
-    pass.set_push_constants(workgroup_offset_offset, bytemuck::bytes_of(&0));
-
-    if number_of_workgroups <= max_compute_workgroups_per_dimension {
-        pass.dispatch_workgroups(number_of_workgroups, 1, 1);
-    } else {
-        let d = number_of_workgroups / max_compute_workgroups_per_dimension;
+/// The digit histograms pipelines of a loaded [`RadixSortPipeline`].
+impl<'a> DigitHistogramsPipelines<'a> {
+    pub fn new(pipeline_cache: &'a PipelineCache, radix_sort_pipeline: &RadixSortPipeline) -> Self {
+        let get = move |id| &**pipeline_cache.get_compute_pipeline(id).unwrap();
 
-        pass.dispatch_workgroups(max_compute_workgroups_per_dimension, d, 1);
-
-        let workgroup_offset = max_compute_workgroups_per_dimension * d;
-        pass.set_push_constants(
-            workgroup_offset_offset,
-            bytemuck::bytes_of(&workgroup_offset),
-        );
-        pass.dispatch_workgroups(number_of_workgroups - workgroup_offset, 1, 1);
+        Self {
+            digit_histograms_pipeline: get(radix_sort_pipeline.digit_histograms_pipeline),
+            scan_digit_histograms_pipeline: get(radix_sort_pipeline.scan_digit_histograms_pipeline),
+            scatter_pipeline: get(radix_sort_pipeline.fused_scatter_pipeline),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use bevy::{
-        render::{
-            Render, RenderPlugin, RenderSet,
-            render_resource::{
-                Buffer, BufferAddress, BufferDescriptor, BufferInitDescriptor,
-                CommandEncoderDescriptor, Maintain, MapMode,
-            },
-            renderer::RenderQueue,
+    use bevy::render::{
+        Render, RenderPlugin, RenderSet,
+        render_resource::{
+            Buffer, BufferAddress, BufferDescriptor, BufferInitDescriptor,
+            CommandEncoderDescriptor, Maintain, MapMode,
         },
-        scene::ScenePlugin,
+        renderer::RenderQueue,
+        settings::WgpuSettings,
     };
 
-    use crate::GetSubgroupSizePlugin;
+    use crate::{
+        GetSubgroupSizePlugin,
+        test_utils::{
+            create_render_test_app, create_render_test_app_with_settings, create_storage_buffer,
+            random_keys, read_buffer, run_once, run_render_system_once,
+        },
+    };
 
     use super::*;
 
@@ -979,29 +2962,11 @@ mod tests {
         commands.insert_resource(unit_test_helper);
     }
 
-    fn run_once(app: &mut App) {
-        app.finish();
-        app.cleanup();
-
-        app.update();
-    }
-
-    fn create_unit_test_app(number_of_keys: u32) -> App {
-        let mut app = App::new();
+    fn create_unit_test_app(settings: RadixSortSettings) -> App {
+        let mut app = create_render_test_app();
 
-        app.add_plugins(MinimalPlugins)
-            .add_plugins(WindowPlugin::default())
-            .add_plugins(AssetPlugin::default())
-            .add_plugins(ScenePlugin)
-            .add_plugins(RenderPlugin {
-                synchronous_pipeline_compilation: true,
-                ..default()
-            })
-            .add_plugins(ImagePlugin::default())
-            .add_plugins(GetSubgroupSizePlugin)
-            .add_plugins(RadixSortPlugin {
-                settings: number_of_keys.into(),
-            });
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
 
         app.sub_app_mut(RenderApp).add_systems(
             Render,
@@ -1019,7 +2984,24 @@ mod tests {
         is_sort_index: bool,
         read_from_even: bool,
     ) {
-        let mut app = create_unit_test_app(number_of_keys);
+        run_sort_test(
+            number_of_keys.into(),
+            pass_count,
+            is_sort_index,
+            read_from_even,
+        );
+    }
+
+    /// Sorts the keys `max_number_of_keys - 1..=0` with the `settings`, they're distinct so the expected vals don't
+    /// depend on the stability of the sort.
+    fn run_sort_test(
+        settings: RadixSortSettings,
+        pass_count: u32,
+        is_sort_index: bool,
+        read_from_even: bool,
+    ) {
+        let number_of_keys = settings.max_number_of_keys();
+        let mut app = create_unit_test_app(settings);
 
         let unit_test_system =
             move |render_device: Res<RenderDevice>,
@@ -1223,6 +3205,1480 @@ mod tests {
         run_radix_sort_test(16_777_216, 3, true, false);
     }
 
+    #[test]
+    fn test_bitonic() {
+        for number_of_keys in [2, 100, 512, 1_000, 16 * 256, 100_000] {
+            let settings =
+                RadixSortSettings::from(number_of_keys).with_algorithm(Algorithm::Bitonic);
+            run_sort_test(settings.clone(), 4, true, true);
+            run_sort_test(settings.clone(), 3, false, true);
+            run_sort_test(settings, 3, true, false);
+        }
+    }
+
+    #[test]
+    fn test_bitonic_duplicate_keys() {
+        let number_of_keys = 3_000u32;
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2_654_435_761) % 97 + ((i % 5) << 24))
+            .collect();
+        // Only the digits 0..2 are compared, the sort isn't stable so only the pairs are checked
+        let compared = |key: u32| key & 0xFFFF;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(number_of_keys)
+                    .with_algorithm(Algorithm::Bitonic)
+                    .with_initial_keys(keys.clone()),
+            });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert_eq!(
+                    radix_sort_pipeline.active_algorithm(&pipeline_cache),
+                    Algorithm::Bitonic
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: bitonic command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..2,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let sorted_keys = sorted_keys_buffer(&sbufs, &(0..2), true).unwrap();
+                let sorted_vals = sorted_vals_buffer(&sbufs, &(0..2), true).unwrap();
+                let sorted_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    sorted_keys,
+                    number_of_keys as usize,
+                );
+                let sorted_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    sorted_vals,
+                    number_of_keys as usize,
+                );
+
+                assert!(
+                    sorted_keys
+                        .windows(2)
+                        .all(|pair| compared(pair[0]) <= compared(pair[1]))
+                );
+                let mut vals = sorted_vals.clone();
+                vals.sort();
+                assert_eq!(vals, (0..number_of_keys).collect::<Vec<_>>());
+                for (key, val) in sorted_keys.iter().zip(&sorted_vals) {
+                    assert_eq!(*key, keys[*val as usize]);
+                }
+            },
+        );
+    }
+
+    fn run_histogram_test(keys: Vec<u32>, digit: u32, read_from_even: bool) {
+        let number_of_keys = keys.len() as u32;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.max(1).into(),
+            });
+
+        let mut expected_counts = vec![0u32; NUMBER_OF_RADIX as usize];
+        for &key in &keys {
+            expected_counts[((key >> (digit * NUMBER_OF_RADIX_BITS)) & 0xFF) as usize] += 1;
+        }
+        let expected_offsets: Vec<u32> = expected_counts
+            .iter()
+            .scan(0, |sum, &count| {
+                let offset = *sum;
+                *sum += count;
+                Some(offset)
+            })
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                for (bucket_offsets, expected) in
+                    [(false, &expected_counts), (true, &expected_offsets)]
+                {
+                    let histogram_buf = create_storage_buffer(
+                        &render_device,
+                        &vec![u32::MAX; NUMBER_OF_RADIX as usize],
+                    );
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: histogram command encoder"),
+                        });
+                    radix_sort_pipeline.record_histogram(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_bind_group,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        digit,
+                        read_from_even,
+                        bucket_offsets,
+                        &histogram_buf,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let histogram = read_buffer(
+                        &render_device,
+                        &render_queue,
+                        &histogram_buf,
+                        NUMBER_OF_RADIX as usize,
+                    );
+                    assert_eq!(&histogram, expected, "bucket_offsets: {bucket_offsets}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_histogram_single_block() {
+        run_histogram_test((0..1_000u32).collect(), 0, true);
+        run_histogram_test(vec![], 0, true);
+    }
+
+    #[test]
+    fn test_histogram_multiple_blocks() {
+        let keys: Vec<u32> = (0..1_000_003u32)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        run_histogram_test(keys.clone(), 0, true);
+        run_histogram_test(keys.clone(), 1, false);
+        run_histogram_test(keys, 3, true);
+    }
+
+    fn run_subgroup_fallback_test(number_of_keys: u32, read_from_even: bool) {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(number_of_keys).with_subgroup_fallback(),
+            });
+
+        // Many duplicates per row, so the emulated ballots have to rank equal radixes
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) % 5_000)
+            .collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert!(radix_sort_pipeline.subgroup_fallback());
+
+                let keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: subgroup fallback command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    read_from_even,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), read_from_even).unwrap();
+                let output_vals_buf = sorted_vals_buffer(&sbufs, &(0..4), read_from_even).unwrap();
+                let output_keys = read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                let output_vals = read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_subgroup_fallback() {
+        run_subgroup_fallback_test(1_000, true);
+        run_subgroup_fallback_test(1_000_003, false);
+    }
+
+    #[test]
+    fn test_reported_subgroup_size_mismatch() {
+        let number_of_keys = 100_003;
+
+        // The kernels must not rely on the reported size matching the width the driver executes at,
+        // without subgroup operations the fallback ignores it
+        for reported_subgroup_size in [16, 64, 128] {
+            let mut app = create_render_test_app();
+
+            let subgroup_size =
+                SubgroupSize::new(std::num::NonZeroU32::new(reported_subgroup_size).unwrap());
+            app.sub_app_mut(RenderApp).insert_resource(subgroup_size);
+            app.add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            });
+
+            let keys: Vec<u32> = (0..number_of_keys)
+                .map(|i| i.wrapping_mul(2654435761) % 3_000)
+                .collect();
+            let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+            expected.sort();
+            let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+            run_render_system_once(
+                &mut app,
+                move |render_device: Res<RenderDevice>,
+                      render_queue: Res<RenderQueue>,
+                      pipeline_cache: Res<PipelineCache>,
+                      radix_sort_pipeline: Res<RadixSortPipeline>,
+                      radix_bind_group: Res<RadixSortBindGroup>,
+                      sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    // Only guaranteed down to the width the shared memory is sized for
+                    let min_subgroup_size = render_device.limits().min_subgroup_size;
+                    if !radix_sort_pipeline.subgroup_fallback()
+                        && min_subgroup_size
+                            < reported_subgroup_size.min(SHARED_MEMORY_SUBGROUP_SIZE)
+                    {
+                        return;
+                    }
+
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: subgroup size mismatch command encoder"),
+                        });
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        0..4,
+                        true,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let n = number_of_keys as usize;
+                    let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), true).unwrap();
+                    let output_vals_buf = sorted_vals_buffer(&sbufs, &(0..4), true).unwrap();
+                    let output_keys =
+                        read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                    let output_vals =
+                        read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                    assert_eq!(output_keys, expected_keys, "{reported_subgroup_size}");
+                    assert_eq!(output_vals, expected_vals, "{reported_subgroup_size}");
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_keys_only() {
+        let number_of_keys = 100_003;
+        let settings = RadixSortSettings::from(number_of_keys).without_values();
+        assert_eq!(
+            RadixSortSettings::from(number_of_keys).allocated_size() - settings.allocated_size(),
+            2 * (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as BufferAddress
+        );
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert!(!radix_sort_pipeline.allocate_values());
+                assert!(global_vals_buffer(&sbufs, true).is_none());
+                assert!(global_vals_buffer(&sbufs, false).is_none());
+
+                let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: keys-only command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    false,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), true).unwrap();
+                let output_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_keys_buf,
+                    number_of_keys as usize,
+                );
+                assert_eq!(output_keys, expected_keys);
+            },
+        );
+    }
+
+    #[test]
+    fn test_value_capacity() {
+        let number_of_keys = 100_003;
+        let number_of_values = 1_000;
+        let settings =
+            RadixSortSettings::from(number_of_keys).with_max_number_of_values(number_of_values);
+        assert_eq!(
+            RadixSortSettings::from(number_of_keys).allocated_size() - settings.allocated_size(),
+            2 * ((number_of_keys - number_of_values) * NUMBER_OF_BYTES_PER_KEY) as BufferAddress
+        );
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert_eq!(radix_bind_group.max_number_of_values(), number_of_values);
+                assert_eq!(
+                    global_vals_buffer(&sbufs, true).unwrap().size(),
+                    (number_of_values * NUMBER_OF_BYTES_PER_KEY) as BufferAddress
+                );
+
+                // Sorts `number_of_keys` keys, returns the keys and vals of the even side
+                let sort = |number_of_keys: u32, init_index: bool| {
+                    let n = number_of_keys as usize;
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys[..n]));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: value capacity command encoder"),
+                        });
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        0..4,
+                        init_index,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let vals_len = n.min(number_of_values as usize);
+                    (
+                        read_buffer(&render_device, &render_queue, keys_buf, n),
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            global_vals_buffer(&sbufs, true).unwrap(),
+                            vals_len,
+                        ),
+                    )
+                };
+
+                // A keys-only sort of the full capacity, above the vals
+                let mut expected_keys = keys.clone();
+                expected_keys.sort();
+                let (sorted_keys, _) = sort(number_of_keys, false);
+                assert_eq!(sorted_keys, expected_keys);
+
+                // A key/value sort within the vals
+                let mut expected: Vec<(u32, u32)> = keys[..number_of_values as usize]
+                    .iter()
+                    .copied()
+                    .zip(0..)
+                    .collect();
+                expected.sort();
+                let (sorted_keys, sorted_vals) = sort(number_of_values, true);
+                let sorted: Vec<(u32, u32)> = sorted_keys.into_iter().zip(sorted_vals).collect();
+                assert_eq!(sorted, expected);
+
+                // A key/value sort above the vals is rejected, the keys stay unsorted
+                let (unsorted_keys, _) = sort(number_of_values + 1, true);
+                assert_eq!(unsorted_keys, keys[..=number_of_values as usize]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_significant_key_bits() {
+        let number_of_keys = 100_003;
+        let settings = RadixSortSettings::from(number_of_keys).with_significant_key_bits(20);
+        let pass_range = settings.default_pass_range();
+        assert_eq!(pass_range, 0..3);
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) & ((1 << 20) - 1))
+            .collect();
+        let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+        expected_vals.sort_by_key(|&i| keys[i as usize]);
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert_eq!(radix_sort_pipeline.key_bits(), 20);
+
+                let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: significant key bits command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    pass_range.clone(),
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                // An odd number of passes, the sorted keys end up on the odd side
+                assert!(!is_output_even(&pass_range, true));
+                let output_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    sorted_keys_buffer(&sbufs, &pass_range, true).unwrap(),
+                    number_of_keys as usize,
+                );
+                let output_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    sorted_vals_buffer(&sbufs, &pass_range, true).unwrap(),
+                    number_of_keys as usize,
+                );
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_fused_digit_histograms_pass_ranges() {
+        let number_of_keys = 70_001;
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                // The counts of a sort are zeroed for the next one, whatever its pass range
+                for (seed, pass_range) in [(0, 0..4), (1, 1..3), (2, 2..4), (3, 0..1)] {
+                    let keys = random_keys(seed, number_of_keys, u32::MAX);
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: fused digit histograms command encoder"),
+                        });
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        pass_range.clone(),
+                        true,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    // A stable sort by the digits of the range only
+                    let mask = (u32::MAX >> (32 - 8 * pass_range.len())) << (8 * pass_range.start);
+                    let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+                    expected_vals.sort_by_key(|&i| keys[i as usize] & mask);
+                    let expected_keys: Vec<u32> =
+                        expected_vals.iter().map(|&i| keys[i as usize]).collect();
+
+                    let output_keys = read_buffer(
+                        &render_device,
+                        &render_queue,
+                        sorted_keys_buffer(&sbufs, &pass_range, true).unwrap(),
+                        number_of_keys as usize,
+                    );
+                    let output_vals = read_buffer(
+                        &render_device,
+                        &render_queue,
+                        sorted_vals_buffer(&sbufs, &pass_range, true).unwrap(),
+                        number_of_keys as usize,
+                    );
+                    assert_eq!(output_keys, expected_keys, "{pass_range:?}");
+                    assert_eq!(output_vals, expected_vals, "{pass_range:?}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_u8_keys() {
+        let number_of_keys = 50_001;
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) >> 24)
+            .collect();
+        // A stable sort of the single byte, the indices of equal keys stay in order
+        let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+        expected_vals.sort_by_key(|&i| keys[i as usize]);
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+
+        // A key/value sort and a keys-only counting sort
+        for allocate_values in [true, false] {
+            let mut settings = RadixSortSettings::from(number_of_keys).with_key_type(KeyType::U8);
+            if !allocate_values {
+                settings = settings.without_values();
+            }
+            let pass_range = settings.default_pass_range();
+            assert_eq!(pass_range, 0..1);
+
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin { settings });
+
+            let keys = keys.clone();
+            let expected_keys = expected_keys.clone();
+            let expected_vals = expected_vals.clone();
+            run_render_system_once(
+                &mut app,
+                move |render_device: Res<RenderDevice>,
+                      render_queue: Res<RenderQueue>,
+                      pipeline_cache: Res<PipelineCache>,
+                      radix_sort_pipeline: Res<RadixSortPipeline>,
+                      radix_bind_group: Res<RadixSortBindGroup>,
+                      sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    let read = |buffer: &Buffer| {
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            buffer,
+                            number_of_keys as usize,
+                        )
+                    };
+                    let sort = |pass_range: Range<u32>| {
+                        render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+                        render_queue.write_buffer(
+                            global_keys_buffer(&sbufs, false).unwrap(),
+                            0,
+                            bytemuck::cast_slice(&vec![0u32; keys.len()]),
+                        );
+
+                        let mut encoder =
+                            render_device.create_command_encoder(&CommandEncoderDescriptor {
+                                label: Some("unit_test: u8 keys command encoder"),
+                            });
+                        run(
+                            &mut encoder,
+                            &pipeline_cache,
+                            &radix_sort_pipeline,
+                            &radix_bind_group,
+                            render_device.limits().max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            pass_range,
+                            allocate_values,
+                            true,
+                        );
+                        render_queue.submit([encoder.finish()]);
+                    };
+
+                    sort(0..1);
+                    assert_eq!(
+                        read(global_keys_buffer(&sbufs, false).unwrap()),
+                        expected_keys
+                    );
+                    if allocate_values {
+                        assert_eq!(
+                            read(global_vals_buffer(&sbufs, false).unwrap()),
+                            expected_vals
+                        );
+                    }
+
+                    // Two passes are rejected, nothing is written
+                    sort(0..2);
+                    assert_eq!(
+                        read(global_keys_buffer(&sbufs, false).unwrap()),
+                        vec![0; keys.len()]
+                    );
+                    assert_eq!(read(keys_buf), keys);
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_u64_keys() {
+        let number_of_keys = 50_001;
+        // Few high halves, so equal keys test the stability
+        let keys: Vec<u64> = random_keys(196, number_of_keys, 16)
+            .into_iter()
+            .zip(random_keys(197, number_of_keys, 1 << 12))
+            .map(|(high, low)| (u64::from(high) << 32) | u64::from(low))
+            .collect();
+        let mut expected_vals: Vec<u32> = (0..number_of_keys).collect();
+        expected_vals.sort_by_key(|&i| keys[i as usize]);
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+
+        // The subgroup fallback scatters the keys words through the workgroup memory too
+        for subgroup_fallback in [false, true] {
+            let mut settings = RadixSortSettings::from(number_of_keys).with_key_type(KeyType::U64);
+            if subgroup_fallback {
+                settings = settings.with_subgroup_fallback();
+            }
+            assert_eq!(settings.default_pass_range(), 0..8);
+
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin { settings });
+
+            let keys = keys.clone();
+            let expected_keys = expected_keys.clone();
+            let expected_vals = expected_vals.clone();
+            run_render_system_once(
+                &mut app,
+                move |render_device: Res<RenderDevice>,
+                      render_queue: Res<RenderQueue>,
+                      pipeline_cache: Res<PipelineCache>,
+                      radix_sort_pipeline: Res<RadixSortPipeline>,
+                      radix_bind_group: Res<RadixSortBindGroup>,
+                      sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    // Whichever path the adapter supports
+                    assert_eq!(
+                        radix_sort_pipeline.native_u64(),
+                        render_device
+                            .features()
+                            .contains(WgpuFeatures::SHADER_INT64)
+                    );
+
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    assert_eq!(keys_buf.size(), 8 * number_of_keys as BufferAddress);
+                    let read_keys = |buffer: &Buffer| {
+                        u64_keys(&read_buffer(
+                            &render_device,
+                            &render_queue,
+                            buffer,
+                            2 * number_of_keys as usize,
+                        ))
+                    };
+                    let sort = |pass_range: Range<u32>| {
+                        render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+                        render_queue.write_buffer(
+                            global_keys_buffer(&sbufs, false).unwrap(),
+                            0,
+                            bytemuck::cast_slice(&vec![0u64; keys.len()]),
+                        );
+
+                        let mut encoder =
+                            render_device.create_command_encoder(&CommandEncoderDescriptor {
+                                label: Some("unit_test: u64 keys command encoder"),
+                            });
+                        run(
+                            &mut encoder,
+                            &pipeline_cache,
+                            &radix_sort_pipeline,
+                            &radix_bind_group,
+                            render_device.limits().max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            pass_range,
+                            true,
+                            true,
+                        );
+                        render_queue.submit([encoder.finish()]);
+                    };
+
+                    // 8 passes, ending in the even buffers like the 4 passes of u32 keys
+                    sort(0..8);
+                    assert_eq!(
+                        read_keys(global_keys_buffer(&sbufs, true).unwrap()),
+                        expected_keys
+                    );
+                    assert_eq!(
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            global_vals_buffer(&sbufs, true).unwrap(),
+                            number_of_keys as usize,
+                        ),
+                        expected_vals
+                    );
+
+                    // A ninth pass is rejected, nothing is written
+                    sort(0..9);
+                    assert_eq!(
+                        read_keys(global_keys_buffer(&sbufs, false).unwrap()),
+                        vec![0; keys.len()]
+                    );
+                    assert_eq!(read_keys(keys_buf), keys);
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_initial_data() {
+        let number_of_keys = 10_000;
+        let initial_keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let initial_vals: Vec<u32> = initial_keys.iter().map(|key| !key).collect();
+        let settings = RadixSortSettings::builder()
+            .max_keys(2 * number_of_keys)
+            .initial_keys(initial_keys.clone())
+            .initial_vals(initial_vals)
+            .build()
+            .unwrap();
+
+        let mut pairs: Vec<(u32, u32)> = initial_keys.iter().map(|&key| (key, !key)).collect();
+        pairs.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = pairs.into_iter().unzip();
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
+
+        assert_eq!(
+            *app.world().resource::<RadixSortInitialCount>(),
+            RadixSortInitialCount(number_of_keys)
+        );
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  initial_count: Res<RadixSortInitialCount>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: initial data command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    initial_count.0,
+                    0..4,
+                    false,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), true).unwrap();
+                let output_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_keys_buf,
+                    initial_count.0 as usize,
+                );
+                let output_vals_buf = sorted_vals_buffer(&sbufs, &(0..4), true).unwrap();
+                let output_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_vals_buf,
+                    initial_count.0 as usize,
+                );
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_reflect() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(1024).with_initial_keys(vec![2, 1]),
+            });
+        app.finish();
+        app.cleanup();
+
+        let type_registry = app.world().resource::<AppTypeRegistry>().clone();
+        let type_registry = type_registry.read();
+        for type_id in [
+            std::any::TypeId::of::<RadixSortSettings>(),
+            std::any::TypeId::of::<RadixSortInitialCount>(),
+            std::any::TypeId::of::<RadixSortPipelineInfo>(),
+            std::any::TypeId::of::<SubgroupSize>(),
+        ] {
+            let registration = type_registry.get(type_id).unwrap();
+            let reflect_resource = registration.data::<ReflectResource>().unwrap();
+            assert!(
+                reflect_resource.reflect(app.world()).is_some(),
+                "{} isn't in the main world",
+                registration.type_info().type_path()
+            );
+        }
+        assert!(type_registry.contains(std::any::TypeId::of::<KeyType>()));
+        assert!(type_registry.contains(std::any::TypeId::of::<Algorithm>()));
+
+        let settings = app.world().resource::<RadixSortSettings>();
+        let max_number_of_keys = bevy::reflect::Struct::field(settings, "max_number_of_keys")
+            .and_then(|field| field.try_downcast_ref::<u32>());
+        assert_eq!(max_number_of_keys, Some(&1024));
+
+        let info = app.world().resource::<RadixSortPipelineInfo>();
+        assert!(info.unsupported.is_none());
+        assert!(info.allocate_values);
+    }
+
+    #[test]
+    fn test_without_render_app() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+
+        assert!(!app.world().contains_resource::<RadixSortSettings>());
+        let info = app.world().resource::<RadixSortPipelineInfo>();
+        assert_eq!(info.unsupported.as_deref(), Some(NO_RENDER_APP));
+    }
+
+    /// Where the [`RadixSortPlugin`] is added among the plugins of [`create_render_test_app`].
+    #[derive(Debug, Clone, Copy)]
+    enum PluginOrder {
+        BeforeAll,
+        BeforeRenderPlugin,
+        AfterAll,
+    }
+
+    fn create_app_with_plugin_order(order: PluginOrder) -> App {
+        let radix_sort_plugin = || RadixSortPlugin {
+            settings: 1024.into(),
+        };
+
+        let mut app = App::new();
+        if let PluginOrder::BeforeAll = order {
+            app.add_plugins(radix_sort_plugin());
+        }
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(WindowPlugin::default())
+            .add_plugins(AssetPlugin::default());
+        if let PluginOrder::BeforeRenderPlugin = order {
+            app.add_plugins(radix_sort_plugin());
+        }
+        app.add_plugins(RenderPlugin {
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default());
+        if let PluginOrder::AfterAll = order {
+            app.add_plugins(radix_sort_plugin());
+        }
+
+        app
+    }
+
+    #[test]
+    fn test_plugin_order() {
+        for order in [
+            PluginOrder::BeforeAll,
+            PluginOrder::BeforeRenderPlugin,
+            PluginOrder::AfterAll,
+        ] {
+            let mut app = create_app_with_plugin_order(order);
+            let number_of_keys = 1024;
+            let keys: Vec<u32> = (0..number_of_keys)
+                .map(|i| i.wrapping_mul(2654435761))
+                .collect();
+            let mut expected_keys = keys.clone();
+            expected_keys.sort();
+
+            run_render_system_once(&mut app, move |world: &World| {
+                assert_eq!(check_load_state(world), LoadState::Loaded, "{order:?}");
+
+                let render_device = world.resource::<RenderDevice>();
+                let render_queue = world.resource::<RenderQueue>();
+                let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+                let keys_buf = global_keys_buffer(sbufs, true).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: plugin order command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    world.resource::<PipelineCache>(),
+                    world.resource::<RadixSortPipeline>(),
+                    world.resource::<RadixSortBindGroup>(),
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let output_keys = read_buffer(
+                    render_device,
+                    render_queue,
+                    sorted_keys_buffer(sbufs, &(0..4), true).unwrap(),
+                    number_of_keys as usize,
+                );
+                assert_eq!(output_keys, expected_keys, "{order:?}");
+            });
+
+            let info = app.world().resource::<RadixSortPipelineInfo>();
+            assert!(info.unsupported.is_none(), "{order:?}");
+            assert!(app.world().contains_resource::<SubgroupSize>(), "{order:?}");
+        }
+    }
+
+    #[test]
+    fn test_sort_from_steps() {
+        let number_of_keys = 100_003;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: number_of_keys.into(),
+        });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) % 50_000)
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let max_compute_workgroups_per_dimension =
+                    render_device.limits().max_compute_workgroups_per_dimension;
+                let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                let read_sorted = || {
+                    let read = |buffer| {
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            buffer,
+                            number_of_keys as usize,
+                        )
+                    };
+                    (
+                        read(sorted_keys_buffer(&sbufs, &(0..4), true).unwrap()),
+                        read(sorted_vals_buffer(&sbufs, &(0..4), true).unwrap()),
+                    )
+                };
+
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: sort steps command encoder"),
+                });
+                {
+                    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                    for digit in 0..4 {
+                        let read_from_even = is_input_even(digit, true);
+                        radix_sort_pipeline.record_count_step(
+                            &mut pass,
+                            &pipeline_cache,
+                            &radix_bind_group,
+                            max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            digit,
+                            read_from_even,
+                        );
+                        radix_sort_pipeline.record_scan_step(
+                            &mut pass,
+                            &pipeline_cache,
+                            &radix_bind_group,
+                            max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            read_from_even,
+                        );
+                        radix_sort_pipeline.record_scatter_step(
+                            &mut pass,
+                            &pipeline_cache,
+                            &radix_bind_group,
+                            max_compute_workgroups_per_dimension,
+                            number_of_keys,
+                            digit,
+                            digit == 0,
+                            read_from_even,
+                        );
+                    }
+                }
+                render_queue.submit([encoder.finish()]);
+                let from_steps = read_sorted();
+
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: run command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+                let from_run = read_sorted();
+
+                assert_eq!(from_steps, from_run);
+                assert!(from_steps.0.is_sorted());
+            },
+        );
+    }
+
+    #[test]
+    fn test_bind_group_for_user_buffers() {
+        let number_of_keys = 50_000;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 100_000.into(),
+        });
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert_eq!(radix_sort_pipeline.bind_group_layouts().len(), 1);
+
+                let zeros = vec![0; number_of_keys as usize];
+                let keys_eve = create_storage_buffer(&render_device, &keys);
+                let keys_odd = create_storage_buffer(&render_device, &zeros);
+                let vals_eve = create_storage_buffer(&render_device, &zeros);
+                let vals_odd = create_storage_buffer(&render_device, &zeros);
+
+                let create = |keys_odd, vals_odd| {
+                    radix_sort_pipeline.create_bind_group_for(
+                        &render_device,
+                        &sbufs,
+                        &keys_eve,
+                        keys_odd,
+                        Some(&vals_eve),
+                        vals_odd,
+                    )
+                };
+                let too_small = create_storage_buffer(&render_device, &zeros[1..]);
+                assert!(create(&too_small, Some(&vals_odd)).is_err());
+                assert!(create(&keys_odd, None).is_err());
+                let not_storage = render_device.create_buffer(&BufferDescriptor {
+                    label: None,
+                    size: (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as BufferAddress,
+                    usage: BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                assert!(create(&keys_odd, Some(&not_storage)).is_err());
+                let radix_bind_group = create(&keys_odd, Some(&vals_odd)).unwrap();
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: user buffers command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let (output_keys_buf, output_vals_buf) = if is_output_even(&(0..4), true) {
+                    (&keys_eve, &vals_eve)
+                } else {
+                    (&keys_odd, &vals_odd)
+                };
+                let output_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_keys_buf,
+                    number_of_keys as usize,
+                );
+                let output_vals = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_vals_buf,
+                    number_of_keys as usize,
+                );
+                let output: Vec<(u32, u32)> = output_keys.into_iter().zip(output_vals).collect();
+                assert_eq!(output, expected);
+            },
+        );
+    }
+
+    // A compile test, removing an item from the prelude breaks the build
+    #[test]
+    #[allow(unused_imports)]
+    fn test_prelude() {
+        use crate::prelude::{
+            Algorithm, EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE, GetSubgroupSizePlugin, KeyType, LoadProgress,
+            LoadState, ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE, RadixSortBindGroup, RadixSortInitialCount,
+            RadixSortPipeline, RadixSortPipelineInfo, RadixSortPlugin, RadixSortPreset,
+            RadixSortRunOptions, RadixSortSettings, RadixSortSettingsBuilder, RadixSortStatus,
+            RadixSortStatusChanged, SettingsError, SubgroupSize, check_load_state,
+            global_keys_buffer, global_vals_buffer, is_input_even, is_output_even,
+            radix_sort_failed, radix_sort_loaded, run, run_with_options, sorted_keys_buffer,
+            sorted_vals_buffer,
+        };
+    }
+
+    #[test]
+    fn test_radix_sort_loaded() {
+        #[derive(Resource, Default)]
+        struct LoadedRuns(u32);
+
+        let mut app = create_unit_test_app(1_000.into());
+        let render_app = app.sub_app_mut(RenderApp);
+        // Before the plugin finished, without panicking
+        assert!(!radix_sort_loaded()(render_app.world()));
+        assert!(!radix_sort_failed()(render_app.world()));
+
+        render_app.init_resource::<LoadedRuns>().add_systems(
+            Render,
+            (|mut runs: ResMut<LoadedRuns>| runs.0 += 1)
+                .in_set(RenderSet::PrepareResources)
+                .run_if(radix_sort_loaded()),
+        );
+        app.finish();
+        app.cleanup();
+
+        // The pipelines compile and the bind group is created after `PrepareResources` in the first frame
+        app.update();
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(render_world.resource::<LoadedRuns>().0, 0);
+
+        app.update();
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(render_world.resource::<LoadedRuns>().0, 1);
+        assert!(radix_sort_loaded()(render_world));
+        assert!(!radix_sort_failed()(render_world));
+    }
+
+    #[test]
+    fn test_load_progress() {
+        let mut app = create_unit_test_app(1_000.into());
+        app.finish();
+        app.cleanup();
+
+        // Queued when the plugin finished, compiled in the first frame
+        let render_world = app.sub_app(RenderApp).world();
+        let total = render_world
+            .resource::<RadixSortPipeline>()
+            .radix_pipeline_ids()
+            .len() as u32;
+        assert_eq!(
+            check_load_state(render_world),
+            LoadState::OnLoad(LoadProgress::CompilingPipelines { ready: 0, total })
+        );
+
+        // Compiled, then the bind group is created after `PrepareResources`
+        app.update();
+        assert_eq!(
+            check_load_state(app.sub_app(RenderApp).world()),
+            LoadState::Loaded
+        );
+
+        let render_world = app.sub_app_mut(RenderApp).world_mut();
+        render_world.remove_resource::<RadixSortBindGroup>();
+        assert_eq!(
+            check_load_state(render_world),
+            LoadState::OnLoad(LoadProgress::PreparingBuffers)
+        );
+
+        app.update();
+        assert_eq!(
+            check_load_state(app.sub_app(RenderApp).world()),
+            LoadState::Loaded
+        );
+
+        let render_world = app.sub_app_mut(RenderApp).world_mut();
+        render_world.remove_resource::<RadixSortPipeline>();
+        assert_eq!(
+            check_load_state(render_world),
+            LoadState::OnLoad(LoadProgress::WaitingForSubgroupSize)
+        );
+    }
+
+    #[test]
+    fn test_pipeline_ids() {
+        let mut app = create_unit_test_app(RadixSortSettings::from(1_000).with_fallback());
+        run_render_system_once(&mut app, |world: &World| {
+            assert_eq!(check_load_state(world), LoadState::Loaded);
+
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let pipeline_ids = world.resource::<RadixSortPipeline>().pipeline_ids();
+            assert_eq!(pipeline_ids.len(), 11);
+            for (name, id) in pipeline_ids {
+                assert!(
+                    pipeline_cache.get_compute_pipeline(id).is_some(),
+                    "{name} isn't ready"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_check_limits() {
+        assert!(check_limits(&WgpuLimits::default()).is_ok());
+
+        let limits = WgpuLimits {
+            max_storage_buffers_per_shader_stage: 4,
+            ..default()
+        };
+        let err = check_limits(&limits).unwrap_err();
+        assert!(
+            err.contains("max_storage_buffers_per_shader_stage is 4"),
+            "{err}"
+        );
+
+        let limits = WgpuLimits {
+            max_compute_workgroup_storage_size: 8_192,
+            ..default()
+        };
+        let err = check_limits(&limits).unwrap_err();
+        assert!(
+            err.contains("max_compute_workgroup_storage_size is 8192"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_clamped_storage_buffer_limit() {
+        let mut app = create_render_test_app_with_settings(WgpuSettings {
+            constrained_limits: Some(WgpuLimits {
+                max_storage_buffers_per_shader_stage: 4,
+                max_push_constant_size: 128,
+                max_subgroup_size: u32::MAX,
+                ..default()
+            }),
+            ..default()
+        });
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+
+        run_render_system_once(&mut app, |world: &World| {
+            assert!(!world.contains_resource::<RadixSortPipeline>());
+            assert!(radix_sort_failed()(world));
+            assert!(!radix_sort_loaded()(world));
+
+            let LoadState::Failed(err) = check_load_state(world) else {
+                panic!("the sort should fail to load with 4 storage buffers per stage");
+            };
+            assert!(
+                err.contains("max_storage_buffers_per_shader_stage"),
+                "{err}"
+            );
+        });
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let keys: Vec<u32> = (0..1024).rev().collect();
+        let create_app = |settings: RadixSortSettings| {
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin { settings });
+            run_once(&mut app);
+            app
+        };
+
+        let mut app = create_app(RadixSortSettings::from(1024).with_initial_keys(keys.clone()));
+        RadixSortPlugin::shutdown(&mut app);
+
+        let sbufs = app.world().resource::<Assets<ShaderStorageBuffer>>();
+        assert!(sbufs.get(&EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE).is_none());
+        assert!(sbufs.get(&GLOBAL_BLOCKS_STORAGE_BUFFER_HANDLE).is_none());
+        let render_world = app.sub_app(RenderApp).world();
+        let gpu_sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        assert!(global_keys_buffer(gpu_sbufs, true).is_none());
+        assert!(global_vals_buffer(gpu_sbufs, false).is_none());
+        assert!(!render_world.contains_resource::<RadixSortPipeline>());
+        assert!(!render_world.contains_resource::<RadixSortBindGroup>());
+
+        // Nothing is re-created by the systems left
+        app.update();
+        let gpu_sbufs = app
+            .sub_app(RenderApp)
+            .world()
+            .resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        assert!(global_keys_buffer(gpu_sbufs, true).is_none());
+        drop(app);
+
+        // Rebuilt without the initial keys, the buffers are fresh
+        let app = create_app(RadixSortSettings::from(1024));
+        let render_world = app.sub_app(RenderApp).world();
+        assert!(render_world.contains_resource::<RadixSortBindGroup>());
+        let gpu_sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let render_device = render_world.resource::<RenderDevice>();
+        let render_queue = render_world.resource::<RenderQueue>();
+        let eve_keys = global_keys_buffer(gpu_sbufs, true).unwrap();
+        assert_eq!(
+            read_buffer(render_device, render_queue, eve_keys, keys.len()),
+            vec![0; keys.len()]
+        );
+    }
+
+    #[test]
+    fn test_buffer_creation_error() {
+        let mut app = create_render_test_app_with_settings(WgpuSettings {
+            constrained_limits: Some(WgpuLimits {
+                max_buffer_size: 1 << 24,
+                max_push_constant_size: 128,
+                max_subgroup_size: u32::MAX,
+                ..default()
+            }),
+            ..default()
+        });
+        // 32 MiB global buffers
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: (1 << 23).into(),
+            });
+
+        run_render_system_once(&mut app, |world: &World| {
+            assert!(!world.contains_resource::<RadixSortBindGroup>());
+
+            let LoadState::Failed(err) = check_load_state(world) else {
+                panic!("the sort should fail to load with buffers over max_buffer_size");
+            };
+            assert!(err.contains("33554432"), "{err}");
+        });
+    }
+
+    #[test]
+    fn test_passes_needed() {
+        assert_eq!(passes_needed(0), 0);
+        assert_eq!(passes_needed(1), 1);
+        assert_eq!(passes_needed(8), 1);
+        assert_eq!(passes_needed(9), 2);
+        assert_eq!(passes_needed(20), 3);
+        assert_eq!(passes_needed(30), 4);
+        assert_eq!(passes_needed(32), 4);
+    }
+
+    #[test]
+    fn test_is_output_even() {
+        assert!(is_output_even(&(0..4), true));
+        assert!(!is_output_even(&(0..4), false));
+        assert!(!is_output_even(&(0..3), true));
+        assert!(is_output_even(&(0..3), false));
+        // Only the parity of the last pass matters
+        assert_eq!(is_output_even(&(2..4), true), is_output_even(&(0..4), true));
+    }
+
     #[test]
     fn test_log2_floor() {
         assert_eq!(log2_floor(1), 0);
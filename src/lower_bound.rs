@@ -0,0 +1,526 @@
+//! Batched binary search against the sorted keys.
+//!
+//! For every query, the index of the first sorted key greater than or equal to it (`lower_bound`),
+//! the number of sorted keys if there is none:
+//!
+//! ```text
+//!  sorted_keys  [ 1, 4, 4, 9 ]
+//!  queries      [ 0, 4, 5, 10 ]
+//!  results      [ 0, 1, 3, 4 ]
+//! ```
+//!
+//! E.g. maps cell ids to the start of their run without building a dense table.
+//!
+//! The pass is meant to be recorded in the same encoder right after [`crate::run`].
+//!
+//! For a single key, [`LowerBoundPipeline::record_count_less_than`] answers from the histograms of
+//! [`crate::run_with_digit_histograms`] instead: the radixes below the one of the key in the histogram of the most
+//! significant digit sorted are summed, and only the run of keys sharing the digit is searched:
+//!
+//! ```text
+//!  key          0x0203
+//!  histogram    [ 0x00: 5, 0x01: 2, 0x02: 4, ... ]   the high byte, 7 keys below 0x0200
+//!  search       sorted_keys[7..11]                   the keys 0x02??
+//! ```
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, compute_pipelines_load_state,
+    dispatch_workgroup_ext, passes_needed, sorted_keys_buffer,
+};
+
+pub const LOWER_BOUND_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(280927966245420566711766036531847953377);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const NUMBER_OF_QUERIES_OFFSET: u32 = 8;
+const KEY_OFFSET: u32 = 8;
+const DIGIT_OFFSET: u32 = 12;
+const ABOVE_OFFSET: u32 = 16;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+const COUNT_LESS_THAN_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..20,
+};
+
+pub struct LowerBoundPlugin;
+
+impl Plugin for LowerBoundPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            LOWER_BOUND_SHADER_HANDLE,
+            "lower_bound.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<LowerBoundPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LowerBoundPipeline {
+    /// One binary search per query
+    pipeline: CachedComputePipelineId,
+    /// One workgroup summing a digit histogram, then a binary search within the run of the key
+    count_less_than_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > sorted_keys: array<u32>;
+    /// @binding(1) var<storage, read      > queries: array<u32>;
+    /// @binding(2) var<storage, read_write> results: array<u32>;
+    /// ```
+    ///
+    /// The count less than pipeline binds the digit histograms as the queries, and writes a single result.
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for LowerBoundPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "lower_bound bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("lower_bound: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: LOWER_BOUND_SHADER_HANDLE,
+            shader_defs: vec![
+                ShaderDefVal::UInt(
+                    "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                    NUMBER_OF_THREADS_PER_WORKGROUP,
+                ),
+                "LOWER_BOUND_PIPELINE".into(),
+            ],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let count_less_than_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("lower_bound: count_less_than pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![COUNT_LESS_THAN_PUSH_CONSTANT_RANGES],
+                shader: LOWER_BOUND_SHADER_HANDLE,
+                shader_defs: vec!["COUNT_LESS_THAN_PIPELINE".into()],
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        Self {
+            pipeline,
+            count_less_than_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl LowerBoundPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("lower_bound pipeline", self.pipeline),
+                (
+                    "lower_bound count_less_than pipeline",
+                    self.count_less_than_pipeline,
+                ),
+            ],
+        )
+    }
+
+    /// `results` must hold at least as many u32 as `queries`.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        queries: &Buffer,
+        results: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "lower_bound: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                queries.as_entire_binding(),
+                results.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group searching the sorted keys in the global buffer holding the result of [`crate::run`]
+    /// over `pass_range` with the same `read_from_even` (see [`crate::sorted_keys_buffer`]).
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    pub fn create_sorted_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        queries: &Buffer,
+        results: &Buffer,
+    ) -> Option<BindGroup> {
+        let sorted_keys = sorted_keys_buffer(sbufs, pass_range, read_from_even)?;
+
+        Some(self.create_bind_group(render_device, sorted_keys, queries, results))
+    }
+
+    /// Writes the lower bound of each of the first `number_of_queries` queries among the `number_of_keys`
+    /// sorted keys into `results`.
+    pub fn record_lower_bound(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        number_of_queries: u32,
+    ) {
+        if number_of_queries == 0 {
+            return;
+        }
+
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("lower_bound compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(
+            NUMBER_OF_QUERIES_OFFSET,
+            bytemuck::bytes_of(&number_of_queries),
+        );
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_queries.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    /// `histograms` holds the digit histograms of [`crate::run_with_digit_histograms`] sorting `sorted_keys`, `out`
+    /// at least one u32.
+    pub fn create_count_less_than_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        histograms: &Buffer,
+        out: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "lower_bound: count_less_than bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                histograms.as_entire_binding(),
+                out.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Writes the number of the `number_of_keys` sorted keys strictly less than `key` into the first u32 of the
+    /// `out` buffer of the bind group: the lower bound of `key`, the keys equal to it aren't counted.
+    ///
+    /// The keys must have been sorted by [`crate::run_with_digit_histograms`] over `pass_range`, starting at the
+    /// digit 0, and not have any bit set above the digits sorted. A `key` above them counts every key.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_count_less_than(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        number_of_keys: u32,
+        pass_range: &Range<u32>,
+        key: u32,
+    ) {
+        debug_assert!(
+            pass_range.start == 0 && pass_range.end > 0,
+            "count_less_than needs the keys sorted from the digit 0"
+        );
+        let digit = pass_range.end - 1;
+        let above = pass_range.end < passes_needed(32) && key >> (8 * pass_range.end) != 0;
+
+        let pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_less_than_pipeline)
+            .unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("lower_bound count_less_than compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(WORKGROUP_OFFSET_OFFSET, bytemuck::bytes_of(&0u32));
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(KEY_OFFSET, bytemuck::bytes_of(&key));
+        pass.set_push_constants(DIGIT_OFFSET, bytemuck::bytes_of(&digit));
+        pass.set_push_constants(ABOVE_OFFSET, bytemuck::bytes_of(&(above as u32)));
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
+
+/// Reads the count written by [`LowerBoundPipeline::record_count_less_than`] back, for tools. `out` must have the
+/// `COPY_SRC` usage and the commands writing it must have been submitted. Blocks, so it's not available on wasm32:
+/// read `out` with a [`crate::BufferReadback`] there.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_count_less_than(
+    render_device: &RenderDevice,
+    render_queue: &bevy::render::renderer::RenderQueue,
+    out: &Buffer,
+) -> u32 {
+    let mut encoder = render_device.create_command_encoder(&Default::default());
+    let readback = crate::BufferReadback::new(render_device, &mut encoder, out, 0, 1);
+    render_queue.submit([encoder.finish()]);
+
+    readback
+        .wait(render_device)
+        .expect("the count_less_than readback should map")[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        DIGIT_HISTOGRAMS_BUFFER_SIZE, GetSubgroupSizePlugin, RadixSortBindGroup, RadixSortPipeline,
+        RadixSortPlugin, global_keys_buffer, run_with_digit_histograms,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    fn run_lower_bound_test(sorted_keys: Vec<u32>, queries: Vec<u32>) {
+        let mut app = create_render_test_app();
+        app.add_plugins(LowerBoundPlugin);
+
+        let expected: Vec<u32> = queries
+            .iter()
+            .map(|&query| sorted_keys.partition_point(|&key| key < query) as u32)
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  lower_bound_pipeline: Res<LowerBoundPipeline>| {
+                // Never empty, a zero-sized binding is invalid
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[sorted_keys.as_slice(), &[0]].concat());
+                let queries_buf = create_storage_buffer(&render_device, &queries);
+                let results_buf =
+                    create_storage_buffer(&render_device, &vec![0xDEADBEEF; queries.len()]);
+                let bind_group = lower_bound_pipeline.create_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &queries_buf,
+                    &results_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: lower_bound command encoder"),
+                });
+                lower_bound_pipeline.record_lower_bound(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    sorted_keys.len() as u32,
+                    queries.len() as u32,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let results =
+                    read_buffer(&render_device, &render_queue, &results_buf, queries.len());
+                assert_eq!(results, expected);
+            },
+        );
+    }
+
+    #[test]
+    fn test_count_less_than() {
+        let number_of_keys = 20_000;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            })
+            .add_plugins(LowerBoundPlugin);
+
+        // 16-bit keys, each one about 10 times
+        let mut rng = StdRng::seed_from_u64(157);
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|_| rng.gen_range(0x100..0x900) * 23)
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+
+        let below = vec![0, sorted[0] - 1];
+        let duplicates = vec![sorted[0], sorted[5_000], sorted[12_345], sorted[19_999]];
+        let between = vec![sorted[5_000] + 1, sorted[17_000] - 1];
+        let above = vec![sorted[19_999] + 1, 0xFFFF, 0x1_0000, 0x0123_4567, u32::MAX];
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  lower_bound_pipeline: Res<LowerBoundPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                // The high digits are all 0, sorting them or not gives the same counts
+                for pass_range in [0..2, 0..4] {
+                    let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+                    let histograms = create_storage_buffer(
+                        &render_device,
+                        &vec![0; (DIGIT_HISTOGRAMS_BUFFER_SIZE / 4) as usize],
+                    );
+                    let out = create_storage_buffer(&render_device, &[0xDEADBEEF]);
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: count_less_than sort command encoder"),
+                        });
+                    run_with_digit_histograms(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        pass_range.clone(),
+                        false,
+                        true,
+                        &histograms,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let sorted_keys_buf = sorted_keys_buffer(&sbufs, &pass_range, true).unwrap();
+                    let bind_group = lower_bound_pipeline.create_count_less_than_bind_group(
+                        &render_device,
+                        sorted_keys_buf,
+                        &histograms,
+                        &out,
+                    );
+
+                    let count_of = |key| {
+                        let mut encoder =
+                            render_device.create_command_encoder(&CommandEncoderDescriptor {
+                                label: Some("unit_test: count_less_than command encoder"),
+                            });
+                        lower_bound_pipeline.record_count_less_than(
+                            &mut encoder,
+                            &pipeline_cache,
+                            &bind_group,
+                            number_of_keys,
+                            &pass_range,
+                            key,
+                        );
+                        render_queue.submit([encoder.finish()]);
+                        read_count_less_than(&render_device, &render_queue, &out)
+                    };
+
+                    for &key in below
+                        .iter()
+                        .chain(&duplicates)
+                        .chain(&between)
+                        .chain(&above)
+                    {
+                        let expected = sorted.partition_point(|&sorted_key| sorted_key < key);
+                        assert_eq!(count_of(key) as usize, expected, "{key:#x} {pass_range:?}");
+                    }
+
+                    // Strictly less: none below the minimum, the duplicates of a key don't count themselves
+                    assert_eq!(count_of(sorted[0]), 0);
+                    assert_eq!(count_of(u32::MAX), number_of_keys);
+                    let run = sorted.partition_point(|&key| key <= duplicates[1]) as u32;
+                    assert_eq!(count_of(duplicates[1] + 1), run);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_lower_bound_below_and_above() {
+        let sorted_keys: Vec<u32> = (100..10_100u32).collect();
+        run_lower_bound_test(sorted_keys, vec![0, 99, 10_100, u32::MAX]);
+        run_lower_bound_test(vec![], vec![0, 7, u32::MAX]);
+    }
+
+    #[test]
+    fn test_lower_bound_inside() {
+        let sorted_keys: Vec<u32> = (0..10_000u32).map(|i| i * 2).collect();
+        // Exact hits and misses between two keys, more queries than a workgroup
+        let queries: Vec<u32> = (0..5_000u32)
+            .map(|i| i.wrapping_mul(2654435761) % 20_000)
+            .collect();
+        run_lower_bound_test(sorted_keys, queries);
+    }
+
+    #[test]
+    fn test_lower_bound_duplicates() {
+        let sorted_keys: Vec<u32> = (0..10_000u32).map(|i| i / 7 * 3).collect();
+        // The first of each run of duplicates, and the gaps between runs
+        let queries: Vec<u32> = (0..5_000u32).collect();
+        run_lower_bound_test(sorted_keys, queries);
+        run_lower_bound_test(vec![5; 1_000], vec![4, 5, 6]);
+    }
+}
@@ -0,0 +1,109 @@
+#ifdef LOWER_BOUND_PIPELINE
+/// Sorted keys to search
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// The keys to search for, in any order
+@group(0) @binding(1) var<storage, read      > queries: array<u32>;
+/// The index of the first sorted key greater than or equal to each query
+@group(0) @binding(2) var<storage, read_write> results: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of sorted keys.
+    number_of_keys: u32,
+    /// The number of queries.
+    number_of_queries: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let query_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+    if query_index >= pc.number_of_queries {
+        return;
+    }
+
+    let query = queries[query_index];
+
+    var lo = 0u;
+    var hi = pc.number_of_keys;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2u;
+        if sorted_keys[mid] < query {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+
+    results[query_index] = lo;
+}
+#endif // LOWER_BOUND_PIPELINE
+
+#ifdef COUNT_LESS_THAN_PIPELINE
+/// Sorted keys to search
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// The histograms of the digits of the sort, see `run_with_digit_histograms`
+@group(0) @binding(1) var<storage, read      > histograms: array<u32>;
+/// `[count]`, the number of sorted keys strictly less than the key
+@group(0) @binding(2) var<storage, read_write> count: array<u32>;
+
+struct PushConstants {
+    /// Unused, a single workgroup
+    workgroup_offset: u32,
+    /// The number of sorted keys.
+    number_of_keys: u32,
+    key: u32,
+    /// The most significant digit sorted, its histogram narrows the search to the keys sharing the digit of `key`.
+    digit: u32,
+    /// 1 if `key` has bits above the digits sorted, every key is then less than it.
+    above: u32,
+}
+var<push_constant> pc: PushConstants;
+
+var<workgroup> below: atomic<u32>;
+
+/// One thread per radix of the digit.
+@compute @workgroup_size(256, 1, 1)
+fn main(@builtin(local_invocation_index) radix: u32) {
+    if pc.above != 0u {
+        if radix == 0u {
+            count[0] = pc.number_of_keys;
+        }
+        return;
+    }
+
+    if radix == 0u {
+        atomicStore(&below, 0u);
+    }
+    workgroupBarrier();
+
+    let histogram = pc.digit * 256u;
+    let key_radix = (pc.key >> (8u * pc.digit)) & 255u;
+    if radix < key_radix {
+        atomicAdd(&below, histograms[histogram + radix]);
+    }
+    workgroupBarrier();
+
+    // The residual digits, among the keys sharing the digit of `key`
+    if radix == 0u {
+        var lo = atomicLoad(&below);
+        var hi = min(lo + histograms[histogram + key_radix], pc.number_of_keys);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2u;
+            if sorted_keys[mid] < pc.key {
+                lo = mid + 1u;
+            } else {
+                hi = mid;
+            }
+        }
+
+        count[0] = lo;
+    }
+}
+#endif // COUNT_LESS_THAN_PIPELINE
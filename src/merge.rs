@@ -0,0 +1,481 @@
+//! Stable merge of two sorted key/value buffers, e.g. the particles spawned this frame, sorted on their own, into the
+//! sorted pool, without sorting the pool again:
+//!
+//! ```text
+//!  a keys  [ 1, 4, 4, 9 ]        b keys  [ 0, 4, 12 ]
+//!  merged  [ 0, 1, 4a, 4a, 4b, 9, 12 ]
+//! ```
+//!
+//! Each thread finds the element of its output position by a binary search along the merge path, so the merge costs
+//! one pass over the output whatever the sizes of the inputs. On equal keys the elements of `a` come first.
+//!
+//! The output can be a side of the global buffers, see [`MergePipeline::record_merge_into_global`], e.g. to sort the
+//! merged keys further or to read them with [`crate::global_keys_buffer`].
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer, BufferAddress,
+            CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP,
+    compute_pipelines_load_state, create_dummy_vals_buffers, dispatch_workgroup_ext,
+    global_keys_buffer, global_vals_buffer,
+};
+
+pub const MERGE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(166812295519406314966329237624615784410);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const A_COUNT_OFFSET: u32 = 4;
+const B_COUNT_OFFSET: u32 = 8;
+const MERGE_VALS_OFFSET: u32 = 12;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..16,
+};
+
+pub struct MergePlugin;
+
+impl Plugin for MergePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, MERGE_SHADER_HANDLE, "merge.wgsl", Shader::from_wgsl);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<MergePipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MergePipeline {
+    /// Writes each element of the output from its position on the merge path
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > a_keys: array<u32>;
+    /// @binding(1) var<storage, read      > a_vals: array<u32>;
+    /// @binding(2) var<storage, read      > b_keys: array<u32>;
+    /// @binding(3) var<storage, read      > b_vals: array<u32>;
+    /// @binding(4) var<storage, read_write> out_keys: array<u32>;
+    /// @binding(5) var<storage, read_write> out_vals: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for MergePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "merge bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("merge: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: MERGE_SHADER_HANDLE,
+            shader_defs: vec![ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            )],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl MergePipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &[("merge pipeline", self.pipeline)])
+    }
+
+    /// Merges the sorted `a` and `b` into the first `a_count + b_count` elements of `out_keys` and `out_vals`.
+    ///
+    /// The vals are merged with the keys if both inputs and `out_vals` have some, the merge is keys-only if none has.
+    /// The outputs must be other buffers than the inputs.
+    ///
+    /// # Panics
+    ///
+    /// If some but not all of the inputs and the output have vals, if a buffer is too small for its count, or if an
+    /// output is also an input.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_merge(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        a_keys: &Buffer,
+        a_vals: Option<&Buffer>,
+        a_count: u32,
+        b_keys: &Buffer,
+        b_vals: Option<&Buffer>,
+        b_count: u32,
+        out_keys: &Buffer,
+        out_vals: Option<&Buffer>,
+        max_compute_workgroups_per_dimension: u32,
+    ) {
+        let merge_vals = out_vals.is_some();
+        assert!(
+            a_vals.is_some() == merge_vals && b_vals.is_some() == merge_vals,
+            "merge: both inputs and the output need vals, or none of them"
+        );
+        let count = a
+            .count
+            .checked_add(b_count)
+            .expect("merge: the output holds more than u32::MAX keys");
+
+        let size = |count: u32| count as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+        let buffers = [
+            ("a_keys", Some(a_keys), a_count),
+            ("a_vals", a_vals, a_count),
+            ("b_keys", Some(b_keys), b_count),
+            ("b_vals", b_vals, b_count),
+            ("out_keys", Some(out_keys), count),
+            ("out_vals", out_vals, count),
+        ];
+        for (name, buffer, count) in buffers {
+            let Some(buffer) = buffer else {
+                continue;
+            };
+            assert!(
+                buffer.size() >= size(count),
+                "merge: {} holds {} bytes, less than its {} u32s",
+                name,
+                buffer.size(),
+                count
+            );
+        }
+        for output in [Some(out_keys), out_vals].into_iter().flatten() {
+            assert!(
+                [Some(a_keys), a_vals, Some(b_keys), b_vals]
+                    .into_iter()
+                    .flatten()
+                    .all(|input| input.id() != output.id()),
+                "merge: an output is also an input"
+            );
+        }
+
+        if count == 0 {
+            return;
+        }
+
+        // Keys-only merges are bound with dummy vals the kernel doesn't touch
+        let dummy_vals;
+        let (a_vals, b_vals, out_vals) = match (a_vals, b_vals, out_vals) {
+            (Some(a_vals), Some(b_vals), Some(out_vals)) => (a_vals, b_vals, out_vals),
+            _ => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                (&dummy_vals.0, &dummy_vals.0, &dummy_vals.1)
+            }
+        };
+
+        let bind_group = render_device.create_bind_group(
+            "merge: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                a_keys.as_entire_binding(),
+                a_vals.as_entire_binding(),
+                b_keys.as_entire_binding(),
+                b_vals.as_entire_binding(),
+                out_keys.as_entire_binding(),
+                out_vals.as_entire_binding(),
+            )),
+        );
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("merge compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_push_constants(A_COUNT_OFFSET, bytemuck::bytes_of(&a_count));
+        pass.set_push_constants(B_COUNT_OFFSET, bytemuck::bytes_of(&b_count));
+        pass.set_push_constants(
+            MERGE_VALS_OFFSET,
+            bytemuck::bytes_of(&u32::from(merge_vals)),
+        );
+        dispatch_workgroup_ext(
+            &mut pass,
+            count.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    /// [`Self::record_merge`] into the `even` side of the global buffers, the vals are merged unless the sort is
+    /// keys-only. A sort reading the same side can then sort the merged keys further.
+    ///
+    /// Returns `false` without recording anything if the global buffers haven't been prepared yet.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Self::record_merge`], e.g. if the merged keys outnumber the capacity or if an input is that side.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_merge_into_global(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        a_keys: &Buffer,
+        a_vals: Option<&Buffer>,
+        a_count: u32,
+        b_keys: &Buffer,
+        b_vals: Option<&Buffer>,
+        b_count: u32,
+        even: bool,
+        max_compute_workgroups_per_dimension: u32,
+    ) -> bool {
+        let Some(out_keys) = global_keys_buffer(sbufs, even) else {
+            return false;
+        };
+
+        self.record_merge(
+            encoder,
+            render_device,
+            pipeline_cache,
+            a_keys,
+            a_vals,
+            a_count,
+            b_keys,
+            b_vals,
+            b_count,
+            out_keys,
+            global_vals_buffer(sbufs, even),
+            max_compute_workgroups_per_dimension,
+        );
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// The bit marking the vals of the elements of `b`, so the tests see which input an element came from.
+    const FROM_B: u32 = 1 << 31;
+
+    /// Sorted keys in `0..distinct`, many duplicates for a small `distinct`.
+    fn sorted_keys(count: u32, distinct: u32, seed: u32) -> Vec<u32> {
+        let mut keys: Vec<u32> = (0..count)
+            .map(|i| (i ^ seed).wrapping_mul(2654435761) % distinct)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// The stable merge on the CPU: `a` then `b`, sorted stably by key.
+    fn expected_merge(a_keys: &[u32], b_keys: &[u32]) -> (Vec<u32>, Vec<u32>) {
+        let mut merged: Vec<(u32, u32)> = a_keys
+            .iter()
+            .copied()
+            .zip(0..)
+            .chain(b_keys.iter().copied().zip((0..).map(|i| i | FROM_B)))
+            .collect();
+        merged.sort_by_key(|&(key, _)| key);
+        merged.into_iter().unzip()
+    }
+
+    fn check_merge(a_keys: Vec<u32>, b_keys: Vec<u32>, merge_vals: bool) {
+        let mut app = create_render_test_app();
+        app.add_plugins(MergePlugin);
+
+        let (expected_keys, expected_vals) = expected_merge(&a_keys, &b_keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  merge_pipeline: Res<MergePipeline>| {
+                // Never empty, a zero-sized binding is invalid
+                let buffer =
+                    |data: &[u32]| create_storage_buffer(&render_device, &[data, &[0]].concat());
+                let a_vals: Vec<u32> = (0..a_keys.len() as u32).collect();
+                let b_vals: Vec<u32> = (0..b_keys.len() as u32).map(|i| i | FROM_B).collect();
+                let count = a_keys.len() + b_keys.len();
+                let (a_keys_buf, a_vals_buf) = (buffer(&a_keys), buffer(&a_vals));
+                let (b_keys_buf, b_vals_buf) = (buffer(&b_keys), buffer(&b_vals));
+                let out_keys_buf = buffer(&vec![0xDEADBEEF; count]);
+                let out_vals_buf = buffer(&vec![0xDEADBEEF; count]);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: merge command encoder"),
+                });
+                merge_pipeline.record_merge(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &a_keys_buf,
+                    merge_vals.then_some(&a_vals_buf),
+                    a_keys.len() as u32,
+                    &b_keys_buf,
+                    merge_vals.then_some(&b_vals_buf),
+                    b_keys.len() as u32,
+                    &out_keys_buf,
+                    merge_vals.then_some(&out_vals_buf),
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let sizes = (a_keys.len(), b_keys.len());
+                assert_eq!(
+                    read_buffer(&render_device, &render_queue, &out_keys_buf, count),
+                    expected_keys,
+                    "{sizes:?}"
+                );
+                let out_vals = read_buffer(&render_device, &render_queue, &out_vals_buf, count);
+                if merge_vals {
+                    assert_eq!(out_vals, expected_vals, "{sizes:?}");
+                } else {
+                    assert_eq!(out_vals, vec![0xDEADBEEF; count], "{sizes:?}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_merge_duplicates() {
+        // Few distinct keys, each run of equal keys spans both inputs
+        check_merge(sorted_keys(10_000, 16, 0), sorted_keys(7_000, 16, 1), true);
+        check_merge(vec![5; 3_000], vec![5; 2_000], true);
+        check_merge(sorted_keys(10_000, 16, 0), sorted_keys(7_000, 16, 1), false);
+    }
+
+    #[test]
+    fn test_merge_unequal_sizes() {
+        for (a_count, b_count) in [(100_000, 3), (1, 50_000), (0, 1_000), (1_000, 0), (0, 0)] {
+            check_merge(
+                sorted_keys(a_count, 1_000, 2),
+                sorted_keys(b_count, 1_000, 3),
+                true,
+            );
+        }
+        // All of `b` before or after all of `a`
+        check_merge((1_000..2_000).collect(), (0..500).collect(), true);
+        check_merge((0..500).collect(), (1_000..2_000).collect(), true);
+    }
+
+    #[test]
+    fn test_merge_into_global() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 20_000.into(),
+            })
+            .add_plugins(MergePlugin);
+
+        let a_keys = sorted_keys(15_000, 5_000, 4);
+        let b_keys = sorted_keys(4_000, 5_000, 5);
+        let (expected_keys, expected_vals) = expected_merge(&a_keys, &b_keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  merge_pipeline: Res<MergePipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let a_vals: Vec<u32> = (0..a_keys.len() as u32).collect();
+                let b_vals: Vec<u32> = (0..b_keys.len() as u32).map(|i| i | FROM_B).collect();
+                let a_keys_buf = create_storage_buffer(&render_device, &a_keys);
+                let a_vals_buf = create_storage_buffer(&render_device, &a_vals);
+                let b_keys_buf = create_storage_buffer(&render_device, &b_keys);
+                let b_vals_buf = create_storage_buffer(&render_device, &b_vals);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: merge into global command encoder"),
+                });
+                assert!(merge_pipeline.record_merge_into_global(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &sbufs,
+                    &a_keys_buf,
+                    Some(&a_vals_buf),
+                    a_keys.len() as u32,
+                    &b_keys_buf,
+                    Some(&b_vals_buf),
+                    b_keys.len() as u32,
+                    false,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                ));
+                render_queue.submit([encoder.finish()]);
+
+                let len = expected_keys.len();
+                assert_eq!(
+                    read_buffer(
+                        &render_device,
+                        &render_queue,
+                        global_keys_buffer(&sbufs, false).unwrap(),
+                        len
+                    ),
+                    expected_keys
+                );
+                assert_eq!(
+                    read_buffer(
+                        &render_device,
+                        &render_queue,
+                        global_vals_buffer(&sbufs, false).unwrap(),
+                        len
+                    ),
+                    expected_vals
+                );
+            },
+        );
+    }
+}
@@ -0,0 +1,75 @@
+// The stable merge of `merge.rs`: each thread writes one element of the output, found on its diagonal of the merge
+// path with a binary search. On equal keys the elements of `a` come first.
+
+/// The keys of the first sorted input
+@group(0) @binding(0) var<storage, read      > a_keys: array<u32>;
+/// The vals of the first input, unless `merge_vals` is 0
+@group(0) @binding(1) var<storage, read      > a_vals: array<u32>;
+/// The keys of the second sorted input
+@group(0) @binding(2) var<storage, read      > b_keys: array<u32>;
+/// The vals of the second input, unless `merge_vals` is 0
+@group(0) @binding(3) var<storage, read      > b_vals: array<u32>;
+/// Write the merged keys to this buffer
+@group(0) @binding(4) var<storage, read_write> out_keys: array<u32>;
+/// Write the merged vals to this buffer, unless `merge_vals` is 0
+@group(0) @binding(5) var<storage, read_write> out_vals: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys of `a`.
+    a_count: u32,
+    /// The number of keys of `b`.
+    b_count: u32,
+    /// 1 if the vals are merged with the keys, 0 for keys-only buffers.
+    merge_vals: u32,
+}
+var<push_constant> pc: PushConstants;
+
+/// The number of elements of `a` among the first `diagonal` elements of the output.
+fn merge_path(diagonal: u32) -> u32 {
+    var lo = select(0u, diagonal - pc.b_count, diagonal > pc.b_count);
+    var hi = min(diagonal, pc.a_count);
+    while lo < hi {
+        let mid = (lo + hi) / 2u;
+        // `a[mid]` precedes `b[diagonal - 1 - mid]` if it isn't greater
+        if a_keys[mid] <= b_keys[diagonal - 1u - mid] {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+    return lo;
+}
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_index;
+    if index >= pc.a_count + pc.b_count {
+        return;
+    }
+
+    let i = merge_path(index);
+    let j = index - i;
+    var from_a = i < pc.a_count;
+    if from_a && j < pc.b_count {
+        from_a = a_keys[i] <= b_keys[j];
+    }
+
+    if from_a {
+        out_keys[index] = a_keys[i];
+        if pc.merge_vals != 0u {
+            out_vals[index] = a_vals[i];
+        }
+    } else {
+        out_keys[index] = b_keys[j];
+        if pc.merge_vals != 0u {
+            out_vals[index] = b_vals[j];
+        }
+    }
+}
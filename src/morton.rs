@@ -0,0 +1,402 @@
+//! 3D Morton code key generation pass.
+//!
+//! Quantizes float positions into 1024 steps per axis of a user-supplied AABB and interleaves the bits
+//! into a 30-bit Morton key, as needed to build LBVHs or z-order particle layouts.
+//! The keys and the original indices are written into a key/value buffer pair, usually the global buffers
+//! [`crate::run`] reads from, so the sort can be recorded right after in the same encoder:
+//!
+//! ```ignore
+//! morton_pipeline.record_morton_keygen(&mut encoder, &pipeline_cache, &bind_group, max_wg, count, 4, &aabb);
+//! run(&mut encoder, /* .. */, count, MORTON_PASS_RANGE, false, read_from_even);
+//! ```
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    math::bounding::Aabb3d,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortPreset, compute_pipelines_load_state,
+    dispatch_workgroup_ext, global_keys_buffer, global_vals_buffer, passes_needed,
+};
+
+pub const MORTON_KEYGEN_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(328147426780848788946944003442547388091);
+
+/// The number of bits per axis of a Morton code.
+pub const MORTON_BITS_PER_AXIS: u32 = 10;
+
+/// The number of significant bits of a 3D Morton code.
+pub const MORTON_KEY_BITS: u32 = 3 * MORTON_BITS_PER_AXIS;
+
+/// The passes [`crate::run`] needs to sort Morton codes, the highest byte only holds 6 bits but still needs a pass.
+pub const MORTON_PASS_RANGE: Range<u32> = 0..passes_needed(MORTON_KEY_BITS);
+
+/// The [`RadixSortPreset`] matching the keys of [`MortonKeygenPipeline`].
+pub const MORTON_PRESET: RadixSortPreset = RadixSortPreset::Morton30;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const MORTON_PARAMS_OFFSET: u32 = 4;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..MORTON_PARAMS_OFFSET + std::mem::size_of::<MortonParams>() as u32,
+};
+
+/// Push constants following `workgroup_offset`, must match `PushConstants` in `morton.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MortonParams {
+    number_of_positions: u32,
+    position_stride: u32,
+    aabb_min: [f32; 3],
+    aabb_max: [f32; 3],
+}
+
+/// Inserts two 0 bits after each of the 10 low bits of `v`.
+fn expand_bits(v: u32) -> u32 {
+    let mut x = v & 0x3FF;
+    x = x.wrapping_mul(0x00010001) & 0xFF0000FF;
+    x = x.wrapping_mul(0x00000101) & 0x0F00F00F;
+    x = x.wrapping_mul(0x00000011) & 0xC30C30C3;
+    x = x.wrapping_mul(0x00000005) & 0x49249249;
+    x
+}
+
+/// CPU version of the key computed by the Morton keygen pass, positions outside of `aabb` are clamped.
+pub fn morton_code_3d(position: Vec3, aabb: &Aabb3d) -> u32 {
+    let min = Vec3::from(aabb.min);
+    let extent = Vec3::from(aabb.max) - min;
+
+    let quantize = |p: f32, min: f32, extent: f32| {
+        // A degenerate axis maps every position to 0 instead of dividing by 0
+        let normalized = if extent > 0.0 {
+            (p - min) / extent
+        } else {
+            0.0
+        };
+        (normalized * 1024.0).clamp(0.0, 1023.0) as u32
+    };
+
+    let x = quantize(position.x, min.x, extent.x);
+    let y = quantize(position.y, min.y, extent.y);
+    let z = quantize(position.z, min.z, extent.z);
+
+    (expand_bits(x) << 2) | (expand_bits(y) << 1) | expand_bits(z)
+}
+
+pub struct MortonKeygenPlugin;
+
+impl Plugin for MortonKeygenPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            MORTON_KEYGEN_SHADER_HANDLE,
+            "morton.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<MortonKeygenPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MortonKeygenPipeline {
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > positions: array<f32>;
+    /// @binding(1) var<storage, read_write> global_keys_o: array<u32>;
+    /// @binding(2) var<storage, read_write> global_vals_o: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for MortonKeygenPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "morton_keygen bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<f32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("morton_keygen: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: MORTON_KEYGEN_SHADER_HANDLE,
+            shader_defs: vec![ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            )],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl MortonKeygenPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &[("morton_keygen pipeline", self.pipeline)])
+    }
+
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        positions: &Buffer,
+        keys: &Buffer,
+        vals: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "morton_keygen: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                positions.as_entire_binding(),
+                keys.as_entire_binding(),
+                vals.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group writing into the global buffers [`crate::run`] reads from with the same `read_from_even`.
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    pub fn create_global_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        positions: &Buffer,
+        read_from_even: bool,
+    ) -> Option<BindGroup> {
+        let keys = global_keys_buffer(sbufs, read_from_even)?;
+        let vals = global_vals_buffer(sbufs, read_from_even)?;
+
+        Some(self.create_bind_group(render_device, positions, keys, vals))
+    }
+
+    /// Writes the Morton code of the first `number_of_positions` positions and their indices into the bound keys and vals.
+    ///
+    /// `position_stride` is the distance between two positions in `f32`s, 4 for a `vec4<f32>` buffer, 3 for a packed `vec3<f32>` one.
+    /// As the vals already hold the indices, the sort can be run with `init_index: false`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_morton_keygen(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_positions: u32,
+        position_stride: u32,
+        aabb: &Aabb3d,
+    ) {
+        if number_of_positions == 0 {
+            return;
+        }
+
+        debug_assert!(position_stride >= 3, "position_stride must be at least 3");
+
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let params = MortonParams {
+            number_of_positions,
+            position_stride,
+            aabb_min: Vec3::from(aabb.min).to_array(),
+            aabb_max: Vec3::from(aabb.max).to_array(),
+        };
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("morton_keygen compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(MORTON_PARAMS_OFFSET, bytemuck::bytes_of(&params));
+
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_positions.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_morton_code_3d_cpu() {
+        let aabb = Aabb3d::new(Vec3::splat(0.5), Vec3::splat(0.5));
+
+        assert_eq!(morton_code_3d(Vec3::ZERO, &aabb), 0);
+        assert_eq!(morton_code_3d(Vec3::ONE, &aabb), (1 << MORTON_KEY_BITS) - 1);
+        // x is the most significant axis of every triple
+        assert_eq!(morton_code_3d(Vec3::new(0.5, 0.0, 0.0), &aabb), 1 << 29);
+        assert_eq!(morton_code_3d(Vec3::new(0.0, 0.5, 0.0), &aabb), 1 << 28);
+        assert_eq!(morton_code_3d(Vec3::new(0.0, 0.0, 0.5), &aabb), 1 << 27);
+        // Positions outside of the aabb are clamped
+        assert_eq!(morton_code_3d(Vec3::splat(-3.0), &aabb), 0);
+        assert_eq!(
+            morton_code_3d(Vec3::splat(7.0), &aabb),
+            (1 << MORTON_KEY_BITS) - 1
+        );
+        assert_eq!(MORTON_PASS_RANGE, 0..4);
+        assert_eq!(MORTON_PRESET.options().pass_range, MORTON_PASS_RANGE);
+        assert_eq!(MORTON_PRESET.key_bits(), MORTON_KEY_BITS);
+    }
+
+    fn run_morton_keygen_test(positions: Vec<Vec3>, position_stride: u32, aabb: Aabb3d) {
+        let mut app = create_render_test_app();
+        app.add_plugins(MortonKeygenPlugin);
+
+        let number_of_positions = positions.len() as u32;
+        let expected_keys: Vec<u32> = positions
+            .iter()
+            .map(|&p| morton_code_3d(p, &aabb))
+            .collect();
+        let expected_vals: Vec<u32> = (0..number_of_positions).collect();
+
+        let packed: Vec<u32> = positions
+            .iter()
+            .flat_map(|p| {
+                let mut v = vec![p.x, p.y, p.z];
+                v.resize(position_stride as usize, 1.0);
+                v
+            })
+            .map(f32::to_bits)
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  morton_pipeline: Res<MortonKeygenPipeline>| {
+                let positions_buf = create_storage_buffer(&render_device, &packed);
+                let zeros = vec![0u32; number_of_positions as usize];
+                let keys_buf = create_storage_buffer(&render_device, &zeros);
+                let vals_buf = create_storage_buffer(&render_device, &zeros);
+                let bind_group = morton_pipeline.create_bind_group(
+                    &render_device,
+                    &positions_buf,
+                    &keys_buf,
+                    &vals_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: morton_keygen command encoder"),
+                });
+                morton_pipeline.record_morton_keygen(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_positions,
+                    position_stride,
+                    &aabb,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_positions as usize;
+                let keys = read_buffer(&render_device, &render_queue, &keys_buf, n);
+                let vals = read_buffer(&render_device, &render_queue, &vals_buf, n);
+                assert_eq!(keys, expected_keys);
+                assert_eq!(vals, expected_vals);
+            },
+        );
+    }
+
+    /// Positions at the center of the quantization cells, so float rounding can't move them across a cell boundary.
+    fn cell_center_positions(count: u32, aabb: &Aabb3d) -> Vec<Vec3> {
+        let min = Vec3::from(aabb.min);
+        let cell = (Vec3::from(aabb.max) - min) / 1024.0;
+
+        (0..count)
+            .map(|i| {
+                let q = UVec3::new(
+                    i.wrapping_mul(2654435761) % 1024,
+                    i.wrapping_mul(40503) % 1024,
+                    i.wrapping_mul(2246822519) % 1024,
+                );
+                min + (q.as_vec3() + 0.5) * cell
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_morton_keygen_vec4() {
+        let aabb = Aabb3d {
+            min: Vec3::new(-54.0, -36.0, -16.0).into(),
+            max: Vec3::new(74.0, 28.0, 16.0).into(),
+        };
+        run_morton_keygen_test(cell_center_positions(100_000, &aabb), 4, aabb);
+    }
+
+    #[test]
+    fn test_morton_keygen_packed_vec3() {
+        let aabb = Aabb3d::new(Vec3::ZERO, Vec3::splat(1.0));
+        run_morton_keygen_test(cell_center_positions(3_000, &aabb), 3, aabb);
+    }
+
+    #[test]
+    fn test_morton_keygen_clamped_and_degenerate() {
+        // The z extent is 0, every position maps to z = 0
+        let aabb = Aabb3d {
+            min: Vec3::new(0.0, 0.0, 5.0).into(),
+            max: Vec3::new(1.0, 1.0, 5.0).into(),
+        };
+        let positions = vec![
+            Vec3::new(-1.0, -1.0, 5.0),
+            Vec3::new(2.0, 2.0, 5.0),
+            Vec3::new(0.25 + 0.5 / 1024.0, 0.75 + 0.5 / 1024.0, 9.0),
+        ];
+        run_morton_keygen_test(positions, 4, aabb);
+    }
+}
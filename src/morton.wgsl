@@ -0,0 +1,68 @@
+/// Positions, the xyz of position `i` start at `positions[i * position_stride]`
+@group(0) @binding(0) var<storage, read      > positions: array<f32>;
+/// Write the morton code of each position to this buffer
+@group(0) @binding(1) var<storage, read_write> global_keys_o: array<u32>;
+/// Write the index of each position to this buffer
+@group(0) @binding(2) var<storage, read_write> global_vals_o: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of positions to generate keys for.
+    number_of_positions: u32,
+    /// The distance between two positions in the `positions` buffer, in `f32`s (4 for `vec4<f32>`).
+    position_stride: u32,
+    aabb_min_x: f32,
+    aabb_min_y: f32,
+    aabb_min_z: f32,
+    aabb_max_x: f32,
+    aabb_max_y: f32,
+    aabb_max_z: f32,
+}
+var<push_constant> pc: PushConstants;
+
+/// Inserts two 0 bits after each of the 10 low bits of `v`.
+fn expand_bits(v: u32) -> u32 {
+    var x = v & 0x3FFu;
+    x = (x * 0x00010001u) & 0xFF0000FFu;
+    x = (x * 0x00000101u) & 0x0F00F00Fu;
+    x = (x * 0x00000011u) & 0xC30C30C3u;
+    x = (x * 0x00000005u) & 0x49249249u;
+    return x;
+}
+
+/// Quantizes `p` into 1024 steps of [`aabb_min`, `aabb_max`], positions outside of the aabb are clamped.
+fn quantize(p: vec3f, aabb_min: vec3f, aabb_max: vec3f) -> vec3u {
+    let extent = aabb_max - aabb_min;
+    // A degenerate axis maps every position to 0 instead of dividing by 0
+    let normalized = select(vec3f(0.0), (p - aabb_min) / extent, extent > vec3f(0.0));
+    return vec3u(clamp(normalized * 1024.0, vec3f(0.0), vec3f(1023.0)));
+}
+
+fn morton_code_3d(p: vec3f, aabb_min: vec3f, aabb_max: vec3f) -> u32 {
+    let q = quantize(p, aabb_min, aabb_max);
+    return (expand_bits(q.x) << 2u) | (expand_bits(q.y) << 1u) | expand_bits(q.z);
+}
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+
+    if index >= pc.number_of_positions {
+        return;
+    }
+
+    let base = index * pc.position_stride;
+    let p = vec3f(positions[base], positions[base + 1u], positions[base + 2u]);
+
+    let aabb_min = vec3f(pc.aabb_min_x, pc.aabb_min_y, pc.aabb_min_z);
+    let aabb_max = vec3f(pc.aabb_max_x, pc.aabb_max_y, pc.aabb_max_z);
+
+    global_keys_o[index] = morton_code_3d(p, aabb_min, aabb_max);
+    global_vals_o[index] = index;
+}
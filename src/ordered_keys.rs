@@ -0,0 +1,207 @@
+//! Order-preserving mappings from `f32`/`i32` to the `u32` keys of the sort, and back.
+//!
+//! The same functions are available to WGSL once the [`crate::RadixSortPlugin`] is added, e.g. in a custom keygen
+//! pass writing into the global buffers:
+//!
+//! ```wgsl
+//! #import bevy_radix_sort::ordered_keys::{f32_to_ordered_u32, i32_to_ordered_u32}
+//! ```
+//!
+//! Floats sort by their total order, `-NaN < -inf < .. < -0.0 < +0.0 < .. < +inf < +NaN` as with [`f32::total_cmp`],
+//! and all mappings round-trip every bit pattern, including NaN payloads.
+
+use bevy::prelude::*;
+
+pub const ORDERED_KEYS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(186736233304742116339410786021998466893);
+
+/// The import path of `ordered_keys.wgsl`.
+pub const ORDERED_KEYS_IMPORT_PATH: &str = "bevy_radix_sort::ordered_keys";
+
+const SIGN_BIT: u32 = 0x8000_0000;
+
+/// Maps a f32 to a u32 so that the unsigned order of the keys matches the total order of the floats.
+pub const fn f32_to_ordered_u32(f: f32) -> u32 {
+    let bits = f.to_bits();
+    let mask = if bits & SIGN_BIT != 0 {
+        0xFFFF_FFFF
+    } else {
+        SIGN_BIT
+    };
+    bits ^ mask
+}
+
+/// The inverse of [`f32_to_ordered_u32`].
+pub const fn ordered_u32_to_f32(key: u32) -> f32 {
+    let mask = if key & SIGN_BIT != 0 {
+        SIGN_BIT
+    } else {
+        0xFFFF_FFFF
+    };
+    f32::from_bits(key ^ mask)
+}
+
+/// Maps an i32 to a u32 so that the unsigned order of the keys matches the order of the integers.
+pub const fn i32_to_ordered_u32(i: i32) -> u32 {
+    i as u32 ^ SIGN_BIT
+}
+
+/// The inverse of [`i32_to_ordered_u32`].
+pub const fn ordered_u32_to_i32(key: u32) -> i32 {
+    (key ^ SIGN_BIT) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::PipelineCache,
+        renderer::{RenderDevice, RenderQueue},
+    };
+
+    use crate::{
+        RadixSortPlugin,
+        test_utils::{
+            TestShaderPipeline, TestShaderPlugin, create_render_test_app, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// Writes the 4 mappings of each input bit pattern, in the order of [`cpu_mappings`].
+    const TEST_SHADER: &str = r"
+#import bevy_radix_sort::ordered_keys::{f32_to_ordered_u32, ordered_u32_to_f32, i32_to_ordered_u32, ordered_u32_to_i32}
+
+@group(0) @binding(0) var<storage, read      > inputs: array<u32>;
+@group(0) @binding(1) var<storage, read_write> outputs: array<u32>;
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3u) {
+    let index = global_invocation_id.x;
+    if index >= arrayLength(&inputs) {
+        return;
+    }
+
+    let bits = inputs[index];
+    outputs[4u * index + 0u] = f32_to_ordered_u32(bitcast<f32>(bits));
+    outputs[4u * index + 1u] = bitcast<u32>(ordered_u32_to_f32(bits));
+    outputs[4u * index + 2u] = i32_to_ordered_u32(bitcast<i32>(bits));
+    outputs[4u * index + 3u] = bitcast<u32>(ordered_u32_to_i32(bits));
+}
+";
+
+    fn cpu_mappings(bits: u32) -> [u32; 4] {
+        [
+            f32_to_ordered_u32(f32::from_bits(bits)),
+            ordered_u32_to_f32(bits).to_bits(),
+            i32_to_ordered_u32(bits as i32),
+            ordered_u32_to_i32(bits) as u32,
+        ]
+    }
+
+    /// Every special float and integer, then a sweep over all bit patterns.
+    fn test_bit_patterns() -> Vec<u32> {
+        let specials = [
+            0.0f32,
+            -0.0,
+            f32::MIN_POSITIVE,
+            -f32::MIN_POSITIVE,
+            f32::from_bits(1),
+            -f32::from_bits(1),
+            f32::from_bits(0x007F_FFFF),
+            -f32::from_bits(0x007F_FFFF),
+            1.0,
+            -1.0,
+            f32::MAX,
+            f32::MIN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            -f32::NAN,
+            f32::from_bits(0x7F80_0001),
+            f32::from_bits(0x7FC0_1234),
+            f32::from_bits(0xFF80_0001),
+            f32::from_bits(0xFFFF_FFFF),
+        ];
+        let integers = [0, 1, -1, i32::MIN, i32::MAX, i32::MIN + 1, i32::MAX - 1];
+
+        specials
+            .iter()
+            .map(|f| f.to_bits())
+            .chain(integers.iter().map(|&i| i as u32))
+            .chain((0..u32::MAX).step_by(65_521))
+            .chain([u32::MAX, 0x8000_0000, 0x7FFF_FFFF])
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for bits in test_bit_patterns() {
+            let f = f32::from_bits(bits);
+            assert_eq!(ordered_u32_to_f32(f32_to_ordered_u32(f)).to_bits(), bits);
+            assert_eq!(f32_to_ordered_u32(ordered_u32_to_f32(bits)), bits);
+
+            let i = bits as i32;
+            assert_eq!(ordered_u32_to_i32(i32_to_ordered_u32(i)), i);
+            assert_eq!(i32_to_ordered_u32(ordered_u32_to_i32(bits)), bits);
+        }
+    }
+
+    #[test]
+    fn test_order_preserving() {
+        let patterns = test_bit_patterns();
+
+        let mut floats: Vec<f32> = patterns.iter().map(|&bits| f32::from_bits(bits)).collect();
+        floats.sort_by(f32::total_cmp);
+        for pair in floats.windows(2) {
+            assert_eq!(
+                f32_to_ordered_u32(pair[0]).cmp(&f32_to_ordered_u32(pair[1])),
+                pair[0].total_cmp(&pair[1]),
+                "{:?} {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+        assert!(f32_to_ordered_u32(-0.0) < f32_to_ordered_u32(0.0));
+        assert!(f32_to_ordered_u32(-f32::NAN) < f32_to_ordered_u32(f32::NEG_INFINITY));
+        assert!(f32_to_ordered_u32(f32::INFINITY) < f32_to_ordered_u32(f32::NAN));
+
+        let mut integers: Vec<i32> = patterns.iter().map(|&bits| bits as i32).collect();
+        integers.sort();
+        for pair in integers.windows(2) {
+            assert_eq!(
+                i32_to_ordered_u32(pair[0]).cmp(&i32_to_ordered_u32(pair[1])),
+                pair[0].cmp(&pair[1])
+            );
+        }
+        assert_eq!(i32_to_ordered_u32(i32::MIN), 0);
+        assert_eq!(i32_to_ordered_u32(i32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_wgsl_matches_cpu() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin { settings: 1.into() })
+            .add_plugins(TestShaderPlugin(TEST_SHADER));
+
+        let inputs = test_bit_patterns();
+        let expected: Vec<u32> = inputs.iter().copied().flat_map(cpu_mappings).collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  test_shader_pipeline: Res<TestShaderPipeline>| {
+                let outputs = test_shader_pipeline.run(
+                    &render_device,
+                    &render_queue,
+                    &pipeline_cache,
+                    &inputs,
+                    expected.len(),
+                    (inputs.len() as u32).div_ceil(64),
+                );
+                assert_eq!(outputs, expected);
+            },
+        );
+    }
+}
@@ -0,0 +1,30 @@
+#define_import_path bevy_radix_sort::ordered_keys
+
+// Order-preserving mappings between signed/float values and the u32 keys of the sort,
+// the WGSL versions of the functions in `ordered_keys.rs`.
+//
+// ```wgsl
+// #import bevy_radix_sort::ordered_keys::f32_to_ordered_u32
+//
+// global_keys_o[index] = f32_to_ordered_u32(depth);
+// ```
+//
+// The mappings are defined in `keys.wgsl`, next to the rest of the definitions shared with the sort.
+
+#import bevy_radix_sort::keys
+
+fn f32_to_ordered_u32(f: f32) -> u32 {
+    return keys::f32_to_ordered_u32(f);
+}
+
+fn ordered_u32_to_f32(key: u32) -> f32 {
+    return keys::ordered_u32_to_f32(key);
+}
+
+fn i32_to_ordered_u32(i: i32) -> u32 {
+    return keys::i32_to_ordered_u32(i);
+}
+
+fn ordered_u32_to_i32(key: u32) -> i32 {
+    return keys::ordered_u32_to_i32(key);
+}
@@ -0,0 +1,503 @@
+//! Sorting into buffers of the user instead of the global buffers, without copying the results out.
+//!
+//! The passes of [`crate::run`] ping-pong between the two sides of the global buffers, the results land on one of
+//! them. [`run_into_output`] runs the passes before the last one the same way, then binds the [`RadixSortOutput`]
+//! in place of the other side for the last one, so its scatter writes the sorted keys and vals where they're
+//! consumed, e.g. the instance buffer of a renderer:
+//!
+//! ```text
+//!  4 passes from the even side:  even ─▶ odd ─▶ even ─▶ odd ─▶ output
+//! ```
+//!
+//! The output side of the last pass isn't written, it keeps what it held. A partial pass range means the same: the
+//! output holds the keys sorted by the digits of the range, and a later [`crate::run`] of the next passes has to
+//! read them from the output, the global buffers don't have them.
+
+use std::fmt;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{Buffer, BufferAddress, BufferUsages, CommandEncoder, PipelineCache},
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    NUMBER_OF_BYTES_PER_KEY, RadixSortBindGroup, RadixSortPipeline, RadixSortRunOptions,
+    create_dummy_vals_buffers, global_blocks_buffer, global_keys_buffer, global_vals_buffer,
+    is_input_even, read_from_even_starting_on, run,
+};
+
+/// The buffers the last pass of [`run_into_output`] writes.
+///
+/// Each holds at least the number of keys sorted, as u32s, and has the `STORAGE` usage.
+#[derive(Debug, Clone, Copy)]
+pub struct RadixSortOutput<'a> {
+    pub keys: &'a Buffer,
+    /// Required unless the sort is keys-only, see [`crate::RadixSortSettings::without_values`]. Ignored by the
+    /// keys-only sorts.
+    pub vals: Option<&'a Buffer>,
+}
+
+/// The reasons [`run_into_output`] rejects a sort, nothing is recorded then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputRedirectError {
+    /// There is no last pass to redirect.
+    EmptyPassRange,
+    /// [`RadixSortRunOptions::preserve_input`] or [`RadixSortRunOptions::stats`], which work on the global buffers.
+    UnsupportedOption(&'static str),
+    /// The sort has vals but the output has no vals buffer.
+    MissingVals,
+    /// The `name` buffer of the output holds `len` u32s, fewer than the `number_of_keys` sorted.
+    BufferTooSmall {
+        name: &'static str,
+        len: u32,
+        number_of_keys: u32,
+    },
+    /// The `name` buffer of the output doesn't have the `STORAGE` usage.
+    MissingStorageUsage(&'static str),
+    /// The `name` buffer of the output is also bound as the input of the last pass, or as the other output buffer.
+    Aliasing(&'static str),
+    /// The global buffers aren't prepared yet.
+    NotPrepared,
+}
+
+impl fmt::Display for OutputRedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyPassRange => write!(f, "an empty pass range has no last pass to redirect"),
+            Self::UnsupportedOption(option) => {
+                write!(
+                    f,
+                    "the {option} option can't be combined with an output redirect"
+                )
+            }
+            Self::MissingVals => write!(f, "the sort has vals, but the output has no vals buffer"),
+            Self::BufferTooSmall {
+                name,
+                len,
+                number_of_keys,
+            } => write!(
+                f,
+                "the output {name} buffer holds {len} u32s, fewer than the {number_of_keys} keys sorted"
+            ),
+            Self::MissingStorageUsage(name) => {
+                write!(f, "the output {name} buffer doesn't have the STORAGE usage")
+            }
+            Self::Aliasing(name) => write!(
+                f,
+                "the output {name} buffer is also bound as another buffer of the last pass"
+            ),
+            Self::NotPrepared => write!(f, "the global buffers aren't prepared"),
+        }
+    }
+}
+
+impl std::error::Error for OutputRedirectError {}
+
+/// [`crate::run_with_options`] on the global buffers, the last pass writing the `output` instead of the other side,
+/// see the module docs.
+///
+/// Like [`crate::run`], fewer than 2 keys aren't sorted and leave the output untouched.
+#[allow(clippy::too_many_arguments)]
+pub fn run_into_output(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    options: &RadixSortRunOptions,
+    read_from_even: bool,
+    output: &RadixSortOutput,
+) -> Result<(), OutputRedirectError> {
+    let pass_range = options.pass_range.clone();
+    if pass_range.is_empty() {
+        return Err(OutputRedirectError::EmptyPassRange);
+    }
+    if options.preserve_input {
+        return Err(OutputRedirectError::UnsupportedOption("preserve_input"));
+    }
+    if options.stats {
+        return Err(OutputRedirectError::UnsupportedOption("stats"));
+    }
+
+    let last_pass = pass_range.end - 1..pass_range.end;
+    let last_input_even = is_input_even(last_pass.start, read_from_even);
+    let (Some(input_keys), Some(blocks)) = (
+        global_keys_buffer(sbufs, last_input_even),
+        global_blocks_buffer(sbufs),
+    ) else {
+        return Err(OutputRedirectError::NotPrepared);
+    };
+
+    // Keys-only sorts bind stand-ins, their kernels never access the vals
+    let dummy_vals;
+    let (input_vals, output_vals) = if radix_sort_pipeline.allocate_values() {
+        let input_vals =
+            global_vals_buffer(sbufs, last_input_even).ok_or(OutputRedirectError::NotPrepared)?;
+        let output_vals = output.vals.ok_or(OutputRedirectError::MissingVals)?;
+        (input_vals, output_vals)
+    } else {
+        dummy_vals = create_dummy_vals_buffers(render_device);
+        (&dummy_vals.0, &dummy_vals.1)
+    };
+
+    let output_buffers = [("keys", output.keys)].into_iter().chain(
+        radix_sort_pipeline
+            .allocate_values()
+            .then_some(("vals", output_vals)),
+    );
+    for (name, buffer) in output_buffers {
+        let len = (buffer.size() / NUMBER_OF_BYTES_PER_KEY as BufferAddress).min(u32::MAX as u64);
+        if len < number_of_keys as u64 {
+            return Err(OutputRedirectError::BufferTooSmall {
+                name,
+                len: len as u32,
+                number_of_keys,
+            });
+        }
+        if !buffer.usage().contains(BufferUsages::STORAGE) {
+            return Err(OutputRedirectError::MissingStorageUsage(name));
+        }
+        if [input_keys, input_vals, blocks]
+            .iter()
+            .any(|bound| bound.id() == buffer.id())
+        {
+            return Err(OutputRedirectError::Aliasing(name));
+        }
+    }
+    if output.keys.id() == output_vals.id() {
+        return Err(OutputRedirectError::Aliasing("vals"));
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!(
+        "radix_sort::run_into_output",
+        number_of_keys,
+        passes = pass_range.len()
+    )
+    .entered();
+
+    let leading_passes = pass_range.start..last_pass.start;
+    if !leading_passes.is_empty() {
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            leading_passes,
+            options.init_index,
+            read_from_even,
+        );
+    }
+
+    // The even side of the pair is the input of the last pass
+    let into_output = RadixSortBindGroup::from_buffers(
+        render_device,
+        radix_sort_pipeline.bind_group_layout(),
+        input_keys,
+        input_vals,
+        blocks,
+        output.keys,
+        output_vals,
+    );
+    run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        &into_output,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        last_pass.clone(),
+        options.init_index && last_pass.start == pass_range.start,
+        read_from_even_starting_on(&last_pass, true),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use bevy::render::{
+        render_resource::{BufferDescriptor, CommandEncoderDescriptor},
+        renderer::RenderQueue,
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    const NUMBER_OF_KEYS: u32 = 3000;
+
+    #[test]
+    fn test_run_into_output() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: NUMBER_OF_KEYS.into(),
+            });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             render_queue: Res<RenderQueue>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>,
+             sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let n = NUMBER_OF_KEYS as usize;
+                let keys: Vec<u32> = (0..NUMBER_OF_KEYS)
+                    .map(|i| i.wrapping_mul(2654435761))
+                    .collect();
+                let vals: Vec<u32> = (0..NUMBER_OF_KEYS).map(|i| i ^ 0x5555).collect();
+                let sentinel = vec![0xdead_beef_u32; n];
+
+                // A stable sort by the digits of the passes
+                let sorted_by = |passes: Range<u32>, init_index: bool| {
+                    if passes.is_empty() {
+                        return (keys.clone(), vals.clone());
+                    }
+                    let digits = |key: u32| {
+                        (key >> (8 * passes.start)) & (u32::MAX >> (32 - 8 * passes.len() as u32))
+                    };
+                    let mut expected: Vec<(u32, u32)> = if init_index {
+                        keys.iter().copied().zip(0..).collect()
+                    } else {
+                        keys.iter().copied().zip(vals.iter().copied()).collect()
+                    };
+                    expected.sort_by_key(|&(key, _)| digits(key));
+                    expected.into_iter().unzip::<_, _, Vec<u32>, Vec<u32>>()
+                };
+
+                let create_output = |label| {
+                    render_device.create_buffer(&BufferDescriptor {
+                        label: Some(label),
+                        size: NUMBER_OF_KEYS as BufferAddress
+                            * NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+                        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    })
+                };
+                let output_keys = create_output("unit_test: output_redirect keys buffer");
+                let output_vals = create_output("unit_test: output_redirect vals buffer");
+                let output = RadixSortOutput {
+                    keys: &output_keys,
+                    vals: Some(&output_vals),
+                };
+
+                // Odd and even pass counts, a single pass, a range starting at an odd pass and both sides
+                for (pass_range, read_from_even, init_index) in [
+                    (0..4, true, true),
+                    (0..3, false, false),
+                    (0..1, true, true),
+                    (1..4, true, false),
+                ] {
+                    let input_even = is_input_even(pass_range.start, read_from_even);
+                    render_queue.write_buffer(
+                        global_keys_buffer(&sbufs, input_even).unwrap(),
+                        0,
+                        bytemuck::cast_slice(&keys),
+                    );
+                    render_queue.write_buffer(
+                        global_vals_buffer(&sbufs, input_even).unwrap(),
+                        0,
+                        bytemuck::cast_slice(&vals),
+                    );
+                    // The side the last pass would write otherwise
+                    let skipped_even = !is_input_even(pass_range.end - 1, read_from_even);
+                    let skipped_keys = global_keys_buffer(&sbufs, skipped_even).unwrap();
+                    let skipped_vals = global_vals_buffer(&sbufs, skipped_even).unwrap();
+                    if pass_range.len() == 1 {
+                        render_queue.write_buffer(skipped_keys, 0, bytemuck::cast_slice(&sentinel));
+                        render_queue.write_buffer(skipped_vals, 0, bytemuck::cast_slice(&sentinel));
+                    }
+
+                    let options = RadixSortRunOptions {
+                        pass_range: pass_range.clone(),
+                        init_index,
+                        ..default()
+                    };
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: output_redirect command encoder"),
+                        });
+                    run_into_output(
+                        &mut encoder,
+                        &render_device,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        NUMBER_OF_KEYS,
+                        &options,
+                        read_from_even,
+                        &output,
+                    )
+                    .unwrap();
+                    render_queue.submit([encoder.finish()]);
+
+                    let label = format!("{pass_range:?}, read_from_even: {read_from_even}");
+                    let read =
+                        |buffer: &Buffer| read_buffer(&render_device, &render_queue, buffer, n);
+                    let (expected_keys, expected_vals) = sorted_by(pass_range.clone(), init_index);
+                    assert_eq!(read(&output_keys), expected_keys, "{label}");
+                    assert_eq!(read(&output_vals), expected_vals, "{label}");
+
+                    // The last pass didn't write the other side: it still holds its input of the pass before, not
+                    // the results
+                    let (skipped_keys_expected, skipped_vals_expected) = if pass_range.len() == 1 {
+                        (sentinel.clone(), sentinel.clone())
+                    } else {
+                        sorted_by(pass_range.start..pass_range.end - 2, init_index)
+                    };
+                    assert_eq!(read(skipped_keys), skipped_keys_expected, "{label}");
+                    assert_eq!(read(skipped_vals), skipped_vals_expected, "{label}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_into_output_rejected() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: NUMBER_OF_KEYS.into(),
+            });
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>,
+             sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let create_output = |keys: u32, usage| {
+                    render_device.create_buffer(&BufferDescriptor {
+                        label: Some("unit_test: output_redirect buffer"),
+                        size: keys as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+                        usage,
+                        mapped_at_creation: false,
+                    })
+                };
+                let keys = create_output(NUMBER_OF_KEYS, BufferUsages::STORAGE);
+                let vals = create_output(NUMBER_OF_KEYS, BufferUsages::STORAGE);
+                let small = create_output(NUMBER_OF_KEYS - 1, BufferUsages::STORAGE);
+                let not_storage = create_output(NUMBER_OF_KEYS, BufferUsages::COPY_DST);
+                // 4 passes from the even side, the last one reads the odd side
+                let last_input = global_keys_buffer(&sbufs, false).unwrap();
+
+                let redirect = |output: RadixSortOutput, options: RadixSortRunOptions| {
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: output_redirect command encoder"),
+                        });
+                    run_into_output(
+                        &mut encoder,
+                        &render_device,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        NUMBER_OF_KEYS,
+                        &options,
+                        true,
+                        &output,
+                    )
+                };
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &keys,
+                            vals: Some(&vals),
+                        },
+                        RadixSortRunOptions {
+                            pass_range: 0..0,
+                            ..default()
+                        }
+                    ),
+                    Err(OutputRedirectError::EmptyPassRange)
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &keys,
+                            vals: Some(&vals),
+                        },
+                        RadixSortRunOptions::default().with_stats()
+                    ),
+                    Err(OutputRedirectError::UnsupportedOption("stats"))
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &keys,
+                            vals: None,
+                        },
+                        default()
+                    ),
+                    Err(OutputRedirectError::MissingVals)
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &small,
+                            vals: Some(&vals),
+                        },
+                        default()
+                    ),
+                    Err(OutputRedirectError::BufferTooSmall {
+                        name: "keys",
+                        len: NUMBER_OF_KEYS - 1,
+                        number_of_keys: NUMBER_OF_KEYS,
+                    })
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &keys,
+                            vals: Some(&not_storage),
+                        },
+                        default()
+                    ),
+                    Err(OutputRedirectError::MissingStorageUsage("vals"))
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: last_input,
+                            vals: Some(&vals),
+                        },
+                        default()
+                    ),
+                    Err(OutputRedirectError::Aliasing("keys"))
+                );
+                assert_eq!(
+                    redirect(
+                        RadixSortOutput {
+                            keys: &keys,
+                            vals: Some(&keys),
+                        },
+                        default()
+                    ),
+                    Err(OutputRedirectError::Aliasing("vals"))
+                );
+            },
+        );
+    }
+}
@@ -0,0 +1,580 @@
+//! Sorts many small arrays with a single [`crate::run`], by storing the segment id of each key above its bits.
+//!
+//! The segments are consecutive ranges of the global keys, described by their offsets. The pack pass
+//! writes `(segment_id << key_bits) | key` into the keys, the sort covers the bits of both and the unpack pass
+//! restores the original keys:
+//!
+//! ```text
+//!  segment_offsets  [ 0,       3,    5 ]
+//!  keys             [ 7, 2, 5, 4, 1 ]
+//!  packed           [ 0|7, 0|2, 0|5, 1|4, 1|1 ]   the segment id in the high bits
+//!  sorted           [ 0|2, 0|5, 0|7, 1|1, 1|4 ]
+//!  unpacked         [ 2, 5, 7, 1, 4 ]
+//! ```
+//!
+//! The sort is stable: keys equal within a segment keep their relative order, and with `init_index` the vals
+//! hold the index of each key in the global buffers. As the segments sort by id, each one ends up sorted in place,
+//! within `segment_offsets[s]..segment_offsets[s + 1]`.
+//!
+//! Compared to a segmented sort, this is limited to `segment_bits(number_of_segments) + key_bits <= 32`, and
+//! every segment pays for the passes of all segment bits.
+
+use std::{fmt, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferInitDescriptor, BufferUsages, CachedComputePipelineId, CommandEncoder,
+            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, PushConstantRange,
+            ShaderDefVal, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup,
+    RadixSortPipeline, compute_pipelines_load_state, dispatch_workgroup_ext, global_keys_buffer,
+    passes_needed, run, sorted_keys_buffer,
+};
+
+pub const PACKED_SEGMENTS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(299429122672918133239209217095307361536);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const NUMBER_OF_SEGMENTS_OFFSET: u32 = 8;
+const KEY_BITS_OFFSET: u32 = 12;
+const KEY_MASK_OFFSET: u32 = 16;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..20,
+};
+
+/// The number of bits storing the id of one of `number_of_segments` segments.
+pub const fn segment_bits(number_of_segments: u32) -> u32 {
+    if number_of_segments <= 1 {
+        0
+    } else {
+        u32::BITS - (number_of_segments - 1).leading_zeros()
+    }
+}
+
+const fn key_mask(key_bits: u32) -> u32 {
+    if key_bits >= 32 {
+        u32::MAX
+    } else {
+        (1 << key_bits) - 1
+    }
+}
+
+/// The reasons [`run_packed_segments`] rejects the segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedSegmentsError {
+    /// The segment ids and the keys don't fit in 32 bits.
+    TooManyBits { segment_bits: u32, key_bits: u32 },
+    /// `segment_offsets` must hold at least the offset of the end, and start at 0.
+    MissingOffsets,
+    /// The offset of segment `segment` is smaller than the offset of the previous one.
+    DecreasingOffsets { segment: usize },
+    /// The segments hold more keys than the global buffers.
+    TooManyKeys { number_of_keys: u32, capacity: u32 },
+}
+
+impl fmt::Display for PackedSegmentsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyBits {
+                segment_bits,
+                key_bits,
+            } => write!(
+                f,
+                "{segment_bits} segment bits and {key_bits} key bits exceed the 32 bits of a key"
+            ),
+            Self::MissingOffsets => write!(
+                f,
+                "the segment offsets must start at 0 and end with the number of keys"
+            ),
+            Self::DecreasingOffsets { segment } => write!(
+                f,
+                "the offset of segment {segment} is smaller than the offset of the previous one"
+            ),
+            Self::TooManyKeys {
+                number_of_keys,
+                capacity,
+            } => write!(
+                f,
+                "the segments hold {number_of_keys} keys, more than the capacity of {capacity} keys"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PackedSegmentsError {}
+
+pub struct PackedSegmentsPlugin;
+
+impl Plugin for PackedSegmentsPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PACKED_SEGMENTS_SHADER_HANDLE,
+            "packed_segments.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<PackedSegmentsPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct PackedSegmentsPipeline {
+    /// Store the segment id of each key above its bits
+    pack_pipeline: CachedComputePipelineId,
+    /// Clear the segment ids
+    unpack_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read_write> keys: array<u32>;
+    /// @binding(1) var<storage, read      > segment_offsets: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for PackedSegmentsPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "packed_segments bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let pack_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("packed_segments: pack pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: PACKED_SEGMENTS_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["PACK_SEGMENTS_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let unpack_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("packed_segments: unpack pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: PACKED_SEGMENTS_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["UNPACK_SEGMENTS_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pack_pipeline,
+            unpack_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl PackedSegmentsPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("packed_segments pack_pipeline", self.pack_pipeline),
+                ("packed_segments unpack_pipeline", self.unpack_pipeline),
+            ],
+        )
+    }
+
+    /// `segment_offsets` holds the start of each segment followed by the number of keys.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        keys: &Buffer,
+        segment_offsets: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "packed_segments: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                keys.as_entire_binding(),
+                segment_offsets.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Replaces each of the first `number_of_keys` keys by `(segment_id << key_bits) | (key & key_mask)`, the
+    /// bits of the keys above `key_bits` are lost.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_pack_segments(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        number_of_segments: u32,
+        key_bits: u32,
+    ) {
+        debug_assert!(segment_bits(number_of_segments) + key_bits <= 32);
+
+        self.record(
+            encoder,
+            pipeline_cache,
+            self.pack_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            number_of_segments,
+            key_bits,
+        );
+    }
+
+    /// Clears the bits above `key_bits` of the first `number_of_keys` keys, the segment ids written by
+    /// [`Self::record_pack_segments`].
+    pub fn record_unpack_segments(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        key_bits: u32,
+    ) {
+        self.record(
+            encoder,
+            pipeline_cache,
+            self.unpack_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            0,
+            key_bits,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        pipeline: CachedComputePipelineId,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        number_of_segments: u32,
+        key_bits: u32,
+    ) {
+        if number_of_keys == 0 {
+            return;
+        }
+
+        let pipeline = pipeline_cache.get_compute_pipeline(pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("packed_segments compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(
+            NUMBER_OF_SEGMENTS_OFFSET,
+            bytemuck::bytes_of(&number_of_segments),
+        );
+        pass.set_push_constants(KEY_BITS_OFFSET, bytemuck::bytes_of(&key_bits));
+        pass.set_push_constants(KEY_MASK_OFFSET, bytemuck::bytes_of(&key_mask(key_bits)));
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+/// Sorts each segment of the global keys with one [`crate::run`], see the module docs.
+///
+/// `segment_offsets` holds the start of each segment followed by the number of keys, the keys must fit in
+/// `key_bits` bits. Returns the pass range of the sort, the sorted segments are in
+/// [`crate::sorted_keys_buffer`] and [`crate::sorted_vals_buffer`] of it.
+#[allow(clippy::too_many_arguments)]
+pub fn run_packed_segments(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    packed_segments_pipeline: &PackedSegmentsPipeline,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    segment_offsets: &[u32],
+    key_bits: u32,
+    init_index: bool,
+    read_from_even: bool,
+) -> Result<Range<u32>, PackedSegmentsError> {
+    let (Some(0), Some(&number_of_keys)) = (segment_offsets.first(), segment_offsets.last()) else {
+        return Err(PackedSegmentsError::MissingOffsets);
+    };
+
+    if let Some(segment) = segment_offsets.windows(2).position(|w| w[0] > w[1]) {
+        return Err(PackedSegmentsError::DecreasingOffsets {
+            segment: segment + 1,
+        });
+    }
+
+    let number_of_segments = segment_offsets.len() as u32 - 1;
+    let segment_bits = segment_bits(number_of_segments);
+    if segment_bits + key_bits > 32 {
+        return Err(PackedSegmentsError::TooManyBits {
+            segment_bits,
+            key_bits,
+        });
+    }
+
+    let input_keys = global_keys_buffer(sbufs, read_from_even).unwrap();
+    let capacity = (input_keys.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+    if number_of_keys > capacity {
+        return Err(PackedSegmentsError::TooManyKeys {
+            number_of_keys,
+            capacity,
+        });
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!(
+        "radix_sort::run_packed_segments",
+        number_of_keys,
+        number_of_segments
+    )
+    .entered();
+
+    let pass_range = 0..passes_needed(segment_bits + key_bits);
+
+    let segment_offsets_buf = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("packed_segments: segment_offsets buffer"),
+        contents: bytemuck::cast_slice(segment_offsets),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let pack_bind_group =
+        packed_segments_pipeline.create_bind_group(render_device, input_keys, &segment_offsets_buf);
+    packed_segments_pipeline.record_pack_segments(
+        encoder,
+        pipeline_cache,
+        &pack_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        number_of_segments,
+        key_bits,
+    );
+
+    run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.clone(),
+        init_index,
+        read_from_even,
+    );
+
+    let output_keys = sorted_keys_buffer(sbufs, &pass_range, read_from_even).unwrap();
+    let unpack_bind_group = packed_segments_pipeline.create_bind_group(
+        render_device,
+        output_keys,
+        &segment_offsets_buf,
+    );
+    packed_segments_pipeline.record_unpack_segments(
+        encoder,
+        pipeline_cache,
+        &unpack_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        key_bits,
+    );
+
+    Ok(pass_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        RadixSortPlugin, sorted_vals_buffer,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_segment_bits() {
+        assert_eq!(segment_bits(0), 0);
+        assert_eq!(segment_bits(1), 0);
+        assert_eq!(segment_bits(2), 1);
+        assert_eq!(segment_bits(200), 8);
+        assert_eq!(segment_bits(256), 8);
+        assert_eq!(segment_bits(257), 9);
+    }
+
+    fn run_packed_segments_test(
+        segment_offsets: Vec<u32>,
+        key_bits: u32,
+        read_from_even: bool,
+        expected_pass_range: Result<Range<u32>, PackedSegmentsError>,
+    ) {
+        let number_of_keys = *segment_offsets.last().unwrap_or(&0);
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: number_of_keys.max(1).into(),
+        })
+        .add_plugins(PackedSegmentsPlugin);
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761).rotate_left(7) & key_mask(key_bits))
+            .collect();
+
+        // Each segment sorted on its own, equal keys in their original order
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        for segment in segment_offsets.windows(2).filter(|w| w[0] <= w[1]) {
+            expected[segment[0] as usize..segment[1] as usize].sort();
+        }
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  packed_segments_pipeline: Res<PackedSegmentsPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: packed_segments command encoder"),
+                });
+                let result = run_packed_segments(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &packed_segments_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    &segment_offsets,
+                    key_bits,
+                    true,
+                    read_from_even,
+                );
+                assert_eq!(result, expected_pass_range);
+                let Ok(pass_range) = result else {
+                    return;
+                };
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &pass_range, read_from_even);
+                let output_vals_buf = sorted_vals_buffer(&sbufs, &pass_range, read_from_even);
+                let output_keys =
+                    read_buffer(&render_device, &render_queue, output_keys_buf.unwrap(), n);
+                let output_vals =
+                    read_buffer(&render_device, &render_queue, output_vals_buf.unwrap(), n);
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_ragged_segments() {
+        // Empty segments, single keys, full 4096-key segments and everything in between
+        let mut segment_offsets = vec![0];
+        for i in 0..200u32 {
+            let len = match i % 7 {
+                0 => 0,
+                1 => 1,
+                2 => 4096,
+                _ => i.wrapping_mul(2654435761) % 4096,
+            };
+            segment_offsets.push(segment_offsets.last().unwrap() + len);
+        }
+
+        // 8 segment bits above 12 key bits, 3 passes
+        run_packed_segments_test(segment_offsets.clone(), 12, true, Ok(0..3));
+        // Duplicates in every segment
+        run_packed_segments_test(segment_offsets, 4, false, Ok(0..2));
+    }
+
+    #[test]
+    fn test_single_segment() {
+        run_packed_segments_test(vec![0, 10_000], 32, true, Ok(0..4));
+    }
+
+    #[test]
+    fn test_invalid_segments() {
+        run_packed_segments_test(vec![], 12, true, Err(PackedSegmentsError::MissingOffsets));
+        run_packed_segments_test(
+            vec![1, 10],
+            12,
+            true,
+            Err(PackedSegmentsError::MissingOffsets),
+        );
+        run_packed_segments_test(
+            vec![0, 10, 5, 20],
+            12,
+            true,
+            Err(PackedSegmentsError::DecreasingOffsets { segment: 2 }),
+        );
+        // 300 segments need 9 bits
+        let segment_offsets: Vec<u32> = (0..=300).collect();
+        run_packed_segments_test(
+            segment_offsets,
+            24,
+            true,
+            Err(PackedSegmentsError::TooManyBits {
+                segment_bits: 9,
+                key_bits: 24,
+            }),
+        );
+    }
+}
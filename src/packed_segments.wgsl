@@ -0,0 +1,56 @@
+/// The keys to pack or unpack in place
+@group(0) @binding(0) var<storage, read_write> keys: array<u32>;
+/// The start of each segment, followed by the number of keys
+@group(0) @binding(1) var<storage, read      > segment_offsets: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys of all segments.
+    number_of_keys: u32,
+    /// The number of segments, `segment_offsets` holds one more offset.
+    number_of_segments: u32,
+    /// The number of bits of the original keys, the segment id is stored above them.
+    key_bits: u32,
+    /// `(1 << key_bits) - 1`
+    key_mask: u32,
+}
+var<push_constant> pc: PushConstants;
+
+/// The last segment starting at or before `key_index`, empty segments are skipped.
+fn segment_of(key_index: u32) -> u32 {
+    var lo = 0u;
+    var hi = pc.number_of_segments + 1u;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2u;
+        if segment_offsets[mid] <= key_index {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+
+    return lo - 1u;
+}
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let key_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+    if key_index >= pc.number_of_keys {
+        return;
+    }
+
+#ifdef PACK_SEGMENTS_PIPELINE
+    // With `key_bits == 32` there is a single segment 0, the shift amount wraps to 0
+    keys[key_index] = (segment_of(key_index) << pc.key_bits) | (keys[key_index] & pc.key_mask);
+#endif // PACK_SEGMENTS_PIPELINE
+
+#ifdef UNPACK_SEGMENTS_PIPELINE
+    keys[key_index] &= pc.key_mask;
+#endif // UNPACK_SEGMENTS_PIPELINE
+}
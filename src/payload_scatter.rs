@@ -0,0 +1,576 @@
+//! A user hook replacing how the last scatter of the sort writes the vals, e.g. to turn the sorted indices into
+//! instance records without a pass of their own.
+//!
+//! The hook is a WGSL snippet defining `write_payload`, appended to the scatter kernel and called for every key
+//! once its sorted position is known. The kernel still writes the sorted keys, only the vals go through the hook:
+//!
+//! ```text
+//!  passes 0..n-1:  count ─▶ scan ─▶ scatter        keys, vals ─▶ global_keys_o, global_vals_o
+//!  pass n-1:       count ─▶ scan ─▶ scatter        keys       ─▶ global_keys_o
+//!                                                  vals       ─▶ write_payload(sorted_index, key, val)
+//! ```
+//!
+//! ```wgsl
+//! struct Instance { position: vec3f, index: u32 }
+//!
+//! @group(1) @binding(0) var<storage, read      > positions: array<vec4f>;
+//! @group(1) @binding(1) var<storage, read_write> instances: array<Instance>;
+//!
+//! fn write_payload(sorted_index: u32, key: u32, val: u32) {
+//!     instances[sorted_index] = Instance(positions[val].xyz, val);
+//! }
+//! ```
+//!
+//! The snippet binds its own buffers in group 1, group 0 is the sort's. It can't declare entry points or
+//! preprocessor directives, nor touch the keys and blocks buffers of the sort. An invalid snippet or one that
+//! doesn't compile fails [`PayloadScatterPipeline::load_state`].
+
+use std::{fmt, ops::Range};
+
+use bevy::{
+    prelude::*,
+    render::{
+        RenderApp,
+        render_resource::{
+            BindGroup, BindGroupLayout, BindGroupLayoutEntry, CachedComputePipelineId,
+            CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    LoadState, PUSH_CONSTANT_RANGES, RADIX_SORT_SHADER_SOURCE, RadixSortBindGroup,
+    RadixSortPipeline, SortPipelines, SubgroupSize, compute_pipelines_load_state, is_input_even,
+    radix_sort_shader_defs, record_count_step, record_scan_step, record_scatter_step,
+    record_sort_passes,
+};
+
+pub const PAYLOAD_SCATTER_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(262490450648164648653488453739210949533);
+
+/// The function the snippet of a [`PayloadScatterPlugin`] defines, called with the sorted position of each key.
+pub const WRITE_PAYLOAD_SIGNATURE: &str = "fn write_payload(sorted_index: u32, key: u32, val: u32)";
+
+/// The bindings of the sort the snippet can't access, the sorted keys must stay intact.
+const RESERVED_BINDINGS: [&str; 4] = [
+    "global_keys_i",
+    "global_vals_i",
+    "global_blocks",
+    "global_keys_o",
+];
+
+/// Why the snippet of a [`PayloadScatterPlugin`] was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadScatterError {
+    /// The snippet doesn't define `write_payload`.
+    MissingWritePayload,
+    /// The snippet defines `write_payload` more than once.
+    DuplicateWritePayload,
+    /// The snippet declares an entry point.
+    EntryPoint,
+    /// The snippet holds a preprocessor directive, e.g. an `#import`.
+    Directive(String),
+    /// The snippet declares a binding in group 0, the bind group of the sort.
+    ReservedGroup,
+    /// The snippet accesses a buffer of the sort.
+    ReservedBinding(&'static str),
+    /// The sort is keys-only, there are no vals to write (see [`crate::RadixSortSettings::without_values`]).
+    KeysOnly,
+}
+
+impl fmt::Display for PayloadScatterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingWritePayload => {
+                write!(f, "the hook must define `{WRITE_PAYLOAD_SIGNATURE}`")
+            }
+            Self::DuplicateWritePayload => write!(f, "the hook defines `write_payload` twice"),
+            Self::EntryPoint => write!(f, "the hook can't declare an entry point"),
+            Self::Directive(line) => {
+                write!(f, "the hook can't hold preprocessor directives: `{line}`")
+            }
+            Self::ReservedGroup => {
+                write!(f, "the hook can't bind group 0, the bind group of the sort")
+            }
+            Self::ReservedBinding(name) => {
+                write!(f, "the hook can't access `{name}`, a buffer of the sort")
+            }
+            Self::KeysOnly => write!(f, "the sort is keys-only, there are no vals to write"),
+        }
+    }
+}
+
+impl std::error::Error for PayloadScatterError {}
+
+/// Checks the snippet of a [`PayloadScatterPlugin`], ignoring the line comments.
+pub fn validate_payload_scatter(source: &str) -> Result<(), PayloadScatterError> {
+    let code: Vec<&str> = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect();
+
+    if let Some(line) = code.iter().find(|line| line.trim_start().starts_with('#')) {
+        return Err(PayloadScatterError::Directive(line.trim().to_owned()));
+    }
+
+    let code = code.join("\n");
+    let compact: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+    match compact.matches("fnwrite_payload(").count() {
+        0 => return Err(PayloadScatterError::MissingWritePayload),
+        1 => {}
+        _ => return Err(PayloadScatterError::DuplicateWritePayload),
+    }
+    if compact.contains("@compute") {
+        return Err(PayloadScatterError::EntryPoint);
+    }
+    if compact.contains("@group(0)") {
+        return Err(PayloadScatterError::ReservedGroup);
+    }
+    if let Some(name) = RESERVED_BINDINGS.into_iter().find(|name| {
+        code.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .any(|word| word == *name)
+    }) {
+        return Err(PayloadScatterError::ReservedBinding(name));
+    }
+
+    Ok(())
+}
+
+/// Requires the [`crate::RadixSortPlugin`] to be added before it. One hook per app, see the module docs.
+pub struct PayloadScatterPlugin {
+    /// The WGSL snippet defining [`WRITE_PAYLOAD_SIGNATURE`] and its bindings in group 1
+    pub source: String,
+    /// The layout of group 1, bound with [`run_with_payload_scatter`]
+    pub bind_group_layout_entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl Plugin for PayloadScatterPlugin {
+    fn build(&self, app: &mut App) {
+        if let Err(err) = validate_payload_scatter(&self.source) {
+            error!("radix_sort: invalid payload scatter hook: {}", err);
+            return;
+        }
+
+        let source = format!("{RADIX_SORT_SHADER_SOURCE}\n{}\n", self.source);
+        let path = std::path::Path::new(file!())
+            .parent()
+            .unwrap()
+            .join("payload_scatter.wgsl")
+            .to_string_lossy()
+            .into_owned();
+        app.world_mut().resource_mut::<Assets<Shader>>().insert(
+            PAYLOAD_SCATTER_SHADER_HANDLE.id(),
+            Shader::from_wgsl(source, path),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        // The adapter doesn't support the sort, see `RadixSortUnsupported`
+        if !render_app.world().contains_resource::<RadixSortPipeline>() {
+            return;
+        }
+
+        let world = render_app.world_mut();
+        let payload_scatter_pipeline =
+            PayloadScatterPipeline::new(world, &self.source, &self.bind_group_layout_entries);
+        world.insert_resource(payload_scatter_pipeline);
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct PayloadScatterPipeline {
+    /// The scatter pipeline calling the hook, if the hook is valid
+    scatter_pipeline: Option<CachedComputePipelineId>,
+    /// The layout of group 1, the bindings of the hook
+    bind_group_layout: BindGroupLayout,
+    /// Why the hook was rejected
+    error: Option<PayloadScatterError>,
+}
+
+impl PayloadScatterPipeline {
+    fn new(
+        world: &World,
+        source: &str,
+        bind_group_layout_entries: &[BindGroupLayoutEntry],
+    ) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let subgroup_size = world.resource::<SubgroupSize>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "payload_scatter bindgroup layout",
+            bind_group_layout_entries,
+        );
+
+        let error = validate_payload_scatter(source).err().or_else(|| {
+            (!radix_sort_pipeline.allocate_values()).then_some(PayloadScatterError::KeysOnly)
+        });
+
+        let scatter_pipeline = error.is_none().then(|| {
+            let mut cdefs = radix_sort_shader_defs(
+                subgroup_size,
+                radix_sort_pipeline.subgroup_fallback(),
+                radix_sort_pipeline.key_transform(),
+            );
+            cdefs.push("SCATTER_PIPELINE".into());
+            cdefs.push("PAYLOAD_SCATTER".into());
+
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("payload_scatter: scatter pipeline".into()),
+                layout: vec![
+                    radix_sort_pipeline.bind_group_layout().clone(),
+                    bind_group_layout.clone(),
+                ],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: PAYLOAD_SCATTER_SHADER_HANDLE,
+                shader_defs: cdefs,
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        });
+
+        Self {
+            scatter_pipeline,
+            bind_group_layout,
+            error,
+        }
+    }
+
+    /// The layout of the bind group of the hook, group 1.
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        match (&self.error, self.scatter_pipeline) {
+            (Some(err), _) => LoadState::Failed(format!("Invalid payload scatter hook: {err}")),
+            (None, Some(scatter_pipeline)) => compute_pipelines_load_state(
+                pipeline_cache,
+                &[("payload_scatter scatter_pipeline", scatter_pipeline)],
+            ),
+            (None, None) => unreachable!("a valid hook always queues its pipeline"),
+        }
+    }
+}
+
+/// [`crate::run`] with the last scatter calling the hook of the [`PayloadScatterPlugin`], `payload_bind_group`
+/// is bound in group 1.
+///
+/// The sorted keys end up in [`crate::sorted_keys_buffer`] as with [`crate::run`], but the last pass doesn't write
+/// the sorted vals buffer, the hook gets them instead. Like [`crate::run`], panics unless the pipelines are loaded
+/// and records nothing below 2 keys.
+#[allow(clippy::too_many_arguments)]
+pub fn run_with_payload_scatter(
+    encoder: &mut CommandEncoder,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    payload_scatter_pipeline: &PayloadScatterPipeline,
+    payload_bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    if let Some(err) = &payload_scatter_pipeline.error {
+        error!("radix_sort: invalid payload scatter hook: {}", err);
+        return;
+    }
+
+    if number_of_keys < 2 || pass_range.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span =
+        tracing::info_span!("radix_sort::run_with_payload_scatter", number_of_keys).entered();
+
+    radix_sort_pipeline.counters().record(number_of_keys);
+
+    let pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+    let scatter_pipeline = pipeline_cache
+        .get_compute_pipeline(payload_scatter_pipeline.scatter_pipeline.unwrap())
+        .unwrap();
+
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("radix_sort payload scatter compute pass"),
+        ..default()
+    });
+
+    let last_pass = pass_range.end - 1;
+    record_sort_passes(
+        &mut pass,
+        &pipelines,
+        radix_bind_group.eve_bind_group(),
+        radix_bind_group.odd_bind_group(),
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.start..last_pass,
+        init_index,
+        read_from_even,
+    );
+
+    let bind_group = radix_bind_group.bind_group(is_input_even(last_pass, read_from_even));
+    record_count_step(
+        &mut pass,
+        &pipelines,
+        bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        last_pass,
+    );
+    record_scan_step(
+        &mut pass,
+        &pipelines,
+        bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+    );
+
+    // Group 1 stays bound while the scatter step sets the same pipeline and group 0
+    pass.set_pipeline(scatter_pipeline);
+    pass.set_bind_group(1, payload_bind_group, &[]);
+    record_scatter_step(
+        &mut pass,
+        &SortPipelines {
+            scatter_pipeline,
+            ..pipelines
+        },
+        bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        last_pass,
+        init_index && last_pass == pass_range.start,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupEntries, BindGroupLayoutEntries, CommandEncoderDescriptor, ShaderStages,
+            binding_types::storage_buffer,
+        },
+        renderer::RenderQueue,
+        storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        RadixSortPlugin, global_keys_buffer, run, sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// Writes a 16-byte record of each sorted key/val pair.
+    const RECORD_HOOK: &str = r"
+@group(1) @binding(0) var<storage, read_write> records: array<vec4u>;
+
+fn write_payload(sorted_index: u32, key: u32, val: u32) {
+    records[sorted_index] = vec4u(val, key, val ^ key, sorted_index);
+}
+";
+
+    fn record_hook_plugin(source: &str) -> PayloadScatterPlugin {
+        PayloadScatterPlugin {
+            source: source.into(),
+            bind_group_layout_entries: BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                storage_buffer::<u32>(false),
+            )
+            .to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_validate_payload_scatter() {
+        assert_eq!(validate_payload_scatter(RECORD_HOOK), Ok(()));
+        // Comments don't count
+        assert_eq!(
+            validate_payload_scatter(&format!(
+                "// global_keys_o, #import, @compute\n{RECORD_HOOK}"
+            )),
+            Ok(())
+        );
+
+        assert_eq!(
+            validate_payload_scatter("fn write(sorted_index: u32, key: u32, val: u32) {}"),
+            Err(PayloadScatterError::MissingWritePayload)
+        );
+        assert_eq!(
+            validate_payload_scatter(&format!("{RECORD_HOOK}{RECORD_HOOK}")),
+            Err(PayloadScatterError::DuplicateWritePayload)
+        );
+        assert_eq!(
+            validate_payload_scatter(&format!(
+                "{RECORD_HOOK}@compute @workgroup_size(1) fn f() {{}}"
+            )),
+            Err(PayloadScatterError::EntryPoint)
+        );
+        assert_eq!(
+            validate_payload_scatter(&format!("#import bevy_radix_sort::keys\n{RECORD_HOOK}")),
+            Err(PayloadScatterError::Directive(
+                "#import bevy_radix_sort::keys".into()
+            ))
+        );
+        assert_eq!(
+            validate_payload_scatter(&RECORD_HOOK.replace("@group(1)", "@group( 0 )")),
+            Err(PayloadScatterError::ReservedGroup)
+        );
+        assert_eq!(
+            validate_payload_scatter(&RECORD_HOOK.replace("val ^ key", "global_keys_o[0]")),
+            Err(PayloadScatterError::ReservedBinding("global_keys_o"))
+        );
+    }
+
+    #[test]
+    fn test_matches_sort_then_transform() {
+        let number_of_keys = 100_003;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: number_of_keys.into(),
+        })
+        .add_plugins(record_hook_plugin(RECORD_HOOK));
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761) % 50_000)
+            .collect();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  payload_scatter_pipeline: Res<PayloadScatterPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                assert_eq!(
+                    payload_scatter_pipeline.load_state(&pipeline_cache),
+                    LoadState::Loaded
+                );
+
+                let max_compute_workgroups_per_dimension =
+                    render_device.limits().max_compute_workgroups_per_dimension;
+                let records_buf =
+                    create_storage_buffer(&render_device, &vec![0; 4 * number_of_keys as usize]);
+                let payload_bind_group = render_device.create_bind_group(
+                    "unit_test: payload scatter bind group",
+                    payload_scatter_pipeline.bind_group_layout(),
+                    &BindGroupEntries::single(records_buf.as_entire_binding()),
+                );
+
+                for pass_range in [0..4, 1..4, 3..4] {
+                    let sort = |with_hook: bool| {
+                        render_queue.write_buffer(
+                            global_keys_buffer(&sbufs, true).unwrap(),
+                            0,
+                            bytemuck::cast_slice(&keys),
+                        );
+
+                        let mut encoder =
+                            render_device.create_command_encoder(&CommandEncoderDescriptor {
+                                label: Some("unit_test: payload scatter command encoder"),
+                            });
+                        if with_hook {
+                            run_with_payload_scatter(
+                                &mut encoder,
+                                &pipeline_cache,
+                                &radix_sort_pipeline,
+                                &radix_bind_group,
+                                &payload_scatter_pipeline,
+                                &payload_bind_group,
+                                max_compute_workgroups_per_dimension,
+                                number_of_keys,
+                                pass_range.clone(),
+                                true,
+                                true,
+                            );
+                        } else {
+                            run(
+                                &mut encoder,
+                                &pipeline_cache,
+                                &radix_sort_pipeline,
+                                &radix_bind_group,
+                                max_compute_workgroups_per_dimension,
+                                number_of_keys,
+                                pass_range.clone(),
+                                true,
+                                true,
+                            );
+                        }
+                        render_queue.submit([encoder.finish()]);
+
+                        let read = |buffer| {
+                            read_buffer(
+                                &render_device,
+                                &render_queue,
+                                buffer,
+                                number_of_keys as usize,
+                            )
+                        };
+                        (
+                            read(sorted_keys_buffer(&sbufs, &pass_range, true).unwrap()),
+                            read(sorted_vals_buffer(&sbufs, &pass_range, true).unwrap()),
+                        )
+                    };
+
+                    let (hooked_keys, _) = sort(true);
+                    let records = read_buffer(
+                        &render_device,
+                        &render_queue,
+                        &records_buf,
+                        4 * number_of_keys as usize,
+                    );
+
+                    let (sorted_keys, sorted_vals) = sort(false);
+                    let expected: Vec<u32> = sorted_keys
+                        .iter()
+                        .zip(&sorted_vals)
+                        .enumerate()
+                        .flat_map(|(index, (&key, &val))| [val, key, val ^ key, index as u32])
+                        .collect();
+
+                    assert_eq!(hooked_keys, sorted_keys, "{pass_range:?}");
+                    assert_eq!(records, expected, "{pass_range:?}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_invalid_hook_fails_to_load() {
+        for source in [
+            // Rejected by the validation
+            RECORD_HOOK.replace("write_payload", "write"),
+            // Doesn't compile, the scatter passes 3 arguments
+            RECORD_HOOK.replace(", key: u32, val: u32", ""),
+        ] {
+            let mut app = create_render_test_app();
+            app.add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            })
+            .add_plugins(record_hook_plugin(&source));
+
+            run_render_system_once(
+                &mut app,
+                |pipeline_cache: Res<PipelineCache>,
+                 payload_scatter_pipeline: Res<PayloadScatterPipeline>| {
+                    assert!(matches!(
+                        payload_scatter_pipeline.load_state(&pipeline_cache),
+                        LoadState::Failed(_)
+                    ));
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Persists a `wgpu::PipelineCache` across runs, so the driver skips most of the compilation of the pipelines of the
+//! sort on the next startups.
+//!
+//! wgpu 23 only supports pipeline caches on Vulkan, behind `Features::PIPELINE_CACHE`. Bevy 0.15 creates the
+//! pipelines of its `PipelineCache` without a wgpu pipeline cache, so this only speeds up the pipelines of a
+//! [`crate::RadixSortCore::with_pipeline_cache`], the ones of the [`crate::RadixSortPlugin`] still compile from scratch:
+//!
+//! ```ignore
+//! let persistent = PersistentPipelineCache::in_platform_cache_dir().unwrap();
+//! let key = PipelineCacheKey::from(&adapter.get_info());
+//! let cache = persistent.create_pipeline_cache(&device, &key);
+//! let core = RadixSortCore::with_pipeline_cache(&device, subgroup_size, false, cache.as_ref());
+//! if let Some(cache) = &cache {
+//!     persistent.store(&key, cache)?;
+//! }
+//! ```
+//!
+//! Every adapter gets its own file, the blob of the driver behind a header checked before the driver sees the data:
+//!
+//! ```text
+//!  magic "BRSPC01\n" | key length: u32 | key | data length: u64 | FNV-1a 64 of the data: u64 | data
+//! ```
+//!
+//! The key holds the [`AdapterCacheKey`] and the version of the crate, the one of its shaders. A file of another key,
+//! truncated or failing its checksum is ignored, and the cache starts empty.
+//!
+//! The tile picked by the [`crate::AutoTunePlugin`] is stored next to the blob, under the same key, in a line of text:
+//!
+//! ```text
+//!  BRSTU01 \t <key> \t <workgroup_size> \t <keys_per_thread>
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::render::render_resource::WgpuAdapterInfo;
+use wgpu::{Device, Features, PipelineCache, PipelineCacheDescriptor};
+
+use crate::{
+    AdapterCacheKey, TileConfig,
+    adapter_cache::{platform_cache_dir, sanitize_field},
+};
+
+const MAGIC: &[u8; 8] = b"BRSPC01\n";
+const TUNING_MAGIC: &str = "BRSTU01";
+
+/// Identifies the adapter and the shaders a cached blob belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineCacheKey {
+    /// The version of `bevy_radix_sort`, the shaders change between versions.
+    pub crate_version: String,
+    pub adapter: AdapterCacheKey,
+}
+
+impl From<&WgpuAdapterInfo> for PipelineCacheKey {
+    fn from(info: &WgpuAdapterInfo) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            adapter: AdapterCacheKey::from(info),
+        }
+    }
+}
+
+impl PipelineCacheKey {
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}",
+            sanitize_field(&self.crate_version),
+            self.adapter.to_fields()
+        )
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistentPipelineCache {
+    dir: PathBuf,
+}
+
+impl PersistentPipelineCache {
+    /// Creates a cache stored in `dir`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Creates a cache stored in the platform cache directory (e.g. `~/.cache/bevy_radix_sort` on Linux).
+    ///
+    /// Returns `None` if the platform has no cache directory.
+    pub fn in_platform_cache_dir() -> Option<Self> {
+        platform_cache_dir().map(Self::new)
+    }
+
+    /// The file of the adapter.
+    pub fn path(&self, key: &PipelineCacheKey) -> PathBuf {
+        self.dir.join(format!(
+            "pipeline_cache_{:016x}.bin",
+            fnv1a_64(key.to_line().as_bytes())
+        ))
+    }
+
+    /// The file of the tuned tile of the adapter, next to [`Self::path`].
+    pub fn tuning_path(&self, key: &PipelineCacheKey) -> PathBuf {
+        self.dir.join(format!(
+            "tuning_{:016x}.txt",
+            fnv1a_64(key.to_line().as_bytes())
+        ))
+    }
+
+    /// Returns the stored blob of the adapter, `None` on a miss, a blob of another key or a corrupted file.
+    pub fn load_data(&self, key: &PipelineCacheKey) -> Option<Vec<u8>> {
+        let contents = fs::read(self.path(key)).ok()?;
+        let line = key.to_line();
+
+        let rest = contents.strip_prefix(MAGIC.as_slice())?;
+        let (key_len, rest) = rest.split_first_chunk::<4>()?;
+        let (file_key, rest) = rest.split_at_checked(u32::from_le_bytes(*key_len) as usize)?;
+        if file_key != line.as_bytes() {
+            return None;
+        }
+
+        let (data_len, rest) = rest.split_first_chunk::<8>()?;
+        let (checksum, data) = rest.split_first_chunk::<8>()?;
+        (data.len() as u64 == u64::from_le_bytes(*data_len)
+            && fnv1a_64(data) == u64::from_le_bytes(*checksum))
+        .then(|| data.to_vec())
+    }
+
+    /// Creates a pipeline cache for the device from the stored blob of the adapter, empty if [`Self::load_data`]
+    /// finds none. `None` without `Features::PIPELINE_CACHE`.
+    pub fn create_pipeline_cache(
+        &self,
+        device: &Device,
+        key: &PipelineCacheKey,
+    ) -> Option<PipelineCache> {
+        if !device.features().contains(Features::PIPELINE_CACHE) {
+            return None;
+        }
+
+        let data = self.load_data(key);
+        // SAFETY: the data was stored by `Self::store` from a cache of the same adapter and driver, the key and the
+        // checksum rule out other adapters and corrupted files. The driver still validates it, and `fallback`
+        // creates an empty cache instead of failing if it rejects it.
+        let cache = unsafe {
+            device.create_pipeline_cache(&PipelineCacheDescriptor {
+                label: Some("radix_sort: pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        Some(cache)
+    }
+
+    /// Stores the blob of `cache` for the adapter, replacing the previous one. Does nothing if the driver has no
+    /// data for the cache.
+    pub fn store(&self, key: &PipelineCacheKey, cache: &PipelineCache) -> io::Result<()> {
+        match cache.get_data() {
+            Some(data) => self.store_data(key, &data),
+            None => Ok(()),
+        }
+    }
+
+    fn store_data(&self, key: &PipelineCacheKey, data: &[u8]) -> io::Result<()> {
+        let line = key.to_line();
+
+        let mut contents = Vec::with_capacity(MAGIC.len() + 4 + line.len() + 16 + data.len());
+        contents.extend_from_slice(MAGIC);
+        contents.extend_from_slice(&(line.len() as u32).to_le_bytes());
+        contents.extend_from_slice(line.as_bytes());
+        contents.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        contents.extend_from_slice(&fnv1a_64(data).to_le_bytes());
+        contents.extend_from_slice(data);
+
+        self.write_renamed(&self.path(key), &contents)
+    }
+
+    /// Returns the tile stored by [`Self::store_tuning`] for the adapter, `None` on a miss, a tile of another key or a
+    /// corrupted file. The tile may not be supported by the device anymore, see [`TileConfig::is_supported`].
+    pub fn load_tuning(&self, key: &PipelineCacheKey) -> Option<TileConfig> {
+        let contents = fs::read_to_string(self.tuning_path(key)).ok()?;
+
+        let line = contents.strip_suffix('\n')?;
+        let mut fields = line.rsplitn(3, '\t');
+        let keys_per_thread = fields.next()?.parse().ok()?;
+        let workgroup_size = fields.next()?.parse().ok()?;
+        (fields.next()? == format!("{TUNING_MAGIC}\t{}", key.to_line())).then_some(TileConfig {
+            workgroup_size,
+            keys_per_thread,
+        })
+    }
+
+    /// Stores the tuned tile of the adapter, replacing the previous one.
+    pub fn store_tuning(&self, key: &PipelineCacheKey, tile: TileConfig) -> io::Result<()> {
+        let contents = format!(
+            "{TUNING_MAGIC}\t{}\t{}\t{}\n",
+            key.to_line(),
+            tile.workgroup_size,
+            tile.keys_per_thread
+        );
+        self.write_renamed(&self.tuning_path(key), contents.as_bytes())
+    }
+
+    fn write_renamed(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        // Renamed into place, another app starting at the same time never reads a partial file
+        fs::create_dir_all(&self.dir)?;
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::temp_dir;
+
+    use super::*;
+
+    fn temp_cache(name: &str) -> PersistentPipelineCache {
+        PersistentPipelineCache::new(temp_dir(&format!("pipeline_cache_{name}")))
+    }
+
+    fn key(device: u32) -> PipelineCacheKey {
+        PipelineCacheKey {
+            crate_version: "0.15.0".into(),
+            adapter: AdapterCacheKey {
+                backend: "Vulkan".into(),
+                vendor: 0x10de,
+                device,
+                driver: "NVIDIA".into(),
+                driver_info: "566.36".into(),
+            },
+        }
+    }
+
+    fn blob() -> Vec<u8> {
+        (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_pipeline_cache_blob() {
+        let cache = temp_cache("blob");
+        assert!(cache.load_data(&key(0x2705)).is_none());
+        cache.store_data(&key(0x2705), &blob()).unwrap();
+        assert_eq!(cache.load_data(&key(0x2705)), Some(blob()));
+
+        // Storing again replaces the blob
+        cache.store_data(&key(0x2705), &[1, 2, 3]).unwrap();
+        assert_eq!(cache.load_data(&key(0x2705)), Some(vec![1, 2, 3]));
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 1);
+
+        // The shaders of another version
+        let other_version = PipelineCacheKey {
+            crate_version: "0.16.0".into(),
+            ..key(0x2705)
+        };
+        assert!(cache.load_data(&other_version).is_none());
+
+        // A file of another key under the name of this one, e.g. a hash collision, is checked against the key of
+        // its header
+        fs::copy(cache.path(&key(0x2705)), cache.path(&key(0x2704))).unwrap();
+        assert!(cache.load_data(&key(0x2704)).is_none());
+    }
+    #[test]
+    fn test_pipeline_cache_corrupted() {
+        let cache = temp_cache("corrupted");
+        cache.store_data(&key(0x2705), &blob()).unwrap();
+        let path = cache.path(&key(0x2705));
+        let contents = fs::read(&path).unwrap();
+
+        // Truncated
+        fs::write(&path, &contents[..contents.len() - 1]).unwrap();
+        assert!(cache.load_data(&key(0x2705)).is_none());
+        fs::write(&path, &contents[..MAGIC.len() + 2]).unwrap();
+        assert!(cache.load_data(&key(0x2705)).is_none());
+
+        // A flipped bit of the data
+        let mut flipped = contents.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, flipped).unwrap();
+        assert!(cache.load_data(&key(0x2705)).is_none());
+
+        // Another format
+        fs::write(&path, b"garbage").unwrap();
+        assert!(cache.load_data(&key(0x2705)).is_none());
+
+        // A corrupted file doesn't prevent storing a fresh blob
+        cache.store_data(&key(0x2705), &blob()).unwrap();
+        assert_eq!(cache.load_data(&key(0x2705)), Some(blob()));
+    }
+
+    #[test]
+    fn test_tuning_persisted() {
+        let cache = temp_cache("tuning");
+        assert!(cache.load_tuning(&key(0x2705)).is_none());
+
+        let tile = TileConfig::new(15);
+        cache.store_tuning(&key(0x2705), tile).unwrap();
+        assert_eq!(cache.load_tuning(&key(0x2705)), Some(tile));
+        // Next to the pipeline cache, which it doesn't replace
+        cache.store_data(&key(0x2705), &blob()).unwrap();
+        assert_eq!(cache.load_data(&key(0x2705)), Some(blob()));
+        assert_eq!(cache.load_tuning(&key(0x2705)), Some(tile));
+
+        // Storing again replaces the tile
+        cache
+            .store_tuning(&key(0x2705), TileConfig::DEFAULT)
+            .unwrap();
+        assert_eq!(cache.load_tuning(&key(0x2705)), Some(TileConfig::DEFAULT));
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 2);
+
+        // Another adapter, or a tile of another key under its name
+        assert!(cache.load_tuning(&key(0x2704)).is_none());
+        fs::copy(
+            cache.tuning_path(&key(0x2705)),
+            cache.tuning_path(&key(0x2704)),
+        )
+        .unwrap();
+        assert!(cache.load_tuning(&key(0x2704)).is_none());
+
+        // Truncated or garbage
+        let path = cache.tuning_path(&key(0x2705));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() - 1]).unwrap();
+        assert!(cache.load_tuning(&key(0x2705)).is_none());
+        fs::write(&path, contents.replace("\t7\n", "\tseven\n")).unwrap();
+        assert!(cache.load_tuning(&key(0x2705)).is_none());
+        fs::write(&path, "garbage").unwrap();
+        assert!(cache.load_tuning(&key(0x2705)).is_none());
+    }
+}
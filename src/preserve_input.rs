@@ -0,0 +1,424 @@
+//! Sorting without writing the input side of the global buffers, for [`RadixSortRunOptions::preserve_input`].
+//!
+//! The ping-pong passes of [`crate::run`] overwrite the input from the second pass on. [`run_preserving_input`]
+//! ping-pongs between the other side and the scratch buffers of the [`PreserveInputPlugin`] instead, the first pass
+//! writing whichever of them the passes left end on the other side:
+//!
+//! ```text
+//!  odd pass count:   input ─▶ other ─▶ scratch ─▶ other
+//!  even pass count:  input ─▶ scratch ─▶ other ─▶ scratch ─▶ other
+//! ```
+//!
+//! The input keys and vals stay bit-identical, the results are always on the other side, see
+//! [`preserved_output_even`]. Neither copies the input: the first pass reads it like any other pass.
+//!
+//! The scratch buffers cost `4 * max_number_of_keys` bytes of keys and `4 * max_number_of_values` bytes of vals,
+//! the vals of keys-only sorts are 4-byte stand-ins. They're allocated once the plugin is added, and again with
+//! the capacities when the [`RadixSortSettings`] change.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_asset::RenderAssets,
+        render_resource::{
+            Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder, PipelineCache,
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    NUMBER_OF_BYTES_PER_KEY, RadixSortBindGroup, RadixSortPipeline, RadixSortRunOptions,
+    RadixSortSettings, create_dummy_vals_buffers, global_blocks_buffer, global_keys_buffer,
+    global_vals_buffer, is_input_even, read_from_even_starting_on, run,
+};
+
+/// Whether [`run_preserving_input`] over `pass_range` leaves the sorted keys/vals in the `EVE_*` (true) or `ODD_*`
+/// (false) global buffers: the side the first pass writes, the input side for an empty range.
+pub fn preserved_output_even(pass_range: &Range<u32>, read_from_even: bool) -> bool {
+    let input_even = is_input_even(pass_range.start, read_from_even);
+    if pass_range.is_empty() {
+        input_even
+    } else {
+        !input_even
+    }
+}
+
+/// Allocates the [`PreserveInputScratch`] in the render world. Requires the [`crate::RadixSortPlugin`].
+pub struct PreserveInputPlugin;
+
+impl Plugin for PreserveInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            PreserveInputScratch::prepare.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+/// The scratch buffers [`run_preserving_input`] ping-pongs with, sized to the capacities of the
+/// [`RadixSortSettings`].
+#[derive(Resource, Debug, Clone)]
+pub struct PreserveInputScratch {
+    keys: Buffer,
+    vals: Buffer,
+    /// Bound in place of the global vals buffers of keys-only sorts, `(eve, odd)`
+    dummy_vals: Option<(Buffer, Buffer)>,
+}
+
+impl PreserveInputScratch {
+    pub fn new(render_device: &RenderDevice, radix_sort_settings: &RadixSortSettings) -> Self {
+        let create_buffer = |label, capacity: u32| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: capacity.max(1) as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+
+        let allocate_values = radix_sort_settings.allocate_values();
+        let vals_capacity = if allocate_values {
+            radix_sort_settings.max_number_of_values()
+        } else {
+            1
+        };
+
+        Self {
+            keys: create_buffer(
+                "preserve_input: scratch keys buffer",
+                radix_sort_settings.max_number_of_keys(),
+            ),
+            vals: create_buffer("preserve_input: scratch vals buffer", vals_capacity),
+            dummy_vals: (!allocate_values).then(|| create_dummy_vals_buffers(render_device)),
+        }
+    }
+
+    fn prepare(
+        mut commands: Commands,
+        render_device: Res<RenderDevice>,
+        radix_sort_settings: Res<RadixSortSettings>,
+        scratch: Option<Res<Self>>,
+    ) {
+        if scratch.is_some() && !radix_sort_settings.is_changed() {
+            return;
+        }
+
+        commands.insert_resource(Self::new(&render_device, &radix_sort_settings));
+    }
+
+    pub fn keys(&self) -> &Buffer {
+        &self.keys
+    }
+
+    pub fn vals(&self) -> &Buffer {
+        &self.vals
+    }
+
+    /// The global vals buffer of the `even` side, or its stand-in for keys-only sorts.
+    fn global_vals<'a>(
+        &'a self,
+        sbufs: &'a RenderAssets<GpuShaderStorageBuffer>,
+        even: bool,
+    ) -> Option<&'a Buffer> {
+        match &self.dummy_vals {
+            Some((eve, odd)) => Some(if even { eve } else { odd }),
+            None => global_vals_buffer(sbufs, even),
+        }
+    }
+}
+
+/// [`crate::run_with_options`] leaving the input side of the global buffers untouched, see the module docs.
+///
+/// The input is read from the side pass `options.pass_range.start` of [`crate::run`] reads with `read_from_even`,
+/// and the results are written to the other side, returned as in [`preserved_output_even`].
+#[allow(clippy::too_many_arguments)]
+pub fn run_preserving_input(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    scratch: &PreserveInputScratch,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    options: &RadixSortRunOptions,
+    read_from_even: bool,
+) -> bool {
+    let pass_range = options.pass_range.clone();
+    let output_even = preserved_output_even(&pass_range, read_from_even);
+    if pass_range.is_empty() {
+        return output_even;
+    }
+
+    let (Some(input_keys), Some(input_vals), Some(output_keys), Some(output_vals), Some(blocks)) = (
+        global_keys_buffer(sbufs, !output_even),
+        scratch.global_vals(sbufs, !output_even),
+        global_keys_buffer(sbufs, output_even),
+        scratch.global_vals(sbufs, output_even),
+        global_blocks_buffer(sbufs),
+    ) else {
+        error!("radix_sort: run_preserving_input before the global buffers are prepared");
+        return output_even;
+    };
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!(
+        "radix_sort::run_preserving_input",
+        number_of_keys,
+        passes = pass_range.len()
+    )
+    .entered();
+
+    let first_pass = pass_range.start..pass_range.start + 1;
+    let other_passes = pass_range.start + 1..pass_range.end;
+    // An odd number of passes left starts from the scratch buffers to end on the other side
+    let first_into_scratch = other_passes.len() % 2 == 1;
+
+    let bind_group_layout = radix_sort_pipeline.bind_group_layout();
+    if first_into_scratch {
+        let into_scratch = RadixSortBindGroup::from_buffers(
+            render_device,
+            bind_group_layout,
+            input_keys,
+            input_vals,
+            blocks,
+            &scratch.keys,
+            &scratch.vals,
+        );
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            &into_scratch,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            first_pass.clone(),
+            options.init_index,
+            read_from_even_starting_on(&first_pass, true),
+        );
+    } else {
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            first_pass,
+            options.init_index,
+            read_from_even,
+        );
+    }
+
+    if !other_passes.is_empty() {
+        // The even side of the pair is the output side of the global buffers
+        let with_scratch = RadixSortBindGroup::from_buffers(
+            render_device,
+            bind_group_layout,
+            output_keys,
+            output_vals,
+            blocks,
+            &scratch.keys,
+            &scratch.vals,
+        );
+        run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            &with_scratch,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            other_passes.clone(),
+            false,
+            read_from_even_starting_on(&other_passes, !first_into_scratch),
+        );
+    }
+
+    if options.stats {
+        radix_sort_pipeline.stats().record(
+            encoder,
+            pipeline_cache,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            options.key_mask,
+            output_even,
+        );
+    }
+
+    output_even
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortJobs, RadixSortPlugin, SortJob,
+        test_utils::{create_render_test_app, read_buffer, run_once, run_render_system_once},
+    };
+
+    use super::*;
+
+    const NUMBER_OF_KEYS: u32 = 3000;
+
+    #[test]
+    fn test_preserve_input() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: NUMBER_OF_KEYS.into(),
+            })
+            .add_plugins(PreserveInputPlugin);
+
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>,
+             render_queue: Res<RenderQueue>,
+             pipeline_cache: Res<PipelineCache>,
+             radix_sort_pipeline: Res<RadixSortPipeline>,
+             radix_bind_group: Res<RadixSortBindGroup>,
+             scratch: Res<PreserveInputScratch>,
+             sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let n = NUMBER_OF_KEYS as usize;
+                let keys: Vec<u32> = (0..NUMBER_OF_KEYS)
+                    .map(|i| i.wrapping_mul(2654435761))
+                    .collect();
+                let vals: Vec<u32> = (0..NUMBER_OF_KEYS).map(|i| i ^ 0x5555).collect();
+
+                // Odd and even pass counts, a range starting at an odd pass, both sides and both val modes
+                for (pass_range, read_from_even, init_index) in [
+                    (0..4, true, true),
+                    (0..3, true, false),
+                    (0..1, false, true),
+                    (1..4, false, false),
+                    (2..4, true, true),
+                ] {
+                    let input_even = is_input_even(pass_range.start, read_from_even);
+                    let input_keys_buf = global_keys_buffer(&sbufs, input_even).unwrap();
+                    let input_vals_buf = global_vals_buffer(&sbufs, input_even).unwrap();
+                    render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+                    render_queue.write_buffer(input_vals_buf, 0, bytemuck::cast_slice(&vals));
+
+                    let options = RadixSortRunOptions {
+                        pass_range: pass_range.clone(),
+                        init_index,
+                        ..default()
+                    };
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: preserve_input command encoder"),
+                        });
+                    let output_even = run_preserving_input(
+                        &mut encoder,
+                        &render_device,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &scratch,
+                        &sbufs,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        NUMBER_OF_KEYS,
+                        &options,
+                        read_from_even,
+                    );
+                    render_queue.submit([encoder.finish()]);
+                    assert_eq!(output_even, !input_even, "{pass_range:?}");
+                    assert_eq!(
+                        output_even,
+                        preserved_output_even(&pass_range, read_from_even)
+                    );
+
+                    let label = format!("{pass_range:?}, read_from_even: {read_from_even}");
+                    assert_eq!(
+                        read_buffer(&render_device, &render_queue, input_keys_buf, n),
+                        keys,
+                        "{label}"
+                    );
+                    assert_eq!(
+                        read_buffer(&render_device, &render_queue, input_vals_buf, n),
+                        vals,
+                        "{label}"
+                    );
+
+                    // A stable sort by the digits of the pass range
+                    let digits = |key: u32| {
+                        let low = 8 * pass_range.start;
+                        (key >> low) & (u32::MAX >> (32 - 8 * pass_range.len() as u32))
+                    };
+                    let mut expected: Vec<(u32, u32)> = if init_index {
+                        keys.iter().copied().zip(0..).collect()
+                    } else {
+                        keys.iter().copied().zip(vals.iter().copied()).collect()
+                    };
+                    expected.sort_by_key(|&(key, _)| digits(key));
+                    let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) =
+                        expected.into_iter().unzip();
+
+                    let output_keys_buf = global_keys_buffer(&sbufs, output_even).unwrap();
+                    let output_vals_buf = global_vals_buffer(&sbufs, output_even).unwrap();
+                    assert_eq!(
+                        read_buffer(&render_device, &render_queue, output_keys_buf, n),
+                        expected_keys,
+                        "{label}"
+                    );
+                    assert_eq!(
+                        read_buffer(&render_device, &render_queue, output_vals_buf, n),
+                        expected_vals,
+                        "{label}"
+                    );
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_preserve_input_job() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: NUMBER_OF_KEYS.into(),
+            })
+            .add_plugins(PreserveInputPlugin);
+        run_once(&mut app);
+
+        let keys: Vec<u32> = (0..NUMBER_OF_KEYS)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+
+        let world = app.sub_app_mut(RenderApp).world_mut();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        world.resource::<RenderQueue>().write_buffer(
+            global_keys_buffer(sbufs, true).unwrap(),
+            0,
+            bytemuck::cast_slice(&keys),
+        );
+        let job = SortJob::new(NUMBER_OF_KEYS)
+            .with_options(RadixSortRunOptions::default().with_preserve_input());
+        // Four passes would end on the even side, the input
+        assert!(!job.output_even());
+        world.resource_mut::<RadixSortJobs>().push(job);
+        app.update();
+
+        let world = app.sub_app(RenderApp).world();
+        let render_device = world.resource::<RenderDevice>();
+        let render_queue = world.resource::<RenderQueue>();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let read = |buffer: &Buffer| read_buffer(render_device, render_queue, buffer, keys.len());
+
+        assert_eq!(read(global_keys_buffer(sbufs, true).unwrap()), keys);
+        let sorted: Vec<(u32, u32)> = read(global_keys_buffer(sbufs, false).unwrap())
+            .into_iter()
+            .zip(read(global_vals_buffer(sbufs, false).unwrap()))
+            .collect();
+        assert_eq!(sorted, expected);
+    }
+}
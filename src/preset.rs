@@ -0,0 +1,307 @@
+//! Pre-validated [`crate::run`] configurations for common key layouts.
+//!
+//! A preset expands into [`RadixSortRunOptions`]: the pass range covering the significant bits of the keys,
+//! the mask of those bits, and whether the vals are initialized with the original indices (argsort):
+//!
+//! ```ignore
+//! let options = RadixSortPreset::Depth16.options();
+//! run_with_options(&mut encoder, /* .. */, count, &options, read_from_even);
+//! ```
+
+use std::ops::Range;
+
+use bevy::{
+    log::error,
+    render::{render_resource::CommandEncoder, render_resource::PipelineCache},
+};
+
+use crate::{
+    KeyType, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_RADIX_BITS, RadixSortBindGroup, RadixSortPipeline,
+    is_output_even, passes_needed, run,
+};
+
+/// The arguments of [`crate::run`] describing the keys, see [`run_with_options`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RadixSortRunOptions {
+    /// The passes to run, pass `i` sorts the bits `8 * i..8 * (i + 1)` of the keys.
+    pub pass_range: Range<u32>,
+    /// The bits the keys may have set, the passes cover all of them. `u32::MAX` for wider keys, e.g.
+    /// [`KeyType::U64`] keys.
+    pub key_mask: u32,
+    /// Initialize the vals with the original indices during the first pass.
+    pub init_index: bool,
+    /// Compute the [`crate::RadixSortStats`] of the sorted keys, read back a few frames later.
+    pub stats: bool,
+    /// Leave the input side of the buffers untouched, the results ending on the other side, see
+    /// [`crate::preserve_input`]. Honored by the jobs on the global buffers and [`crate::run_preserving_input`].
+    pub preserve_input: bool,
+}
+
+impl Default for RadixSortRunOptions {
+    fn default() -> Self {
+        Self::for_key_bits(NUMBER_OF_BYTES_PER_KEY * 8)
+    }
+}
+
+impl RadixSortRunOptions {
+    /// An argsort of keys whose set bits all lie within the lowest `key_bits` bits.
+    pub const fn for_key_bits(key_bits: u32) -> Self {
+        assert!(key_bits <= KeyType::U64.bits());
+
+        Self {
+            pass_range: 0..passes_needed(key_bits),
+            key_mask: if key_bits >= 32 {
+                u32::MAX
+            } else {
+                (1 << key_bits) - 1
+            },
+            init_index: true,
+            stats: false,
+            preserve_input: false,
+        }
+    }
+
+    pub const fn with_stats(mut self) -> Self {
+        self.stats = true;
+        self
+    }
+
+    pub const fn with_preserve_input(mut self) -> Self {
+        self.preserve_input = true;
+        self
+    }
+
+    /// Returns `true` if the passes cover every bit of `key_mask`, so keys within the mask are fully sorted.
+    pub fn is_valid(&self) -> bool {
+        let covered_bits = self.pass_range.end * NUMBER_OF_RADIX_BITS;
+        let covered_mask = if covered_bits >= 32 {
+            u32::MAX
+        } else {
+            (1 << covered_bits) - 1
+        };
+
+        self.pass_range.start == 0
+            && self.pass_range.end <= passes_needed(KeyType::U64.bits())
+            && self.key_mask & !covered_mask == 0
+    }
+}
+
+/// A common key layout and the [`RadixSortRunOptions`] sorting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RadixSortPreset {
+    /// 3D Morton codes with 10 bits per axis, as written by [`crate::MortonKeygenPipeline`].
+    ///
+    /// The 30 bits span the 4 bytes of the key, so all 4 passes run, the preset only saves the boilerplate.
+    Morton30,
+    /// 16-bit quantized depths, 2 passes.
+    Depth16,
+    /// 20-bit spatial hash cell ids, 3 passes.
+    CellHash20,
+    /// 8-bit buckets, e.g. LOD tiers, in the single pass of [`crate::KeyType::U8`].
+    Bucket8,
+}
+
+impl RadixSortPreset {
+    const ALL: [Self; 4] = [
+        Self::Morton30,
+        Self::Depth16,
+        Self::CellHash20,
+        Self::Bucket8,
+    ];
+
+    /// The available presets.
+    pub fn all() -> &'static [Self] {
+        &Self::ALL
+    }
+
+    /// The number of significant bits of the keys.
+    pub const fn key_bits(self) -> u32 {
+        match self {
+            Self::Morton30 => 30,
+            Self::Depth16 => 16,
+            Self::CellHash20 => 20,
+            Self::Bucket8 => 8,
+        }
+    }
+
+    pub const fn options(self) -> RadixSortRunOptions {
+        RadixSortRunOptions::for_key_bits(self.key_bits())
+    }
+}
+
+impl From<RadixSortPreset> for RadixSortRunOptions {
+    fn from(preset: RadixSortPreset) -> Self {
+        preset.options()
+    }
+}
+
+/// [`crate::run`] with the pass range and index initialization of `options`, followed by the statistics pass of
+/// [`RadixSortRunOptions::stats`].
+///
+/// Refuses [`RadixSortRunOptions::preserve_input`] with an error, which needs the scratch buffers of
+/// [`crate::run_preserving_input`].
+#[allow(clippy::too_many_arguments)]
+#[track_caller]
+pub fn run_with_options(
+    encoder: &mut CommandEncoder,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    options: &RadixSortRunOptions,
+    read_from_even: bool,
+) {
+    debug_assert!(options.is_valid(), "invalid run options: {options:?}");
+    if options.preserve_input {
+        error!("radix_sort: run_with_options can't preserve the input, use run_preserving_input");
+        return;
+    }
+
+    run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        options.pass_range.clone(),
+        options.init_index,
+        read_from_even,
+    );
+
+    if options.stats && radix_sort_pipeline.key_type() == KeyType::U64 {
+        error!(
+            "radix_sort: the statistics of the sorted keys read u32 keys, not KeyType::U64 keys"
+        );
+    } else if options.stats {
+        radix_sort_pipeline.stats().record(
+            encoder,
+            pipeline_cache,
+            radix_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            options.key_mask,
+            is_output_even(&options.pass_range, read_from_even),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        prelude::*,
+        render::{
+            render_asset::RenderAssets, render_resource::CommandEncoderDescriptor,
+            renderer::RenderDevice, renderer::RenderQueue, storage::GpuShaderStorageBuffer,
+        },
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, MORTON_PRESET, RadixSortPlugin, global_keys_buffer,
+        sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{create_render_test_app, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_presets_are_valid() {
+        for preset in RadixSortPreset::all() {
+            let options = preset.options();
+            assert!(options.is_valid(), "{preset:?}");
+            assert_eq!(options.key_mask.count_ones(), preset.key_bits());
+        }
+
+        assert_eq!(RadixSortPreset::Morton30.options().pass_range, 0..4);
+        assert_eq!(RadixSortPreset::Depth16.options().pass_range, 0..2);
+        assert_eq!(RadixSortPreset::CellHash20.options().pass_range, 0..3);
+        assert_eq!(RadixSortPreset::Bucket8.options().pass_range, 0..1);
+        assert_eq!(RadixSortRunOptions::default().key_mask, u32::MAX);
+        let u64_keys = RadixSortRunOptions::for_key_bits(64);
+        assert_eq!(
+            (u64_keys.pass_range.clone(), u64_keys.key_mask),
+            (0..8, u32::MAX)
+        );
+        assert!(u64_keys.is_valid());
+
+        let too_few_passes = RadixSortRunOptions {
+            pass_range: 0..2,
+            ..RadixSortPreset::CellHash20.options()
+        };
+        assert!(!too_few_passes.is_valid());
+    }
+
+    fn run_preset_test(preset: RadixSortPreset, number_of_keys: u32) {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            });
+
+        let options = preset.options();
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761).rotate_left(11) & options.key_mask)
+            .collect();
+
+        // The same result as a full 4-pass argsort
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let read_from_even = true;
+                let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: preset command encoder"),
+                });
+                run_with_options(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    &options,
+                    read_from_even,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let pass_range = &options.pass_range;
+                let output_keys_buf = sorted_keys_buffer(&sbufs, pass_range, read_from_even);
+                let output_vals_buf = sorted_vals_buffer(&sbufs, pass_range, read_from_even);
+                let output_keys =
+                    read_buffer(&render_device, &render_queue, output_keys_buf.unwrap(), n);
+                let output_vals =
+                    read_buffer(&render_device, &render_queue, output_vals_buf.unwrap(), n);
+                assert_eq!(output_keys, expected_keys, "{preset:?}");
+                assert_eq!(output_vals, expected_vals, "{preset:?}");
+            },
+        );
+    }
+
+    #[test]
+    fn test_preset_morton30() {
+        run_preset_test(MORTON_PRESET, 100_003);
+    }
+
+    #[test]
+    fn test_preset_depth16() {
+        run_preset_test(RadixSortPreset::Depth16, 100_003);
+    }
+
+    #[test]
+    fn test_preset_cell_hash20() {
+        run_preset_test(RadixSortPreset::CellHash20, 100_003);
+    }
+}
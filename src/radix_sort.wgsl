@@ -1,56 +1,82 @@
+#import bevy_radix_sort::keys::{NUMBER_OF_KEYS_PER_SCATTER_BLOCK, NUMBER_OF_PASSES, RadixSortGpuParams, SORT_PARAMS_INIT_INDEX, SortParams, extract_digit}
+#import bevy_radix_sort::key_transform::transform_key
+
+// The keys of the global keys buffers: u32 keys, or the 8-byte keys of `KeyType::U64`, native u64 with
+// `SHADER_INT64` or their low and high u32 halves otherwise.
+#ifdef KEY_U64
+#ifdef KEY_U64_NATIVE
+alias Key = u64;
+#else
+alias Key = vec2<u32>;
+#endif // KEY_U64_NATIVE
+#else
+alias Key = u32;
+#endif // KEY_U64
+
 /// Read unsorted(sub-sort) keys from this buffer
-@group(0) @binding(0) var<storage, read      > global_keys_i: array<u32>;
+@group(0) @binding(0) var<storage, read      > global_keys_i: array<Key>;
 /// Read unsorted(sub-sort) vals from this buffer
 @group(0) @binding(1) var<storage, read      > global_vals_i: array<u32>;
 /// Read/Write histograms of count of each radix
+#ifdef DIGIT_HISTOGRAMS_PIPELINE
+@group(0) @binding(2) var<storage, read_write> global_blocks: array<atomic<u32>>;
+#else
 @group(0) @binding(2) var<storage, read_write> global_blocks: array<u32>;
+#endif // DIGIT_HISTOGRAMS_PIPELINE
 /// Write sorted(sub-sort) keys to this buffer
-@group(0) @binding(3) var<storage, read_write> global_keys_o: array<u32>;
+@group(0) @binding(3) var<storage, read_write> global_keys_o: array<Key>;
 /// Write sorted(sub-sort) vals to this buffer
 @group(0) @binding(4) var<storage, read_write> global_vals_o: array<u32>;
 
-struct PushConstants {
-    /// In most cases, the parameters `x`, `y`, `z` in [`ComputePass::dispatch_workgroups(x: u32, y: u32, z: u32)`]
-    /// are limited to the range\[1, 65535\](the maximum number of workgroups per dimension can be queried through
-    /// [`Limits::max_compute_workgroups_per_dimension`]).
-    ///
-    /// When dealing with a particularly large 1-dimensional array, for example, `number_of_keys` = 2^24,
-    /// then `number_of_workgroups` = 2^16 = 65536; So (65536, 1, 1), (1, 65536, 1), and (1, 1, 65536) all exceed
-    /// the valid range and will be rejected by the graphics API.
-    ///
-    /// Therefore, the simplest solution is to split one `dispatch_workgroups(..)` into two or more. Here I choose two:
-    /// - `workgroup_offset` = 0:           dispatch_workgroups(65535, 1, 1)
-    /// - `workgroup_offset` = 65535,       dispatch_workgroups(1, 1, 1)
-    ///
-    /// If `number_of_workgroups` is even larger, for example, `number_of_workgroups` = 2^24, then:
-    /// - `workgroup_offset` = 0:           dispatch_workgroups(65535, 256, 1)
-    /// - `workgroup_offset` = 16776960:    dispatch_workgroups(256, 1, 1)
-    ///
-    /// (Complaint: This is a very annoying limitation that adds unnecessary complexity to the code, but currently there is no better solution)
-    workgroup_offset: u32,
-    /// The number of keys to be sorted.
-    number_of_keys: u32,
-    /// The number of blocks(histogram) required.
-    ///
-    /// `number_of_blks` = ceil(`number_of_keys` / [`NUMBER_OF_THREADS_PER_WORKGROUP`])
-    number_of_blks: u32,
-    /// The current `pass` index being processed. For `u32` type with 8-bit `radix`, it requires 4 passes to process.
-    /// So the valid range for `pass_index` is [0, 3].
-    ///
-    /// Since we are using the LSD (Least Significant Digit) sorting method, the `pass_index` represents:
-    /// - `pass_index` = 0: Processing the least significant 8 bits of the `radix`,         0x000000XX
-    /// - `pass_index` = 1: Processing the second least significant 8 bits of the `radix`,  0x0000XX00
-    /// - `pass_index` = 2: Processing the second most significant 8 bits of the `radix`,   0x00XX0000
-    /// - `pass_index` = 3: Processing the most significant 8 bits of the `radix`,          0xXX000000
-    pass_index: u32,
-    /// Used to control the step size of the prefix sum (inclusive) algorithm in step 2, up-sweep and down-sweep
-    sweep_size: u32,
-    /// Used to control whether to automatically write the index to `odd_global_vals_buf` in the 0th pass
-    init_index: u32,
+// The push constants are the `RadixSortGpuParams` of `keys.wgsl`, whose layout user dispatch drivers rely on.
+//
+// `workgroup_offset`: in most cases, the parameters `x`, `y`, `z` in [`ComputePass::dispatch_workgroups(x: u32, y: u32, z: u32)`]
+// are limited to the range\[1, 65535\](the maximum number of workgroups per dimension can be queried through
+// [`Limits::max_compute_workgroups_per_dimension`]).
+//
+// When dealing with a particularly large 1-dimensional array, for example, `number_of_keys` = 2^24,
+// then `number_of_workgroups` = 2^16 = 65536; So (65536, 1, 1), (1, 65536, 1), and (1, 1, 65536) all exceed
+// the valid range and will be rejected by the graphics API.
+//
+// Therefore, the simplest solution is to split one `dispatch_workgroups(..)` into two or more. Here I choose two:
+// - `workgroup_offset` = 0:           dispatch_workgroups(65535, 1, 1)
+// - `workgroup_offset` = 65535,       dispatch_workgroups(1, 1, 1)
+//
+// If `number_of_workgroups` is even larger, for example, `number_of_workgroups` = 2^24, then:
+// - `workgroup_offset` = 0:           dispatch_workgroups(65535, 256, 1)
+// - `workgroup_offset` = 16776960:    dispatch_workgroups(256, 1, 1)
+//
+// (Complaint: This is a very annoying limitation that adds unnecessary complexity to the code, but currently there is no better solution)
+//
+// `pass_index`: since we are using the LSD (Least Significant Digit) sorting method, the `pass_index` represents:
+// - `pass_index` = 0: Processing the least significant 8 bits of the `radix`,         0x000000XX
+// - `pass_index` = 1: Processing the second least significant 8 bits of the `radix`,  0x0000XX00
+// - `pass_index` = 2: Processing the second most significant 8 bits of the `radix`,   0x00XX0000
+// - `pass_index` = 3: Processing the most significant 8 bits of the `radix`,          0xXX000000
+var<push_constant> pc: RadixSortGpuParams;
+
+#ifdef GPU_DRIVEN
+/// The parameters validated by `run_gpu_driven`, `pc.number_of_keys` is the capacity the sort is recorded for
+@group(1) @binding(0) var<storage, read> params: SortParams;
+#endif // GPU_DRIVEN
+
+/// The number of keys to be sorted.
+fn get_number_of_keys() -> u32 {
+#ifdef GPU_DRIVEN
+    return min(params.count, pc.number_of_keys);
+#else
+    return pc.number_of_keys;
+#endif // GPU_DRIVEN
 }
-var<push_constant> pc: PushConstants;
 
-const NUMBER_OF_KEYS_PER_SCATTER_BLOCK: u32 = #NUMBER_OF_THREADS_PER_WORKGROUP * #NUMBER_OF_ROWS_PER_WORKGROUP;
+/// Whether this pass writes the indices of the keys into the vals.
+fn is_init_index() -> bool {
+#ifdef GPU_DRIVEN
+    return pc.init_index != 0u && (params.flags & SORT_PARAMS_INIT_INDEX) != 0u;
+#else
+    return pc.init_index != 0u;
+#endif // GPU_DRIVEN
+}
 
 fn get_workgroup_index(workgroup_id: vec3u, num_workgroups: vec3u) -> u32 {
     return workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
@@ -60,8 +86,48 @@ fn get_radix_index(workgroup_index: u32, local_invocation_id_x: u32) -> u32 {
     return workgroup_index * #NUMBER_OF_THREADS_PER_WORKGROUP + local_invocation_id_x;
 }
 
-fn calc_radix(key: u32) -> u32 {
-    return extractBits(key, pc.pass_index * #NUMBER_OF_RADIX_BITS, #{NUMBER_OF_RADIX_BITS}u);
+/// The key the passes order by, the keys are written back untransformed. The u64 keys aren't transformed.
+fn sort_key(key: Key) -> Key {
+#ifdef KEY_U64
+    return key;
+#else
+    return transform_key(key);
+#endif // KEY_U64
+}
+
+/// The digit `digit` of a `sort_key`, 0 is the least significant one.
+fn sort_key_digit(key: Key, digit: u32) -> u32 {
+#ifdef KEY_U64
+#ifdef KEY_U64_NATIVE
+    return u32(key >> (digit * #{NUMBER_OF_RADIX_BITS}u)) & (#{NUMBER_OF_RADIX}u - 1u);
+#else
+    // The 4 low digits are the ones of the low half
+    return extract_digit(key[digit / NUMBER_OF_PASSES], digit % NUMBER_OF_PASSES);
+#endif // KEY_U64_NATIVE
+#else
+    return extract_digit(key, digit);
+#endif // KEY_U64
+}
+
+/// The digit of the transformed key, the keys are written back untransformed.
+fn calc_radix(key: Key) -> u32 {
+    return sort_key_digit(sort_key(key), pc.pass_index);
+}
+
+// The digit histograms of `run`, at the end of `global_blocks` past the blocks of the keys (see `blocks_buffer_size`):
+//
+//  [ blocks ... | counts: NUMBER_OF_PASSES x NUMBER_OF_RADIX | offsets: NUMBER_OF_PASSES x NUMBER_OF_RADIX ]
+//
+// The counts of every digit of the pass range are added in a single read of the keys, then scanned into the
+// offsets where each radix of the digit starts. The scan zeroes the counts for the next sort.
+const NUMBER_OF_DIGIT_COUNTS: u32 = NUMBER_OF_PASSES * #{NUMBER_OF_RADIX}u;
+
+fn digit_count_index(digit: u32, radix: u32) -> u32 {
+    return arrayLength(&global_blocks) - 2u * NUMBER_OF_DIGIT_COUNTS + digit * #{NUMBER_OF_RADIX}u + radix;
+}
+
+fn digit_offset_index(digit: u32, radix: u32) -> u32 {
+    return arrayLength(&global_blocks) - NUMBER_OF_DIGIT_COUNTS + digit * #{NUMBER_OF_RADIX}u + radix;
 }
 
 #ifdef COUNT_RADIX_PIPELINE
@@ -82,7 +148,7 @@ fn main(
     workgroupBarrier();
 
     let start_index = workgroup_index * NUMBER_OF_KEYS_PER_SCATTER_BLOCK + local_invocation_id.x;
-    let close_index = min(start_index + NUMBER_OF_KEYS_PER_SCATTER_BLOCK, pc.number_of_keys);
+    let close_index = min(start_index + NUMBER_OF_KEYS_PER_SCATTER_BLOCK, get_number_of_keys());
     for (var key_index = start_index; key_index < close_index; key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u) {
         let key = global_keys_i[key_index];
         let radix = calc_radix(key);
@@ -95,6 +161,46 @@ fn main(
 }
 #endif // COUNT_RADIX_PIPELINE
 
+#ifdef DIGIT_HISTOGRAMS_PIPELINE
+var<workgroup> digit_histograms: array<atomic<u32>, NUMBER_OF_DIGIT_COUNTS>;
+
+// The pass range is `pc.pass_index..pc.sweep_size`
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+
+    // zeroing
+    for (var i = local_invocation_id.x; i < NUMBER_OF_DIGIT_COUNTS; i += #{NUMBER_OF_THREADS_PER_WORKGROUP}u) {
+        atomicStore(&digit_histograms[i], 0u);
+    }
+
+    workgroupBarrier();
+
+    let start_index = workgroup_index * NUMBER_OF_KEYS_PER_SCATTER_BLOCK + local_invocation_id.x;
+    let close_index = min(start_index + NUMBER_OF_KEYS_PER_SCATTER_BLOCK, get_number_of_keys());
+    for (var key_index = start_index; key_index < close_index; key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u) {
+        let key = sort_key(global_keys_i[key_index]);
+        for (var digit = pc.pass_index; digit < pc.sweep_size; digit++) {
+            atomicAdd(&digit_histograms[digit * #{NUMBER_OF_RADIX}u + sort_key_digit(key, digit)], 1u);
+        }
+    }
+
+    workgroupBarrier();
+
+    // One global atomic per workgroup and non-empty radix
+    for (var digit = pc.pass_index; digit < pc.sweep_size; digit++) {
+        let count = atomicLoad(&digit_histograms[digit * #{NUMBER_OF_RADIX}u + local_invocation_id.x]);
+        if count > 0u {
+            atomicAdd(&global_blocks[digit_count_index(digit, local_invocation_id.x)], count);
+        }
+    }
+}
+#endif // DIGIT_HISTOGRAMS_PIPELINE
+
 #ifdef SCAN_UP_SWEEP_PIPELINE
 @compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
 fn main(
@@ -140,14 +246,37 @@ fn main(
 #endif // SCAN_DOWN_SWEEP_PIPELINE
 
 #ifdef SCAN_LAST_BLOCK_PIPELINE
-const NUMBER_OF_SUBGROUPS: u32 = #NUMBER_OF_THREADS_PER_WORKGROUP / #NUMBER_OF_THREADS_PER_SUBGROUP;
+// The most subgroups a workgroup may have, the lane math uses the `subgroup_size` builtin
+const NUMBER_OF_SUBGROUPS: u32 = #{NUMBER_OF_SUBGROUPS_PER_WORKGROUP}u;
+
+#ifdef SUBGROUP_FALLBACK
+var<workgroup> subgroup_sums: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32, subgroup_size: u32) -> u32 {
+    let thread_index = subgroup_id * subgroup_size + subgroup_invocation_id;
+
+    // Hillis-Steele scan in shared memory
+    subgroup_sums[thread_index] = value;
+    workgroupBarrier();
 
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var prev_sum = 0u;
+        if thread_index >= offset { prev_sum = subgroup_sums[thread_index - offset]; }
+        workgroupBarrier();
+
+        subgroup_sums[thread_index] += prev_sum;
+        workgroupBarrier();
+    }
+
+    return subgroup_sums[thread_index] - value;
+}
+#else
 var<workgroup> subgroup_sums: array<u32, NUMBER_OF_SUBGROUPS>;
 
-fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32) -> u32 {
+fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32, subgroup_size: u32) -> u32 {
     let subgroup_prefix_sum = subgroupInclusiveAdd(value);
 
-    if subgroup_invocation_id == #NUMBER_OF_THREADS_PER_SUBGROUP - 1u { subgroup_sums[subgroup_id] = subgroup_prefix_sum; }
+    if subgroup_invocation_id == subgroup_size - 1u { subgroup_sums[subgroup_id] = subgroup_prefix_sum; }
     workgroupBarrier();
     
     let prev_subgroup_sum = select(0u, subgroup_sums[subgroup_invocation_id], subgroup_invocation_id < subgroup_id);
@@ -155,25 +284,117 @@ fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32) ->
 
     return prev_sum + subgroup_prefix_sum - value;
 }
+#endif // SUBGROUP_FALLBACK
 
 @compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
 fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
     @builtin(local_invocation_id) local_invocation_id: vec3u,
+#ifndef SUBGROUP_FALLBACK
     @builtin(subgroup_id) subgroup_id: u32,
     @builtin(subgroup_invocation_id) subgroup_invocation_id: u32,
+    @builtin(subgroup_size) subgroup_size: u32,
+#endif
 ) {
+#ifdef SUBGROUP_FALLBACK
+    let subgroup_size = #{NUMBER_OF_THREADS_PER_SUBGROUP}u;
+    let subgroup_id = local_invocation_id.x / subgroup_size;
+    let subgroup_invocation_id = local_invocation_id.x % subgroup_size;
+#endif
+#ifdef SCAN_DIGIT_HISTOGRAMS
+    // A workgroup per digit of the pass range, from `pc.pass_index`
+    let digit = pc.pass_index + workgroup_id.x;
+    let radix_count_index = digit_count_index(digit, local_invocation_id.x);
+    let radix_count = global_blocks[radix_count_index];
+    global_blocks[radix_count_index] = 0u;
+
+    let prefix_sum_exclusive = scan_exclusive(radix_count, subgroup_id, subgroup_invocation_id, subgroup_size);
+
+    global_blocks[digit_offset_index(digit, local_invocation_id.x)] = prefix_sum_exclusive;
+#else
     let block_index = pc.number_of_blks - 1u;
     let radix_count_index = get_radix_index(block_index, local_invocation_id.x);
     let radix_count = global_blocks[radix_count_index];
 
-    let prefix_sum_exclusive = scan_exclusive(radix_count, subgroup_id, subgroup_invocation_id);
+    let prefix_sum_exclusive = scan_exclusive(radix_count, subgroup_id, subgroup_invocation_id, subgroup_size);
 
     global_blocks[radix_count_index] = prefix_sum_exclusive;
+#endif // SCAN_DIGIT_HISTOGRAMS
 }
 #endif // SCAN_LAST_BLOCK_PIPELINE
 
+// The standalone exclusive scan of `number_of_keys` values (see `scan.rs`) reuses the scan of the blocks:
+// the value `i` is laid out in column `i / number_of_blks` of block `i % number_of_blks`, so the offset of each slot
+// (as read by `fill_global_radix_offset`) is the exclusive prefix sum of the values in column-major order.
+
+#ifdef SCAN_LOAD_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let block_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let value_index = local_invocation_id.x * pc.number_of_blks + block_index;
+
+    var value = 0u;
+    if value_index < get_number_of_keys() {
+        value = global_keys_i[value_index];
+    }
+
+    global_blocks[get_radix_index(block_index, local_invocation_id.x)] = value;
+}
+#endif // SCAN_LOAD_PIPELINE
+
+#ifdef SCAN_STORE_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let block_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let value_index = local_invocation_id.x * pc.number_of_blks + block_index;
+
+    if value_index >= get_number_of_keys() {
+        return;
+    }
+
+    let last_block_index = pc.number_of_blks - 1u;
+    var offset = global_blocks[get_radix_index(last_block_index, local_invocation_id.x)];
+    if block_index > 0u {
+        offset += global_blocks[get_radix_index(block_index - 1u, local_invocation_id.x)];
+    }
+
+    global_keys_o[value_index] = offset;
+}
+#endif // SCAN_STORE_PIPELINE
+
+// Copy the keys/vals to the other side of the global buffers, used by `run_auto` after skipping an odd number of passes
+#ifdef COPY_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_radix_index(workgroup_index, local_invocation_id.x);
+
+    if key_index < get_number_of_keys() {
+        global_keys_o[key_index] = global_keys_i[key_index];
+#ifndef KEYS_ONLY
+        if key_index < arrayLength(&global_vals_o) {
+            global_vals_o[key_index] = global_vals_i[key_index];
+        }
+#endif // KEYS_ONLY
+    }
+}
+#endif // COPY_PIPELINE
+
 #ifdef SCATTER_PIPELINE
-const NUMBER_OF_SUBGROUPS: u32 = #NUMBER_OF_THREADS_PER_WORKGROUP / #NUMBER_OF_THREADS_PER_SUBGROUP;
+// The most subgroups a workgroup may have, the lane math uses the `subgroup_size` builtin
+const NUMBER_OF_SUBGROUPS: u32 = #{NUMBER_OF_SUBGROUPS_PER_WORKGROUP}u;
 const NUMBER_OF_RADIX_COUNTS: u32 = #NUMBER_OF_RADIX * NUMBER_OF_SUBGROUPS;
 
 // In the first stage, `subgroup_histograms` is used as a histogram for counting `radix` within the `subgroup`:
@@ -198,20 +419,47 @@ var<workgroup> histogram: array<u32, #NUMBER_OF_RADIX>;
 // 2. The `SCATTER_BLOCK` will be sorted, and the sorted results will be written back into `thread_keys/thread_vals` in row order;
 // 3. The data in `thread_keys/thread_vals` will be written into `global_keys_o/global_vals_o` according to `global_radix_offset`;
 //
+// After step 2 the tile is staged in shared memory ordered by radix, so in step 3 consecutive threads write the keys
+// of a radix to consecutive addresses: each bucket of the tile is one contiguous, coalesced run in the output.
+// The staging needs `MIN_WORKGROUP_STORAGE_SIZE` bytes of workgroup memory, see `check_limits`.
+//
 // ## Why use such a complex data structure?
 //
 // It is to avoid delays caused by high `L2 Cache Throughput`.
-var<private> thread_keys: array<u32, #NUMBER_OF_ROWS_PER_WORKGROUP>;
+var<private> thread_keys: array<Key, #NUMBER_OF_ROWS_PER_WORKGROUP>;
 var<private> thread_vals: array<u32, #NUMBER_OF_ROWS_PER_WORKGROUP>;
 // `order` indicates the number of times the corresponding key's radix appears in the `SCATTER_BLOCK`
 var<private> thread_ords: array<u32, #NUMBER_OF_ROWS_PER_WORKGROUP>;
 
 // In the `scatter` step, when using `scan_exclusive`, `subgroup_histograms` is idle and can be used as `subgroup_sums`,
 // saving the use of `shared memory` (although it's not much).
-fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32) -> u32 {
+#ifdef SUBGROUP_FALLBACK
+fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32, subgroup_size: u32) -> u32 {
+    let thread_index = subgroup_id * subgroup_size + subgroup_invocation_id;
+
+    // Hillis-Steele scan in shared memory
+    subgroup_histograms[thread_index] = value;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var prev_sum = 0u;
+        if thread_index >= offset { prev_sum = subgroup_histograms[thread_index - offset]; }
+        workgroupBarrier();
+
+        subgroup_histograms[thread_index] += prev_sum;
+        workgroupBarrier();
+    }
+
+    return subgroup_histograms[thread_index] - value;
+}
+
+// The radix of each thread of the current row, to emulate `subgroupBallot(..)`
+var<workgroup> thread_radixes: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+#else
+fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32, subgroup_size: u32) -> u32 {
     let subgroup_prefix_sum = subgroupInclusiveAdd(value);
 
-    if subgroup_invocation_id == #NUMBER_OF_THREADS_PER_SUBGROUP - 1u { subgroup_histograms[subgroup_id] = subgroup_prefix_sum; }
+    if subgroup_invocation_id == subgroup_size - 1u { subgroup_histograms[subgroup_id] = subgroup_prefix_sum; }
     workgroupBarrier();
     
     let prev_subgroup_sum = select(0u, subgroup_histograms[subgroup_invocation_id], subgroup_invocation_id < subgroup_id);
@@ -219,14 +467,42 @@ fn scan_exclusive(value: u32, subgroup_id: u32, subgroup_invocation_id: u32) ->
 
     return prev_sum + subgroup_prefix_sum - value;
 }
+#endif // SUBGROUP_FALLBACK
+
+#ifdef KEY_U64
+// The u64 keys are staged in `subgroup_histograms` a u32 word at a time, the low word first
+fn key_word(key: Key, word: u32) -> u32 {
+#ifdef KEY_U64_NATIVE
+    return u32(key >> (32u * word));
+#else
+    return key[word];
+#endif // KEY_U64_NATIVE
+}
+
+fn with_key_word(key: Key, word: u32, value: u32) -> Key {
+#ifdef KEY_U64_NATIVE
+    let shift = 32u * word;
+    return (key & ~(u64(0xFFFFFFFFu) << shift)) | (u64(value) << shift);
+#else
+    var words = key;
+    words[word] = value;
+    return words;
+#endif // KEY_U64_NATIVE
+}
+#endif // KEY_U64
 
 fn div_ceil(a: u32, b: u32) -> u32 {
     return (a + b - 1u) / b;
 }
 
 fn fill_global_radix_offset(workgroup_index: u32, local_invocation_id_x: u32) {
+#ifdef FUSED_DIGIT_HISTOGRAMS
+    // Where the radix starts, scanned from the digit histograms before the passes
+    let radix_initial_offset_index = digit_offset_index(pc.pass_index, local_invocation_id_x);
+#else
     let last_block_index = pc.number_of_blks - 1u;
     let radix_initial_offset_index = get_radix_index(last_block_index, local_invocation_id_x);
+#endif // FUSED_DIGIT_HISTOGRAMS
 
     var radix_offset = global_blocks[radix_initial_offset_index];
 
@@ -265,32 +541,62 @@ fn main(
     @builtin(workgroup_id) workgroup_id: vec3u,
     @builtin(num_workgroups) num_workgroups: vec3u,
     @builtin(local_invocation_id) local_invocation_id: vec3u,
+#ifndef SUBGROUP_FALLBACK
     @builtin(subgroup_id) subgroup_id: u32,
     @builtin(subgroup_invocation_id) subgroup_invocation_id: u32,
+    @builtin(subgroup_size) subgroup_size: u32,
+#endif
 ) {
+#ifdef SUBGROUP_FALLBACK
+    let subgroup_size = #{NUMBER_OF_THREADS_PER_SUBGROUP}u;
+    let subgroup_id = local_invocation_id.x / subgroup_size;
+    let subgroup_invocation_id = local_invocation_id.x % subgroup_size;
+#endif
+    // The rows of `subgroup_histograms` in use, the driver may pick a wider subgroup than it reported
+    let number_of_radix_counts = #{NUMBER_OF_RADIX}u * div_ceil(#{NUMBER_OF_THREADS_PER_WORKGROUP}u, subgroup_size);
+
     let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
 
     // zeroing: no workgroupBarrier() required
     histogram[local_invocation_id.x] = 0u;
 
     let base_index = workgroup_index * NUMBER_OF_KEYS_PER_SCATTER_BLOCK;
-    let number_of_keys_of_scatter_block = min(NUMBER_OF_KEYS_PER_SCATTER_BLOCK, pc.number_of_keys - base_index);
+    // The workgroups past the keys of a GPU-driven sort have none
+    let number_of_keys_of_scatter_block = min(NUMBER_OF_KEYS_PER_SCATTER_BLOCK, max(get_number_of_keys(), base_index) - base_index);
     let number_of_rows_of_scatter_block = div_ceil(number_of_keys_of_scatter_block, #{NUMBER_OF_THREADS_PER_WORKGROUP}u);
 
     var key_index = base_index + local_invocation_id.x;
     for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
-        let is_active = key_index < pc.number_of_keys;
+        let is_active = key_index < get_number_of_keys();
 
         // Avoid reading out-of-bounds data
-        var key = 0xFFFFFFFFu;
+        var key = ~Key();
         var val = key_index;
         if is_active {
             key = global_keys_i[key_index];
-            if pc.init_index == 0u { val = global_vals_i[key_index]; }
+#ifndef KEYS_ONLY
+            // The vals buffers may hold fewer vals than the keys, see `RadixSortSettings::with_max_number_of_values`
+            if !is_init_index() && key_index < arrayLength(&global_vals_i) { val = global_vals_i[key_index]; }
+#endif // KEYS_ONLY
         }
         
         let radix = calc_radix(key);
 
+#ifdef SUBGROUP_FALLBACK
+        // Without subgroup operations, the ballots are emulated by comparing the radixes of the `subgroup_threads`
+        // in shared memory, the inactive threads match no radix.
+        thread_radixes[local_invocation_id.x] = select(0xFFFFFFFFu, radix, is_active);
+        workgroupBarrier();
+
+        let subgroup_base_index = subgroup_id * subgroup_size;
+        var radix_count_of_subgroup = 0u;
+        var prv_sgtid_radix_count_of_subgroup = 0u;
+        for (var i = 0u; i < subgroup_size; i++) {
+            let is_same_radix = thread_radixes[subgroup_base_index + i] == radix;
+            radix_count_of_subgroup += u32(is_same_radix);
+            prv_sgtid_radix_count_of_subgroup += u32(is_same_radix && i < subgroup_invocation_id);
+        }
+#else
         // This loop's task is to find all `subgroup_threads` in the `subgroup` that have the same `radix`.
         //
         // ## Idea
@@ -323,16 +629,23 @@ fn main(
         // The number of the radix has appeared before this subgroup_invocation_id in the subgroup.
         let prv_sgtid_subgroup_mask = calc_prv_sgtid_subgroup_mask(subgroup_invocation_id);
         let prv_sgtid_radix_count_of_subgroup = count_one_bits_vec4u(prv_sgtid_subgroup_mask & radix_subgroup_mask);
+        let radix_count_of_subgroup = count_one_bits_vec4u(radix_subgroup_mask);
+#endif // SUBGROUP_FALLBACK
 
         // zeroing: no workgroupBarrier() required
         let base_index = subgroup_id * #NUMBER_OF_RADIX + subgroup_invocation_id;
         let close_index = subgroup_id * #NUMBER_OF_RADIX + #NUMBER_OF_RADIX;
-        for (var i = base_index; i < close_index; i += #{NUMBER_OF_THREADS_PER_SUBGROUP}u) {
+        for (var i = base_index; i < close_index; i += subgroup_size) {
             subgroup_histograms[i] = 0u;
         }
 
+#ifdef SUBGROUP_FALLBACK
+        // The emulated `subgroup_threads` don't execute in lockstep
+        workgroupBarrier();
+#endif
+
         let radix_count_index = subgroup_id * #NUMBER_OF_RADIX + radix;
-        subgroup_histograms[radix_count_index] = count_one_bits_vec4u(radix_subgroup_mask);
+        subgroup_histograms[radix_count_index] = radix_count_of_subgroup;
 
         workgroupBarrier();
 
@@ -340,7 +653,7 @@ fn main(
 
         // prefix sum exclusively
         var accumulation = 0u;
-        for (var i = local_invocation_id.x; i < NUMBER_OF_RADIX_COUNTS; i += #{NUMBER_OF_RADIX}u) {
+        for (var i = local_invocation_id.x; i < number_of_radix_counts; i += #{NUMBER_OF_RADIX}u) {
             let radix_count_of_subgroup = subgroup_histograms[i];
             subgroup_histograms[i] = accumulation;
             accumulation += radix_count_of_subgroup;
@@ -362,7 +675,7 @@ fn main(
     workgroupBarrier();
 
     // Calculate the local_radix_offset
-    histogram[local_invocation_id.x] = scan_exclusive(histogram[local_invocation_id.x], subgroup_id, subgroup_invocation_id);
+    histogram[local_invocation_id.x] = scan_exclusive(histogram[local_invocation_id.x], subgroup_id, subgroup_invocation_id, subgroup_size);
 
     workgroupBarrier();
 
@@ -384,6 +697,29 @@ fn main(
 
     workgroupBarrier();
 
+#ifdef KEY_U64
+    // KEYS: reorder in the `SCATTER_BLOCK`, then write the sorted back to the `thread_keys`, a word at a time
+    var sorted_keys: array<Key, #NUMBER_OF_ROWS_PER_WORKGROUP>;
+    for (var word = 0u; word < 2u; word++) {
+        for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
+            let local_ordered_index = thread_ords[row] >> 16u;
+
+            subgroup_histograms[local_ordered_index] = key_word(thread_keys[row], word);
+        }
+
+        workgroupBarrier();
+
+        key_index = local_invocation_id.x;
+        for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
+            sorted_keys[row] = with_key_word(sorted_keys[row], word, subgroup_histograms[key_index]);
+
+            key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+        }
+
+        workgroupBarrier();
+    }
+    thread_keys = sorted_keys;
+#else
     // KEYS: reorder in the `SCATTER_BLOCK`
     for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
         let key = thread_keys[row];
@@ -413,7 +749,9 @@ fn main(
     }
 
     workgroupBarrier();
+#endif // KEY_U64
 
+#ifndef KEYS_ONLY
     // VALS: reorder in the `SCATTER_BLOCK`
     for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
         let val = thread_vals[row];
@@ -433,6 +771,7 @@ fn main(
     }
 
     workgroupBarrier();
+#endif // KEYS_ONLY
 
     // ORDS: reoder in the `SCATTER_BLOCK`
     for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
@@ -456,7 +795,7 @@ fn main(
     // Write the sorted results back to the `global_keys_o/global_vals_o`
     key_index = base_index + local_invocation_id.x;
     for (var row = 0u; row < number_of_rows_of_scatter_block; row++) {
-        let is_active = key_index < pc.number_of_keys;
+        let is_active = key_index < get_number_of_keys();
 
         if is_active {
             let key = thread_keys[row];
@@ -468,7 +807,16 @@ fn main(
             let global_ordered_index = histogram[radix] + ord;
 
             global_keys_o[global_ordered_index] = key;
-            global_vals_o[global_ordered_index] = val;
+#ifdef PAYLOAD_SCATTER
+            // Defined by the hook of the `PayloadScatterPlugin`, appended to this shader
+            write_payload(global_ordered_index, key, val);
+#else
+#ifndef KEYS_ONLY
+            if global_ordered_index < arrayLength(&global_vals_o) {
+                global_vals_o[global_ordered_index] = val;
+            }
+#endif // KEYS_ONLY
+#endif // PAYLOAD_SCATTER
         }
 
         key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
@@ -0,0 +1,257 @@
+//! Readbacks of GPU buffers that never block, the only kind wasm32 allows: the browser owns the event loop, so
+//! `RenderDevice::poll(Maintain::Wait)` can't wait for a mapping there.
+//!
+//! ```text
+//!  frame N:      BufferReadback::new (copy to a staging buffer) ─▶ submit
+//!  frame N + k:  poll: map, Maintain::Poll ─▶ None ─▶ ... ─▶ Some(data)
+//! ```
+//!
+//! [`BufferReadback::read`] wraps the polling into a future resolving across frames. Uploads don't need a helper,
+//! `RenderQueue::write_buffer` never blocks. The blocking helpers of the crate ([`BufferReadback::wait`],
+//! [`crate::read_count_less_than`], the CPU fallback and the subgroup size probe) aren't compiled for wasm32.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Poll, Waker},
+};
+
+use bevy::render::{
+    render_resource::{
+        Buffer, BufferAddress, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+        Maintain, MapMode,
+    },
+    renderer::RenderDevice,
+};
+
+use crate::{KeyType, NUMBER_OF_BYTES_PER_KEY};
+
+/// The u32s copied out of a buffer, read back once the copy is done, see the [module docs](self).
+#[derive(Debug)]
+pub struct BufferReadback {
+    staging: Buffer,
+    len: usize,
+    state: Arc<ReadbackState>,
+    /// `map_async` was called
+    mapping: bool,
+    /// The data was returned, the staging buffer is unmapped
+    read: bool,
+}
+
+#[derive(Debug, Default)]
+struct ReadbackState {
+    mapped: OnceLock<Result<(), BufferAsyncError>>,
+    /// Woken by the mapping, see [`BufferReadback::read`]
+    waker: Mutex<Option<Waker>>,
+}
+
+impl BufferReadback {
+    /// Copies `len` u32s of `buffer` from `offset` into a staging buffer, `buffer` needs the `COPY_SRC` usage.
+    pub fn new(
+        render_device: &RenderDevice,
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        offset: BufferAddress,
+        len: usize,
+    ) -> Self {
+        let size = (len * NUMBER_OF_BYTES_PER_KEY as usize) as BufferAddress;
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("radix_sort: readback staging buffer"),
+            // A buffer can't be mapped empty
+            size: size.max(NUMBER_OF_BYTES_PER_KEY as BufferAddress),
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        if size > 0 {
+            encoder.copy_buffer_to_buffer(buffer, offset, &staging, 0, size);
+        }
+
+        Self {
+            staging,
+            len,
+            state: Arc::default(),
+            mapping: false,
+            read: false,
+        }
+    }
+
+    /// Copies the first `number_of_keys` keys of a keys buffer holding `key_type` keys, two u32s per [`KeyType::U64`]
+    /// key, see [`u64_keys`].
+    pub fn keys(
+        render_device: &RenderDevice,
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        number_of_keys: usize,
+        key_type: KeyType,
+    ) -> Self {
+        let len = number_of_keys * (key_type.size() / NUMBER_OF_BYTES_PER_KEY) as usize;
+        Self::new(render_device, encoder, buffer, 0, len)
+    }
+
+    /// The number of u32s read back.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Maps the staging buffer on the first call, the commands of [`Self::new`] must have been submitted, and returns
+    /// the data once it's mapped. Never blocks: call it once per frame until it returns `Some`, then drop it.
+    pub fn poll(
+        &mut self,
+        render_device: &RenderDevice,
+    ) -> Option<Result<Vec<u32>, BufferAsyncError>> {
+        self.map();
+        render_device.poll(Maintain::Poll);
+
+        self.take()
+    }
+
+    /// [`Self::poll`] as a future, resolving once the data is mapped. The mapping completes when the device is
+    /// polled, e.g. by the submissions of the next frames, and wakes the future to read it.
+    pub fn read(
+        mut self,
+        render_device: RenderDevice,
+    ) -> impl Future<Output = Result<Vec<u32>, BufferAsyncError>> + Send {
+        std::future::poll_fn(move |cx| {
+            // Before polling, a mapping completing in between still wakes the future
+            *self.state.waker.lock().unwrap() = Some(cx.waker().clone());
+            match self.poll(&render_device) {
+                Some(result) => Poll::Ready(result),
+                None => Poll::Pending,
+            }
+        })
+    }
+
+    /// Blocks until the data is mapped. Not available on wasm32, see [`Self::poll`] and [`Self::read`] instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait(mut self, render_device: &RenderDevice) -> Result<Vec<u32>, BufferAsyncError> {
+        self.map();
+        render_device.poll(Maintain::Wait).panic_on_timeout();
+
+        self.take()
+            .expect("the readback staging buffer should be mapped after Maintain::Wait")
+    }
+
+    fn map(&mut self) {
+        if std::mem::replace(&mut self.mapping, true) {
+            return;
+        }
+
+        let state = self.state.clone();
+        self.staging
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = state.mapped.set(result);
+                if let Some(waker) = state.waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            });
+    }
+
+    fn take(&mut self) -> Option<Result<Vec<u32>, BufferAsyncError>> {
+        if self.read {
+            return None;
+        }
+        let result = self.state.mapped.get()?.clone();
+        self.read = true;
+
+        Some(result.map(|()| {
+            let data = bytemuck::cast_slice(&self.staging.slice(..).get_mapped_range())[..self.len]
+                .to_vec();
+            self.staging.unmap();
+            data
+        }))
+    }
+}
+
+/// The [`KeyType::U64`] keys of the u32s read back from their keys buffer, the low half of each key first.
+pub fn u64_keys(words: &[u32]) -> Vec<u64> {
+    words
+        .chunks_exact(2)
+        .map(|halves| u64::from(halves[0]) | (u64::from(halves[1]) << 32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::pin, task::Context};
+
+    use bevy::{
+        prelude::*,
+        render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue},
+    };
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_readback() {
+        let mut app = create_render_test_app();
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>, render_queue: Res<RenderQueue>| {
+                let data: Vec<u32> = (0..1000).map(|i| i * 3).collect();
+                let buffer = create_storage_buffer(&render_device, &data);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: readback command encoder"),
+                });
+                let mut polled =
+                    BufferReadback::new(&render_device, &mut encoder, &buffer, 0, 1000);
+                let waited = BufferReadback::new(&render_device, &mut encoder, &buffer, 40, 10);
+                let awaited = BufferReadback::new(&render_device, &mut encoder, &buffer, 0, 0);
+                assert!(awaited.is_empty());
+                render_queue.submit([encoder.finish()]);
+
+                let polled = loop {
+                    if let Some(result) = polled.poll(&render_device) {
+                        break result.unwrap();
+                    }
+                };
+                assert_eq!(polled, data);
+
+                assert_eq!(waited.wait(&render_device).unwrap(), &data[10..20]);
+
+                let mut future = pin!(awaited.read(render_device.clone()));
+                let mut cx = Context::from_waker(Waker::noop());
+                let awaited = loop {
+                    if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+                        break result.unwrap();
+                    }
+                };
+                assert!(awaited.is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn test_u64_keys_readback() {
+        let mut app = create_render_test_app();
+        run_render_system_once(
+            &mut app,
+            |render_device: Res<RenderDevice>, render_queue: Res<RenderQueue>| {
+                let keys: Vec<u64> = (0..100).map(|i| (i << 40) | (i * 7)).collect();
+                let buffer = create_storage_buffer(&render_device, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: readback command encoder"),
+                });
+                let readback =
+                    BufferReadback::keys(&render_device, &mut encoder, &buffer, 10, KeyType::U64);
+                assert_eq!(readback.len(), 20);
+                render_queue.submit([encoder.finish()]);
+
+                assert_eq!(
+                    u64_keys(&readback.wait(&render_device).unwrap()),
+                    &keys[..10]
+                );
+            },
+        );
+    }
+}
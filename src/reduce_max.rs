@@ -0,0 +1,1951 @@
+//! Device-wide maximum of the keys, and [`run_auto`]: a sort skipping the high-digit passes the keys don't need.
+//!
+//! The maximum stays on the GPU: [`run_auto`] turns it into the indirect dispatch args of the sort,
+//! the passes above the highest non-zero digit of the maximum get 0 workgroups. It's also useful on its own,
+//! e.g. to normalize keys.
+//!
+//! [`run_unless_sorted`] works the same way with a cheaper reduction, whether any key is greater than the next
+//! one, and skips every pass of keys already sorted, e.g. depths seen by a mostly static camera:
+//!
+//! ```text
+//!  run_auto:           reduce_max    ─▶ auto_args   ─▶ passes below the highest digit ─▶ copy
+//!  run_unless_sorted:  check_sorted  ─▶ sorted_args ─▶ all passes, or none            ─▶ copy
+//!  run_gpu_driven:     user params   ─▶ gpu_args    ─▶ passes below the mask, count   ─▶ copy
+//! ```
+//!
+//! [`run_gpu_driven`] takes the count and the passes from a [`GpuSortParams`] buffer a user shader writes, e.g. a
+//! culling pass knowing how many keys it kept and which digits they use. Nothing is read back, the sort is recorded
+//! for an upper bound of the count.
+//!
+//! The output location of [`run_auto`] doesn't depend on the passes actually run: when an odd number of passes
+//! is skipped the result is copied to where [`crate::run`] over the same `pass_range` would have left it
+//! (see [`crate::sorted_keys_buffer`]).
+
+use std::{
+    fmt,
+    num::NonZeroU64,
+    ops::Range,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BindingResource,
+            Buffer, BufferAddress, BufferBinding, BufferDescriptor, BufferId, BufferUsages,
+            CachedComputePipelineId, CommandEncoder, ComputePass, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{
+                storage_buffer, storage_buffer_read_only, storage_buffer_read_only_sized,
+                storage_buffer_sized,
+            },
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    Algorithm, DebugPassRecorder, KeyType, LoadState, NUMBER_OF_ROWS_PER_WORKGROUP,
+    NUMBER_OF_THREADS_PER_WORKGROUP, PUSH_CONSTANT_RANGES, PassRecorder, RADIX_SORT_SHADER_HANDLE,
+    RadixSortBindGroup, RadixSortDebugInfo, RadixSortEarlyOut, RadixSortPipeline, SortPipelines,
+    SubgroupSize, compute_pipelines_load_state, dispatch_workgroup_ext,
+    dispatch_workgroup_ext_with, global_keys_buffer, radix_sort_shader_defs, radix_stages,
+    record_sort_passes, tile_size,
+};
+
+pub const REDUCE_MAX_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(48476874896110401968825358499184429804);
+
+/// The size in bytes of the `max_key` buffer.
+pub const MAX_KEY_BUFFER_SIZE: BufferAddress = std::mem::size_of::<u32>() as BufferAddress;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+
+const REDUCE_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..8,
+};
+
+const NUMBER_OF_DISPATCHES_OFFSET: u32 = 0;
+const PASS_START_OFFSET: u32 = 4;
+const PASS_END_OFFSET: u32 = 8;
+const READ_FROM_EVEN_OFFSET: u32 = 12;
+
+const AUTO_ARGS_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..16,
+};
+
+const INIT_INDEX_OFFSET: u32 = 16;
+
+const SORTED_ARGS_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..20,
+};
+
+const MAX_NUMBER_OF_KEYS_OFFSET: u32 = 16;
+const FLAGS_MASK_OFFSET: u32 = 20;
+
+const GPU_DRIVEN_ARGS_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..24,
+};
+
+/// The parameters of [`run_gpu_driven`], written by a user shader.
+///
+/// Must match `SortParams` in the WGSL module `bevy_radix_sort::keys`, which documents the layout:
+///
+/// ```wgsl
+/// #import bevy_radix_sort::keys
+///
+/// @group(0) @binding(0) var<storage, read_write> params: keys::SortParams;
+///
+/// params.count = atomicLoad(&number_of_visible);
+/// params.pass_mask = 0x3u; // the keys are below 2^16
+/// params.flags = keys::SORT_PARAMS_INIT_INDEX;
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct GpuSortParams {
+    /// The number of keys, clamped to the `max_number_of_keys` of [`run_gpu_driven`].
+    pub count: u32,
+    /// Bit `i` set if digit `i` of some key isn't zero: the first pass and the passes up to the highest set bit run,
+    /// the bits outside the pass range are cleared.
+    pub pass_mask: u32,
+    /// [`SORT_PARAMS_INIT_INDEX`], the other bits are cleared.
+    pub flags: u32,
+    pub padding: u32,
+}
+
+/// The size in bytes of a [`GpuSortParams`] buffer.
+pub const SORT_PARAMS_SIZE: BufferAddress = std::mem::size_of::<GpuSortParams>() as BufferAddress;
+
+/// The first pass writes the indices of the keys into the vals, ignored by keys-only sorts.
+pub const SORT_PARAMS_INIT_INDEX: u32 = 1;
+
+/// See `reduce_max.wgsl`
+const COPY_FROM_EVEN_TAG: u32 = 0xFFFFFFFE;
+const COPY_FROM_ODD_TAG: u32 = 0xFFFFFFFF;
+
+/// `[x, y, z]`
+const DISPATCH_ARGS_SIZE: BufferAddress = 3 * std::mem::size_of::<u32>() as BufferAddress;
+
+/// Requires the [`crate::RadixSortPlugin`] to be added before it, [`run_auto`] shares its pipeline layout.
+pub struct ReduceMaxPlugin;
+
+impl Plugin for ReduceMaxPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            REDUCE_MAX_SHADER_HANDLE,
+            "reduce_max.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        // The adapter doesn't support the sort, see `RadixSortUnsupported`
+        if !render_app.world().contains_resource::<RadixSortPipeline>() {
+            return;
+        }
+
+        render_app
+            .init_resource::<ReduceMaxPipeline>()
+            .add_systems(Render, reset_indirect_args_pool.in_set(RenderSet::Cleanup));
+    }
+}
+
+/// Hands the slots of the [`IndirectArgsPool`] out again, the sorts of the frame were submitted by the render graph.
+fn reset_indirect_args_pool(reduce_max_pipeline: Res<ReduceMaxPipeline>) {
+    reduce_max_pipeline.args_pool.next_frame();
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ReduceMaxPipeline {
+    /// Reset the maximum to 0
+    clear_max_pipeline: CachedComputePipelineId,
+    /// Fold the keys of each block into the maximum
+    reduce_max_pipeline: CachedComputePipelineId,
+    /// Zero the indirect args of the sort passes not needed by the maximum
+    auto_args_pipeline: CachedComputePipelineId,
+    /// Set the unsorted flag if some key is greater than the next one, with the layout of the reduction
+    check_sorted_pipeline: CachedComputePipelineId,
+    /// Zero the indirect args of the sort passes if the keys are already sorted
+    sorted_args_pipeline: CachedComputePipelineId,
+    /// Copy the keys/vals to the other side of the global buffers, with the layout of the [`RadixSortPipeline`]
+    copy_pipeline: CachedComputePipelineId,
+    /// Validate the [`GpuSortParams`] and turn them into the indirect args of the sort
+    gpu_args_pipeline: CachedComputePipelineId,
+    /// The steps of the sort reading the count from the validated [`GpuSortParams`], bound to group 1
+    gpu_count_radix_pipeline: CachedComputePipelineId,
+    gpu_scatter_pipeline: CachedComputePipelineId,
+    gpu_copy_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout of the reduction is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > keys: array<u32>;
+    /// @binding(1) var<storage, read_write> max_key: atomic<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+    /// The bindgroup layout of the indirect args of [`run_auto`] is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > max_key: u32;
+    /// @binding(1) var<storage, read_write> args: array<u32>;
+    /// ```
+    ///
+    /// `args` is a slot of the [`IndirectArgsPool`], bound with a dynamic offset.
+    auto_args_bind_group_layout: BindGroupLayout,
+    /// The bindgroup layout of the indirect args of [`run_gpu_driven`] is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > user_params: keys::SortParams;
+    /// @binding(1) var<storage, read_write> args: array<u32>;
+    /// @binding(2) var<storage, read_write> params: keys::SortParams;
+    /// ```
+    ///
+    /// `args` is a slot of the [`IndirectArgsPool`], like with [`run_auto`].
+    gpu_args_bind_group_layout: BindGroupLayout,
+    /// The bindgroup layout of the validated parameters, group 1 of the sort steps of [`run_gpu_driven`]:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read> params: SortParams;
+    /// ```
+    params_bind_group_layout: BindGroupLayout,
+    /// The maximum written by [`run_auto`]
+    max_key_buf: Buffer,
+    /// The unsorted flag written by [`run_unless_sorted`]
+    unsorted_buf: Buffer,
+    /// The parameters validated by [`run_gpu_driven`]
+    params_buf: Buffer,
+    /// `params_buf` bound to the [`Self::params_bind_group_layout`]
+    params_bind_group: BindGroup,
+    /// The indirect args of the sorts of the frame and the bind groups of the indirect sorts
+    args_pool: Arc<IndirectArgsPool>,
+}
+
+impl FromWorld for ReduceMaxPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let subgroup_size = world.resource::<SubgroupSize>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "reduce_max bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let auto_args_bind_group_layout = render_device.create_bind_group_layout(
+            "reduce_max: auto_args bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_sized(true, None),
+                ),
+            ),
+        );
+
+        let params_size = NonZeroU64::new(SORT_PARAMS_SIZE);
+        let gpu_args_bind_group_layout = render_device.create_bind_group_layout(
+            "reduce_max: gpu_args bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only_sized(false, params_size),
+                    storage_buffer_sized(true, None),
+                    storage_buffer_sized(false, params_size),
+                ),
+            ),
+        );
+
+        let params_bind_group_layout = render_device.create_bind_group_layout(
+            "reduce_max: params bindgroup layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                storage_buffer_read_only_sized(false, params_size),
+            ),
+        );
+
+        // The maximum and the order are those of the transformed keys the passes extract their digits from
+        let cdefs = [
+            vec![
+                ShaderDefVal::UInt(
+                    "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                    NUMBER_OF_THREADS_PER_WORKGROUP,
+                ),
+                ShaderDefVal::UInt(
+                    "NUMBER_OF_ROWS_PER_WORKGROUP".into(),
+                    NUMBER_OF_ROWS_PER_WORKGROUP,
+                ),
+            ],
+            radix_sort_pipeline.key_transform().shader_defs(),
+        ]
+        .concat();
+
+        let clear_max_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("reduce_max: clear_max pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![REDUCE_PUSH_CONSTANT_RANGES],
+            shader: REDUCE_MAX_SHADER_HANDLE,
+            shader_defs: [
+                cdefs.as_slice(),
+                &["REDUCE_MAX_BINDINGS".into(), "CLEAR_MAX_PIPELINE".into()],
+            ]
+            .concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let reduce_max_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("reduce_max: reduce_max pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![REDUCE_PUSH_CONSTANT_RANGES],
+                shader: REDUCE_MAX_SHADER_HANDLE,
+                shader_defs: [
+                    cdefs.as_slice(),
+                    &["REDUCE_MAX_BINDINGS".into(), "REDUCE_MAX_PIPELINE".into()],
+                ]
+                .concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let auto_args_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("reduce_max: auto_args pipeline".into()),
+            layout: vec![auto_args_bind_group_layout.clone()],
+            push_constant_ranges: vec![AUTO_ARGS_PUSH_CONSTANT_RANGES],
+            shader: REDUCE_MAX_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["AUTO_ARGS_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let check_sorted_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("reduce_max: check_sorted pipeline".into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![REDUCE_PUSH_CONSTANT_RANGES],
+                shader: REDUCE_MAX_SHADER_HANDLE,
+                shader_defs: [
+                    cdefs.as_slice(),
+                    &["REDUCE_MAX_BINDINGS".into(), "CHECK_SORTED_PIPELINE".into()],
+                ]
+                .concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let sorted_args_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("reduce_max: sorted_args pipeline".into()),
+                layout: vec![auto_args_bind_group_layout.clone()],
+                push_constant_ranges: vec![SORTED_ARGS_PUSH_CONSTANT_RANGES],
+                shader: REDUCE_MAX_SHADER_HANDLE,
+                shader_defs: [
+                    cdefs.as_slice(),
+                    &["AUTO_ARGS_PIPELINE".into(), "SORTED_ARGS".into()],
+                ]
+                .concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        let gpu_args_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("reduce_max: gpu_args pipeline".into()),
+            layout: vec![gpu_args_bind_group_layout.clone()],
+            push_constant_ranges: vec![GPU_DRIVEN_ARGS_PUSH_CONSTANT_RANGES],
+            shader: REDUCE_MAX_SHADER_HANDLE,
+            shader_defs: [
+                cdefs.as_slice(),
+                &["AUTO_ARGS_PIPELINE".into(), "GPU_DRIVEN_ARGS".into()],
+            ]
+            .concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let mut sort_defs = radix_sort_shader_defs(
+            subgroup_size,
+            radix_sort_pipeline.subgroup_fallback(),
+            radix_sort_pipeline.key_transform(),
+        );
+        if !radix_sort_pipeline.allocate_values() {
+            sort_defs.push("KEYS_ONLY".into());
+        }
+
+        let queue_sort_pipeline = |label: &'static str, step: &'static str, gpu_driven: bool| {
+            let mut layout = vec![radix_sort_pipeline.bind_group_layout().clone()];
+            let mut shader_defs = [sort_defs.as_slice(), &[step.into()]].concat();
+            if gpu_driven {
+                layout.push(params_bind_group_layout.clone());
+                shader_defs.push("GPU_DRIVEN".into());
+            }
+
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout,
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs,
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let copy_pipeline =
+            queue_sort_pipeline("reduce_max: copy pipeline", "COPY_PIPELINE", false);
+        let gpu_count_radix_pipeline = queue_sort_pipeline(
+            "reduce_max: gpu_driven count_radix pipeline",
+            "COUNT_RADIX_PIPELINE",
+            true,
+        );
+        let gpu_scatter_pipeline = queue_sort_pipeline(
+            "reduce_max: gpu_driven scatter pipeline",
+            "SCATTER_PIPELINE",
+            true,
+        );
+        let gpu_copy_pipeline = queue_sort_pipeline(
+            "reduce_max: gpu_driven copy pipeline",
+            "COPY_PIPELINE",
+            true,
+        );
+
+        let max_key_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("reduce_max: max_key buffer"),
+            size: MAX_KEY_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let unsorted_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("reduce_max: unsorted buffer"),
+            size: MAX_KEY_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let params_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("reduce_max: params buffer"),
+            size: SORT_PARAMS_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let params_bind_group = render_device.create_bind_group(
+            "reduce_max: params bind_group",
+            &params_bind_group_layout,
+            &BindGroupEntries::single(params_buf.as_entire_binding()),
+        );
+
+        let args_pool = Arc::new(IndirectArgsPool::new(
+            render_device,
+            world.resource::<RenderQueue>().clone(),
+        ));
+
+        Self {
+            clear_max_pipeline,
+            reduce_max_pipeline,
+            auto_args_pipeline,
+            check_sorted_pipeline,
+            sorted_args_pipeline,
+            copy_pipeline,
+            gpu_args_pipeline,
+            gpu_count_radix_pipeline,
+            gpu_scatter_pipeline,
+            gpu_copy_pipeline,
+            bind_group_layout,
+            auto_args_bind_group_layout,
+            gpu_args_bind_group_layout,
+            params_bind_group_layout,
+            max_key_buf,
+            unsorted_buf,
+            params_buf,
+            params_bind_group,
+            args_pool,
+        }
+    }
+}
+
+impl ReduceMaxPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("reduce_max clear_max_pipeline", self.clear_max_pipeline),
+                ("reduce_max reduce_max_pipeline", self.reduce_max_pipeline),
+                ("reduce_max auto_args_pipeline", self.auto_args_pipeline),
+                (
+                    "reduce_max check_sorted_pipeline",
+                    self.check_sorted_pipeline,
+                ),
+                ("reduce_max sorted_args_pipeline", self.sorted_args_pipeline),
+                ("reduce_max copy_pipeline", self.copy_pipeline),
+                ("reduce_max gpu_args_pipeline", self.gpu_args_pipeline),
+                (
+                    "reduce_max gpu_count_radix_pipeline",
+                    self.gpu_count_radix_pipeline,
+                ),
+                ("reduce_max gpu_scatter_pipeline", self.gpu_scatter_pipeline),
+                ("reduce_max gpu_copy_pipeline", self.gpu_copy_pipeline),
+            ],
+        )
+    }
+
+    /// The maximum of the keys written by the last [`run_auto`], [`MAX_KEY_BUFFER_SIZE`] bytes.
+    pub fn max_key_buffer(&self) -> &Buffer {
+        &self.max_key_buf
+    }
+
+    /// The flag written by the last [`run_unless_sorted`], 1 if the keys weren't sorted and 0 if the passes were
+    /// skipped, [`MAX_KEY_BUFFER_SIZE`] bytes.
+    pub fn unsorted_buffer(&self) -> &Buffer {
+        &self.unsorted_buf
+    }
+
+    /// The [`GpuSortParams`] the last [`run_gpu_driven`] validated and sorted with, [`SORT_PARAMS_SIZE`] bytes.
+    pub fn params_buffer(&self) -> &Buffer {
+        &self.params_buf
+    }
+
+    /// The pool of the indirect args of [`run_auto`], [`run_unless_sorted`] and [`run_gpu_driven`].
+    pub fn indirect_args_pool(&self) -> &IndirectArgsPool {
+        &self.args_pool
+    }
+
+    /// `max_key` must hold at least [`MAX_KEY_BUFFER_SIZE`] bytes.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        keys: &Buffer,
+        max_key: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "reduce_max: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((keys.as_entire_binding(), max_key.as_entire_binding())),
+        )
+    }
+
+    /// Writes the maximum of the first `number_of_keys` keys into `max_key`, 0 if there is no key. The keys are
+    /// transformed by the [`crate::RadixSortPipeline::key_transform`] first.
+    pub fn record_reduce_max(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("reduce_max compute pass"),
+            ..default()
+        });
+
+        self.record_reduction_in_pass(
+            &mut pass,
+            pipeline_cache,
+            self.reduce_max_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+    }
+
+    /// Writes 1 into `max_key` if some of the first `number_of_keys` keys is greater than the next one, 0 if they're
+    /// sorted. The transformed keys are compared, like [`Self::record_reduce_max`].
+    pub fn record_check_sorted(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("reduce_max check_sorted compute pass"),
+            ..default()
+        });
+
+        self.record_reduction_in_pass(
+            &mut pass,
+            pipeline_cache,
+            self.check_sorted_pipeline,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+    }
+
+    /// Clears the result, then folds the keys into it with `reduce_pipeline`.
+    fn record_reduction_in_pass(
+        &self,
+        pass: &mut ComputePass,
+        pipeline_cache: &PipelineCache,
+        reduce_pipeline: CachedComputePipelineId,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        let clear_max_pipeline = pipeline_cache
+            .get_compute_pipeline(self.clear_max_pipeline)
+            .unwrap();
+        let reduce_pipeline = pipeline_cache
+            .get_compute_pipeline(reduce_pipeline)
+            .unwrap();
+
+        // Both pipelines share the same layout so the push constants are kept across `set_pipeline`.
+        pass.set_pipeline(clear_max_pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.dispatch_workgroups(1, 1, 1);
+
+        let number_of_keys_per_workgroup =
+            NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP;
+
+        pass.set_pipeline(reduce_pipeline);
+        dispatch_workgroup_ext(
+            pass,
+            number_of_keys.div_ceil(number_of_keys_per_workgroup),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+/// The slots of the first frame, the buffer doubles whenever a frame needs more.
+const INITIAL_NUMBER_OF_ARGS_SLOTS: u32 = 4;
+
+/// The indirect args of the indirect sorts of a frame ([`run_auto`], [`run_unless_sorted`] and [`run_gpu_driven`]),
+/// each in a slot of one persistent buffer bound with a dynamic offset, along the bind groups of the sorts.
+///
+/// The buffer grows geometrically when a frame runs more sorts than it has slots, or a sort has more dispatches than
+/// a slot holds. The slots are handed out again from the first one after the render graph of each frame, and the
+/// bind groups not used during a frame are dropped with it, so steady-state frames create neither buffers nor bind
+/// groups.
+pub struct IndirectArgsPool {
+    render_queue: RenderQueue,
+    offset_alignment: BufferAddress,
+    state: Mutex<IndirectArgsPoolState>,
+    buffers_created: AtomicU32,
+    bind_groups_created: AtomicU32,
+}
+
+#[derive(Default)]
+struct IndirectArgsPoolState {
+    buffer: Option<Buffer>,
+    /// The size of the bindings, the largest args written so far rounded up to a power of two
+    slot_size: BufferAddress,
+    /// `slot_size` rounded up to `min_storage_buffer_offset_alignment`
+    slot_stride: BufferAddress,
+    number_of_slots: u32,
+    next_slot: u32,
+    bind_groups: Vec<PooledBindGroup>,
+}
+
+/// What a cached bind group binds, the args being the slots of the current buffer of the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PooledBindGroupKey {
+    /// The keys and the result of the reduction
+    Reduce { keys: BufferId, result: BufferId },
+    /// The result of the reduction and the args of [`run_auto`] or [`run_unless_sorted`]
+    Args { result: BufferId },
+    /// The user params and the args of [`run_gpu_driven`]
+    GpuArgs { params: BufferId },
+}
+
+struct PooledBindGroup {
+    key: PooledBindGroupKey,
+    bind_group: BindGroup,
+    used: bool,
+}
+
+/// The args of a sort, `size` bytes at `offset` in `buffer`.
+struct IndirectArgsSlot {
+    buffer: Buffer,
+    offset: u32,
+    size: BufferAddress,
+}
+
+impl IndirectArgsSlot {
+    /// The binding of the first slot, offset by `offset` when the bind group is set.
+    fn binding(&self) -> BindingResource<'_> {
+        BindingResource::Buffer(BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(self.size),
+        })
+    }
+}
+
+impl IndirectArgsPool {
+    fn new(render_device: &RenderDevice, render_queue: RenderQueue) -> Self {
+        Self {
+            render_queue,
+            offset_alignment: render_device.limits().min_storage_buffer_offset_alignment
+                as BufferAddress,
+            state: default(),
+            buffers_created: AtomicU32::new(0),
+            bind_groups_created: AtomicU32::new(0),
+        }
+    }
+
+    /// The number of buffers the pool created since startup, only growing while the frames need more slots.
+    pub fn buffers_created(&self) -> u32 {
+        self.buffers_created.load(Ordering::Relaxed)
+    }
+
+    /// The number of bind groups the pool created since startup, including the ones of the buffers it replaced.
+    pub fn bind_groups_created(&self) -> u32 {
+        self.bind_groups_created.load(Ordering::Relaxed)
+    }
+
+    /// Writes `args` into the next slot of the frame, replacing the buffer by a larger one if it has no slot left
+    /// or its slots are too small. The sorts recorded earlier keep the previous buffer alive.
+    fn write(&self, render_device: &RenderDevice, args: &[u32]) -> IndirectArgsSlot {
+        let contents: &[u8] = bytemuck::cast_slice(args);
+        let size = contents.len() as BufferAddress;
+        let mut state = self.state.lock().unwrap();
+
+        let full = state.next_slot == state.number_of_slots;
+        if full || size > state.slot_size {
+            state.slot_size = state.slot_size.max(size.next_power_of_two());
+            state.slot_stride = state.slot_size.next_multiple_of(self.offset_alignment);
+            if full {
+                state.number_of_slots =
+                    (state.number_of_slots * 2).max(INITIAL_NUMBER_OF_ARGS_SLOTS);
+            }
+            state.next_slot = 0;
+            state.buffer = Some(render_device.create_buffer(&BufferDescriptor {
+                label: Some("reduce_max: indirect args pool buffer"),
+                size: state.slot_stride * state.number_of_slots as BufferAddress,
+                usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+            // They bind the args of the previous buffer
+            state.bind_groups.clear();
+            self.buffers_created.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let buffer = state.buffer.clone().unwrap();
+        let offset = state.next_slot as BufferAddress * state.slot_stride;
+        state.next_slot += 1;
+        self.render_queue.write_buffer(&buffer, offset, contents);
+
+        IndirectArgsSlot {
+            buffer,
+            offset: offset as u32,
+            size: state.slot_size,
+        }
+    }
+
+    /// The cached bind group of `key`, created by `create` on a miss.
+    fn bind_group(&self, key: PooledBindGroupKey, create: impl FnOnce() -> BindGroup) -> BindGroup {
+        let mut state = self.state.lock().unwrap();
+        if let Some(cached) = state
+            .bind_groups
+            .iter_mut()
+            .find(|cached| cached.key == key)
+        {
+            cached.used = true;
+            return cached.bind_group.clone();
+        }
+
+        let bind_group = create();
+        self.bind_groups_created.fetch_add(1, Ordering::Relaxed);
+        state.bind_groups.push(PooledBindGroup {
+            key,
+            bind_group: bind_group.clone(),
+            used: true,
+        });
+        bind_group
+    }
+
+    /// Hands the slots out again from the first one and drops the bind groups unused since the previous frame,
+    /// e.g. the ones of resized global buffers, which they would keep alive.
+    fn next_frame(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.next_slot = 0;
+        state
+            .bind_groups
+            .retain_mut(|cached| std::mem::take(&mut cached.used));
+    }
+}
+
+impl fmt::Debug for IndirectArgsPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndirectArgsPool")
+            .field("buffers_created", &self.buffers_created())
+            .field("bind_groups_created", &self.bind_groups_created())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A dispatch of the sort, as recorded for the CPU count.
+struct CollectedDispatch {
+    /// The pass index, or the tag of a copy
+    tag: u32,
+    workgroups: [u32; 3],
+    /// The number of keys a workgroup covers if the workgroups depend on the count, 0 otherwise
+    keys_per_workgroup: u32,
+    workgroup_offset: u32,
+}
+
+/// Collects the dispatches of the sort, tagged with their pass index, without recording anything.
+#[derive(Default)]
+struct DispatchCollector<'a> {
+    tag: u32,
+    /// The pipelines whose dispatches [`run_gpu_driven`] resizes to the count, with the keys of a workgroup
+    scaled_pipelines: Vec<(&'a wgpu::ComputePipeline, u32)>,
+    keys_per_workgroup: u32,
+    workgroup_offset: u32,
+    dispatches: Vec<CollectedDispatch>,
+}
+
+impl PassRecorder for DispatchCollector<'_> {
+    fn set_pipeline(&mut self, pipeline: &wgpu::ComputePipeline) {
+        self.keys_per_workgroup = self
+            .scaled_pipelines
+            .iter()
+            .find(|(scaled, _)| std::ptr::eq(*scaled, pipeline))
+            .map_or(0, |(_, keys_per_workgroup)| *keys_per_workgroup);
+    }
+
+    fn set_bind_group(&mut self, _bind_group: &wgpu::BindGroup) {}
+
+    fn set_push_constants(&mut self, offset: u32, data: &[u8]) {
+        if offset == WORKGROUP_OFFSET_OFFSET {
+            // The whole params at the start of a step, or the offset alone
+            self.workgroup_offset = bytemuck::pod_read_unaligned(&data[..4]);
+        }
+    }
+
+    fn dispatch_workgroups(&mut self, x: u32, y: u32, z: u32) {
+        self.dispatches.push(CollectedDispatch {
+            tag: self.tag,
+            workgroups: [x, y, z],
+            keys_per_workgroup: self.keys_per_workgroup,
+            workgroup_offset: self.workgroup_offset,
+        });
+    }
+
+    fn begin_sort_pass(&mut self, pass_index: u32) {
+        self.tag = pass_index;
+    }
+}
+
+/// Replaces the dispatches with indirect ones reading the consecutive args of `args`, from `args_offset`.
+struct IndirectRecorder<'a, 'p> {
+    pass: &'a mut ComputePass<'p>,
+    args: &'a Buffer,
+    args_offset: BufferAddress,
+    next_dispatch: BufferAddress,
+    /// Group 1 of the pipelines of [`run_gpu_driven`], set again with each pipeline
+    params_bind_group: Option<&'a BindGroup>,
+}
+
+impl PassRecorder for IndirectRecorder<'_, '_> {
+    fn set_pipeline(&mut self, pipeline: &wgpu::ComputePipeline) {
+        self.pass.set_pipeline(pipeline);
+        if let Some(params_bind_group) = self.params_bind_group {
+            self.pass.set_bind_group(1, params_bind_group, &[]);
+        }
+    }
+
+    fn set_bind_group(&mut self, bind_group: &wgpu::BindGroup) {
+        self.pass.set_bind_group(0, bind_group, &[]);
+    }
+
+    fn set_push_constants(&mut self, offset: u32, data: &[u8]) {
+        self.pass.set_push_constants(offset, data);
+    }
+
+    fn dispatch_workgroups(&mut self, _x: u32, _y: u32, _z: u32) {
+        self.pass.dispatch_workgroups_indirect(
+            self.args,
+            self.args_offset + self.next_dispatch * DISPATCH_ARGS_SIZE,
+        );
+        self.next_dispatch += 1;
+    }
+}
+
+/// Records the dispatches shared by the collection and the recording of [`run_auto`]:
+/// the sort passes, then the copy of the result from either side of the global buffers, if any.
+#[allow(clippy::too_many_arguments)]
+fn record_auto_dispatches<R: PassRecorder>(
+    pass: &mut R,
+    sort_pipelines: &SortPipelines,
+    copy_pipeline: Option<&wgpu::ComputePipeline>,
+    radix_bind_group: &RadixSortBindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    record_sort_passes(
+        pass,
+        sort_pipelines,
+        radix_bind_group.eve_bind_group(),
+        radix_bind_group.odd_bind_group(),
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range,
+        init_index,
+        read_from_even,
+    );
+
+    let Some(copy_pipeline) = copy_pipeline else {
+        return;
+    };
+
+    pass.set_pipeline(copy_pipeline);
+    for (tag, bind_group) in [
+        (COPY_FROM_EVEN_TAG, radix_bind_group.eve_bind_group()),
+        (COPY_FROM_ODD_TAG, radix_bind_group.odd_bind_group()),
+    ] {
+        pass.begin_sort_pass(tag);
+        pass.set_bind_group(bind_group);
+        dispatch_workgroup_ext_with(
+            pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+/// Like [`crate::run`], but the passes above the highest non-zero digit of the maximum key are skipped on the GPU,
+/// without a readback. The first pass of `pass_range` always runs, so `init_index` behaves as with [`crate::run`].
+///
+/// The result is left in the same global buffers as [`crate::run`] over `pass_range` (see [`crate::is_output_even`]),
+/// the maximum key is kept in [`ReduceMaxPipeline::max_key_buffer`].
+#[allow(clippy::too_many_arguments)]
+pub fn run_auto(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    reduce_max_pipeline: &ReduceMaxPipeline,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_auto", number_of_keys).entered();
+
+    let keys = global_keys_buffer(sbufs, read_from_even).unwrap();
+    run_indirect(
+        encoder,
+        render_device,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        reduce_max_pipeline,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range,
+        init_index,
+        read_from_even,
+        SkipCondition::HighDigits { keys },
+    );
+}
+
+/// Like [`crate::run`], but every pass is skipped on the GPU if the keys are already sorted, without a readback.
+/// The check reads the keys once, much cheaper than the count, scan and scatter steps of the passes.
+///
+/// With `init_index` the first pass of `pass_range` still runs on sorted keys, it's the one writing the indices
+/// into the vals.
+///
+/// With `normalize_output` the result is left in the same global buffers as [`crate::run`] over `pass_range`
+/// (see [`crate::is_output_even`]), sorted keys with an odd number of skipped passes are copied there. Without it
+/// sorted keys stay where the passes actually run left them, the input buffers unless `init_index`,
+/// [`ReduceMaxPipeline::unsorted_buffer`] tells the cases apart on the GPU.
+#[allow(clippy::too_many_arguments)]
+pub fn run_unless_sorted(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    reduce_max_pipeline: &ReduceMaxPipeline,
+    sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+    normalize_output: bool,
+) {
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_unless_sorted", number_of_keys).entered();
+
+    let keys = global_keys_buffer(sbufs, read_from_even).unwrap();
+    run_indirect(
+        encoder,
+        render_device,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        reduce_max_pipeline,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range,
+        init_index,
+        read_from_even,
+        SkipCondition::Sorted {
+            keys,
+            normalize_output,
+        },
+    );
+}
+
+/// Like [`crate::run`], but the number of keys and the passes are read on the GPU from `params`, a
+/// [`GpuSortParams`] written by an earlier pass of the same encoder, e.g. a culling shader. Nothing is read back.
+///
+/// The sort is recorded for `max_number_of_keys`, at most the capacity of the global buffers: a first dispatch
+/// clamps the parameters into [`ReduceMaxPipeline::params_buffer`], which the count and scatter steps read the
+/// count from, and turns them into the indirect args of the sort. The scatter steps only dispatch the workgroups
+/// of the count, the passes above the highest pass of `pass_mask` get 0 workgroups, see [`run_auto`].
+///
+/// The result is left in the same global buffers as [`crate::run`] over `pass_range` (see
+/// [`crate::is_output_even`]), the first pass initializes the vals with the indices if `flags` has
+/// [`SORT_PARAMS_INIT_INDEX`]. `params` needs the `STORAGE` usage and [`SORT_PARAMS_SIZE`] bytes.
+#[allow(clippy::too_many_arguments)]
+pub fn run_gpu_driven(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    reduce_max_pipeline: &ReduceMaxPipeline,
+    max_compute_workgroups_per_dimension: u32,
+    max_number_of_keys: u32,
+    pass_range: Range<u32>,
+    read_from_even: bool,
+    params: &Buffer,
+) {
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_gpu_driven", max_number_of_keys).entered();
+
+    if params.size() < SORT_PARAMS_SIZE {
+        error!(
+            "radix_sort: the params buffer of run_gpu_driven holds {} bytes, less than the {SORT_PARAMS_SIZE} bytes of GpuSortParams",
+            params.size()
+        );
+        return;
+    }
+
+    // The first pass is recorded with the indices, the flags of the params turn them off on the GPU
+    run_indirect(
+        encoder,
+        render_device,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        reduce_max_pipeline,
+        max_compute_workgroups_per_dimension,
+        max_number_of_keys,
+        pass_range,
+        radix_sort_pipeline.allocate_values(),
+        read_from_even,
+        SkipCondition::GpuDriven { params },
+    );
+}
+
+/// The passes an indirect sort skips.
+#[derive(Debug, Clone, Copy)]
+enum SkipCondition<'a> {
+    /// The passes above the highest non-zero digit of the maximum of `keys`, see [`run_auto`].
+    HighDigits { keys: &'a Buffer },
+    /// Every pass if `keys` are sorted, see [`run_unless_sorted`].
+    Sorted {
+        keys: &'a Buffer,
+        normalize_output: bool,
+    },
+    /// The passes and the workgroups past the [`GpuSortParams`] of `params`, see [`run_gpu_driven`].
+    GpuDriven { params: &'a Buffer },
+}
+
+/// Turns the skip condition into the indirect args of the sort and records the sort, after reducing the keys unless
+/// the sort is GPU-driven.
+#[allow(clippy::too_many_arguments)]
+fn run_indirect(
+    encoder: &mut CommandEncoder,
+    render_device: &RenderDevice,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    reduce_max_pipeline: &ReduceMaxPipeline,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+    skip_condition: SkipCondition,
+) {
+    let debug_info = RadixSortDebugInfo::new(
+        radix_sort_pipeline,
+        Algorithm::Radix,
+        number_of_keys,
+        &pass_range,
+        init_index,
+        read_from_even,
+    );
+
+    if init_index && !radix_sort_pipeline.allocate_values() {
+        error!(
+            "radix_sort: an indirect sort with init_index requires the vals buffers (RadixSortSettings::without_values)"
+        );
+        radix_sort_pipeline
+            .debug_info()
+            .record(debug_info.with_early_out(RadixSortEarlyOut::InvalidArguments));
+        return;
+    }
+
+    if radix_sort_pipeline.key_type() == KeyType::U64 {
+        error!(
+            "radix_sort: the indirect sorts reduce u32 keys, sort the KeyType::U64 keys with run"
+        );
+        radix_sort_pipeline
+            .debug_info()
+            .record(debug_info.with_early_out(RadixSortEarlyOut::InvalidArguments));
+        return;
+    }
+
+    if number_of_keys < 2 || pass_range.is_empty() {
+        radix_sort_pipeline
+            .debug_info()
+            .record(debug_info.with_early_out(RadixSortEarlyOut::Empty));
+        return;
+    }
+
+    radix_sort_pipeline.counters().record(number_of_keys);
+
+    // The reduction, the keys it reads and its result bound to the args pipeline, none for a GPU-driven sort
+    let (reduction, args_pipeline, copy_back) = match skip_condition {
+        SkipCondition::HighDigits { keys } => (
+            Some((
+                reduce_max_pipeline.reduce_max_pipeline,
+                keys,
+                &reduce_max_pipeline.max_key_buf,
+            )),
+            reduce_max_pipeline.auto_args_pipeline,
+            true,
+        ),
+        SkipCondition::Sorted {
+            keys,
+            normalize_output,
+        } => (
+            Some((
+                reduce_max_pipeline.check_sorted_pipeline,
+                keys,
+                &reduce_max_pipeline.unsorted_buf,
+            )),
+            reduce_max_pipeline.sorted_args_pipeline,
+            normalize_output,
+        ),
+        SkipCondition::GpuDriven { .. } => (None, reduce_max_pipeline.gpu_args_pipeline, true),
+    };
+    let gpu_driven = reduction.is_none();
+
+    let get = move |id| &**pipeline_cache.get_compute_pipeline(id).unwrap();
+    let mut sort_pipelines = SortPipelines::new(pipeline_cache, radix_sort_pipeline);
+    if gpu_driven {
+        sort_pipelines.count_radix_pipeline = get(reduce_max_pipeline.gpu_count_radix_pipeline);
+        sort_pipelines.scatter_pipeline = get(reduce_max_pipeline.gpu_scatter_pipeline);
+    }
+    let args_pipeline = get(args_pipeline);
+    let copy_pipeline = copy_back.then(|| {
+        get(if gpu_driven {
+            reduce_max_pipeline.gpu_copy_pipeline
+        } else {
+            reduce_max_pipeline.copy_pipeline
+        })
+    });
+
+    // The full sequence of dispatches, then the args of the skipped ones are zeroed on the GPU
+    let mut collector = DispatchCollector::default();
+    if gpu_driven {
+        // The count step still clears the histograms of every block the scan reads
+        collector
+            .scaled_pipelines
+            .push((sort_pipelines.scatter_pipeline, tile_size()));
+        collector.scaled_pipelines.extend(
+            copy_pipeline.map(|copy_pipeline| (copy_pipeline, NUMBER_OF_THREADS_PER_WORKGROUP)),
+        );
+    }
+    let mut recorder = DebugPassRecorder::new(
+        &mut collector,
+        radix_stages(&sort_pipelines)
+            .into_iter()
+            .chain(copy_pipeline.map(|copy_pipeline| (copy_pipeline, "copy_pipeline"))),
+    );
+    record_auto_dispatches(
+        &mut recorder,
+        &sort_pipelines,
+        copy_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range.clone(),
+        init_index,
+        read_from_even,
+    );
+    let early_out = match skip_condition {
+        SkipCondition::HighDigits { .. } => RadixSortEarlyOut::HighDigits,
+        SkipCondition::Sorted { .. } => RadixSortEarlyOut::IfSorted,
+        SkipCondition::GpuDriven { .. } => RadixSortEarlyOut::GpuDriven,
+    };
+    radix_sort_pipeline.debug_info().record(
+        debug_info
+            .with_early_out(early_out)
+            .with_stages(recorder.finish()),
+    );
+
+    let dispatches = &collector.dispatches;
+    let number_of_dispatches = dispatches.len() as u32;
+    let mut args: Vec<u32> = dispatches
+        .iter()
+        .flat_map(|dispatch| dispatch.workgroups)
+        .chain(dispatches.iter().map(|dispatch| dispatch.tag))
+        .collect();
+    if gpu_driven {
+        args.extend(
+            dispatches
+                .iter()
+                .map(|dispatch| dispatch.keys_per_workgroup),
+        );
+        args.extend(dispatches.iter().map(|dispatch| dispatch.workgroup_offset));
+    }
+
+    let args_pool = &reduce_max_pipeline.args_pool;
+    let args_slot = args_pool.write(render_device, &args);
+    let args_bind_group = match (reduction, skip_condition) {
+        (Some((_, _, result_buf)), _) => args_pool.bind_group(
+            PooledBindGroupKey::Args {
+                result: result_buf.id(),
+            },
+            || {
+                render_device.create_bind_group(
+                    "reduce_max: auto_args bind_group",
+                    &reduce_max_pipeline.auto_args_bind_group_layout,
+                    &BindGroupEntries::sequential((
+                        result_buf.as_entire_binding(),
+                        args_slot.binding(),
+                    )),
+                )
+            },
+        ),
+        (None, SkipCondition::GpuDriven { params }) => args_pool.bind_group(
+            PooledBindGroupKey::GpuArgs {
+                params: params.id(),
+            },
+            || {
+                render_device.create_bind_group(
+                    "reduce_max: gpu_args bind_group",
+                    &reduce_max_pipeline.gpu_args_bind_group_layout,
+                    &BindGroupEntries::sequential((
+                        params.as_entire_binding(),
+                        args_slot.binding(),
+                        reduce_max_pipeline.params_buf.as_entire_binding(),
+                    )),
+                )
+            },
+        ),
+        (None, _) => unreachable!("only the GPU-driven sort has no reduction"),
+    };
+    let params_bind_group = gpu_driven.then_some(&reduce_max_pipeline.params_bind_group);
+
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("radix_sort auto compute pass"),
+        ..default()
+    });
+
+    if let Some((reduce_pipeline, keys, result_buf)) = reduction {
+        let reduce_bind_group = args_pool.bind_group(
+            PooledBindGroupKey::Reduce {
+                keys: keys.id(),
+                result: result_buf.id(),
+            },
+            || reduce_max_pipeline.create_bind_group(render_device, keys, result_buf),
+        );
+        reduce_max_pipeline.record_reduction_in_pass(
+            &mut pass,
+            pipeline_cache,
+            reduce_pipeline,
+            &reduce_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+    }
+
+    pass.set_pipeline(args_pipeline);
+    pass.set_bind_group(0, &args_bind_group, &[args_slot.offset]);
+    pass.set_push_constants(
+        NUMBER_OF_DISPATCHES_OFFSET,
+        bytemuck::bytes_of(&number_of_dispatches),
+    );
+    pass.set_push_constants(PASS_START_OFFSET, bytemuck::bytes_of(&pass_range.start));
+    pass.set_push_constants(PASS_END_OFFSET, bytemuck::bytes_of(&pass_range.end));
+    pass.set_push_constants(
+        READ_FROM_EVEN_OFFSET,
+        bytemuck::bytes_of(&(read_from_even as u32)),
+    );
+    match skip_condition {
+        SkipCondition::Sorted { .. } => {
+            pass.set_push_constants(INIT_INDEX_OFFSET, bytemuck::bytes_of(&(init_index as u32)));
+        }
+        SkipCondition::GpuDriven { .. } => {
+            let flags_mask = if init_index {
+                SORT_PARAMS_INIT_INDEX
+            } else {
+                0
+            };
+            pass.set_push_constants(
+                MAX_NUMBER_OF_KEYS_OFFSET,
+                bytemuck::bytes_of(&number_of_keys),
+            );
+            pass.set_push_constants(FLAGS_MASK_OFFSET, bytemuck::bytes_of(&flags_mask));
+        }
+        SkipCondition::HighDigits { .. } => {}
+    }
+    pass.dispatch_workgroups(
+        number_of_dispatches.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+        1,
+        1,
+    );
+
+    let mut recorder = IndirectRecorder {
+        pass: &mut pass,
+        args: &args_slot.buffer,
+        args_offset: args_slot.offset as BufferAddress,
+        next_dispatch: 0,
+        params_bind_group,
+    };
+    record_auto_dispatches(
+        &mut recorder,
+        &sort_pipelines,
+        copy_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+        pass_range,
+        init_index,
+        read_from_even,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::CommandEncoderDescriptor;
+
+    use crate::{
+        GetSubgroupSizePlugin, NUMBER_OF_RADIX, NUMBER_OF_RADIX_BITS, RadixSortPlugin,
+        sorted_keys_buffer, sorted_vals_buffer,
+        test_utils::{
+            TestShaderPipeline, TestShaderPlugin, create_render_test_app, create_storage_buffer,
+            random_keys, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    fn create_test_app(max_number_of_keys: u32) -> App {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: max_number_of_keys.into(),
+            })
+            .add_plugins(ReduceMaxPlugin);
+
+        app
+    }
+
+    fn run_reduce_max_test(keys: Vec<u32>) {
+        let mut app = create_test_app(keys.len().max(1) as u32);
+
+        let expected = keys.iter().copied().max().unwrap_or(0);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  reduce_max_pipeline: Res<ReduceMaxPipeline>| {
+                // Never empty, a zero-sized binding is invalid
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[keys.as_slice(), &[u32::MAX]].concat());
+                let max_key_buf = create_storage_buffer(&render_device, &[0xDEADBEEF]);
+                let bind_group =
+                    reduce_max_pipeline.create_bind_group(&render_device, &keys_buf, &max_key_buf);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: reduce_max command encoder"),
+                });
+                reduce_max_pipeline.record_reduce_max(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    keys.len() as u32,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let max_key = read_buffer(&render_device, &render_queue, &max_key_buf, 1);
+                assert_eq!(max_key, [expected]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_reduce_max_small() {
+        run_reduce_max_test(vec![]);
+        run_reduce_max_test(vec![42]);
+        run_reduce_max_test(random_keys(116, 255, 1_000));
+    }
+
+    #[test]
+    fn test_reduce_max_multiple_levels() {
+        // Many workgroups, counts not a power of two, the maximum in the last partial block
+        let mut keys = random_keys(116, 1_000_003, 1 << 20);
+        *keys.last_mut().unwrap() = 1 << 24;
+        run_reduce_max_test(keys);
+        run_reduce_max_test(random_keys(116, 3_000_017, u32::MAX));
+    }
+
+    fn run_auto_test(keys: Vec<u32>, read_from_even: bool) {
+        let number_of_keys = keys.len() as u32;
+        let mut app = create_test_app(number_of_keys);
+
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  reduce_max_pipeline: Res<ReduceMaxPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: run_auto command encoder"),
+                });
+                run_auto(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &reduce_max_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    read_from_even,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), read_from_even).unwrap();
+                let output_vals_buf = sorted_vals_buffer(&sbufs, &(0..4), read_from_even).unwrap();
+                let output_keys = read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                let output_vals = read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                assert_eq!(output_keys, expected_keys);
+                assert_eq!(output_vals, expected_vals);
+
+                let max_key = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    reduce_max_pipeline.max_key_buffer(),
+                    1,
+                );
+                assert_eq!(max_key, [*expected_keys.last().unwrap()]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_auto_one_pass() {
+        // 3 skipped passes, the result is copied to the side of a full sort
+        run_auto_test(random_keys(116, 100_000, 256), true);
+        run_auto_test(random_keys(116, 100_000, 256), false);
+    }
+
+    #[test]
+    fn test_run_auto_two_passes() {
+        run_auto_test(random_keys(116, 100_000, 1 << 16), true);
+    }
+
+    #[test]
+    fn test_run_auto_all_passes() {
+        run_auto_test(random_keys(116, 100_000, u32::MAX), false);
+        // All keys equal to 0, only the first pass runs
+        run_auto_test(vec![0; 5_000], true);
+    }
+
+    fn run_check_sorted_test(keys: Vec<u32>) {
+        let mut app = create_test_app(keys.len().max(1) as u32);
+
+        let expected = !keys.is_sorted() as u32;
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  reduce_max_pipeline: Res<ReduceMaxPipeline>| {
+                // The key past the end is never compared
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[keys.as_slice(), &[0]].concat());
+                let unsorted_buf = create_storage_buffer(&render_device, &[0xDEADBEEF]);
+                let bind_group =
+                    reduce_max_pipeline.create_bind_group(&render_device, &keys_buf, &unsorted_buf);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: check_sorted command encoder"),
+                });
+                reduce_max_pipeline.record_check_sorted(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    keys.len() as u32,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let unsorted = read_buffer(&render_device, &render_queue, &unsorted_buf, 1);
+                assert_eq!(unsorted, [expected]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_check_sorted() {
+        let number_of_keys_per_workgroup =
+            NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_ROWS_PER_WORKGROUP;
+
+        run_check_sorted_test(vec![]);
+        run_check_sorted_test(vec![42]);
+        run_check_sorted_test((0..1_000_003).collect());
+        run_check_sorted_test(vec![7; 100_000]);
+        run_check_sorted_test(random_keys(116, 100_000, 1_000));
+
+        // A single inversion at the very end
+        let mut keys: Vec<u32> = (0..1_000_003).collect();
+        keys.swap(1_000_001, 1_000_002);
+        run_check_sorted_test(keys);
+
+        // A single inversion across the boundary of two workgroups
+        let mut keys: Vec<u32> = (0..3 * number_of_keys_per_workgroup).collect();
+        keys.swap(
+            number_of_keys_per_workgroup as usize - 1,
+            number_of_keys_per_workgroup as usize,
+        );
+        run_check_sorted_test(keys);
+    }
+
+    fn run_unless_sorted_test(keys: Vec<u32>, read_from_even: bool, normalize_output: bool) {
+        let number_of_keys = keys.len() as u32;
+        let mut app = create_test_app(number_of_keys);
+
+        let unsorted = !keys.is_sorted();
+        let mut expected = keys.clone();
+        expected.sort();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  reduce_max_pipeline: Res<ReduceMaxPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: run_unless_sorted command encoder"),
+                });
+                run_unless_sorted(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &reduce_max_pipeline,
+                    &sbufs,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    normalize_output,
+                    read_from_even,
+                    normalize_output,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_keys as usize;
+                let output_keys_buf = if normalize_output || unsorted {
+                    sorted_keys_buffer(&sbufs, &(0..4), read_from_even).unwrap()
+                } else {
+                    input_keys_buf
+                };
+                let output_keys = read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                assert_eq!(output_keys, expected);
+
+                // Sorted keys keep their indices
+                if normalize_output && !unsorted {
+                    let output_vals_buf =
+                        sorted_vals_buffer(&sbufs, &(0..4), read_from_even).unwrap();
+                    let output_vals =
+                        read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                    assert_eq!(output_vals, (0..number_of_keys).collect::<Vec<_>>());
+                }
+
+                let unsorted_flag = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    reduce_max_pipeline.unsorted_buffer(),
+                    1,
+                );
+                assert_eq!(unsorted_flag, [unsorted as u32]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_unless_sorted_skipped() {
+        let keys: Vec<u32> = (0..100_000).map(|i| i * 3).collect();
+        run_unless_sorted_test(keys.clone(), true, true);
+        run_unless_sorted_test(keys.clone(), false, true);
+        // Left in the input buffers
+        run_unless_sorted_test(keys, true, false);
+    }
+
+    #[test]
+    fn test_run_unless_sorted_not_skipped() {
+        // A single inversion at the very end runs all passes
+        let mut keys: Vec<u32> = (0..100_000).map(|i| i * 3).collect();
+        keys.swap(99_998, 99_999);
+        run_unless_sorted_test(keys.clone(), true, true);
+        run_unless_sorted_test(keys, false, false);
+        run_unless_sorted_test(random_keys(116, 100_000, u32::MAX), true, true);
+    }
+
+    /// A culling shader writing the [`GpuSortParams`] of the keys it kept, the culled keys are `u32::MAX`.
+    const PARAMS_SHADER: &str = r"
+#import bevy_radix_sort::keys
+
+@group(0) @binding(0) var<storage, read      > inputs: array<u32>;
+// The fields of a `keys::SortParams`, accumulated with atomics
+@group(0) @binding(1) var<storage, read_write> outputs: array<atomic<u32>>;
+
+@compute @workgroup_size(keys::NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3u) {
+    let index = global_invocation_id.x;
+    if index == 0u {
+        atomicStore(&outputs[2], keys::SORT_PARAMS_INIT_INDEX);
+    }
+    if index >= arrayLength(&inputs) || inputs[index] == 0xFFFFFFFFu {
+        return;
+    }
+
+    var pass_mask = 0u;
+    for (var pass_index = 0u; pass_index < keys::NUMBER_OF_PASSES; pass_index++) {
+        if keys::extract_digit(inputs[index], pass_index) != 0u {
+            pass_mask |= 1u << pass_index;
+        }
+    }
+    atomicAdd(&outputs[0], 1u);
+    atomicOr(&outputs[1], pass_mask);
+}
+";
+
+    /// Sorts the `valid_keys` followed by culled keys up to `capacity`, with a sort recorded for
+    /// `max_number_of_keys` and the parameters written by the [`PARAMS_SHADER`] in the same encoder.
+    fn run_gpu_driven_test(valid_keys: Vec<u32>, capacity: u32, max_number_of_keys: u32) {
+        let mut app = create_test_app(capacity);
+        app.add_plugins(TestShaderPlugin(PARAMS_SHADER));
+
+        let mut keys = valid_keys.clone();
+        keys.resize(capacity as usize, u32::MAX);
+
+        let count = valid_keys.len().min(max_number_of_keys as usize);
+        let mut expected: Vec<(u32, u32)> = keys[..count].iter().copied().zip(0..).collect();
+        expected.sort_by_key(|&(key, _)| key);
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+        let pass_mask = valid_keys.iter().fold(0, |pass_mask, &key| {
+            (0..4).fold(pass_mask, |pass_mask, pass_index| {
+                let digit = (key >> (pass_index * NUMBER_OF_RADIX_BITS)) & (NUMBER_OF_RADIX - 1);
+                pass_mask | (((digit != 0) as u32) << pass_index)
+            })
+        });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  reduce_max_pipeline: Res<ReduceMaxPipeline>,
+                  test_shader_pipeline: Res<TestShaderPipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+                let params_buf = create_storage_buffer(&render_device, &[0; 4]);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: run_gpu_driven command encoder"),
+                });
+                test_shader_pipeline.record(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    input_keys_buf,
+                    &params_buf,
+                    capacity.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                );
+                run_gpu_driven(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    &reduce_max_pipeline,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    max_number_of_keys,
+                    0..4,
+                    true,
+                    &params_buf,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let params = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    reduce_max_pipeline.params_buffer(),
+                    4,
+                );
+                assert_eq!(
+                    params,
+                    [count as u32, pass_mask, SORT_PARAMS_INIT_INDEX, 0],
+                    "validated params"
+                );
+
+                // The keys past the count are left as is
+                let output_keys_buf = sorted_keys_buffer(&sbufs, &(0..4), true).unwrap();
+                let output_vals_buf = sorted_vals_buffer(&sbufs, &(0..4), true).unwrap();
+                let output_keys = read_buffer(
+                    &render_device,
+                    &render_queue,
+                    output_keys_buf,
+                    capacity as usize,
+                );
+                let output_vals =
+                    read_buffer(&render_device, &render_queue, output_vals_buf, count);
+                assert_eq!(output_keys[..count], expected_keys);
+                assert_eq!(output_keys[count..], keys[count..]);
+                assert_eq!(output_vals, expected_vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_run_gpu_driven() {
+        // A single pass, copied to the side of a full sort
+        run_gpu_driven_test(random_keys(116, 3_000, 256), 10_000, 10_000);
+        // Two passes over many workgroups, a count not a multiple of the tile size
+        run_gpu_driven_test(random_keys(116, 50_017, 1 << 16), 100_000, 100_000);
+        run_gpu_driven_test(random_keys(116, 70_001, u32::MAX - 1), 100_000, 100_000);
+    }
+
+    #[test]
+    fn test_run_gpu_driven_clamped() {
+        // More keys than the sort is recorded for
+        run_gpu_driven_test(random_keys(116, 5_000, u32::MAX - 1), 8_000, 4_000);
+        // Every key culled
+        run_gpu_driven_test(Vec::new(), 1_000, 1_000);
+    }
+
+    /// The keys of the global buffers sorted by `sort_every_frame`.
+    const POOL_TEST_KEYS: u32 = 4096;
+
+    /// The frames recorded by `sort_every_frame`.
+    #[derive(Resource, Default)]
+    struct SortedFrames(u32);
+
+    /// Records the three indirect sorts of the global buffers, the keys left sorted by the previous frame.
+    #[allow(clippy::too_many_arguments)]
+    fn sort_every_frame(
+        render_device: Res<RenderDevice>,
+        render_queue: Res<RenderQueue>,
+        pipeline_cache: Res<PipelineCache>,
+        radix_sort_pipeline: Res<RadixSortPipeline>,
+        radix_bind_group: Res<RadixSortBindGroup>,
+        reduce_max_pipeline: Res<ReduceMaxPipeline>,
+        sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+        mut frames: ResMut<SortedFrames>,
+        mut params_buf: Local<Option<Buffer>>,
+    ) {
+        let number_of_keys = POOL_TEST_KEYS;
+        let max_compute_workgroups_per_dimension =
+            render_device.limits().max_compute_workgroups_per_dimension;
+        let params_buf = params_buf.get_or_insert_with(|| {
+            create_storage_buffer(
+                &render_device,
+                bytemuck::cast_slice(&[GpuSortParams {
+                    count: number_of_keys,
+                    pass_mask: 0xF,
+                    ..default()
+                }]),
+            )
+        });
+
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("unit_test: indirect_args_pool command encoder"),
+        });
+        run_auto(
+            &mut encoder,
+            &render_device,
+            &pipeline_cache,
+            &radix_sort_pipeline,
+            &radix_bind_group,
+            &reduce_max_pipeline,
+            &sbufs,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            0..4,
+            true,
+            true,
+        );
+        run_unless_sorted(
+            &mut encoder,
+            &render_device,
+            &pipeline_cache,
+            &radix_sort_pipeline,
+            &radix_bind_group,
+            &reduce_max_pipeline,
+            &sbufs,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            0..4,
+            false,
+            true,
+            true,
+        );
+        run_gpu_driven(
+            &mut encoder,
+            &render_device,
+            &pipeline_cache,
+            &radix_sort_pipeline,
+            &radix_bind_group,
+            &reduce_max_pipeline,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            0..4,
+            true,
+            params_buf,
+        );
+        render_queue.submit([encoder.finish()]);
+
+        frames.0 += 1;
+    }
+
+    #[test]
+    fn test_indirect_args_pool_steady_state() {
+        const WARM_UP_FRAMES: u32 = 10;
+        const FRAMES: u32 = 1000;
+
+        let mut app = create_test_app(POOL_TEST_KEYS);
+        app.sub_app_mut(RenderApp)
+            .init_resource::<SortedFrames>()
+            .add_systems(Render, sort_every_frame.in_set(RenderSet::Cleanup));
+
+        let keys = random_keys(116, POOL_TEST_KEYS, u32::MAX);
+        run_render_system_once(
+            &mut app,
+            (move |render_queue: Res<RenderQueue>,
+                   sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+            })
+            .before(sort_every_frame),
+        );
+        for _ in 1..WARM_UP_FRAMES {
+            app.update();
+        }
+
+        let created = |app: &App| {
+            let pool = app
+                .sub_app(RenderApp)
+                .world()
+                .resource::<ReduceMaxPipeline>()
+                .indirect_args_pool();
+            (pool.buffers_created(), pool.bind_groups_created())
+        };
+        let warmed_up = created(&app);
+        assert!(warmed_up.0 > 0 && warmed_up.1 > 0, "{warmed_up:?}");
+
+        for _ in 0..FRAMES {
+            app.update();
+        }
+
+        let render_world = app.sub_app(RenderApp).world();
+        assert_eq!(
+            render_world.resource::<SortedFrames>().0,
+            WARM_UP_FRAMES + FRAMES
+        );
+        // The pool's own slots, `tests/indirect_args_pool.rs` counts every buffer and bind group of the sorts
+        assert_eq!(created(&app), warmed_up);
+
+        // The pooled args still sort
+        let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let output_keys = read_buffer(
+            render_world.resource::<RenderDevice>(),
+            render_world.resource::<RenderQueue>(),
+            global_keys_buffer(sbufs, true).unwrap(),
+            POOL_TEST_KEYS as usize,
+        );
+        let mut expected = random_keys(116, POOL_TEST_KEYS, u32::MAX);
+        expected.sort();
+        assert_eq!(output_keys, expected);
+    }
+}
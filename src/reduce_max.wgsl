@@ -0,0 +1,220 @@
+#import bevy_radix_sort::keys
+#import bevy_radix_sort::key_transform::transform_key
+
+#ifdef REDUCE_MAX_BINDINGS
+/// Read the keys from this buffer
+@group(0) @binding(0) var<storage, read      > keys: array<u32>;
+/// The maximum of the keys, or with `CHECK_SORTED_PIPELINE` 1 if some key is greater than the next one
+@group(0) @binding(1) var<storage, read_write> max_key: atomic<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys to reduce.
+    number_of_keys: u32,
+}
+var<push_constant> pc: PushConstants;
+#endif // REDUCE_MAX_BINDINGS
+
+#ifdef CLEAR_MAX_PIPELINE
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    atomicStore(&max_key, 0u);
+}
+#endif // CLEAR_MAX_PIPELINE
+
+#ifdef REDUCE_MAX_PIPELINE
+var<workgroup> local_max: atomic<u32>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+
+    if local_invocation_id.x == 0u {
+        atomicStore(&local_max, 0u);
+    }
+    workgroupBarrier();
+
+    // Each workgroup reduces `NUMBER_OF_ROWS_PER_WORKGROUP` rows of keys
+    let start_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u * #{NUMBER_OF_ROWS_PER_WORKGROUP}u + local_invocation_id.x;
+    let close_index = min(start_index + #{NUMBER_OF_THREADS_PER_WORKGROUP}u * #{NUMBER_OF_ROWS_PER_WORKGROUP}u, pc.number_of_keys);
+    var thread_max = 0u;
+    for (var key_index = start_index; key_index < close_index; key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u) {
+        thread_max = max(thread_max, transform_key(keys[key_index]));
+    }
+    atomicMax(&local_max, thread_max);
+    workgroupBarrier();
+
+    if local_invocation_id.x == 0u {
+        atomicMax(&max_key, atomicLoad(&local_max));
+    }
+}
+#endif // REDUCE_MAX_PIPELINE
+
+#ifdef CHECK_SORTED_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+
+    // Same blocks as `REDUCE_MAX_PIPELINE`, each key is compared with the next one, which may be in the next block
+    let start_index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u * #{NUMBER_OF_ROWS_PER_WORKGROUP}u + local_invocation_id.x;
+    let close_index = min(start_index + #{NUMBER_OF_THREADS_PER_WORKGROUP}u * #{NUMBER_OF_ROWS_PER_WORKGROUP}u, max(pc.number_of_keys, 1u) - 1u);
+    var thread_unsorted = false;
+    for (var key_index = start_index; key_index < close_index; key_index += #{NUMBER_OF_THREADS_PER_WORKGROUP}u) {
+        thread_unsorted = thread_unsorted || transform_key(keys[key_index]) > transform_key(keys[key_index + 1u]);
+    }
+
+    // Most keys are sorted, only the threads finding an inversion touch the flag
+    if thread_unsorted {
+        atomicMax(&max_key, 1u);
+    }
+}
+#endif // CHECK_SORTED_PIPELINE
+
+#ifdef AUTO_ARGS_PIPELINE
+#ifdef SORTED_ARGS
+/// 1 if some key is greater than the next one, written by `CHECK_SORTED_PIPELINE`
+@group(0) @binding(0) var<storage, read      > unsorted: u32;
+#else
+#ifdef GPU_DRIVEN_ARGS
+/// The parameters written by the user
+@group(0) @binding(0) var<storage, read      > user_params: keys::SortParams;
+#else
+/// The maximum of the keys, written by `REDUCE_MAX_PIPELINE`
+@group(0) @binding(0) var<storage, read      > max_key: u32;
+#endif // GPU_DRIVEN_ARGS
+#endif // SORTED_ARGS
+/// `[x, y, z]` of every dispatch of the sort, followed by the tag of every dispatch, and with `GPU_DRIVEN_ARGS`
+/// the number of keys a workgroup of every dispatch covers (0 if it doesn't depend on the count) and its
+/// `workgroup_offset`
+@group(0) @binding(1) var<storage, read_write> args: array<u32>;
+#ifdef GPU_DRIVEN_ARGS
+/// The validated parameters the sort passes read
+@group(0) @binding(2) var<storage, read_write> params: keys::SortParams;
+#endif // GPU_DRIVEN_ARGS
+
+struct PushConstants {
+    /// The number of dispatches of the sort.
+    number_of_dispatches: u32,
+    /// The range of passes of the sort.
+    pass_start: u32,
+    pass_end: u32,
+    read_from_even: u32,
+#ifdef SORTED_ARGS
+    /// Whether the first pass initializes the vals with the indices.
+    init_index: u32,
+#endif // SORTED_ARGS
+#ifdef GPU_DRIVEN_ARGS
+    /// The capacity the sort is recorded for, the upper bound of the count.
+    max_number_of_keys: u32,
+    /// The `SORT_PARAMS_*` flags the sort supports.
+    flags_mask: u32,
+#endif // GPU_DRIVEN_ARGS
+}
+var<push_constant> pc: PushConstants;
+
+/// The tag of the copy from the `EVE_*` to the `ODD_*` global buffers, the tag of a sort dispatch is its pass index.
+const COPY_FROM_EVEN: u32 = 0xFFFFFFFEu;
+/// The tag of the copy from the `ODD_*` to the `EVE_*` global buffers.
+const COPY_FROM_ODD: u32 = 0xFFFFFFFFu;
+
+#ifdef SORTED_ARGS
+/// Sorted keys skip every pass, except the first one if it initializes the vals with the indices.
+fn is_pass_needed(pass_index: u32) -> bool {
+    return unsorted != 0u || (pc.init_index != 0u && pass_index == pc.pass_start);
+}
+#else
+#ifdef GPU_DRIVEN_ARGS
+/// The user parameters clamped to what the recorded sort can run.
+fn validate_params() -> keys::SortParams {
+    var validated = user_params;
+    validated.count = min(validated.count, pc.max_number_of_keys);
+    validated.pass_mask &= (1u << pc.pass_end) - (1u << pc.pass_start);
+    validated.flags &= pc.flags_mask;
+    validated.padding = 0u;
+    return validated;
+}
+
+/// The first pass always runs, the others up to the highest pass of the mask.
+fn is_pass_needed(pass_index: u32) -> bool {
+    return pass_index == pc.pass_start || (validate_params().pass_mask >> pass_index) != 0u;
+}
+#else
+/// The first pass always runs, the others only if some key has a non-zero digit at or above it.
+fn is_pass_needed(pass_index: u32) -> bool {
+    return pass_index == pc.pass_start || (max_key >> (pass_index * keys::NUMBER_OF_RADIX_BITS)) != 0u;
+}
+#endif // GPU_DRIVEN_ARGS
+#endif // SORTED_ARGS
+
+fn is_output_even(pass_end: u32) -> bool {
+    return (pass_end + pc.read_from_even) % 2u == 1u;
+}
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3u) {
+    let dispatch_index = global_invocation_id.x;
+    if dispatch_index >= pc.number_of_dispatches {
+        return;
+    }
+
+    // The needed passes are a prefix of the pass range
+    var executed_end = pc.pass_start;
+    for (var pass_index = pc.pass_start; pass_index < pc.pass_end; pass_index++) {
+        if is_pass_needed(pass_index) {
+            executed_end = pass_index + 1u;
+        }
+    }
+
+    // Move the result to where the full pass range would have left it
+    let actual_even = is_output_even(executed_end);
+    let expected_even = is_output_even(pc.pass_end);
+
+    let tag = args[3u * pc.number_of_dispatches + dispatch_index];
+    var enabled = false;
+    if tag == COPY_FROM_EVEN {
+        enabled = actual_even && !expected_even;
+    } else if tag == COPY_FROM_ODD {
+        enabled = !actual_even && expected_even;
+    } else {
+        enabled = is_pass_needed(tag);
+    }
+
+    if !enabled {
+        args[3u * dispatch_index + 0u] = 0u;
+        args[3u * dispatch_index + 1u] = 0u;
+        args[3u * dispatch_index + 2u] = 0u;
+    }
+
+#ifdef GPU_DRIVEN_ARGS
+    let validated = validate_params();
+    if dispatch_index == 0u {
+        params = validated;
+    }
+
+    // Only the workgroups covering the keys of the count, the recorded ones cover the capacity
+    let keys_per_workgroup = args[4u * pc.number_of_dispatches + dispatch_index];
+    let x = args[3u * dispatch_index + 0u];
+    let y = args[3u * dispatch_index + 1u];
+    if keys_per_workgroup != 0u && x != 0u {
+        let workgroup_offset = args[5u * pc.number_of_dispatches + dispatch_index];
+        let number_of_workgroups = (validated.count + keys_per_workgroup - 1u) / keys_per_workgroup;
+
+        let remaining = min(max(number_of_workgroups, workgroup_offset) - workgroup_offset, x * y);
+        // The rows of `x` workgroups covering them, the workgroups past the count find no key
+        let rows = (remaining + x - 1u) / x;
+        args[3u * dispatch_index + 0u] = select(x, remaining, rows <= 1u);
+        args[3u * dispatch_index + 1u] = rows;
+        args[3u * dispatch_index + 2u] = select(1u, 0u, rows == 0u);
+    }
+#endif // GPU_DRIVEN_ARGS
+}
+#endif // AUTO_ARGS_PIPELINE
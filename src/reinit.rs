@@ -0,0 +1,445 @@
+//! Re-initialization of the sort when the [`RadixSortSettings`] change after startup.
+//!
+//! Replacing the main world settings, e.g. `*settings = RadixSortSettings::from(1 << 20)`, is extracted to the
+//! render world. Each world compares them to the settings it applied last, and only rebuilds what they affect:
+//!
+//! - the capacity, key type, extra usages, vals, memory mode or user buffers reallocate the global buffers and
+//!   rebuild the bind group,
+//! - the subgroup fallback, vals or memory mode re-queue the pipelines of the sort,
+//! - the algorithm queues (or drops) the bitonic pipelines, the key bits only update the [`RadixSortPipeline`].
+//!
+//! The initial contents and the CPU fallback only matter at startup, changing them rebuilds nothing. Reallocated
+//! buffers lose their contents: the keys are zeroed (or the initial keys again) and the vals are the indices again.
+//! [`crate::check_load_state`] reports [`crate::LoadProgress::PreparingBuffers`] until the bind group of the new
+//! buffers is created.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::PipelineCache,
+        renderer::RenderDevice,
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+    },
+};
+
+use crate::{
+    EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE, KeyType, MemoryMode,
+    ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE, RadixSortBindGroup, RadixSortCreationErrors,
+    RadixSortPipeline, RadixSortPipelineInfo, RadixSortSettings, RadixSortUnsupported,
+    create_shader_storage_buffers, global_keys_buffer, global_vals_buffer,
+    run_init_radix_sort_pipeline,
+};
+
+/// What a change of the [`RadixSortSettings`] rebuilds, see [`SettingsChanges::between`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SettingsChanges {
+    /// The global buffers are reallocated and the bind group rebuilt.
+    pub buffers: bool,
+    /// The kernels are compiled with other shader defs, the pipelines are re-queued.
+    pub kernels: bool,
+    /// The backend [`crate::run`] sorts with, the bitonic pipelines may be queued.
+    pub algorithm: bool,
+    /// See [`RadixSortSettings::key_bits`].
+    pub key_bits: bool,
+}
+
+impl SettingsChanges {
+    pub fn between(old: &RadixSortSettings, new: &RadixSortSettings) -> Self {
+        Self {
+            buffers: old.max_number_of_keys() != new.max_number_of_keys()
+                || old.max_number_of_values() != new.max_number_of_values()
+                || old.key_type() != new.key_type()
+                || old.extra_buffer_usages() != new.extra_buffer_usages()
+                || old.allocate_values() != new.allocate_values()
+                || old.user_buffers() != new.user_buffers()
+                || old.memory_mode() != new.memory_mode(),
+            kernels: old.force_subgroup_fallback() != new.force_subgroup_fallback()
+                || old.allocate_values() != new.allocate_values()
+                || old.key_transform() != new.key_transform()
+                || old.memory_mode() != new.memory_mode()
+                || (old.key_type() == KeyType::U64) != (new.key_type() == KeyType::U64),
+            algorithm: old.algorithm() != new.algorithm()
+                || old.allow_fallback() != new.allow_fallback(),
+            key_bits: old.key_bits() != new.key_bits(),
+        }
+    }
+
+    /// `true` if nothing needs rebuilding.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Inserted into the [`RenderApp`](bevy::render::RenderApp) world while the bind group of reallocated global buffers
+/// isn't created yet, [`crate::check_load_state`] reports [`crate::LoadProgress::PreparingBuffers`]
+/// meanwhile.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct RadixSortReinitializing;
+
+/// The settings a world applied last, compared to the changed [`RadixSortSettings`].
+#[derive(Resource, Debug, Clone)]
+pub(crate) struct AppliedRadixSortSettings(pub RadixSortSettings);
+
+/// Reallocates the global buffers of the main world when the settings change their size or usages.
+pub(crate) fn reallocate_global_buffers(
+    radix_sort_settings: Res<RadixSortSettings>,
+    mut applied: ResMut<AppliedRadixSortSettings>,
+    mut sbufs: ResMut<Assets<ShaderStorageBuffer>>,
+    info: Option<ResMut<RadixSortPipelineInfo>>,
+) {
+    let changes = SettingsChanges::between(&applied.0, &radix_sort_settings);
+    applied.0 = radix_sort_settings.clone();
+    if !changes.buffers {
+        return;
+    }
+
+    info!(
+        "radix_sort: reallocating the global buffers for {} keys",
+        radix_sort_settings.max_number_of_keys()
+    );
+    if !radix_sort_settings.allocate_values() {
+        sbufs.remove(EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id());
+        sbufs.remove(ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE.id());
+    }
+    create_shader_storage_buffers(&mut sbufs, &radix_sort_settings);
+
+    if let Some(mut info) = info {
+        info.allocate_values = radix_sort_settings.allocate_values()
+            && radix_sort_settings.memory_mode() == MemoryMode::Full;
+        info.memory_mode = radix_sort_settings.memory_mode();
+    }
+}
+
+/// Rebuilds the render world resources the changed settings affect, before the bind groups are prepared.
+pub(crate) fn reinitialize_radix_sort(world: &mut World) {
+    let radix_sort_settings = world.resource::<RadixSortSettings>().clone();
+    let Some(mut applied) = world.get_resource_mut::<AppliedRadixSortSettings>() else {
+        return;
+    };
+    let changes = SettingsChanges::between(&applied.0, &radix_sort_settings);
+    applied.0 = radix_sort_settings.clone();
+    if changes.is_empty() {
+        return;
+    }
+
+    info!("radix_sort: re-initializing the sort, {:?}", changes);
+
+    // The errors of the previous capacity or kernels
+    if changes.buffers || changes.kernels {
+        world.remove_resource::<RadixSortCreationErrors>();
+    }
+
+    if changes.kernels {
+        world.remove_resource::<RadixSortPipeline>();
+        world.remove_resource::<RadixSortUnsupported>();
+        run_init_radix_sort_pipeline(world);
+    } else if world.contains_resource::<RadixSortPipeline>() {
+        world.resource_scope(|world, mut radix_sort_pipeline: Mut<RadixSortPipeline>| {
+            radix_sort_pipeline
+                .apply_settings(world.resource::<PipelineCache>(), &radix_sort_settings);
+        });
+    }
+
+    if changes.buffers {
+        // Created by `RadixSortBindGroup::initialize` once the new buffers are prepared, it also resets the vals
+        world.remove_resource::<RadixSortBindGroup>();
+        world.insert_resource(RadixSortReinitializing);
+    } else if changes.kernels {
+        rebind_global_buffers(world);
+    }
+}
+
+/// Binds the global buffers to the layout of a rebuilt [`RadixSortPipeline`], keeping their contents.
+fn rebind_global_buffers(world: &mut World) {
+    let (Some(radix_sort_pipeline), Some(sbufs)) = (
+        world.get_resource::<RadixSortPipeline>(),
+        world.get_resource::<RenderAssets<GpuShaderStorageBuffer>>(),
+    ) else {
+        return;
+    };
+    let (Some(keys_eve), Some(keys_odd)) = (
+        global_keys_buffer(sbufs, true),
+        global_keys_buffer(sbufs, false),
+    ) else {
+        return;
+    };
+
+    let radix_sort_bind_group = radix_sort_pipeline.create_bind_group_for(
+        world.resource::<RenderDevice>(),
+        sbufs,
+        keys_eve,
+        keys_odd,
+        global_vals_buffer(sbufs, true),
+        global_vals_buffer(sbufs, false),
+    );
+    match radix_sort_bind_group {
+        Ok(radix_sort_bind_group) => world.insert_resource(radix_sort_bind_group),
+        Err(err) => {
+            error!("radix_sort: failed to rebind the global buffers: {}", err);
+            world.insert_resource(RadixSortCreationErrors(vec![err]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        render::{
+            RenderApp,
+            render_resource::CommandEncoderDescriptor,
+            renderer::{RenderDevice, RenderQueue},
+        },
+    };
+
+    use crate::{
+        Algorithm, GetSubgroupSizePlugin, KeyTransform, LoadState, NUMBER_OF_BYTES_PER_KEY,
+        RadixSortPlugin, SubgroupSize, check_load_state, run, sorted_keys_buffer,
+        test_utils::{create_render_test_app, read_buffer, run_once},
+    };
+
+    use super::*;
+
+    fn create_test_app(settings: RadixSortSettings) -> App {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin { settings });
+        run_once(&mut app);
+
+        app
+    }
+
+    /// Sorts `number_of_keys` keys of the global buffers in the render world, with the current settings.
+    fn assert_sorts(app: &mut App, number_of_keys: u32) {
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let mut expected_keys = keys.clone();
+        expected_keys.sort();
+
+        let render_world = app.sub_app_mut(RenderApp).world_mut();
+        assert_eq!(check_load_state(render_world), LoadState::Loaded);
+        render_world
+            .run_system_once(
+                move |render_device: Res<RenderDevice>,
+                      render_queue: Res<RenderQueue>,
+                      pipeline_cache: Res<PipelineCache>,
+                      radix_sort_pipeline: Res<RadixSortPipeline>,
+                      radix_bind_group: Res<RadixSortBindGroup>,
+                      sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    let keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    assert_eq!(
+                        keys_buf.size(),
+                        (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as u64
+                    );
+                    render_queue.write_buffer(keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: reinit command encoder"),
+                        });
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        0..4,
+                        true,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let output_keys = read_buffer(
+                        &render_device,
+                        &render_queue,
+                        sorted_keys_buffer(&sbufs, &(0..4), true).unwrap(),
+                        number_of_keys as usize,
+                    );
+                    assert_eq!(output_keys, expected_keys);
+                },
+            )
+            .unwrap();
+    }
+
+    fn set_settings(app: &mut App, settings: RadixSortSettings) {
+        *app.world_mut().resource_mut::<RadixSortSettings>() = settings;
+        app.update();
+    }
+
+    fn bind_group_id(app: &App) -> bevy::render::render_resource::BindGroupId {
+        app.get_sub_app(RenderApp)
+            .unwrap()
+            .world()
+            .resource::<RadixSortBindGroup>()
+            .eve_bind_group()
+            .id()
+    }
+
+    #[test]
+    fn test_changes() {
+        let settings = RadixSortSettings::from(1_000);
+        let changes = |new: RadixSortSettings| SettingsChanges::between(&settings, &new);
+
+        assert!(changes(RadixSortSettings::from(1_000)).is_empty());
+        assert!(changes(RadixSortSettings::from(1_000).with_initial_keys(vec![2, 1])).is_empty());
+        assert_eq!(
+            changes(RadixSortSettings::from(2_000)),
+            SettingsChanges {
+                buffers: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).without_values()),
+            SettingsChanges {
+                buffers: true,
+                kernels: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_memory_mode(MemoryMode::Compact)),
+            SettingsChanges {
+                buffers: true,
+                kernels: true,
+                ..default()
+            }
+        );
+        // The u64 keys have kernels of their own, the u8 keys don't
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_key_type(KeyType::U64)),
+            SettingsChanges {
+                buffers: true,
+                kernels: true,
+                key_bits: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_key_type(KeyType::U8)),
+            SettingsChanges {
+                buffers: true,
+                key_bits: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_subgroup_fallback()),
+            SettingsChanges {
+                kernels: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_key_transform(KeyTransform::Complement)),
+            SettingsChanges {
+                kernels: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_algorithm(Algorithm::Bitonic)),
+            SettingsChanges {
+                algorithm: true,
+                ..default()
+            }
+        );
+        assert_eq!(
+            changes(RadixSortSettings::from(1_000).with_significant_key_bits(16)),
+            SettingsChanges {
+                key_bits: true,
+                ..default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_capacity_change() {
+        let mut app = create_test_app(RadixSortSettings::from(1_000));
+        assert_sorts(&mut app, 1_000);
+
+        // The initial contents only matter at startup
+        let bind_group = bind_group_id(&app);
+        set_settings(
+            &mut app,
+            RadixSortSettings::from(1_000).with_initial_keys(vec![2, 1]),
+        );
+        assert_eq!(bind_group_id(&app), bind_group);
+
+        set_settings(&mut app, RadixSortSettings::from(5_000));
+        assert_ne!(bind_group_id(&app), bind_group);
+        assert!(
+            !app.sub_app(RenderApp)
+                .world()
+                .contains_resource::<RadixSortReinitializing>()
+        );
+        assert_sorts(&mut app, 5_000);
+    }
+
+    #[test]
+    fn test_algorithm_change() {
+        let mut app = create_test_app(RadixSortSettings::from(1_000));
+        let bind_group = bind_group_id(&app);
+
+        set_settings(
+            &mut app,
+            RadixSortSettings::from(1_000).with_algorithm(Algorithm::Bitonic),
+        );
+        {
+            let world = app.sub_app(RenderApp).world();
+            let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+            assert!(radix_sort_pipeline.bitonic_pipeline().is_some());
+            assert_eq!(
+                radix_sort_pipeline.active_algorithm(world.resource::<PipelineCache>()),
+                Algorithm::Bitonic
+            );
+        }
+        assert_eq!(bind_group_id(&app), bind_group);
+        assert_sorts(&mut app, 1_000);
+
+        // The kernels are rebuilt, the buffers are bound to their layout again
+        set_settings(
+            &mut app,
+            RadixSortSettings::from(1_000).with_subgroup_fallback(),
+        );
+        {
+            let world = app.sub_app(RenderApp).world();
+            let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+            assert!(radix_sort_pipeline.subgroup_fallback());
+            assert!(radix_sort_pipeline.bitonic_pipeline().is_none());
+        }
+        assert_ne!(bind_group_id(&app), bind_group);
+        assert_sorts(&mut app, 1_000);
+    }
+
+    #[test]
+    fn test_missing_subgroup_size_fails() {
+        let mut app = create_test_app(RadixSortSettings::from(1_000));
+        app.sub_app_mut(RenderApp)
+            .world_mut()
+            .remove_resource::<SubgroupSize>();
+
+        // The kernels are rebuilt without the params of `init_radix_sort_pipeline`
+        set_settings(
+            &mut app,
+            RadixSortSettings::from(1_000).with_subgroup_fallback(),
+        );
+
+        let world = app.sub_app(RenderApp).world();
+        assert!(!world.contains_resource::<RadixSortPipeline>());
+        let unsupported = world.resource::<RadixSortUnsupported>();
+        assert!(
+            unsupported.0.contains("init_radix_sort_pipeline"),
+            "{}",
+            unsupported.0
+        );
+        // Reported instead of loading forever
+        assert_eq!(
+            check_load_state(world),
+            LoadState::Failed(unsupported.0.clone())
+        );
+    }
+}
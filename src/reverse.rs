@@ -0,0 +1,515 @@
+//! Reversal of sorted keys and vals, a descending view of an ascending sort without sorting again, e.g. the opaque
+//! phase drawn front to back and the transparent phase back to front from the same depth sort:
+//!
+//! ```text
+//!  sorted keys    [ 1, 4, 4, 9, 12 ]    vals  [ 3, 0, 2, 4, 1 ]
+//!  reversed keys  [ 12, 9, 4, 4, 1 ]    vals  [ 1, 4, 2, 0, 3 ]
+//! ```
+//!
+//! [`ReversePipeline::record_reverse`] reverses any buffers, in place or into a separate destination.
+//! [`ReversePipeline::record_reverse_sorted`] reverses the global buffers holding the result of [`crate::run`],
+//! the side is resolved like [`crate::sorted_keys_buffer`].
+//!
+//! Equal keys come out in the reverse of their sorted order, unlike a descending sort (see
+//! [`crate::KeyTransform::Complement`]) which keeps them in the order of the input.
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer, BufferAddress,
+            CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, PushConstantRange, ShaderDefVal,
+            ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP,
+    compute_pipelines_load_state, create_dummy_vals_buffers, dispatch_workgroup_ext,
+    sorted_keys_buffer, sorted_vals_buffer,
+};
+
+pub const REVERSE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(281529146928077387501676552977802179573);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const REVERSE_VALS_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+pub struct ReversePlugin;
+
+impl Plugin for ReversePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            REVERSE_SHADER_HANDLE,
+            "reverse.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ReversePipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ReversePipeline {
+    /// Swaps the elements of each mirrored pair
+    in_place_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read_write> keys: array<u32>;
+    /// @binding(1) var<storage, read_write> vals: array<u32>;
+    /// ```
+    in_place_bind_group_layout: BindGroupLayout,
+    /// Writes each element to its mirrored position in the destination
+    copy_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > src_keys: array<u32>;
+    /// @binding(1) var<storage, read      > src_vals: array<u32>;
+    /// @binding(2) var<storage, read_write> dst_keys: array<u32>;
+    /// @binding(3) var<storage, read_write> dst_vals: array<u32>;
+    /// ```
+    copy_bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for ReversePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let in_place_bind_group_layout = render_device.create_bind_group_layout(
+            "reverse: in_place bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (storage_buffer::<u32>(false), storage_buffer::<u32>(false)),
+            ),
+        );
+        let copy_bind_group_layout = render_device.create_bind_group_layout(
+            "reverse: copy bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let in_place_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("reverse: in_place pipeline".into()),
+            layout: vec![in_place_bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: REVERSE_SHADER_HANDLE,
+            shader_defs: [cdefs.as_slice(), &["IN_PLACE_PIPELINE".into()]].concat(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let copy_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("reverse: copy pipeline".into()),
+            layout: vec![copy_bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: REVERSE_SHADER_HANDLE,
+            shader_defs: cdefs,
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            in_place_pipeline,
+            in_place_bind_group_layout,
+            copy_pipeline,
+            copy_bind_group_layout,
+        }
+    }
+}
+
+impl ReversePipeline {
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("reverse: in_place pipeline", self.in_place_pipeline),
+                ("reverse: copy pipeline", self.copy_pipeline),
+            ],
+        )
+    }
+
+    /// Reverses the first `count` `keys` and `vals`, in place or into the `destination` keys and vals.
+    ///
+    /// The vals are reversed with the keys unless they're `None`, the `destination` then needs vals too. With a
+    /// `destination` the middle element of an odd `count` is copied, the elements past `count` are left as is.
+    ///
+    /// # Panics
+    ///
+    /// If a buffer holds fewer than `count` u32s, if the `destination` vals are missing, or if the `destination`
+    /// keys are the `keys`, reverse those in place instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_reverse(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        keys: &Buffer,
+        vals: Option<&Buffer>,
+        destination: Option<(&Buffer, Option<&Buffer>)>,
+        count: u32,
+        max_compute_workgroups_per_dimension: u32,
+    ) {
+        let size = count as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+        let (dst_keys, dst_vals) = destination.unzip();
+        for buffer in [Some(keys), vals, dst_keys, dst_vals.flatten()]
+            .into_iter()
+            .flatten()
+        {
+            assert!(
+                buffer.size() >= size,
+                "reverse: a buffer of {} bytes can't hold {} u32s",
+                buffer.size(),
+                count
+            );
+        }
+        if let Some((dst_keys, dst_vals)) = destination {
+            assert!(
+                vals.is_none() || dst_vals.is_some(),
+                "reverse: the destination needs a vals buffer to reverse the vals into"
+            );
+            assert_ne!(
+                dst_keys.id(),
+                keys.id(),
+                "reverse: the destination is the source, reverse it in place"
+            );
+        }
+
+        if count == 0 {
+            return;
+        }
+
+        // Keys-only buffers are bound with dummy vals the kernel doesn't touch
+        let dummy_vals;
+        let (src_vals, dst_vals) = match (vals, destination.and_then(|(_, vals)| vals)) {
+            (Some(src_vals), dst_vals) => (src_vals, dst_vals.unwrap_or(src_vals)),
+            (None, _) => {
+                dummy_vals = create_dummy_vals_buffers(render_device);
+                (&dummy_vals.0, &dummy_vals.1)
+            }
+        };
+
+        let (pipeline_id, bind_group, number_of_threads) = match destination {
+            None => (
+                self.in_place_pipeline,
+                render_device.create_bind_group(
+                    "reverse: in_place bind_group",
+                    &self.in_place_bind_group_layout,
+                    &BindGroupEntries::sequential((
+                        keys.as_entire_binding(),
+                        src_vals.as_entire_binding(),
+                    )),
+                ),
+                count / 2,
+            ),
+            Some((dst_keys, _)) => (
+                self.copy_pipeline,
+                render_device.create_bind_group(
+                    "reverse: copy bind_group",
+                    &self.copy_bind_group_layout,
+                    &BindGroupEntries::sequential((
+                        keys.as_entire_binding(),
+                        src_vals.as_entire_binding(),
+                        dst_keys.as_entire_binding(),
+                        dst_vals.as_entire_binding(),
+                    )),
+                ),
+                count,
+            ),
+        };
+        let pipeline = pipeline_cache.get_compute_pipeline(pipeline_id).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("reverse compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&count));
+        pass.set_push_constants(
+            REVERSE_VALS_OFFSET,
+            bytemuck::bytes_of(&u32::from(vals.is_some())),
+        );
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_threads.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    /// [`Self::record_reverse`] on the global buffers holding the result of [`crate::run`] over `pass_range` with
+    /// the same `read_from_even`, the vals are reversed unless the sort is keys-only.
+    ///
+    /// Returns `false` without recording anything if the global buffers haven't been prepared yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_reverse_sorted(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        destination: Option<(&Buffer, Option<&Buffer>)>,
+        count: u32,
+        max_compute_workgroups_per_dimension: u32,
+    ) -> bool {
+        let Some(keys) = sorted_keys_buffer(sbufs, pass_range, read_from_even) else {
+            return false;
+        };
+        let vals = sorted_vals_buffer(sbufs, pass_range, read_from_even);
+
+        self.record_reverse(
+            encoder,
+            render_device,
+            pipeline_cache,
+            keys,
+            vals,
+            destination,
+            count,
+            max_compute_workgroups_per_dimension,
+        );
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortBindGroup, RadixSortPipeline, RadixSortPlugin,
+        global_keys_buffer, run,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    /// Reverses `count` of `len` keys and vals in place and into separate buffers, the elements past `count` must
+    /// be untouched.
+    fn check_reverse(len: u32, count: u32) {
+        let mut app = create_render_test_app();
+        app.add_plugins(ReversePlugin);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  reverse_pipeline: Res<ReversePipeline>| {
+                let keys: Vec<u32> = (0..len).map(|i| i.wrapping_mul(2654435761)).collect();
+                let vals: Vec<u32> = (0..len).collect();
+                let reversed = |data: &[u32], untouched: &[u32]| -> Vec<u32> {
+                    let count = count as usize;
+                    data[..count]
+                        .iter()
+                        .rev()
+                        .chain(&untouched[count..])
+                        .copied()
+                        .collect()
+                };
+                let read = |buffer: &Buffer| {
+                    read_buffer(&render_device, &render_queue, buffer, len as usize)
+                };
+                let max_workgroups = render_device.limits().max_compute_workgroups_per_dimension;
+
+                // In place, with and without the vals
+                for with_vals in [true, false] {
+                    let keys_buf = create_storage_buffer(&render_device, &keys);
+                    let vals_buf = create_storage_buffer(&render_device, &vals);
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: reverse command encoder"),
+                        });
+                    reverse_pipeline.record_reverse(
+                        &mut encoder,
+                        &render_device,
+                        &pipeline_cache,
+                        &keys_buf,
+                        with_vals.then_some(&vals_buf),
+                        None,
+                        count,
+                        max_workgroups,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    assert_eq!(read(&keys_buf), reversed(&keys, &keys), "count {count}");
+                    let expected_vals = if with_vals {
+                        reversed(&vals, &vals)
+                    } else {
+                        vals.clone()
+                    };
+                    assert_eq!(read(&vals_buf), expected_vals, "count {count}");
+                }
+
+                // Into the destination, the source is left as is
+                let keys_buf = create_storage_buffer(&render_device, &keys);
+                let vals_buf = create_storage_buffer(&render_device, &vals);
+                let untouched = vec![0xDEADBEEF; len as usize];
+                let dst_keys_buf = create_storage_buffer(&render_device, &untouched);
+                let dst_vals_buf = create_storage_buffer(&render_device, &untouched);
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: reverse into command encoder"),
+                });
+                reverse_pipeline.record_reverse(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &keys_buf,
+                    Some(&vals_buf),
+                    Some((&dst_keys_buf, Some(&dst_vals_buf))),
+                    count,
+                    max_workgroups,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                assert_eq!(
+                    read(&dst_keys_buf),
+                    reversed(&keys, &untouched),
+                    "count {count}"
+                );
+                assert_eq!(
+                    read(&dst_vals_buf),
+                    reversed(&vals, &untouched),
+                    "count {count}"
+                );
+                assert_eq!(read(&keys_buf), keys);
+                assert_eq!(read(&vals_buf), vals);
+            },
+        );
+    }
+
+    #[test]
+    fn test_reverse_even() {
+        check_reverse(10_000, 10_000);
+        check_reverse(10_000, 2);
+    }
+
+    #[test]
+    fn test_reverse_odd() {
+        check_reverse(10_001, 10_001);
+        check_reverse(10_000, 4_321);
+        check_reverse(2, 1);
+    }
+
+    #[test]
+    fn test_reverse_sorted() {
+        let number_of_keys = 5_001u32;
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            })
+            .add_plugins(ReversePlugin);
+
+        // The low byte only, one pass leaves the result on the odd side
+        let keys: Vec<u32> = (0..number_of_keys).map(|i| (i * 7919) % 256).collect();
+        let mut sorted: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        sorted.sort_by_key(|&(key, _)| key);
+        let (mut expected_keys, mut expected_vals): (Vec<u32>, Vec<u32>) =
+            sorted.into_iter().unzip();
+        expected_keys.reverse();
+        expected_vals.reverse();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  reverse_pipeline: Res<ReversePipeline>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                render_queue.write_buffer(
+                    global_keys_buffer(&sbufs, true).unwrap(),
+                    0,
+                    bytemuck::cast_slice(&keys),
+                );
+                let max_workgroups = render_device.limits().max_compute_workgroups_per_dimension;
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: reverse sorted command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    max_workgroups,
+                    number_of_keys,
+                    0..1,
+                    true,
+                    true,
+                );
+                assert!(reverse_pipeline.record_reverse_sorted(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &sbufs,
+                    &(0..1),
+                    true,
+                    None,
+                    number_of_keys,
+                    max_workgroups,
+                ));
+                render_queue.submit([encoder.finish()]);
+
+                let len = number_of_keys as usize;
+                let sorted_keys = sorted_keys_buffer(&sbufs, &(0..1), true).unwrap();
+                let sorted_vals = sorted_vals_buffer(&sbufs, &(0..1), true).unwrap();
+                assert_eq!(
+                    read_buffer(&render_device, &render_queue, sorted_keys, len),
+                    expected_keys
+                );
+                assert_eq!(
+                    read_buffer(&render_device, &render_queue, sorted_vals, len),
+                    expected_vals
+                );
+            },
+        );
+    }
+}
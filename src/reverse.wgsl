@@ -0,0 +1,64 @@
+// The reversal of `reverse.rs`: the element `i` ends at `number_of_keys - 1 - i`, the vals follow their keys.
+
+#ifdef IN_PLACE_PIPELINE
+/// The keys reversed in place
+@group(0) @binding(0) var<storage, read_write> keys: array<u32>;
+/// The vals reversed in place, unless `reverse_vals` is 0
+@group(0) @binding(1) var<storage, read_write> vals: array<u32>;
+#else
+/// Read the keys from this buffer
+@group(0) @binding(0) var<storage, read      > src_keys: array<u32>;
+/// Read the vals from this buffer, unless `reverse_vals` is 0
+@group(0) @binding(1) var<storage, read      > src_vals: array<u32>;
+/// Write the reversed keys to this buffer
+@group(0) @binding(2) var<storage, read_write> dst_keys: array<u32>;
+/// Write the reversed vals to this buffer, unless `reverse_vals` is 0
+@group(0) @binding(3) var<storage, read_write> dst_vals: array<u32>;
+#endif // IN_PLACE_PIPELINE
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys reversed.
+    number_of_keys: u32,
+    /// 1 if the vals are reversed with the keys, 0 for keys-only buffers.
+    reverse_vals: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_index;
+
+#ifdef IN_PLACE_PIPELINE
+    // One thread per pair, the middle element of an odd count already is at its place
+    if index >= pc.number_of_keys / 2u {
+        return;
+    }
+    let mirror = pc.number_of_keys - 1u - index;
+
+    let key = keys[index];
+    keys[index] = keys[mirror];
+    keys[mirror] = key;
+    if pc.reverse_vals != 0u {
+        let val = vals[index];
+        vals[index] = vals[mirror];
+        vals[mirror] = val;
+    }
+#else
+    if index >= pc.number_of_keys {
+        return;
+    }
+    let mirror = pc.number_of_keys - 1u - index;
+
+    dst_keys[mirror] = src_keys[index];
+    if pc.reverse_vals != 0u {
+        dst_vals[mirror] = src_vals[index];
+    }
+#endif // IN_PLACE_PIPELINE
+}
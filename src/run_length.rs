@@ -0,0 +1,623 @@
+//! Run-length encoding of the sorted keys: the distinct keys and the number of elements of each.
+//!
+//! ```text
+//!  sorted_keys   [ 3, 3, 3, 8, 9, 9 ]
+//!  unique_keys   [ 3, 8, 9 ]
+//!  counts        [ 3, 1, 2 ]
+//!  unique_count  [ 3, 1, 1, 1 ]
+//! ```
+//!
+//! The run heads are marked and counted per block, the block counts are scanned, then the heads are compacted in order.
+//! The number of unique keys is written on the GPU along with the workgroup count of a dispatch over them, so the
+//! per-run work can be launched with `dispatch_workgroups_indirect` without a readback.
+//!
+//! [`RunLengthEncodePipeline::record_dense_rank`] replaces each sorted key by the index of its run instead, its dense
+//! rank among the distinct keys:
+//!
+//! ```text
+//!  sorted_keys   [ 3, 3, 3, 8, 9, 9 ]
+//!  ranks         [ 0, 0, 0, 1, 2, 2 ]
+//! ```
+//!
+//! The passes are meant to be recorded in the same encoder right after [`crate::run`].
+
+use std::{num::NonZeroU64, ops::Range};
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, BufferDescriptor, BufferUsages, CachedComputePipelineId, CommandEncoder,
+            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, PushConstantRange,
+            ShaderDefVal, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only, storage_buffer_sized},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_THREADS_PER_WORKGROUP,
+    compute_pipelines_load_state, dispatch_workgroup_ext, sorted_keys_buffer,
+};
+
+pub const RUN_LENGTH_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(152805812069691577769864136750459962675);
+
+/// The size in bytes of the `unique_count` buffer: `[number_of_unique_keys, workgroups_x, workgroups_y, workgroups_z]`.
+///
+/// It should be created with [`BufferUsages::INDIRECT`] to be passed to `dispatch_workgroups_indirect`.
+pub const UNIQUE_COUNT_BUFFER_SIZE: BufferAddress = 4 * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+/// The offset in bytes of the indirect dispatch args in the `unique_count` buffer,
+/// one thread per unique key with workgroups of [`NUMBER_OF_THREADS_PER_WORKGROUP`] threads.
+pub const UNIQUE_COUNT_INDIRECT_OFFSET: BufferAddress = NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..8,
+};
+
+pub struct RunLengthEncodePlugin;
+
+impl Plugin for RunLengthEncodePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            RUN_LENGTH_SHADER_HANDLE,
+            "run_length.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<RunLengthEncodePipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct RunLengthEncodePipeline {
+    /// Count the run heads of each block
+    count_heads_pipeline: CachedComputePipelineId,
+    /// Exclusive prefix sum of the run heads of each block, and write the number of unique keys
+    scan_blocks_pipeline: CachedComputePipelineId,
+    /// Write the key and the start of each run, in order
+    compact_heads_pipeline: CachedComputePipelineId,
+    /// Turn the start of each run into its length
+    count_runs_pipeline: CachedComputePipelineId,
+    /// Write the index of the run of each key
+    dense_rank_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > sorted_keys: array<u32>;
+    /// @binding(1) var<storage, read_write> unique_keys: array<u32>;
+    /// @binding(2) var<storage, read_write> counts: array<u32>;
+    /// @binding(3) var<storage, read_write> unique_count: array<u32, 4>;
+    /// @binding(4) var<storage, read_write> block_offsets: array<u32>;
+    /// ```
+    ///
+    /// The dense rank binds the ranks as both `unique_keys` and `counts`.
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for RunLengthEncodePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "run_length bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer_sized(false, NonZeroU64::new(UNIQUE_COUNT_BUFFER_SIZE)),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+
+        let queue = |label: &'static str, defs: &[&'static str]| {
+            let defs: Vec<ShaderDefVal> = defs.iter().map(|&def| def.into()).collect();
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RUN_LENGTH_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), defs.as_slice()].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let count_heads_pipeline = queue(
+            "run_length: count_heads pipeline",
+            &["COUNT_HEADS_PIPELINE"],
+        );
+        let scan_blocks_pipeline = queue(
+            "run_length: scan_blocks pipeline",
+            &["SCAN_BLOCKS_PIPELINE"],
+        );
+        let compact_heads_pipeline = queue(
+            "run_length: compact_heads pipeline",
+            &["RUN_INDEX_PIPELINE", "COMPACT_HEADS_PIPELINE"],
+        );
+        let count_runs_pipeline = queue(
+            "run_length: count_runs pipeline",
+            &["RUN_INDEX_PIPELINE", "COUNT_RUNS_PIPELINE"],
+        );
+
+        let dense_rank_pipeline = queue(
+            "run_length: dense_rank pipeline",
+            &["RUN_INDEX_PIPELINE", "DENSE_RANK_PIPELINE"],
+        );
+
+        Self {
+            count_heads_pipeline,
+            scan_blocks_pipeline,
+            compact_heads_pipeline,
+            count_runs_pipeline,
+            dense_rank_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl RunLengthEncodePipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("run_length count_heads_pipeline", self.count_heads_pipeline),
+                ("run_length scan_blocks_pipeline", self.scan_blocks_pipeline),
+                (
+                    "run_length compact_heads_pipeline",
+                    self.compact_heads_pipeline,
+                ),
+                ("run_length count_runs_pipeline", self.count_runs_pipeline),
+                ("run_length dense_rank_pipeline", self.dense_rank_pipeline),
+            ],
+        )
+    }
+
+    /// `unique_keys` and `counts` must be as large as `sorted_keys` in the worst case (all keys unique),
+    /// `unique_count` must hold at least [`UNIQUE_COUNT_BUFFER_SIZE`] bytes.
+    ///
+    /// The scratch buffer of the block offsets is sized from `sorted_keys` and kept alive by the bind group.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        unique_keys: &Buffer,
+        counts: &Buffer,
+        unique_count: &Buffer,
+    ) -> BindGroup {
+        let block_offsets = create_block_offsets_buffer(render_device, sorted_keys);
+
+        render_device.create_bind_group(
+            "run_length: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                unique_keys.as_entire_binding(),
+                counts.as_entire_binding(),
+                unique_count.as_entire_binding(),
+                block_offsets.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group reading the sorted keys from the global buffer holding the result of [`crate::run`]
+    /// over `pass_range` with the same `read_from_even` (see [`crate::sorted_keys_buffer`]).
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_sorted_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        unique_keys: &Buffer,
+        counts: &Buffer,
+        unique_count: &Buffer,
+    ) -> Option<BindGroup> {
+        let sorted_keys = sorted_keys_buffer(sbufs, pass_range, read_from_even)?;
+
+        Some(self.create_bind_group(
+            render_device,
+            sorted_keys,
+            unique_keys,
+            counts,
+            unique_count,
+        ))
+    }
+
+    /// `ranks` must be as large as `sorted_keys`, `unique_count` must hold at least [`UNIQUE_COUNT_BUFFER_SIZE`]
+    /// bytes.
+    pub fn create_dense_rank_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        ranks: &Buffer,
+        unique_count: &Buffer,
+    ) -> BindGroup {
+        let block_offsets = create_block_offsets_buffer(render_device, sorted_keys);
+
+        render_device.create_bind_group(
+            "run_length: dense_rank bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                ranks.as_entire_binding(),
+                ranks.as_entire_binding(),
+                unique_count.as_entire_binding(),
+                block_offsets.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Writes the distinct keys of the first `number_of_keys` sorted keys into `unique_keys`, the length of each run
+    /// into `counts`, and `[number_of_unique_keys, workgroups_x, 1, 1]` into `unique_count`.
+    ///
+    /// Only the first `number_of_unique_keys` elements of `unique_keys` and `counts` are written.
+    pub fn record_run_length_encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+    ) {
+        let count_heads_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_heads_pipeline)
+            .unwrap();
+        let scan_blocks_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_blocks_pipeline)
+            .unwrap();
+        let compact_heads_pipeline = pipeline_cache
+            .get_compute_pipeline(self.compact_heads_pipeline)
+            .unwrap();
+        let count_runs_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_runs_pipeline)
+            .unwrap();
+
+        let number_of_blocks = number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("run_length compute pass"),
+            ..default()
+        });
+
+        // All pipelines share the same layout so the push constants are kept across `set_pipeline`.
+        pass.set_pipeline(scan_blocks_pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+
+        // The scan still runs without keys, so `unique_count` is reset to 0
+        if number_of_keys == 0 {
+            pass.dispatch_workgroups(1, 1, 1);
+            return;
+        }
+
+        pass.set_pipeline(count_heads_pipeline);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blocks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+
+        pass.set_pipeline(scan_blocks_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+
+        for pipeline in [compact_heads_pipeline, count_runs_pipeline] {
+            pass.set_pipeline(pipeline);
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_blocks,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+        }
+    }
+
+    /// Writes the dense rank of each of the first `number_of_keys` sorted keys, the index of its run in
+    /// `0..number_of_unique_keys`, into the `ranks` of the bind group (see
+    /// [`RunLengthEncodePipeline::create_dense_rank_bind_group`]), and `[number_of_unique_keys, workgroups_x, 1, 1]`
+    /// into `unique_count`.
+    ///
+    /// With `write_back`, the ranks are then copied over it, usually the sorted keys buffer to remap the keys in
+    /// place. It must have the `COPY_DST` usage and be a different buffer than the ranks.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_dense_rank(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        ranks: &Buffer,
+        write_back: Option<&Buffer>,
+    ) {
+        let count_heads_pipeline = pipeline_cache
+            .get_compute_pipeline(self.count_heads_pipeline)
+            .unwrap();
+        let scan_blocks_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_blocks_pipeline)
+            .unwrap();
+        let dense_rank_pipeline = pipeline_cache
+            .get_compute_pipeline(self.dense_rank_pipeline)
+            .unwrap();
+
+        let number_of_blocks = number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("run_length dense_rank compute pass"),
+                ..default()
+            });
+
+            pass.set_pipeline(scan_blocks_pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+
+            // The scan still runs without keys, so `unique_count` is reset to 0
+            if number_of_keys == 0 {
+                pass.dispatch_workgroups(1, 1, 1);
+                return;
+            }
+
+            pass.set_pipeline(count_heads_pipeline);
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_blocks,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+
+            pass.set_pipeline(scan_blocks_pipeline);
+            pass.dispatch_workgroups(1, 1, 1);
+
+            pass.set_pipeline(dense_rank_pipeline);
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_blocks,
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+        }
+
+        if let Some(write_back) = write_back {
+            encoder.copy_buffer_to_buffer(
+                ranks,
+                0,
+                write_back,
+                0,
+                (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as BufferAddress,
+            );
+        }
+    }
+}
+
+/// The scratch buffer of the block offsets, sized from `sorted_keys`.
+fn create_block_offsets_buffer(render_device: &RenderDevice, sorted_keys: &Buffer) -> Buffer {
+    let max_number_of_keys = (sorted_keys.size() / NUMBER_OF_BYTES_PER_KEY as u64) as u32;
+    let max_number_of_blocks = max_number_of_keys
+        .div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP)
+        .max(1);
+
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("run_length: block_offsets buffer"),
+        size: (max_number_of_blocks * NUMBER_OF_BYTES_PER_KEY) as u64,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    fn cpu_run_length_encode(sorted_keys: &[u32]) -> (Vec<u32>, Vec<u32>) {
+        let mut unique_keys = Vec::new();
+        let mut counts: Vec<u32> = Vec::new();
+
+        for &key in sorted_keys {
+            if unique_keys.last() == Some(&key) {
+                *counts.last_mut().unwrap() += 1;
+            } else {
+                unique_keys.push(key);
+                counts.push(1);
+            }
+        }
+
+        (unique_keys, counts)
+    }
+
+    fn run_run_length_test(sorted_keys: Vec<u32>) {
+        let mut app = create_render_test_app();
+        app.add_plugins(RunLengthEncodePlugin);
+
+        let (expected_keys, expected_counts) = cpu_run_length_encode(&sorted_keys);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  run_length_pipeline: Res<RunLengthEncodePipeline>| {
+                let number_of_keys = sorted_keys.len();
+                let keys_buf = create_storage_buffer(&render_device, &sorted_keys);
+                let unique_keys_buf =
+                    create_storage_buffer(&render_device, &vec![0u32; number_of_keys]);
+                let counts_buf = create_storage_buffer(&render_device, &vec![0u32; number_of_keys]);
+                let unique_count_buf = create_storage_buffer(&render_device, &[u32::MAX; 4]);
+                let bind_group = run_length_pipeline.create_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &unique_keys_buf,
+                    &counts_buf,
+                    &unique_count_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: run_length command encoder"),
+                });
+                run_length_pipeline.record_run_length_encode(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys as u32,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = expected_keys.len();
+                let unique_count = read_buffer(&render_device, &render_queue, &unique_count_buf, 4);
+                assert_eq!(
+                    unique_count,
+                    [
+                        n as u32,
+                        (n as u32).div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                        1,
+                        1
+                    ]
+                );
+
+                let unique_keys = read_buffer(&render_device, &render_queue, &unique_keys_buf, n);
+                let counts = read_buffer(&render_device, &render_queue, &counts_buf, n);
+                assert_eq!(unique_keys, expected_keys);
+                assert_eq!(counts, expected_counts);
+            },
+        );
+    }
+
+    fn cpu_dense_rank(sorted_keys: &[u32]) -> Vec<u32> {
+        let mut rank = 0;
+        sorted_keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| {
+                if i > 0 && key != sorted_keys[i - 1] {
+                    rank += 1;
+                }
+                rank
+            })
+            .collect()
+    }
+
+    fn run_dense_rank_test(sorted_keys: Vec<u32>, write_back: bool) {
+        let mut app = create_render_test_app();
+        app.add_plugins(RunLengthEncodePlugin);
+
+        let expected_ranks = cpu_dense_rank(&sorted_keys);
+        let expected_unique_count = expected_ranks.last().map_or(0, |rank| rank + 1);
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  run_length_pipeline: Res<RunLengthEncodePipeline>| {
+                let number_of_keys = sorted_keys.len();
+                // Never empty, a zero-sized binding is invalid
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[sorted_keys.as_slice(), &[0]].concat());
+                let ranks_buf =
+                    create_storage_buffer(&render_device, &vec![u32::MAX; number_of_keys + 1]);
+                let unique_count_buf = create_storage_buffer(&render_device, &[u32::MAX; 4]);
+                let bind_group = run_length_pipeline.create_dense_rank_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &ranks_buf,
+                    &unique_count_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: dense_rank command encoder"),
+                });
+                run_length_pipeline.record_dense_rank(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys as u32,
+                    &ranks_buf,
+                    write_back.then_some(&keys_buf),
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let unique_count = read_buffer(&render_device, &render_queue, &unique_count_buf, 4);
+                assert_eq!(unique_count[0], expected_unique_count);
+
+                let ranks = read_buffer(&render_device, &render_queue, &ranks_buf, number_of_keys);
+                assert_eq!(ranks, expected_ranks);
+
+                let keys = read_buffer(&render_device, &render_queue, &keys_buf, number_of_keys);
+                if write_back {
+                    assert_eq!(keys, expected_ranks);
+                } else {
+                    assert_eq!(keys, sorted_keys);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_dense_rank() {
+        run_dense_rank_test(vec![3, 3, 3, 8, 9, 9], false);
+        run_dense_rank_test(vec![], false);
+        // Runs crossing block boundaries, sparse keys
+        let sorted_keys: Vec<u32> = (0..100_000u32).map(|i| i / 777 * 1_013).collect();
+        run_dense_rank_test(sorted_keys.clone(), false);
+        run_dense_rank_test(sorted_keys, true);
+        run_dense_rank_test(vec![42; 70_000], true);
+    }
+
+    #[test]
+    fn test_run_length_known_layout() {
+        run_run_length_test(vec![3, 3, 3, 8, 9, 9]);
+    }
+
+    #[test]
+    fn test_run_length_heavy_duplicates() {
+        // Runs crossing block boundaries, and a single run over many blocks
+        let sorted_keys: Vec<u32> = (0..100_000u32).map(|i| i / 1_000).collect();
+        run_run_length_test(sorted_keys);
+        run_run_length_test(vec![42; 70_000]);
+    }
+
+    #[test]
+    fn test_run_length_all_unique() {
+        let sorted_keys: Vec<u32> = (0..100_000u32).map(|i| i * 3).collect();
+        run_run_length_test(sorted_keys);
+    }
+}
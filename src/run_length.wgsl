@@ -0,0 +1,163 @@
+/// Sorted keys, usually the output of the radix sort
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// `unique_keys[i]` = the key of the i-th run, or the ranks of the sorted keys with `DENSE_RANK_PIPELINE`
+@group(0) @binding(1) var<storage, read_write> unique_keys: array<u32>;
+/// `counts[i]` = the length of the i-th run
+@group(0) @binding(2) var<storage, read_write> counts: array<u32>;
+/// [number_of_unique_keys, workgroups_x, workgroups_y, workgroups_z], the last 3 can be passed to `dispatch_workgroups_indirect`
+@group(0) @binding(3) var<storage, read_write> unique_count: array<u32, 4>;
+/// The number of run heads in each block, then the exclusive prefix sum of it
+@group(0) @binding(4) var<storage, read_write> block_offsets: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of sorted keys.
+    number_of_keys: u32,
+}
+var<push_constant> pc: PushConstants;
+
+fn get_workgroup_index(workgroup_id: vec3u, num_workgroups: vec3u) -> u32 {
+    return workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+}
+
+fn get_key_index(workgroup_index: u32, local_invocation_id_x: u32) -> u32 {
+    return workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id_x;
+}
+
+fn is_run_head(key_index: u32) -> bool {
+    return key_index < pc.number_of_keys && (key_index == 0u || sorted_keys[key_index] != sorted_keys[key_index - 1u]);
+}
+
+fn is_run_tail(key_index: u32) -> bool {
+    return key_index < pc.number_of_keys
+        && (key_index == pc.number_of_keys - 1u || sorted_keys[key_index] != sorted_keys[key_index + 1u]);
+}
+
+#ifdef COUNT_HEADS_PIPELINE
+var<workgroup> local_count: atomic<u32>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, local_invocation_id.x);
+
+    if local_invocation_id.x == 0u {
+        atomicStore(&local_count, 0u);
+    }
+    workgroupBarrier();
+
+    if is_run_head(key_index) {
+        atomicAdd(&local_count, 1u);
+    }
+    workgroupBarrier();
+
+    if local_invocation_id.x == 0u {
+        block_offsets[workgroup_index] = atomicLoad(&local_count);
+    }
+}
+#endif // COUNT_HEADS_PIPELINE
+
+#ifdef SCAN_BLOCKS_PIPELINE
+var<workgroup> sums: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(local_invocation_id) local_invocation_id: vec3u) {
+    let tid = local_invocation_id.x;
+    let number_of_blocks = (pc.number_of_keys + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+
+    // Each thread owns a contiguous chunk of blocks
+    let chunk = (number_of_blocks + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+    let chunk_begin = min(tid * chunk, number_of_blocks);
+    let chunk_end = min(chunk_begin + chunk, number_of_blocks);
+
+    var sum = 0u;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        sum += block_offsets[i];
+    }
+    sums[tid] = sum;
+    workgroupBarrier();
+
+    // Inclusive scan of the chunk sums (Hillis-Steele)
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = sums[tid - offset];
+        }
+        workgroupBarrier();
+        sums[tid] += v;
+        workgroupBarrier();
+    }
+
+    var running = sums[tid] - sum;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        let count = block_offsets[i];
+        block_offsets[i] = running;
+        running += count;
+    }
+
+    if tid == #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u {
+        let number_of_unique_keys = sums[tid];
+        unique_count[0] = number_of_unique_keys;
+        unique_count[1] = (number_of_unique_keys + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+        unique_count[2] = 1u;
+        unique_count[3] = 1u;
+    }
+}
+#endif // SCAN_BLOCKS_PIPELINE
+
+// Shared by `COMPACT_HEADS_PIPELINE`, `COUNT_RUNS_PIPELINE` and `DENSE_RANK_PIPELINE`
+#ifdef RUN_INDEX_PIPELINE
+var<workgroup> run_indices: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let tid = local_invocation_id.x;
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, tid);
+
+    // Inclusive scan of the run heads: the index of the run a key belongs to, plus one
+    run_indices[tid] = u32(is_run_head(key_index));
+    workgroupBarrier();
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = run_indices[tid - offset];
+        }
+        workgroupBarrier();
+        run_indices[tid] += v;
+        workgroupBarrier();
+    }
+
+    let run_index = block_offsets[workgroup_index] + run_indices[tid] - 1u;
+
+#ifdef COMPACT_HEADS_PIPELINE
+    // Store the start of the run in `counts`, turned into its length by its tail
+    if is_run_head(key_index) {
+        unique_keys[run_index] = sorted_keys[key_index];
+        counts[run_index] = key_index;
+    }
+#endif // COMPACT_HEADS_PIPELINE
+
+#ifdef COUNT_RUNS_PIPELINE
+    if is_run_tail(key_index) {
+        counts[run_index] = key_index + 1u - counts[run_index];
+    }
+#endif // COUNT_RUNS_PIPELINE
+
+#ifdef DENSE_RANK_PIPELINE
+    // `unique_keys` holds the ranks, aligned with the sorted keys
+    if key_index < pc.number_of_keys {
+        unique_keys[key_index] = run_index;
+    }
+#endif // DENSE_RANK_PIPELINE
+}
+#endif // RUN_INDEX_PIPELINE
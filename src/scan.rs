@@ -0,0 +1,350 @@
+//! Standalone device-wide exclusive prefix sum of `u32` values, e.g. for stream compaction.
+//!
+//! ```text
+//!  input   [ 3, 0, 2, 5, 1 ]
+//!  output  [ 0, 3, 3, 5, 10 ]
+//! ```
+//!
+//! It reuses the scan kernels of the sorter: the values are loaded into a scratch `global_blocks`-like buffer,
+//! scanned like the radix histograms, then the offsets are stored back in the order of the input.
+//! The sums wrap around on overflow.
+
+use bevy::{
+    prelude::*,
+    render::{
+        RenderApp,
+        render_resource::{
+            BindGroupEntries, Buffer, BufferDescriptor, BufferUsages, CachedComputePipelineId,
+            CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BLKS_OFFSET, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_KEYS_OFFSET,
+    NUMBER_OF_THREADS_PER_WORKGROUP, PUSH_CONSTANT_RANGES, RADIX_SORT_SHADER_HANDLE,
+    RadixSortPipeline, RadixSortSettings, SubgroupSize, WORKGROUP_OFFSET_OFFSET,
+    compute_pipelines_load_state, dispatch_workgroup_ext, radix_sort_shader_defs,
+    record_scan_blocks,
+};
+
+/// Requires the [`crate::RadixSortPlugin`] to be added before it, the scan shares its pipelines and capacity.
+pub struct ScanPlugin;
+
+impl Plugin for ScanPlugin {
+    fn build(&self, _app: &mut App) {}
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        // The adapter doesn't support the sort, see `RadixSortUnsupported`
+        if !render_app.world().contains_resource::<RadixSortPipeline>() {
+            return;
+        }
+
+        render_app.init_resource::<ScanPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ScanPipeline {
+    /// Lay the input out in `scan_blocks`
+    scan_load_pipeline: CachedComputePipelineId,
+    /// Write the offsets of `scan_blocks` to the output, in the order of the input
+    scan_store_pipeline: CachedComputePipelineId,
+    /// The scan kernels of the [`RadixSortPipeline`]
+    scan_upsweep_pipeline: CachedComputePipelineId,
+    scan_dnsweep_pipeline: CachedComputePipelineId,
+    scan_last_block_pipeline: CachedComputePipelineId,
+    /// Scratch buffer of the scan, bound as `global_blocks`
+    scan_blocks_buf: Buffer,
+    /// Bound to the slots of the radix sort layout the store step doesn't read
+    dummy_buf: Buffer,
+    /// The maximum number of values, the same as the sorter's
+    max_number_of_values: u32,
+}
+
+impl FromWorld for ScanPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let subgroup_size = world.resource::<SubgroupSize>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+        let radix_sort_settings = world.resource::<RadixSortSettings>();
+
+        let cdefs = radix_sort_shader_defs(
+            subgroup_size,
+            radix_sort_pipeline.subgroup_fallback(),
+            radix_sort_pipeline.key_transform(),
+        );
+
+        let queue = |label: &'static str, def: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![radix_sort_pipeline.bind_group_layout().clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: RADIX_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &[def.into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let scan_load_pipeline = queue("scan: load pipeline", "SCAN_LOAD_PIPELINE");
+        let scan_store_pipeline = queue("scan: store pipeline", "SCAN_STORE_PIPELINE");
+
+        let max_number_of_values = radix_sort_settings.max_number_of_keys();
+        let max_number_of_blks = max_number_of_values
+            .div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP)
+            .max(1);
+
+        let scan_blocks_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("scan: blocks buffer"),
+            size: (max_number_of_blks * NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_BYTES_PER_KEY)
+                as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let dummy_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("scan: dummy buffer"),
+            size: NUMBER_OF_BYTES_PER_KEY as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            scan_load_pipeline,
+            scan_store_pipeline,
+            scan_upsweep_pipeline: radix_sort_pipeline.scan_upsweep_pipeline,
+            scan_dnsweep_pipeline: radix_sort_pipeline.scan_dnsweep_pipeline,
+            scan_last_block_pipeline: radix_sort_pipeline.scan_last_block_pipeline,
+            scan_blocks_buf,
+            dummy_buf,
+            max_number_of_values,
+        }
+    }
+}
+
+impl ScanPipeline {
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("scan load_pipeline", self.scan_load_pipeline),
+                ("scan store_pipeline", self.scan_store_pipeline),
+                ("scan upsweep_pipeline", self.scan_upsweep_pipeline),
+                ("scan dnsweep_pipeline", self.scan_dnsweep_pipeline),
+                ("scan last_block_pipeline", self.scan_last_block_pipeline),
+            ],
+        )
+    }
+
+    /// The maximum `count` of [`ScanPipeline::record_exclusive_scan`], the capacity of the sorter.
+    pub fn max_number_of_values(&self) -> u32 {
+        self.max_number_of_values
+    }
+
+    /// Writes the exclusive prefix sum of the first `count` values of `input` into `output`.
+    ///
+    /// `input` and `output` can be the same buffer. As the scan shares its scratch buffer between calls,
+    /// the scans recorded in the same encoder run one after another.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_exclusive_scan(
+        &self,
+        render_device: &RenderDevice,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_sort_pipeline: &RadixSortPipeline,
+        max_compute_workgroups_per_dimension: u32,
+        input: &Buffer,
+        output: &Buffer,
+        count: u32,
+    ) {
+        assert!(
+            count <= self.max_number_of_values,
+            "count({count}) exceeds the capacity of the scan({})",
+            self.max_number_of_values
+        );
+
+        if count == 0 {
+            return;
+        }
+
+        let scan_load_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_load_pipeline)
+            .unwrap();
+        let scan_store_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_store_pipeline)
+            .unwrap();
+        let scan_upsweep_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_upsweep_pipeline)
+            .unwrap();
+        let scan_dnsweep_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_dnsweep_pipeline)
+            .unwrap();
+        let scan_last_block_pipeline = pipeline_cache
+            .get_compute_pipeline(self.scan_last_block_pipeline)
+            .unwrap();
+
+        // A buffer can't be bound as both read-only and read-write storage in the same bind group,
+        // so the load step only reads `input` and the store step only writes `output`.
+        let load_bind_group = render_device.create_bind_group(
+            "scan: bind_group for load",
+            radix_sort_pipeline.bind_group_layout(),
+            &BindGroupEntries::sequential((
+                input.as_entire_binding(),
+                input.as_entire_binding(),
+                self.scan_blocks_buf.as_entire_binding(),
+                self.scan_blocks_buf.as_entire_binding(),
+                self.scan_blocks_buf.as_entire_binding(),
+            )),
+        );
+        let store_bind_group = render_device.create_bind_group(
+            "scan: bind_group for store",
+            radix_sort_pipeline.bind_group_layout(),
+            &BindGroupEntries::sequential((
+                self.dummy_buf.as_entire_binding(),
+                self.dummy_buf.as_entire_binding(),
+                self.scan_blocks_buf.as_entire_binding(),
+                output.as_entire_binding(),
+                output.as_entire_binding(),
+            )),
+        );
+
+        let number_of_blks = count.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("scan compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(scan_load_pipeline);
+        pass.set_bind_group(0, &load_bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&count));
+        pass.set_push_constants(NUMBER_OF_BLKS_OFFSET, bytemuck::bytes_of(&number_of_blks));
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+
+        record_scan_blocks(
+            &mut pass,
+            scan_upsweep_pipeline,
+            scan_dnsweep_pipeline,
+            number_of_blks,
+            max_compute_workgroups_per_dimension,
+        );
+
+        pass.set_pipeline(scan_last_block_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+
+        pass.set_pipeline(scan_store_pipeline);
+        pass.set_bind_group(0, &store_bind_group, &[]);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{
+            create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+        },
+    };
+
+    use super::*;
+
+    fn cpu_exclusive_scan(values: &[u32]) -> Vec<u32> {
+        values
+            .iter()
+            .scan(0u32, |sum, &value| {
+                let offset = *sum;
+                *sum = sum.wrapping_add(value);
+                Some(offset)
+            })
+            .collect()
+    }
+
+    fn run_scan_test(sizes: &[u32], in_place: bool) {
+        let max_number_of_values = sizes.iter().copied().max().unwrap();
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: max_number_of_values.into(),
+            })
+            .add_plugins(ScanPlugin);
+
+        let sizes = sizes.to_vec();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  scan_pipeline: Res<ScanPipeline>| {
+                for &count in &sizes {
+                    let values: Vec<u32> = (0..count)
+                        .map(|i| i.wrapping_mul(2654435761).rotate_left(7) % 1_000)
+                        .collect();
+                    let expected = cpu_exclusive_scan(&values);
+
+                    let input_buf = create_storage_buffer(&render_device, &values);
+                    let output_buf = if in_place {
+                        input_buf.clone()
+                    } else {
+                        create_storage_buffer(&render_device, &vec![0u32; count as usize])
+                    };
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: scan command encoder"),
+                        });
+                    scan_pipeline.record_exclusive_scan(
+                        &render_device,
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        &input_buf,
+                        &output_buf,
+                        count,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let output =
+                        read_buffer(&render_device, &render_queue, &output_buf, count as usize);
+                    assert_eq!(output, expected, "exclusive scan of {count} values");
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_scan_single_block() {
+        run_scan_test(&[1, 5, 255, 256], false);
+    }
+
+    #[test]
+    fn test_scan_multiple_levels() {
+        // From 2 blocks to thousands of blocks, with counts not a power of two
+        run_scan_test(&[257, 1_000, 65_536, 100_003, 1_234_567], false);
+    }
+
+    #[test]
+    fn test_scan_in_place() {
+        run_scan_test(&[300, 77_777], true);
+    }
+}
@@ -0,0 +1,923 @@
+//! Validated construction of [`RadixSortSettings`].
+//!
+//! ```ignore
+//! let settings = RadixSortSettings::builder()
+//!     .max_keys(1 << 20)
+//!     .key_type(KeyType::U32)
+//!     .extra_usages(BufferUsages::VERTEX)
+//!     .allow_fallback(true)
+//!     .build()?;
+//! ```
+//!
+//! The capacity isn't rounded: the global keys/vals buffers hold exactly `max_keys` keys, only the `global_blocks`
+//! buffer is rounded up to whole scatter blocks. [`RadixSortSettings::max_number_of_keys`] is the effective capacity.
+//! `max_values` allocates the vals buffers for fewer vals, see [`RadixSortSettings::with_max_number_of_values`].
+
+use std::fmt;
+
+use bevy::{
+    prelude::*,
+    render::{render_resource::BufferUsages, settings::WgpuLimits},
+};
+
+use crate::{KeyTransform, NUMBER_OF_BYTES_PER_KEY, RadixSortSettings, RadixSortUserBuffers};
+
+/// The type of the keys in the global keys buffers.
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum KeyType {
+    #[default]
+    U32,
+    /// Bytes in the low 8 bits of u32 keys, e.g. LOD tiers or material groups: a single histogram, scan and
+    /// scatter. The default pass range is `0..1` and [`crate::run`] rejects any other, keys-only it's a counting
+    /// sort.
+    U8,
+    /// u64 keys, 8 bytes each in the global keys buffers: 8 passes extracting the digits from the whole key, the
+    /// default pass range is `0..8`. With `WgpuFeatures::SHADER_INT64` the kernels load them as u64, otherwise as
+    /// their low and high u32 halves, see [`crate::RadixSortPipeline::native_u64`]. Writes and readbacks take the
+    /// keys as little-endian u64, e.g. `bytemuck::cast_slice(&keys)` and [`crate::u64_keys`].
+    ///
+    /// Radix sorts of the global buffers only: no bitonic sort, CPU fallback, key transforms, initial keys or user
+    /// buffers, see [`crate::RadixSortSettings::validate_key_type`]. The indirect sorts and the statistics read u32
+    /// keys.
+    U64,
+}
+
+impl KeyType {
+    /// The size of a key in the keys buffers in bytes, [`Self::U8`] keys take a u32 each.
+    pub const fn size(self) -> u32 {
+        match self {
+            Self::U32 | Self::U8 => NUMBER_OF_BYTES_PER_KEY,
+            Self::U64 => 2 * NUMBER_OF_BYTES_PER_KEY,
+        }
+    }
+
+    /// The number of low bits the keys may have set.
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::U32 => NUMBER_OF_BYTES_PER_KEY * 8,
+            Self::U8 => 8,
+            Self::U64 => 64,
+        }
+    }
+
+    /// Whether `key` fits in the bits of the key type.
+    pub const fn contains(self, key: u32) -> bool {
+        self.bits() >= u32::BITS || key >> self.bits() == 0
+    }
+}
+
+/// The backend [`crate::run`] sorts with, see [`RadixSortSettings::with_algorithm`].
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum Algorithm {
+    /// The stable LSD radix sort.
+    #[default]
+    Radix,
+    /// A bitonic sorting network, see [`crate::bitonic`]. Simpler kernels without subgroups, for a few thousand keys
+    /// or adapters the radix kernels don't build on.
+    ///
+    /// **Not stable**: the keys equal within the bits of the pass range come out in any order. It only replaces
+    /// [`crate::run`], the helpers recording the radix steps themselves still need the radix pipelines.
+    Bitonic,
+}
+
+/// How many global vals buffers the sort allocates, see [`RadixSortSettings::with_memory_mode`].
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum MemoryMode {
+    /// Two vals buffers the passes scatter the vals between, like the keys.
+    #[default]
+    Full,
+    /// A single vals buffer, a quarter less memory than [`Self::Full`]: the kernels sort the keys only, and argsorts
+    /// go through [`crate::run_compact`], which packs the index of each key below its bits and unpacks the indices
+    /// into the vals buffer after the sort.
+    ///
+    /// The keys and the indices must fit in 32 bits together, `key_bits + compact_index_bits(max_keys) <= 32`: e.g.
+    /// 8-bit keys for 2^24 keys, 12-bit keys for 2^20, see [`RadixSortSettings::with_significant_key_bits`]. The
+    /// keys are untransformed [`KeyType::U32`] keys and the vals are always the indices, there are no initial vals.
+    Compact,
+}
+
+/// The number of bits storing the index of one of `number_of_keys` keys, in [`MemoryMode::Compact`].
+pub const fn compact_index_bits(number_of_keys: u32) -> u32 {
+    if number_of_keys <= 1 {
+        0
+    } else {
+        u32::BITS - (number_of_keys - 1).leading_zeros()
+    }
+}
+
+/// The reasons [`RadixSortSettingsBuilder::build`] rejects the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsError {
+    /// `max_keys` wasn't called or was 0.
+    ZeroCapacity,
+    /// A global buffer would exceed the `max_storage_buffer_binding_size` every adapter supports.
+    CapacityTooLarge { max_keys: u32, limit: u32 },
+    /// The value capacity must be within `1..=max_keys`.
+    InvalidValueCapacity { max_values: u32, max_keys: u32 },
+    /// Usages a storage buffer can't have (`MAP_READ`/`MAP_WRITE`).
+    IncompatibleUsages(BufferUsages),
+    /// The initial keys or vals don't fit the capacity, `max_keys` is the value capacity for the vals.
+    InitialDataTooLong {
+        name: &'static str,
+        len: usize,
+        max_keys: u32,
+    },
+    /// The initial keys and vals have different lengths.
+    InitialLengthMismatch { keys: usize, vals: usize },
+    /// Initial vals for a keys-only sort.
+    InitialValsWithoutValues,
+    /// More significant key bits than the keys have.
+    KeyBitsTooLarge { bits: u8, max_bits: u8 },
+    /// An initial key outside the bits of the key type, e.g. above 255 for [`KeyType::U8`].
+    InitialKeyOutOfRange {
+        index: usize,
+        key: u32,
+        key_type: KeyType,
+    },
+    /// A [`KeyTransform::Custom`] expression that doesn't compile, [`KeyTransform::validate`] returns the message.
+    InvalidKeyTransform,
+    /// [`MemoryMode::Compact`] with settings it doesn't support, e.g. `"keys-only sorts"`.
+    CompactUnsupported(&'static str),
+    /// The keys and their indices don't fit in 32 bits in [`MemoryMode::Compact`].
+    CompactTooManyBits { key_bits: u32, index_bits: u32 },
+    /// [`KeyType::U64`] with settings it doesn't support, e.g. `"the bitonic sort"`.
+    U64Unsupported(&'static str),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroCapacity => write!(f, "the capacity of the sort must not be 0"),
+            Self::CapacityTooLarge { max_keys, limit } => write!(
+                f,
+                "a capacity of {max_keys} keys exceeds the {limit} keys a storage buffer binding holds on every adapter"
+            ),
+            Self::InvalidValueCapacity {
+                max_values,
+                max_keys,
+            } => write!(
+                f,
+                "a capacity of {max_values} vals must be positive and at most the capacity of {max_keys} keys"
+            ),
+            Self::IncompatibleUsages(usages) => write!(
+                f,
+                "the global buffers are storage buffers, they can't have the usages {usages:?}"
+            ),
+            Self::InitialDataTooLong {
+                name,
+                len,
+                max_keys,
+            } => write!(
+                f,
+                "{name} holds {len} elements, more than the capacity of {max_keys} keys"
+            ),
+            Self::InitialLengthMismatch { keys, vals } => {
+                write!(f, "the initial contents hold {keys} keys but {vals} vals")
+            }
+            Self::InitialValsWithoutValues => write!(
+                f,
+                "initial vals were given but the vals buffers aren't allocated"
+            ),
+            Self::KeyBitsTooLarge { bits, max_bits } => write!(
+                f,
+                "{bits} significant key bits were declared, the keys only have {max_bits}"
+            ),
+            Self::InitialKeyOutOfRange {
+                index,
+                key,
+                key_type,
+            } => write!(
+                f,
+                "initial_keys[{index}] is {key}, it doesn't fit in {key_type:?} keys"
+            ),
+            Self::InvalidKeyTransform => {
+                write!(f, "the custom key transform doesn't compile")
+            }
+            Self::CompactUnsupported(unsupported) => {
+                write!(f, "the compact memory mode doesn't support {unsupported}")
+            }
+            Self::CompactTooManyBits {
+                key_bits,
+                index_bits,
+            } => write!(
+                f,
+                "{key_bits} key bits and {index_bits} index bits exceed the 32 bits the compact memory mode packs them in"
+            ),
+            Self::U64Unsupported(unsupported) => write!(f, "u64 keys don't support {unsupported}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// The largest capacity the default (WebGPU) limits allow for keys of `key_type`.
+pub fn max_keys_limit(key_type: KeyType) -> u32 {
+    WgpuLimits::downlevel_defaults().max_storage_buffer_binding_size / key_type.size()
+}
+
+/// See [`RadixSortSettings::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct RadixSortSettingsBuilder {
+    max_keys: u32,
+    max_values: Option<u32>,
+    key_type: KeyType,
+    extra_usages: BufferUsages,
+    subgroup_fallback: bool,
+    algorithm: Algorithm,
+    allow_fallback: bool,
+    without_values: bool,
+    initial_keys: Option<Vec<u32>>,
+    initial_vals: Option<Vec<u32>>,
+    significant_key_bits: Option<u8>,
+    user_buffers: Option<RadixSortUserBuffers>,
+    key_transform: KeyTransform,
+    memory_mode: MemoryMode,
+}
+
+impl RadixSortSettingsBuilder {
+    /// The number of keys the global buffers hold, required.
+    pub fn max_keys(mut self, max_keys: u32) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
+
+    /// The number of vals the global vals buffers hold, at most `max_keys`. As many as the keys by default.
+    ///
+    /// See [`RadixSortSettings::with_max_number_of_values`].
+    pub fn max_values(mut self, max_values: u32) -> Self {
+        self.max_values = Some(max_values);
+        self
+    }
+
+    pub fn key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_extra_buffer_usages`].
+    pub fn extra_usages(mut self, usages: BufferUsages) -> Self {
+        self.extra_usages |= usages;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_subgroup_fallback`].
+    pub fn subgroup_fallback(mut self, subgroup_fallback: bool) -> Self {
+        self.subgroup_fallback = subgroup_fallback;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_algorithm`].
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_fallback`].
+    pub fn allow_fallback(mut self, allow_fallback: bool) -> Self {
+        self.allow_fallback = allow_fallback;
+        self
+    }
+
+    /// See [`RadixSortSettings::without_values`].
+    pub fn allocate_values(mut self, allocate_values: bool) -> Self {
+        self.without_values = !allocate_values;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_initial_keys`].
+    pub fn initial_keys(mut self, keys: Vec<u32>) -> Self {
+        self.initial_keys = Some(keys);
+        self
+    }
+
+    /// See [`RadixSortSettings::with_initial_vals`].
+    pub fn initial_vals(mut self, vals: Vec<u32>) -> Self {
+        self.initial_vals = Some(vals);
+        self
+    }
+
+    /// See [`RadixSortSettings::with_significant_key_bits`].
+    pub fn significant_key_bits(mut self, bits: u8) -> Self {
+        self.significant_key_bits = Some(bits);
+        self
+    }
+
+    /// See [`RadixSortSettings::with_user_buffers`].
+    pub fn user_buffers(mut self, user_buffers: RadixSortUserBuffers) -> Self {
+        self.user_buffers = Some(user_buffers);
+        self
+    }
+
+    /// See [`RadixSortSettings::with_key_transform`], a [`KeyTransform::Custom`] expression is validated.
+    pub fn key_transform(mut self, key_transform: KeyTransform) -> Self {
+        self.key_transform = key_transform;
+        self
+    }
+
+    /// See [`RadixSortSettings::with_memory_mode`], the constraints of [`MemoryMode::Compact`] are validated.
+    pub fn memory_mode(mut self, memory_mode: MemoryMode) -> Self {
+        self.memory_mode = memory_mode;
+        self
+    }
+
+    pub fn build(self) -> Result<RadixSortSettings, SettingsError> {
+        if self.max_keys == 0 {
+            return Err(SettingsError::ZeroCapacity);
+        }
+
+        let limit = max_keys_limit(self.key_type);
+        if self.max_keys > limit {
+            return Err(SettingsError::CapacityTooLarge {
+                max_keys: self.max_keys,
+                limit,
+            });
+        }
+
+        let max_values = self.max_values.unwrap_or(self.max_keys);
+        if max_values == 0 || max_values > self.max_keys {
+            return Err(SettingsError::InvalidValueCapacity {
+                max_values,
+                max_keys: self.max_keys,
+            });
+        }
+
+        let mappable = self.extra_usages & (BufferUsages::MAP_READ | BufferUsages::MAP_WRITE);
+        if !mappable.is_empty() {
+            return Err(SettingsError::IncompatibleUsages(mappable));
+        }
+
+        for (name, data, capacity) in [
+            ("initial_keys", &self.initial_keys, self.max_keys),
+            ("initial_vals", &self.initial_vals, max_values),
+        ] {
+            if let Some(data) = data.as_ref().filter(|data| data.len() > capacity as usize) {
+                return Err(SettingsError::InitialDataTooLong {
+                    name,
+                    len: data.len(),
+                    max_keys: capacity,
+                });
+            }
+        }
+
+        if self.without_values && self.initial_vals.is_some() {
+            return Err(SettingsError::InitialValsWithoutValues);
+        }
+
+        let max_bits = self.key_type.bits() as u8;
+        if let Some(bits) = self.significant_key_bits.filter(|&bits| bits > max_bits) {
+            return Err(SettingsError::KeyBitsTooLarge { bits, max_bits });
+        }
+
+        let out_of_range = self.initial_keys.as_ref().and_then(|keys| {
+            keys.iter()
+                .position(|&key| !self.key_type.contains(key))
+                .map(|index| (index, keys[index]))
+        });
+        if let Some((index, key)) = out_of_range {
+            return Err(SettingsError::InitialKeyOutOfRange {
+                index,
+                key,
+                key_type: self.key_type,
+            });
+        }
+
+        if self.key_transform.validate().is_err() {
+            return Err(SettingsError::InvalidKeyTransform);
+        }
+
+        match (&self.initial_keys, &self.initial_vals) {
+            (Some(keys), Some(vals)) if keys.len() != vals.len() => {
+                return Err(SettingsError::InitialLengthMismatch {
+                    keys: keys.len(),
+                    vals: vals.len(),
+                });
+            }
+            _ => {}
+        }
+
+        if self.memory_mode == MemoryMode::Compact {
+            let unsupported = if self.without_values {
+                Some("keys-only sorts")
+            } else if self.user_buffers.is_some() {
+                Some("user buffers")
+            } else if self.initial_vals.is_some() {
+                Some("initial vals")
+            } else if self.key_type != KeyType::U32 {
+                Some("other key types than U32")
+            } else if self.key_transform != KeyTransform::None {
+                Some("key transforms")
+            } else {
+                None
+            };
+            if let Some(unsupported) = unsupported {
+                return Err(SettingsError::CompactUnsupported(unsupported));
+            }
+
+            let key_bits = self
+                .significant_key_bits
+                .map_or(self.key_type.bits(), u32::from);
+            let index_bits = compact_index_bits(self.max_keys);
+            if key_bits + index_bits > u32::BITS {
+                return Err(SettingsError::CompactTooManyBits {
+                    key_bits,
+                    index_bits,
+                });
+            }
+        }
+
+        let mut settings = RadixSortSettings::from(self.max_keys)
+            .with_max_number_of_values(max_values)
+            .with_key_type(self.key_type)
+            .with_key_transform(self.key_transform)
+            .with_extra_buffer_usages(self.extra_usages)
+            .with_algorithm(self.algorithm)
+            .with_memory_mode(self.memory_mode);
+        if self.allow_fallback {
+            settings = settings.with_fallback();
+        }
+        if self.subgroup_fallback {
+            settings = settings.with_subgroup_fallback();
+        }
+        if self.without_values {
+            settings = settings.without_values();
+        }
+        if let Some(keys) = self.initial_keys {
+            settings = settings.with_initial_keys(keys);
+        }
+        if let Some(vals) = self.initial_vals {
+            settings = settings.with_initial_vals(vals);
+        }
+        if let Some(bits) = self.significant_key_bits {
+            settings = settings.with_significant_key_bits(bits);
+        }
+        if let Some(user_buffers) = self.user_buffers {
+            settings = settings.with_user_buffers(user_buffers);
+        }
+        settings.validate_key_type()?;
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .key_type(KeyType::U32)
+            .extra_usages(BufferUsages::VERTEX)
+            .subgroup_fallback(true)
+            .algorithm(Algorithm::Bitonic)
+            .allow_fallback(true)
+            .allocate_values(false)
+            .build()
+            .unwrap();
+
+        assert_eq!(settings.max_number_of_keys(), 1_000);
+        assert_eq!(settings.key_type(), KeyType::U32);
+        assert_eq!(settings.extra_buffer_usages(), BufferUsages::VERTEX);
+        assert!(settings.force_subgroup_fallback());
+        assert_eq!(settings.algorithm(), Algorithm::Bitonic);
+        assert!(settings.allow_fallback());
+        assert!(!settings.allocate_values());
+
+        let limit = max_keys_limit(KeyType::U32);
+        assert_eq!(limit, 1 << 25);
+        let largest = RadixSortSettings::builder()
+            .max_keys(limit)
+            .build()
+            .unwrap();
+        assert_eq!(largest.max_number_of_keys(), limit);
+        assert_eq!(largest.algorithm(), Algorithm::Radix);
+        assert!(!largest.allow_fallback());
+    }
+
+    #[test]
+    fn test_value_capacity() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1 << 20)
+            .max_values(1_000)
+            .build()
+            .unwrap();
+        assert_eq!(settings.max_number_of_keys(), 1 << 20);
+        assert_eq!(settings.max_number_of_values(), 1_000);
+        assert_eq!(settings.allocated_keys_size(), 2 * 4 * (1 << 20));
+        assert_eq!(settings.allocated_vals_size(), 2 * 4 * 1_000);
+        assert_eq!(
+            settings.allocated_size(),
+            RadixSortSettings::from(1 << 20).allocated_size() - 2 * 4 * ((1 << 20) - 1_000)
+        );
+
+        let keys_only = RadixSortSettings::from(1 << 20).without_values();
+        assert_eq!(keys_only.allocated_vals_size(), 0);
+
+        for max_values in [0, (1 << 20) + 1] {
+            assert_eq!(
+                RadixSortSettings::builder()
+                    .max_keys(1 << 20)
+                    .max_values(max_values)
+                    .build()
+                    .unwrap_err(),
+                SettingsError::InvalidValueCapacity {
+                    max_values,
+                    max_keys: 1 << 20
+                }
+            );
+        }
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(4)
+                .max_values(2)
+                .initial_keys(vec![3, 1, 2])
+                .initial_vals(vec![30, 10, 20])
+                .build()
+                .unwrap_err(),
+            SettingsError::InitialDataTooLong {
+                name: "initial_vals",
+                len: 3,
+                max_keys: 2
+            }
+        );
+
+        // Unvalidated, the value capacity is clamped to the keys
+        let settings = RadixSortSettings::from(1_000).with_max_number_of_values(2_000);
+        assert_eq!(settings.max_number_of_values(), 1_000);
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        assert_eq!(
+            RadixSortSettings::builder().build().unwrap_err(),
+            SettingsError::ZeroCapacity
+        );
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(0)
+                .build()
+                .unwrap_err(),
+            SettingsError::ZeroCapacity
+        );
+    }
+
+    #[test]
+    fn test_capacity_too_large() {
+        let limit = max_keys_limit(KeyType::U32);
+        for max_keys in [limit + 1, u32::MAX] {
+            assert_eq!(
+                RadixSortSettings::builder()
+                    .max_keys(max_keys)
+                    .build()
+                    .unwrap_err(),
+                SettingsError::CapacityTooLarge { max_keys, limit }
+            );
+        }
+    }
+
+    #[test]
+    fn test_incompatible_usages() {
+        let err = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .extra_usages(BufferUsages::VERTEX | BufferUsages::MAP_READ)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SettingsError::IncompatibleUsages(BufferUsages::MAP_READ)
+        );
+        assert!(err.to_string().contains("MAP_READ"));
+    }
+
+    #[test]
+    fn test_initial_data() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(4)
+            .initial_keys(vec![3, 1, 2])
+            .initial_vals(vec![30, 10, 20])
+            .build()
+            .unwrap();
+        assert_eq!(settings.initial_keys(), Some([3, 1, 2].as_slice()));
+        assert_eq!(settings.initial_vals(), Some([30, 10, 20].as_slice()));
+        assert_eq!(settings.initial_count(), 3);
+
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(2)
+                .initial_keys(vec![3, 1, 2])
+                .build()
+                .unwrap_err(),
+            SettingsError::InitialDataTooLong {
+                name: "initial_keys",
+                len: 3,
+                max_keys: 2
+            }
+        );
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(4)
+                .initial_keys(vec![3, 1, 2])
+                .initial_vals(vec![30, 10])
+                .build()
+                .unwrap_err(),
+            SettingsError::InitialLengthMismatch { keys: 3, vals: 2 }
+        );
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(4)
+                .allocate_values(false)
+                .initial_vals(vec![30, 10])
+                .build()
+                .unwrap_err(),
+            SettingsError::InitialValsWithoutValues
+        );
+
+        // Unvalidated, the initial contents are truncated to the capacity
+        let settings = RadixSortSettings::from(2).with_initial_keys(vec![3, 1, 2]);
+        assert_eq!(settings.initial_keys(), Some([3, 1].as_slice()));
+    }
+
+    #[test]
+    fn test_significant_key_bits() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .build()
+            .unwrap();
+        assert_eq!(settings.significant_key_bits(), None);
+        assert_eq!(settings.key_bits(), 32);
+        assert_eq!(settings.default_pass_range(), 0..4);
+
+        let settings = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .significant_key_bits(20)
+            .build()
+            .unwrap();
+        assert_eq!(settings.significant_key_bits(), Some(20));
+        assert_eq!(settings.default_pass_range(), 0..3);
+        assert_eq!(
+            settings.default_run_options(),
+            crate::RadixSortPreset::CellHash20.options()
+        );
+        assert_eq!(
+            RadixSortSettings::from(1_000)
+                .with_significant_key_bits(16)
+                .default_pass_range(),
+            0..2
+        );
+
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(1_000)
+                .significant_key_bits(33)
+                .build()
+                .unwrap_err(),
+            SettingsError::KeyBitsTooLarge {
+                bits: 33,
+                max_bits: 32
+            }
+        );
+
+        // Unvalidated, the bits are clamped to the keys
+        let settings = RadixSortSettings::from(1_000).with_significant_key_bits(40);
+        assert_eq!(settings.significant_key_bits(), Some(32));
+        assert_eq!(settings.default_pass_range(), 0..4);
+    }
+
+    #[test]
+    fn test_u8_keys() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .key_type(KeyType::U8)
+            .initial_keys(vec![255, 0, 7])
+            .build()
+            .unwrap();
+        assert_eq!(settings.key_bits(), 8);
+        assert_eq!(settings.default_pass_range(), 0..1);
+        assert_eq!(
+            settings.default_run_options(),
+            crate::RadixSortPreset::Bucket8.options()
+        );
+        // More significant bits don't add passes
+        assert_eq!(
+            RadixSortSettings::from(1_000)
+                .with_key_type(KeyType::U8)
+                .with_significant_key_bits(16)
+                .key_bits(),
+            8
+        );
+
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(1_000)
+                .key_type(KeyType::U8)
+                .initial_keys(vec![1, 256])
+                .build()
+                .unwrap_err(),
+            SettingsError::InitialKeyOutOfRange {
+                index: 1,
+                key: 256,
+                key_type: KeyType::U8
+            }
+        );
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(1_000)
+                .key_type(KeyType::U8)
+                .significant_key_bits(12)
+                .build()
+                .unwrap_err(),
+            SettingsError::KeyBitsTooLarge {
+                bits: 12,
+                max_bits: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_u64_keys() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1_000)
+            .key_type(KeyType::U64)
+            .build()
+            .unwrap();
+        assert_eq!(KeyType::U64.size(), 8);
+        assert_eq!(settings.key_bits(), 64);
+        assert_eq!(settings.default_pass_range(), 0..8);
+        assert_eq!(settings.allocated_keys_size(), 2 * 8 * 1_000);
+        // The vals stay u32
+        assert_eq!(settings.allocated_vals_size(), 2 * 4 * 1_000);
+        assert_eq!(
+            max_keys_limit(KeyType::U64),
+            max_keys_limit(KeyType::U32) / 2
+        );
+        assert_eq!(
+            RadixSortSettings::from(1_000)
+                .with_key_type(KeyType::U64)
+                .with_significant_key_bits(40)
+                .default_pass_range(),
+            0..5
+        );
+
+        let builder = || {
+            RadixSortSettings::builder()
+                .max_keys(1_000)
+                .key_type(KeyType::U64)
+        };
+        assert_eq!(
+            builder()
+                .max_keys(max_keys_limit(KeyType::U64) + 1)
+                .build()
+                .unwrap_err(),
+            SettingsError::CapacityTooLarge {
+                max_keys: max_keys_limit(KeyType::U64) + 1,
+                limit: max_keys_limit(KeyType::U64)
+            }
+        );
+        assert_eq!(
+            builder().algorithm(Algorithm::Bitonic).build().unwrap_err(),
+            SettingsError::U64Unsupported("the bitonic sort")
+        );
+        assert_eq!(
+            builder().allow_fallback(true).build().unwrap_err(),
+            SettingsError::U64Unsupported("the bitonic fallback")
+        );
+        assert_eq!(
+            builder()
+                .key_transform(KeyTransform::Complement)
+                .build()
+                .unwrap_err(),
+            SettingsError::U64Unsupported("key transforms")
+        );
+        assert_eq!(
+            builder().initial_keys(vec![1, 2]).build().unwrap_err(),
+            SettingsError::U64Unsupported("initial keys")
+        );
+
+        // The setters aren't validated, whichever order they're called in
+        let u64_settings = || RadixSortSettings::from(1_000).with_key_type(KeyType::U64);
+        assert_eq!(u64_settings().validate_key_type(), Ok(()));
+        assert_eq!(
+            RadixSortSettings::from(1_000)
+                .with_algorithm(Algorithm::Bitonic)
+                .with_key_type(KeyType::U64)
+                .validate_key_type(),
+            Err(SettingsError::U64Unsupported("the bitonic sort"))
+        );
+        assert_eq!(
+            u64_settings().with_fallback().validate_key_type(),
+            Err(SettingsError::U64Unsupported("the bitonic fallback"))
+        );
+        #[cfg(all(feature = "cpu_fallback", not(target_arch = "wasm32")))]
+        assert_eq!(
+            u64_settings().with_cpu_fallback().validate_key_type(),
+            Err(SettingsError::U64Unsupported("the CPU fallback"))
+        );
+        assert_eq!(
+            u64_settings()
+                .with_key_transform(KeyTransform::Complement)
+                .validate_key_type(),
+            Err(SettingsError::U64Unsupported("key transforms"))
+        );
+        assert_eq!(
+            u64_settings()
+                .with_initial_keys(vec![1])
+                .validate_key_type(),
+            Err(SettingsError::U64Unsupported("initial keys"))
+        );
+        for settings in [
+            RadixSortSettings::from(1_000)
+                .with_significant_key_bits(40)
+                .with_key_type(KeyType::U64),
+            u64_settings().with_significant_key_bits(40),
+        ] {
+            assert_eq!(settings.significant_key_bits(), Some(40));
+            assert_eq!(settings.default_pass_range(), 0..5);
+        }
+        assert_eq!(
+            RadixSortSettings::from(1_000)
+                .with_significant_key_bits(40)
+                .significant_key_bits(),
+            Some(32)
+        );
+    }
+
+    #[test]
+    fn test_compact_memory_mode() {
+        let settings = RadixSortSettings::builder()
+            .max_keys(1 << 20)
+            .significant_key_bits(12)
+            .memory_mode(MemoryMode::Compact)
+            .build()
+            .unwrap();
+        assert_eq!(settings.memory_mode(), MemoryMode::Compact);
+        assert_eq!(settings.compact_index_bits(), 20);
+        // A single vals buffer, a quarter less than the 4 buffers of the full mode
+        assert_eq!(settings.allocated_vals_size(), 4 * (1 << 20));
+        let full = RadixSortSettings::from(1 << 20).with_significant_key_bits(12);
+        assert_eq!(full.compact_index_bits(), 0);
+        assert_eq!(
+            full.allocated_size() - settings.allocated_size(),
+            4 * (1 << 20)
+        );
+
+        let compact = || {
+            RadixSortSettings::builder()
+                .max_keys(1_000)
+                .significant_key_bits(8)
+                .memory_mode(MemoryMode::Compact)
+        };
+        assert!(compact().build().is_ok());
+        assert_eq!(
+            compact().allocate_values(false).build().unwrap_err(),
+            SettingsError::CompactUnsupported("keys-only sorts")
+        );
+        assert_eq!(
+            compact()
+                .initial_keys(vec![3, 1, 2])
+                .initial_vals(vec![30, 10, 20])
+                .build()
+                .unwrap_err(),
+            SettingsError::CompactUnsupported("initial vals")
+        );
+        assert_eq!(
+            compact().key_type(KeyType::U8).build().unwrap_err(),
+            SettingsError::CompactUnsupported("other key types than U32")
+        );
+        assert_eq!(
+            compact()
+                .key_transform(KeyTransform::Complement)
+                .build()
+                .unwrap_err(),
+            SettingsError::CompactUnsupported("key transforms")
+        );
+        // 32-bit keys leave no room for the indices
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(1 << 24)
+                .significant_key_bits(9)
+                .memory_mode(MemoryMode::Compact)
+                .build()
+                .unwrap_err(),
+            SettingsError::CompactTooManyBits {
+                key_bits: 9,
+                index_bits: 24
+            }
+        );
+        assert_eq!(
+            RadixSortSettings::builder()
+                .max_keys(2)
+                .memory_mode(MemoryMode::Compact)
+                .build()
+                .unwrap_err(),
+            SettingsError::CompactTooManyBits {
+                key_bits: 32,
+                index_bits: 1
+            }
+        );
+    }
+}
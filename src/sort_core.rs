@@ -0,0 +1,1554 @@
+//! The sort on plain `wgpu` types, without `bevy_render`.
+//!
+//! [`RadixSortCore`] creates the pipelines from a `wgpu::Device` and records the sort into a `wgpu::CommandEncoder`
+//! with raw buffers, for non-Bevy projects and pure-wgpu tests:
+//!
+//! ```ignore
+//! let core = RadixSortCore::new(&device, subgroup_size, false);
+//! let bind_groups = core.create_bind_groups(&device, &buffers);
+//! core.record(&mut encoder, &bind_groups, max_wg, count, 0..4, true, true);
+//! ```
+//!
+//! The [`crate::RadixSortPlugin`] is a thin wrapper: its pipelines are queued into the `PipelineCache` with the
+//! same [`shader_defs`] and recorded with the same steps, [`crate::run`] counting the digit histograms of all its
+//! passes first (see [`record_fused_sort_passes`]).
+
+use std::{borrow::Cow, ops::Range};
+
+use bevy::{prelude::ReflectDefault, reflect::Reflect};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, CommandEncoder,
+    ComputePass, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineCache, PipelineCompilationOptions, PipelineLayoutDescriptor, PushConstantRange,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages,
+};
+
+use crate::{
+    NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_RADIX, NUMBER_OF_RADIX_BITS, NUMBER_OF_ROWS_PER_WORKGROUP,
+    NUMBER_OF_THREADS_PER_WORKGROUP, is_input_even, passes_needed,
+};
+
+/// The source of the sort kernels, see [`preprocess_wgsl`].
+pub const RADIX_SORT_SHADER_SOURCE: &str = include_str!("radix_sort.wgsl");
+
+/// The source of `bevy_radix_sort::keys`, inlined by [`preprocess_wgsl`] where the kernels import it.
+pub const KEYS_SHADER_SOURCE: &str = include_str!("keys.wgsl");
+
+/// The source of `bevy_radix_sort::key_transform`, inlined by [`preprocess_wgsl`] like [`KEYS_SHADER_SOURCE`].
+/// The pipelines of [`RadixSortCore`] don't transform the keys.
+pub const KEY_TRANSFORM_SHADER_SOURCE: &str = include_str!("key_transform.wgsl");
+
+/// The push constants of the sort kernels, `RadixSortGpuParams` in the WGSL module `bevy_radix_sort::keys`, e.g.
+/// for a dispatch driver of your own recording the steps with the pipelines of [`crate::RadixSortPipeline`]:
+///
+/// ```text
+///  offset  field
+///  0       workgroup_offset
+///  4       number_of_keys
+///  8       number_of_blks
+///  12      pass_index
+///  16      sweep_size
+///  20      init_index
+/// ```
+///
+/// Each step uploads all of them before its first dispatch, then updates `workgroup_offset` (and `sweep_size` in
+/// the scan) between its dispatches. The layout only changes with [`RADIX_SORT_GPU_PARAMS_VERSION`], in a breaking
+/// release.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RadixSortGpuParams {
+    /// The workgroups dispatched before by the same step, when the step is split over several dispatches to stay
+    /// under `max_compute_workgroups_per_dimension`, see [`dispatch_workgroup_ext`].
+    pub workgroup_offset: u32,
+    /// The number of keys to be sorted.
+    pub number_of_keys: u32,
+    /// The number of blocks (histograms) required, [`workgroups_for`] the keys.
+    pub number_of_blks: u32,
+    /// The digit being sorted, 0 for the least significant one. For `u32` keys with 8-bit digits the valid range
+    /// is [0, 3].
+    pub pass_index: u32,
+    /// The step size of the prefix sum (inclusive) of the scan step, up-sweep and down-sweep, the end of the pass
+    /// range in the digit histograms step of [`crate::run`], 0 in the other steps.
+    pub sweep_size: u32,
+    /// 1 if the scatter writes the indices of the keys to the output vals, only in the first pass of a sort with
+    /// `init_index`.
+    pub init_index: u32,
+}
+
+impl RadixSortGpuParams {
+    /// The params a step of the pass `pass_index` over `number_of_keys` keys starts with.
+    pub const fn new(number_of_keys: u32, pass_index: u32, init_index: bool) -> Self {
+        Self::with_tile(number_of_keys, pass_index, init_index, TileConfig::DEFAULT)
+    }
+
+    /// [`Self::new`] for the kernels compiled with `tile`.
+    pub const fn with_tile(
+        number_of_keys: u32,
+        pass_index: u32,
+        init_index: bool,
+        tile: TileConfig,
+    ) -> Self {
+        Self {
+            workgroup_offset: 0,
+            number_of_keys,
+            number_of_blks: tile.workgroups_for(number_of_keys),
+            pass_index,
+            sweep_size: 0,
+            init_index: init_index as u32,
+        }
+    }
+}
+
+/// The version of the layout of [`RadixSortGpuParams`], `RADIX_SORT_GPU_PARAMS_VERSION` in WGSL.
+pub const RADIX_SORT_GPU_PARAMS_VERSION: u32 = 1;
+
+/// The size in bytes of [`RadixSortGpuParams`], the push constants range of the sort pipelines.
+pub const RADIX_SORT_GPU_PARAMS_SIZE: u32 = std::mem::size_of::<RadixSortGpuParams>() as u32;
+
+pub(crate) const WORKGROUP_OFFSET_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, workgroup_offset) as u32;
+pub(crate) const NUMBER_OF_KEYS_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, number_of_keys) as u32;
+pub(crate) const NUMBER_OF_BLKS_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, number_of_blks) as u32;
+pub(crate) const PASS_INDEX_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, pass_index) as u32;
+pub(crate) const SWEEP_SIZE_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, sweep_size) as u32;
+pub(crate) const INIT_INDEX_OFFSET: u32 =
+    std::mem::offset_of!(RadixSortGpuParams, init_index) as u32;
+
+const _: () = assert!(RADIX_SORT_GPU_PARAMS_SIZE == 24 && INIT_INDEX_OFFSET == 20);
+
+pub(crate) const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..RADIX_SORT_GPU_PARAMS_SIZE,
+};
+
+/// The size of the subgroups emulated in shared memory by the fallback kernels.
+pub const FALLBACK_SUBGROUP_SIZE: u32 = 32;
+
+/// The shared memory holding one value per subgroup is sized for subgroups of at most this many threads,
+/// even if a wider size was reported: Metal may run a pipeline 32 or 64 lanes wide depending on its register
+/// pressure, so the kernels take the lane math from the `subgroup_size` builtin instead of the reported size.
+pub const SHARED_MEMORY_SUBGROUP_SIZE: u32 = 32;
+
+/// The bytes of workgroup memory the scatter kernel ranks the keys of a tile with, on subgroups of `subgroup_size`
+/// threads or on the subgroups emulated with `subgroup_fallback`.
+///
+/// The ballots count the radixes per subgroup, so the narrower the subgroups the larger their histograms: on
+/// subgroups of 16 threads they no longer fit in the 16 KiB WebGPU guarantees.
+pub const fn scatter_workgroup_storage_size(subgroup_size: u32, subgroup_fallback: bool) -> u32 {
+    TileConfig::DEFAULT.scatter_workgroup_storage_size(subgroup_size, subgroup_fallback)
+}
+
+/// The tile of the count and scatter steps: the threads of a workgroup, one per radix, and the keys each of them
+/// ranks, the rows of [`NUMBER_OF_ROWS_PER_WORKGROUP`].
+///
+/// The [`crate::RadixSortPlugin`] always sorts with [`TileConfig::DEFAULT`], the tile of the WGSL module
+/// `bevy_radix_sort::keys` the user keygens import. A [`RadixSortCore`] compiles its kernels with any supported
+/// tile, e.g. the one picked by the [`crate::AutoTunePlugin`].
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub struct TileConfig {
+    /// The threads of a workgroup, only [`NUMBER_OF_THREADS_PER_WORKGROUP`]: the kernels count a radix per thread.
+    pub workgroup_size: u32,
+    /// The keys each thread ranks in the scatter step, the rows of a tile.
+    pub keys_per_thread: u32,
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TileConfig {
+    /// The tile of [`tile_size`], 7 rows of 256 threads.
+    pub const DEFAULT: Self = Self::new(NUMBER_OF_ROWS_PER_WORKGROUP);
+
+    /// The most keys a thread ranks, they're held in registers.
+    pub const MAX_KEYS_PER_THREAD: u32 = 32;
+
+    /// A tile of `keys_per_thread` rows of [`NUMBER_OF_THREADS_PER_WORKGROUP`] threads.
+    pub const fn new(keys_per_thread: u32) -> Self {
+        Self {
+            workgroup_size: NUMBER_OF_THREADS_PER_WORKGROUP,
+            keys_per_thread,
+        }
+    }
+
+    /// The number of keys a workgroup of the count and scatter steps covers, a block of `global_blocks`.
+    pub const fn tile_size(self) -> u32 {
+        self.workgroup_size * self.keys_per_thread
+    }
+
+    /// The number of workgroups the count and scatter steps dispatch for `count` keys.
+    pub const fn workgroups_for(self, count: u32) -> u32 {
+        count.div_ceil(self.tile_size())
+    }
+
+    /// The size in bytes of the `global_blocks` buffer for up to `max_number_of_keys` keys, the smaller the tile the
+    /// more blocks.
+    pub const fn blocks_buffer_size(self, max_number_of_keys: u32) -> BufferAddress {
+        (self.workgroups_for(max_number_of_keys) * histogram_buckets() * NUMBER_OF_BYTES_PER_KEY)
+            as BufferAddress
+    }
+
+    /// See [`scatter_workgroup_storage_size`], the ranks of the tile share the memory of the subgroup histograms.
+    pub const fn scatter_workgroup_storage_size(
+        self,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+    ) -> u32 {
+        let subgroup_size = if subgroup_fallback {
+            FALLBACK_SUBGROUP_SIZE
+        } else if subgroup_size < SHARED_MEMORY_SUBGROUP_SIZE {
+            subgroup_size
+        } else {
+            SHARED_MEMORY_SUBGROUP_SIZE
+        };
+        let radix_counts = NUMBER_OF_RADIX * self.workgroup_size.div_ceil(subgroup_size);
+
+        // `subgroup_histograms` and `histogram`, plus `thread_radixes` emulating the ballots
+        let subgroup_histograms = if radix_counts > self.tile_size() {
+            radix_counts
+        } else {
+            self.tile_size()
+        };
+        let thread_radixes = if subgroup_fallback {
+            self.workgroup_size
+        } else {
+            0
+        };
+
+        (subgroup_histograms + NUMBER_OF_RADIX + thread_radixes) * NUMBER_OF_BYTES_PER_KEY
+    }
+
+    /// Whether the kernels compile with the tile and fit in `max_compute_workgroup_storage_size` bytes on subgroups
+    /// of `subgroup_size` threads, or on the emulated ones with `subgroup_fallback`.
+    pub const fn is_supported(
+        self,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+        max_compute_workgroup_storage_size: u32,
+    ) -> bool {
+        self.workgroup_size == NUMBER_OF_THREADS_PER_WORKGROUP
+            && self.keys_per_thread >= 1
+            && self.keys_per_thread <= Self::MAX_KEYS_PER_THREAD
+            && self.scatter_workgroup_storage_size(subgroup_size, subgroup_fallback)
+                <= max_compute_workgroup_storage_size
+    }
+}
+
+/// Returns `true` if the ballot ranking of the scatter kernel fits in `max_compute_workgroup_storage_size` bytes on
+/// subgroups of `subgroup_size` threads, the kernels are compiled with the emulated subgroups otherwise.
+pub const fn multisplit_supported(
+    subgroup_size: u32,
+    max_compute_workgroup_storage_size: u32,
+) -> bool {
+    scatter_workgroup_storage_size(subgroup_size, false) <= max_compute_workgroup_storage_size
+}
+
+/// The shader defs `radix_sort.wgsl` is compiled with: `(name, value)`, `None` for a flag.
+///
+/// With `subgroup_fallback` the subgroups are emulated in shared memory with [`FALLBACK_SUBGROUP_SIZE`] threads.
+pub fn shader_defs(
+    subgroup_size: u32,
+    subgroup_fallback: bool,
+) -> Vec<(&'static str, Option<u32>)> {
+    tile_shader_defs(subgroup_size, subgroup_fallback, TileConfig::DEFAULT)
+}
+
+/// [`shader_defs`] with the rows and threads of `tile`, the kernels of a [`RadixSortCore::with_tile_config`].
+pub fn tile_shader_defs(
+    subgroup_size: u32,
+    subgroup_fallback: bool,
+    tile: TileConfig,
+) -> Vec<(&'static str, Option<u32>)> {
+    let subgroup_size = if subgroup_fallback {
+        FALLBACK_SUBGROUP_SIZE
+    } else {
+        subgroup_size
+    };
+
+    let mut shader_defs = vec![
+        ("NUMBER_OF_THREADS_PER_WORKGROUP", Some(tile.workgroup_size)),
+        ("NUMBER_OF_ROWS_PER_WORKGROUP", Some(tile.keys_per_thread)),
+        ("NUMBER_OF_RADIX", Some(NUMBER_OF_RADIX)),
+        ("NUMBER_OF_RADIX_BITS", Some(NUMBER_OF_RADIX_BITS)),
+        ("NUMBER_OF_THREADS_PER_SUBGROUP", Some(subgroup_size)),
+        (
+            "NUMBER_OF_SUBGROUPS_PER_WORKGROUP",
+            Some(
+                tile.workgroup_size
+                    .div_ceil(subgroup_size.min(SHARED_MEMORY_SUBGROUP_SIZE)),
+            ),
+        ),
+    ];
+
+    if subgroup_fallback {
+        shader_defs.push(("SUBGROUP_FALLBACK", None));
+    }
+
+    shader_defs
+}
+
+/// Resolves the subset of the `naga_oil` preprocessor the kernels of this crate use:
+/// `#ifdef`/`#ifndef`/`#else`/`#endif` on the defined names, `#NAME`/`#{NAME}` replaced by their value,
+/// and `#import bevy_radix_sort::keys` replaced by [`KEYS_SHADER_SOURCE`], once, like
+/// `bevy_radix_sort::key_transform` by [`KEY_TRANSFORM_SHADER_SOURCE`]. The kernels only import unqualified items
+/// from them, so inlining keeps their names.
+///
+/// # Panics
+///
+/// On any other `#import`.
+pub fn preprocess_wgsl(source: &str, shader_defs: &[(&str, Option<u32>)]) -> String {
+    // The longest names first, so `#NUMBER_OF_RADIX` doesn't eat the prefix of `#NUMBER_OF_RADIX_BITS`
+    let mut values: Vec<(&str, u32)> = shader_defs
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| (*name, value)))
+        .collect();
+    values.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    let is_defined = |name: &str| shader_defs.iter().any(|(def, _)| *def == name);
+
+    let mut output = String::with_capacity(source.len());
+    // Whether each enclosing branch is taken
+    let mut branches: Vec<bool> = Vec::new();
+    let mut keys_imported = false;
+    let mut key_transform_imported = false;
+
+    for line in source.lines() {
+        let directive = line.trim_start();
+        let mut words = directive.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+
+        match keyword {
+            "#ifdef" | "#ifndef" => {
+                let name = words.next().unwrap_or_default();
+                branches.push(is_defined(name) == (keyword == "#ifdef"));
+            }
+            "#else" => {
+                let taken = branches.last_mut().expect("#else without #ifdef");
+                *taken = !*taken;
+            }
+            "#endif" => {
+                branches.pop().expect("#endif without #ifdef");
+            }
+            "#define_import_path" => {}
+            "#import" if branches.iter().all(|&taken| taken) => {
+                let path = words.next().unwrap_or_default();
+                let (imported, source) = if path.starts_with("bevy_radix_sort::keys") {
+                    (&mut keys_imported, KEYS_SHADER_SOURCE)
+                } else if path.starts_with("bevy_radix_sort::key_transform") {
+                    (&mut key_transform_imported, KEY_TRANSFORM_SHADER_SOURCE)
+                } else {
+                    panic!("preprocess_wgsl: can't resolve #import {path}");
+                };
+                if !*imported {
+                    *imported = true;
+                    output.push_str(&preprocess_wgsl(source, shader_defs));
+                }
+            }
+            _ if branches.iter().all(|&taken| taken) => {
+                let mut line = line.to_owned();
+                for (name, value) in &values {
+                    line = line
+                        .replace(&format!("#{{{name}}}"), &value.to_string())
+                        .replace(&format!("#{name}"), &value.to_string());
+                }
+                output.push_str(&line);
+                output.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    output
+}
+
+/// The number of keys a workgroup of the count and scatter steps covers, a block of `global_blocks`.
+///
+/// Companion dispatches covering the same tiles as the sort, e.g. a keygen, size themselves with
+/// [`workgroups_for`] instead of repeating the math.
+pub const fn tile_size() -> u32 {
+    TileConfig::DEFAULT.tile_size()
+}
+
+/// The number of workgroups the count and scatter steps dispatch for `count` keys.
+///
+/// Over `max_compute_workgroups_per_dimension` they're spread over 2 dimensions, see [`dispatch_workgroup_ext`].
+pub const fn workgroups_for(count: u32) -> u32 {
+    TileConfig::DEFAULT.workgroups_for(count)
+}
+
+/// `count` rounded up to whole tiles, the keys the workgroups of [`workgroups_for`] cover.
+pub const fn padded_count(count: u32) -> u32 {
+    workgroups_for(count) * tile_size()
+}
+
+/// The number of buckets of the histogram of a pass, one per radix.
+pub const fn histogram_buckets() -> u32 {
+    NUMBER_OF_RADIX
+}
+
+/// The size in bytes of the digit histograms [`crate::run`] keeps at the end of the `global_blocks` buffer: the
+/// counts of each digit of a key, then their exclusive scans, see [`record_fused_sort_passes`].
+pub const DIGIT_HISTOGRAMS_TAIL_SIZE: BufferAddress =
+    2 * (passes_needed(32) * histogram_buckets() * NUMBER_OF_BYTES_PER_KEY) as BufferAddress;
+
+/// The size in bytes of the `global_blocks` buffer for up to `max_number_of_keys` keys, the blocks of the default
+/// tile followed by the [`DIGIT_HISTOGRAMS_TAIL_SIZE`] bytes of the digit histograms.
+pub const fn blocks_buffer_size(max_number_of_keys: u32) -> BufferAddress {
+    TileConfig::DEFAULT.blocks_buffer_size(max_number_of_keys) + DIGIT_HISTOGRAMS_TAIL_SIZE
+}
+
+/// The most keys a `global_blocks` buffer of `blocks_size` bytes holds the blocks of, the inverse of
+/// [`blocks_buffer_size`]. The shaders find the digit histograms at the end of the buffer (`arrayLength`), a smaller
+/// buffer would overlap them with the blocks.
+pub const fn max_number_of_keys_for_blocks(blocks_size: BufferAddress) -> u32 {
+    // `arrayLength` counts whole u32s
+    let blocks_size = blocks_size - blocks_size % NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+    if blocks_size < DIGIT_HISTOGRAMS_TAIL_SIZE {
+        return 0;
+    }
+
+    let block_size = (histogram_buckets() * NUMBER_OF_BYTES_PER_KEY) as BufferAddress;
+    let number_of_blocks = (blocks_size - DIGIT_HISTOGRAMS_TAIL_SIZE) / block_size;
+    let max_number_of_keys = number_of_blocks * TileConfig::DEFAULT.tile_size() as BufferAddress;
+    if max_number_of_keys > u32::MAX as BufferAddress {
+        u32::MAX
+    } else {
+        max_number_of_keys as u32
+    }
+}
+
+/// The buffers a sort reads and writes, all created with `STORAGE` usage.
+///
+/// The keys/vals buffers hold at least `max_number_of_keys` u32, `blocks` at least [`blocks_buffer_size`] bytes, or
+/// [`TileConfig::blocks_buffer_size`] for the tile of the [`RadixSortCore`].
+#[derive(Debug, Clone, Copy)]
+pub struct RadixSortBuffers<'a> {
+    pub eve_keys: &'a Buffer,
+    pub eve_vals: &'a Buffer,
+    pub blocks: &'a Buffer,
+    pub odd_keys: &'a Buffer,
+    pub odd_vals: &'a Buffer,
+}
+
+/// The bind groups of the passes reading from the `EVE_*` and the `ODD_*` buffers.
+#[derive(Debug)]
+pub struct RadixSortCoreBindGroups {
+    pub eve_bind_group: BindGroup,
+    pub odd_bind_group: BindGroup,
+}
+
+/// The pipelines of the sort created directly on a `wgpu::Device`.
+#[derive(Debug)]
+pub struct RadixSortCore {
+    count_radix_pipeline: ComputePipeline,
+    scan_upsweep_pipeline: ComputePipeline,
+    scan_dnsweep_pipeline: ComputePipeline,
+    scan_last_block_pipeline: ComputePipeline,
+    scatter_pipeline: ComputePipeline,
+    /// The same layout as [`crate::RadixSortPipeline::bind_group_layout`]
+    bind_group_layout: BindGroupLayout,
+    tile: TileConfig,
+}
+
+impl RadixSortCore {
+    /// The device needs `Features::PUSH_CONSTANTS` with a `max_push_constant_size` of at least 24 bytes,
+    /// and `Features::SUBGROUP` unless `subgroup_fallback`.
+    ///
+    /// `subgroup_size` must be the size the driver actually uses, it's ignored with `subgroup_fallback`.
+    pub fn new(device: &Device, subgroup_size: u32, subgroup_fallback: bool) -> Self {
+        Self::with_pipeline_cache(device, subgroup_size, subgroup_fallback, None)
+    }
+
+    /// Like [`RadixSortCore::new`], compiling the pipelines through `cache`, e.g. the one of a
+    /// `PersistentPipelineCache` (feature `pipeline_cache`) to skip most of the compilation on the next runs.
+    ///
+    /// The cache needs `Features::PIPELINE_CACHE` on the device.
+    pub fn with_pipeline_cache(
+        device: &Device,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+        cache: Option<&PipelineCache>,
+    ) -> Self {
+        Self::with_tile_config(
+            device,
+            subgroup_size,
+            subgroup_fallback,
+            TileConfig::DEFAULT,
+            cache,
+        )
+    }
+
+    /// Like [`RadixSortCore::with_pipeline_cache`], with the kernels compiled for `tile`, e.g. the
+    /// [`crate::RadixSortTuning::tile_config`] of the adapter. The blocks buffer holds
+    /// [`TileConfig::blocks_buffer_size`] bytes for the tile.
+    ///
+    /// # Panics
+    ///
+    /// If the tile isn't [`TileConfig::is_supported`] by the device.
+    pub fn with_tile_config(
+        device: &Device,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+        tile: TileConfig,
+        cache: Option<&PipelineCache>,
+    ) -> Self {
+        assert!(
+            tile.is_supported(
+                subgroup_size,
+                subgroup_fallback,
+                device.limits().max_compute_workgroup_storage_size
+            ),
+            "radix_sort_core: the device doesn't support the tile {tile:?}"
+        );
+
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("radix_sort_core bindgroup layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, false),
+                storage_entry(3, false),
+                storage_entry(4, false),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("radix_sort_core pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[PUSH_CONSTANT_RANGES],
+        });
+
+        let cdefs = tile_shader_defs(subgroup_size, subgroup_fallback, tile);
+
+        let create_pipeline = |label: &str, def: &'static str| {
+            let source = preprocess_wgsl(
+                RADIX_SORT_SHADER_SOURCE,
+                &[cdefs.as_slice(), &[(def, None)]].concat(),
+            );
+            let module = device.create_shader_module(ShaderModuleDescriptor {
+                label: Some(label),
+                source: ShaderSource::Wgsl(Cow::Owned(source)),
+            });
+
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some("main"),
+                compilation_options: PipelineCompilationOptions {
+                    zero_initialize_workgroup_memory: false,
+                    ..Default::default()
+                },
+                cache,
+            })
+        };
+
+        Self {
+            count_radix_pipeline: create_pipeline(
+                "radix_sort_core: count_radix pipeline",
+                "COUNT_RADIX_PIPELINE",
+            ),
+            scan_upsweep_pipeline: create_pipeline(
+                "radix_sort_core: scan_upsweep pipeline",
+                "SCAN_UP_SWEEP_PIPELINE",
+            ),
+            scan_dnsweep_pipeline: create_pipeline(
+                "radix_sort_core: scan_dnsweep pipeline",
+                "SCAN_DOWN_SWEEP_PIPELINE",
+            ),
+            scan_last_block_pipeline: create_pipeline(
+                "radix_sort_core: scan_last_block pipeline",
+                "SCAN_LAST_BLOCK_PIPELINE",
+            ),
+            scatter_pipeline: create_pipeline(
+                "radix_sort_core: scatter pipeline",
+                "SCATTER_PIPELINE",
+            ),
+            bind_group_layout,
+            tile,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// The tile the kernels are compiled with.
+    pub fn tile_config(&self) -> TileConfig {
+        self.tile
+    }
+
+    pub(crate) fn pipelines(&self) -> SortPipelines<'_> {
+        SortPipelines {
+            count_radix_pipeline: &self.count_radix_pipeline,
+            scan_upsweep_pipeline: &self.scan_upsweep_pipeline,
+            scan_dnsweep_pipeline: &self.scan_dnsweep_pipeline,
+            scan_last_block_pipeline: &self.scan_last_block_pipeline,
+            scatter_pipeline: &self.scatter_pipeline,
+            tile: self.tile,
+        }
+    }
+
+    pub fn create_bind_groups(
+        &self,
+        device: &Device,
+        buffers: &RadixSortBuffers,
+    ) -> RadixSortCoreBindGroups {
+        let create_bind_group =
+            |label, keys_i: &Buffer, vals_i: &Buffer, keys_o: &Buffer, vals_o: &Buffer| {
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: Some(label),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: keys_i.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: vals_i.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: buffers.blocks.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: keys_o.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 4,
+                            resource: vals_o.as_entire_binding(),
+                        },
+                    ],
+                })
+            };
+
+        RadixSortCoreBindGroups {
+            eve_bind_group: create_bind_group(
+                "radix_sort_core: bind_group for even-pass",
+                buffers.eve_keys,
+                buffers.eve_vals,
+                buffers.odd_keys,
+                buffers.odd_vals,
+            ),
+            odd_bind_group: create_bind_group(
+                "radix_sort_core: bind_group for odd-pass",
+                buffers.odd_keys,
+                buffers.odd_vals,
+                buffers.eve_keys,
+                buffers.eve_vals,
+            ),
+        }
+    }
+
+    /// Records the sort like [`crate::run`], the result is in the `EVE_*` buffers if
+    /// [`crate::is_output_even`] returns `true` for `pass_range` and `read_from_even`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        bind_groups: &RadixSortCoreBindGroups,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        pass_range: Range<u32>,
+        init_index: bool,
+        read_from_even: bool,
+    ) {
+        if number_of_keys < 2 {
+            return;
+        }
+
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::core::record", number_of_keys).entered();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("radix_sort_core compute pass"),
+            timestamp_writes: None,
+        });
+
+        record_sort_passes(
+            &mut pass,
+            &self.pipelines(),
+            &bind_groups.eve_bind_group,
+            &bind_groups.odd_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_range,
+            init_index,
+            read_from_even,
+        );
+    }
+}
+
+/// The compute pipelines a sort pass dispatches.
+pub(crate) struct SortPipelines<'a> {
+    pub count_radix_pipeline: &'a ComputePipeline,
+    pub scan_upsweep_pipeline: &'a ComputePipeline,
+    pub scan_dnsweep_pipeline: &'a ComputePipeline,
+    pub scan_last_block_pipeline: &'a ComputePipeline,
+    pub scatter_pipeline: &'a ComputePipeline,
+    /// The tile the pipelines are compiled with
+    pub tile: TileConfig,
+}
+
+/// The pipelines of the digit histograms [`record_fused_sort_passes`] dispatches, compiled by the
+/// [`crate::RadixSortPlugin`] only.
+pub(crate) struct DigitHistogramsPipelines<'a> {
+    /// Counts every digit of the pass range of the keys, in a single read of the keys
+    pub digit_histograms_pipeline: &'a ComputePipeline,
+    /// Scans the histogram of each digit of the pass range into where each radix starts
+    pub scan_digit_histograms_pipeline: &'a ComputePipeline,
+    /// The scatter reading where each radix starts from the scanned histograms
+    pub scatter_pipeline: &'a ComputePipeline,
+}
+
+/// The commands of a compute pass the sort records, so the same sequence of dispatches can be recorded directly
+/// into a [`ComputePass`] or redirected (e.g. to indirect dispatches, see [`crate::run_auto`]).
+pub(crate) trait PassRecorder {
+    fn set_pipeline(&mut self, pipeline: &ComputePipeline);
+
+    fn set_bind_group(&mut self, bind_group: &BindGroup);
+
+    fn set_push_constants(&mut self, offset: u32, data: &[u8]);
+
+    fn dispatch_workgroups(&mut self, x: u32, y: u32, z: u32);
+
+    /// Called before the dispatches of each sort pass.
+    fn begin_sort_pass(&mut self, _pass_index: u32) {}
+}
+
+impl PassRecorder for ComputePass<'_> {
+    fn set_pipeline(&mut self, pipeline: &ComputePipeline) {
+        ComputePass::set_pipeline(self, pipeline);
+    }
+
+    fn set_bind_group(&mut self, bind_group: &BindGroup) {
+        ComputePass::set_bind_group(self, 0, bind_group, &[]);
+    }
+
+    fn set_push_constants(&mut self, offset: u32, data: &[u8]) {
+        ComputePass::set_push_constants(self, offset, data);
+    }
+
+    fn dispatch_workgroups(&mut self, x: u32, y: u32, z: u32) {
+        ComputePass::dispatch_workgroups(self, x, y, z);
+    }
+}
+
+/// Records the sort passes of [`crate::run`], `number_of_keys` must be at least 2.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_sort_passes<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    eve_bind_group: &BindGroup,
+    odd_bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    for pass_index in pass_range.clone() {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::pass", pass_index).entered();
+
+        pass.begin_sort_pass(pass_index);
+
+        // If read_from_even is true:
+        //   pass_index == 0: `even_global_keys_buf`-> `odd_global_keys_buf`
+        //   pass_index == 1: `odd_global_keys_buf` -> `even_global_keys_buf`
+        //   pass_index == 2: `even_global_keys_buf`-> `odd_global_keys_buf`
+        //   pass_index == 3: `odd_global_keys_buf` -> `even_global_keys_buf`
+        // If read_from_even is false:
+        //   pass_index == 0: `odd_global_keys_buf` -> `even_global_keys_buf`
+        //   pass_index == 1: `even_global_keys_buf`-> `odd_global_keys_buf`
+        //   pass_index == 2: `odd_global_keys_buf` -> `even_global_keys_buf`
+        //   pass_index == 3: `even_global_keys_buf`-> `odd_global_keys_buf`
+        let bind_group = if is_input_even(pass_index, read_from_even) {
+            eve_bind_group
+        } else {
+            odd_bind_group
+        };
+
+        // 1. count radix histogram
+        record_count_step(
+            pass,
+            pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_index,
+        );
+
+        // 2. scan blocks
+        record_scan_step(
+            pass,
+            pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+
+        // 3. scatter, only the first pass needs to write the index to `global_vals_buf`
+        record_scatter_step(
+            pass,
+            pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_index,
+            init_index && pass_index == pass_range.start,
+        );
+    }
+}
+
+/// Records the sort passes of [`crate::run`], `number_of_keys` must be at least 2: the histograms of every digit of
+/// the `pass_range` are counted in a single read of the input keys and scanned first, at the end of the
+/// `global_blocks` buffer (see [`blocks_buffer_size`]). Each pass still counts and scans its blocks, the offsets of
+/// the keys of each tile, but its scatter reads where each radix starts from the histogram of its digit instead of
+/// a scan of its last block.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_fused_sort_passes<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    digit_histograms: &DigitHistogramsPipelines,
+    eve_bind_group: &BindGroup,
+    odd_bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_range: Range<u32>,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    if pass_range.is_empty() {
+        return;
+    }
+
+    let bind_group_of = |pass_index| {
+        if is_input_even(pass_index, read_from_even) {
+            eve_bind_group
+        } else {
+            odd_bind_group
+        }
+    };
+
+    // 0. the digit histograms of the range, counted from the input of the first pass, then their exclusive scans
+    begin_step(
+        pass,
+        digit_histograms.digit_histograms_pipeline,
+        bind_group_of(pass_range.start),
+        pipelines.tile,
+        number_of_keys,
+        pass_range.start,
+        false,
+    );
+    pass.set_push_constants(SWEEP_SIZE_OFFSET, bytemuck::bytes_of(&pass_range.end));
+    dispatch_workgroup_ext_with(
+        pass,
+        pipelines.tile.workgroups_for(number_of_keys),
+        max_compute_workgroups_per_dimension,
+        WORKGROUP_OFFSET_OFFSET,
+    );
+
+    begin_step(
+        pass,
+        digit_histograms.scan_digit_histograms_pipeline,
+        bind_group_of(pass_range.start),
+        pipelines.tile,
+        number_of_keys,
+        pass_range.start,
+        false,
+    );
+    pass.dispatch_workgroups(pass_range.len() as u32, 1, 1);
+
+    let pipelines = SortPipelines {
+        scatter_pipeline: digit_histograms.scatter_pipeline,
+        ..*pipelines
+    };
+    for pass_index in pass_range.clone() {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::pass", pass_index).entered();
+
+        pass.begin_sort_pass(pass_index);
+        let bind_group = bind_group_of(pass_index);
+
+        // 1. count radix histogram
+        record_count_step(
+            pass,
+            &pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_index,
+        );
+
+        // 2. scan blocks, the last block isn't needed
+        record_scan_blocks_step(
+            pass,
+            &pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+        );
+
+        // 3. scatter, only the first pass needs to write the index to `global_vals_buf`
+        record_scatter_step(
+            pass,
+            &pipelines,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            pass_index,
+            init_index && pass_index == pass_range.start,
+        );
+    }
+}
+
+/// Sets the pipeline, the bind group and all the push constants of a step, push constants can only be set
+/// once a pipeline is.
+fn begin_step<R: PassRecorder>(
+    pass: &mut R,
+    pipeline: &ComputePipeline,
+    bind_group: &BindGroup,
+    tile: TileConfig,
+    number_of_keys: u32,
+    pass_index: u32,
+    init_index: bool,
+) {
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(bind_group);
+    pass.set_push_constants(
+        0,
+        bytemuck::bytes_of(&RadixSortGpuParams::with_tile(
+            number_of_keys,
+            pass_index,
+            init_index,
+            tile,
+        )),
+    );
+}
+
+/// Counts the radix `pass_index` of the keys of each block into the histograms of `global_blocks`.
+pub(crate) fn record_count_step<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_index: u32,
+) {
+    begin_step(
+        pass,
+        pipelines.count_radix_pipeline,
+        bind_group,
+        pipelines.tile,
+        number_of_keys,
+        pass_index,
+        false,
+    );
+
+    dispatch_workgroup_ext_with(
+        pass,
+        pipelines.tile.workgroups_for(number_of_keys),
+        max_compute_workgroups_per_dimension,
+        WORKGROUP_OFFSET_OFFSET,
+    );
+}
+
+/// Turns the block histograms of `global_blocks` into the scatter offsets of each block and radix.
+pub(crate) fn record_scan_step<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+) {
+    record_scan_blocks_step(
+        pass,
+        pipelines,
+        bind_group,
+        max_compute_workgroups_per_dimension,
+        number_of_keys,
+    );
+
+    // scan last block/histogram(exclusive)
+    pass.set_pipeline(pipelines.scan_last_block_pipeline);
+    pass.dispatch_workgroups(1, 1, 1);
+}
+
+/// The first half of [`record_scan_step`]: the block-wise scan, leaving the total count of each radix in the last
+/// block of `global_blocks`.
+pub(crate) fn record_scan_blocks_step<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+) {
+    begin_step(
+        pass,
+        pipelines.scan_upsweep_pipeline,
+        bind_group,
+        pipelines.tile,
+        number_of_keys,
+        0,
+        false,
+    );
+
+    record_scan_blocks(
+        pass,
+        pipelines.scan_upsweep_pipeline,
+        pipelines.scan_dnsweep_pipeline,
+        pipelines.tile.workgroups_for(number_of_keys),
+        max_compute_workgroups_per_dimension,
+    );
+}
+
+/// The second half of [`record_scan_step`], e.g. in another compute pass: the exclusive scan of the last block.
+pub(crate) fn record_scan_last_block_step<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    bind_group: &BindGroup,
+    number_of_keys: u32,
+) {
+    begin_step(
+        pass,
+        pipelines.scan_last_block_pipeline,
+        bind_group,
+        pipelines.tile,
+        number_of_keys,
+        0,
+        false,
+    );
+    pass.dispatch_workgroups(1, 1, 1);
+}
+
+/// Writes the keys (and vals) to their sorted positions by the radix `pass_index`, into the other side of the
+/// global buffers. With `init_index`, the vals written are the indices of the keys.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_scatter_step<R: PassRecorder>(
+    pass: &mut R,
+    pipelines: &SortPipelines,
+    bind_group: &BindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    pass_index: u32,
+    init_index: bool,
+) {
+    begin_step(
+        pass,
+        pipelines.scatter_pipeline,
+        bind_group,
+        pipelines.tile,
+        number_of_keys,
+        pass_index,
+        init_index,
+    );
+
+    dispatch_workgroup_ext_with(
+        pass,
+        pipelines.tile.workgroups_for(number_of_keys),
+        max_compute_workgroups_per_dimension,
+        WORKGROUP_OFFSET_OFFSET,
+    );
+}
+
+/// Scans the histograms of the first `number_of_blks` blocks of `global_blocks` block-wise (inclusive) for each radix,
+/// the last block then holds the total count of each radix.
+///
+/// The bind group and the other push constants must already be set.
+pub(crate) fn record_scan_blocks<R: PassRecorder>(
+    pass: &mut R,
+    scan_upsweep_pipeline: &ComputePipeline,
+    scan_dnsweep_pipeline: &ComputePipeline,
+    number_of_blks: u32,
+    max_compute_workgroups_per_dimension: u32,
+) {
+    // scan up sweep(inclusive)
+    pass.set_pipeline(scan_upsweep_pipeline);
+    let num_round = log2_floor(number_of_blks);
+    for r in 0..num_round {
+        let sweep_size = 1 << r;
+        let number_of_workgroups = number_of_blks / (2 * sweep_size);
+
+        pass.set_push_constants(SWEEP_SIZE_OFFSET, bytemuck::bytes_of(&sweep_size));
+
+        dispatch_workgroup_ext_with(
+            pass,
+            number_of_workgroups,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    // scan down sweep(inclusive)
+    pass.set_pipeline(scan_dnsweep_pipeline);
+    let num_round = log2_ceil(number_of_blks).saturating_sub(1);
+    for r in 0..num_round {
+        let num_slots = num_round - r;
+        let sweep_size = 1 << num_slots;
+
+        let num_src_blocks_with_full_slots = number_of_blks / (2 * sweep_size);
+        let extra_slots = 32 - (number_of_blks % sweep_size).leading_zeros();
+
+        let number_of_workgroups = num_src_blocks_with_full_slots * num_slots + extra_slots;
+
+        pass.set_push_constants(SWEEP_SIZE_OFFSET, bytemuck::bytes_of(&sweep_size));
+
+        dispatch_workgroup_ext_with(
+            pass,
+            number_of_workgroups,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+}
+
+pub(crate) const fn log2_floor(x: u32) -> u32 {
+    31 - x.leading_zeros()
+}
+
+pub(crate) const fn log2_ceil(x: u32) -> u32 {
+    32 - x.leading_zeros() - (x.is_power_of_two() as u32)
+}
+
+pub fn dispatch_workgroup_ext(
+    pass: &mut ComputePass,
+    number_of_workgroups: u32,
+    max_compute_workgroups_per_dimension: u32,
+    workgroup_offset_offset: u32,
+) {
+    dispatch_workgroup_ext_with(
+        pass,
+        number_of_workgroups,
+        max_compute_workgroups_per_dimension,
+        workgroup_offset_offset,
+    );
+}
+
+/// [`dispatch_workgroup_ext`] for any [`PassRecorder`].
+pub(crate) fn dispatch_workgroup_ext_with<R: PassRecorder + ?Sized>(
+    pass: &mut R,
+    number_of_workgroups: u32,
+    max_compute_workgroups_per_dimension: u32,
+    workgroup_offset_offset: u32,
+) {
+    pass.set_push_constants(workgroup_offset_offset, bytemuck::bytes_of(&0));
+
+    if number_of_workgroups <= max_compute_workgroups_per_dimension {
+        pass.dispatch_workgroups(number_of_workgroups, 1, 1);
+    } else {
+        let d = number_of_workgroups / max_compute_workgroups_per_dimension;
+
+        pass.dispatch_workgroups(max_compute_workgroups_per_dimension, d, 1);
+
+        let workgroup_offset = max_compute_workgroups_per_dimension * d;
+        pass.set_push_constants(
+            workgroup_offset_offset,
+            bytemuck::bytes_of(&workgroup_offset),
+        );
+        pass.dispatch_workgroups(number_of_workgroups - workgroup_offset, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::{
+        BufferDescriptor, BufferUsages, CommandEncoderDescriptor, DeviceDescriptor, Features,
+        Instance, Maintain, MapMode, Queue, RequestAdapterOptions,
+        util::{BufferInitDescriptor, DeviceExt},
+    };
+
+    use crate::is_output_even;
+
+    use super::*;
+
+    /// A plain wgpu device with every limit of the adapter, which needs push constants like the other GPU tests.
+    fn request_device() -> (Device, Queue) {
+        let instance = Instance::default();
+        let adapter =
+            bevy::tasks::block_on(instance.request_adapter(&RequestAdapterOptions::default()))
+                .expect("unit_test: no adapter");
+
+        let limits = adapter.limits();
+        assert!(
+            adapter.features().contains(Features::PUSH_CONSTANTS)
+                && limits.max_push_constant_size >= PUSH_CONSTANT_RANGES.range.end,
+            "unit_test: the adapter can't run the sort without push constants"
+        );
+
+        bevy::tasks::block_on(adapter.request_device(
+            &DeviceDescriptor {
+                label: Some("unit_test: radix_sort_core device"),
+                required_features: Features::PUSH_CONSTANTS
+                    | (adapter.features() & Features::SUBGROUP),
+                required_limits: limits,
+                memory_hints: Default::default(),
+            },
+            None,
+        ))
+        .expect("unit_test: failed to request the device")
+    }
+
+    fn read(device: &Device, queue: &Queue, buffer: &Buffer, len: usize) -> Vec<u32> {
+        let size = (len * std::mem::size_of::<u32>()) as BufferAddress;
+        let staging_buf = device.create_buffer(&BufferDescriptor {
+            label: Some("unit_test: radix_sort_core staging buffer"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buf, 0, size);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging_buf.slice(..);
+        slice.map_async(MapMode::Read, |_| ());
+        device.poll(Maintain::Wait).panic_on_timeout();
+
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging_buf.unmap();
+
+        data
+    }
+
+    fn run_core_sort_test(
+        number_of_keys: u32,
+        subgroup_fallback: bool,
+        read_from_even: bool,
+        tile: TileConfig,
+    ) {
+        let (device, queue) = request_device();
+
+        // Where the limits don't pin the size the driver uses, the subgroups are emulated like the plugin does
+        let limits = device.limits();
+        let subgroup_size = limits.min_subgroup_size;
+        let subgroup_fallback = subgroup_fallback
+            || !device.features().contains(Features::SUBGROUP)
+            || subgroup_size == 0
+            || subgroup_size != limits.max_subgroup_size;
+
+        assert!(
+            tile.is_supported(
+                subgroup_size,
+                subgroup_fallback,
+                limits.max_compute_workgroup_storage_size,
+            ),
+            "the tile {tile:?} doesn't fit in the workgroup storage of the adapter"
+        );
+        let core =
+            RadixSortCore::with_tile_config(&device, subgroup_size, subgroup_fallback, tile, None);
+
+        let keys: Vec<u32> = (0..number_of_keys)
+            .map(|i| i.wrapping_mul(2654435761).rotate_left(9))
+            .collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+        let (expected_keys, expected_vals): (Vec<u32>, Vec<u32>) = expected.into_iter().unzip();
+
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        let create_buffer = |contents: &[u32]| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("unit_test: radix_sort_core buffer"),
+                contents: bytemuck::cast_slice(contents),
+                usage,
+            })
+        };
+        let zeros = vec![0u32; number_of_keys as usize];
+        let (eve_keys, odd_keys) = if read_from_even {
+            (create_buffer(&keys), create_buffer(&zeros))
+        } else {
+            (create_buffer(&zeros), create_buffer(&keys))
+        };
+        let eve_vals = create_buffer(&zeros);
+        let odd_vals = create_buffer(&zeros);
+        let blocks = device.create_buffer(&BufferDescriptor {
+            label: Some("unit_test: radix_sort_core blocks buffer"),
+            size: tile.blocks_buffer_size(number_of_keys),
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let bind_groups = core.create_bind_groups(
+            &device,
+            &RadixSortBuffers {
+                eve_keys: &eve_keys,
+                eve_vals: &eve_vals,
+                blocks: &blocks,
+                odd_keys: &odd_keys,
+                odd_vals: &odd_vals,
+            },
+        );
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        core.record(
+            &mut encoder,
+            &bind_groups,
+            device.limits().max_compute_workgroups_per_dimension,
+            number_of_keys,
+            0..4,
+            true,
+            read_from_even,
+        );
+        queue.submit([encoder.finish()]);
+
+        let (output_keys, output_vals) = if is_output_even(&(0..4), read_from_even) {
+            (&eve_keys, &eve_vals)
+        } else {
+            (&odd_keys, &odd_vals)
+        };
+        let n = number_of_keys as usize;
+        assert_eq!(read(&device, &queue, output_keys, n), expected_keys);
+        assert_eq!(read(&device, &queue, output_vals, n), expected_vals);
+    }
+
+    #[test]
+    fn test_core_sort_subgroup_fallback() {
+        run_core_sort_test(1_000, true, true, TileConfig::DEFAULT);
+        run_core_sort_test(1_000_003, true, false, TileConfig::DEFAULT);
+    }
+
+    #[test]
+    fn test_core_sort_subgroups() {
+        run_core_sort_test(100_003, false, true, TileConfig::DEFAULT);
+    }
+
+    #[test]
+    fn test_core_sort_tiles() {
+        // Smaller and larger tiles than the default, partial last tiles
+        for keys_per_thread in [1, 3, 11, 15] {
+            let tile = TileConfig::new(keys_per_thread);
+            run_core_sort_test(100_003, true, true, tile);
+            run_core_sort_test(100_003, false, false, tile);
+        }
+    }
+
+    #[test]
+    fn test_tile_math() {
+        assert_eq!(tile_size(), 256 * 7);
+        assert_eq!(histogram_buckets(), 1 << NUMBER_OF_RADIX_BITS);
+
+        assert_eq!(workgroups_for(0), 0);
+        assert_eq!(workgroups_for(1), 1);
+        assert_eq!(workgroups_for(tile_size()), 1);
+        assert_eq!(workgroups_for(tile_size() + 1), 2);
+
+        assert_eq!(padded_count(0), 0);
+        assert_eq!(padded_count(1), tile_size());
+        assert_eq!(padded_count(tile_size() + 1), 2 * tile_size());
+
+        // A block of histograms per tile, then the digit histograms
+        assert_eq!(
+            blocks_buffer_size(tile_size() + 1),
+            (2 * histogram_buckets() * NUMBER_OF_BYTES_PER_KEY) as BufferAddress
+                + DIGIT_HISTOGRAMS_TAIL_SIZE
+        );
+        assert_eq!(
+            DIGIT_HISTOGRAMS_TAIL_SIZE,
+            2 * crate::DIGIT_HISTOGRAMS_BUFFER_SIZE
+        );
+        assert_eq!(
+            max_number_of_keys_for_blocks(blocks_buffer_size(tile_size() + 1)),
+            2 * tile_size()
+        );
+        // The digit histograms would overlap the last block
+        assert_eq!(
+            max_number_of_keys_for_blocks(blocks_buffer_size(tile_size() + 1) - 4),
+            tile_size()
+        );
+        assert_eq!(
+            max_number_of_keys_for_blocks(blocks_buffer_size(tile_size()) + 2),
+            tile_size()
+        );
+        assert_eq!(
+            max_number_of_keys_for_blocks(DIGIT_HISTOGRAMS_TAIL_SIZE - 4),
+            0
+        );
+
+        assert_eq!(TileConfig::default(), TileConfig::DEFAULT);
+        assert_eq!(TileConfig::DEFAULT.tile_size(), tile_size());
+        let tile = TileConfig::new(3);
+        assert_eq!(tile.tile_size(), 256 * 3);
+        assert_eq!(tile.workgroups_for(tile_size()), 3);
+        assert_eq!(
+            tile.blocks_buffer_size(tile_size()),
+            3 * TileConfig::DEFAULT.blocks_buffer_size(tile_size())
+        );
+        assert_eq!(
+            RadixSortGpuParams::with_tile(tile_size(), 0, false, tile).number_of_blks,
+            3
+        );
+    }
+
+    #[test]
+    fn test_tile_support() {
+        let default_limit = 16_384;
+        assert!(TileConfig::DEFAULT.is_supported(32, false, default_limit));
+        assert!(TileConfig::DEFAULT.is_supported(0, true, default_limit));
+
+        // The ranks of 15 rows fill the 16 KiB, the emulated ballots need more
+        let tile = TileConfig::new(15);
+        assert_eq!(
+            tile.scatter_workgroup_storage_size(32, false),
+            default_limit
+        );
+        assert!(tile.is_supported(32, false, default_limit));
+        assert!(!tile.is_supported(0, true, default_limit));
+        assert!(tile.is_supported(0, true, 32_768));
+        // Smaller tiles than the subgroup histograms don't need less
+        assert_eq!(
+            TileConfig::new(3).scatter_workgroup_storage_size(32, false),
+            scatter_workgroup_storage_size(32, false)
+        );
+
+        assert!(!TileConfig::new(0).is_supported(32, false, default_limit));
+        assert!(
+            !TileConfig::new(TileConfig::MAX_KEYS_PER_THREAD + 1).is_supported(32, false, 1 << 20)
+        );
+        let wide = TileConfig {
+            workgroup_size: 512,
+            ..TileConfig::DEFAULT
+        };
+        assert!(!wide.is_supported(32, false, 1 << 20));
+    }
+
+    #[test]
+    fn test_scatter_workgroup_storage_size() {
+        // The limit WebGPU guarantees
+        let default_limit = 16_384;
+
+        assert_eq!(
+            scatter_workgroup_storage_size(32, false),
+            (8 * NUMBER_OF_RADIX + NUMBER_OF_RADIX) * NUMBER_OF_BYTES_PER_KEY
+        );
+        // Wider subgroups share the shared memory layout of 32 threads
+        assert_eq!(
+            scatter_workgroup_storage_size(64, false),
+            scatter_workgroup_storage_size(32, false)
+        );
+        assert_eq!(
+            scatter_workgroup_storage_size(0, true),
+            scatter_workgroup_storage_size(32, false)
+                + NUMBER_OF_THREADS_PER_WORKGROUP * NUMBER_OF_BYTES_PER_KEY
+        );
+
+        assert!(multisplit_supported(32, default_limit));
+        assert!(multisplit_supported(64, default_limit));
+        assert!(!multisplit_supported(16, default_limit));
+        assert!(!multisplit_supported(8, default_limit));
+        assert!(multisplit_supported(16, 32_768));
+        assert!(scatter_workgroup_storage_size(0, true) <= default_limit);
+    }
+
+    #[test]
+    fn test_preprocess_wgsl() {
+        let source = "\
+#ifdef A
+a #N #{N}u #N_BITS
+#else
+not a
+#endif
+#ifndef B
+not b
+#ifdef B
+nested b
+#endif // B
+#endif
+";
+        let output = preprocess_wgsl(source, &[("A", None), ("N", Some(7)), ("N_BITS", Some(3))]);
+        assert_eq!(output, "a 7 7u 3\nnot b\n");
+    }
+
+    #[test]
+    fn test_gpu_params_layout() {
+        use wgpu::naga;
+
+        let source = preprocess_wgsl(KEYS_SHADER_SOURCE, &shader_defs(32, false));
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+
+        let (members, span) = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match (&ty.name, &ty.inner) {
+                (Some(name), naga::TypeInner::Struct { members, span })
+                    if name == "RadixSortGpuParams" =>
+                {
+                    Some((members, *span))
+                }
+                _ => None,
+            })
+            .expect("keys.wgsl should define RadixSortGpuParams");
+        let layout: Vec<(&str, u32)> = members
+            .iter()
+            .map(|member| (member.name.as_deref().unwrap(), member.offset))
+            .collect();
+
+        assert_eq!(span, RADIX_SORT_GPU_PARAMS_SIZE);
+        assert_eq!(
+            layout,
+            [
+                ("workgroup_offset", WORKGROUP_OFFSET_OFFSET),
+                ("number_of_keys", NUMBER_OF_KEYS_OFFSET),
+                ("number_of_blks", NUMBER_OF_BLKS_OFFSET),
+                ("pass_index", PASS_INDEX_OFFSET),
+                ("sweep_size", SWEEP_SIZE_OFFSET),
+                ("init_index", INIT_INDEX_OFFSET),
+            ]
+        );
+
+        let version = module
+            .constants
+            .iter()
+            .find(|(_, constant)| constant.name.as_deref() == Some("RADIX_SORT_GPU_PARAMS_VERSION"))
+            .map(|(_, constant)| &module.global_expressions[constant.init]);
+        assert!(matches!(
+            version,
+            Some(naga::Expression::Literal(naga::Literal::U32(
+                RADIX_SORT_GPU_PARAMS_VERSION
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_preprocess_wgsl_import_keys() {
+        let source = "\
+#import bevy_radix_sort::keys::extract_digit
+#import bevy_radix_sort::keys::NUMBER_OF_KEYS_PER_SCATTER_BLOCK
+#import bevy_radix_sort::key_transform::transform_key
+fn f() {}
+";
+        let shader_defs = shader_defs(32, false);
+        let output = preprocess_wgsl(source, &shader_defs);
+        assert_eq!(output.matches("fn extract_digit").count(), 1);
+        assert_eq!(output.matches("fn transform_key").count(), 1);
+        assert!(!output.contains("custom_transform_key"));
+        assert!(!output.contains("#{"));
+        assert!(
+            output
+                .lines()
+                .all(|line| !line.trim_start().starts_with('#'))
+        );
+        assert!(output.ends_with("fn f() {}\n"));
+    }
+}
@@ -0,0 +1,172 @@
+//! The initialization of the sort in the render world, as ordered startup systems:
+//!
+//! ```text
+//!  RadixSortStartupSet::Pipelines   init_radix_sort_pipeline        queues the pipelines
+//!  RadixSortStartupSet::BindGroups  RadixSortBindGroup::initialize  once the global buffers are prepared
+//!  RadixSortStartupSet::Info        publishes the RadixSortPipelineInfo to the main world
+//! ```
+//!
+//! By default the systems run in the [`RadixSortStartup`] schedule of the crate, which [`crate::RadixSortPlugin`]
+//! runs once in `Plugin::finish`, after the `RenderPlugin` created the [`RenderDevice`](bevy::render::renderer::RenderDevice)
+//! and the [`PipelineCache`](bevy::render::render_resource::PipelineCache). With the `render_startup` feature, for
+//! the Bevy versions with a `RenderStartup` schedule, they run in `RenderStartup` instead, which Bevy runs once the
+//! render resources exist, and the main world gets the [`RadixSortPipelineInfo`] at the start of its next frame.
+//!
+//! The global buffers are main world assets, prepared in the `Render` schedule. Until they are, the bind groups
+//! aren't created at startup but by the same system in `RenderSet::PrepareBindGroups`.
+
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(feature = "render_startup"))]
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::{prelude::*, render::RenderApp};
+
+use crate::{
+    RadixSortBindGroup, RadixSortCreationErrors, RadixSortPipeline, RadixSortPipelineInfo,
+    RadixSortUnsupported, init_radix_sort_pipeline,
+};
+
+/// The render world schedule of the startup systems of the sort, run once by [`crate::RadixSortPlugin`], see the
+/// [module docs](self).
+#[cfg(not(feature = "render_startup"))]
+#[derive(ScheduleLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RadixSortStartup;
+
+/// The render world schedule of the startup systems of the sort, run by Bevy, see the [module docs](self).
+#[cfg(feature = "render_startup")]
+pub use bevy::render::RenderStartup as RadixSortStartup;
+
+/// The stages of the [`RadixSortStartup`] systems, in order.
+///
+/// Startup systems of other plugins using the [`RadixSortPipeline`] go after [`RadixSortStartupSet::Pipelines`].
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RadixSortStartupSet {
+    /// Queues the pipelines, or inserts [`RadixSortUnsupported`].
+    Pipelines,
+    /// Creates the [`RadixSortBindGroup`] if the global buffers are already prepared.
+    BindGroups,
+    /// Publishes the [`RadixSortPipelineInfo`] to the main world.
+    Info,
+}
+
+/// The [`RadixSortPipelineInfo`] the render world published and the main world didn't take yet.
+#[derive(Resource, Debug, Clone, Default)]
+pub(crate) struct RadixSortPipelineInfoCell(Arc<Mutex<Option<RadixSortPipelineInfo>>>);
+
+impl RadixSortPipelineInfoCell {
+    fn take(&self) -> Option<RadixSortPipelineInfo> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Adds the [`RadixSortStartup`] systems to the render world, and the cell they publish the info into to both worlds.
+pub(crate) fn build_startup(app: &mut App) {
+    let cell = RadixSortPipelineInfoCell::default();
+
+    #[cfg(feature = "render_startup")]
+    app.insert_resource(cell.clone()).add_systems(
+        First,
+        sync_radix_sort_pipeline_info.run_if(resource_exists::<RadixSortPipelineInfo>),
+    );
+    app.sub_app_mut(RenderApp)
+        .insert_resource(cell)
+        .configure_sets(
+            RadixSortStartup,
+            (
+                RadixSortStartupSet::Pipelines,
+                RadixSortStartupSet::BindGroups,
+                RadixSortStartupSet::Info,
+            )
+                .chain(),
+        )
+        .add_systems(
+            RadixSortStartup,
+            (
+                init_radix_sort_pipeline.in_set(RadixSortStartupSet::Pipelines),
+                RadixSortBindGroup::initialize
+                    .in_set(RadixSortStartupSet::BindGroups)
+                    .run_if(resource_exists::<RadixSortPipeline>)
+                    .run_if(not(resource_exists::<RadixSortBindGroup>))
+                    .run_if(not(resource_exists::<RadixSortCreationErrors>)),
+                publish_radix_sort_pipeline_info.in_set(RadixSortStartupSet::Info),
+            ),
+        );
+}
+
+/// Runs the [`RadixSortStartup`] schedule in the render `world`, and returns the info it published.
+#[cfg(not(feature = "render_startup"))]
+pub(crate) fn run_radix_sort_startup(world: &mut World) -> RadixSortPipelineInfo {
+    world.run_schedule(RadixSortStartup);
+    world
+        .resource::<RadixSortPipelineInfoCell>()
+        .take()
+        .unwrap_or_default()
+}
+
+fn publish_radix_sort_pipeline_info(
+    cell: Res<RadixSortPipelineInfoCell>,
+    radix_sort_pipeline: Option<Res<RadixSortPipeline>>,
+    unsupported: Option<Res<RadixSortUnsupported>>,
+) {
+    let info = match radix_sort_pipeline {
+        Some(radix_sort_pipeline) => RadixSortPipelineInfo {
+            subgroup_fallback: radix_sort_pipeline.subgroup_fallback(),
+            allocate_values: radix_sort_pipeline.allocate_values(),
+            memory_mode: radix_sort_pipeline.memory_mode(),
+            unsupported: None,
+        },
+        None => RadixSortPipelineInfo {
+            unsupported: unsupported.map(|unsupported| unsupported.0.clone()),
+            ..default()
+        },
+    };
+    *cell.0.lock().unwrap() = Some(info);
+}
+
+#[cfg(feature = "render_startup")]
+fn sync_radix_sort_pipeline_info(
+    cell: Res<RadixSortPipelineInfoCell>,
+    mut info: ResMut<RadixSortPipelineInfo>,
+) {
+    if let Some(published) = cell.take() {
+        *info = published;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{prelude::*, render::RenderApp};
+
+    use super::*;
+    use crate::{
+        RadixSortPlugin,
+        test_utils::{create_render_test_app, run_once},
+    };
+
+    #[derive(Resource)]
+    struct SawPipeline(bool);
+
+    fn record_pipeline(
+        mut commands: Commands,
+        radix_sort_pipeline: Option<Res<RadixSortPipeline>>,
+    ) {
+        commands.insert_resource(SawPipeline(radix_sort_pipeline.is_some()));
+    }
+
+    #[test]
+    fn test_startup_systems_after_pipelines() {
+        let mut app = create_render_test_app();
+        app.add_plugins(RadixSortPlugin {
+            settings: 1024.into(),
+        });
+        app.sub_app_mut(RenderApp).add_systems(
+            RadixSortStartup,
+            record_pipeline.after(RadixSortStartupSet::Pipelines),
+        );
+        run_once(&mut app);
+
+        assert!(app.sub_app(RenderApp).world().resource::<SawPipeline>().0);
+        let info = app.world().resource::<RadixSortPipelineInfo>();
+        assert!(info.unsupported.is_none());
+    }
+}
@@ -0,0 +1,472 @@
+//! What a sort actually sorted: the minimum, the maximum and a coarse distribution of the keys.
+//!
+//! With [`RadixSortRunOptions::stats`](crate::RadixSortRunOptions::stats), [`crate::run_with_options`] appends a
+//! statistics pass over the sorted keys. The 76 bytes of results are copied to a staging buffer and read back frames
+//! later by [`poll_sort_stats`], without waiting on the GPU, into the [`RadixSortStats`] resource and event of the
+//! render world:
+//!
+//! ```text
+//!  run_with_options:  sort passes ─▶ clear ─▶ stats(output) ─▶ copy to staging
+//!  Render:            render_system (submits) ─▶ poll_sort_stats: map the new stagings, read the mapped ones
+//! ```
+//!
+//! The 16 buckets split the significant bits of the keys, those of [`RadixSortRunOptions::key_mask`], by their 4
+//! highest bits. Without the option nothing is recorded.
+//!
+//! [`RadixSortRunOptions::key_mask`]: crate::RadixSortRunOptions::key_mask
+
+use std::{
+    num::NonZeroU64,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{
+        BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+        BufferAddress, BufferAsyncError, BufferDescriptor, BufferUsages, CachedComputePipelineId,
+        CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, Maintain, MapMode,
+        PipelineCache, PushConstantRange, ShaderDefVal, ShaderStages,
+        binding_types::storage_buffer_sized,
+    },
+    render::renderer::RenderDevice,
+};
+
+use crate::{
+    LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup, RadixSortPipeline,
+    compute_pipelines_load_state, dispatch_workgroup_ext,
+};
+
+pub const SORT_STATS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(217043021096770774897232526245454751228);
+
+/// The number of buckets of [`RadixSortStats::buckets`].
+pub const NUMBER_OF_STATS_BUCKETS: usize = 16;
+
+/// The size in bytes of the results of the statistics pass, `[!min, max, count, buckets[16]]`.
+pub const STATS_BUFFER_SIZE: BufferAddress =
+    (3 + NUMBER_OF_STATS_BUCKETS) as BufferAddress * std::mem::size_of::<u32>() as BufferAddress;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const BUCKET_SHIFT_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+/// The keys of a sort, computed on the GPU after it.
+#[derive(Resource, Event, Debug, Clone, PartialEq, Eq)]
+pub struct RadixSortStats {
+    /// The number of keys counted, the `number_of_keys` of the sort.
+    pub count: u32,
+    /// `u32::MAX` without any key.
+    pub min: u32,
+    /// `0` without any key.
+    pub max: u32,
+    /// The number of keys per value of their 4 highest significant bits, see [`stats_bucket_shift`].
+    pub buckets: [u32; NUMBER_OF_STATS_BUCKETS],
+    /// The keys shifted right by it give their bucket.
+    pub bucket_shift: u32,
+}
+
+impl RadixSortStats {
+    /// The statistics of `keys` computed on the CPU, the GPU computes the same ones.
+    pub fn from_keys(keys: &[u32], key_mask: u32) -> Self {
+        let bucket_shift = stats_bucket_shift(key_mask);
+        let mut buckets = [0; NUMBER_OF_STATS_BUCKETS];
+        for key in keys {
+            buckets[(key >> bucket_shift) as usize & (NUMBER_OF_STATS_BUCKETS - 1)] += 1;
+        }
+
+        Self {
+            count: keys.len() as u32,
+            min: keys.iter().copied().min().unwrap_or(u32::MAX),
+            max: keys.iter().copied().max().unwrap_or(0),
+            buckets,
+            bucket_shift,
+        }
+    }
+
+    fn from_results(results: [u32; 3 + NUMBER_OF_STATS_BUCKETS], bucket_shift: u32) -> Self {
+        let [inv_min, max, count, buckets @ ..] = results;
+
+        Self {
+            count,
+            min: !inv_min,
+            max,
+            buckets,
+            bucket_shift,
+        }
+    }
+}
+
+/// The shift keeping the 4 highest bits of `key_mask`.
+pub const fn stats_bucket_shift(key_mask: u32) -> u32 {
+    (32 - key_mask.leading_zeros()).saturating_sub(NUMBER_OF_STATS_BUCKETS.trailing_zeros())
+}
+
+/// The statistics pass and the statistics in flight, part of the [`RadixSortPipeline`].
+pub struct SortStatsRecorder {
+    render_device: RenderDevice,
+    stats_pipeline: CachedComputePipelineId,
+    stats_layout: BindGroupLayout,
+    /// The buffers of the statistics read back
+    free: Mutex<Vec<StatsBuffers>>,
+    /// Recorded by [`crate::run_with_options`], not mapped yet
+    recorded: Mutex<Vec<PendingStats>>,
+    /// Mapped after the commands were submitted
+    mapping: Mutex<Vec<PendingStats>>,
+}
+
+struct StatsBuffers {
+    stats: Buffer,
+    staging: Buffer,
+    bind_group: BindGroup,
+}
+
+struct PendingStats {
+    buffers: StatsBuffers,
+    bucket_shift: u32,
+    mapped: Arc<OnceLock<Result<(), BufferAsyncError>>>,
+}
+
+impl std::fmt::Debug for SortStatsRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SortStatsRecorder")
+            .field("stats_pipeline", &self.stats_pipeline)
+            .field("pending", &self.pending())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SortStatsRecorder {
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let stats_layout = render_device.create_bind_group_layout(
+            "radix_sort: stats bind group layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                storage_buffer_sized(false, NonZeroU64::new(STATS_BUFFER_SIZE)),
+            ),
+        );
+
+        let stats_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("radix_sort: stats pipeline".into()),
+            layout: vec![bind_group_layout.clone(), stats_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: SORT_STATS_SHADER_HANDLE,
+            shader_defs: vec![ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            )],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            render_device: render_device.clone(),
+            stats_pipeline,
+            stats_layout,
+            free: default(),
+            recorded: default(),
+            mapping: default(),
+        }
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(pipeline_cache, &[("stats_pipeline", self.stats_pipeline)])
+    }
+
+    /// The number of statistics not read back yet.
+    pub fn pending(&self) -> usize {
+        self.recorded.lock().unwrap().len() + self.mapping.lock().unwrap().len()
+    }
+
+    /// Records the statistics of the sorted keys, on the `EVE_*` side if `output_even`. Returns `false` while the
+    /// pipeline is compiling, the sort then has no statistics.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        key_mask: u32,
+        output_even: bool,
+    ) -> bool {
+        if self.load_state(pipeline_cache) != LoadState::Loaded {
+            return false;
+        }
+
+        let buffers = self
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| self.create_buffers());
+        encoder.clear_buffer(&buffers.stats, 0, None);
+
+        let bucket_shift = stats_bucket_shift(key_mask);
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("radix_sort: stats compute pass"),
+                ..default()
+            });
+            let pipeline = pipeline_cache
+                .get_compute_pipeline(self.stats_pipeline)
+                .unwrap();
+            pass.set_pipeline(pipeline);
+            let keys_bind_group = if output_even {
+                radix_bind_group.eve_bind_group()
+            } else {
+                radix_bind_group.odd_bind_group()
+            };
+            pass.set_bind_group(0, keys_bind_group, &[]);
+            pass.set_bind_group(1, &buffers.bind_group, &[]);
+            pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+            pass.set_push_constants(BUCKET_SHIFT_OFFSET, bytemuck::bytes_of(&bucket_shift));
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(&buffers.stats, 0, &buffers.staging, 0, STATS_BUFFER_SIZE);
+        self.recorded.lock().unwrap().push(PendingStats {
+            buffers,
+            bucket_shift,
+            mapped: default(),
+        });
+
+        true
+    }
+
+    /// Maps the statistics recorded since the last call, whose commands must have been submitted, and reads the
+    /// ones mapped since, in the order they were recorded. Never blocks.
+    pub fn poll(&self) -> Vec<RadixSortStats> {
+        let mut mapping = self.mapping.lock().unwrap();
+
+        for pending in std::mem::take(&mut *self.recorded.lock().unwrap()) {
+            let mapped = pending.mapped.clone();
+            pending
+                .buffers
+                .staging
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    let _ = mapped.set(result);
+                });
+            mapping.push(pending);
+        }
+
+        self.render_device.poll(Maintain::Poll);
+
+        // The mappings complete in submission order, the first pending ones are done first
+        let done = mapping
+            .iter()
+            .take_while(|pending| pending.mapped.get().is_some())
+            .count();
+
+        let mut stats = Vec::new();
+        for PendingStats {
+            buffers,
+            bucket_shift,
+            mapped,
+        } in mapping.drain(..done)
+        {
+            match mapped.get() {
+                Some(Ok(())) => {
+                    let results =
+                        bytemuck::pod_read_unaligned(&buffers.staging.slice(..).get_mapped_range());
+                    buffers.staging.unmap();
+                    stats.push(RadixSortStats::from_results(results, bucket_shift));
+                }
+                Some(Err(err)) => {
+                    warn!(
+                        "radix_sort: failed to read back the stats of a sort: {}",
+                        err
+                    );
+                    continue;
+                }
+                None => unreachable!(),
+            }
+
+            self.free.lock().unwrap().push(buffers);
+        }
+
+        stats
+    }
+
+    fn create_buffers(&self) -> StatsBuffers {
+        let stats = self.render_device.create_buffer(&BufferDescriptor {
+            label: Some("radix_sort: stats buffer"),
+            size: STATS_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging = self.render_device.create_buffer(&BufferDescriptor {
+            label: Some("radix_sort: stats staging buffer"),
+            size: STATS_BUFFER_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.render_device.create_bind_group(
+            "radix_sort: stats bind group",
+            &self.stats_layout,
+            &BindGroupEntries::single(stats.as_entire_binding()),
+        );
+
+        StatsBuffers {
+            stats,
+            staging,
+            bind_group,
+        }
+    }
+}
+
+/// Reads back the statistics of the [`RadixSortPipeline`], after the render graph submitted its commands, into the
+/// [`RadixSortStats`] resource, the latest one, and events.
+pub fn poll_sort_stats(
+    mut commands: Commands,
+    radix_sort_pipeline: Res<RadixSortPipeline>,
+    mut events: ResMut<Events<RadixSortStats>>,
+) {
+    // The render world doesn't run the `First` schedule updating the events
+    events.update();
+
+    let stats = radix_sort_pipeline.stats().poll();
+    if let Some(last) = stats.last() {
+        commands.insert_resource(last.clone());
+    }
+    events.send_batch(stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssets, render_resource::CommandEncoderDescriptor,
+        renderer::RenderQueue, storage::GpuShaderStorageBuffer,
+    };
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, RadixSortPreset, RadixSortRunOptions,
+        global_keys_buffer, run_with_options,
+        test_utils::{create_render_test_app, run_render_system_once},
+    };
+
+    use super::*;
+
+    /// Reads back every pending statistics.
+    fn poll_until_read(
+        render_device: &RenderDevice,
+        recorder: &SortStatsRecorder,
+    ) -> Vec<RadixSortStats> {
+        let mut stats = recorder.poll();
+        while recorder.pending() > 0 {
+            render_device.poll(Maintain::Wait).panic_on_timeout();
+            stats.extend(recorder.poll());
+        }
+
+        stats
+    }
+
+    #[test]
+    fn test_from_keys() {
+        assert_eq!(stats_bucket_shift(u32::MAX), 28);
+        assert_eq!(stats_bucket_shift(0xFFFF), 12);
+        assert_eq!(stats_bucket_shift(0x7), 0);
+
+        let stats = RadixSortStats::from_keys(&[0x1234, 0xF000, 0x0001], 0xFFFF);
+        assert_eq!((stats.count, stats.min, stats.max), (3, 0x0001, 0xF000));
+        assert_eq!(stats.buckets[0], 1);
+        assert_eq!(stats.buckets[1], 1);
+        assert_eq!(stats.buckets[15], 1);
+
+        let empty = RadixSortStats::from_keys(&[], u32::MAX);
+        assert_eq!((empty.count, empty.min, empty.max), (0, u32::MAX, 0));
+    }
+
+    #[test]
+    fn test_sort_stats() {
+        let number_of_keys = 50_003;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: number_of_keys.into(),
+            });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let recorder = radix_sort_pipeline.stats();
+                let mut rng = StdRng::seed_from_u64(155);
+
+                for options in [
+                    RadixSortRunOptions::default().with_stats(),
+                    RadixSortPreset::Depth16.options().with_stats(),
+                ] {
+                    let keys: Vec<u32> = (0..number_of_keys)
+                        .map(|_| rng.r#gen::<u32>() & options.key_mask)
+                        .collect();
+                    let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                    render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: stats command encoder"),
+                        });
+                    run_with_options(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        &options,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+                    assert_eq!(recorder.pending(), 1);
+
+                    let stats = poll_until_read(&render_device, recorder);
+                    assert_eq!(
+                        stats,
+                        vec![RadixSortStats::from_keys(&keys, options.key_mask)],
+                        "{options:?}"
+                    );
+                }
+
+                // Nothing is recorded without the option
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: stats command encoder"),
+                });
+                run_with_options(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    &RadixSortRunOptions::default(),
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+                assert_eq!(recorder.pending(), 0);
+            },
+        );
+    }
+}
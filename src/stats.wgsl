@@ -0,0 +1,60 @@
+// The statistics of `RadixSortRunOptions::stats`, on the bindings of `radix_sort.wgsl`.
+
+/// The keys of one side of the global buffers
+@group(0) @binding(0) var<storage, read      > global_keys_i: array<u32>;
+
+/// `[!min, max, count, buckets[16]]`, cleared before the pass: the minimum is the maximum of the complements
+@group(1) @binding(0) var<storage, read_write> stats: array<atomic<u32>, 19>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys sorted.
+    number_of_keys: u32,
+    /// The keys shifted by it keep their 4 highest significant bits, their bucket.
+    bucket_shift: u32,
+}
+var<push_constant> pc: PushConstants;
+
+var<workgroup> local_inv_min: atomic<u32>;
+var<workgroup> local_max: atomic<u32>;
+var<workgroup> local_buckets: array<atomic<u32>, 16>;
+
+/// Reduced in shared memory first, then one atomic per workgroup and non-empty bucket.
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    if local_invocation_index < 16u {
+        atomicStore(&local_buckets[local_invocation_index], 0u);
+    }
+    if local_invocation_index == 0u {
+        atomicStore(&local_inv_min, 0u);
+        atomicStore(&local_max, 0u);
+    }
+    workgroupBarrier();
+
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_index;
+    if index < pc.number_of_keys {
+        let key = global_keys_i[index];
+        atomicMax(&local_inv_min, ~key);
+        atomicMax(&local_max, key);
+        atomicAdd(&local_buckets[(key >> pc.bucket_shift) & 15u], 1u);
+    }
+    workgroupBarrier();
+
+    if local_invocation_index == 0u {
+        atomicMax(&stats[0], atomicLoad(&local_inv_min));
+        atomicMax(&stats[1], atomicLoad(&local_max));
+    }
+    if local_invocation_index < 16u {
+        let count = atomicLoad(&local_buckets[local_invocation_index]);
+        if count > 0u {
+            atomicAdd(&stats[2], count);
+            atomicAdd(&stats[3u + local_invocation_index], count);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! The load state of the sort in the main world, e.g. for a UI reporting that GPU sorting is unavailable.
+//!
+//! The render world publishes its state once per frame into a cell shared with the main world, which copies it into
+//! the [`RadixSortStatus`] at the start of its next frame:
+//!
+//! ```text
+//!  render world (Cleanup)                         main world (First)
+//!  check_load_state, device lost  ──▶  cell  ──▶  RadixSortStatus
+//! ```
+//!
+//! The status lags the render world by a frame, two with pipelined rendering. Each change is also sent as a
+//! [`RadixSortStatusChanged`] event, e.g. to log how long each stage of the loading took.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        renderer::{RenderAdapterInfo, RenderDevice},
+    },
+};
+
+use crate::{LoadProgress, LoadState, try_check_load_state};
+
+/// The main world mirror of [`crate::check_load_state`], inserted by the [`crate::RadixSortPlugin`].
+///
+/// [`crate::radix_sort_loaded`] and [`crate::radix_sort_failed`] read it in main world systems.
+#[derive(Resource, Reflect, Debug, Clone, PartialEq, Eq)]
+#[reflect(Resource, Debug, Default)]
+pub enum RadixSortStatus {
+    /// The pipelines or the bind group of the sort aren't ready yet, or the sort is reinitializing, see
+    /// [`LoadState::OnLoad`].
+    Loading(LoadProgress),
+    /// [`crate::run`] can record the sort.
+    Ready,
+    /// The sort can't run, with [`LoadState::Failed`] or once the device is lost.
+    Failed {
+        message: String,
+        /// The name of the adapter, empty without one.
+        adapter: String,
+    },
+    /// See [`LoadState::FallbackCpu`].
+    FallbackCpu,
+}
+
+impl Default for RadixSortStatus {
+    fn default() -> Self {
+        Self::Loading(default())
+    }
+}
+
+/// Sent in the main world when the [`RadixSortStatus`] changes, with the new status.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct RadixSortStatusChanged(pub RadixSortStatus);
+
+/// The status published by the render world, and the device loss reported by wgpu from any thread.
+#[derive(Debug, Default)]
+struct StatusCell {
+    status: Mutex<RadixSortStatus>,
+    device_lost: OnceLock<String>,
+}
+
+/// The [`StatusCell`] shared by both worlds.
+#[derive(Resource, Debug, Clone, Default)]
+pub(crate) struct RadixSortStatusCell(Arc<StatusCell>);
+
+impl RadixSortStatusCell {
+    pub(crate) fn publish(&self, status: RadixSortStatus) {
+        *self.0.status.lock().unwrap() = status;
+    }
+
+    /// Reports [`RadixSortStatus::Failed`] once wgpu loses the device, e.g. after a driver reset.
+    ///
+    /// Replaces the device lost callback of the device, Bevy doesn't set one.
+    pub(crate) fn watch_device_lost(&self, render_device: &RenderDevice) {
+        let cell = self.0.clone();
+        render_device
+            .wgpu_device()
+            .set_device_lost_callback(move |reason, message| {
+                // The other reasons are the device being dropped or the callback replaced
+                if matches!(reason, wgpu::DeviceLostReason::Unknown) {
+                    let _ = cell
+                        .device_lost
+                        .set(format!("The device was lost: {message}"));
+                }
+            });
+    }
+}
+
+/// Adds the [`RadixSortStatus`], the cell and the systems keeping them in sync to both worlds.
+pub(crate) fn build_status(app: &mut App) {
+    let cell = RadixSortStatusCell::default();
+
+    app.register_type::<RadixSortStatus>()
+        .register_type::<LoadProgress>()
+        .init_resource::<RadixSortStatus>()
+        .add_event::<RadixSortStatusChanged>()
+        .insert_resource(cell.clone())
+        .add_systems(
+            First,
+            sync_radix_sort_status.run_if(resource_exists::<RadixSortStatusCell>),
+        );
+    app.sub_app_mut(RenderApp)
+        .insert_resource(cell)
+        .add_systems(
+            Render,
+            publish_radix_sort_status
+                .in_set(RenderSet::Cleanup)
+                .run_if(resource_exists::<RadixSortStatusCell>),
+        );
+}
+
+/// Publishes the state of the sort, left as is until the plugin created its resources.
+fn publish_radix_sort_status(world: &World) {
+    let cell = world.resource::<RadixSortStatusCell>();
+    let failed = |message: String| RadixSortStatus::Failed {
+        message,
+        adapter: world
+            .get_resource::<RenderAdapterInfo>()
+            .map(|adapter_info| adapter_info.name.clone())
+            .unwrap_or_default(),
+    };
+
+    let status = match (cell.0.device_lost.get(), try_check_load_state(world)) {
+        (Some(message), _) => failed(message.clone()),
+        (None, None) => return,
+        (None, Some(LoadState::Loaded)) => RadixSortStatus::Ready,
+        (None, Some(LoadState::OnLoad(progress))) => RadixSortStatus::Loading(progress),
+        (None, Some(LoadState::Failed(message))) => failed(message),
+        (None, Some(LoadState::FallbackCpu)) => RadixSortStatus::FallbackCpu,
+    };
+    cell.publish(status);
+}
+
+fn sync_radix_sort_status(
+    cell: Res<RadixSortStatusCell>,
+    mut status: ResMut<RadixSortStatus>,
+    mut status_changed: EventWriter<RadixSortStatusChanged>,
+) {
+    let published = cell.0.status.lock().unwrap();
+    if *status != *published {
+        *status = published.clone();
+        status_changed.send(RadixSortStatusChanged(published.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::settings::{WgpuLimits, WgpuSettings};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, radix_sort_failed, radix_sort_loaded,
+        test_utils::{create_render_test_app, create_render_test_app_with_settings, run_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_status_ready() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+        assert_eq!(
+            *app.world().resource::<RadixSortStatus>(),
+            RadixSortStatus::Loading(LoadProgress::WaitingForSubgroupSize)
+        );
+        assert!(!radix_sort_loaded()(app.world()));
+
+        // Published by the first frame, mirrored at the start of the next one
+        app.update();
+        assert_eq!(
+            *app.world().resource::<RadixSortStatus>(),
+            RadixSortStatus::Ready
+        );
+        assert!(radix_sort_loaded()(app.world()));
+        assert!(!radix_sort_failed()(app.world()));
+
+        let events = app.world().resource::<Events<RadixSortStatusChanged>>();
+        assert_eq!(
+            events.get_cursor().read(events).last(),
+            Some(&RadixSortStatusChanged(RadixSortStatus::Ready))
+        );
+    }
+
+    #[test]
+    fn test_status_failed() {
+        let mut app = create_render_test_app_with_settings(WgpuSettings {
+            constrained_limits: Some(WgpuLimits {
+                max_storage_buffers_per_shader_stage: 4,
+                max_push_constant_size: 128,
+                max_subgroup_size: u32::MAX,
+                ..default()
+            }),
+            ..default()
+        });
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+        app.update();
+
+        let adapter_name = app
+            .sub_app(RenderApp)
+            .world()
+            .resource::<RenderAdapterInfo>()
+            .name
+            .clone();
+        let RadixSortStatus::Failed { message, adapter } =
+            app.world().resource::<RadixSortStatus>()
+        else {
+            panic!("the sort should fail to load with 4 storage buffers per stage");
+        };
+        assert!(
+            message.contains("max_storage_buffers_per_shader_stage"),
+            "{message}"
+        );
+        assert_eq!(*adapter, adapter_name);
+        assert!(radix_sort_failed()(app.world()));
+        assert!(!radix_sort_loaded()(app.world()));
+    }
+}
@@ -0,0 +1,156 @@
+//! Persists the probed [`SubgroupSize`] across runs.
+//!
+//! The probe dispatch plus readback costs a visible chunk of startup time on slow drivers,
+//! but the answer never changes for a given adapter, so it is stored in a small text file
+//! keyed by the adapter's backend, vendor, device and driver, see [`AdapterCacheKey`].
+//!
+//! Every line of the file is an entry of the form
+//! `<backend>\t<vendor>\t<device>\t<driver>\t<driver_info>\t<subgroup_size>`. Lines that can't be parsed are
+//! ignored.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{AdapterCacheKey, SubgroupSize, adapter_cache::platform_cache_dir};
+
+const CACHE_FILE_NAME: &str = "subgroup_size.txt";
+
+/// Identifies the adapter a cached [`SubgroupSize`] belongs to.
+pub type SubgroupSizeCacheKey = AdapterCacheKey;
+
+#[derive(Debug, Clone)]
+pub struct SubgroupSizeCache {
+    path: PathBuf,
+}
+
+impl SubgroupSizeCache {
+    /// Creates a cache stored in `dir`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            path: dir.as_ref().join(CACHE_FILE_NAME),
+        }
+    }
+
+    /// Creates a cache stored in the platform cache directory (e.g. `~/.cache/bevy_radix_sort` on Linux).
+    ///
+    /// Returns `None` if the platform has no cache directory.
+    pub fn in_platform_cache_dir() -> Option<Self> {
+        platform_cache_dir().map(Self::new)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the cached subgroup size of the adapter, `None` on a miss or a corrupted entry.
+    pub fn load(&self, key: &SubgroupSizeCacheKey) -> Option<SubgroupSize> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let prefix = key.to_fields();
+
+        contents.lines().find_map(|line| {
+            let (line_prefix, value) = line.rsplit_once('\t')?;
+            if line_prefix != prefix {
+                return None;
+            }
+
+            let subgroup_size = SubgroupSize::try_from(value.trim().parse::<u32>().ok()?).ok()?;
+            subgroup_size.log2().is_ok().then_some(subgroup_size)
+        })
+    }
+
+    /// Stores the subgroup size of the adapter, replacing a previous entry of the same adapter.
+    pub fn store(&self, key: &SubgroupSizeCacheKey, subgroup_size: SubgroupSize) -> io::Result<()> {
+        let prefix = key.to_fields();
+        let contents = fs::read_to_string(&self.path).unwrap_or_default();
+
+        let mut lines: Vec<String> = contents
+            .lines()
+            .filter(|line| {
+                line.rsplit_once('\t')
+                    .is_some_and(|(line_prefix, _)| line_prefix != prefix)
+            })
+            .map(str::to_owned)
+            .collect();
+        lines.push(format!("{}\t{}", prefix, u32::from(subgroup_size)));
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, lines.join("\n") + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::temp_dir;
+
+    use super::*;
+
+    fn temp_cache(name: &str) -> SubgroupSizeCache {
+        SubgroupSizeCache::new(temp_dir(name))
+    }
+
+    fn size(value: u32) -> SubgroupSize {
+        SubgroupSize::try_from(value).unwrap()
+    }
+
+    fn key(device: u32) -> SubgroupSizeCacheKey {
+        SubgroupSizeCacheKey {
+            backend: "Vulkan".into(),
+            vendor: 0x10de,
+            device,
+            driver: "NVIDIA".into(),
+            driver_info: "566.36".into(),
+        }
+    }
+
+    #[test]
+    fn test_cache_miss() {
+        let cache = temp_cache("miss");
+        assert!(cache.load(&key(0x2705)).is_none());
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let cache = temp_cache("hit");
+        cache.store(&key(0x2705), size(32)).unwrap();
+        assert_eq!(cache.load(&key(0x2705)), Some(size(32)));
+
+        // Refreshing replaces the entry instead of appending a duplicate
+        cache.store(&key(0x2705), size(64)).unwrap();
+        assert_eq!(cache.load(&key(0x2705)), Some(size(64)));
+        assert_eq!(fs::read_to_string(cache.path()).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_cache_mismatch() {
+        let cache = temp_cache("mismatch");
+        cache.store(&key(0x2705), size(32)).unwrap();
+
+        assert!(cache.load(&key(0x2704)).is_none());
+        let other_driver = SubgroupSizeCacheKey {
+            driver_info: "572.16".into(),
+            ..key(0x2705)
+        };
+        assert!(cache.load(&other_driver).is_none());
+    }
+
+    #[test]
+    fn test_cache_corrupted() {
+        let cache = temp_cache("corrupted");
+        fs::create_dir_all(cache.path().parent().unwrap()).unwrap();
+
+        let prefix = key(0x2705).to_fields();
+        fs::write(cache.path(), format!("garbage\n{prefix}\tnot-a-number\n")).unwrap();
+        assert!(cache.load(&key(0x2705)).is_none());
+
+        fs::write(cache.path(), format!("{prefix}\t48\n")).unwrap();
+        assert!(cache.load(&key(0x2705)).is_none());
+
+        // A corrupted file doesn't prevent storing a fresh entry
+        cache.store(&key(0x2705), size(32)).unwrap();
+        assert_eq!(cache.load(&key(0x2705)), Some(size(32)));
+    }
+}
@@ -0,0 +1,260 @@
+//! Swapping the `EVE_*` and `ODD_*` global buffers, so the `EVE_*` handles always name the sorted buffers.
+//!
+//! A sort with an odd number of passes leaves its results on the other side of the global buffers, a bind group
+//! created from the `EVE_*` handles then reads the unsorted input. [`swap_global_buffers`] swaps the GPU buffers
+//! behind the handles in the render world, and the sides of the [`RadixSortBindGroup`] with them, so the sort keeps
+//! reading the side `read_from_even` names:
+//!
+//! ```text
+//!                  before the swap          after the swap
+//!  EVE_* handles   buffers A (input)  ──▶   buffers B (sorted)
+//!  ODD_* handles   buffers B (sorted) ──▶   buffers A
+//! ```
+//!
+//! With [`crate::RadixSortJobsConfig::swap_global_buffers`], the jobs on the global buffers swap them after the
+//! frames whose last global job ends on the `ODD_*` side. The bind groups created from the handles (materials,
+//! the bind groups of the helper passes) must be recreated on a [`GlobalBuffersSwapped`], sent in both worlds: a
+//! material is prepared again once its asset is touched, e.g. with `materials.get_mut(&handle)`.
+//!
+//! The sort overwrites both sides while it runs: a bind group reading the sorted side also sees the next sort in
+//! progress, for an odd number of passes above one. The simpler alternative is to run an even number of passes,
+//! or to read the side [`crate::is_output_even`] names, or to copy the results out with
+//! [`crate::SortJob::with_on_complete`].
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet, render_asset::RenderAssets, storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+    ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+    RadixSortBindGroup,
+};
+
+/// Sent by [`swap_global_buffers`] in the render world, and in the main world at the start of its next frame.
+///
+/// The render world events can be read until the end of the next frame.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalBuffersSwapped {
+    /// The number of swaps since startup, the `EVE_*` handles name the buffers created for them while it's even.
+    pub swaps: u32,
+}
+
+/// The number of swaps, shared by both worlds.
+#[derive(Resource, Debug, Clone, Default)]
+pub(crate) struct GlobalBuffersSwaps(Arc<AtomicU32>);
+
+/// Swaps the `EVE_*` and `ODD_*` global keys buffers, and the vals buffers unless the sort is keys-only, in the
+/// [`RenderApp`] world. The keys and vals stay paired, the next sort reading the even side sorts the buffers the
+/// `EVE_*` handles name now.
+///
+/// Call it after recording a sort whose results end on the `ODD_*` side, see [`crate::is_output_even`], before
+/// the bind groups reading the results are created.
+pub fn swap_global_buffers(world: &mut World) {
+    let Some(mut sbufs) = world.get_resource_mut::<RenderAssets<GpuShaderStorageBuffer>>() else {
+        return;
+    };
+
+    for (eve, odd) in [
+        (
+            EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+        ),
+        (
+            EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+            ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+        ),
+    ] {
+        // Keys-only sorts have no global vals buffers
+        let (Some(eve_buf), Some(odd_buf)) = (sbufs.remove(eve.id()), sbufs.remove(odd.id()))
+        else {
+            continue;
+        };
+        sbufs.insert(eve.id(), odd_buf);
+        sbufs.insert(odd.id(), eve_buf);
+    }
+
+    if let Some(mut radix_sort_bind_group) = world.get_resource_mut::<RadixSortBindGroup>() {
+        radix_sort_bind_group.swap_sides();
+    }
+
+    let swaps = world
+        .get_resource::<GlobalBuffersSwaps>()
+        .map_or(0, |swaps| swaps.0.fetch_add(1, Ordering::Relaxed) + 1);
+    world.send_event(GlobalBuffersSwapped { swaps });
+}
+
+/// Adds the [`GlobalBuffersSwapped`] events to both worlds.
+pub(crate) fn build_swap(app: &mut App) {
+    let swaps = GlobalBuffersSwaps::default();
+
+    app.add_event::<GlobalBuffersSwapped>()
+        .insert_resource(swaps.clone())
+        .add_systems(
+            First,
+            send_global_buffers_swapped.run_if(resource_exists::<GlobalBuffersSwaps>),
+        );
+    app.sub_app_mut(RenderApp)
+        .init_resource::<Events<GlobalBuffersSwapped>>()
+        .insert_resource(swaps)
+        .add_systems(
+            Render,
+            update_global_buffers_swapped.in_set(RenderSet::ExtractCommands),
+        );
+}
+
+/// The render world doesn't run the `First` schedule updating the events.
+fn update_global_buffers_swapped(mut events: ResMut<Events<GlobalBuffersSwapped>>) {
+    events.update();
+}
+
+fn send_global_buffers_swapped(
+    swaps: Res<GlobalBuffersSwaps>,
+    mut sent: Local<u32>,
+    mut events: EventWriter<GlobalBuffersSwapped>,
+) {
+    let swaps = swaps.0.load(Ordering::Relaxed);
+    if swaps != *sent {
+        *sent = swaps;
+        events.send(GlobalBuffersSwapped { swaps });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::renderer::{RenderDevice, RenderQueue};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortJobs, RadixSortJobsConfig, RadixSortPlugin,
+        RadixSortRunOptions, SortJob, global_keys_buffer, global_vals_buffer,
+        test_utils::{create_render_test_app, read_buffer, run_once},
+    };
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct SwapsSeen(u32);
+
+    /// Pushes a job sorting `keys` in `pass_count` passes, uploaded into the even global keys buffer.
+    fn push_job(app: &mut App, keys: &[u32], pass_count: u32) {
+        let world = app.sub_app_mut(RenderApp).world_mut();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        world.resource::<RenderQueue>().write_buffer(
+            global_keys_buffer(sbufs, true).unwrap(),
+            0,
+            bytemuck::cast_slice(keys),
+        );
+
+        let options = RadixSortRunOptions {
+            pass_range: 0..pass_count,
+            ..default()
+        };
+        world
+            .resource_mut::<RadixSortJobs>()
+            .push(SortJob::new(keys.len() as u32).with_options(options));
+    }
+
+    /// Reads the keys and vals a bind group created from the `EVE_*` handles would read.
+    fn read_even_side(app: &App, len: usize) -> (Vec<u32>, Vec<u32>) {
+        let world = app.sub_app(RenderApp).world();
+        let render_device = world.resource::<RenderDevice>();
+        let render_queue = world.resource::<RenderQueue>();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+
+        (
+            read_buffer(
+                render_device,
+                render_queue,
+                global_keys_buffer(sbufs, true).unwrap(),
+                len,
+            ),
+            read_buffer(
+                render_device,
+                render_queue,
+                global_vals_buffer(sbufs, true).unwrap(),
+                len,
+            ),
+        )
+    }
+
+    #[test]
+    fn test_swap_global_buffers_after_jobs() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            })
+            .init_resource::<SwapsSeen>()
+            .add_systems(
+                Update,
+                |mut events: EventReader<GlobalBuffersSwapped>, mut seen: ResMut<SwapsSeen>| {
+                    seen.0 += events.read().count() as u32;
+                },
+            );
+        run_once(&mut app);
+        app.sub_app_mut(RenderApp)
+            .world_mut()
+            .resource_mut::<RadixSortJobsConfig>()
+            .swap_global_buffers = true;
+
+        // The low byte only, so one pass sorts them
+        let keys: Vec<u32> = (0..1000u32).map(|i| (i * 7919) % 256).collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort_by_key(|&(key, _)| key);
+
+        // Odd and even pass counts: the even side holds the results either way
+        for (pass_count, swaps) in [(1, 1), (2, 1), (1, 2)] {
+            push_job(&mut app, &keys, pass_count);
+            app.update();
+
+            let (sorted_keys, sorted_vals) = read_even_side(&app, keys.len());
+            let sorted: Vec<(u32, u32)> = sorted_keys.into_iter().zip(sorted_vals).collect();
+            assert_eq!(sorted, expected, "{pass_count} passes");
+
+            let render_world = app.sub_app(RenderApp).world();
+            let events = render_world.resource::<Events<GlobalBuffersSwapped>>();
+            assert_eq!(
+                events.iter_current_update_events().last().map(|e| e.swaps),
+                (pass_count % 2 == 1).then_some(swaps),
+            );
+        }
+
+        // Forwarded to the main world at the start of the next frame
+        app.update();
+        assert_eq!(app.world().resource::<SwapsSeen>().0, 2);
+    }
+
+    #[test]
+    fn test_swap_global_buffers() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            });
+        run_once(&mut app);
+
+        let world = app.sub_app_mut(RenderApp).world_mut();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let eve_keys = global_keys_buffer(sbufs, true).unwrap().id();
+        let odd_keys = global_keys_buffer(sbufs, false).unwrap().id();
+        let eve_bind_group = world.resource::<RadixSortBindGroup>().eve_bind_group().id();
+
+        swap_global_buffers(world);
+
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        assert_eq!(global_keys_buffer(sbufs, true).unwrap().id(), odd_keys);
+        assert_eq!(global_keys_buffer(sbufs, false).unwrap().id(), eve_keys);
+        assert_eq!(
+            world.resource::<RadixSortBindGroup>().odd_bind_group().id(),
+            eve_bind_group
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Shared helpers for the GPU unit tests.
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderPlugin, RenderSet,
+        render_resource::{
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer, BufferAddress,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+            CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor,
+            ComputePipelineDescriptor, Maintain, MapMode, PipelineCache, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only},
+        },
+        renderer::{RenderDevice, RenderQueue},
+        settings::WgpuSettings,
+    },
+    scene::ScenePlugin,
+    window::ExitCondition,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+/// Creates an app with the render plugin set up for tests (no surface, synchronous pipeline compilation).
+pub fn create_render_test_app() -> App {
+    create_render_test_app_with_settings(WgpuSettings::default())
+}
+
+/// [`create_render_test_app`] requesting the device with `wgpu_settings`, e.g. to clamp its limits.
+pub fn create_render_test_app_with_settings(wgpu_settings: WgpuSettings) -> App {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(WindowPlugin::default())
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(ScenePlugin)
+        .add_plugins(RenderPlugin {
+            render_creation: wgpu_settings.into(),
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default());
+
+    app
+}
+
+/// Creates an app without any window (no primary window, no surface), like a command-line tool would.
+pub fn create_headless_render_test_app() -> App {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(RenderPlugin {
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .add_plugins(ImagePlugin::default());
+
+    app
+}
+
+pub fn run_once(app: &mut App) {
+    app.finish();
+    app.cleanup();
+
+    app.update();
+}
+
+/// `number_of_keys` keys below `modulo`, the same ones for the same `seed` on every run.
+pub fn random_keys(seed: u64, number_of_keys: u32, modulo: u32) -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..number_of_keys)
+        .map(|_| rng.gen_range(0..modulo))
+        .collect()
+}
+
+/// Creates a `STORAGE | COPY_SRC | COPY_DST` buffer initialized with `contents`.
+pub fn create_storage_buffer(render_device: &RenderDevice, contents: &[u32]) -> Buffer {
+    render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("unit_test: storage buffer"),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        contents: bytemuck::cast_slice(contents),
+    })
+}
+
+/// Copies the first `len` u32s of `buffer` into a staging buffer and reads them back, blocking until done.
+pub fn read_buffer(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    buffer: &Buffer,
+    len: usize,
+) -> Vec<u32> {
+    let size = (len * std::mem::size_of::<u32>()) as BufferAddress;
+    if size == 0 {
+        return Vec::new();
+    }
+
+    let staging_buf = render_device.create_buffer(&BufferDescriptor {
+        label: Some("unit_test: readback staging buffer"),
+        size,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("unit_test: readback command encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buf, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    let slice = staging_buf.slice(0..size);
+    slice.map_async(MapMode::Read, |_| ());
+    render_device.poll(Maintain::Wait).panic_on_timeout();
+
+    let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging_buf.unmap();
+
+    data
+}
+
+/// Runs `system` once in the render world, after the pipelines queued during `finish` have been compiled.
+pub fn run_render_system_once<M>(app: &mut App, system: impl IntoSystemConfigs<M>) {
+    app.sub_app_mut(RenderApp)
+        .add_systems(Render, system.in_set(RenderSet::Cleanup));
+
+    run_once(app);
+}
+
+const TEST_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(114729382593984072602211472143263419822);
+
+/// Compiles a WGSL source through the pipeline cache, like a user shader importing the WGSL modules of the crate.
+///
+/// The shader must bind `inputs: array<u32>` (read) at `@binding(0)` and `outputs: array<u32>` (read_write)
+/// at `@binding(1)`, its entry point is `main`. Requires the plugins loading the imported modules.
+pub struct TestShaderPlugin(pub &'static str);
+
+impl Plugin for TestShaderPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut()
+            .resource_mut::<Assets<Shader>>()
+            .insert(TEST_SHADER_HANDLE.id(), Shader::from_wgsl(self.0, file!()));
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TestShaderPipeline>();
+    }
+}
+
+/// The pipeline of the [`TestShaderPlugin`].
+#[derive(Resource)]
+pub struct TestShaderPipeline {
+    pipeline: CachedComputePipelineId,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for TestShaderPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "unit_test: test shader bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("unit_test: test shader pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![],
+            shader: TEST_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl TestShaderPipeline {
+    /// Dispatches `number_of_workgroups` workgroups over `inputs` (not empty) and reads back `len` outputs.
+    ///
+    /// Panics if the shader didn't compile.
+    pub fn run(
+        &self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+        inputs: &[u32],
+        len: usize,
+        number_of_workgroups: u32,
+    ) -> Vec<u32> {
+        let inputs_buf = create_storage_buffer(render_device, inputs);
+        let outputs_buf = create_storage_buffer(render_device, &vec![0; len.max(1)]);
+
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("unit_test: test shader command encoder"),
+        });
+        self.record(
+            &mut encoder,
+            render_device,
+            pipeline_cache,
+            &inputs_buf,
+            &outputs_buf,
+            number_of_workgroups,
+        );
+        render_queue.submit([encoder.finish()]);
+
+        read_buffer(render_device, render_queue, &outputs_buf, len)
+    }
+
+    /// Records `number_of_workgroups` workgroups over the buffers, e.g. to feed the outputs to another pass of the
+    /// same encoder.
+    ///
+    /// Panics if the shader didn't compile.
+    pub fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        inputs_buf: &Buffer,
+        outputs_buf: &Buffer,
+        number_of_workgroups: u32,
+    ) {
+        let pipeline = pipeline_cache
+            .get_compute_pipeline(self.pipeline)
+            .expect("the test shader should compile");
+
+        let bind_group = render_device.create_bind_group(
+            "unit_test: test shader bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                inputs_buf.as_entire_binding(),
+                outputs_buf.as_entire_binding(),
+            )),
+        );
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(number_of_workgroups, 1, 1);
+    }
+}
+
+/// An empty directory of the temp directory named after `name` and the test process, for the persisted caches.
+#[cfg(any(feature = "subgroup_size_cache", feature = "pipeline_cache"))]
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("bevy_radix_sort_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A location in this file, e.g. for a claim of the global buffers by another plugin than the test.
+pub fn test_utils_location() -> &'static std::panic::Location<'static> {
+    std::panic::Location::caller()
+}
@@ -0,0 +1,555 @@
+//! Top-k partial sort: the `k` smallest keys (and their vals) in ascending order.
+//!
+//! Instead of sorting all the keys, the threshold key is selected digit by digit from the most significant one (MSD-style
+//! selection on a global histogram of the keys matching the digits selected so far), then only the `k` candidates are
+//! compacted and sorted with [`crate::run`].
+//!
+//! Ties at the boundary are resolved by the index of the keys: among the keys equal to the `k`-th smallest key,
+//! the ones with the lowest indices are kept. The candidates keep the order of the input, so the result is exactly
+//! the first `k` pairs of a stable sort.
+
+use std::num::NonZeroU64;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferDescriptor, BufferUsages, CachedComputePipelineId, CommandEncoder,
+            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, PushConstantRange,
+            ShaderDefVal, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only, storage_buffer_sized},
+        },
+        renderer::RenderDevice,
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use crate::{
+    LoadState, NUMBER_OF_BYTES_PER_KEY, NUMBER_OF_RADIX, NUMBER_OF_RADIX_BITS,
+    NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup, RadixSortPipeline, RadixSortSettings,
+    compute_pipelines_load_state, dispatch_workgroup_ext, global_keys_buffer, global_vals_buffer,
+    passes_needed,
+};
+
+pub const TOP_K_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(168582471074476178330033208019069938420);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const K_OFFSET: u32 = 8;
+const DIGIT_OFFSET: u32 = 12;
+const INDEX_ROUND_OFFSET: u32 = 16;
+const INIT_INDEX_OFFSET: u32 = 20;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..24,
+};
+
+/// `[threshold_key, threshold_index, remaining, _]`
+const STATE_SIZE: u64 = 4 * NUMBER_OF_BYTES_PER_KEY as u64;
+/// The size of the `histogram` of the selected digit in bytes.
+const HISTOGRAM_SIZE: u64 = (NUMBER_OF_RADIX * NUMBER_OF_BYTES_PER_KEY) as u64;
+
+/// Whether [`run_top_k`] leaves its result in the `EVE_*` (true) or `ODD_*` (false) global buffers.
+///
+/// The result is always on the other side of the input.
+pub fn is_top_k_output_even(read_from_even: bool) -> bool {
+    !read_from_even
+}
+
+pub struct TopKPlugin;
+
+impl Plugin for TopKPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, TOP_K_SHADER_HANDLE, "top_k.wgsl", Shader::from_wgsl);
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            TopKBindGroup::initialize
+                .in_set(RenderSet::PrepareBindGroups)
+                .run_if(not(resource_exists::<TopKBindGroup>)),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<TopKPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct TopKPipeline {
+    /// Reset the histogram and the selection state
+    init_pipeline: CachedComputePipelineId,
+    /// Count the digit of the keys matching the digits selected so far
+    histogram_pipeline: CachedComputePipelineId,
+    /// Select the digit of the threshold from the histogram
+    select_pipeline: CachedComputePipelineId,
+    /// Count the candidates of each block
+    count_candidates_pipeline: CachedComputePipelineId,
+    /// Exclusive prefix sum of the candidates of each block
+    scan_block_counts_pipeline: CachedComputePipelineId,
+    /// Write the candidates to the front of the output buffers, in the order of the input
+    compact_candidates_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > global_keys_i: array<u32>;
+    /// @binding(1) var<storage, read      > global_vals_i: array<u32>;
+    /// @binding(2) var<storage, read_write> global_keys_o: array<u32>;
+    /// @binding(3) var<storage, read_write> global_vals_o: array<u32>;
+    /// @binding(4) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+    /// @binding(5) var<storage, read_write> state: array<u32, 4>;
+    /// @binding(6) var<storage, read_write> block_counts: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for TopKPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "top_k bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                    storage_buffer_sized(false, NonZeroU64::new(HISTOGRAM_SIZE)),
+                    storage_buffer_sized(false, NonZeroU64::new(STATE_SIZE)),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let cdefs = vec![
+            ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            ),
+            ShaderDefVal::UInt("NUMBER_OF_RADIX".into(), NUMBER_OF_RADIX),
+            ShaderDefVal::UInt("NUMBER_OF_RADIX_BITS".into(), NUMBER_OF_RADIX_BITS),
+        ];
+
+        let queue = |label: &'static str, def: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: TOP_K_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &[def.into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        let init_pipeline = queue("top_k: init pipeline", "INIT_PIPELINE");
+        let histogram_pipeline = queue("top_k: histogram pipeline", "HISTOGRAM_PIPELINE");
+        let select_pipeline = queue("top_k: select pipeline", "SELECT_PIPELINE");
+        let count_candidates_pipeline = queue(
+            "top_k: count_candidates pipeline",
+            "COUNT_CANDIDATES_PIPELINE",
+        );
+        let scan_block_counts_pipeline = queue(
+            "top_k: scan_block_counts pipeline",
+            "SCAN_BLOCK_COUNTS_PIPELINE",
+        );
+        let compact_candidates_pipeline = queue(
+            "top_k: compact_candidates pipeline",
+            "COMPACT_CANDIDATES_PIPELINE",
+        );
+
+        Self {
+            init_pipeline,
+            histogram_pipeline,
+            select_pipeline,
+            count_candidates_pipeline,
+            scan_block_counts_pipeline,
+            compact_candidates_pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl TopKPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("top_k init_pipeline", self.init_pipeline),
+                ("top_k histogram_pipeline", self.histogram_pipeline),
+                ("top_k select_pipeline", self.select_pipeline),
+                (
+                    "top_k count_candidates_pipeline",
+                    self.count_candidates_pipeline,
+                ),
+                (
+                    "top_k scan_block_counts_pipeline",
+                    self.scan_block_counts_pipeline,
+                ),
+                (
+                    "top_k compact_candidates_pipeline",
+                    self.compact_candidates_pipeline,
+                ),
+            ],
+        )
+    }
+}
+
+/// The scratch buffers of the top-k selection, and the bind groups reading from either side of the global buffers.
+#[derive(Resource, Debug, Clone)]
+pub struct TopKBindGroup {
+    /// Read from the `EVE_*` global buffers, write the candidates to the `ODD_*` ones
+    eve_bind_group: BindGroup,
+    /// Read from the `ODD_*` global buffers, write the candidates to the `EVE_*` ones
+    odd_bind_group: BindGroup,
+}
+
+impl TopKBindGroup {
+    pub fn initialize(
+        mut commands: Commands,
+        top_k_pipeline: Res<TopKPipeline>,
+        radix_sort_settings: Res<RadixSortSettings>,
+        render_device: Res<RenderDevice>,
+        sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    ) {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!("radix_sort::top_k::prepare_bind_groups").entered();
+
+        let (
+            Some(eve_global_keys_buf),
+            Some(eve_global_vals_buf),
+            Some(odd_global_keys_buf),
+            Some(odd_global_vals_buf),
+        ) = (
+            global_keys_buffer(&sbufs, true),
+            global_vals_buffer(&sbufs, true),
+            global_keys_buffer(&sbufs, false),
+            global_vals_buffer(&sbufs, false),
+        )
+        else {
+            return;
+        };
+
+        let usages = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+
+        let histogram_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("top_k: histogram buffer"),
+            size: HISTOGRAM_SIZE,
+            usage: usages,
+            mapped_at_creation: false,
+        });
+
+        let state_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("top_k: state buffer"),
+            size: STATE_SIZE,
+            usage: usages,
+            mapped_at_creation: false,
+        });
+
+        let max_number_of_blocks = radix_sort_settings
+            .max_number_of_keys()
+            .div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP)
+            .max(1);
+        let block_counts_buf = render_device.create_buffer(&BufferDescriptor {
+            label: Some("top_k: block_counts buffer"),
+            size: (max_number_of_blocks * NUMBER_OF_BYTES_PER_KEY) as u64,
+            usage: usages,
+            mapped_at_creation: false,
+        });
+
+        let create_bind_group = |label: &'static str,
+                                 keys_i: &Buffer,
+                                 vals_i: &Buffer,
+                                 keys_o: &Buffer,
+                                 vals_o: &Buffer| {
+            render_device.create_bind_group(
+                label,
+                top_k_pipeline.bind_group_layout(),
+                &BindGroupEntries::sequential((
+                    keys_i.as_entire_binding(),
+                    vals_i.as_entire_binding(),
+                    keys_o.as_entire_binding(),
+                    vals_o.as_entire_binding(),
+                    histogram_buf.as_entire_binding(),
+                    state_buf.as_entire_binding(),
+                    block_counts_buf.as_entire_binding(),
+                )),
+            )
+        };
+
+        let eve_bind_group = create_bind_group(
+            "top_k: bind_group reading from even",
+            eve_global_keys_buf,
+            eve_global_vals_buf,
+            odd_global_keys_buf,
+            odd_global_vals_buf,
+        );
+        let odd_bind_group = create_bind_group(
+            "top_k: bind_group reading from odd",
+            odd_global_keys_buf,
+            odd_global_vals_buf,
+            eve_global_keys_buf,
+            eve_global_vals_buf,
+        );
+
+        commands.insert_resource(Self {
+            eve_bind_group,
+            odd_bind_group,
+        });
+    }
+
+    pub fn eve_bind_group(&self) -> &BindGroup {
+        &self.eve_bind_group
+    }
+
+    pub fn odd_bind_group(&self) -> &BindGroup {
+        &self.odd_bind_group
+    }
+}
+
+/// Writes the `min(k, number_of_keys)` smallest keys and their vals, in ascending order, to the front of the
+/// global buffers on the other side of the input (see [`is_top_k_output_even`]).
+///
+/// The input is read from the `EVE_*` global buffers if `read_from_even` is true, `ODD_*` otherwise.
+/// With `init_index`, the vals of the result are the indices of the keys in the input.
+#[allow(clippy::too_many_arguments)]
+pub fn run_top_k(
+    encoder: &mut CommandEncoder,
+    pipeline_cache: &PipelineCache,
+    radix_sort_pipeline: &RadixSortPipeline,
+    radix_bind_group: &RadixSortBindGroup,
+    top_k_pipeline: &TopKPipeline,
+    top_k_bind_group: &TopKBindGroup,
+    max_compute_workgroups_per_dimension: u32,
+    number_of_keys: u32,
+    k: u32,
+    init_index: bool,
+    read_from_even: bool,
+) {
+    let k = k.min(number_of_keys);
+    if k == 0 {
+        return;
+    }
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::run_top_k", number_of_keys, k).entered();
+
+    let init_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.init_pipeline)
+        .unwrap();
+    let histogram_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.histogram_pipeline)
+        .unwrap();
+    let select_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.select_pipeline)
+        .unwrap();
+    let count_candidates_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.count_candidates_pipeline)
+        .unwrap();
+    let scan_block_counts_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.scan_block_counts_pipeline)
+        .unwrap();
+    let compact_candidates_pipeline = pipeline_cache
+        .get_compute_pipeline(top_k_pipeline.compact_candidates_pipeline)
+        .unwrap();
+
+    let number_of_blocks = number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP);
+
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("top_k compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(init_pipeline);
+        pass.set_bind_group(
+            0,
+            if read_from_even {
+                top_k_bind_group.eve_bind_group()
+            } else {
+                top_k_bind_group.odd_bind_group()
+            },
+            &[],
+        );
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(K_OFFSET, bytemuck::bytes_of(&k));
+        pass.set_push_constants(INIT_INDEX_OFFSET, bytemuck::bytes_of(&(init_index as u32)));
+        pass.dispatch_workgroups(1, 1, 1);
+
+        // When every key is selected the threshold is left at `u32::MAX`, only the compaction is needed
+        if k < number_of_keys {
+            // The digits of the threshold key, then the digits of the threshold index among the keys equal to it
+            let index_bits = 32 - (number_of_keys - 1).leading_zeros();
+            let key_rounds = (0..passes_needed(32)).rev().map(|digit| (digit, 0u32));
+            let index_rounds = (0..passes_needed(index_bits))
+                .rev()
+                .map(|digit| (digit, 1u32));
+
+            for (digit, index_round) in key_rounds.chain(index_rounds) {
+                pass.set_push_constants(DIGIT_OFFSET, bytemuck::bytes_of(&digit));
+                pass.set_push_constants(INDEX_ROUND_OFFSET, bytemuck::bytes_of(&index_round));
+
+                pass.set_pipeline(histogram_pipeline);
+                dispatch_workgroup_ext(
+                    &mut pass,
+                    number_of_blocks,
+                    max_compute_workgroups_per_dimension,
+                    WORKGROUP_OFFSET_OFFSET,
+                );
+
+                pass.set_pipeline(select_pipeline);
+                pass.dispatch_workgroups(1, 1, 1);
+            }
+        }
+
+        pass.set_pipeline(count_candidates_pipeline);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blocks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+
+        pass.set_pipeline(scan_block_counts_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+
+        pass.set_pipeline(compact_candidates_pipeline);
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_blocks,
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    // The candidates already hold their vals (or indices), the stable sort keeps the ties in the order of the input
+    crate::run(
+        encoder,
+        pipeline_cache,
+        radix_sort_pipeline,
+        radix_bind_group,
+        max_compute_workgroups_per_dimension,
+        k,
+        0..passes_needed(32),
+        false,
+        !read_from_even,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{render_resource::CommandEncoderDescriptor, renderer::RenderQueue};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, random_keys, read_buffer, run_render_system_once},
+    };
+
+    use super::*;
+
+    /// Expected `(keys, vals)` of a top-k with `init_index`, from `select_nth_unstable` + sort.
+    fn cpu_top_k(keys: &[u32], k: u32) -> (Vec<u32>, Vec<u32>) {
+        let mut pairs: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+
+        let k = (k as usize).min(pairs.len());
+        if k < pairs.len() {
+            pairs.select_nth_unstable(k);
+            pairs.truncate(k);
+        }
+        pairs.sort_unstable();
+
+        pairs.into_iter().unzip()
+    }
+
+    fn run_top_k_test(keys: Vec<u32>, ks: &[u32], read_from_even: bool) {
+        let number_of_keys = keys.len() as u32;
+
+        for &k in ks {
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin {
+                    settings: number_of_keys.into(),
+                })
+                .add_plugins(TopKPlugin);
+
+            let keys = keys.clone();
+            let (expected_keys, expected_vals) = cpu_top_k(&keys, k);
+
+            run_render_system_once(
+                &mut app,
+                move |render_device: Res<RenderDevice>,
+                      render_queue: Res<RenderQueue>,
+                      pipeline_cache: Res<PipelineCache>,
+                      radix_sort_pipeline: Res<RadixSortPipeline>,
+                      radix_bind_group: Res<RadixSortBindGroup>,
+                      top_k_pipeline: Res<TopKPipeline>,
+                      top_k_bind_group: Res<TopKBindGroup>,
+                      sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    let input_keys_buf = global_keys_buffer(&sbufs, read_from_even).unwrap();
+                    render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: top_k command encoder"),
+                        });
+                    run_top_k(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_bind_group,
+                        &top_k_pipeline,
+                        &top_k_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        number_of_keys,
+                        k,
+                        true,
+                        read_from_even,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let output_even = is_top_k_output_even(read_from_even);
+                    let output_keys_buf = global_keys_buffer(&sbufs, output_even).unwrap();
+                    let output_vals_buf = global_vals_buffer(&sbufs, output_even).unwrap();
+
+                    let n = expected_keys.len();
+                    let keys = read_buffer(&render_device, &render_queue, output_keys_buf, n);
+                    let vals = read_buffer(&render_device, &render_queue, output_vals_buf, n);
+                    assert_eq!(keys, expected_keys, "keys of top-{k}");
+                    assert_eq!(vals, expected_vals, "vals of top-{k}");
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_k_unique_keys() {
+        let keys: Vec<u32> = (0..10_000u32).rev().map(|i| i * 3).collect();
+        run_top_k_test(keys, &[0, 1, 256, 9_999, 10_000, 20_000], true);
+    }
+
+    #[test]
+    fn test_top_k_full_range() {
+        run_top_k_test(random_keys(111, 50_000, u32::MAX), &[1, 256, 4_321], false);
+    }
+
+    #[test]
+    fn test_top_k_ties_at_the_boundary() {
+        // Heavy duplicates: only some of the keys equal to the threshold are kept, the lowest indices win
+        run_top_k_test(random_keys(111, 30_000, 100), &[1, 300, 301, 15_000], true);
+        run_top_k_test(vec![7; 5_000], &[1, 2_500, 4_999], false);
+    }
+}
@@ -0,0 +1,256 @@
+/// Read the unsorted keys from this buffer
+@group(0) @binding(0) var<storage, read      > global_keys_i: array<u32>;
+/// Read the unsorted vals from this buffer
+@group(0) @binding(1) var<storage, read      > global_vals_i: array<u32>;
+/// Write the k candidates (unsorted) to this buffer
+@group(0) @binding(2) var<storage, read_write> global_keys_o: array<u32>;
+/// Write the vals of the k candidates to this buffer
+@group(0) @binding(3) var<storage, read_write> global_vals_o: array<u32>;
+/// The histogram of the digit being selected, among the keys matching the digits selected so far
+@group(0) @binding(4) var<storage, read_write> histogram: array<atomic<u32>, #NUMBER_OF_RADIX>;
+/// [threshold_key, threshold_index, number_of_keys_still_to_select, _]
+@group(0) @binding(5) var<storage, read_write> state: array<u32, 4>;
+/// The number of candidates in each block, then the exclusive prefix sum of it
+@group(0) @binding(6) var<storage, read_write> block_counts: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys to select from.
+    number_of_keys: u32,
+    /// The number of smallest keys to select.
+    k: u32,
+    /// The digit being selected, 3 is the most significant one.
+    digit: u32,
+    /// 0: select a digit of the threshold key, 1: select a digit of the threshold index among the keys equal to it.
+    index_round: u32,
+    /// Write the index of the candidates instead of their vals.
+    init_index: u32,
+}
+var<push_constant> pc: PushConstants;
+
+const THRESHOLD_KEY: u32 = 0u;
+const THRESHOLD_INDEX: u32 = 1u;
+const REMAINING: u32 = 2u;
+
+fn get_workgroup_index(workgroup_id: vec3u, num_workgroups: vec3u) -> u32 {
+    return workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+}
+
+fn get_key_index(workgroup_index: u32, local_invocation_id_x: u32) -> u32 {
+    return workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id_x;
+}
+
+/// Whether the digits of `value` above `digit` equal the ones of `prefix`.
+fn prefix_matches(value: u32, prefix: u32, digit: u32) -> bool {
+    if digit == 3u {
+        return true;
+    }
+
+    let shift = (digit + 1u) * #{NUMBER_OF_RADIX_BITS}u;
+    return (value >> shift) == (prefix >> shift);
+}
+
+/// Keys smaller than the threshold key, plus the keys equal to it up to the threshold index, exactly `k` keys.
+fn is_candidate(key: u32, key_index: u32) -> bool {
+    let threshold_key = state[THRESHOLD_KEY];
+    return key < threshold_key || (key == threshold_key && key_index <= state[THRESHOLD_INDEX]);
+}
+
+#ifdef INIT_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_RADIX, 1, 1)
+fn main(@builtin(local_invocation_id) local_invocation_id: vec3u) {
+    atomicStore(&histogram[local_invocation_id.x], 0u);
+
+    if local_invocation_id.x == 0u {
+        if pc.k >= pc.number_of_keys {
+            // Every key is a candidate, no digit is selected
+            state[THRESHOLD_KEY] = 0xFFFFFFFFu;
+            state[THRESHOLD_INDEX] = 0xFFFFFFFFu;
+            state[REMAINING] = 0u;
+        } else {
+            state[THRESHOLD_KEY] = 0u;
+            state[THRESHOLD_INDEX] = 0u;
+            state[REMAINING] = pc.k;
+        }
+    }
+}
+#endif // INIT_PIPELINE
+
+#ifdef HISTOGRAM_PIPELINE
+var<workgroup> local_histogram: array<atomic<u32>, #NUMBER_OF_RADIX>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, local_invocation_id.x);
+
+    atomicStore(&local_histogram[local_invocation_id.x], 0u);
+    workgroupBarrier();
+
+    if key_index < pc.number_of_keys {
+        let key = global_keys_i[key_index];
+
+        var value = key;
+        var matches = prefix_matches(key, state[THRESHOLD_KEY], pc.digit);
+        if pc.index_round != 0u {
+            value = key_index;
+            matches = key == state[THRESHOLD_KEY] && prefix_matches(key_index, state[THRESHOLD_INDEX], pc.digit);
+        }
+
+        if matches {
+            let radix = extractBits(value, pc.digit * #{NUMBER_OF_RADIX_BITS}u, #{NUMBER_OF_RADIX_BITS}u);
+            atomicAdd(&local_histogram[radix], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    let count = atomicLoad(&local_histogram[local_invocation_id.x]);
+    if count != 0u {
+        atomicAdd(&histogram[local_invocation_id.x], count);
+    }
+}
+#endif // HISTOGRAM_PIPELINE
+
+#ifdef SELECT_PIPELINE
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    let remaining = state[REMAINING];
+
+    // Find the bucket holding the `remaining`-th smallest matching key, and reset the histogram for the next round
+    var before = 0u;
+    var bucket = 0u;
+    var found = false;
+    for (var radix = 0u; radix < #{NUMBER_OF_RADIX}u; radix++) {
+        let count = atomicLoad(&histogram[radix]);
+        atomicStore(&histogram[radix], 0u);
+
+        if !found {
+            if before + count >= remaining {
+                bucket = radix;
+                found = true;
+            } else {
+                before += count;
+            }
+        }
+    }
+
+    state[REMAINING] = remaining - before;
+
+    let slot = select(THRESHOLD_KEY, THRESHOLD_INDEX, pc.index_round != 0u);
+    state[slot] = state[slot] | (bucket << (pc.digit * #{NUMBER_OF_RADIX_BITS}u));
+}
+#endif // SELECT_PIPELINE
+
+#ifdef COUNT_CANDIDATES_PIPELINE
+var<workgroup> local_count: atomic<u32>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, local_invocation_id.x);
+
+    if local_invocation_id.x == 0u {
+        atomicStore(&local_count, 0u);
+    }
+    workgroupBarrier();
+
+    if key_index < pc.number_of_keys && is_candidate(global_keys_i[key_index], key_index) {
+        atomicAdd(&local_count, 1u);
+    }
+    workgroupBarrier();
+
+    if local_invocation_id.x == 0u {
+        block_counts[workgroup_index] = atomicLoad(&local_count);
+    }
+}
+#endif // COUNT_CANDIDATES_PIPELINE
+
+#ifdef SCAN_BLOCK_COUNTS_PIPELINE
+var<workgroup> sums: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(@builtin(local_invocation_id) local_invocation_id: vec3u) {
+    let tid = local_invocation_id.x;
+    let number_of_blocks = (pc.number_of_keys + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+
+    // Each thread owns a contiguous chunk of blocks
+    let chunk = (number_of_blocks + #{NUMBER_OF_THREADS_PER_WORKGROUP}u - 1u) / #{NUMBER_OF_THREADS_PER_WORKGROUP}u;
+    let chunk_begin = min(tid * chunk, number_of_blocks);
+    let chunk_end = min(chunk_begin + chunk, number_of_blocks);
+
+    var sum = 0u;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        sum += block_counts[i];
+    }
+    sums[tid] = sum;
+    workgroupBarrier();
+
+    // Inclusive scan of the chunk sums (Hillis-Steele)
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = sums[tid - offset];
+        }
+        workgroupBarrier();
+        sums[tid] += v;
+        workgroupBarrier();
+    }
+
+    var running = sums[tid] - sum;
+    for (var i = chunk_begin; i < chunk_end; i++) {
+        let count = block_counts[i];
+        block_counts[i] = running;
+        running += count;
+    }
+}
+#endif // SCAN_BLOCK_COUNTS_PIPELINE
+
+#ifdef COMPACT_CANDIDATES_PIPELINE
+var<workgroup> offsets: array<u32, #NUMBER_OF_THREADS_PER_WORKGROUP>;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let tid = local_invocation_id.x;
+    let workgroup_index = get_workgroup_index(workgroup_id, num_workgroups);
+    let key_index = get_key_index(workgroup_index, tid);
+
+    var key = 0u;
+    var is_selected = false;
+    if key_index < pc.number_of_keys {
+        key = global_keys_i[key_index];
+        is_selected = is_candidate(key, key_index);
+    }
+
+    // Inclusive scan of the flags, so the candidates keep the order of the input
+    offsets[tid] = u32(is_selected);
+    workgroupBarrier();
+    for (var offset = 1u; offset < #{NUMBER_OF_THREADS_PER_WORKGROUP}u; offset <<= 1u) {
+        var v = 0u;
+        if tid >= offset {
+            v = offsets[tid - offset];
+        }
+        workgroupBarrier();
+        offsets[tid] += v;
+        workgroupBarrier();
+    }
+
+    if is_selected {
+        let position = block_counts[workgroup_index] + offsets[tid] - 1u;
+        global_keys_o[position] = key;
+        global_vals_o[position] = select(global_vals_i[key_index], key_index, pc.init_index != 0u);
+    }
+}
+#endif // COMPACT_CANDIDATES_PIPELINE
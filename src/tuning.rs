@@ -0,0 +1,818 @@
+//! Picks the [`TileConfig`] of the adapter by timing the candidates, for the [`RadixSortCore`]s of the app.
+//!
+//! Tables of the best tile per vendor go stale with every GPU and driver, so the [`AutoTunePlugin`] measures instead:
+//! once the pipelines of the sort are loaded, it sorts hidden random keys with each candidate of the [`AutoTune`],
+//! times them with timestamp queries and keeps the fastest in the [`RadixSortTuning`] of the render world. The work
+//! is spread over the frames, so the app stays responsive:
+//!
+//! ```text
+//!  frames 0..c:  a RadixSortCore per supported candidate, compiled one per frame
+//!  frames c..m:  sorts_per_frame timed sorts, round-robin over the candidates and the sizes ─▶ BufferReadback
+//!  frames m..:   the readbacks polled, never blocking ─▶ the fastest tile ─▶ RadixSortTuning, stored per adapter
+//! ```
+//!
+//! Only the [`RadixSortCore`]s you create benefit: the sorts of the [`crate::RadixSortPlugin`] (`run`, `run_auto`,
+//! the jobs, ...) keep [`TileConfig::DEFAULT`] whatever the tuning, even once re-initialized by a change of the
+//! [`crate::RadixSortSettings`], since their tile is a constant of the WGSL module `bevy_radix_sort::keys` the user
+//! keygens import. Create your cores from the tuning once it [`RadixSortTuning::is_finished`]:
+//!
+//! ```ignore
+//! let core = tuning.create_core(render_device.wgpu_device(), subgroup_size.get(), false, None);
+//! ```
+//!
+//! Timing needs `WgpuFeatures::TIMESTAMP_QUERY`, without it the tuning ends at once with
+//! [`TuningStatus::Unsupported`] and the default tile. With the `pipeline_cache` feature and an [`AutoTune::cache`],
+//! the tile is stored per adapter alongside the pipeline cache, and the next runs load it instead of timing.
+
+use std::{hash::BuildHasher, time::Duration};
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor,
+        },
+        renderer::{RenderAdapterInfo, RenderDevice, RenderQueue},
+        settings::WgpuFeatures,
+    },
+    utils::{FixedState, Instant},
+};
+use wgpu::{ComputePassTimestampWrites, QuerySet, QuerySetDescriptor, QueryType};
+
+use crate::{
+    BufferReadback, NUMBER_OF_BYTES_PER_KEY, RadixSortBuffers, RadixSortCore,
+    RadixSortCoreBindGroups, RadixSortPipeline, SubgroupSize, TileConfig, radix_sort_loaded,
+    record_sort_passes,
+};
+
+/// The u32s of the pair of timestamps of a sort.
+const TIMESTAMP_PAIR_LEN: usize = 4;
+
+/// The configuration of the [`AutoTunePlugin`], a resource of the render world.
+#[derive(Resource, Debug, Clone)]
+pub struct AutoTune {
+    /// The tiles to time, 2 to 4 of them: each costs the compilation of a [`RadixSortCore`]. The ones the device
+    /// doesn't support are skipped, the first one wins the ties.
+    pub candidates: Vec<TileConfig>,
+    /// The numbers of keys sorted with each candidate, representative of the sorts of the app.
+    pub sizes: Vec<u32>,
+    /// The timed sorts of each candidate and size, their median is compared.
+    pub samples: u32,
+    /// The timed sorts recorded per frame, the fewer the shorter the hitches.
+    pub sorts_per_frame: u32,
+    /// No more sorts are recorded this long after the tuning started, once each candidate and size has a sample.
+    pub time_budget: Duration,
+    /// Where the tuned tile is stored and loaded from, see the [module docs](self).
+    #[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+    pub cache: Option<crate::PersistentPipelineCache>,
+}
+
+impl Default for AutoTune {
+    fn default() -> Self {
+        Self {
+            // The 3/7/15 rows avoiding the bank conflicts, see `NUMBER_OF_ROWS_PER_WORKGROUP`
+            candidates: vec![TileConfig::DEFAULT, TileConfig::new(3), TileConfig::new(15)],
+            sizes: vec![1 << 16, 1 << 20],
+            samples: 5,
+            sorts_per_frame: 4,
+            time_budget: Duration::from_millis(300),
+            #[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+            cache: None,
+        }
+    }
+}
+
+/// Where the [`RadixSortTuning`] is at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TuningStatus {
+    /// The pipelines of the sort aren't loaded yet.
+    #[default]
+    Pending,
+    /// The candidates are being timed.
+    Running,
+    /// The tile is the fastest of the candidates timed on this run.
+    Benchmarked,
+    /// The tile was stored by a previous run, see [`AutoTune::cache`].
+    Loaded,
+    /// The device can't time the sorts or supports none of the candidates, the tile is [`TileConfig::DEFAULT`].
+    Unsupported,
+}
+
+/// The median durations of the timed sorts of a candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileBenchmark {
+    pub tile: TileConfig,
+    /// By [`AutoTune::sizes`], `None` if none of the sorts of a size could be read back.
+    pub durations: Vec<Option<Duration>>,
+}
+
+/// The tile picked by the [`AutoTunePlugin`] for the adapter, in the render world.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RadixSortTuning {
+    tile: TileConfig,
+    status: TuningStatus,
+    benchmarks: Vec<TileBenchmark>,
+}
+
+impl RadixSortTuning {
+    /// The tuned tile, [`TileConfig::DEFAULT`] until [`Self::is_finished`].
+    pub fn tile_config(&self) -> TileConfig {
+        self.tile
+    }
+
+    pub fn status(&self) -> TuningStatus {
+        self.status
+    }
+
+    /// `true` once the tile won't change anymore.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TuningStatus::Benchmarked | TuningStatus::Loaded | TuningStatus::Unsupported
+        )
+    }
+
+    /// The timings of the supported candidates, empty unless [`TuningStatus::Benchmarked`].
+    pub fn benchmarks(&self) -> &[TileBenchmark] {
+        &self.benchmarks
+    }
+
+    /// Creates a [`RadixSortCore`] with the tuned tile, see [`RadixSortCore::with_tile_config`].
+    pub fn create_core(
+        &self,
+        device: &wgpu::Device,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> RadixSortCore {
+        RadixSortCore::with_tile_config(device, subgroup_size, subgroup_fallback, self.tile, cache)
+    }
+}
+
+/// The tile of the fastest of the `benchmarks` timed at every size, `None` if none was.
+///
+/// A candidate scores the sum over the sizes of its duration relative to the fastest one of the size, so the
+/// smaller sizes weigh as much as the larger ones. The first of the best scores wins.
+pub fn select_fastest(benchmarks: &[TileBenchmark]) -> Option<TileConfig> {
+    let complete: Vec<(TileConfig, Vec<f64>)> = benchmarks
+        .iter()
+        .filter_map(|benchmark| {
+            let nanos = benchmark
+                .durations
+                .iter()
+                .map(|duration| duration.map(|duration| duration.as_nanos() as f64))
+                .collect::<Option<Vec<f64>>>()?;
+            Some((benchmark.tile, nanos))
+        })
+        .collect();
+
+    let sizes = complete.first()?.1.len();
+    let fastest: Vec<f64> = (0..sizes)
+        .map(|size| {
+            complete
+                .iter()
+                .map(|(_, nanos)| nanos[size])
+                .fold(f64::INFINITY, f64::min)
+                // All the sorts of a size may be below the resolution of the timestamps
+                .max(1.0)
+        })
+        .collect();
+
+    let mut best: Option<(TileConfig, f64)> = None;
+    for (tile, nanos) in complete {
+        let score: f64 = nanos.iter().zip(&fastest).map(|(n, f)| n / f).sum();
+        if best.is_none_or(|(_, best_score)| score < best_score) {
+            best = Some((tile, score));
+        }
+    }
+
+    best.map(|(tile, _)| tile)
+}
+
+/// Times the [`AutoTune`] candidates after the pipelines of the sort are loaded and fills the [`RadixSortTuning`],
+/// see the [module docs](self). Requires the [`crate::RadixSortPlugin`].
+///
+/// The tuned tile only applies to the [`RadixSortCore`]s created with [`RadixSortTuning::create_core`], the
+/// pipelines of the [`crate::RadixSortPlugin`] keep [`TileConfig::DEFAULT`].
+#[derive(Debug, Clone, Default)]
+pub struct AutoTunePlugin {
+    pub auto_tune: AutoTune,
+}
+
+impl Plugin for AutoTunePlugin {
+    fn build(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .insert_resource(self.auto_tune.clone())
+            .init_resource::<RadixSortTuning>()
+            .add_systems(
+                Render,
+                auto_tune_tiles
+                    .in_set(RenderSet::Cleanup)
+                    .run_if(resource_exists::<RadixSortPipeline>)
+                    .run_if(radix_sort_loaded())
+                    .run_if(|tuning: Res<RadixSortTuning>| !tuning.is_finished()),
+            );
+    }
+}
+
+/// A candidate and its samples.
+struct TunedCandidate {
+    tile: TileConfig,
+    /// Compiled on a frame of its own
+    core: Option<(RadixSortCore, RadixSortCoreBindGroups)>,
+    /// By size
+    samples: Vec<Vec<Duration>>,
+}
+
+/// The sorts of a frame whose timestamps are read back.
+struct PendingSorts {
+    readback: BufferReadback,
+    /// The candidate and the size of each sort
+    sorts: Vec<(usize, usize)>,
+}
+
+/// The state of a running tuning, removed once it's finished with the buffers and the cores.
+#[derive(Resource)]
+struct AutoTuner {
+    candidates: Vec<TunedCandidate>,
+    sizes: Vec<u32>,
+    /// The random keys copied into `eve_keys` before each sort
+    input: Buffer,
+    eve_keys: Buffer,
+    eve_vals: Buffer,
+    blocks: Buffer,
+    odd_keys: Buffer,
+    odd_vals: Buffer,
+    /// A pair of timestamps per sort of a frame
+    query_set: QuerySet,
+    resolve: Buffer,
+    pending: Vec<PendingSorts>,
+    /// The sorts recorded, the schedule goes round-robin over the candidates, then the sizes
+    recorded: u32,
+    started: Instant,
+}
+
+impl AutoTuner {
+    fn new(
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        auto_tune: &AutoTune,
+        candidates: Vec<TileConfig>,
+    ) -> Self {
+        let max_size = auto_tune.sizes.iter().copied().max().unwrap_or_default();
+        let size = (max_size.max(1) * NUMBER_OF_BYTES_PER_KEY) as u64;
+
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        let create_buffer = |label, size| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        // The smallest tile has the most blocks
+        let blocks_size = candidates
+            .iter()
+            .map(|tile| tile.blocks_buffer_size(max_size))
+            .max()
+            .unwrap_or_default()
+            .max(NUMBER_OF_BYTES_PER_KEY as u64);
+
+        let input = create_buffer("radix_sort: auto tune input buffer", size);
+        let keys: Vec<u32> = (0..max_size)
+            .map(|index| FixedState.hash_one(index) as u32)
+            .collect();
+        render_queue.write_buffer(&input, 0, bytemuck::cast_slice(&keys));
+
+        let queries = 2 * auto_tune.sorts_per_frame.max(1);
+        let query_set = render_device
+            .wgpu_device()
+            .create_query_set(&QuerySetDescriptor {
+                label: Some("radix_sort: auto tune timestamps"),
+                ty: QueryType::Timestamp,
+                count: queries,
+            });
+
+        Self {
+            candidates: candidates
+                .into_iter()
+                .map(|tile| TunedCandidate {
+                    tile,
+                    core: None,
+                    samples: vec![Vec::new(); auto_tune.sizes.len()],
+                })
+                .collect(),
+            sizes: auto_tune.sizes.clone(),
+            input,
+            eve_keys: create_buffer("radix_sort: auto tune eve_keys buffer", size),
+            eve_vals: create_buffer("radix_sort: auto tune eve_vals buffer", size),
+            blocks: create_buffer("radix_sort: auto tune blocks buffer", blocks_size),
+            odd_keys: create_buffer("radix_sort: auto tune odd_keys buffer", size),
+            odd_vals: create_buffer("radix_sort: auto tune odd_vals buffer", size),
+            query_set,
+            resolve: render_device.create_buffer(&BufferDescriptor {
+                label: Some("radix_sort: auto tune timestamps resolve buffer"),
+                size: queries as u64 * std::mem::size_of::<u64>() as u64,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            pending: Vec::new(),
+            recorded: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Compiles the next candidate, returns `false` once they're all compiled.
+    fn compile_next(
+        &mut self,
+        render_device: &RenderDevice,
+        subgroup_size: u32,
+        subgroup_fallback: bool,
+    ) -> bool {
+        let Some(candidate) = self.candidates.iter_mut().find(|c| c.core.is_none()) else {
+            return false;
+        };
+
+        let device = render_device.wgpu_device();
+        let core = RadixSortCore::with_tile_config(
+            device,
+            subgroup_size,
+            subgroup_fallback,
+            candidate.tile,
+            None,
+        );
+        let bind_groups = core.create_bind_groups(
+            device,
+            &RadixSortBuffers {
+                eve_keys: &self.eve_keys,
+                eve_vals: &self.eve_vals,
+                blocks: &self.blocks,
+                odd_keys: &self.odd_keys,
+                odd_vals: &self.odd_vals,
+            },
+        );
+        candidate.core = Some((core, bind_groups));
+
+        true
+    }
+
+    /// Whether more sorts are recorded: every candidate and size gets a sample, then more until the
+    /// [`AutoTune::samples`] or the [`AutoTune::time_budget`].
+    fn wants_more_sorts(&self, auto_tune: &AutoTune) -> bool {
+        let round = (self.candidates.len() * self.sizes.len()) as u32;
+        self.recorded < round
+            || (self.recorded < round * auto_tune.samples
+                && self.started.elapsed() < auto_tune.time_budget)
+    }
+
+    /// Records and submits the timed sorts of the frame.
+    fn record_sorts(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        auto_tune: &AutoTune,
+    ) {
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("radix_sort: auto tune command encoder"),
+        });
+        let max_compute_workgroups_per_dimension =
+            render_device.limits().max_compute_workgroups_per_dimension;
+
+        let mut sorts = Vec::new();
+        while sorts.len() < auto_tune.sorts_per_frame.max(1) as usize
+            && self.wants_more_sorts(auto_tune)
+        {
+            let entry = self.recorded as usize % (self.candidates.len() * self.sizes.len());
+            let (candidate, size) = (entry % self.candidates.len(), entry / self.candidates.len());
+            let number_of_keys = self.sizes[size];
+            let (core, bind_groups) = self.candidates[candidate].core.as_ref().unwrap();
+
+            // Every sort starts from the same unsorted keys
+            encoder.copy_buffer_to_buffer(
+                &self.input,
+                0,
+                &self.eve_keys,
+                0,
+                (number_of_keys * NUMBER_OF_BYTES_PER_KEY) as u64,
+            );
+
+            let index = 2 * sorts.len() as u32;
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("radix_sort: auto tune compute pass"),
+                timestamp_writes: Some(ComputePassTimestampWrites {
+                    query_set: &self.query_set,
+                    beginning_of_pass_write_index: Some(index),
+                    end_of_pass_write_index: Some(index + 1),
+                }),
+            });
+            if number_of_keys >= 2 {
+                record_sort_passes(
+                    &mut pass,
+                    &core.pipelines(),
+                    &bind_groups.eve_bind_group,
+                    &bind_groups.odd_bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+            }
+            drop(pass);
+
+            sorts.push((candidate, size));
+            self.recorded += 1;
+        }
+
+        let queries = 2 * sorts.len() as u32;
+        encoder.resolve_query_set(&self.query_set, 0..queries, &self.resolve, 0);
+        let readback = BufferReadback::new(
+            render_device,
+            &mut encoder,
+            &self.resolve,
+            0,
+            TIMESTAMP_PAIR_LEN * sorts.len(),
+        );
+        render_queue.submit([encoder.finish()]);
+
+        self.pending.push(PendingSorts { readback, sorts });
+    }
+
+    /// Adds the samples of the sorts read back.
+    fn poll_readbacks(&mut self, render_device: &RenderDevice, render_queue: &RenderQueue) {
+        let period = render_queue.get_timestamp_period() as f64;
+
+        let candidates = &mut self.candidates;
+        self.pending.retain_mut(|pending| {
+            let Some(result) = pending.readback.poll(render_device) else {
+                return true;
+            };
+            // A lost mapping only loses the samples
+            let Ok(data) = result else {
+                return false;
+            };
+
+            for (pair, &(candidate, size)) in
+                data.chunks_exact(TIMESTAMP_PAIR_LEN).zip(&pending.sorts)
+            {
+                let start = pair[0] as u64 | (pair[1] as u64) << 32;
+                let end = pair[2] as u64 | (pair[3] as u64) << 32;
+                let nanos = end.saturating_sub(start) as f64 * period;
+                candidates[candidate].samples[size].push(Duration::from_nanos(nanos as u64));
+            }
+
+            false
+        });
+    }
+
+    fn benchmarks(&self) -> Vec<TileBenchmark> {
+        self.candidates
+            .iter()
+            .map(|candidate| TileBenchmark {
+                tile: candidate.tile,
+                durations: candidate
+                    .samples
+                    .iter()
+                    .map(|samples| {
+                        let mut samples = samples.clone();
+                        samples.sort();
+                        samples.get(samples.len() / 2).copied()
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+fn load_tuning(auto_tune: &AutoTune, adapter_info: &RenderAdapterInfo) -> Option<TileConfig> {
+    let key = crate::PipelineCacheKey::from(&**adapter_info);
+    auto_tune.cache.as_ref()?.load_tuning(&key)
+}
+
+#[cfg(not(all(feature = "pipeline_cache", not(target_arch = "wasm32"))))]
+fn load_tuning(_auto_tune: &AutoTune, _adapter_info: &RenderAdapterInfo) -> Option<TileConfig> {
+    None
+}
+
+#[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+fn store_tuning(auto_tune: &AutoTune, adapter_info: &RenderAdapterInfo, tile: TileConfig) {
+    let Some(cache) = &auto_tune.cache else {
+        return;
+    };
+
+    let key = crate::PipelineCacheKey::from(&**adapter_info);
+    if let Err(err) = cache.store_tuning(&key, tile) {
+        warn!(
+            "Failed to store the tuned tile in {}: {}",
+            cache.tuning_path(&key).display(),
+            err
+        );
+    }
+}
+
+#[cfg(not(all(feature = "pipeline_cache", not(target_arch = "wasm32"))))]
+fn store_tuning(_auto_tune: &AutoTune, _adapter_info: &RenderAdapterInfo, _tile: TileConfig) {}
+
+#[allow(clippy::too_many_arguments)]
+fn auto_tune_tiles(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    adapter_info: Res<RenderAdapterInfo>,
+    radix_sort_pipeline: Res<RadixSortPipeline>,
+    subgroup_size: Res<SubgroupSize>,
+    auto_tune: Res<AutoTune>,
+    mut tuning: ResMut<RadixSortTuning>,
+    tuner: Option<ResMut<AutoTuner>>,
+) {
+    let subgroup_fallback = radix_sort_pipeline.subgroup_fallback();
+
+    let Some(mut tuner) = tuner else {
+        let max_storage = render_device.limits().max_compute_workgroup_storage_size;
+        let candidates: Vec<TileConfig> = auto_tune
+            .candidates
+            .iter()
+            .copied()
+            .filter(|tile| tile.is_supported(subgroup_size.get(), subgroup_fallback, max_storage))
+            .collect();
+
+        if let Some(tile) = load_tuning(&auto_tune, &adapter_info)
+            .filter(|tile| tile.is_supported(subgroup_size.get(), subgroup_fallback, max_storage))
+        {
+            info!("radix_sort: tile (cache): {:?}", tile);
+            *tuning = RadixSortTuning {
+                tile,
+                status: TuningStatus::Loaded,
+                benchmarks: Vec::new(),
+            };
+            return;
+        }
+
+        if !render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+            || candidates.is_empty()
+            || auto_tune.sizes.is_empty()
+        {
+            info!("radix_sort: the tiles can't be timed, keeping the default tile");
+            *tuning = RadixSortTuning {
+                status: TuningStatus::Unsupported,
+                ..default()
+            };
+            return;
+        }
+
+        tuning.status = TuningStatus::Running;
+        commands.insert_resource(AutoTuner::new(
+            &render_device,
+            &render_queue,
+            &auto_tune,
+            candidates,
+        ));
+        return;
+    };
+
+    #[cfg(feature = "trace")]
+    let _span = tracing::info_span!("radix_sort::auto_tune_tiles").entered();
+
+    if tuner.compile_next(&render_device, subgroup_size.get(), subgroup_fallback) {
+        return;
+    }
+
+    tuner.poll_readbacks(&render_device, &render_queue);
+    if tuner.wants_more_sorts(&auto_tune) {
+        tuner.record_sorts(&render_device, &render_queue, &auto_tune);
+        return;
+    }
+    if !tuner.pending.is_empty() {
+        return;
+    }
+
+    let benchmarks = tuner.benchmarks();
+    *tuning = match select_fastest(&benchmarks) {
+        Some(tile) => {
+            info!("radix_sort: tile (benchmark): {:?}", tile);
+            store_tuning(&auto_tune, &adapter_info, tile);
+            RadixSortTuning {
+                tile,
+                status: TuningStatus::Benchmarked,
+                benchmarks,
+            }
+        }
+        None => {
+            warn!("radix_sort: none of the timed sorts was read back, keeping the default tile");
+            RadixSortTuning {
+                status: TuningStatus::Unsupported,
+                ..default()
+            }
+        }
+    };
+    commands.remove_resource::<AutoTuner>();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, run_once},
+    };
+
+    use super::*;
+
+    fn benchmark(keys_per_thread: u32, micros: &[Option<u64>]) -> TileBenchmark {
+        TileBenchmark {
+            tile: TileConfig::new(keys_per_thread),
+            durations: micros
+                .iter()
+                .map(|micros| micros.map(Duration::from_micros))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_fastest() {
+        assert_eq!(select_fastest(&[]), None);
+        assert_eq!(select_fastest(&[benchmark(7, &[Some(10), None])]), None);
+
+        // The fastest at both sizes
+        assert_eq!(
+            select_fastest(&[
+                benchmark(7, &[Some(100), Some(1_000)]),
+                benchmark(15, &[Some(90), Some(800)]),
+                benchmark(3, &[Some(120), Some(1_500)]),
+            ]),
+            Some(TileConfig::new(15))
+        );
+        // The small size weighs as much as the large one: 1.0 + 1.1 beats 1.5 + 1.0
+        assert_eq!(
+            select_fastest(&[
+                benchmark(7, &[Some(10), Some(1_100)]),
+                benchmark(15, &[Some(15), Some(1_000)]),
+            ]),
+            Some(TileConfig::new(7))
+        );
+        // The candidates missing a size are out, the first wins the ties
+        assert_eq!(
+            select_fastest(&[
+                benchmark(3, &[Some(1), None]),
+                benchmark(7, &[Some(100), Some(1_000)]),
+                benchmark(15, &[Some(100), Some(1_000)]),
+            ]),
+            Some(TileConfig::new(7))
+        );
+        // Sorts below the resolution of the timestamps
+        assert_eq!(
+            select_fastest(&[benchmark(7, &[Some(0)]), benchmark(15, &[Some(0)])]),
+            Some(TileConfig::new(7))
+        );
+    }
+
+    fn create_auto_tune_app(auto_tune: AutoTune) -> App {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            })
+            .add_plugins(AutoTunePlugin { auto_tune });
+
+        run_once(&mut app);
+        for _ in 0..200 {
+            if app
+                .sub_app(RenderApp)
+                .world()
+                .resource::<RadixSortTuning>()
+                .is_finished()
+            {
+                break;
+            }
+            app.update();
+        }
+
+        app
+    }
+
+    fn test_auto_tune_config() -> AutoTune {
+        AutoTune {
+            candidates: vec![TileConfig::DEFAULT, TileConfig::new(3)],
+            sizes: vec![10_000, 100_003],
+            samples: 3,
+            sorts_per_frame: 3,
+            // Every sample is recorded
+            time_budget: Duration::from_secs(60),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn test_auto_tune() {
+        let app = create_auto_tune_app(test_auto_tune_config());
+
+        let render_world = app.sub_app(RenderApp).world();
+        let tuning = render_world.resource::<RadixSortTuning>();
+        assert!(tuning.is_finished());
+        assert!(!render_world.contains_resource::<AutoTuner>());
+
+        let render_device = render_world.resource::<RenderDevice>();
+        if !render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+        {
+            assert_eq!(tuning.status(), TuningStatus::Unsupported);
+            assert_eq!(tuning.tile_config(), TileConfig::DEFAULT);
+            return;
+        }
+
+        assert_eq!(tuning.status(), TuningStatus::Benchmarked);
+        let tiles: Vec<TileConfig> = tuning.benchmarks().iter().map(|b| b.tile).collect();
+        assert_eq!(tiles, [TileConfig::DEFAULT, TileConfig::new(3)]);
+        for benchmark in tuning.benchmarks() {
+            assert_eq!(benchmark.durations.len(), 2);
+            assert!(benchmark.durations.iter().all(Option::is_some));
+        }
+        assert_eq!(
+            select_fastest(tuning.benchmarks()),
+            Some(tuning.tile_config())
+        );
+    }
+
+    #[test]
+    fn test_auto_tune_unsupported_candidates() {
+        let app = create_auto_tune_app(AutoTune {
+            candidates: vec![
+                TileConfig::new(0),
+                TileConfig::new(TileConfig::MAX_KEYS_PER_THREAD + 1),
+            ],
+            ..test_auto_tune_config()
+        });
+
+        let tuning = app.sub_app(RenderApp).world().resource::<RadixSortTuning>();
+        assert_eq!(tuning.status(), TuningStatus::Unsupported);
+        assert_eq!(tuning.tile_config(), TileConfig::DEFAULT);
+        assert!(tuning.benchmarks().is_empty());
+    }
+
+    #[cfg(all(feature = "pipeline_cache", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_auto_tune_persisted() {
+        use crate::{PersistentPipelineCache, PipelineCacheKey, test_utils::temp_dir};
+
+        let cache = PersistentPipelineCache::new(temp_dir("auto_tune"));
+        let config = AutoTune {
+            cache: Some(cache.clone()),
+            ..test_auto_tune_config()
+        };
+
+        // A first run times the candidates and stores the fastest
+        let app = create_auto_tune_app(config.clone());
+        let render_world = app.sub_app(RenderApp).world();
+        let key = PipelineCacheKey::from(&**render_world.resource::<RenderAdapterInfo>());
+        let tuning = render_world.resource::<RadixSortTuning>();
+        match tuning.status() {
+            TuningStatus::Benchmarked => {
+                assert_eq!(cache.load_tuning(&key), Some(tuning.tile_config()));
+            }
+            status => {
+                assert_eq!(status, TuningStatus::Unsupported);
+                assert_eq!(cache.load_tuning(&key), None);
+                cache.store_tuning(&key, TileConfig::new(3)).unwrap();
+            }
+        }
+        let stored = cache.load_tuning(&key).unwrap();
+        drop(app);
+
+        // The next runs load it
+        let app = create_auto_tune_app(config.clone());
+        let tuning = app.sub_app(RenderApp).world().resource::<RadixSortTuning>();
+        assert_eq!(tuning.status(), TuningStatus::Loaded);
+        assert_eq!(tuning.tile_config(), stored);
+        assert!(tuning.benchmarks().is_empty());
+
+        // The cores created from the reloaded tuning use the stored tile, the plugin's sort keeps the default one
+        let render_world = app.sub_app(RenderApp).world();
+        let core = tuning.create_core(
+            render_world.resource::<RenderDevice>().wgpu_device(),
+            render_world.resource::<SubgroupSize>().get(),
+            render_world
+                .resource::<RadixSortPipeline>()
+                .subgroup_fallback(),
+            None,
+        );
+        assert_eq!(core.tile_config(), stored);
+        let plugin_pipelines = crate::SortPipelines::new(
+            render_world.resource::<bevy::render::render_resource::PipelineCache>(),
+            render_world.resource::<RadixSortPipeline>(),
+        );
+        assert_eq!(plugin_pipelines.tile, TileConfig::DEFAULT);
+        drop(app);
+
+        // A tile the device doesn't support is timed again
+        cache.store_tuning(&key, TileConfig::new(0)).unwrap();
+        let app = create_auto_tune_app(config);
+        let tuning = app.sub_app(RenderApp).world().resource::<RadixSortTuning>();
+        assert_ne!(tuning.status(), TuningStatus::Loaded);
+        assert_ne!(tuning.tile_config(), TileConfig::new(0));
+    }
+}
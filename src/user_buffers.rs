@@ -0,0 +1,322 @@
+//! Sorting buffer assets of the user instead of the global buffers the [`crate::RadixSortPlugin`] allocates.
+//!
+//! ```ignore
+//! let settings = RadixSortSettings::from(1 << 20).with_user_buffers(RadixSortUserBuffers {
+//!     eve_keys: keys.clone(),
+//!     odd_keys: scratch_keys.clone(),
+//!     eve_vals: Some(vals.clone()),
+//!     odd_vals: Some(scratch_vals.clone()),
+//! });
+//! ```
+//!
+//! Once the assets are prepared, the render world checks them against the capacity and binds their buffers under
+//! the handles of the global buffers, so [`crate::global_keys_buffer`], [`crate::run`] and the helper passes sort
+//! them like the global buffers. [`crate::check_load_state`] reports [`crate::LoadProgress::PreparingBuffers`] until
+//! then, and [`crate::LoadState::Failed`] if a buffer is too small or isn't a storage buffer.
+//!
+//! Preparing an asset again (e.g. after `assets.get_mut`) rebinds the new buffer. The global blocks buffer is
+//! still allocated by the plugin.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{Buffer, BufferAddress, BufferId, BufferUsages},
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+    },
+};
+
+use crate::{
+    EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE, EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+    NUMBER_OF_BYTES_PER_KEY, ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+    ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE, RadixSortBindGroup, RadixSortCreationErrors,
+    RadixSortSettings,
+};
+
+/// The buffer assets [`RadixSortSettings::with_user_buffers`] sorts, in place of the `EVE_*` and `ODD_*` global
+/// buffers of the same name.
+///
+/// Each keys buffer holds at least [`RadixSortSettings::max_number_of_keys`] u32s, each vals buffer at least
+/// [`RadixSortSettings::max_number_of_values`] u32s. They have the `STORAGE` usage, the
+/// `COPY_SRC`/`COPY_DST` usages for the readbacks and uploads of the helpers.
+#[derive(Reflect, Debug, Clone, PartialEq, Eq)]
+#[reflect(Debug, PartialEq)]
+pub struct RadixSortUserBuffers {
+    pub eve_keys: Handle<ShaderStorageBuffer>,
+    pub odd_keys: Handle<ShaderStorageBuffer>,
+    /// Required unless the sort is keys-only, see [`RadixSortSettings::without_values`].
+    pub eve_vals: Option<Handle<ShaderStorageBuffer>>,
+    pub odd_vals: Option<Handle<ShaderStorageBuffer>>,
+}
+
+impl RadixSortUserBuffers {
+    /// The user buffers bound by the sort, with their names and the global buffers they replace.
+    fn bindings(
+        &self,
+        allocate_values: bool,
+    ) -> Result<
+        Vec<(
+            &'static str,
+            AssetId<ShaderStorageBuffer>,
+            Handle<ShaderStorageBuffer>,
+        )>,
+        String,
+    > {
+        let mut bindings = vec![
+            (
+                "eve_keys",
+                self.eve_keys.id(),
+                EVE_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            ),
+            (
+                "odd_keys",
+                self.odd_keys.id(),
+                ODD_GLOBAL_KEYS_STORAGE_BUFFER_HANDLE,
+            ),
+        ];
+        if allocate_values {
+            let (Some(eve_vals), Some(odd_vals)) = (&self.eve_vals, &self.odd_vals) else {
+                return Err(
+                    "both user vals buffers are required unless the sort is keys-only".into(),
+                );
+            };
+            bindings.push((
+                "eve_vals",
+                eve_vals.id(),
+                EVE_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+            ));
+            bindings.push((
+                "odd_vals",
+                odd_vals.id(),
+                ODD_GLOBAL_VALS_STORAGE_BUFFER_HANDLE,
+            ));
+        }
+
+        Ok(bindings)
+    }
+}
+
+/// Returns an error naming the user buffer if it can't replace a global buffer of `size` bytes.
+fn check_user_buffer(name: &str, buffer: &Buffer, size: BufferAddress) -> Result<(), String> {
+    if buffer.size() < size {
+        return Err(format!(
+            "the user buffer {name} holds {} bytes, less than the {size} bytes of the capacity",
+            buffer.size()
+        ));
+    }
+    if !buffer.usage().contains(BufferUsages::STORAGE) {
+        return Err(format!(
+            "the user buffer {name} doesn't have the STORAGE usage"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Binds the prepared user buffers under the handles of the global buffers, once they're all prepared and each time
+/// one of them is prepared again. The [`RadixSortBindGroup`] is rebuilt over them.
+pub(crate) fn prepare_user_buffers(
+    mut commands: Commands,
+    radix_sort_settings: Res<RadixSortSettings>,
+    mut sbufs: ResMut<RenderAssets<GpuShaderStorageBuffer>>,
+    mut bound: Local<Vec<BufferId>>,
+) {
+    if radix_sort_settings.is_changed() {
+        bound.clear();
+    }
+    let Some(user_buffers) = radix_sort_settings.user_buffers() else {
+        return;
+    };
+
+    let bindings = match user_buffers.bindings(radix_sort_settings.allocate_values()) {
+        Ok(bindings) => bindings,
+        Err(err) => {
+            // Reported once, the settings must change to retry
+            if radix_sort_settings.is_changed() {
+                error!("radix_sort: {}", err);
+                commands.insert_resource(RadixSortCreationErrors(vec![err]));
+            }
+            return;
+        }
+    };
+
+    let Some(buffers) = bindings
+        .iter()
+        .map(|(_, id, _)| sbufs.get(*id).map(|sbuf| sbuf.buffer.clone()))
+        .collect::<Option<Vec<Buffer>>>()
+    else {
+        return;
+    };
+    let ids: Vec<BufferId> = buffers.iter().map(Buffer::id).collect();
+    if *bound == ids {
+        return;
+    }
+    *bound = ids;
+
+    let size = |capacity: u32| capacity as BufferAddress * NUMBER_OF_BYTES_PER_KEY as BufferAddress;
+    let keys_size = size(radix_sort_settings.max_number_of_keys());
+    let vals_size = size(radix_sort_settings.max_number_of_values());
+    let errors: Vec<String> = bindings
+        .iter()
+        .zip(&buffers)
+        .filter_map(|((name, ..), buffer)| {
+            let size = if name.ends_with("vals") {
+                vals_size
+            } else {
+                keys_size
+            };
+            check_user_buffer(name, buffer, size).err()
+        })
+        .collect();
+    if !errors.is_empty() {
+        error!("radix_sort: {}", errors.join("; "));
+        commands.insert_resource(RadixSortCreationErrors(errors));
+        return;
+    }
+
+    for ((_, _, global), buffer) in bindings.into_iter().zip(buffers) {
+        sbufs.insert(global.id(), GpuShaderStorageBuffer { buffer });
+    }
+    commands.remove_resource::<RadixSortBindGroup>();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        asset::RenderAssetUsages,
+        ecs::system::RunSystemOnce,
+        render::{
+            RenderApp,
+            render_resource::{CommandEncoderDescriptor, PipelineCache},
+            renderer::{RenderDevice, RenderQueue},
+        },
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, LoadState, RadixSortPipeline, RadixSortPlugin, check_load_state,
+        global_keys_buffer, global_vals_buffer, run,
+        test_utils::{create_render_test_app, read_buffer, run_once},
+    };
+
+    use super::*;
+
+    const MAX_NUMBER_OF_KEYS: u32 = 1024;
+
+    /// Sorts `keys` in user buffers of `len` u32s, returns the load state and the sorted keys and vals if it loaded.
+    fn sort_in_user_buffers(len: u32, keys: &[u32]) -> (LoadState, Option<(Vec<u32>, Vec<u32>)>) {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default());
+
+        let mut sbufs = app
+            .world_mut()
+            .resource_mut::<Assets<ShaderStorageBuffer>>();
+        let mut add_buffer = |data: &[u32]| {
+            let mut contents = vec![0; len as usize];
+            contents[..data.len()].copy_from_slice(data);
+            let mut sbuf = ShaderStorageBuffer::new(
+                bytemuck::cast_slice(&contents),
+                RenderAssetUsages::default(),
+            );
+            sbuf.buffer_description.usage |= BufferUsages::COPY_SRC;
+            sbufs.add(sbuf)
+        };
+        let user_buffers = RadixSortUserBuffers {
+            eve_keys: add_buffer(keys),
+            odd_keys: add_buffer(&[]),
+            eve_vals: Some(add_buffer(&[])),
+            odd_vals: Some(add_buffer(&[])),
+        };
+        let eve_keys_id = user_buffers.eve_keys.id();
+
+        app.add_plugins(RadixSortPlugin {
+            settings: RadixSortSettings::from(MAX_NUMBER_OF_KEYS).with_user_buffers(user_buffers),
+        });
+        run_once(&mut app);
+
+        let render_world = app.sub_app_mut(RenderApp).world_mut();
+        let load_state = check_load_state(render_world);
+        if load_state != LoadState::Loaded {
+            return (load_state, None);
+        }
+
+        let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        assert_eq!(
+            global_keys_buffer(sbufs, true).unwrap().id(),
+            sbufs.get(eve_keys_id).unwrap().buffer.id()
+        );
+
+        let sorted = render_world
+            .run_system_once(
+                |render_device: Res<RenderDevice>,
+                 render_queue: Res<RenderQueue>,
+                 pipeline_cache: Res<PipelineCache>,
+                 radix_sort_pipeline: Res<RadixSortPipeline>,
+                 radix_sort_bind_group: Res<RadixSortBindGroup>,
+                 sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                    let mut encoder =
+                        render_device.create_command_encoder(&CommandEncoderDescriptor {
+                            label: Some("unit_test: user buffers command encoder"),
+                        });
+                    run(
+                        &mut encoder,
+                        &pipeline_cache,
+                        &radix_sort_pipeline,
+                        &radix_sort_bind_group,
+                        render_device.limits().max_compute_workgroups_per_dimension,
+                        MAX_NUMBER_OF_KEYS,
+                        0..4,
+                        true,
+                        true,
+                    );
+                    render_queue.submit([encoder.finish()]);
+
+                    let len = MAX_NUMBER_OF_KEYS as usize;
+                    (
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            global_keys_buffer(&sbufs, true).unwrap(),
+                            len,
+                        ),
+                        read_buffer(
+                            &render_device,
+                            &render_queue,
+                            global_vals_buffer(&sbufs, true).unwrap(),
+                            len,
+                        ),
+                    )
+                },
+            )
+            .unwrap();
+
+        (load_state, Some(sorted))
+    }
+
+    #[test]
+    fn test_user_buffers() {
+        let keys: Vec<u32> = (0..MAX_NUMBER_OF_KEYS)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..).collect();
+        expected.sort();
+
+        // Exactly the capacity, and larger buffers of which the sort uses the first `MAX_NUMBER_OF_KEYS` u32s
+        for len in [MAX_NUMBER_OF_KEYS, 2 * MAX_NUMBER_OF_KEYS] {
+            let (load_state, sorted) = sort_in_user_buffers(len, &keys);
+            assert_eq!(load_state, LoadState::Loaded, "{len} u32s");
+
+            let (sorted_keys, sorted_vals) = sorted.unwrap();
+            let sorted: Vec<(u32, u32)> = sorted_keys.into_iter().zip(sorted_vals).collect();
+            assert_eq!(sorted, expected, "{len} u32s");
+        }
+    }
+
+    #[test]
+    fn test_user_buffers_too_small() {
+        let (load_state, _) = sort_in_user_buffers(MAX_NUMBER_OF_KEYS - 1, &[]);
+        let LoadState::Failed(err) = load_state else {
+            panic!("user buffers under the capacity should fail to load, got {load_state:?}");
+        };
+        assert!(err.contains("less than the 4096 bytes"), "{err}");
+    }
+}
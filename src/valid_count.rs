@@ -0,0 +1,505 @@
+//! Count of the valid elements after sorting, when the rejected ones are marked with a sentinel key.
+//!
+//! A culling pass typically writes `u32::MAX` as the key of the rejected elements, after an ascending sort they are
+//! all at the end and the number of valid elements is the index of the first sentinel:
+//!
+//! ```text
+//!  sorted_keys  [ 1, 4, 4, 9, MAX, MAX ]
+//!  valid_count  4
+//! ```
+//!
+//! The count is found with a binary search and written to a 4-byte buffer, which can be read back or copied into the
+//! `instance_count` of a `DrawIndirectArgs` buffer (see [`DRAW_INDIRECT_INSTANCE_COUNT_OFFSET`]).
+//!
+//! The pass is meant to be recorded in the same encoder right after [`crate::run`].
+//!
+//! [`ValidCountPipeline::record_write_draw_indirect`] goes one step further and writes a complete
+//! `DrawIndexedIndirectArgs` from the count and a [`DrawIndexedIndirectTemplate`] holding the other fields, ready for
+//! `draw_indexed_indirect`:
+//!
+//! ```text
+//!  run                         sorted_keys  [ 1, 4, 4, 9, MAX, MAX ]
+//!  record_valid_count          valid_count  4
+//!  record_write_draw_indirect  args         { index_count, instance_count: 4, first_index, base_vertex, first_instance }
+//! ```
+
+use std::ops::Range;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+            CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+            PushConstantRange, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only, uniform_buffer},
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{LoadState, compute_pipelines_load_state, sorted_keys_buffer};
+
+pub const VALID_COUNT_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(47686450074729497361928192256034464122);
+
+/// The sentinel key written by culling passes for the rejected elements.
+pub const DEFAULT_SENTINEL_KEY: u32 = u32::MAX;
+
+/// The size in bytes of the `valid_count` buffer.
+pub const VALID_COUNT_BUFFER_SIZE: BufferAddress = std::mem::size_of::<u32>() as BufferAddress;
+
+/// The offset in bytes of `instance_count` in `DrawIndirectArgs`, the destination offset when copying the valid count
+/// with `copy_buffer_to_buffer`.
+///
+/// Storage buffer bindings must be aligned to `min_storage_buffer_offset_alignment`, so the count can't be written
+/// into the indirect args in place.
+pub const DRAW_INDIRECT_INSTANCE_COUNT_OFFSET: BufferAddress =
+    std::mem::size_of::<u32>() as BufferAddress;
+
+/// The size in bytes of the `DrawIndexedIndirectArgs` written by
+/// [`ValidCountPipeline::record_write_draw_indirect`].
+pub const DRAW_INDEXED_INDIRECT_ARGS_SIZE: BufferAddress =
+    5 * std::mem::size_of::<u32>() as BufferAddress;
+
+const NUMBER_OF_KEYS_OFFSET: u32 = 0;
+const SENTINEL_OFFSET: u32 = 4;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..8,
+};
+
+const ARGS_OFFSET_OFFSET: u32 = 0;
+
+const WRITE_DRAW_INDIRECT_PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..4,
+};
+
+/// The fields of `DrawIndexedIndirectArgs` other than `instance_count`, which is the valid count.
+///
+/// Must match `DrawIndexedIndirectTemplate` in `valid_count.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndexedIndirectTemplate {
+    pub index_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+pub struct ValidCountPlugin;
+
+impl Plugin for ValidCountPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            VALID_COUNT_SHADER_HANDLE,
+            "valid_count.wgsl",
+            Shader::from_wgsl
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ValidCountPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ValidCountPipeline {
+    /// Binary search of the first sentinel
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > sorted_keys: array<u32>;
+    /// @binding(1) var<storage, read_write> valid_count: u32;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+    /// Writes the `DrawIndexedIndirectArgs` from the valid count
+    write_draw_indirect_pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<uniform>             draw_template: DrawIndexedIndirectTemplate;
+    /// @binding(1) var<storage, read      > valid_count: u32;
+    /// @binding(2) var<storage, read_write> args: array<u32>;
+    /// ```
+    write_draw_indirect_bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for ValidCountPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "valid_count bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("valid_count: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: VALID_COUNT_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        let write_draw_indirect_bind_group_layout = render_device.create_bind_group_layout(
+            "valid_count: write_draw_indirect bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    uniform_buffer::<DrawIndexedIndirectTemplate>(false),
+                    storage_buffer_read_only::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let write_draw_indirect_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("valid_count: write_draw_indirect pipeline".into()),
+                layout: vec![write_draw_indirect_bind_group_layout.clone()],
+                push_constant_ranges: vec![WRITE_DRAW_INDIRECT_PUSH_CONSTANT_RANGES],
+                shader: VALID_COUNT_SHADER_HANDLE,
+                shader_defs: vec!["WRITE_DRAW_INDIRECT_PIPELINE".into()],
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            write_draw_indirect_pipeline,
+            write_draw_indirect_bind_group_layout,
+        }
+    }
+}
+
+impl ValidCountPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("valid_count pipeline", self.pipeline),
+                (
+                    "valid_count: write_draw_indirect pipeline",
+                    self.write_draw_indirect_pipeline,
+                ),
+            ],
+        )
+    }
+
+    /// `valid_count` must hold at least [`VALID_COUNT_BUFFER_SIZE`] bytes, create it with `COPY_SRC` to read it back
+    /// or copy it into indirect args.
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sorted_keys: &Buffer,
+        valid_count: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "valid_count: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                sorted_keys.as_entire_binding(),
+                valid_count.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group reading the sorted keys from the global buffer holding the result of [`crate::run`]
+    /// over `pass_range` with the same `read_from_even` (see [`crate::sorted_keys_buffer`]).
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    pub fn create_sorted_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        pass_range: &Range<u32>,
+        read_from_even: bool,
+        valid_count: &Buffer,
+    ) -> Option<BindGroup> {
+        let sorted_keys = sorted_keys_buffer(sbufs, pass_range, read_from_even)?;
+
+        Some(self.create_bind_group(render_device, sorted_keys, valid_count))
+    }
+
+    /// Writes the index of the first of the `number_of_keys` sorted keys greater than or equal to `sentinel`
+    /// (usually [`DEFAULT_SENTINEL_KEY`]) into `valid_count`, `number_of_keys` if there is none.
+    pub fn record_valid_count(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        number_of_keys: u32,
+        sentinel: u32,
+    ) {
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("valid_count compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(SENTINEL_OFFSET, bytemuck::bytes_of(&sentinel));
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    /// Creates the uniform buffer holding `template`, update it with [`Self::write_template_buffer`].
+    pub fn create_template_buffer(
+        &self,
+        render_device: &RenderDevice,
+        template: &DrawIndexedIndirectTemplate,
+    ) -> Buffer {
+        render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("valid_count: draw_template buffer"),
+            contents: bytemuck::bytes_of(template),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        })
+    }
+
+    pub fn write_template_buffer(
+        &self,
+        render_queue: &RenderQueue,
+        template_buffer: &Buffer,
+        template: &DrawIndexedIndirectTemplate,
+    ) {
+        render_queue.write_buffer(template_buffer, 0, bytemuck::bytes_of(template));
+    }
+
+    /// Writes the `DrawIndexedIndirectArgs` made of `template` (see [`Self::create_template_buffer`]) and of the
+    /// valid count in `count_buffer` as `instance_count` into `out_buffer` at `out_offset`.
+    ///
+    /// `out_buffer` must be created with `STORAGE | INDIRECT` and hold [`DRAW_INDEXED_INDIRECT_ARGS_SIZE`] bytes at
+    /// `out_offset`, which must be a multiple of 4. Record it after [`Self::record_valid_count`] wrote `count_buffer`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_write_draw_indirect(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        template: &Buffer,
+        count_buffer: &Buffer,
+        out_buffer: &Buffer,
+        out_offset: BufferAddress,
+    ) {
+        assert!(
+            out_buffer
+                .usage()
+                .contains(BufferUsages::STORAGE | BufferUsages::INDIRECT),
+            "the indirect args buffer must be created with STORAGE | INDIRECT"
+        );
+        assert_eq!(
+            out_offset % 4,
+            0,
+            "the indirect args must be 4-byte aligned"
+        );
+        assert!(
+            out_offset + DRAW_INDEXED_INDIRECT_ARGS_SIZE <= out_buffer.size(),
+            "the indirect args at {} overflow the buffer of {} bytes",
+            out_offset,
+            out_buffer.size()
+        );
+
+        let pipeline = pipeline_cache
+            .get_compute_pipeline(self.write_draw_indirect_pipeline)
+            .unwrap();
+
+        let bind_group = render_device.create_bind_group(
+            "valid_count: write_draw_indirect bind_group",
+            &self.write_draw_indirect_bind_group_layout,
+            &BindGroupEntries::sequential((
+                template.as_entire_binding(),
+                count_buffer.as_entire_binding(),
+                out_buffer.as_entire_binding(),
+            )),
+        );
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("valid_count: write_draw_indirect compute pass"),
+            ..default()
+        });
+
+        let args_offset = (out_offset / 4) as u32;
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_push_constants(ARGS_OFFSET_OFFSET, bytemuck::bytes_of(&args_offset));
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::CommandEncoderDescriptor;
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    fn run_valid_count_test(sorted_keys: Vec<u32>, sentinel: u32) {
+        let mut app = create_render_test_app();
+        app.add_plugins(ValidCountPlugin);
+
+        let expected = sorted_keys.partition_point(|&key| key < sentinel) as u32;
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  valid_count_pipeline: Res<ValidCountPipeline>| {
+                // Never empty, a zero-sized binding is invalid
+                let keys_buf =
+                    create_storage_buffer(&render_device, &[sorted_keys.as_slice(), &[0]].concat());
+                let valid_count_buf = create_storage_buffer(&render_device, &[0xDEADBEEF]);
+                let bind_group = valid_count_pipeline.create_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &valid_count_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: valid_count command encoder"),
+                });
+                valid_count_pipeline.record_valid_count(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    sorted_keys.len() as u32,
+                    sentinel,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let valid_count = read_buffer(&render_device, &render_queue, &valid_count_buf, 1);
+                assert_eq!(valid_count, [expected]);
+            },
+        );
+    }
+
+    #[test]
+    fn test_valid_count_no_sentinel() {
+        let sorted_keys: Vec<u32> = (0..10_000u32).collect();
+        run_valid_count_test(sorted_keys, DEFAULT_SENTINEL_KEY);
+        run_valid_count_test(vec![], DEFAULT_SENTINEL_KEY);
+    }
+
+    #[test]
+    fn test_valid_count_some_sentinels() {
+        let mut sorted_keys: Vec<u32> = (0..10_000u32).map(|i| i / 3).collect();
+        sorted_keys.extend([DEFAULT_SENTINEL_KEY; 1_234]);
+        run_valid_count_test(sorted_keys, DEFAULT_SENTINEL_KEY);
+
+        // A custom sentinel, the keys above it are rejected too
+        run_valid_count_test(vec![0, 1, 1, 5, 7, 7, 9], 7);
+    }
+
+    #[test]
+    fn test_write_draw_indirect() {
+        let mut app = create_render_test_app();
+        app.add_plugins(ValidCountPlugin);
+
+        let mut sorted_keys: Vec<u32> = (0..1_000u32).collect();
+        sorted_keys.extend([DEFAULT_SENTINEL_KEY; 24]);
+        let template = DrawIndexedIndirectTemplate {
+            index_count: 6,
+            first_index: 12,
+            base_vertex: -4,
+            first_instance: 3,
+        };
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  valid_count_pipeline: Res<ValidCountPipeline>| {
+                let keys_buf = create_storage_buffer(&render_device, &sorted_keys);
+                let valid_count_buf = create_storage_buffer(&render_device, &[0xDEADBEEF]);
+                let bind_group = valid_count_pipeline.create_bind_group(
+                    &render_device,
+                    &keys_buf,
+                    &valid_count_buf,
+                );
+                let template_buf =
+                    valid_count_pipeline.create_template_buffer(&render_device, &template);
+                // The args at a nonzero offset, the words around them must be untouched
+                let args_buf = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("unit_test: draw indirect args buffer"),
+                    contents: bytemuck::cast_slice(&[0xDEADBEEFu32; 8]),
+                    usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_SRC,
+                });
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: write_draw_indirect command encoder"),
+                });
+                valid_count_pipeline.record_valid_count(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    sorted_keys.len() as u32,
+                    DEFAULT_SENTINEL_KEY,
+                );
+                valid_count_pipeline.record_write_draw_indirect(
+                    &mut encoder,
+                    &render_device,
+                    &pipeline_cache,
+                    &template_buf,
+                    &valid_count_buf,
+                    &args_buf,
+                    8,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let args = read_buffer(&render_device, &render_queue, &args_buf, 8);
+                assert_eq!(
+                    args,
+                    [
+                        0xDEADBEEF,
+                        0xDEADBEEF,
+                        6,
+                        1_000,
+                        12,
+                        -4i32 as u32,
+                        3,
+                        0xDEADBEEF
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_valid_count_all_sentinels() {
+        run_valid_count_test(vec![DEFAULT_SENTINEL_KEY; 5_000], DEFAULT_SENTINEL_KEY);
+        run_valid_count_test(vec![7, 8, 9], 7);
+    }
+}
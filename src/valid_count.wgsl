@@ -0,0 +1,60 @@
+#ifdef WRITE_DRAW_INDIRECT_PIPELINE
+struct DrawIndexedIndirectTemplate {
+    index_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+/// The fields of the args other than `instance_count`
+@group(0) @binding(0) var<uniform>             draw_template: DrawIndexedIndirectTemplate;
+/// The number of instances to draw, written by the valid-count pipeline
+@group(0) @binding(1) var<storage, read      > valid_count: u32;
+/// Write the `DrawIndexedIndirectArgs` to this buffer
+@group(0) @binding(2) var<storage, read_write> args: array<u32>;
+
+struct PushConstants {
+    /// The offset of the args in `args`, in u32s.
+    args_offset: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    args[pc.args_offset + 0u] = draw_template.index_count;
+    args[pc.args_offset + 1u] = valid_count;
+    args[pc.args_offset + 2u] = draw_template.first_index;
+    args[pc.args_offset + 3u] = bitcast<u32>(draw_template.base_vertex);
+    args[pc.args_offset + 4u] = draw_template.first_instance;
+}
+#else
+/// Sorted keys, the rejected elements hold the sentinel key and are at the end
+@group(0) @binding(0) var<storage, read      > sorted_keys: array<u32>;
+/// The index of the first key greater than or equal to the sentinel, i.e. the number of valid elements
+@group(0) @binding(1) var<storage, read_write> valid_count: u32;
+
+struct PushConstants {
+    /// The number of sorted keys.
+    number_of_keys: u32,
+    /// The key marking the rejected elements.
+    sentinel: u32,
+}
+var<push_constant> pc: PushConstants;
+
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    // Lower bound of the sentinel
+    var lo = 0u;
+    var hi = pc.number_of_keys;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2u;
+        if sorted_keys[mid] < pc.sentinel {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+
+    valid_count = lo;
+}
+#endif // WRITE_DRAW_INDIRECT_PIPELINE
@@ -0,0 +1,593 @@
+//! The `verify-sorts` feature: in debug builds every [`crate::run`] validates its own output on the GPU.
+//!
+//! [`crate::run`] records a checksum of the input keys before the sort, and after it the same checksum of the
+//! output keys and whether they're in order. The 20 bytes of results are copied to a staging buffer mapped once the
+//! commands were submitted, and read frames later by [`poll_sort_verifications`], without waiting on the GPU:
+//!
+//! ```text
+//!  run:      clear ─▶ checksum(input) ─▶ sort passes ─▶ checksum(output), check_order ─▶ copy to staging
+//!  Render:   render_system (submits) ─▶ poll_sort_verifications: map the new stagings, read the mapped ones
+//! ```
+//!
+//! A failure is logged as an error with the metadata of the run and counted in [`SortVerifier::failures`].
+//! The checksum is a wrapping sum and a xor of the keys: it catches lost, duplicated or overwritten keys, not every
+//! permutation bug of the vals.
+//!
+//! Nothing of this module is compiled without the feature or in release builds.
+
+use std::{
+    num::NonZeroU64,
+    ops::Range,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferAddress, BufferAsyncError, BufferDescriptor, BufferUsages,
+            CachedComputePipelineId, CommandEncoder, ComputePass, ComputePassDescriptor,
+            ComputePipelineDescriptor, Maintain, MapMode, PipelineCache, PushConstantRange,
+            ShaderDefVal, ShaderStages, binding_types::storage_buffer_sized,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    Algorithm, KeyTransform, LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup,
+    RadixSortPipeline, compared_bits, compute_pipelines_load_state, dispatch_workgroup_ext,
+    is_output_even,
+};
+
+pub const VERIFY_SORT_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(207145901816486917030455569136733801173);
+
+/// The size in bytes of the results of a verification, `[input_sum, input_xor, output_sum, output_xor, unsorted]`.
+pub const VERIFICATION_BUFFER_SIZE: BufferAddress = 5 * std::mem::size_of::<u32>() as BufferAddress;
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_KEYS_OFFSET: u32 = 4;
+const KEY_SHIFT_OFFSET: u32 = 8;
+const KEY_MASK_OFFSET: u32 = 12;
+const SLOT_OFFSET: u32 = 16;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..20,
+};
+
+/// The checksum slots of `verify.wgsl`.
+const INPUT_SLOT: u32 = 0;
+const OUTPUT_SLOT: u32 = 1;
+
+/// The metadata of a verified [`crate::run`], logged with a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortRunInfo {
+    /// The number of runs recorded before this one.
+    pub run: u64,
+    pub number_of_keys: u32,
+    pub pass_range: Range<u32>,
+    pub init_index: bool,
+    pub read_from_even: bool,
+    pub algorithm: Algorithm,
+}
+
+/// The validation passes and the verifications in flight, part of the [`RadixSortPipeline`].
+pub struct SortVerifier {
+    render_device: RenderDevice,
+    /// The wrapping sum and the xor of the keys of one side
+    checksum_pipeline: CachedComputePipelineId,
+    /// Whether any key is greater than the next one
+    check_order_pipeline: CachedComputePipelineId,
+    verification_layout: BindGroupLayout,
+    /// The buffers of the verifications read back
+    free: Mutex<Vec<VerificationBuffers>>,
+    /// Recorded by [`crate::run`], not mapped yet
+    recorded: Mutex<Vec<PendingVerification>>,
+    /// Mapped after the commands were submitted
+    mapping: Mutex<Vec<PendingVerification>>,
+    runs: AtomicU64,
+    failures: AtomicU32,
+}
+
+pub(crate) struct VerificationBuffers {
+    verification: Buffer,
+    staging: Buffer,
+    bind_group: BindGroup,
+}
+
+struct PendingVerification {
+    buffers: VerificationBuffers,
+    info: SortRunInfo,
+    mapped: Arc<OnceLock<Result<(), BufferAsyncError>>>,
+}
+
+impl std::fmt::Debug for SortVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SortVerifier")
+            .field("checksum_pipeline", &self.checksum_pipeline)
+            .field("check_order_pipeline", &self.check_order_pipeline)
+            .field("pending", &self.pending())
+            .field("failures", &self.failures())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SortVerifier {
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        pipeline_cache: &PipelineCache,
+        bind_group_layout: &BindGroupLayout,
+        key_transform: &KeyTransform,
+    ) -> Self {
+        let verification_layout = render_device.create_bind_group_layout(
+            "radix_sort: verification bind group layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                storage_buffer_sized(false, NonZeroU64::new(VERIFICATION_BUFFER_SIZE)),
+            ),
+        );
+
+        let mut cdefs = vec![ShaderDefVal::UInt(
+            "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+            NUMBER_OF_THREADS_PER_WORKGROUP,
+        )];
+        // The order is checked on the transformed keys
+        cdefs.extend(key_transform.shader_defs());
+
+        let queue = |label: &'static str, def: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![bind_group_layout.clone(), verification_layout.clone()],
+                push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+                shader: VERIFY_SORT_SHADER_HANDLE,
+                shader_defs: [cdefs.as_slice(), &[def.into()]].concat(),
+                entry_point: "main".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        };
+
+        Self {
+            render_device: render_device.clone(),
+            checksum_pipeline: queue("radix_sort: verify checksum pipeline", "CHECKSUM_PIPELINE"),
+            check_order_pipeline: queue(
+                "radix_sort: verify check_order pipeline",
+                "CHECK_ORDER_PIPELINE",
+            ),
+            verification_layout,
+            free: default(),
+            recorded: default(),
+            mapping: default(),
+            runs: default(),
+            failures: default(),
+        }
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[
+                ("verify checksum_pipeline", self.checksum_pipeline),
+                ("verify check_order_pipeline", self.check_order_pipeline),
+            ],
+        )
+    }
+
+    /// The number of failed verifications so far.
+    pub fn failures(&self) -> u32 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// The number of verifications not read back yet.
+    pub fn pending(&self) -> usize {
+        self.recorded.lock().unwrap().len() + self.mapping.lock().unwrap().len()
+    }
+
+    /// Records the checksum of the input keys, before the sort. Returns `None` while the pipelines are compiling,
+    /// the run then isn't verified.
+    pub(crate) fn begin(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        read_from_even: bool,
+    ) -> Option<VerificationBuffers> {
+        if self.load_state(pipeline_cache) != LoadState::Loaded {
+            return None;
+        }
+
+        let buffers = self
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| self.create_buffers());
+        encoder.clear_buffer(&buffers.verification, 0, None);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("radix_sort: verify input compute pass"),
+            ..default()
+        });
+        self.record_checksum(
+            &mut pass,
+            pipeline_cache,
+            side_bind_group(radix_bind_group, read_from_even),
+            &buffers,
+            max_compute_workgroups_per_dimension,
+            number_of_keys,
+            INPUT_SLOT,
+        );
+
+        Some(buffers)
+    }
+
+    /// Records the checksum and the order check of the output keys, after the sort, and the copy of the results.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn finish(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        radix_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        buffers: Option<VerificationBuffers>,
+        number_of_keys: u32,
+        pass_range: Range<u32>,
+        init_index: bool,
+        read_from_even: bool,
+        algorithm: Algorithm,
+    ) {
+        let Some(buffers) = buffers else {
+            return;
+        };
+
+        let bind_group = side_bind_group(
+            radix_bind_group,
+            is_output_even(&pass_range, read_from_even),
+        );
+
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("radix_sort: verify output compute pass"),
+                ..default()
+            });
+            self.record_checksum(
+                &mut pass,
+                pipeline_cache,
+                bind_group,
+                &buffers,
+                max_compute_workgroups_per_dimension,
+                number_of_keys,
+                OUTPUT_SLOT,
+            );
+
+            let (key_shift, key_mask) = compared_bits(&pass_range);
+            let pipeline = pipeline_cache
+                .get_compute_pipeline(self.check_order_pipeline)
+                .unwrap();
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.set_bind_group(1, &buffers.bind_group, &[]);
+            pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+            pass.set_push_constants(KEY_SHIFT_OFFSET, bytemuck::bytes_of(&key_shift));
+            pass.set_push_constants(KEY_MASK_OFFSET, bytemuck::bytes_of(&key_mask));
+            dispatch_workgroup_ext(
+                &mut pass,
+                number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+                max_compute_workgroups_per_dimension,
+                WORKGROUP_OFFSET_OFFSET,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(
+            &buffers.verification,
+            0,
+            &buffers.staging,
+            0,
+            VERIFICATION_BUFFER_SIZE,
+        );
+
+        let info = SortRunInfo {
+            run: self.runs.fetch_add(1, Ordering::Relaxed),
+            number_of_keys,
+            pass_range,
+            init_index,
+            read_from_even,
+            algorithm,
+        };
+        self.recorded.lock().unwrap().push(PendingVerification {
+            buffers,
+            info,
+            mapped: default(),
+        });
+    }
+
+    /// Maps the verifications recorded since the last call, whose commands must have been submitted, and reads the
+    /// ones mapped since. Never blocks, returns the number of failures read.
+    pub fn poll(&self) -> u32 {
+        let mut mapping = self.mapping.lock().unwrap();
+
+        for pending in std::mem::take(&mut *self.recorded.lock().unwrap()) {
+            let mapped = pending.mapped.clone();
+            pending
+                .buffers
+                .staging
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    let _ = mapped.set(result);
+                });
+            mapping.push(pending);
+        }
+
+        self.render_device.poll(Maintain::Poll);
+
+        let (done, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut *mapping)
+            .into_iter()
+            .partition(|pending| pending.mapped.get().is_some());
+        *mapping = in_flight;
+
+        let mut failures = 0;
+        for PendingVerification {
+            buffers,
+            info,
+            mapped,
+        } in done
+        {
+            let results = match mapped.get() {
+                Some(Ok(())) => {
+                    let results: [u32; 5] =
+                        bytemuck::pod_read_unaligned(&buffers.staging.slice(..).get_mapped_range());
+                    buffers.staging.unmap();
+                    results
+                }
+                Some(Err(err)) => {
+                    warn!(
+                        "radix_sort: failed to read back the verification of {:?}: {}",
+                        info, err
+                    );
+                    continue;
+                }
+                None => unreachable!(),
+            };
+
+            if let Some(err) = verification_error(results) {
+                error!("radix_sort: verify-sorts: {} in {:?}", err, info);
+                failures += 1;
+            }
+
+            self.free.lock().unwrap().push(buffers);
+        }
+
+        self.failures.fetch_add(failures, Ordering::Relaxed);
+        failures
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record_checksum(
+        &self,
+        pass: &mut ComputePass,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        buffers: &VerificationBuffers,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_keys: u32,
+        slot: u32,
+    ) {
+        let pipeline = pipeline_cache
+            .get_compute_pipeline(self.checksum_pipeline)
+            .unwrap();
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_bind_group(1, &buffers.bind_group, &[]);
+        pass.set_push_constants(NUMBER_OF_KEYS_OFFSET, bytemuck::bytes_of(&number_of_keys));
+        pass.set_push_constants(SLOT_OFFSET, bytemuck::bytes_of(&slot));
+        dispatch_workgroup_ext(
+            pass,
+            number_of_keys.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    fn create_buffers(&self) -> VerificationBuffers {
+        let verification = self.render_device.create_buffer(&BufferDescriptor {
+            label: Some("radix_sort: verification buffer"),
+            size: VERIFICATION_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging = self.render_device.create_buffer(&BufferDescriptor {
+            label: Some("radix_sort: verification staging buffer"),
+            size: VERIFICATION_BUFFER_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.render_device.create_bind_group(
+            "radix_sort: verification bind group",
+            &self.verification_layout,
+            &BindGroupEntries::single(verification.as_entire_binding()),
+        );
+
+        VerificationBuffers {
+            verification,
+            staging,
+            bind_group,
+        }
+    }
+}
+
+/// The bind group whose input slot holds the keys of the even or odd side.
+fn side_bind_group(radix_bind_group: &RadixSortBindGroup, even: bool) -> &BindGroup {
+    if even {
+        radix_bind_group.eve_bind_group()
+    } else {
+        radix_bind_group.odd_bind_group()
+    }
+}
+
+/// Describes what `[input_sum, input_xor, output_sum, output_xor, unsorted]` tells is wrong, if anything.
+fn verification_error(results: [u32; 5]) -> Option<String> {
+    let [input_sum, input_xor, output_sum, output_xor, unsorted] = results;
+
+    if (input_sum, input_xor) != (output_sum, output_xor) {
+        return Some(format!(
+            "the output keys aren't a permutation of the input keys (sum/xor {:#010x}/{:#010x}, expected {:#010x}/{:#010x})",
+            output_sum, output_xor, input_sum, input_xor
+        ));
+    }
+
+    if unsorted != 0 {
+        return Some("the output keys aren't sorted".into());
+    }
+
+    None
+}
+
+/// Reads back the verifications of the [`RadixSortPipeline`], after the render graph submitted its commands.
+pub fn poll_sort_verifications(radix_sort_pipeline: Res<RadixSortPipeline>) {
+    radix_sort_pipeline.verifier().poll();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssets, render_resource::CommandEncoderDescriptor,
+        renderer::RenderQueue, storage::GpuShaderStorageBuffer,
+    };
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin, RadixSortSettings, global_keys_buffer, run,
+        test_utils::{create_render_test_app, run_render_system_once},
+    };
+
+    use super::*;
+
+    /// Reads back every pending verification, returns the number of failures.
+    fn poll_until_read(render_device: &RenderDevice, verifier: &SortVerifier) -> u32 {
+        let mut failures = verifier.poll();
+        while verifier.pending() > 0 {
+            render_device.poll(Maintain::Wait).panic_on_timeout();
+            failures += verifier.poll();
+        }
+
+        failures
+    }
+
+    #[test]
+    fn test_verification_error() {
+        assert_eq!(verification_error([7, 3, 7, 3, 0]), None);
+        assert!(verification_error([7, 3, 7, 3, 1]).is_some());
+        assert!(verification_error([7, 3, 8, 3, 0]).is_some());
+        assert!(verification_error([7, 3, 7, 2, 0]).is_some());
+    }
+
+    #[test]
+    fn test_verifier_pipelines_load() {
+        // The order is checked on the transformed keys, with other shader defs
+        for key_transform in [KeyTransform::None, KeyTransform::Complement] {
+            let mut app = create_render_test_app();
+            app.add_plugins(GetSubgroupSizePlugin::default())
+                .add_plugins(RadixSortPlugin {
+                    settings: RadixSortSettings::from(1_000).with_key_transform(key_transform),
+                });
+
+            run_render_system_once(
+                &mut app,
+                |pipeline_cache: Res<PipelineCache>,
+                 radix_sort_pipeline: Res<RadixSortPipeline>| {
+                    // `run` leaves the sorts unverified while the pipelines aren't loaded, a failed one never is
+                    assert_eq!(
+                        radix_sort_pipeline.verifier().load_state(&pipeline_cache),
+                        LoadState::Loaded
+                    );
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_sorts() {
+        let keys: Vec<u32> = (0..10_000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let number_of_keys = keys.len() as u32;
+
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: RadixSortSettings::from(number_of_keys),
+            });
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  radix_sort_pipeline: Res<RadixSortPipeline>,
+                  radix_bind_group: Res<RadixSortBindGroup>,
+                  sbufs: Res<RenderAssets<GpuShaderStorageBuffer>>| {
+                let verifier = radix_sort_pipeline.verifier();
+                let max_compute_workgroups_per_dimension =
+                    render_device.limits().max_compute_workgroups_per_dimension;
+
+                let input_keys_buf = global_keys_buffer(&sbufs, true).unwrap();
+                render_queue.write_buffer(input_keys_buf, 0, bytemuck::cast_slice(&keys));
+
+                // A correct sort verifies
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: verify_sorts command encoder"),
+                });
+                run(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_sort_pipeline,
+                    &radix_bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                );
+                render_queue.submit([encoder.finish()]);
+                assert_eq!(verifier.pending(), 1);
+                assert_eq!(poll_until_read(&render_device, verifier), 0);
+
+                // Keys overwritten between the sort and its output checks
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: verify_sorts command encoder"),
+                });
+                let buffers = verifier.begin(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_bind_group,
+                    max_compute_workgroups_per_dimension,
+                    number_of_keys,
+                    true,
+                );
+                assert!(buffers.is_some());
+                encoder.clear_buffer(input_keys_buf, 4_000, Some(64));
+                verifier.finish(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &radix_bind_group,
+                    max_compute_workgroups_per_dimension,
+                    buffers,
+                    number_of_keys,
+                    0..4,
+                    true,
+                    true,
+                    Algorithm::Radix,
+                );
+                render_queue.submit([encoder.finish()]);
+                assert_eq!(poll_until_read(&render_device, verifier), 1);
+                assert_eq!(verifier.failures(), 1);
+            },
+        );
+    }
+}
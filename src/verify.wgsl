@@ -0,0 +1,80 @@
+// The validation passes of the `verify-sorts` feature, on the bindings of `radix_sort.wgsl`.
+
+#import bevy_radix_sort::key_transform::transform_key
+
+/// The keys of one side of the global buffers
+@group(0) @binding(0) var<storage, read      > global_keys_i: array<u32>;
+
+/// `[input_sum, input_xor, output_sum, output_xor, unsorted]`, cleared before the sort
+@group(1) @binding(0) var<storage, read_write> verification: array<atomic<u32>, 5>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of keys sorted.
+    number_of_keys: u32,
+    /// The lowest bit of the keys compared, `8 * pass_range.start`.
+    key_shift: u32,
+    /// The bits of the shifted keys compared.
+    key_mask: u32,
+    /// CHECKSUM_PIPELINE only, 0 for the input of the sort, 1 for its output.
+    slot: u32,
+}
+var<push_constant> pc: PushConstants;
+
+fn get_index(workgroup_id: vec3u, num_workgroups: vec3u, local_invocation_index: u32) -> u32 {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    return workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_index;
+}
+
+#ifdef CHECKSUM_PIPELINE
+var<workgroup> local_sum: atomic<u32>;
+var<workgroup> local_xor: atomic<u32>;
+
+/// The wrapping sum and the xor of the keys, reduced in shared memory first.
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    if local_invocation_index == 0u {
+        atomicStore(&local_sum, 0u);
+        atomicStore(&local_xor, 0u);
+    }
+    workgroupBarrier();
+
+    let index = get_index(workgroup_id, num_workgroups, local_invocation_index);
+    if index < pc.number_of_keys {
+        let key = global_keys_i[index];
+        atomicAdd(&local_sum, key);
+        atomicXor(&local_xor, key);
+    }
+    workgroupBarrier();
+
+    if local_invocation_index == 0u {
+        atomicAdd(&verification[2u * pc.slot], atomicLoad(&local_sum));
+        atomicXor(&verification[2u * pc.slot + 1u], atomicLoad(&local_xor));
+    }
+}
+#endif // CHECKSUM_PIPELINE
+
+#ifdef CHECK_ORDER_PIPELINE
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let index = get_index(workgroup_id, num_workgroups, local_invocation_index);
+    if index + 1u >= pc.number_of_keys {
+        return;
+    }
+
+    let key = (transform_key(global_keys_i[index]) >> pc.key_shift) & pc.key_mask;
+    let next = (transform_key(global_keys_i[index + 1u]) >> pc.key_shift) & pc.key_mask;
+    if key > next {
+        atomicStore(&verification[4u], 1u);
+    }
+}
+#endif // CHECK_ORDER_PIPELINE
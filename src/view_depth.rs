@@ -0,0 +1,544 @@
+//! View-depth key generation pass.
+//!
+//! Gaussian-splat and blended-particle renderers sort their elements by view depth every frame.
+//! This pass projects every position with the view-projection of a camera and writes the clip-space depth,
+//! encoded so the unsigned order of the keys matches the draw order, plus the original indices into a key/value buffer pair.
+//! Elements outside of the view frustum get [`ViewDepthKeygenSettings::far_key`].
+//!
+//! [`ViewDepthKeygenPipeline::record_view_depth_sort`] records the keygen and [`crate::run`] in one go.
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    render::{
+        RenderApp,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferInitDescriptor, BufferSize, BufferUsages, CachedComputePipelineId,
+            CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+            PushConstantRange, ShaderDefVal, ShaderStages,
+            binding_types::{storage_buffer, storage_buffer_read_only, uniform_buffer_sized},
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+        view::ExtractedView,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    LoadState, NUMBER_OF_THREADS_PER_WORKGROUP, RadixSortBindGroup, RadixSortPipeline,
+    compute_pipelines_load_state, dispatch_workgroup_ext, f32_to_ordered_u32, global_keys_buffer,
+    global_vals_buffer, load_keys_shader, passes_needed,
+};
+
+pub const VIEW_DEPTH_KEYGEN_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(216258076738559451316008465411210144146);
+
+const WORKGROUP_OFFSET_OFFSET: u32 = 0;
+const NUMBER_OF_POSITIONS_OFFSET: u32 = 4;
+const POSITION_STRIDE_OFFSET: u32 = 8;
+
+const PUSH_CONSTANT_RANGES: PushConstantRange = PushConstantRange {
+    stages: ShaderStages::COMPUTE,
+    range: 0..12,
+};
+
+const DESCENDING_FLAG: u32 = 1;
+
+/// The order in which the sorted elements come out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DepthOrder {
+    /// Farthest first, the draw order of blended elements.
+    #[default]
+    BackToFront,
+    /// Nearest first.
+    FrontToBack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ViewDepthKeygenSettings {
+    pub order: DepthOrder,
+    /// Whether the projection maps the near plane to depth 1 and the far plane to depth 0.
+    ///
+    /// Both the perspective and the orthographic projections of bevy are reversed-Z.
+    pub reversed_z: bool,
+    /// The key of the elements outside of the view frustum, `u32::MAX` sorts them last.
+    pub far_key: u32,
+}
+
+impl Default for ViewDepthKeygenSettings {
+    fn default() -> Self {
+        Self {
+            order: DepthOrder::BackToFront,
+            reversed_z: true,
+            far_key: u32::MAX,
+        }
+    }
+}
+
+/// The uniform read by the view-depth keygen pass, must match `ViewDepthUniform` in `view_depth.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct ViewDepthUniform {
+    clip_from_world: [f32; 16],
+    far_key: u32,
+    flags: u32,
+    _padding: [u32; 2],
+}
+
+impl ViewDepthUniform {
+    pub fn new(clip_from_world: Mat4, settings: &ViewDepthKeygenSettings) -> Self {
+        // Ascending clip-space depth is back-to-front with reversed-Z and front-to-back otherwise
+        let descending = (settings.order == DepthOrder::BackToFront) != settings.reversed_z;
+
+        Self {
+            clip_from_world: clip_from_world.to_cols_array(),
+            far_key: settings.far_key,
+            flags: if descending { DESCENDING_FLAG } else { 0 },
+            _padding: [0; 2],
+        }
+    }
+
+    pub fn from_extracted_view(view: &ExtractedView, settings: &ViewDepthKeygenSettings) -> Self {
+        let clip_from_world = view.clip_from_world.unwrap_or_else(|| {
+            view.clip_from_view * view.world_from_view.compute_matrix().inverse()
+        });
+
+        Self::new(clip_from_world, settings)
+    }
+
+    pub fn clip_from_world(&self) -> Mat4 {
+        Mat4::from_cols_array(&self.clip_from_world)
+    }
+}
+
+/// CPU version of the key computed by the view-depth keygen pass.
+pub fn view_depth_key(position: Vec3, uniform: &ViewDepthUniform) -> u32 {
+    let clip = uniform.clip_from_world() * position.extend(1.0);
+
+    if clip.w <= 0.0 {
+        return uniform.far_key;
+    }
+
+    let ndc = clip.truncate() / clip.w;
+    let on_screen = ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && (0.0..=1.0).contains(&ndc.z);
+    if !on_screen {
+        return uniform.far_key;
+    }
+
+    let key = f32_to_ordered_u32(ndc.z);
+    if uniform.flags & DESCENDING_FLAG != 0 {
+        !key
+    } else {
+        key
+    }
+}
+
+pub struct ViewDepthKeygenPlugin;
+
+impl Plugin for ViewDepthKeygenPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            VIEW_DEPTH_KEYGEN_SHADER_HANDLE,
+            "view_depth.wgsl",
+            Shader::from_wgsl
+        );
+        load_keys_shader(app);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ViewDepthKeygenPipeline>();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ViewDepthKeygenPipeline {
+    pipeline: CachedComputePipelineId,
+    /// The bindgroup layout is:
+    ///
+    /// ```wgsl
+    /// @binding(0) var<storage, read      > positions: array<f32>;
+    /// @binding(1) var<uniform>             view: ViewDepthUniform;
+    /// @binding(2) var<storage, read_write> global_keys_o: array<u32>;
+    /// @binding(3) var<storage, read_write> global_vals_o: array<u32>;
+    /// ```
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for ViewDepthKeygenPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "view_depth_keygen bindgroup layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only::<f32>(false),
+                    uniform_buffer_sized(
+                        false,
+                        BufferSize::new(std::mem::size_of::<ViewDepthUniform>() as u64),
+                    ),
+                    storage_buffer::<u32>(false),
+                    storage_buffer::<u32>(false),
+                ),
+            ),
+        );
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("view_depth_keygen: pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: vec![PUSH_CONSTANT_RANGES],
+            shader: VIEW_DEPTH_KEYGEN_SHADER_HANDLE,
+            shader_defs: vec![ShaderDefVal::UInt(
+                "NUMBER_OF_THREADS_PER_WORKGROUP".into(),
+                NUMBER_OF_THREADS_PER_WORKGROUP,
+            )],
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+}
+
+impl ViewDepthKeygenPipeline {
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn load_state(&self, pipeline_cache: &PipelineCache) -> LoadState {
+        compute_pipelines_load_state(
+            pipeline_cache,
+            &[("view_depth_keygen pipeline", self.pipeline)],
+        )
+    }
+
+    /// Creates the uniform buffer of the pass, update it every frame with [`ViewDepthKeygenPipeline::write_uniform_buffer`].
+    pub fn create_uniform_buffer(
+        &self,
+        render_device: &RenderDevice,
+        uniform: &ViewDepthUniform,
+    ) -> Buffer {
+        render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("view_depth_keygen: uniform buffer"),
+            contents: bytemuck::bytes_of(uniform),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        })
+    }
+
+    pub fn write_uniform_buffer(
+        &self,
+        render_queue: &RenderQueue,
+        uniform_buffer: &Buffer,
+        uniform: &ViewDepthUniform,
+    ) {
+        render_queue.write_buffer(uniform_buffer, 0, bytemuck::bytes_of(uniform));
+    }
+
+    pub fn create_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        positions: &Buffer,
+        uniform_buffer: &Buffer,
+        keys: &Buffer,
+        vals: &Buffer,
+    ) -> BindGroup {
+        render_device.create_bind_group(
+            "view_depth_keygen: bind_group",
+            &self.bind_group_layout,
+            &BindGroupEntries::sequential((
+                positions.as_entire_binding(),
+                uniform_buffer.as_entire_binding(),
+                keys.as_entire_binding(),
+                vals.as_entire_binding(),
+            )),
+        )
+    }
+
+    /// Creates a bind group writing into the global buffers [`crate::run`] reads from with the same `read_from_even`.
+    ///
+    /// Returns `None` if the global buffers haven't been prepared yet.
+    pub fn create_global_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        sbufs: &RenderAssets<GpuShaderStorageBuffer>,
+        positions: &Buffer,
+        uniform_buffer: &Buffer,
+        read_from_even: bool,
+    ) -> Option<BindGroup> {
+        let keys = global_keys_buffer(sbufs, read_from_even)?;
+        let vals = global_vals_buffer(sbufs, read_from_even)?;
+
+        Some(self.create_bind_group(render_device, positions, uniform_buffer, keys, vals))
+    }
+
+    /// Writes the depth key of the first `number_of_positions` positions and their indices into the bound keys and vals.
+    ///
+    /// `position_stride` is the distance between two positions in `f32`s, 4 for a `vec4<f32>` buffer, 3 for a packed `vec3<f32>` one.
+    pub fn record_view_depth_keygen(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_positions: u32,
+        position_stride: u32,
+    ) {
+        if number_of_positions == 0 {
+            return;
+        }
+
+        debug_assert!(position_stride >= 3, "position_stride must be at least 3");
+
+        let pipeline = pipeline_cache.get_compute_pipeline(self.pipeline).unwrap();
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("view_depth_keygen compute pass"),
+            ..default()
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_push_constants(
+            NUMBER_OF_POSITIONS_OFFSET,
+            bytemuck::bytes_of(&number_of_positions),
+        );
+        pass.set_push_constants(POSITION_STRIDE_OFFSET, bytemuck::bytes_of(&position_stride));
+
+        dispatch_workgroup_ext(
+            &mut pass,
+            number_of_positions.div_ceil(NUMBER_OF_THREADS_PER_WORKGROUP),
+            max_compute_workgroups_per_dimension,
+            WORKGROUP_OFFSET_OFFSET,
+        );
+    }
+
+    /// Records the keygen followed by a full 32-bit [`crate::run`] over the global buffers.
+    ///
+    /// `bind_group` must come from [`ViewDepthKeygenPipeline::create_global_bind_group`] with the same `read_from_even`.
+    /// The indices in draw order end up in the `EVE_*` global vals buffer if `read_from_even` is true, `ODD_*` otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_view_depth_sort(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        bind_group: &BindGroup,
+        radix_sort_pipeline: &RadixSortPipeline,
+        radix_sort_bind_group: &RadixSortBindGroup,
+        max_compute_workgroups_per_dimension: u32,
+        number_of_positions: u32,
+        position_stride: u32,
+        read_from_even: bool,
+    ) {
+        self.record_view_depth_keygen(
+            encoder,
+            pipeline_cache,
+            bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_positions,
+            position_stride,
+        );
+
+        crate::run(
+            encoder,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_sort_bind_group,
+            max_compute_workgroups_per_dimension,
+            number_of_positions,
+            0..passes_needed(32),
+            false,
+            read_from_even,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        camera::{CameraProjection, OrthographicProjection, PerspectiveProjection},
+        render_resource::CommandEncoderDescriptor,
+    };
+
+    use crate::test_utils::{
+        create_render_test_app, create_storage_buffer, read_buffer, run_render_system_once,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_ordered_f32_key() {
+        let floats = [
+            f32::NEG_INFINITY,
+            -1e30,
+            -2.5,
+            -0.0,
+            0.0,
+            1e-30,
+            0.5,
+            1.0,
+            1e30,
+            f32::INFINITY,
+        ];
+
+        for pair in floats.windows(2) {
+            assert!(f32_to_ordered_u32(pair[0]) <= f32_to_ordered_u32(pair[1]));
+        }
+    }
+
+    /// Runs the keygen and returns the indices of the positions sorted by the keys computed on the GPU.
+    fn run_view_depth_keygen(positions: Vec<Vec3>, uniform: ViewDepthUniform) -> Vec<u32> {
+        let mut app = create_render_test_app();
+        app.add_plugins(ViewDepthKeygenPlugin);
+
+        let number_of_positions = positions.len() as u32;
+        let packed: Vec<u32> = positions
+            .iter()
+            .flat_map(|p| [p.x, p.y, p.z, 1.0])
+            .map(f32::to_bits)
+            .collect();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        run_render_system_once(
+            &mut app,
+            move |render_device: Res<RenderDevice>,
+                  render_queue: Res<RenderQueue>,
+                  pipeline_cache: Res<PipelineCache>,
+                  view_depth_pipeline: Res<ViewDepthKeygenPipeline>| {
+                let positions_buf = create_storage_buffer(&render_device, &packed);
+                let uniform_buf =
+                    view_depth_pipeline.create_uniform_buffer(&render_device, &uniform);
+                let zeros = vec![0u32; number_of_positions as usize];
+                let keys_buf = create_storage_buffer(&render_device, &zeros);
+                let vals_buf = create_storage_buffer(&render_device, &zeros);
+                let bind_group = view_depth_pipeline.create_bind_group(
+                    &render_device,
+                    &positions_buf,
+                    &uniform_buf,
+                    &keys_buf,
+                    &vals_buf,
+                );
+
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("unit_test: view_depth_keygen command encoder"),
+                });
+                view_depth_pipeline.record_view_depth_keygen(
+                    &mut encoder,
+                    &pipeline_cache,
+                    &bind_group,
+                    render_device.limits().max_compute_workgroups_per_dimension,
+                    number_of_positions,
+                    4,
+                );
+                render_queue.submit([encoder.finish()]);
+
+                let n = number_of_positions as usize;
+                let keys = read_buffer(&render_device, &render_queue, &keys_buf, n);
+                let vals = read_buffer(&render_device, &render_queue, &vals_buf, n);
+                sender.send((keys, vals)).unwrap();
+            },
+        );
+
+        let (keys, vals) = receiver.recv().unwrap();
+        assert_eq!(vals, (0..number_of_positions).collect::<Vec<_>>());
+
+        // Off-screen elements get exactly the far key, the CPU reference agrees on them
+        for (position, key) in positions.iter().zip(&keys) {
+            if view_depth_key(*position, &uniform) == uniform.far_key {
+                assert_eq!(*key, uniform.far_key);
+            }
+        }
+
+        let mut sorted: Vec<u32> = vals;
+        sorted.sort_by_key(|&i| (keys[i as usize], i));
+        sorted
+    }
+
+    /// A camera at (0, 0, 5) looking at the origin, and elements at known depths along the view axis.
+    fn known_depths_scene() -> (Mat4, Vec<Vec3>) {
+        let view_from_world = Transform::from_xyz(0.0, 0.0, 5.0)
+            .looking_at(Vec3::ZERO, Vec3::Y)
+            .compute_matrix()
+            .inverse();
+
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 2.0),    // 0: depth 3
+            Vec3::new(0.0, 0.0, -20.0),  // 1: depth 25
+            Vec3::new(0.0, 0.0, 10.0),   // 2: behind the camera
+            Vec3::new(0.1, 0.0, 4.0),    // 3: depth 1
+            Vec3::new(1000.0, 0.0, 0.0), // 4: far off to the side
+            Vec3::new(0.0, 0.1, -5.0),   // 5: depth 10
+        ];
+
+        (view_from_world, positions)
+    }
+
+    #[test]
+    fn test_view_depth_keygen_perspective() {
+        let (view_from_world, positions) = known_depths_scene();
+        let clip_from_world =
+            PerspectiveProjection::default().get_clip_from_view() * view_from_world;
+
+        let back_to_front = ViewDepthUniform::new(clip_from_world, &default());
+        assert_eq!(
+            run_view_depth_keygen(positions.clone(), back_to_front),
+            vec![1, 5, 0, 3, 2, 4]
+        );
+
+        let front_to_back = ViewDepthUniform::new(
+            clip_from_world,
+            &ViewDepthKeygenSettings {
+                order: DepthOrder::FrontToBack,
+                far_key: 0,
+                ..default()
+            },
+        );
+        // A far key of 0 sorts the off-screen elements first
+        assert_eq!(
+            run_view_depth_keygen(positions, front_to_back),
+            vec![2, 4, 3, 0, 5, 1]
+        );
+    }
+
+    #[test]
+    fn test_view_depth_keygen_orthographic() {
+        let (view_from_world, positions) = known_depths_scene();
+        let clip_from_world =
+            OrthographicProjection::default_3d().get_clip_from_view() * view_from_world;
+
+        let back_to_front = ViewDepthUniform::new(clip_from_world, &default());
+        assert_eq!(
+            run_view_depth_keygen(positions, back_to_front),
+            vec![1, 5, 0, 3, 2, 4]
+        );
+    }
+
+    #[test]
+    fn test_view_depth_keygen_forward_z() {
+        let (view_from_world, positions) = known_depths_scene();
+        // A classic forward-Z projection, near at depth 0 and far at depth 1
+        let clip_from_view = Mat4::perspective_rh(std::f32::consts::FRAC_PI_4, 1.0, 0.1, 100.0);
+
+        let back_to_front = ViewDepthUniform::new(
+            clip_from_view * view_from_world,
+            &ViewDepthKeygenSettings {
+                reversed_z: false,
+                ..default()
+            },
+        );
+        assert_eq!(
+            run_view_depth_keygen(positions, back_to_front),
+            vec![1, 5, 0, 3, 2, 4]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+#import bevy_radix_sort::ordered_keys::f32_to_ordered_u32
+
+struct ViewDepthUniform {
+    clip_from_world: mat4x4<f32>,
+    /// The key of the elements outside of the view frustum.
+    far_key: u32,
+    /// Bit 0 set: invert the encoded depth so the keys sort by descending clip-space depth.
+    flags: u32,
+}
+
+/// Positions, the xyz of position `i` start at `positions[i * position_stride]`
+@group(0) @binding(0) var<storage, read      > positions: array<f32>;
+@group(0) @binding(1) var<uniform>             view: ViewDepthUniform;
+/// Write the encoded depth of each position to this buffer
+@group(0) @binding(2) var<storage, read_write> global_keys_o: array<u32>;
+/// Write the index of each position to this buffer
+@group(0) @binding(3) var<storage, read_write> global_vals_o: array<u32>;
+
+struct PushConstants {
+    /// See `radix_sort.wgsl`
+    workgroup_offset: u32,
+    /// The number of positions to generate keys for.
+    number_of_positions: u32,
+    /// The distance between two positions in the `positions` buffer, in `f32`s (4 for `vec4<f32>`).
+    position_stride: u32,
+}
+var<push_constant> pc: PushConstants;
+
+const DESCENDING_FLAG: u32 = 1u;
+
+@compute @workgroup_size(#NUMBER_OF_THREADS_PER_WORKGROUP, 1, 1)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3u,
+    @builtin(num_workgroups) num_workgroups: vec3u,
+    @builtin(local_invocation_id) local_invocation_id: vec3u
+) {
+    let workgroup_index = workgroup_id.y * num_workgroups.x + workgroup_id.x + pc.workgroup_offset;
+    let index = workgroup_index * #{NUMBER_OF_THREADS_PER_WORKGROUP}u + local_invocation_id.x;
+
+    if index >= pc.number_of_positions {
+        return;
+    }
+
+    let base = index * pc.position_stride;
+    let p = vec3f(positions[base], positions[base + 1u], positions[base + 2u]);
+
+    let clip = view.clip_from_world * vec4f(p, 1.0);
+
+    var key = view.far_key;
+    // Behind the camera (perspective) or degenerate, the division below would flip or blow up
+    if clip.w > 0.0 {
+        let ndc = clip.xyz / clip.w;
+        let on_screen = all(abs(ndc.xy) <= vec2f(1.0)) && ndc.z >= 0.0 && ndc.z <= 1.0;
+
+        if on_screen {
+            key = f32_to_ordered_u32(ndc.z);
+            if (view.flags & DESCENDING_FLAG) != 0u {
+                key = ~key;
+            }
+        }
+    }
+
+    global_keys_o[index] = key;
+    global_vals_o[index] = index;
+}
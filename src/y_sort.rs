@@ -0,0 +1,241 @@
+//! A Y-sorted draw order of the visible sprites of a 2D scene, sorted on the GPU, for a custom 2D phase or batcher.
+//!
+//! Bevy sorts the items of the `Transparent2d` phase on the CPU, by their z. The [`YSortPlugin`] doesn't change how
+//! Bevy draws the sprites: it extracts their y, sorts them on the GPU and publishes the order in the
+//! [`YSortedSprites`], so a pipeline of your own draws them in that order instead of sorting its items:
+//!
+//! ```text
+//!  Extract:            visible Sprite / YSorted entities ─▶ y_sort_key(y), or their YSortKey
+//!  PrepareResources:   YSortedSprites: the keys uploaded, a SortJob argsorting them
+//!  PrepareBindGroups:  your instance data written in the order of YSortedSprites::entities, bound with the buffer
+//!  Render:             RadixSortJobsNode, then one draw of YSortedSprites::instances
+//! ```
+//!
+//! The vertex shader of the draw reads its sprite through the sorted dense indices, the sprites highest on the screen
+//! first so the lower ones are drawn over them:
+//!
+//! ```wgsl
+//! @group(1) @binding(0) var<storage, read> y_sorted: array<u32>;
+//! @group(1) @binding(1) var<storage, read> sprites: array<SpriteInstance>;
+//!
+//! let sprite = sprites[y_sorted[instance_index]];
+//! ```
+//!
+//! See [`crate::entity_sort`] for the dense indices and the despawns between the extraction and the draw.
+
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    render::{
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+    },
+};
+
+use crate::{
+    RadixSortJobs, RadixSortPipeline, SortedEntityIndices, f32_to_ordered_u32, radix_sort_loaded,
+};
+
+/// The key of a world-space y, ascending from the highest y on the screen: the back-to-front order of a Y-sort.
+pub const fn y_sort_key(y: f32) -> u32 {
+    f32_to_ordered_u32(-y)
+}
+
+/// Sorts the entity by this key instead of its [`y_sort_key`], e.g. its feet rather than its center. The lower keys
+/// are drawn first.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[reflect(Component, Debug, Default, PartialEq, Hash)]
+pub struct YSortKey(pub u32);
+
+/// Y-sorts an entity without a [`Sprite`], e.g. a 2D mesh drawn by the same custom pipeline.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[reflect(Component, Debug, Default, PartialEq)]
+pub struct YSorted;
+
+/// Y-sorts the visible entities with a [`Sprite`] or [`YSorted`] every frame into the [`YSortedSprites`] of the
+/// render world. Requires the [`crate::RadixSortPlugin`], like the [`crate::EntitySortPlugin`].
+pub struct YSortPlugin;
+
+impl Plugin for YSortPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<YSortKey>().register_type::<YSorted>();
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<ExtractedYSortKeys>()
+            .init_resource::<YSortedSprites>()
+            .add_systems(ExtractSchedule, extract_y_sort_keys)
+            .add_systems(
+                Render,
+                queue_y_sort
+                    .in_set(RenderSet::PrepareResources)
+                    .run_if(resource_exists::<RadixSortPipeline>),
+            );
+    }
+}
+
+/// The keys extracted this frame, along the entities they belong to.
+#[derive(Resource, Debug, Default)]
+struct ExtractedYSortKeys {
+    entities: Vec<MainEntity>,
+    keys: Vec<u32>,
+}
+
+fn extract_y_sort_keys(
+    mut extracted: ResMut<ExtractedYSortKeys>,
+    query: Extract<
+        Query<
+            (Entity, &GlobalTransform, &ViewVisibility, Option<&YSortKey>),
+            Or<(With<Sprite>, With<YSorted>)>,
+        >,
+    >,
+) {
+    extracted.entities.clear();
+    extracted.keys.clear();
+    for (entity, transform, visibility, key) in &query {
+        if !visibility.get() {
+            continue;
+        }
+
+        extracted.entities.push(entity.into());
+        extracted
+            .keys
+            .push(key.map_or_else(|| y_sort_key(transform.translation().y), |key| key.0));
+    }
+}
+
+/// The visible sprites of the frame in Y-sort order, in the render world, see the [module docs](self).
+///
+/// Dereferences to the [`SortedEntityIndices`] holding them: the [`SortedEntityIndices::buffer`] of the dense
+/// indices, in draw order, and the [`SortedEntityIndices::entities`] by dense index.
+#[derive(Resource, Debug, Default, Deref)]
+pub struct YSortedSprites(SortedEntityIndices);
+
+impl YSortedSprites {
+    /// The instances of the draw of all the sprites, `instance_index` being the position in the draw order.
+    pub fn instances(&self) -> Range<u32> {
+        0..self.len()
+    }
+}
+
+fn queue_y_sort(world: &mut World) {
+    let loaded = radix_sort_loaded()(world);
+    let mut extracted = world.resource_mut::<ExtractedYSortKeys>();
+    let entities = std::mem::take(&mut extracted.entities);
+    let keys = std::mem::take(&mut extracted.keys);
+
+    world.resource_scope(|world, mut sorted: Mut<YSortedSprites>| {
+        let previous = if loaded {
+            world.resource_scope(|world, mut jobs: Mut<RadixSortJobs>| {
+                sorted.0.queue(
+                    world.resource::<RenderDevice>(),
+                    world.resource::<RenderQueue>(),
+                    world.resource::<RadixSortPipeline>(),
+                    &mut jobs,
+                    entities,
+                    &keys,
+                )
+            })
+        } else {
+            sorted.0.clear()
+        };
+
+        // Handed back for the next extraction, keeping the allocations
+        let mut extracted = world.resource_mut::<ExtractedYSortKeys>();
+        extracted.entities = previous;
+        extracted.keys = keys;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{render::view::VisibilitySystems, utils::FloatOrd};
+
+    use crate::{
+        GetSubgroupSizePlugin, RadixSortPlugin,
+        test_utils::{create_render_test_app, read_buffer, run_once},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_y_sort_key() {
+        let ys = [-1e6, -2.5, -0.0, 0.0, 1.0, 3.5, f32::INFINITY];
+        for pair in ys.windows(2) {
+            assert!(y_sort_key(pair[0]) >= y_sort_key(pair[1]), "{pair:?}");
+        }
+        assert!(y_sort_key(1.0) < y_sort_key(0.5));
+    }
+
+    /// Hidden from the views in the tests.
+    #[derive(Component)]
+    struct Hidden;
+
+    /// Shows the other entities to a view, in place of the visibility checks of the sprite plugin.
+    fn show_entities(mut query: Query<&mut ViewVisibility, Without<Hidden>>) {
+        for mut visibility in &mut query {
+            visibility.set();
+        }
+    }
+
+    #[test]
+    fn test_y_sort() {
+        let mut app = create_render_test_app();
+        app.add_plugins(GetSubgroupSizePlugin::default())
+            .add_plugins(RadixSortPlugin {
+                settings: 1024.into(),
+            })
+            .add_plugins(YSortPlugin)
+            .add_systems(
+                PostUpdate,
+                show_entities.in_set(VisibilitySystems::CheckVisibility),
+            );
+
+        // Sprites and YSorted entities, more than the capacity of the global buffers
+        let mut expected = Vec::new();
+        for i in 0..2000u32 {
+            let y = (i.wrapping_mul(2_654_435_761) % 1000) as f32 - 500.0;
+            let translation = Vec3::new(0.0, y, 0.0);
+            let mut entity = app.world_mut().spawn((
+                Transform::from_translation(translation),
+                GlobalTransform::from_translation(translation),
+                ViewVisibility::default(),
+            ));
+            if i % 2 == 0 {
+                entity.insert(Sprite::default());
+            } else {
+                entity.insert(YSorted);
+            }
+            expected.push(FloatOrd(-y));
+        }
+        expected.sort();
+        // Neither hidden entities nor the ones without a Sprite or YSorted
+        app.world_mut()
+            .spawn((Sprite::default(), ViewVisibility::default(), Hidden));
+        app.world_mut()
+            .spawn((GlobalTransform::IDENTITY, ViewVisibility::default()));
+
+        run_once(&mut app);
+        app.update();
+
+        let render_world = app.sub_app(RenderApp).world();
+        let sorted = render_world.resource::<YSortedSprites>();
+        assert_eq!(sorted.instances(), 0..expected.len() as u32);
+        let indices = read_buffer(
+            render_world.resource::<RenderDevice>(),
+            render_world.resource::<RenderQueue>(),
+            sorted.buffer().unwrap(),
+            sorted.len() as usize,
+        );
+        let keys: Vec<FloatOrd> = indices
+            .into_iter()
+            .map(|index| {
+                let entity = sorted.entity(index).unwrap().id();
+                let transform = app.world().get::<GlobalTransform>(entity).unwrap();
+                FloatOrd(-transform.translation().y)
+            })
+            .collect();
+        assert_eq!(keys, expected);
+    }
+}
@@ -0,0 +1,203 @@
+//! Counts the buffers and bind groups the indirect sorts create over 1000 frames.
+//!
+//! Bevy numbers every `Buffer` and `BindGroup` it wraps from a process-wide counter, the gap between two new ids is
+//! the number of resources created in between. The count needs a process of its own, the unit tests create
+//! resources from other threads, hence this integration test on their helpers (`test-utils` feature).
+
+use std::num::NonZeroU32;
+
+use bevy::{
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSet,
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroupId, Buffer, BufferId, BufferInitDescriptor, BufferUsages,
+            CommandEncoderDescriptor, PipelineCache,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+    },
+};
+use bevy_radix_sort::{
+    GetSubgroupSizePlugin, GpuSortParams, RadixSortBindGroup, RadixSortPipeline, RadixSortPlugin,
+    ReduceMaxPipeline, ReduceMaxPlugin, global_keys_buffer, run_auto, run_gpu_driven,
+    run_unless_sorted,
+    test_utils::{create_render_test_app, random_keys, read_buffer, run_once},
+};
+
+const NUMBER_OF_KEYS: u32 = 4096;
+const WARM_UP_FRAMES: usize = 10;
+const FRAMES: usize = 1000;
+
+/// The buffers and bind groups created by the sorts of each frame.
+#[derive(Resource, Default)]
+struct CreatedPerFrame(Vec<(u32, u32)>);
+
+/// The params of [`run_gpu_driven`], created once.
+#[derive(Resource)]
+struct ParamsBuffer(Buffer);
+
+/// The next ids of the Bevy buffers and bind groups, taking one of each.
+fn next_ids() -> (u32, u32) {
+    (
+        NonZeroU32::from(BufferId::new()).get(),
+        NonZeroU32::from(BindGroupId::new()).get(),
+    )
+}
+
+/// Records and submits the three indirect sorts of the global buffers alone, so the ids taken in between are theirs.
+fn sort_every_frame(world: &mut World) {
+    let before = next_ids();
+    {
+        let render_device = world.resource::<RenderDevice>();
+        let render_queue = world.resource::<RenderQueue>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let radix_sort_pipeline = world.resource::<RadixSortPipeline>();
+        let radix_bind_group = world.resource::<RadixSortBindGroup>();
+        let reduce_max_pipeline = world.resource::<ReduceMaxPipeline>();
+        let sbufs = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let params = &world.resource::<ParamsBuffer>().0;
+        let max_compute_workgroups_per_dimension =
+            render_device.limits().max_compute_workgroups_per_dimension;
+
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("indirect_args_pool: command encoder"),
+        });
+        run_auto(
+            &mut encoder,
+            render_device,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            reduce_max_pipeline,
+            sbufs,
+            max_compute_workgroups_per_dimension,
+            NUMBER_OF_KEYS,
+            0..4,
+            true,
+            true,
+        );
+        run_unless_sorted(
+            &mut encoder,
+            render_device,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            reduce_max_pipeline,
+            sbufs,
+            max_compute_workgroups_per_dimension,
+            NUMBER_OF_KEYS,
+            0..4,
+            false,
+            true,
+            true,
+        );
+        run_gpu_driven(
+            &mut encoder,
+            render_device,
+            pipeline_cache,
+            radix_sort_pipeline,
+            radix_bind_group,
+            reduce_max_pipeline,
+            max_compute_workgroups_per_dimension,
+            NUMBER_OF_KEYS,
+            0..4,
+            true,
+            params,
+        );
+        render_queue.submit([encoder.finish()]);
+    }
+    let after = next_ids();
+
+    // The ids of `before` itself
+    let created = (after.0 - before.0 - 1, after.1 - before.1 - 1);
+    world.resource_mut::<CreatedPerFrame>().0.push(created);
+}
+
+#[test]
+fn test_indirect_sorts_steady_state_allocations() {
+    let mut app = create_render_test_app();
+    app.add_plugins(GetSubgroupSizePlugin::default())
+        .add_plugins(RadixSortPlugin {
+            settings: NUMBER_OF_KEYS.into(),
+        })
+        .add_plugins(ReduceMaxPlugin);
+    // The global buffers are prepared by the first frame
+    run_once(&mut app);
+
+    let render_world = app.sub_app_mut(RenderApp).world_mut();
+    let params = render_world
+        .resource::<RenderDevice>()
+        .create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("indirect_args_pool: params buffer"),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            contents: bytemuck::bytes_of(&GpuSortParams {
+                count: NUMBER_OF_KEYS,
+                pass_mask: 0xF,
+                ..default()
+            }),
+        });
+    render_world
+        .insert_resource(ParamsBuffer(params))
+        .init_resource::<CreatedPerFrame>();
+    app.sub_app_mut(RenderApp).add_systems(
+        Render,
+        sort_every_frame
+            .in_set(RenderSet::Cleanup)
+            .run_if(resource_exists::<RadixSortBindGroup>),
+    );
+
+    let mut keys = random_keys(199, NUMBER_OF_KEYS, u32::MAX);
+    let render_world = app.sub_app(RenderApp).world();
+    let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+    render_world.resource::<RenderQueue>().write_buffer(
+        global_keys_buffer(sbufs, true).unwrap(),
+        0,
+        bytemuck::cast_slice(&keys),
+    );
+
+    while app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<CreatedPerFrame>()
+        .0
+        .len()
+        < WARM_UP_FRAMES + FRAMES
+    {
+        app.update();
+    }
+
+    let created = &app
+        .sub_app(RenderApp)
+        .world()
+        .resource::<CreatedPerFrame>()
+        .0;
+    let (warm_up, steady) = created.split_at(WARM_UP_FRAMES);
+    // The ids do count the pool and its bind groups
+    assert!(
+        warm_up
+            .iter()
+            .any(|&(buffers, bind_groups)| buffers > 0 && bind_groups > 0),
+        "{warm_up:?}"
+    );
+    for (frame, &created) in steady.iter().enumerate() {
+        assert_eq!(
+            created,
+            (0, 0),
+            "frame {frame} after the warm-up created (buffers, bind groups)"
+        );
+    }
+
+    // The pooled args still sort
+    keys.sort();
+    let render_world = app.sub_app(RenderApp).world();
+    let sbufs = render_world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+    let output_keys = read_buffer(
+        render_world.resource::<RenderDevice>(),
+        render_world.resource::<RenderQueue>(),
+        global_keys_buffer(sbufs, true).unwrap(),
+        NUMBER_OF_KEYS as usize,
+    );
+    assert_eq!(output_keys, keys);
+}